@@ -0,0 +1,29 @@
+//! Build script for probe-common
+//!
+//! With the `proto` feature, generates the protobuf types in proto/ with
+//! prost. protoc comes from protoc-bin-vendored unless `PROTOC` is set.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto/latency.proto");
+
+    #[cfg(feature = "proto")]
+    generate_protos();
+}
+
+#[cfg(feature = "proto")]
+fn generate_protos() {
+    println!("cargo:rerun-if-env-changed=PROTOC");
+
+    let mut config = prost_build::Config::new();
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("No vendored protoc for this host");
+        config.protoc_executable(protoc);
+    }
+
+    // This crate is no_std, so maps use alloc's BTreeMap
+    config.btree_map(["."]);
+    config
+        .compile_protos(&["proto/latency.proto"], &["proto"])
+        .expect("Failed to generate protobuf types");
+}
@@ -16,6 +16,18 @@ pub const MAX_EVENTS: u32 = 1024;
 /// Maximum number of packet drop events to track
 pub const MAX_PACKET_DROPS: u32 = 4096;
 
+/// Maximum number of entries in the service filter
+pub const MAX_SERVICE_FILTERS: u32 = 256;
+
+/// Maximum number of DNS queries awaiting a response
+pub const MAX_DNS_QUERIES: u32 = 1024;
+
+/// Maximum number of QUIC sockets with a flight awaiting a response
+pub const MAX_QUIC_FLIGHTS: u32 = 4096;
+
+/// Maximum number of listening sockets whose accept queues are tracked
+pub const MAX_LISTENERS: u32 = 4096;
+
 // ============================================================================
 // Event Types (for LatencyEvent.event_type)
 // ============================================================================
@@ -29,6 +41,107 @@ pub const EVENT_TYPE_RECV: u8 = 1;
 /// Event triggered by tcp_cleanup_rbuf kprobe
 pub const EVENT_TYPE_CLEANUP: u8 = 2;
 
+/// Event triggered by the tcp:tcp_probe tracepoint (latency is the
+/// connection's smoothed RTT)
+pub const EVENT_TYPE_TCP_PROBE: u8 = 3;
+
+/// DNS query to response latency (udp_sendmsg to port 53, then
+/// skb_consume_udp on the same socket)
+pub const EVENT_TYPE_DNS: u8 = 4;
+
+/// DNS server port
+pub const DNS_PORT: u16 = 53;
+
+/// QUIC flight to response latency (first udp_sendmsg to the QUIC port
+/// since the last read, then skb_consume_udp on the same socket)
+pub const EVENT_TYPE_QUIC: u8 = 5;
+
+/// Port QUIC (HTTP/3) servers usually listen on
+pub const DEFAULT_QUIC_PORT: u16 = 443;
+
+/// RST received from the peer (tcp_reset kprobe, no latency)
+pub const EVENT_TYPE_RESET_RECEIVED: u8 = 6;
+
+/// RST sent to abort a connection (tcp_send_active_reset kprobe, no
+/// latency)
+pub const EVENT_TYPE_RESET_SENT: u8 = 7;
+
+/// Retransmission timeout: the peer did not acknowledge in time
+/// (tcp_retransmit_timer kprobe, no latency)
+pub const EVENT_TYPE_TIMEOUT: u8 = 8;
+
+// ============================================================================
+// HTTP Status Classes (for LatencyEvent.http_status_class)
+// ============================================================================
+
+/// No HTTP response seen on the connection
+pub const HTTP_STATUS_UNKNOWN: u8 = 0;
+
+/// Lowest status class (1xx)
+pub const HTTP_STATUS_MIN_CLASS: u8 = 1;
+
+/// Highest status class (5xx)
+pub const HTTP_STATUS_MAX_CLASS: u8 = 5;
+
+/// Bytes of a response inspected for the status line ("HTTP/1.1 200")
+pub const HTTP_STATUS_LINE_LEN: usize = 12;
+
+// ============================================================================
+// TCP States (for LatencyEvent.tcp_state, as in include/net/tcp_states.h)
+// ============================================================================
+
+/// State not read (UDP events, tracepoint events)
+pub const TCP_STATE_UNKNOWN: u8 = 0;
+
+/// Handshake complete, data flowing
+pub const TCP_STATE_ESTABLISHED: u8 = 1;
+
+/// Active open, SYN sent
+pub const TCP_STATE_SYN_SENT: u8 = 2;
+
+/// Passive open, SYN received (fast open child sockets)
+pub const TCP_STATE_SYN_RECV: u8 = 3;
+
+/// Local side closed, FIN sent
+pub const TCP_STATE_FIN_WAIT1: u8 = 4;
+
+/// Local FIN acknowledged, waiting for the peer's FIN
+pub const TCP_STATE_FIN_WAIT2: u8 = 5;
+
+/// Both FINs exchanged, waiting out stray segments
+pub const TCP_STATE_TIME_WAIT: u8 = 6;
+
+/// Closed (reset, aborted, or fully shut down)
+pub const TCP_STATE_CLOSE: u8 = 7;
+
+/// Peer closed, FIN received
+pub const TCP_STATE_CLOSE_WAIT: u8 = 8;
+
+/// Peer closed first, then local FIN sent
+pub const TCP_STATE_LAST_ACK: u8 = 9;
+
+/// Listening socket
+pub const TCP_STATE_LISTEN: u8 = 10;
+
+/// Simultaneous close
+pub const TCP_STATE_CLOSING: u8 = 11;
+
+/// Request socket for a received SYN
+pub const TCP_STATE_NEW_SYN_RECV: u8 = 12;
+
+// ============================================================================
+// TCP Flags (bits of LatencyEvent.tcp_flags, derived from the TCP state)
+// ============================================================================
+
+/// Handshake not complete (SYN sent or received, not yet acknowledged)
+pub const TCP_FLAG_SYN: u8 = 1 << 0;
+
+/// A FIN was sent or received
+pub const TCP_FLAG_FIN: u8 = 1 << 1;
+
+/// Connection closed without a FIN exchange: reset or aborted
+pub const TCP_FLAG_RST: u8 = 1 << 2;
+
 // ============================================================================
 // Connection States (for ConnectionState.state)
 // ============================================================================
@@ -68,12 +181,13 @@ pub const DROP_LOCATION_APP: u8 = 4;
 // Latency Thresholds
 // ============================================================================
 
-/// Maximum latency to consider valid (60 seconds in nanoseconds)
+/// Default maximum latency to consider valid (60 seconds in nanoseconds)
 /// Latencies above this are likely measurement errors
 pub const MAX_LATENCY_NS: u64 = 60_000_000_000;
 
-/// Minimum latency to consider valid (1 microsecond in nanoseconds)
-/// Latencies below this are likely measurement errors
+/// Default minimum latency to consider valid (1 microsecond in
+/// nanoseconds). Latencies below this are likely measurement errors,
+/// though loopback round trips on fast hosts can be shorter
 pub const MIN_LATENCY_NS: u64 = 1_000;
 
 // ============================================================================
@@ -105,6 +219,126 @@ pub const DEFAULT_SAMPLE_RATE: u32 = 1;
 /// Maximum sampling rate (capture 1 in N events)
 pub const MAX_SAMPLE_RATE: u32 = 1000;
 
+// ============================================================================
+// Event Pipeline (indices into the PIPELINE program array)
+// ============================================================================
+
+/// Stage that applies the service filter
+pub const PIPELINE_STAGE_FILTER: u32 = 0;
+
+/// Stage that applies kernel-side sampling
+pub const PIPELINE_STAGE_SAMPLE: u32 = 1;
+
+/// Stage that sends the event to userspace
+pub const PIPELINE_STAGE_EMIT: u32 = 2;
+
+/// Slots in the PIPELINE program array
+pub const MAX_PIPELINE_STAGES: u32 = 8;
+
+// ============================================================================
+// Runtime Configuration (indices into the CONFIG map)
+// ============================================================================
+
+/// Sampling rate applied in the kernel (0 or 1 = capture all events)
+pub const CONFIG_SAMPLE_RATE: u32 = 0;
+
+/// Non-zero when only connections in SERVICE_FILTER are reported
+pub const CONFIG_FILTER_ENABLED: u32 = 1;
+
+/// Byte offset of `skc_net` in `struct sock_common` (0 = netns capture disabled)
+pub const CONFIG_NETNS_SKC_NET_OFFSET: u32 = 2;
+
+/// Byte offset of the namespace inode (`ns.inum`) in `struct net`
+pub const CONFIG_NETNS_INUM_OFFSET: u32 = 3;
+
+/// Offset of `saddr` in the tcp:tcp_probe record (0 = not configured)
+pub const CONFIG_TCP_PROBE_SADDR_OFFSET: u32 = 4;
+
+/// Offset of `daddr` in the tcp:tcp_probe record
+pub const CONFIG_TCP_PROBE_DADDR_OFFSET: u32 = 5;
+
+/// Offset of `srtt` in the tcp:tcp_probe record
+pub const CONFIG_TCP_PROBE_SRTT_OFFSET: u32 = 6;
+
+/// Offset of `skaddr` in the tcp:tcp_probe record (0 = not present)
+pub const CONFIG_TCP_PROBE_SKADDR_OFFSET: u32 = 7;
+
+/// UDP destination port treated as QUIC (0 = QUIC tracking disabled)
+pub const CONFIG_QUIC_PORT: u32 = 8;
+
+/// Offset of skc_cookie in struct sock_common (0 = cookies not read)
+pub const CONFIG_SKC_COOKIE_OFFSET: u32 = 9;
+
+/// Clock event timestamps are read from (CLOCK_SOURCE_*)
+pub const CONFIG_CLOCK_SOURCE: u32 = 10;
+
+/// Smallest valid latency in nanoseconds (used with CONFIG_MAX_LATENCY_NS)
+pub const CONFIG_MIN_LATENCY_NS: u32 = 11;
+
+/// Largest valid latency in nanoseconds (0 = MIN_LATENCY_NS and
+/// MAX_LATENCY_NS apply)
+pub const CONFIG_MAX_LATENCY_NS: u32 = 12;
+
+/// How events are sampled (SAMPLE_MODE_*)
+pub const CONFIG_SAMPLE_MODE: u32 = 13;
+
+/// Offsets of the struct sock_common fields read by the probes, packed by
+/// `SockCommonLayout::pack` (0 = the compiled default layout)
+pub const CONFIG_SKC_LAYOUT: u32 = 14;
+
+/// First of the per event type sampling rates, at
+/// CONFIG_EVENT_SAMPLE_RATE + EVENT_TYPE_* (0 = CONFIG_SAMPLE_RATE applies)
+pub const CONFIG_EVENT_SAMPLE_RATE: u32 = 16;
+
+/// Offset of `snd_cwnd` in the tcp:tcp_probe record (0 = congestion state
+/// not tracked)
+pub const CONFIG_TCP_PROBE_SND_CWND_OFFSET: u32 = 25;
+
+/// Offset of `ssthresh` in the tcp:tcp_probe record
+pub const CONFIG_TCP_PROBE_SSTHRESH_OFFSET: u32 = 26;
+
+/// Offset of `sk_ack_backlog` in `struct sock` (0 = accept queues not
+/// tracked)
+pub const CONFIG_SK_ACK_BACKLOG_OFFSET: u32 = 27;
+
+/// Offset of `sk_max_ack_backlog` in `struct sock`
+pub const CONFIG_SK_MAX_ACK_BACKLOG_OFFSET: u32 = 28;
+
+/// Offset of the SYN queue length (`icsk_accept_queue.qlen`) in
+/// `struct inet_connection_sock` (0 = not read)
+pub const CONFIG_SYN_QUEUE_LEN_OFFSET: u32 = 29;
+
+/// Total number of configuration slots
+pub const MAX_CONFIG: u32 = 32;
+
+/// Bytes at the start of struct sock_common read at once; every field in
+/// a `SockCommonLayout` lies within them
+pub const SKC_LAYOUT_WINDOW: usize = 32;
+
+// ============================================================================
+// Sampling Modes (values of CONFIG_SAMPLE_MODE)
+// ============================================================================
+
+/// Keep a random 1 in N events
+pub const SAMPLE_MODE_EVENT: u64 = 0;
+
+/// Keep every event of 1 in N connections, picked by ConnectionKey::flow_hash
+pub const SAMPLE_MODE_FLOW: u64 = 1;
+
+// ============================================================================
+// Clock Sources (values of CONFIG_CLOCK_SOURCE)
+// ============================================================================
+
+/// bpf_ktime_get_ns: CLOCK_MONOTONIC, stops during suspend
+pub const CLOCK_SOURCE_MONOTONIC: u64 = 0;
+
+/// bpf_ktime_get_boot_ns: CLOCK_BOOTTIME, includes suspend (Linux 5.8+)
+pub const CLOCK_SOURCE_BOOTTIME: u64 = 1;
+
+/// bpf_ktime_get_tai_ns: CLOCK_TAI, comparable across synchronized nodes
+/// (Linux 6.1+, kernel crate feature `tai-clock`)
+pub const CLOCK_SOURCE_TAI: u64 = 2;
+
 // ============================================================================
 // Protocol Numbers (from linux/in.h)
 // ============================================================================
@@ -195,5 +429,50 @@ pub const STAT_XDP_OTHER_PACKETS: u32 = 15;
 /// Number of context switches observed
 pub const STAT_CONTEXT_SWITCHES: u32 = 16;
 
+/// Number of latency events discarded by the service filter
+pub const STAT_FILTERED_EVENTS: u32 = 17;
+
+/// Number of latency events discarded by kernel-side sampling
+pub const STAT_SAMPLED_OUT_EVENTS: u32 = 18;
+
+/// Number of tcp:tcp_probe tracepoint hits
+pub const STAT_TCP_PROBE_EVENTS: u32 = 19;
+
+/// Number of DNS queries sent
+pub const STAT_DNS_QUERIES: u32 = 20;
+
+/// Number of DNS responses matched to a query
+pub const STAT_DNS_RESPONSES: u32 = 21;
+
+/// Number of HTTP responses whose status line was classified
+pub const STAT_HTTP_RESPONSES: u32 = 22;
+
+/// Number of datagrams sent to the QUIC port
+pub const STAT_QUIC_PACKETS: u32 = 23;
+
+/// Number of QUIC flights matched to a response
+pub const STAT_QUIC_RESPONSES: u32 = 24;
+
+/// Number of latency events handled inline because a pipeline stage was
+/// not loaded
+pub const STAT_PIPELINE_FALLBACKS: u32 = 25;
+
+/// Number of RSTs received
+pub const STAT_RESETS_RECEIVED: u32 = 26;
+
+/// Number of RSTs sent
+pub const STAT_RESETS_SENT: u32 = 27;
+
+/// Number of retransmission timeouts
+pub const STAT_TIMEOUTS: u32 = 28;
+
+/// Number of latencies rejected below the minimum (also counted in
+/// STAT_INVALID_LATENCY)
+pub const STAT_LATENCY_BELOW_MIN: u32 = 29;
+
+/// Number of latencies rejected above the maximum (also counted in
+/// STAT_INVALID_LATENCY)
+pub const STAT_LATENCY_ABOVE_MAX: u32 = 30;
+
 /// Total number of statistics counters
 pub const MAX_STATS: u32 = 32;
@@ -7,6 +7,8 @@
 
 pub mod types;
 pub mod constants;
+#[cfg(feature = "proto")]
+pub mod proto;
 
 // Re-export commonly used types
 pub use types::{ConnectionKey, LatencyEvent, PacketDropEvent, ConnectionState};
@@ -0,0 +1,70 @@
+//! Protobuf types (generated from proto/latency.proto)
+//!
+//! Converts between the kernel event layout and its protobuf message.
+//! Report messages are filled in by the daemon, which owns the report types.
+
+include!(concat!(env!("OUT_DIR"), "/latency_probe.v1.rs"));
+
+impl From<&crate::ConnectionKey> for ConnectionKey {
+    fn from(key: &crate::ConnectionKey) -> Self {
+        Self {
+            saddr: u32::from_be(key.saddr),
+            daddr: u32::from_be(key.daddr),
+            sport: u16::from_be(key.sport).into(),
+            dport: u16::from_be(key.dport).into(),
+        }
+    }
+}
+
+impl From<&ConnectionKey> for crate::ConnectionKey {
+    fn from(key: &ConnectionKey) -> Self {
+        Self {
+            saddr: key.saddr.to_be(),
+            daddr: key.daddr.to_be(),
+            sport: (key.sport as u16).to_be(),
+            dport: (key.dport as u16).to_be(),
+        }
+    }
+}
+
+impl From<&crate::LatencyEvent> for LatencyEvent {
+    fn from(event: &crate::LatencyEvent) -> Self {
+        Self {
+            key: Some((&event.key).into()),
+            netns: event.netns,
+            cookie: event.cookie,
+            timestamp_ns: event.timestamp_ns,
+            latency_ns: event.latency_ns,
+            pid: event.pid,
+            event_type: event.event_type.into(),
+            http_status_class: event.http_status_class.into(),
+            tcp_state: event.tcp_state.into(),
+            tcp_flags: event.tcp_flags.into(),
+            protocol: event.protocol.into(),
+        }
+    }
+}
+
+impl From<&LatencyEvent> for crate::LatencyEvent {
+    fn from(event: &LatencyEvent) -> Self {
+        Self {
+            key: event.key.as_ref().map(Into::into).unwrap_or(crate::ConnectionKey {
+                saddr: 0,
+                daddr: 0,
+                sport: 0,
+                dport: 0,
+            }),
+            netns: event.netns,
+            cookie: event.cookie,
+            timestamp_ns: event.timestamp_ns,
+            latency_ns: event.latency_ns,
+            pid: event.pid,
+            event_type: event.event_type as u8,
+            http_status_class: event.http_status_class as u8,
+            tcp_state: event.tcp_state as u8,
+            tcp_flags: event.tcp_flags as u8,
+            protocol: event.protocol as u8,
+            _padding: [0; 7],
+        }
+    }
+}
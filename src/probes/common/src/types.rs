@@ -6,9 +6,10 @@
 /// Connection tracking key (4-tuple)
 ///
 /// Used to uniquely identify TCP connections in BPF maps.
-/// All fields are in network byte order (big-endian).
+/// All fields are in network byte order (big-endian). Compares and orders
+/// field by field, so userspace can key maps on it directly.
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ConnectionKey {
     /// Source IP address (network byte order)
     pub saddr: u32,
@@ -20,6 +21,45 @@ pub struct ConnectionKey {
     pub dport: u16,
 }
 
+impl ConnectionKey {
+    /// Hash of the connection, the same from both of its ends
+    ///
+    /// Picks the connections kept by flow sampling; the kernel and replays
+    /// must agree on it, so it lives here.
+    #[inline(always)]
+    pub fn flow_hash(&self) -> u32 {
+        let local = mix(self.saddr ^ ((self.sport as u32) << 16 | self.sport as u32));
+        let remote = mix(self.daddr ^ ((self.dport as u32) << 16 | self.dport as u32));
+        mix(local.wrapping_add(remote))
+    }
+}
+
+/// Finalizer of MurmurHash3: spreads every input bit over the output
+#[inline(always)]
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+/// Service filter entry (address and port)
+///
+/// Key of the SERVICE_FILTER map. A zero address matches any address on
+/// the port, and a zero port any port of the address. All fields are in network byte order (big-endian).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ServiceFilterKey {
+    /// IP address (network byte order, 0 = any)
+    pub addr: u32,
+    /// Port (network byte order)
+    pub port: u16,
+    /// Padding for alignment
+    pub _padding: [u8; 2],
+}
+
 /// Latency event data sent from kernel to userspace
 ///
 /// Captures timing information for network operations.
@@ -28,6 +68,11 @@ pub struct ConnectionKey {
 pub struct LatencyEvent {
     /// Connection identifier
     pub key: ConnectionKey,
+    /// Network namespace inode of the socket (0 if unknown)
+    pub netns: u32,
+    /// Socket cookie, unique per socket for the life of the host (0 if
+    /// unknown); unlike the 4-tuple it is never reused
+    pub cookie: u64,
     /// Timestamp when event occurred (nanoseconds)
     pub timestamp_ns: u64,
     /// Measured latency (nanoseconds)
@@ -36,8 +81,30 @@ pub struct LatencyEvent {
     pub pid: u32,
     /// Type of event (see EVENT_TYPE_* constants)
     pub event_type: u8,
+    /// First digit of the HTTP status of the last response on the
+    /// connection (see HTTP_STATUS_* constants, 0 if unknown)
+    pub http_status_class: u8,
+    /// TCP state of the socket when the event fired (see TCP_STATE_*
+    /// constants, 0 if unknown)
+    pub tcp_state: u8,
+    /// SYN/FIN/RST indicators (see TCP_FLAG_* constants)
+    pub tcp_flags: u8,
+    /// Transport protocol (IPPROTO_TCP, IPPROTO_UDP, 0 if unknown)
+    pub protocol: u8,
     /// Padding for alignment
-    pub _padding: [u8; 3],
+    pub _padding: [u8; 7],
+}
+
+/// Outstanding DNS query or QUIC flight
+///
+/// Value of the DNS_START and QUIC_START maps.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DnsQuery {
+    /// Query 4-tuple (client to server)
+    pub key: ConnectionKey,
+    /// Timestamp when the query was sent (nanoseconds)
+    pub start_ns: u64,
 }
 
 /// Packet drop event data
@@ -100,6 +167,68 @@ pub struct XdpConnStats {
     pub drop_count: u64,
 }
 
+/// Congestion control state of a connection
+///
+/// Updated on each tcp:tcp_probe record. Counters are cumulative;
+/// userspace computes per-interval values from the difference between
+/// readings.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CongestionState {
+    /// Records seen
+    pub samples: u64,
+    /// Sum of the smoothed RTTs recorded (microseconds)
+    pub srtt_sum_us: u64,
+    /// Times the congestion window fell to half or less of the previous record
+    pub cwnd_collapses: u64,
+    /// Congestion window at the last record (segments)
+    pub snd_cwnd: u32,
+    /// Slow start threshold at the last record (segments)
+    pub ssthresh: u32,
+    /// Smoothed RTT at the last record (microseconds)
+    pub srtt_us: u32,
+    /// Smallest congestion window recorded (segments)
+    pub min_snd_cwnd: u32,
+    /// Largest smoothed RTT recorded (microseconds)
+    pub max_srtt_us: u32,
+    /// Padding for alignment
+    pub _padding: [u8; 4],
+}
+
+/// Listening socket identifier
+///
+/// Key of the LISTENERS map.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ListenerKey {
+    /// Network namespace inode (0 if unknown)
+    pub netns: u32,
+    /// Listening port (host byte order)
+    pub port: u16,
+    /// Padding for alignment
+    pub _padding: [u8; 2],
+}
+
+/// Accept queue counters of a listening socket
+///
+/// Counters are cumulative; userspace computes per-interval values from
+/// the difference between readings.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ListenerStats {
+    /// Connection requests (SYNs) received
+    pub requests: u64,
+    /// Requests dropped because the accept queue was full, at the SYN or
+    /// at the handshake's final ACK (the kernel's ListenOverflows)
+    pub overflows: u64,
+    /// SYNs received while the SYN queue was full
+    pub syn_queue_full: u64,
+    /// Longest accept queue seen
+    pub max_backlog: u32,
+    /// Accept queue limit (the listen() backlog, capped by somaxconn)
+    pub backlog_limit: u32,
+}
+
 /// Context switch event data
 ///
 /// Captures scheduler context switch information for overhead analysis.
@@ -114,21 +243,106 @@ pub struct ContextSwitchEvent {
     pub next_pid: u32,
 }
 
+/// Byte offsets of the struct sock_common fields the probes read
+///
+/// The leading fields of sock_common hold no pointers, so their layout is
+/// the same on x86_64 and aarch64; userspace still resolves it from the
+/// running kernel's BTF and passes it through CONFIG_SKC_LAYOUT, one byte
+/// per field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SockCommonLayout {
+    /// `skc_daddr` (u32)
+    pub daddr: u8,
+    /// `skc_rcv_saddr` (u32)
+    pub rcv_saddr: u8,
+    /// `skc_dport` (u16)
+    pub dport: u8,
+    /// `skc_num` (u16)
+    pub num: u8,
+    /// `skc_family` (u16)
+    pub family: u8,
+    /// `skc_state` (u8)
+    pub state: u8,
+}
+
+impl SockCommonLayout {
+    /// Layout of mainline kernels since 4.x, on every 64-bit architecture
+    pub const DEFAULT: Self = Self {
+        daddr: 0,
+        rcv_saddr: 4,
+        dport: 12,
+        num: 14,
+        family: 16,
+        state: 18,
+    };
+
+    /// Marks a packed layout, so that offset 0 is not mistaken for unset
+    const PACKED: u64 = 1 << 56;
+
+    /// Whether every field lies within SKC_LAYOUT_WINDOW
+    pub const fn fits(&self) -> bool {
+        let window = crate::constants::SKC_LAYOUT_WINDOW;
+        self.daddr as usize + 4 <= window
+            && self.rcv_saddr as usize + 4 <= window
+            && self.dport as usize + 2 <= window
+            && self.num as usize + 2 <= window
+            && self.family as usize + 2 <= window
+            && (self.state as usize) < window
+    }
+
+    /// Pack into a CONFIG_SKC_LAYOUT value
+    pub const fn pack(&self) -> u64 {
+        Self::PACKED
+            | self.daddr as u64
+            | (self.rcv_saddr as u64) << 8
+            | (self.dport as u64) << 16
+            | (self.num as u64) << 24
+            | (self.family as u64) << 32
+            | (self.state as u64) << 40
+    }
+
+    /// Unpack a CONFIG_SKC_LAYOUT value (None if unset)
+    #[inline(always)]
+    pub const fn unpack(value: u64) -> Option<Self> {
+        if value & Self::PACKED == 0 {
+            return None;
+        }
+        Some(Self {
+            daddr: value as u8,
+            rcv_saddr: (value >> 8) as u8,
+            dport: (value >> 16) as u8,
+            num: (value >> 24) as u8,
+            family: (value >> 32) as u8,
+            state: (value >> 40) as u8,
+        })
+    }
+}
+
 // Compile-time alignment checks
 // These will fail to compile if alignment is wrong
 const _: () = {
     // ConnectionKey alignment check
-    assert!(core::mem::size_of::<ConnectionKey>() % core::mem::align_of::<ConnectionKey>() == 0);
+    assert!(core::mem::size_of::<ConnectionKey>().is_multiple_of(core::mem::align_of::<ConnectionKey>()));
+    // ServiceFilterKey alignment check
+    assert!(core::mem::size_of::<ServiceFilterKey>().is_multiple_of(core::mem::align_of::<ServiceFilterKey>()));
     // LatencyEvent alignment check
-    assert!(core::mem::size_of::<LatencyEvent>() % core::mem::align_of::<LatencyEvent>() == 0);
+    assert!(core::mem::size_of::<LatencyEvent>().is_multiple_of(core::mem::align_of::<LatencyEvent>()));
     // PacketDropEvent alignment check
-    assert!(core::mem::size_of::<PacketDropEvent>() % core::mem::align_of::<PacketDropEvent>() == 0);
+    assert!(core::mem::size_of::<PacketDropEvent>().is_multiple_of(core::mem::align_of::<PacketDropEvent>()));
     // ConnectionState alignment check
-    assert!(core::mem::size_of::<ConnectionState>() % core::mem::align_of::<ConnectionState>() == 0);
+    assert!(core::mem::size_of::<ConnectionState>().is_multiple_of(core::mem::align_of::<ConnectionState>()));
     // XdpConnStats alignment check
-    assert!(core::mem::size_of::<XdpConnStats>() % core::mem::align_of::<XdpConnStats>() == 0);
+    assert!(core::mem::size_of::<XdpConnStats>().is_multiple_of(core::mem::align_of::<XdpConnStats>()));
+    // CongestionState alignment check
+    assert!(core::mem::size_of::<CongestionState>().is_multiple_of(core::mem::align_of::<CongestionState>()));
+    // ListenerKey alignment check
+    assert!(core::mem::size_of::<ListenerKey>().is_multiple_of(core::mem::align_of::<ListenerKey>()));
+    // ListenerStats alignment check
+    assert!(core::mem::size_of::<ListenerStats>().is_multiple_of(core::mem::align_of::<ListenerStats>()));
     // ContextSwitchEvent alignment check
-    assert!(core::mem::size_of::<ContextSwitchEvent>() % core::mem::align_of::<ContextSwitchEvent>() == 0);
+    assert!(core::mem::size_of::<ContextSwitchEvent>().is_multiple_of(core::mem::align_of::<ContextSwitchEvent>()));
+    // DnsQuery alignment check
+    assert!(core::mem::size_of::<DnsQuery>().is_multiple_of(core::mem::align_of::<DnsQuery>()));
 };
 
 // Implement Aya's Pod trait for userspace usage
@@ -138,9 +352,14 @@ mod userspace_impls {
 
     // Pod trait implementations for reading from perf buffers in userspace
     unsafe impl aya::Pod for ConnectionKey {}
+    unsafe impl aya::Pod for ServiceFilterKey {}
     unsafe impl aya::Pod for LatencyEvent {}
     unsafe impl aya::Pod for PacketDropEvent {}
     unsafe impl aya::Pod for ConnectionState {}
     unsafe impl aya::Pod for XdpConnStats {}
+    unsafe impl aya::Pod for CongestionState {}
+    unsafe impl aya::Pod for ListenerKey {}
+    unsafe impl aya::Pod for ListenerStats {}
     unsafe impl aya::Pod for ContextSwitchEvent {}
+    unsafe impl aya::Pod for DnsQuery {}
 }
@@ -1,7 +1,19 @@
 #!/bin/bash
 set -e
 
-echo "🔨 Building eBPF Latency Probe..."
+# Architecture of the nodes the probe runs on (x86_64 or aarch64).
+# kprobe objects are architecture-specific, so cross-builds set it.
+ARCH="${ARCH:-$(uname -m)}"
+case $ARCH in
+    x86_64|aarch64) ;;
+    arm64) ARCH=aarch64 ;;
+    *)
+        echo "Unsupported ARCH: $ARCH (expected x86_64 or aarch64)"
+        exit 1
+        ;;
+esac
+
+echo "🔨 Building eBPF Latency Probe for $ARCH..."
 echo
 
 # Colors for output
@@ -79,9 +91,11 @@ echo
 echo "${BLUE}🏗️  Building eBPF kernel program...${NC}"
 cd kernel
 
-if cargo +nightly build --release --target=bpfel-unknown-none -Zbuild-std=core; then
+if cargo +nightly build --release --target=bpfel-unknown-none -Zbuild-std=core \
+    --config "target.bpfel-unknown-none.rustflags=[\"--cfg\", \"bpf_target_arch=\\\"$ARCH\\\"\"]" \
+    --target-dir "target/$ARCH"; then
     echo "${GREEN}✓ eBPF program built successfully${NC}"
-    echo "   Output: target/bpfel-unknown-none/release/latency-probe"
+    echo "   Output: target/$ARCH/bpfel-unknown-none/release/latency-probe"
 else
     echo "${RED}❌ eBPF build failed${NC}"
     exit 1
@@ -94,9 +108,19 @@ echo
 echo "${BLUE}🏗️  Building userspace loader...${NC}"
 cd daemon
 
-if cargo build --release; then
+if [ "$ARCH" = "$(uname -m)" ]; then
+    DAEMON_TARGET=""
+    DAEMON_OUTPUT="target/release/latency-probe"
+else
+    # Needs the target's std and a cross linker, e.g.
+    # rustup target add aarch64-unknown-linux-gnu
+    DAEMON_TARGET="--target=$ARCH-unknown-linux-gnu"
+    DAEMON_OUTPUT="target/$ARCH-unknown-linux-gnu/release/latency-probe"
+fi
+
+if cargo build --release $DAEMON_TARGET; then
     echo "${GREEN}✓ Userspace program built successfully${NC}"
-    echo "   Output: target/release/latency-probe"
+    echo "   Output: $DAEMON_OUTPUT"
 else
     echo "${RED}❌ Userspace build failed${NC}"
     exit 1
@@ -108,7 +132,7 @@ echo
 # Set capabilities (optional, requires sudo)
 if [ "$EUID" -eq 0 ]; then
     echo "${BLUE}🔐 Setting capabilities...${NC}"
-    setcap cap_bpf,cap_net_admin=ep daemon/target/release/latency-probe
+    setcap cap_bpf,cap_net_admin=ep "daemon/$DAEMON_OUTPUT"
     echo "${GREEN}✓ Capabilities set (can run without sudo)${NC}"
 else
     echo "${BLUE}ℹ️  Run with sudo to set capabilities: sudo ./build.sh${NC}"
@@ -118,6 +142,9 @@ echo
 echo "${GREEN}✅ Build complete!${NC}"
 echo
 echo "Usage:"
-echo "  sudo ./daemon/target/release/latency-probe"
-echo "  sudo ./daemon/target/release/latency-probe --help"
+echo "  sudo ./daemon/$DAEMON_OUTPUT --ebpf-object kernel/target/$ARCH/bpfel-unknown-none/release/latency-probe"
+echo "  sudo ./daemon/$DAEMON_OUTPUT --help"
+echo
+echo "For a single binary with the eBPF program embedded:"
+echo "  (cd daemon && cargo build --release --features embedded)"
 echo
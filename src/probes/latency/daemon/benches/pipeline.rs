@@ -0,0 +1,107 @@
+//! Benchmarks of the userspace pipeline
+//!
+//! The daemon shares the nodes it measures, so time it spends per event is
+//! time taken from the mesh. These cover the per-event path (decoding and
+//! collecting) and the per-report path (percentiles and export), on
+//! synthetic events.
+//!
+//! Run with `cargo bench -p latency-probe-userspace`; criterion compares
+//! each run against the previous one in target/criterion.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use latency_probe_userspace::{
+    collector::MetricsCollector,
+    events::decode_event,
+    exporter::PrometheusExporter,
+    testing::{SyntheticConfig, SyntheticEventGenerator},
+    types::{calculate_percentiles, LatencyEvent, LatencyMetrics},
+};
+
+/// Events in each benchmark batch
+const EVENTS: usize = 10_000;
+
+fn events(connections: usize) -> Vec<LatencyEvent> {
+    SyntheticEventGenerator::new(SyntheticConfig {
+        events: EVENTS,
+        connections,
+        ..Default::default()
+    })
+    .collect()
+}
+
+fn metrics() -> LatencyMetrics {
+    let mut collector = MetricsCollector::new();
+    for event in events(100) {
+        collector.add_event(&event);
+    }
+    collector.generate_metrics(60)
+}
+
+fn bench_add_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("collector");
+    group.throughput(Throughput::Elements(EVENTS as u64));
+    for connections in [10, 1000] {
+        let events = events(connections);
+        group.bench_function(format!("add_event/{}_connections", connections), |b| {
+            b.iter_batched_ref(
+                MetricsCollector::new,
+                |collector| {
+                    for event in &events {
+                        collector.add_event(event);
+                    }
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_percentiles(c: &mut Criterion) {
+    let samples: Vec<f64> = events(10).iter().map(|event| event.latency_ns as f64 / 1000.0).collect();
+    let mut group = c.benchmark_group("percentiles");
+    group.throughput(Throughput::Elements(samples.len() as u64));
+    group.bench_function("calculate", |b| {
+        b.iter_batched(|| samples.clone(), calculate_percentiles, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn bench_export(c: &mut Criterion) {
+    let metrics = metrics();
+    let mut group = c.benchmark_group("export");
+    group.bench_function("json", |b| b.iter(|| serde_json::to_string(black_box(&metrics)).unwrap()));
+    group.bench_function("prometheus", |b| {
+        b.iter(|| PrometheusExporter::to_prometheus_format(black_box(&metrics)))
+    });
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    // Perf buffer records, as the readers receive them
+    let records: Vec<Vec<u8>> = events(10)
+        .iter()
+        .map(|event| {
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    (event as *const LatencyEvent).cast::<u8>(),
+                    std::mem::size_of::<LatencyEvent>(),
+                )
+            };
+            bytes.to_vec()
+        })
+        .collect();
+    let mut group = c.benchmark_group("events");
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.bench_function("decode", |b| {
+        b.iter(|| {
+            for record in &records {
+                black_box(decode_event::<LatencyEvent>(black_box(record)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_add_event, bench_percentiles, bench_export, bench_decode);
+criterion_main!(benches);
@@ -0,0 +1,123 @@
+//! Build script for the userspace probe
+//!
+//! With the `embedded` feature, compiles the kernel crate for
+//! bpfel-unknown-none and passes the object path to the loader through
+//! `LATENCY_PROBE_EBPF_OBJECT`, producing a single self-contained binary.
+//!
+//! The eBPF build needs a nightly toolchain (for `-Z build-std`) and
+//! bpf-linker. Set `LATENCY_PROBE_EBPF_TOOLCHAIN` to use a toolchain other
+//! than `nightly`.
+//!
+//! kprobe arguments are read from `struct pt_regs`, whose layout depends on
+//! the architecture the object will run on. aya-ebpf takes it from the
+//! `bpf_target_arch` cfg, which defaults to the build host's; it is set
+//! here to the daemon's target architecture, so cross-compiled binaries
+//! embed an object for the node they run on. `LATENCY_PROBE_EBPF_ARCH`
+//! overrides it.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// eBPF target triple
+const TARGET: &str = "bpfel-unknown-none";
+
+/// Binary name of the kernel crate
+const OBJECT_NAME: &str = "latency-probe";
+
+/// Environment set by the outer cargo that must not leak into the eBPF build
+const ISOLATED_ENV: &[&str] = &[
+    "RUSTC",
+    "RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_TARGET",
+    "CARGO_TARGET_DIR",
+    "RUSTUP_TOOLCHAIN",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=LATENCY_PROBE_EBPF_TOOLCHAIN");
+    println!("cargo:rerun-if-env-changed=LATENCY_PROBE_EBPF_ARCH");
+
+    if env::var_os("CARGO_FEATURE_EMBEDDED").is_none() {
+        return;
+    }
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let kernel_dir = manifest_dir.join("../kernel");
+    let common_dir = manifest_dir.join("../../common");
+    let target_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("ebpf");
+
+    for path in [
+        kernel_dir.join("src"),
+        kernel_dir.join("Cargo.toml"),
+        kernel_dir.join(".cargo/config.toml"),
+        common_dir.join("src"),
+        common_dir.join("Cargo.toml"),
+    ] {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    build_ebpf(&kernel_dir, &target_dir);
+
+    let object = target_dir.join(TARGET).join("release").join(OBJECT_NAME);
+    if !object.exists() {
+        panic!("eBPF build succeeded but {} is missing", object.display());
+    }
+    println!("cargo:rustc-env=LATENCY_PROBE_EBPF_OBJECT={}", object.display());
+}
+
+/// Compile the kernel crate into `target_dir`
+///
+/// Uses its own target directory so the nested cargo does not contend for
+/// the outer build's lock.
+fn build_ebpf(kernel_dir: &Path, target_dir: &Path) {
+    let toolchain =
+        env::var("LATENCY_PROBE_EBPF_TOOLCHAIN").unwrap_or_else(|_| "nightly".to_string());
+    let arch = env::var("LATENCY_PROBE_EBPF_ARCH")
+        .or_else(|_| env::var("CARGO_CFG_TARGET_ARCH"))
+        .unwrap();
+
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(kernel_dir)
+        .arg(format!("+{}", toolchain))
+        .args(["build", "--release", "--target", TARGET, "-Z", "build-std=core"])
+        .arg("--target-dir")
+        .arg(target_dir)
+        // Appended to the rustflags of the kernel crate's .cargo/config.toml
+        .arg("--config")
+        .arg(format!(
+            "target.{}.rustflags=[\"--cfg\", \"bpf_target_arch=\\\"{}\\\"\"]",
+            TARGET, arch
+        ));
+    // Kernel features selected through this crate's features
+    if env::var_os("CARGO_FEATURE_PERCPU_CONNECTION_START").is_some() {
+        command.args(["--features", "percpu-connection-start"]);
+    }
+    if env::var_os("CARGO_FEATURE_TAI_CLOCK").is_some() {
+        command.args(["--features", "tai-clock"]);
+    }
+    for var in ISOLATED_ENV {
+        command.env_remove(var);
+    }
+
+    let status = command.status().unwrap_or_else(|e| {
+        panic!(
+            "Failed to run cargo for the eBPF build ({}). \
+             Install rustup and bpf-linker, or build without the 'embedded' feature",
+            e
+        )
+    });
+    if !status.success() {
+        panic!(
+            "eBPF build failed ({}). Requires the {} toolchain with rust-src and bpf-linker",
+            status, toolchain
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! Perf buffer records of any size and content, as a kernel object built
+//! from a different `LatencyEvent` layout would send them
+//!
+//! Decoding must reject short records, collecting must accept any field
+//! values, and the report must still export to valid documents.
+
+#![no_main]
+
+use latency_probe_userspace::{
+    collector::MetricsCollector,
+    events::decode_event,
+    exporter::{InfluxExporter, PrometheusExporter},
+    types::LatencyEvent,
+};
+use latency_probe_userspace_fuzz::{check_influx, check_prometheus};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte sets the record size, around the expected one
+    let Some((&size, records)) = data.split_first() else {
+        return;
+    };
+    let size = std::mem::size_of::<LatencyEvent>() - 8 + (size as usize % 16);
+
+    let mut collector = MetricsCollector::new();
+    for record in records.chunks(size) {
+        let event = decode_event::<LatencyEvent>(record);
+        assert_eq!(event.is_some(), record.len() >= std::mem::size_of::<LatencyEvent>());
+        if let Some(event) = event {
+            collector.add_event(&event);
+        }
+    }
+
+    let metrics = collector.generate_metrics(1);
+    check_prometheus(&PrometheusExporter::to_prometheus_format(&metrics));
+    check_influx(&InfluxExporter::to_influx_format(&metrics, "latency"));
+});
@@ -0,0 +1,79 @@
+//! Reports with arbitrary values and label strings
+//!
+//! Whatever the values, the Prometheus and InfluxDB documents must parse:
+//! labels escaped, NaN and infinities represented or dropped.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use latency_probe_userspace::{
+    exporter::{InfluxExporter, PrometheusExporter},
+    types::{LatencyMetrics, NamespaceMetrics, Percentiles, ServiceMetrics},
+};
+use latency_probe_userspace_fuzz::{check_influx, check_prometheus};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Breakdown {
+    name: String,
+    events: u64,
+    avg_latency_us: f64,
+    percentiles: [f64; 6],
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    total_events: u64,
+    duration_seconds: u64,
+    percentiles: [f64; 6],
+    jitter: [f64; 2],
+    services: Vec<Breakdown>,
+    namespaces: Vec<Breakdown>,
+    instance: Option<String>,
+}
+
+fn percentiles([p50, p75, p90, p95, p99, p999]: [f64; 6]) -> Percentiles {
+    Percentiles {
+        p50,
+        p75,
+        p90,
+        p95,
+        p99,
+        p999,
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let mut metrics = LatencyMetrics {
+        total_events: input.total_events,
+        duration_seconds: input.duration_seconds,
+        percentiles: percentiles(input.percentiles),
+        instance: input.instance,
+        ..Default::default()
+    };
+    metrics.jitter.mean_us = input.jitter[0];
+    metrics.jitter.max_us = input.jitter[1];
+    for service in input.services {
+        metrics.services.insert(
+            service.name,
+            ServiceMetrics {
+                events: service.events,
+                avg_latency_us: service.avg_latency_us,
+                percentiles: percentiles(service.percentiles),
+            },
+        );
+    }
+    for namespace in input.namespaces {
+        metrics.namespaces.insert(
+            namespace.name,
+            NamespaceMetrics {
+                events: namespace.events,
+                avg_latency_us: namespace.avg_latency_us,
+                percentiles: percentiles(namespace.percentiles),
+            },
+        );
+    }
+
+    check_prometheus(&PrometheusExporter::to_prometheus_format(&metrics));
+    check_influx(&InfluxExporter::to_influx_format(&metrics, "latency"));
+});
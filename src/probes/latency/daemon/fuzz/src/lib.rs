@@ -0,0 +1,125 @@
+//! Checks of exporter output shared by the fuzz targets
+//!
+//! Each check panics on the first line a scraper or InfluxDB would reject,
+//! which libFuzzer reports as a crash.
+
+/// Whether a value parses as a sample value (Prometheus accepts NaN and
+/// infinities)
+fn is_number(value: &str) -> bool {
+    value.parse::<f64>().is_ok()
+}
+
+/// Check a document in the Prometheus text format
+pub fn check_prometheus(text: &str) {
+    for line in text.lines() {
+        if line.is_empty() || line.starts_with("# HELP ") || line.starts_with("# TYPE ") {
+            continue;
+        }
+
+        let name_end = line.find(['{', ' ']).unwrap_or_else(|| panic!("No value: {:?}", line));
+        let name = &line[..name_end];
+        assert!(
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':'),
+            "Invalid metric name: {:?}",
+            line
+        );
+
+        let mut rest = &line[name_end..];
+        if let Some(labels) = rest.strip_prefix('{') {
+            rest = check_labels(labels, line);
+        }
+
+        let mut parts = rest.strip_prefix(' ').unwrap_or_else(|| panic!("No value: {:?}", line)).split(' ');
+        let value = parts.next().unwrap_or_default();
+        assert!(is_number(value), "Invalid value {:?}: {:?}", value, line);
+        if let Some(timestamp) = parts.next() {
+            assert!(timestamp.parse::<i64>().is_ok(), "Invalid timestamp: {:?}", line);
+        }
+        assert!(parts.next().is_none(), "Trailing text: {:?}", line);
+    }
+}
+
+/// Check the labels of a sample, up to the closing brace
+///
+/// # Returns
+///
+/// The rest of the line
+fn check_labels<'a>(mut labels: &'a str, line: &str) -> &'a str {
+    loop {
+        if let Some(rest) = labels.strip_prefix('}') {
+            return rest;
+        }
+        let (name, value) = labels.split_once("=\"").unwrap_or_else(|| panic!("Invalid label: {:?}", line));
+        assert!(
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "Invalid label name {:?}: {:?}",
+            name,
+            line
+        );
+
+        // The value runs to the first unescaped quote
+        let mut chars = value.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, '\\' | '"' | 'n')) => {}
+                    _ => panic!("Invalid escape in label value: {:?}", line),
+                },
+                Some((i, '"')) => break i,
+                Some(_) => {}
+                None => panic!("Unterminated label value: {:?}", line),
+            }
+        };
+
+        labels = &value[end + 1..];
+        labels = labels.strip_prefix(',').unwrap_or(labels);
+    }
+}
+
+/// Split at the separators not escaped by a backslash
+fn split_unescaped(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if c == separator && !escaped {
+            parts.push(&text[start..i]);
+            start = i + 1;
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Check a document in the InfluxDB line protocol
+pub fn check_influx(text: &str) {
+    for line in text.lines() {
+        let [series, fields, timestamp] = split_unescaped(line, ' ')[..] else {
+            panic!("Not a series, fields and timestamp: {:?}", line);
+        };
+
+        let mut tags = split_unescaped(series, ',').into_iter();
+        assert!(!tags.next().unwrap_or_default().is_empty(), "No measurement: {:?}", line);
+        for tag in tags {
+            let [key, value] = split_unescaped(tag, '=')[..] else {
+                panic!("Invalid tag {:?}: {:?}", tag, line);
+            };
+            assert!(!key.is_empty() && !value.is_empty(), "Empty tag {:?}: {:?}", tag, line);
+        }
+
+        for field in fields.split(',') {
+            let (key, value) = field.split_once('=').unwrap_or_else(|| panic!("Invalid field {:?}: {:?}", field, line));
+            assert!(!key.is_empty(), "Empty field key: {:?}", line);
+            let valid = match value.strip_suffix('i') {
+                Some(integer) => integer.parse::<i64>().is_ok() || integer.parse::<u64>().is_ok(),
+                None => value.parse::<f64>().is_ok_and(f64::is_finite) || matches!(value, "true" | "false"),
+            };
+            assert!(valid, "Invalid field value {:?}: {:?}", value, line);
+        }
+
+        assert!(timestamp.parse::<i64>().is_ok(), "Invalid timestamp: {:?}", line);
+    }
+}
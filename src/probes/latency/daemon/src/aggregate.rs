@@ -0,0 +1,107 @@
+//! Cluster-wide aggregation
+//!
+//! The probes of a DaemonSet each see one node. With `--cluster-lease`,
+//! one of them is elected aggregator (see crate::leader): the others push
+//! each report and snapshot they export to its HTTP API
+//! (`POST /reports?from=<probe>`), and it serves the merge of the latest
+//! report of every probe, its own included, at `GET /aggregate`.
+//!
+//! Only the latest report of each probe is kept, so a probe pushing
+//! snapshots replaces its previous one rather than being counted twice.
+//! Reports are merged as by `merge` (see crate::merge). Probes that are not
+//! the aggregator refuse pushes, so a probe that missed a change of leader
+//! retries against the new one; a new aggregator starts empty and fills up
+//! with the next push of every probe.
+
+use crate::types::LatencyMetrics;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Latest report of every probe of the cluster
+#[derive(Default)]
+pub struct Aggregate {
+    reports: Mutex<BTreeMap<String, LatencyMetrics>>,
+    leading: AtomicBool,
+}
+
+impl Aggregate {
+    /// Create an empty aggregate of a probe that is not the aggregator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether this probe is the aggregator
+    pub fn is_leading(&self) -> bool {
+        self.leading.load(Ordering::Relaxed)
+    }
+
+    /// Record whether this probe is the aggregator; reports kept from an
+    /// earlier term are dropped when it becomes the aggregator again
+    pub fn set_leading(&self, leading: bool) {
+        if leading && !self.leading.swap(true, Ordering::Relaxed) {
+            self.reports.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        self.leading.store(leading, Ordering::Relaxed);
+    }
+
+    /// Keep the latest report of a probe
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Identity of the probe (its advertised address)
+    /// * `report` - Report or snapshot, replacing the probe's previous one
+    pub fn submit(&self, from: &str, report: LatencyMetrics) {
+        self.reports
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(from.to_string(), report);
+    }
+
+    /// Probes that submitted a report
+    pub fn probes(&self) -> Vec<String> {
+        self.reports.lock().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect()
+    }
+
+    /// Merge of the latest reports (None before any was submitted)
+    pub fn merged(&self) -> Option<LatencyMetrics> {
+        let reports = self.reports.lock().unwrap_or_else(|e| e.into_inner());
+        let reports: Vec<LatencyMetrics> = reports.values().cloned().collect();
+        LatencyMetrics::merged(&reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_latest_reports() {
+        let report = |total_events: u64| LatencyMetrics {
+            total_events,
+            ..Default::default()
+        };
+        let aggregate = Aggregate::new();
+        assert!(aggregate.merged().is_none());
+
+        aggregate.submit("10.0.0.1:9100", report(10));
+        aggregate.submit("10.0.0.2:9100", report(5));
+        // A newer snapshot replaces the probe's previous one
+        aggregate.submit("10.0.0.1:9100", report(20));
+
+        assert_eq!(aggregate.probes(), ["10.0.0.1:9100", "10.0.0.2:9100"]);
+        assert_eq!(aggregate.merged().unwrap().total_events, 25);
+
+        // A new term starts empty
+        assert!(!aggregate.is_leading());
+        aggregate.set_leading(true);
+        assert!(aggregate.is_leading() && aggregate.merged().is_none());
+        aggregate.submit("10.0.0.2:9100", report(5));
+        aggregate.set_leading(true);
+        assert_eq!(aggregate.probes(), ["10.0.0.2:9100"]);
+    }
+}
@@ -0,0 +1,560 @@
+//! HTTP API
+//!
+//! With `--listen`, the daemon answers read-only requests while it
+//! collects:
+//!
+//! * `GET /top?sort=p99&limit=20` - the worst connections so far, sorted by
+//!   `p99`, `avg`, `events` or `retransmits` (see
+//!   [`MetricsCollector::top_connections`])
+//! * `GET /metrics` - the latency histogram, event-type counts and
+//!   discarded events since the probe started, in the Prometheus text
+//!   format (see [`LiveCounters`])
+//! * `GET /healthz` and `GET /readyz` - liveness and readiness, 503 when
+//!   failing (see crate::health)
+//!
+//! With `--cluster-lease`, the aggregator of the cluster also answers (see
+//! crate::aggregate):
+//!
+//! * `POST /reports?from=<probe>` - a report pushed by another probe
+//! * `GET /aggregate` - the merge of the latest report of every probe
+//!
+//! Responses are JSON, except for /metrics. The server speaks just enough HTTP/1.1 for curl and
+//! HTTP probes: one request per connection, with a body only for pushed reports. The
+//! collector is only locked to rank its per-connection digests, never to
+//! generate a report, and /metrics scrapes do not lock it at all.
+
+use crate::{
+    aggregate::Aggregate,
+    collector::MetricsCollector,
+    counters::LiveCounters,
+    health::Health,
+    types::{ConnectionSort, LatencyMetrics},
+};
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    task::JoinHandle,
+};
+
+/// Connections returned by /top without a limit
+pub const DEFAULT_TOP_LIMIT: usize = 20;
+
+/// Most connections /top returns
+pub const MAX_TOP_LIMIT: usize = 1000;
+
+/// Longest request head accepted
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Longest request body accepted (a pushed report)
+const MAX_BODY_BYTES: usize = 64 << 20;
+
+/// Time a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a client has to send its request body
+const BODY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request line of an HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// Method (e.g. "GET")
+    pub method: String,
+    /// Path, without the query
+    pub path: String,
+    /// Query parameters (not percent-decoded)
+    pub query: BTreeMap<String, String>,
+    /// Length of the body (Content-Length, 0 without one)
+    pub content_length: usize,
+    /// Body, read after the head
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Parse the head of a request
+    ///
+    /// # Arguments
+    ///
+    /// * `head` - Request line and headers
+    pub fn parse(head: &str) -> Result<Self> {
+        let line = head.lines().next().unwrap_or_default();
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+            anyhow::bail!("Malformed request line: {:?}", line);
+        };
+
+        let content_length = head
+            .lines()
+            .skip(1)
+            .filter_map(|header| header.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .map(|(_, value)| value.trim().parse::<usize>())
+            .transpose()
+            .context("Invalid Content-Length")?
+            .unwrap_or(0);
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (name.to_string(), value.to_string())
+            })
+            .collect();
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            content_length,
+            body: Vec::new(),
+        })
+    }
+
+    /// Parse a query parameter, if given
+    fn param<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: std::str::FromStr,
+        T::Err: Into<anyhow::Error>,
+    {
+        self.query
+            .get(name)
+            .map(|value| {
+                value
+                    .parse::<T>()
+                    .map_err(Into::into)
+                    .with_context(|| format!("Invalid {} parameter: {:?}", name, value))
+            })
+            .transpose()
+    }
+}
+
+/// HTTP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// Status code
+    pub status: u16,
+    /// Content-Type header
+    pub content_type: &'static str,
+    /// Body
+    pub body: String,
+}
+
+impl Response {
+    /// 200 response with a JSON body
+    pub fn json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_string_pretty(value) {
+            Ok(body) => Self {
+                status: 200,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Self::error(500, format!("Failed to serialize the response: {}", e)),
+        }
+    }
+
+    /// Error response, with the message as `{"error": ...}`
+    pub fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::json!({ "error": message.to_string() }).to_string(),
+        }
+    }
+
+    /// Status line, headers and body
+    fn encode(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        };
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+        .into_bytes()
+    }
+}
+
+/// Body of a /top response
+#[derive(Serialize)]
+struct TopResponse {
+    sort: String,
+    connections: Vec<crate::types::TopConnection>,
+}
+
+/// What the endpoints answer from
+#[derive(Clone)]
+pub struct ApiState {
+    collector: Arc<Mutex<MetricsCollector>>,
+    counters: Arc<LiveCounters>,
+    aggregate: Option<Arc<Aggregate>>,
+    health: Option<Arc<Health>>,
+}
+
+impl ApiState {
+    /// Answer from a collector
+    ///
+    /// # Arguments
+    ///
+    /// * `collector` - Collector of the run
+    /// * `counters` - Its live counters (see [`MetricsCollector::live_counters`])
+    pub fn new(collector: Arc<Mutex<MetricsCollector>>, counters: Arc<LiveCounters>) -> Self {
+        Self {
+            collector,
+            counters,
+            aggregate: None,
+            health: None,
+        }
+    }
+
+    /// Also answer liveness and readiness checks
+    pub fn with_health(mut self, health: Arc<Health>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Also take pushed reports into an aggregate, and serve their merge
+    pub fn with_aggregate(mut self, aggregate: Arc<Aggregate>) -> Self {
+        self.aggregate = Some(aggregate);
+        self
+    }
+
+    /// Answer a request
+    pub async fn handle(&self, request: &Request) -> Response {
+        match (request.method.as_str(), &self.aggregate) {
+            ("GET", _) => {}
+            ("POST", Some(aggregate)) if request.path == "/reports" => return receive_report(aggregate, request),
+            (method, _) => return Response::error(405, format!("Method not allowed: {}", method)),
+        }
+
+        match (request.path.as_str(), &self.aggregate) {
+            ("/top", _) => self.top(request).await,
+            ("/healthz" | "/readyz", _) => self.health(&request.path),
+            ("/metrics", _) => Response {
+                status: 200,
+                content_type: "text/plain; version=0.0.4",
+                body: self.counters.to_prometheus(),
+            },
+            ("/aggregate", Some(aggregate)) if !aggregate.is_leading() => {
+                Response::error(503, "This probe is not the aggregator of the cluster")
+            }
+            ("/aggregate", Some(aggregate)) => match aggregate.merged() {
+                Some(merged) => Response::json(&merged),
+                None => Response::error(503, "No reports yet"),
+            },
+            (path, _) => Response::error(404, format!("No such endpoint: {}", path)),
+        }
+    }
+
+    /// Liveness or readiness of the probe
+    fn health(&self, path: &str) -> Response {
+        let Some(health) = &self.health else {
+            return Response::error(404, format!("No such endpoint: {}", path));
+        };
+        let status = health.check(Instant::now());
+        let ok = if path == "/healthz" { status.live } else { status.ready };
+        Response {
+            status: if ok { 200 } else { 503 },
+            ..Response::json(&status)
+        }
+    }
+
+    /// Worst connections so far
+    async fn top(&self, request: &Request) -> Response {
+        let (sort, limit) = match (request.param::<ConnectionSort>("sort"), request.param::<usize>("limit")) {
+            (Ok(sort), Ok(limit)) => (sort.unwrap_or_default(), limit.unwrap_or(DEFAULT_TOP_LIMIT)),
+            (Err(e), _) | (_, Err(e)) => return Response::error(400, format!("{:#}", e)),
+        };
+
+        let connections = self
+            .collector
+            .lock()
+            .await
+            .top_connections(sort, limit.min(MAX_TOP_LIMIT));
+        Response::json(&TopResponse {
+            sort: sort.to_string(),
+            connections,
+        })
+    }
+}
+
+/// Keep a report pushed to the aggregator
+fn receive_report(aggregate: &Aggregate, request: &Request) -> Response {
+    if !aggregate.is_leading() {
+        return Response::error(503, "This probe is not the aggregator of the cluster");
+    }
+    let Some(from) = request.query.get("from").filter(|from| !from.is_empty()) else {
+        return Response::error(400, "Missing from parameter");
+    };
+    match serde_json::from_slice::<LatencyMetrics>(&request.body) {
+        Ok(report) => {
+            debug!("Report of {} pushed ({} events)", from, report.total_events);
+            aggregate.submit(from, report);
+            Response::json(&serde_json::json!({ "probes": aggregate.probes().len() }))
+        }
+        Err(e) => Response::error(400, format!("Invalid report: {}", e)),
+    }
+}
+
+/// Listening HTTP API server
+pub struct ApiServer {
+    listener: TcpListener,
+}
+
+impl ApiServer {
+    /// Listen on an address
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to listen on (port 0 picks a free port)
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen on {}", addr))?;
+        Ok(Self { listener })
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr().context("Failed to read the listening address")
+    }
+
+    /// Serve requests in the background until the task is aborted
+    pub fn spawn(self, state: ApiState) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match self.listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let state = state.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = serve(stream, &state).await {
+                                debug!("API request from {} failed: {:#}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Failed to accept an API connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Answer the request on one connection
+async fn serve(mut stream: TcpStream, state: &ApiState) -> Result<()> {
+    let (head, body_start) = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream))
+        .await
+        .context("Timed out reading the request")??;
+    let response = match Request::parse(&head) {
+        Ok(request) if request.content_length > MAX_BODY_BYTES => {
+            Response::error(400, format!("Request body longer than {} bytes", MAX_BODY_BYTES))
+        }
+        Ok(mut request) => {
+            request.body = tokio::time::timeout(BODY_TIMEOUT, read_body(&mut stream, body_start, request.content_length))
+                .await
+                .context("Timed out reading the request body")??;
+            state.handle(&request).await
+        }
+        Err(e) => Response::error(400, format!("{:#}", e)),
+    };
+
+    stream.write_all(&response.encode()).await.context("Failed to write the response")?;
+    stream.shutdown().await.context("Failed to close the connection")
+}
+
+/// Read a request up to the end of its headers
+///
+/// # Returns
+///
+/// The head, and the start of the body read with it
+async fn read_head(stream: &mut TcpStream) -> Result<(String, Vec<u8>)> {
+    let mut head = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    let end = loop {
+        if let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if head.len() > MAX_REQUEST_BYTES {
+            anyhow::bail!("Request head longer than {} bytes", MAX_REQUEST_BYTES);
+        }
+        let read = stream.read(&mut chunk).await.context("Failed to read the request")?;
+        if read == 0 {
+            break head.len();
+        }
+        head.extend_from_slice(&chunk[..read]);
+    };
+    let body = head.split_off(end);
+    Ok((String::from_utf8_lossy(&head).into_owned(), body))
+}
+
+/// Read the rest of a request body
+///
+/// # Arguments
+///
+/// * `body` - Start of the body, read with the head
+/// * `length` - Content-Length of the request
+async fn read_body(stream: &mut TcpStream, mut body: Vec<u8>, length: usize) -> Result<Vec<u8>> {
+    body.truncate(length);
+    if body.len() < length {
+        let start = body.len();
+        body.resize(length, 0);
+        stream
+            .read_exact(&mut body[start..])
+            .await
+            .context("Failed to read the request body")?;
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::{ConnectionKey, LatencyEvent};
+    use probe_common::constants::{EVENT_TYPE_RECV, EVENT_TYPE_TIMEOUT};
+
+    /// Send a request and split the response into status line and body
+    async fn get(addr: SocketAddr, target: &str) -> (String, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), serde_json::from_str(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_top_endpoint() {
+        let event = |sport: u16, latency_us: u64, event_type: u8| LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 1,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a,
+                    daddr: 0x0200000a,
+                    sport: sport.to_be(),
+                    dport: 8080u16.to_be(),
+                },
+                latency_us * 1000,
+                event_type,
+            )
+        };
+        let mut collector = MetricsCollector::new();
+        // 40001 is the slowest, 40002 the busiest, 40003 retransmits
+        collector.add_event(&event(40001, 9000, EVENT_TYPE_RECV));
+        for _ in 0..5 {
+            collector.add_event(&event(40002, 100, EVENT_TYPE_RECV));
+        }
+        collector.add_event(&event(40003, 500, EVENT_TYPE_RECV));
+        collector.add_event(&event(40003, 0, EVENT_TYPE_TIMEOUT));
+
+        let server = ApiServer::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let counters = collector.live_counters();
+        let collector = Arc::new(Mutex::new(collector));
+        let task = server.spawn(ApiState::new(Arc::clone(&collector), counters));
+
+        let (status, body) = get(addr, "/top").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body["sort"], "p99");
+        assert_eq!(body["connections"].as_array().unwrap().len(), 3);
+        assert_eq!(body["connections"][0]["connection"], "10.0.0.1:40001 -> 10.0.0.2:8080");
+
+        let (_, body) = get(addr, "/top?sort=events&limit=1").await;
+        assert_eq!(body["connections"].as_array().unwrap().len(), 1);
+        assert_eq!(body["connections"][0]["events"], 5);
+
+        let (_, body) = get(addr, "/top?sort=retransmits&limit=1").await;
+        assert_eq!(body["connections"][0]["connection"], "10.0.0.1:40003 -> 10.0.0.2:8080");
+        assert_eq!(body["connections"][0]["timeouts"], 1);
+
+        let (status, body) = get(addr, "/top?sort=median").await;
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert!(body["error"].as_str().unwrap().contains("Unknown sort order"));
+        let (status, _) = get(addr, "/stats").await;
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+        // Scrapes are answered while the collector is locked
+        let guard = collector.lock().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        drop(guard);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("latency_probe_live_events_by_type{type=\"tcp_recvmsg\"} 7\n"));
+        assert!(response.contains("latency_probe_internal_dropped_events_total 0\n"));
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_endpoints() {
+        let collector = MetricsCollector::new();
+        let counters = collector.live_counters();
+        let aggregate = Arc::new(Aggregate::new());
+        let server = ApiServer::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let state = ApiState::new(Arc::new(Mutex::new(collector)), counters).with_aggregate(Arc::clone(&aggregate));
+        let task = server.spawn(state);
+
+        let push = |from: &str, total_events: u64| {
+            let report = serde_json::to_string(&LatencyMetrics {
+                total_events,
+                ..Default::default()
+            })
+            .unwrap();
+            format!(
+                "POST /reports?from={} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                from,
+                report.len(),
+                report
+            )
+        };
+        let send = |request: String| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        // Only the aggregator takes reports
+        assert!(send(push("10.0.0.2:9100", 5)).await.starts_with("HTTP/1.1 503"));
+        aggregate.set_leading(true);
+        assert!(send(push("10.0.0.2:9100", 5)).await.starts_with("HTTP/1.1 200 OK"));
+        assert!(send(push("10.0.0.3:9100", 7)).await.starts_with("HTTP/1.1 200 OK"));
+        assert!(send(push("", 7)).await.starts_with("HTTP/1.1 400"));
+
+        let (status, body) = get(addr, "/aggregate").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body["total_events"], 12);
+        assert_eq!(aggregate.probes(), ["10.0.0.2:9100", "10.0.0.3:9100"]);
+
+        task.abort();
+    }
+}
@@ -0,0 +1,309 @@
+//! Apache Arrow IPC output
+//!
+//! JSON reports and recordings get slow to load once they reach gigabytes.
+//! With the `arrow` feature, reports and recordings can be written as Arrow
+//! IPC instead, which analytics tools (pyarrow, pandas, polars, DuckDB)
+//! load without parsing:
+//!
+//! ```python
+//! import pyarrow as pa
+//! intervals = pa.ipc.open_file("report.arrow").read_all()
+//! events = pa.ipc.open_stream("events.arrows").read_all()
+//! ```
+//!
+//! Intervals have a `run_id` column; recordings hold the run ID in the
+//! `run_id` key of the schema metadata.
+
+use crate::{
+    exporter::MetricsExporter,
+    output::Output,
+    run::{RunId, RUN_ID_LABEL},
+    types::*,
+};
+use anyhow::{Context, Result};
+use arrow_array::{
+    ArrayRef, Float64Array, RecordBatch, StringArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::{DataType, Field, Schema};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufWriter,
+    net::Ipv4Addr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// Events buffered before a record batch is written
+pub const RECORD_BATCH_SIZE: usize = 65536;
+
+/// Schema of the interval metrics written by [`ArrowExporter`]
+pub fn interval_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("timestamp", DataType::Utf8, false),
+        Field::new("run_id", DataType::Utf8, true),
+        Field::new("duration_seconds", DataType::UInt64, false),
+        Field::new("total_events", DataType::UInt64, false),
+        Field::new("lost_events", DataType::UInt64, false),
+        Field::new("connections", DataType::UInt64, false),
+        Field::new("p50_us", DataType::Float64, false),
+        Field::new("p75_us", DataType::Float64, false),
+        Field::new("p90_us", DataType::Float64, false),
+        Field::new("p95_us", DataType::Float64, false),
+        Field::new("p99_us", DataType::Float64, false),
+        Field::new("p999_us", DataType::Float64, false),
+        Field::new("bytes_sent", DataType::UInt64, false),
+        Field::new("bytes_received", DataType::UInt64, false),
+        Field::new("dns_queries", DataType::UInt64, false),
+    ])
+}
+
+/// Schema of the events written by [`ArrowRecorder`]
+pub fn event_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("timestamp_ns", DataType::UInt64, false),
+        Field::new("latency_ns", DataType::UInt64, false),
+        Field::new("saddr", DataType::Utf8, false),
+        Field::new("sport", DataType::UInt16, false),
+        Field::new("daddr", DataType::Utf8, false),
+        Field::new("dport", DataType::UInt16, false),
+        Field::new("netns", DataType::UInt32, false),
+        Field::new("cookie", DataType::UInt64, false),
+        Field::new("pid", DataType::UInt32, false),
+        Field::new("event_type", DataType::UInt8, false),
+        Field::new("http_status_class", DataType::UInt8, false),
+        Field::new("tcp_state", DataType::UInt8, false),
+        Field::new("tcp_flags", DataType::UInt8, false),
+        Field::new("protocol", DataType::UInt8, false),
+    ])
+}
+
+/// Convert the summary of a collection interval to a one-row record batch
+pub fn interval_batch(metrics: &LatencyMetrics) -> Result<RecordBatch> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(vec![metrics.timestamp.as_str()])),
+        Arc::new(StringArray::from(vec![metrics.run_id.as_deref()])),
+        Arc::new(UInt64Array::from(vec![metrics.duration_seconds])),
+        Arc::new(UInt64Array::from(vec![metrics.total_events])),
+        Arc::new(UInt64Array::from(vec![metrics.lost_events])),
+        Arc::new(UInt64Array::from(vec![metrics.connections.len() as u64])),
+        Arc::new(Float64Array::from(vec![metrics.percentiles.p50])),
+        Arc::new(Float64Array::from(vec![metrics.percentiles.p75])),
+        Arc::new(Float64Array::from(vec![metrics.percentiles.p90])),
+        Arc::new(Float64Array::from(vec![metrics.percentiles.p95])),
+        Arc::new(Float64Array::from(vec![metrics.percentiles.p99])),
+        Arc::new(Float64Array::from(vec![metrics.percentiles.p999])),
+        Arc::new(UInt64Array::from(vec![metrics.throughput.bytes_sent])),
+        Arc::new(UInt64Array::from(vec![metrics.throughput.bytes_received])),
+        Arc::new(UInt64Array::from(vec![metrics.dns_latency.queries])),
+    ];
+
+    RecordBatch::try_new(Arc::new(interval_schema()), columns)
+        .context("Failed to build interval record batch")
+}
+
+/// Convert latency events to a record batch
+pub fn event_batch(events: &[LatencyEvent]) -> Result<RecordBatch> {
+    let addr = |be: u32| Ipv4Addr::from(u32::from_be(be)).to_string();
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(events.iter().map(|e| e.timestamp_ns))),
+        Arc::new(UInt64Array::from_iter_values(events.iter().map(|e| e.latency_ns))),
+        Arc::new(StringArray::from_iter_values(events.iter().map(|e| addr(e.key.saddr)))),
+        Arc::new(UInt16Array::from_iter_values(events.iter().map(|e| u16::from_be(e.key.sport)))),
+        Arc::new(StringArray::from_iter_values(events.iter().map(|e| addr(e.key.daddr)))),
+        Arc::new(UInt16Array::from_iter_values(events.iter().map(|e| u16::from_be(e.key.dport)))),
+        Arc::new(UInt32Array::from_iter_values(events.iter().map(|e| e.netns))),
+        Arc::new(UInt64Array::from_iter_values(events.iter().map(|e| e.cookie))),
+        Arc::new(UInt32Array::from_iter_values(events.iter().map(|e| e.pid))),
+        Arc::new(UInt8Array::from_iter_values(events.iter().map(|e| e.event_type))),
+        Arc::new(UInt8Array::from_iter_values(events.iter().map(|e| e.http_status_class))),
+        Arc::new(UInt8Array::from_iter_values(events.iter().map(|e| e.tcp_state))),
+        Arc::new(UInt8Array::from_iter_values(events.iter().map(|e| e.tcp_flags))),
+        Arc::new(UInt8Array::from_iter_values(events.iter().map(|e| e.protocol))),
+    ];
+
+    RecordBatch::try_new(Arc::new(event_schema()), columns)
+        .context("Failed to build event record batch")
+}
+
+/// Arrow IPC file exporter
+///
+/// Writes the interval summary as a one-row Arrow IPC file. Rotated
+/// reports produce one file per interval, which can be read as a dataset.
+pub struct ArrowExporter {
+    output: Output,
+}
+
+impl ArrowExporter {
+    /// Create a new Arrow exporter
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Output file (`-` for stdout) or writer
+    pub fn new(output: impl Into<Output>) -> Self {
+        Self { output: output.into() }
+    }
+}
+
+impl MetricsExporter for ArrowExporter {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let batch = interval_batch(metrics)?;
+
+        let mut writer = FileWriter::try_new(BufWriter::new(self.output.create()?), &batch.schema())
+            .context("Failed to start Arrow IPC file")?;
+        writer.write(&batch)?;
+        writer
+            .finish()
+            .with_context(|| format!("Failed to write to output {}", self.output))?;
+
+        Ok(())
+    }
+}
+
+/// Buffered events and the stream they are written to
+struct RecorderState {
+    writer: StreamWriter<BufWriter<File>>,
+    pending: Vec<LatencyEvent>,
+}
+
+impl RecorderState {
+    /// Write buffered events as one record batch
+    fn write_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let batch = event_batch(&self.pending)?;
+        self.writer.write(&batch).context("Failed to write record batch")?;
+        self.pending.clear();
+
+        Ok(())
+    }
+}
+
+/// Writes latency events to an Arrow IPC stream
+///
+/// The Arrow counterpart of [`EventRecorder`](crate::replay::EventRecorder),
+/// for recordings too large to load as JSON. Events are written in batches
+/// of RECORD_BATCH_SIZE. Arrow recordings cannot be replayed.
+pub struct ArrowRecorder {
+    state: Mutex<RecorderState>,
+}
+
+impl ArrowRecorder {
+    /// Create a recorder, truncating any existing file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the Arrow IPC stream output file
+    /// * `run_id` - ID of the run, kept in the schema metadata
+    pub fn create(path: &Path, run_id: Option<&RunId>) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording file: {:?}", path))?;
+        let metadata = run_id
+            .map(|run_id| HashMap::from([(RUN_ID_LABEL.to_string(), run_id.to_string())]))
+            .unwrap_or_default();
+        let writer = StreamWriter::try_new(BufWriter::new(file), &event_schema().with_metadata(metadata))
+            .context("Failed to start Arrow IPC stream")?;
+
+        Ok(Self {
+            state: Mutex::new(RecorderState {
+                writer,
+                pending: Vec::with_capacity(RECORD_BATCH_SIZE),
+            }),
+        })
+    }
+
+    /// Append one event to the recording
+    pub fn record(&self, event: &LatencyEvent) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Recorder lock poisoned"))?;
+
+        state.pending.push(*event);
+        if state.pending.len() >= RECORD_BATCH_SIZE {
+            state.write_pending()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write buffered events and end the stream
+    ///
+    /// Events recorded afterwards are not written.
+    pub fn finish(&self) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Recorder lock poisoned"))?;
+
+        state.write_pending()?;
+        state.writer.finish().context("Failed to finish recording file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use arrow_array::Array;
+    use arrow_ipc::reader::{FileReader, StreamReader};
+
+    #[test]
+    fn test_arrow_output() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let metrics = LatencyMetrics {
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            total_events: 3,
+            ..Default::default()
+        };
+        let report = dir.path().join("report.arrow");
+        ArrowExporter::new(report.clone()).export(&metrics).unwrap();
+
+        let batches: Vec<_> = FileReader::try_new(File::open(&report).unwrap(), None)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 1);
+        let total = batches[0].column_by_name("total_events").unwrap();
+        assert_eq!(total.as_any().downcast_ref::<UInt64Array>().unwrap().value(0), 3);
+        assert!(batches[0].column_by_name("run_id").unwrap().is_null(0));
+
+        let recording = dir.path().join("events.arrows");
+        let run_id: RunId = "nightly-42".parse().unwrap();
+        let recorder = ArrowRecorder::create(&recording, Some(&run_id)).unwrap();
+        for latency_ns in [1_000, 2_000] {
+            recorder
+                .record(&LatencyEvent {
+                    pid: 1,
+                    ..testing::event(
+                        ConnectionKey {
+                            saddr: 0x0100000a, // 10.0.0.1 in network byte order
+                            daddr: 0x0100007f,
+                            sport: 0x5000, // Port 80 in network byte order
+                            dport: 0x901f,
+                        },
+                        latency_ns,
+                        probe_common::constants::EVENT_TYPE_RECV,
+                    )
+                })
+                .unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let reader = StreamReader::try_new(File::open(&recording).unwrap(), None).unwrap();
+        assert_eq!(reader.schema().metadata()["run_id"], "nightly-42");
+        let batches: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+        let saddr = batches[0].column_by_name("saddr").unwrap();
+        assert_eq!(saddr.as_any().downcast_ref::<StringArray>().unwrap().value(0), "10.0.0.1");
+        let sport = batches[0].column_by_name("sport").unwrap();
+        assert_eq!(sport.as_any().downcast_ref::<UInt16Array>().unwrap().value(0), 80);
+    }
+}
@@ -0,0 +1,300 @@
+//! Bounded queues between the perf buffer readers and the collector
+//!
+//! Readers used to lock the collector for every event, so under load they
+//! spent their time waiting on each other while the perf buffers filled.
+//! Each reader now appends to a queue of its own, and a single aggregator
+//! task moves the queued events into the collector, locking it once per
+//! batch. When a queue is full, the [`OverflowPolicy`] decides what gives:
+//! the new event, the oldest queued one, or the reader, which then stops
+//! reading and lets the kernel drop events into the lost count. Every
+//! dropped event is counted in the report's `health.channel`.
+
+use crate::{collector::MetricsCollector, types::{ChannelStats, LatencyEvent}};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{watch, Mutex, Notify};
+
+/// Events each per-CPU queue holds by default
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 8192;
+
+/// What happens to an event that finds its queue full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverflowPolicy {
+    /// Drop the new event
+    DropNewest,
+    /// Drop the oldest queued event to make room
+    DropOldest,
+    /// Wait for room; the perf buffer absorbs the backlog, then the kernel
+    /// drops events
+    #[default]
+    Block,
+}
+
+impl std::fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverflowPolicy::DropNewest => write!(f, "drop-newest"),
+            OverflowPolicy::DropOldest => write!(f, "drop-oldest"),
+            OverflowPolicy::Block => write!(f, "block"),
+        }
+    }
+}
+
+/// Bounded queue of one reader's events
+pub struct EventQueue {
+    events: std::sync::Mutex<VecDeque<LatencyEvent>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Woken when the aggregator made room
+    space: Notify,
+    /// Wakes the aggregator
+    ready: Arc<Notify>,
+    /// Events lost to the full perf buffer, for the aggregator to report
+    lost: AtomicU64,
+    dropped: AtomicU64,
+    blocked: AtomicU64,
+    max_depth: AtomicU64,
+}
+
+impl EventQueue {
+    /// Create an empty queue
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Events the queue holds before the policy applies
+    /// * `policy` - What to do with events that find the queue full
+    /// * `ready` - Wakes the aggregator draining the queue
+    fn new(capacity: usize, policy: OverflowPolicy, ready: Arc<Notify>) -> Self {
+        Self {
+            events: std::sync::Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_CHANNEL_CAPACITY))),
+            capacity: capacity.max(1),
+            policy,
+            space: Notify::new(),
+            ready,
+            lost: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            blocked: AtomicU64::new(0),
+            max_depth: AtomicU64::new(0),
+        }
+    }
+
+    /// Queue an event, applying the overflow policy if the queue is full
+    ///
+    /// Only waits with [`OverflowPolicy::Block`].
+    pub async fn push(&self, event: LatencyEvent) {
+        loop {
+            {
+                let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+                if events.len() < self.capacity {
+                    events.push_back(event);
+                    self.max_depth.fetch_max(events.len() as u64, Ordering::Relaxed);
+                    return;
+                }
+                match self.policy {
+                    OverflowPolicy::DropNewest => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                    OverflowPolicy::DropOldest => {
+                        events.pop_front();
+                        events.push_back(event);
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                    OverflowPolicy::Block => {}
+                }
+            }
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+            self.notify();
+            self.space.notified().await;
+        }
+    }
+
+    /// Tell the aggregator events were queued
+    pub fn notify(&self) {
+        self.ready.notify_one();
+    }
+
+    /// Count events the perf buffer lost before they were read
+    pub fn add_lost(&self, count: u64) {
+        self.lost.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Take every queued event, waking a blocked reader
+    fn drain(&self) -> VecDeque<LatencyEvent> {
+        let drained = std::mem::take(&mut *self.events.lock().unwrap_or_else(|e| e.into_inner()));
+        self.space.notify_one();
+        drained
+    }
+}
+
+/// Per-CPU queues and the aggregator draining them
+#[derive(Clone)]
+pub struct EventChannels {
+    queues: Arc<Vec<Arc<EventQueue>>>,
+    /// Woken when a reader queued events
+    ready: Arc<Notify>,
+}
+
+impl EventChannels {
+    /// Create a queue for each of `readers` readers
+    ///
+    /// # Arguments
+    ///
+    /// * `readers` - Number of readers
+    /// * `capacity` - Events each queue holds before the policy applies
+    /// * `policy` - What to do with events that find a queue full
+    pub fn new(readers: usize, capacity: usize, policy: OverflowPolicy) -> Self {
+        let ready = Arc::new(Notify::new());
+        Self {
+            queues: Arc::new(
+                (0..readers)
+                    .map(|_| Arc::new(EventQueue::new(capacity, policy, Arc::clone(&ready))))
+                    .collect(),
+            ),
+            ready,
+        }
+    }
+
+    /// Queue of a reader
+    pub fn queue(&self, reader: usize) -> Arc<EventQueue> {
+        Arc::clone(&self.queues[reader])
+    }
+
+    /// Move every queued event into the collector
+    ///
+    /// # Returns
+    ///
+    /// Number of events moved
+    pub async fn drain_into(&self, collector: &Mutex<MetricsCollector>) -> usize {
+        let batches: Vec<_> = self.queues.iter().map(|queue| queue.drain()).collect();
+        let lost: u64 = self.queues.iter().map(|queue| queue.lost.swap(0, Ordering::Relaxed)).sum();
+
+        let mut collector = collector.lock().await;
+        if lost > 0 {
+            collector.add_lost_events(lost);
+        }
+        let mut moved = 0;
+        for event in batches.iter().flatten() {
+            collector.add_event(event);
+            moved += 1;
+        }
+        collector.set_channel_stats(self.stats());
+        moved
+    }
+
+    /// Drain queued events into the collector whenever readers queue some
+    ///
+    /// Runs until `stop` changes (or the task is aborted), never between
+    /// taking events off the queues and adding them to the collector.
+    pub async fn aggregate(self, collector: Arc<Mutex<MetricsCollector>>, mut stop: watch::Receiver<u64>) {
+        loop {
+            tokio::select! {
+                _ = self.ready.notified() => {}
+                _ = stop.changed() => return,
+            }
+            self.drain_into(&collector).await;
+        }
+    }
+
+    /// Drops and waits so far, over every queue
+    pub fn stats(&self) -> ChannelStats {
+        let first = self.queues.first();
+        let sum = |counter: fn(&EventQueue) -> &AtomicU64| {
+            self.queues.iter().map(|queue| counter(queue).load(Ordering::Relaxed)).sum()
+        };
+        ChannelStats {
+            policy: first.map(|queue| queue.policy.to_string()).unwrap_or_default(),
+            capacity: first.map_or(0, |queue| queue.capacity as u64),
+            dropped_events: sum(|queue| &queue.dropped),
+            blocked_waits: sum(|queue| &queue.blocked),
+            max_depth: self
+                .queues
+                .iter()
+                .map(|queue| queue.max_depth.load(Ordering::Relaxed))
+                .max()
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let event = |latency_us: u64| LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 42,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a,
+                    daddr: 0x0200000a,
+                    sport: 0x3930,
+                    dport: 0x901f,
+                },
+                latency_us * 1000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        // Which events survive three pushes into a queue of two
+        for (policy, kept) in [(OverflowPolicy::DropNewest, [1, 2]), (OverflowPolicy::DropOldest, [2, 3])] {
+            let channels = EventChannels::new(1, 2, policy);
+            for latency_us in 1..=3 {
+                channels.queue(0).push(event(latency_us)).await;
+            }
+            let queued: Vec<_> = channels.queue(0).drain().iter().map(|e| e.latency_ns / 1000).collect();
+            assert_eq!(queued, kept);
+            assert_eq!(channels.stats().dropped_events, 1);
+            assert_eq!(channels.stats().max_depth, 2);
+        }
+
+        // A blocked reader continues once the aggregator made room, and
+        // nothing is dropped
+        let channels = EventChannels::new(2, 1, OverflowPolicy::Block);
+        let queue = channels.queue(1);
+        let reader = tokio::spawn(async move {
+            for latency_us in 1..=3 {
+                queue.push(event(latency_us)).await;
+            }
+        });
+        let collector = Mutex::new(MetricsCollector::new());
+        channels.queue(0).add_lost(4);
+        let mut moved = 0;
+        while moved < 3 {
+            moved += channels.drain_into(&collector).await;
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        reader.await.unwrap();
+
+        let stats = channels.stats();
+        assert_eq!(stats.policy, "block");
+        assert_eq!(stats.dropped_events, 0);
+        assert!(stats.blocked_waits >= 1);
+        let metrics = collector.lock().await.generate_metrics(1);
+        assert_eq!(metrics.total_events, 3);
+        assert_eq!(metrics.lost_events, 4);
+        assert_eq!(metrics.health.channel, Some(stats));
+
+        // The aggregator moves queued events until it is stopped
+        let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+        let (stop, stopped) = watch::channel(0);
+        let aggregator = tokio::spawn(channels.clone().aggregate(Arc::clone(&collector), stopped));
+        channels.queue(0).push(event(1)).await;
+        channels.queue(0).notify();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(collector.lock().await.event_count(), 1);
+        stop.send_modify(|generation| *generation += 1);
+        tokio::time::timeout(Duration::from_secs(1), aggregator).await.unwrap().unwrap();
+    }
+}
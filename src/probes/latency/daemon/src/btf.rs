@@ -0,0 +1,310 @@
+//! Minimal BTF reader for kernel struct layouts
+//!
+//! The eBPF programs read a few kernel struct fields whose offsets vary
+//! between kernel versions and configs. This module resolves those offsets
+//! from the running kernel's BTF (`/sys/kernel/btf/vmlinux`) so userspace
+//! can pass them to the programs through the CONFIG map.
+//!
+//! Only struct/union member and enumerator lookup is supported.
+
+use anyhow::{Context, Result};
+use std::{collections::HashMap, path::Path};
+
+/// Location of the running kernel's BTF
+pub const VMLINUX_BTF: &str = "/sys/kernel/btf/vmlinux";
+
+const BTF_MAGIC: u16 = 0xeb9f;
+
+const BTF_KIND_INT: u32 = 1;
+const BTF_KIND_ARRAY: u32 = 3;
+const BTF_KIND_STRUCT: u32 = 4;
+const BTF_KIND_UNION: u32 = 5;
+const BTF_KIND_ENUM: u32 = 6;
+const BTF_KIND_FUNC_PROTO: u32 = 13;
+const BTF_KIND_VAR: u32 = 14;
+const BTF_KIND_DATASEC: u32 = 15;
+const BTF_KIND_DECL_TAG: u32 = 17;
+const BTF_KIND_ENUM64: u32 = 19;
+
+/// Struct or union member
+#[derive(Debug, Clone)]
+struct Member {
+    name: String,
+    type_id: u32,
+    bit_offset: u32,
+}
+
+/// Parsed type information (members only for structs and unions)
+#[derive(Debug, Clone, Default)]
+struct BtfType {
+    members: Vec<Member>,
+}
+
+/// Kernel type information
+pub struct Btf {
+    /// Types indexed by BTF type ID (ID 0 is void)
+    types: Vec<BtfType>,
+    /// Named structs and unions to their type ID
+    composites: HashMap<String, u32>,
+    /// Named enums to the names of their enumerators
+    enums: HashMap<String, Vec<String>>,
+}
+
+impl Btf {
+    /// Load BTF for the running kernel
+    pub fn from_sys_fs() -> Result<Self> {
+        Self::from_file(Path::new(VMLINUX_BTF))
+    }
+
+    /// Load BTF from a raw BTF file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("Failed to read BTF from {:?}", path))?;
+        Self::parse(&data).with_context(|| format!("Failed to parse BTF from {:?}", path))
+    }
+
+    /// Parse raw BTF data (native byte order)
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.u16()? != BTF_MAGIC {
+            anyhow::bail!("Bad BTF magic (foreign byte order or not BTF)");
+        }
+        reader.skip(2)?; // version, flags
+        let hdr_len = reader.u32()? as usize;
+        let type_off = reader.u32()? as usize;
+        let type_len = reader.u32()? as usize;
+        let str_off = reader.u32()? as usize;
+        let str_len = reader.u32()? as usize;
+
+        let strings = data
+            .get(hdr_len + str_off..hdr_len + str_off + str_len)
+            .context("BTF string section out of bounds")?;
+        let type_data = data
+            .get(hdr_len + type_off..hdr_len + type_off + type_len)
+            .context("BTF type section out of bounds")?;
+
+        let mut reader = Reader {
+            data: type_data,
+            pos: 0,
+        };
+        let mut types = vec![BtfType::default()];
+        let mut composites = HashMap::new();
+        let mut enums: HashMap<String, Vec<String>> = HashMap::new();
+
+        while reader.pos < type_data.len() {
+            let name_off = reader.u32()?;
+            let info = reader.u32()?;
+            reader.skip(4)?; // size or type
+
+            let vlen = (info & 0xffff) as usize;
+            let kind = (info >> 24) & 0x1f;
+            let kind_flag = info >> 31 == 1;
+            let mut parsed = BtfType::default();
+
+            match kind {
+                BTF_KIND_STRUCT | BTF_KIND_UNION => {
+                    for _ in 0..vlen {
+                        let member_name = reader.u32()?;
+                        let type_id = reader.u32()?;
+                        let offset = reader.u32()?;
+                        parsed.members.push(Member {
+                            name: string_at(strings, member_name)?,
+                            type_id,
+                            // With kind_flag, the top 8 bits hold the bitfield size
+                            bit_offset: if kind_flag { offset & 0xff_ffff } else { offset },
+                        });
+                    }
+
+                    let name = string_at(strings, name_off)?;
+                    if !name.is_empty() {
+                        composites.entry(name).or_insert(types.len() as u32);
+                    }
+                }
+                BTF_KIND_INT | BTF_KIND_VAR | BTF_KIND_DECL_TAG => reader.skip(4)?,
+                BTF_KIND_ARRAY => reader.skip(12)?,
+                BTF_KIND_ENUM | BTF_KIND_ENUM64 => {
+                    let mut names = Vec::with_capacity(vlen);
+                    for _ in 0..vlen {
+                        names.push(string_at(strings, reader.u32()?)?);
+                        // value, or its low and high halves
+                        reader.skip(if kind == BTF_KIND_ENUM { 4 } else { 8 })?;
+                    }
+
+                    let name = string_at(strings, name_off)?;
+                    if !name.is_empty() {
+                        enums.entry(name).or_insert(names);
+                    }
+                }
+                BTF_KIND_FUNC_PROTO => reader.skip(8 * vlen)?,
+                BTF_KIND_DATASEC => reader.skip(12 * vlen)?,
+                _ => {}
+            }
+
+            types.push(parsed);
+        }
+
+        Ok(Self {
+            types,
+            composites,
+            enums,
+        })
+    }
+
+    /// Byte offset of a member within a named struct or union
+    ///
+    /// Searches anonymous nested structs and unions as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `composite` - Struct or union name, e.g. `sock_common`
+    /// * `member` - Member name, e.g. `skc_net`
+    pub fn member_offset(&self, composite: &str, member: &str) -> Result<u32> {
+        let type_id = *self
+            .composites
+            .get(composite)
+            .with_context(|| format!("struct {} not found in BTF", composite))?;
+
+        let bits = self
+            .find_member(type_id, member, 0)
+            .with_context(|| format!("struct {} has no member {}", composite, member))?;
+
+        Ok(bits / 8)
+    }
+
+    /// Whether a named enum has an enumerator
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Enum name, e.g. `bpf_map_type`
+    /// * `enumerator` - Enumerator name, e.g. `BPF_MAP_TYPE_RINGBUF`
+    pub fn has_enumerator(&self, name: &str, enumerator: &str) -> bool {
+        self.enums
+            .get(name)
+            .is_some_and(|enumerators| enumerators.iter().any(|e| e == enumerator))
+    }
+
+    fn find_member(&self, type_id: u32, member: &str, base_bits: u32) -> Option<u32> {
+        let members = &self.types.get(type_id as usize)?.members;
+
+        for m in members {
+            if m.name == member {
+                return Some(base_bits + m.bit_offset);
+            }
+        }
+
+        // Descend into anonymous structs/unions
+        members
+            .iter()
+            .filter(|m| m.name.is_empty())
+            .find_map(|m| self.find_member(m.type_id, member, base_bits + m.bit_offset))
+    }
+}
+
+fn string_at(strings: &[u8], offset: u32) -> Result<String> {
+    let tail = strings
+        .get(offset as usize..)
+        .context("BTF string offset out of bounds")?;
+    let end = tail.iter().position(|&b| b == 0).unwrap_or(tail.len());
+    Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+}
+
+/// Cursor over native-endian BTF data
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .context("Truncated BTF data")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_ne_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_ne_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a BTF blob with the given types and string table
+    fn encode(types: &[u32], strings: &[u8]) -> Vec<u8> {
+        let type_bytes: Vec<u8> = types.iter().flat_map(|v| v.to_ne_bytes()).collect();
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&BTF_MAGIC.to_ne_bytes());
+        data.extend_from_slice(&[1, 0]);
+        for v in [24u32, 0, type_bytes.len() as u32, type_bytes.len() as u32, strings.len() as u32] {
+            data.extend_from_slice(&v.to_ne_bytes());
+        }
+        data.extend_from_slice(&type_bytes);
+        data.extend_from_slice(strings);
+        data
+    }
+
+    #[test]
+    fn test_member_offsets() {
+        // Strings: 1="int", 5="ns_common", 15="inum", 20="net", 24="ns", 27="count",
+        // 33="bpf_map_type", 46="BPF_MAP_TYPE_HASH"
+        let strings = b"\0int\0ns_common\0inum\0net\0ns\0count\0bpf_map_type\0BPF_MAP_TYPE_HASH\0";
+        #[rustfmt::skip]
+        let types = [
+            // [1] int, 4 bytes
+            1, BTF_KIND_INT << 24, 4, 32,
+            // [2] struct ns_common { int count @0; int inum @64 bits }
+            5, (BTF_KIND_STRUCT << 24) | 2, 16,
+            27, 1, 0,
+            15, 1, 64,
+            // [3] anonymous union { struct ns_common ns @0 }
+            0, (BTF_KIND_UNION << 24) | 1, 16,
+            24, 2, 0,
+            // [4] struct net { int count @0; union { ns } @128 bits }
+            20, (BTF_KIND_STRUCT << 24) | 2, 32,
+            27, 1, 0,
+            0, 3, 128,
+            // [5] enum bpf_map_type { BPF_MAP_TYPE_HASH = 1 }
+            33, (BTF_KIND_ENUM << 24) | 1, 4,
+            46, 1,
+        ];
+
+        let btf = Btf::parse(&encode(&types, strings)).unwrap();
+        assert_eq!(btf.member_offset("ns_common", "inum").unwrap(), 8);
+        assert_eq!(btf.member_offset("net", "ns").unwrap(), 16);
+        assert!(btf.member_offset("net", "missing").is_err());
+        assert!(btf.member_offset("sock", "skc_net").is_err());
+        assert!(btf.has_enumerator("bpf_map_type", "BPF_MAP_TYPE_HASH"));
+        assert!(!btf.has_enumerator("bpf_map_type", "BPF_MAP_TYPE_RINGBUF"));
+    }
+
+    #[test]
+    fn test_running_kernel_offsets() {
+        // Only meaningful on hosts that expose kernel BTF
+        let Ok(btf) = Btf::from_sys_fs() else {
+            return;
+        };
+
+        assert!(btf.member_offset("sock_common", "skc_net").unwrap() > 0);
+        assert!(btf.member_offset("net", "ns").is_ok());
+        assert!(btf.member_offset("ns_common", "inum").is_ok());
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        assert!(Btf::parse(&[0u8; 24]).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Microburst detection
+//!
+//! Sidecars batch work, so events can arrive in short bursts that a
+//! per-second event rate averages away. Events are counted in 10ms
+//! buckets, and runs of consecutive buckets whose count exceeds a multiple
+//! of the median bucket are reported as bursts.
+
+use crate::types::{BurstStats, EventRateSeries};
+use serde::{Deserialize, Serialize};
+
+/// Width of the buckets bursts are detected in, in milliseconds
+pub const BURST_RESOLUTION_MS: u64 = 10;
+
+/// Default multiple of the median rate above which a bucket is in a burst
+pub const DEFAULT_BURST_FACTOR: f64 = 4.0;
+
+/// Counts events in fine-grained buckets for burst detection
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BurstDetector {
+    rate: EventRateSeries,
+}
+
+impl Default for BurstDetector {
+    fn default() -> Self {
+        Self {
+            rate: EventRateSeries::new(BURST_RESOLUTION_MS),
+        }
+    }
+}
+
+impl BurstDetector {
+    /// Count an event
+    pub fn add(&mut self, timestamp_ns: u64) {
+        self.rate.add(timestamp_ns);
+    }
+
+    /// Find the bursts in the buckets so far
+    ///
+    /// The last bucket is still filling and is left out. A bucket is in a
+    /// burst when its count exceeds `factor` times the median count (or
+    /// `factor` events, when most buckets are empty).
+    ///
+    /// # Arguments
+    ///
+    /// * `factor` - Multiple of the median rate that makes a burst
+    pub fn stats(&self, factor: f64) -> BurstStats {
+        let buckets = match self.rate.events.len() {
+            0 | 1 => &self.rate.events[..],
+            n => &self.rate.events[..n - 1],
+        };
+        let per_second = 1000.0 / BURST_RESOLUTION_MS as f64;
+
+        let mut sorted = buckets.to_vec();
+        sorted.sort_unstable();
+        let median = sorted.get(sorted.len() / 2).copied().unwrap_or(0) as f64;
+        let threshold = factor * median.max(1.0);
+
+        let mut stats = BurstStats {
+            resolution_ms: BURST_RESOLUTION_MS,
+            factor,
+            median_rate: median * per_second,
+            threshold_rate: threshold * per_second,
+            ..Default::default()
+        };
+
+        let mut run = 0;
+        for &count in buckets.iter().chain([&0]) {
+            if count as f64 > threshold {
+                run += 1;
+                stats.events += count;
+                stats.peak_rate = stats.peak_rate.max(count as f64 * per_second);
+            } else if run > 0 {
+                stats.bursts += 1;
+                stats.total_duration_ms += run * BURST_RESOLUTION_MS;
+                stats.max_duration_ms = stats.max_duration_ms.max(run * BURST_RESOLUTION_MS);
+                run = 0;
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_detection() {
+        let mut detector = BurstDetector::default();
+        let bucket_ns = BURST_RESOLUTION_MS * 1_000_000;
+
+        // 2 events per bucket, with a 3-bucket burst of 20 and a single
+        // bucket of 12
+        for bucket in 0..100u64 {
+            let count = match bucket {
+                10..=12 => 20,
+                50 => 12,
+                _ => 2,
+            };
+            for i in 0..count {
+                detector.add(bucket * bucket_ns + i);
+            }
+        }
+
+        let stats = detector.stats(DEFAULT_BURST_FACTOR);
+        assert_eq!(stats.median_rate, 200.0);
+        assert_eq!(stats.threshold_rate, 800.0);
+        assert_eq!(stats.bursts, 2);
+        assert_eq!(stats.total_duration_ms, 40);
+        assert_eq!(stats.max_duration_ms, 30);
+        assert_eq!(stats.peak_rate, 2000.0);
+        assert_eq!(stats.events, 72);
+
+        // No bursts in steady traffic
+        assert_eq!(detector.stats(20.0).bursts, 0);
+    }
+}
@@ -0,0 +1,176 @@
+//! Collector checkpoints
+//!
+//! Multi-hour runs lose their samples if the daemon restarts. With
+//! `--checkpoint`, the collector's samples are written to disk periodically
+//! and at shutdown; `--resume` loads them on the next start, so collection
+//! continues into the same run.
+//!
+//! Checkpoints are bincode encoded and start with a format version; a
+//! checkpoint written by an incompatible version is rejected rather than
+//! misread. Event timestamps come from the kernel's monotonic clock, so the
+//! event rate and heatmap only line up when resuming on the same boot.
+
+use crate::{collector::MetricsCollector, run::RunId};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// Version of the checkpoint format, bumped when the collector's
+/// serialized fields change
+pub const CHECKPOINT_VERSION: u32 = 32;
+
+/// Checkpoint being written (borrows the collector to avoid a copy)
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    written_at: String,
+    elapsed_secs: u64,
+    run_id: Option<&'a RunId>,
+    collector: &'a MetricsCollector,
+}
+
+/// Collector state read back from a checkpoint
+#[derive(Deserialize)]
+pub struct Checkpoint {
+    /// When the checkpoint was written (RFC 3339)
+    pub written_at: String,
+    /// Collection time covered by the checkpoint in seconds
+    pub elapsed_secs: u64,
+    /// ID of the run (None if the collector had none)
+    pub run_id: Option<RunId>,
+    /// Collected samples
+    pub collector: MetricsCollector,
+}
+
+/// Write a checkpoint of the collector
+///
+/// The checkpoint is written to a temporary file and renamed into place, so
+/// a crash while writing leaves the previous checkpoint intact.
+///
+/// # Arguments
+///
+/// * `path` - Path to the checkpoint file
+/// * `collector` - Collector to checkpoint
+/// * `elapsed_secs` - Collection time since the collector started (or was
+///   last rotated); time before a resume is added
+pub fn write(path: &Path, collector: &MetricsCollector, elapsed_secs: u64) -> Result<()> {
+    let checkpoint = CheckpointRef {
+        written_at: chrono::Utc::now().to_rfc3339(),
+        elapsed_secs: elapsed_secs + collector.resumed_secs(),
+        run_id: collector.run_id(),
+        collector,
+    };
+
+    let tmp_path = temporary_path(path);
+    let file = File::create(&tmp_path)
+        .with_context(|| format!("Failed to create checkpoint file: {:?}", tmp_path))?;
+    let mut writer = BufWriter::new(file);
+
+    bincode::serialize_into(&mut writer, &CHECKPOINT_VERSION)
+        .and_then(|_| bincode::serialize_into(&mut writer, &checkpoint))
+        .with_context(|| format!("Failed to write checkpoint: {:?}", tmp_path))?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("Failed to write checkpoint: {:?}", tmp_path))?;
+
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace checkpoint: {:?}", path))
+}
+
+/// Read a checkpoint
+///
+/// # Arguments
+///
+/// * `path` - Path to the checkpoint file
+pub fn read(path: &Path) -> Result<Checkpoint> {
+    let file = File::open(path).with_context(|| format!("Failed to open checkpoint: {:?}", path))?;
+    let mut reader = BufReader::new(file);
+
+    let version: u32 = bincode::deserialize_from(&mut reader)
+        .with_context(|| format!("Failed to read checkpoint: {:?}", path))?;
+    if version != CHECKPOINT_VERSION {
+        anyhow::bail!(
+            "Checkpoint {:?} has format version {}, expected {}",
+            path,
+            version,
+            CHECKPOINT_VERSION
+        );
+    }
+
+    bincode::deserialize_from(&mut reader)
+        .with_context(|| format!("Failed to read checkpoint: {:?}", path))
+}
+
+/// Sibling file the checkpoint is written to before being renamed
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::{ConnectionKey, LatencyEvent};
+
+    fn event(latency_ns: u64) -> LatencyEvent {
+        LatencyEvent {
+            timestamp_ns: 1_000_000_000,
+            pid: 1,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a,
+                    daddr: 0x0200000a,
+                    sport: 0x5000,
+                    dport: 0x901f,
+                },
+                latency_ns,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.bin");
+
+        let run_id = RunId::generate();
+        let mut collector = MetricsCollector::new();
+        collector.set_run_id(run_id.clone());
+        collector.add_event(&event(1_000_000));
+        collector.add_event(&event(2_000_000));
+        write(&path, &collector, 30).unwrap();
+        assert!(!temporary_path(&path).exists());
+
+        // A restarted daemon continues into the same run
+        let checkpoint = read(&path).unwrap();
+        assert_eq!(checkpoint.elapsed_secs, 30);
+        assert_eq!(checkpoint.run_id.as_ref(), Some(&run_id));
+        let mut restarted = MetricsCollector::new();
+        restarted.resume(checkpoint.collector, checkpoint.elapsed_secs);
+        restarted.add_event(&event(3_000_000));
+
+        let metrics = restarted.generate_metrics(30);
+        assert_eq!(metrics.total_events, 3);
+        assert_eq!(metrics.duration_seconds, 60);
+        assert_eq!(metrics.connections.len(), 1);
+        assert_eq!(metrics.event_rate.events, vec![3]);
+
+        // Checkpoints of a resumed run cover the time before the resume
+        write(&path, &restarted, 30).unwrap();
+        assert_eq!(read(&path).unwrap().elapsed_secs, 60);
+
+        // Incompatible checkpoints are rejected
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[0] = 0xff;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(read(&path).is_err());
+    }
+}
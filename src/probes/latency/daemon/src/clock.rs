@@ -0,0 +1,118 @@
+//! Event clock selection and wall clock alignment
+//!
+//! Event timestamps come from a kernel clock counting from boot, so they
+//! cannot be compared between nodes as they are. `--clock` selects the
+//! clock the eBPF programs read, and every report carries the offset from
+//! that clock to the wall clock (CLOCK_REALTIME) at report time:
+//!
+//! ```text
+//! wall_clock_ns = timestamp_ns + clock.wall_offset_ns
+//! ```
+//!
+//! which puts the events of several nodes on one time line, as accurately
+//! as their wall clocks are synchronized (NTP or PTP). With CLOCK_TAI the
+//! timestamps are already comparable between synchronized nodes, and the
+//! offset is the negated TAI-UTC offset (leap seconds).
+
+use crate::types::ClockInfo;
+use probe_common::constants::{CLOCK_SOURCE_BOOTTIME, CLOCK_SOURCE_MONOTONIC, CLOCK_SOURCE_TAI};
+
+/// Readings taken to capture an offset; the tightest one is kept
+const OFFSET_READINGS: usize = 5;
+
+/// Kernel clock of event timestamps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClockSource {
+    /// CLOCK_MONOTONIC (bpf_ktime_get_ns): stops while suspended
+    #[default]
+    Monotonic,
+    /// CLOCK_BOOTTIME: keeps counting while suspended
+    Boottime,
+    /// CLOCK_TAI: wall clock without leap seconds (Linux 6.1+)
+    Tai,
+}
+
+impl std::fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClockSource::Monotonic => write!(f, "monotonic"),
+            ClockSource::Boottime => write!(f, "boottime"),
+            ClockSource::Tai => write!(f, "tai"),
+        }
+    }
+}
+
+impl ClockSource {
+    /// Value of the CONFIG_CLOCK_SOURCE slot selecting this clock
+    pub fn config_value(self) -> u64 {
+        match self {
+            ClockSource::Monotonic => CLOCK_SOURCE_MONOTONIC,
+            ClockSource::Boottime => CLOCK_SOURCE_BOOTTIME,
+            ClockSource::Tai => CLOCK_SOURCE_TAI,
+        }
+    }
+
+    fn clock_id(self) -> libc::clockid_t {
+        match self {
+            ClockSource::Monotonic => libc::CLOCK_MONOTONIC,
+            ClockSource::Boottime => libc::CLOCK_BOOTTIME,
+            ClockSource::Tai => libc::CLOCK_TAI,
+        }
+    }
+
+    /// Current time of this clock in nanoseconds
+    pub fn now_ns(self) -> i64 {
+        read_clock(self.clock_id())
+    }
+
+    /// Capture the offset from this clock to the wall clock
+    ///
+    /// The wall clock is read between two readings of this clock, and
+    /// compared with their midpoint; half the gap between them bounds the
+    /// error.
+    pub fn capture(self) -> ClockInfo {
+        let (offset, uncertainty) = (0..OFFSET_READINGS)
+            .map(|_| {
+                let before = self.now_ns();
+                let wall = read_clock(libc::CLOCK_REALTIME);
+                let after = self.now_ns();
+                (wall - (before + (after - before) / 2), (after - before) / 2)
+            })
+            .min_by_key(|&(_, uncertainty)| uncertainty)
+            .unwrap_or_default();
+
+        ClockInfo {
+            source: self.to_string(),
+            wall_offset_ns: offset,
+            uncertainty_ns: uncertainty.max(0) as u64,
+            captured_at: chrono::Utc::now().to_rfc3339(),
+            sync: None,
+        }
+    }
+}
+
+/// Read a clock in nanoseconds
+fn read_clock(clock: libc::clockid_t) -> i64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // clock_gettime cannot fail for the clocks used here
+    unsafe { libc::clock_gettime(clock, &mut ts) };
+    ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wall_clock_offset() {
+        let info = ClockSource::Monotonic.capture();
+        assert_eq!(info.source, "monotonic");
+
+        // A monotonic reading converted with the offset is close to now
+        let wall = ClockSource::Monotonic.now_ns() + info.wall_offset_ns;
+        let now = read_clock(libc::CLOCK_REALTIME);
+        assert!((now - wall).abs() < 1_000_000_000, "offset off by {}ns", now - wall);
+        assert_eq!(info.to_wall_ns(1_000), 1_000 + info.wall_offset_ns);
+        assert!(info.uncertainty_ns < 1_000_000_000);
+    }
+}
@@ -2,30 +2,271 @@
 //!
 //! Aggregates latency events from the kernel and computes statistics.
 
-use crate::types::*;
-use std::collections::HashMap;
+use crate::{
+    burst::{BurstDetector, DEFAULT_BURST_FACTOR},
+    clock::ClockSource,
+    config::ProbeConfig,
+    conntrack::ConntrackCache,
+    counters::{IntervalCounters, LiveCounters},
+    dedup::{DedupPolicy, Deduplicator},
+    digest::LatencyDigest,
+    instance::InstanceId,
+    jitter::JitterEstimator,
+    listen::ListenQueues,
+    omission,
+    pods::PodCache,
+    process::{ProcessCache, ProcessInfo},
+    run::RunId,
+    services::ServiceClassifier,
+    tail::TailAnalyzer,
+    tenants::TenantSet,
+    types::*,
+    window::RollingWindow,
+    zones::ZoneMap,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, sync::Arc, time::Duration};
+
+/// Latency samples of one connection
+#[derive(Default, Serialize, Deserialize)]
+struct ConnectionLatency {
+    digest: LatencyDigest,
+    jitter: JitterEstimator,
+    /// Retransmission timeouts
+    #[serde(default)]
+    timeouts: u64,
+    /// Destination behind NAT (address and port, network byte order)
+    #[serde(default)]
+    nat_destination: Option<(u32, u16)>,
+}
+
+impl ConnectionLatency {
+    fn add(&mut self, latency_us: f64) {
+        self.digest.add(latency_us);
+        self.jitter.add(latency_us);
+    }
+}
+
+/// Latencies of one transport protocol
+#[derive(Default, Serialize, Deserialize)]
+struct ProtocolLatency {
+    digest: LatencyDigest,
+    histogram: LatencyHistogram,
+}
+
+impl ProtocolLatency {
+    fn add(&mut self, latency_us: f64) {
+        self.digest.add(latency_us);
+        self.histogram.add_sample(latency_us);
+    }
+}
+
+/// Slow start threshold of a connection that has not left slow start
+/// (TCP_INFINITE_SSTHRESH)
+const INFINITE_SSTHRESH: u32 = 0x7fff_ffff;
+
+/// Congestion readings of one connection during an interval
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct IntervalCongestion {
+    samples: u64,
+    srtt_sum_us: u64,
+    cwnd_collapses: u64,
+    /// Last reading
+    state: Option<CongestionReading>,
+}
+
+/// Non-cumulative fields of a CONGESTION map entry
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct CongestionReading {
+    snd_cwnd: u32,
+    ssthresh: u32,
+    srtt_us: u32,
+    min_snd_cwnd: u32,
+    max_srtt_us: u32,
+}
+
+impl IntervalCongestion {
+    /// Report of the interval (None without records)
+    fn metrics(&self) -> Option<CongestionMetrics> {
+        let state = self.state.filter(|_| self.samples > 0)?;
+        Some(CongestionMetrics {
+            samples: self.samples,
+            snd_cwnd: state.snd_cwnd,
+            min_snd_cwnd: state.min_snd_cwnd,
+            ssthresh: Some(state.ssthresh).filter(|&ssthresh| ssthresh != 0 && ssthresh < INFINITE_SSTHRESH),
+            srtt_us: state.srtt_us,
+            avg_srtt_us: self.srtt_sum_us as f64 / self.samples as f64,
+            max_srtt_us: state.max_srtt_us,
+            cwnd_collapses: self.cwnd_collapses,
+        })
+    }
+}
 
 /// Metrics collector for aggregating latency events
-#[derive(Default)]
+///
+/// Serializes to the samples collected so far (see crate::checkpoint);
+/// probe metadata and settings are not included.
+#[derive(Default, Serialize, Deserialize)]
 pub struct MetricsCollector {
     /// All latency samples (for percentile calculation)
     all_latencies: Vec<f64>,
-    /// Per-connection latency samples
-    connection_latencies: HashMap<String, Vec<f64>>,
-    /// Latency histogram
-    histogram: LatencyHistogram,
-    /// Event type breakdown
-    event_types: EventTypeBreakdown,
+    /// Per-connection latency digests and jitter
+    connection_latencies: HashMap<ConnectionId, ConnectionLatency>,
+    /// Most connections tracked individually (None = unlimited)
+    #[serde(skip)]
+    connection_limit: Option<usize>,
+    /// Events on connections beyond the limit
+    untracked_connection_events: u64,
+    /// Per network namespace latency digests (keyed by inode, 0 = unknown)
+    namespace_latencies: HashMap<u32, LatencyDigest>,
+    /// Latency histogram and event type breakdown, readable without the
+    /// collector lock
+    counters: IntervalCounters,
     /// Total number of events processed
     total_events: u64,
     /// Packet drop tracking
     packet_drops: PacketDropStats,
+    /// Resets and retransmission timeouts
+    connection_errors: ConnectionErrorStats,
     /// Connection state tracking
     connection_states: ConnectionStateStats,
     /// Connection durations for calculating average
     connection_durations: Vec<f64>,
     /// Context switch counter
     context_switch_count: u64,
+    /// Events lost to full perf buffers
+    lost_events: u64,
+    /// Configuration reloads during collection
+    config_changes: Vec<ConfigChange>,
+    /// Faults injected during collection
+    fault_injections: Vec<FaultInjection>,
+    /// Attached eBPF programs
+    #[serde(skip)]
+    probes: Vec<ProbeAttachment>,
+    /// Latest map occupancy readings
+    #[serde(skip)]
+    map_health: Vec<MapHealth>,
+    /// Latest kernel discard counter readings
+    #[serde(skip)]
+    internal: InternalCounters,
+    /// Latest exporter thread reading
+    #[serde(skip)]
+    export: Option<ExportStats>,
+    /// Latest reader queue reading
+    #[serde(skip)]
+    channel: Option<ChannelStats>,
+    /// Per process latency digests
+    process_latencies: HashMap<u32, LatencyDigest>,
+    /// Most processes tracked individually (None = unlimited)
+    #[serde(skip)]
+    process_limit: Option<usize>,
+    /// Events of processes beyond the limit
+    untracked_process_events: u64,
+    /// Names of processes seen in events, resolved when first seen
+    process_names: HashMap<u32, ProcessInfo>,
+    /// PID to name lookup (None = report PIDs only)
+    #[serde(skip)]
+    process_cache: Option<ProcessCache>,
+    /// Clock of live event timestamps (None for replays)
+    #[serde(skip)]
+    clock_source: Option<ClockSource>,
+    /// Synchronization of the wall clock (None if unknown)
+    #[serde(skip)]
+    clock_sync: Option<ClockSync>,
+    /// Node of live events (None for replays)
+    #[serde(skip)]
+    host: Option<HostInfo>,
+    /// BPF features of the node and the probe (None for replays)
+    #[serde(skip)]
+    features: Option<KernelFeatures>,
+    /// Name of the probe instance
+    #[serde(skip)]
+    instance: Option<InstanceId>,
+    /// ID of the run
+    #[serde(skip)]
+    run_id: Option<RunId>,
+    /// Per pod latency digests, keyed by pod UID
+    pod_latencies: HashMap<String, LatencyDigest>,
+    /// QoS class and containers seen of each pod
+    pod_details: HashMap<String, (String, BTreeSet<String>)>,
+    /// PID to pod resolver (None = no pod breakdown)
+    #[serde(skip)]
+    pod_cache: Option<PodCache>,
+    /// Resolves destinations behind NAT (None = destinations as the
+    /// sockets see them)
+    #[serde(skip)]
+    conntrack: Option<ConntrackCache>,
+    /// Per service latency digests
+    service_latencies: HashMap<String, LatencyDigest>,
+    /// Port to service name mapping
+    #[serde(skip)]
+    services: ServiceClassifier,
+    /// Per transport protocol latency digests and histograms
+    protocol_latencies: HashMap<String, ProtocolLatency>,
+    /// Per traffic class latency digests
+    traffic_class_latencies: HashMap<String, LatencyDigest>,
+    /// Per zone locality latency digests
+    zone_latencies: HashMap<String, LatencyDigest>,
+    /// Per connection phase latency digests
+    phase_latencies: HashMap<String, LatencyDigest>,
+    /// Address to zone mapping (None = no zone breakdown)
+    #[serde(skip)]
+    zone_map: Option<ZoneMap>,
+    /// Per tenant latency digests
+    tenant_digests: HashMap<String, LatencyDigest>,
+    /// Tenant filters (None = no tenant breakdown)
+    #[serde(skip)]
+    tenants: Option<TenantSet>,
+    /// DNS query latency digest
+    dns_latencies: LatencyDigest,
+    /// Pairs receives with their cleanups
+    dedup: Deduplicator,
+    /// Cleanup latency digest, with the separate dedup policy
+    cleanup_latencies: LatencyDigest,
+    /// Per HTTP status class latency digests
+    http_status_latencies: HashMap<u8, LatencyDigest>,
+    /// Cumulative (sent, received) byte counters at the last reading
+    byte_counters: HashMap<ConnectionId, (u64, u64)>,
+    /// (sent, received) bytes per connection during this interval
+    connection_bytes: HashMap<ConnectionId, (u64, u64)>,
+    /// Cumulative (samples, srtt sum, cwnd collapses) congestion counters
+    /// at the last reading
+    congestion_counters: HashMap<ConnectionId, (u64, u64, u64)>,
+    /// Congestion state per connection during this interval
+    connection_congestion: HashMap<ConnectionId, IntervalCongestion>,
+    /// Accept queue counters of the listeners
+    listen_queues: ListenQueues,
+    /// Events per time bucket
+    event_rate: EventRateSeries,
+    /// Events per 10ms bucket, for burst detection
+    bursts: BurstDetector,
+    /// Multiple of the median rate that makes a burst (None = default)
+    #[serde(skip)]
+    burst_factor: Option<f64>,
+    /// Latency histogram per time bucket
+    heatmap: LatencyHeatmap,
+    /// Percentiles of the closed snapshot intervals
+    trajectory: Vec<IntervalPercentiles>,
+    /// Latencies since the last closed snapshot interval (None = no
+    /// trajectory)
+    interval_digest: Option<LatencyDigest>,
+    /// Request interval for coordinated omission correction (None = off)
+    #[serde(skip)]
+    expected_interval_us: Option<f64>,
+    /// Synthetic samples added by the correction
+    synthetic_samples: u64,
+    /// Factors of tail latency (None = --tail-analysis off)
+    tail: Option<TailAnalyzer>,
+    /// Collection time before the run was resumed from a checkpoint
+    #[serde(skip)]
+    resumed_secs: u64,
+    /// Finished panes, in rolling-window mode (not checkpointed: reports
+    /// do not round-trip through the checkpoint encoding)
+    #[serde(skip)]
+    window: Option<RollingWindow>,
+    /// Aggregate connections with their ephemeral ports replaced by "*"
+    #[serde(skip)]
+    normalize_ports: bool,
 }
 
 impl MetricsCollector {
@@ -34,39 +275,606 @@ impl MetricsCollector {
         Self::default()
     }
 
+    /// Report the offset of the event clock to the wall clock
+    ///
+    /// Only meaningful for live events.
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.clock_source = Some(source);
+    }
+
+    /// Report the synchronization of the wall clock (see crate::timesync)
+    pub fn set_clock_sync(&mut self, sync: ClockSync) {
+        self.clock_sync = Some(sync);
+    }
+
+    /// Record the node live events come from
+    pub fn set_host(&mut self, host: HostInfo) {
+        self.host = Some(host);
+    }
+
+    /// Record the BPF features of the node and the probe
+    pub fn set_features(&mut self, features: KernelFeatures) {
+        self.features = Some(features);
+    }
+
+    /// Name the probe instance in reports
+    pub fn set_instance(&mut self, instance: InstanceId) {
+        self.instance = Some(instance);
+    }
+
+    /// Stamp reports with the ID of the run
+    pub fn set_run_id(&mut self, run_id: RunId) {
+        self.run_id = Some(run_id);
+    }
+
+    /// ID of the run (None if not set)
+    pub fn run_id(&self) -> Option<&RunId> {
+        self.run_id.as_ref()
+    }
+
+    /// Resolve the PIDs in events to process names
+    ///
+    /// Only meaningful for live events; recorded PIDs belong to another host.
+    pub fn set_process_cache(&mut self, cache: ProcessCache) {
+        self.process_cache = Some(cache);
+    }
+
+    /// Resolve the PIDs in events to pods through their cgroups
+    ///
+    /// Only meaningful for live events; recorded PIDs belong to another host.
+    pub fn set_pod_cache(&mut self, cache: PodCache) {
+        self.pod_cache = Some(cache);
+    }
+
+    /// Attribute connections to their destination behind NAT (see
+    /// crate::conntrack)
+    pub fn set_conntrack(&mut self, conntrack: ConntrackCache) {
+        self.conntrack = Some(conntrack);
+    }
+
+    /// Tuple of a connection with its destination behind NAT, if the
+    /// destination was translated
+    fn attributed_key(&mut self, key: &ConnectionKey) -> ConnectionKey {
+        match self.conntrack.as_mut().and_then(|conntrack| conntrack.destination(key)) {
+            Some((daddr, dport)) => ConnectionKey { daddr, dport, ..*key },
+            None => *key,
+        }
+    }
+
+    /// Replace the port to service name mapping
+    pub fn set_service_classifier(&mut self, services: ServiceClassifier) {
+        self.services = services;
+    }
+
+    /// Set the width of the event rate and heatmap time buckets
+    pub fn set_rate_resolution(&mut self, resolution_ms: u64) {
+        self.event_rate = EventRateSeries::new(resolution_ms);
+        self.heatmap = LatencyHeatmap::new(resolution_ms);
+    }
+
+    /// Set the multiple of the median event rate above which events count
+    /// as a burst
+    pub fn set_burst_factor(&mut self, factor: f64) {
+        self.burst_factor = Some(factor);
+    }
+
+    /// Correct the overall distribution for coordinated omission
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_interval` - Interval at which the load generator meant
+    ///   to send requests
+    pub fn set_expected_interval(&mut self, expected_interval: Duration) {
+        self.expected_interval_us = Some(expected_interval.as_nanos() as f64 / 1000.0);
+    }
+
+    /// Break the events above p99 down by what coincided with them (see
+    /// crate::tail)
+    pub fn enable_tail_analysis(&mut self) {
+        self.tail.get_or_insert_with(TailAnalyzer::new);
+    }
+
+    /// Choose which of a receive and the cleanup that follows it count
+    /// (see crate::dedup)
+    pub fn set_dedup_policy(&mut self, policy: DedupPolicy) {
+        self.dedup.set_policy(policy);
+    }
+
+    /// Break latency down into intra-zone and cross-zone connections
+    pub fn set_zone_map(&mut self, zones: ZoneMap) {
+        self.zone_map = Some(zones);
+    }
+
+    /// Break latency down by tenant
+    pub fn set_tenants(&mut self, tenants: TenantSet) {
+        self.tenants = Some(tenants);
+    }
+
+    /// Tenant filters (None without a tenant breakdown)
+    pub fn tenants(&self) -> Option<&TenantSet> {
+        self.tenants.as_ref()
+    }
+
+    /// Limit the connections tracked individually, bounding the memory of
+    /// the per-connection breakdown
+    ///
+    /// Events on further connections still count towards every other
+    /// statistic.
+    pub fn set_connection_limit(&mut self, limit: usize) {
+        self.connection_limit = Some(limit);
+    }
+
+    /// Limit the processes tracked individually, bounding the memory of
+    /// the per-process breakdown on nodes that churn through PIDs
+    ///
+    /// Events of further processes still count towards every other
+    /// statistic.
+    pub fn set_process_limit(&mut self, limit: usize) {
+        self.process_limit = Some(limit);
+    }
+
+    /// Aggregate connections that differ only in an ephemeral port
+    ///
+    /// Ports in the dynamic range are replaced by "*" in the keys of the
+    /// per-connection breakdown and of per-connection drops (see
+    /// [`normalized_key_to_string`]), so the connections of one client to
+    /// one server share an entry. Socket cookies are then not used.
+    pub fn set_port_normalization(&mut self, normalize: bool) {
+        self.normalize_ports = normalize;
+    }
+
+    /// Connection of an event
+    fn connection_of(&self, event: &LatencyEvent) -> ConnectionId {
+        if self.normalize_ports {
+            ConnectionId::normalized(&event.key)
+        } else {
+            ConnectionId::of(event)
+        }
+    }
+
+    /// Connection of a 4-tuple
+    fn connection_tuple(&self, key: &ConnectionKey) -> ConnectionId {
+        if self.normalize_ports {
+            ConnectionId::normalized(key)
+        } else {
+            ConnectionId::tuple(key)
+        }
+    }
+
+    /// Report only the last `window` of collection (see crate::window)
+    ///
+    /// [`roll_window`](Self::roll_window) must then be called every
+    /// [`RollingWindow::pane_length`].
+    pub fn set_rolling_window(&mut self, window: Duration) {
+        self.window = Some(RollingWindow::new(window));
+    }
+
+    /// Rolling window, if enabled
+    pub fn rolling_window(&self) -> Option<&RollingWindow> {
+        self.window.as_ref()
+    }
+
+    /// Continue collecting into the samples of a checkpointed collector
+    ///
+    /// Probe metadata and settings are kept, except the event rate and
+    /// heatmap resolution, which must match the checkpointed buckets.
+    ///
+    /// # Arguments
+    ///
+    /// * `resumed` - Collector read from a checkpoint
+    /// * `elapsed_secs` - Collection time covered by the checkpoint
+    pub fn resume(&mut self, mut resumed: MetricsCollector, elapsed_secs: u64) {
+        resumed.probes = std::mem::take(&mut self.probes);
+        resumed.map_health = std::mem::take(&mut self.map_health);
+        resumed.internal = std::mem::take(&mut self.internal);
+        resumed.export = self.export.take();
+        resumed.channel = self.channel.take();
+        resumed.process_cache = self.process_cache.take();
+        resumed.clock_source = self.clock_source;
+        resumed.clock_sync = self.clock_sync.take();
+        resumed.host = self.host.take();
+        resumed.features = self.features.take();
+        resumed.instance = self.instance.clone();
+        resumed.run_id = self.run_id.clone();
+        resumed.pod_cache = self.pod_cache.take();
+        resumed.conntrack = self.conntrack.take();
+        resumed.services = std::mem::take(&mut self.services);
+        resumed.zone_map = self.zone_map.take();
+        resumed.tenants = self.tenants.take();
+        resumed.expected_interval_us = self.expected_interval_us;
+        resumed.connection_limit = self.connection_limit;
+        resumed.process_limit = self.process_limit;
+        resumed.burst_factor = self.burst_factor;
+        resumed.dedup.set_policy(self.dedup.policy());
+        resumed.window = self.window.take();
+        resumed.normalize_ports = self.normalize_ports;
+        resumed.counters = self.counters.resume(&resumed.counters);
+        if self.tail.is_none() {
+            resumed.tail = None;
+        } else if resumed.tail.is_none() {
+            resumed.tail = self.tail.take();
+        }
+        if self.interval_digest.is_none() {
+            resumed.interval_digest = None;
+        } else if resumed.interval_digest.is_none() {
+            resumed.interval_digest = self.interval_digest.take();
+        }
+        resumed.resumed_secs = elapsed_secs;
+        *self = resumed;
+    }
+
+    /// Collection time before the run was resumed (0 if it was not)
+    pub fn resumed_secs(&self) -> u64 {
+        self.resumed_secs
+    }
+
+    /// Start a new collection interval
+    ///
+    /// The probe metadata, map health, process cache, byte, congestion and
+    /// listener counter readings, and settings carry over to the new
+    /// interval.
+    ///
+    /// # Returns
+    ///
+    /// Collector holding the samples of the finished interval
+    pub fn rotate(&mut self) -> Self {
+        let mut next = Self {
+            probes: self.probes.clone(),
+            map_health: self.map_health.clone(),
+            internal: self.internal.clone(),
+            export: self.export.clone(),
+            channel: self.channel.clone(),
+            process_cache: self.process_cache.take(),
+            clock_source: self.clock_source,
+            clock_sync: self.clock_sync.clone(),
+            host: self.host.clone(),
+            features: self.features.clone(),
+            instance: self.instance.clone(),
+            run_id: self.run_id.clone(),
+            pod_cache: self.pod_cache.take(),
+            conntrack: self.conntrack.take(),
+            services: self.services.clone(),
+            zone_map: self.zone_map.clone(),
+            tenants: self.tenants.clone(),
+            byte_counters: std::mem::take(&mut self.byte_counters),
+            congestion_counters: std::mem::take(&mut self.congestion_counters),
+            counters: self.counters.rotate(),
+            event_rate: EventRateSeries::new(self.event_rate.resolution_ms),
+            heatmap: LatencyHeatmap::new(self.heatmap.resolution_ms),
+            expected_interval_us: self.expected_interval_us,
+            connection_limit: self.connection_limit,
+            process_limit: self.process_limit,
+            burst_factor: self.burst_factor,
+            tail: self.tail.as_ref().map(|_| TailAnalyzer::new()),
+            interval_digest: self.interval_digest.as_ref().map(|_| LatencyDigest::new()),
+            dedup: self.dedup.rotate(),
+            listen_queues: self.listen_queues.rotate(),
+            window: self.window.as_ref().map(|w| RollingWindow::new(w.window())),
+            normalize_ports: self.normalize_ports,
+            ..Self::default()
+        };
+        std::mem::swap(self, &mut next);
+        next
+    }
+
+    /// Drop the samples collected so far, keeping the settings (see
+    /// [`rotate`](Self::rotate))
+    pub fn reset(&mut self) {
+        self.rotate();
+    }
+
+    /// Report the samples collected so far and start over
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed_secs` - Duration of the finished collection period
+    pub fn snapshot_and_reset(&mut self, elapsed_secs: u64) -> LatencyMetrics {
+        self.rotate().generate_metrics(elapsed_secs)
+    }
+
+    /// Close the current pane of the rolling window (no-op without one)
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed_secs` - Duration of the pane
+    pub fn roll_window(&mut self, elapsed_secs: u64) {
+        let Some(mut window) = self.window.take() else {
+            return;
+        };
+        let pane = self.rotate().interval_metrics(elapsed_secs);
+        window.push(pane);
+        self.window = Some(window);
+    }
+
+    /// Keep the percentiles of each snapshot interval for the report's
+    /// trajectory (see [`close_interval`](Self::close_interval))
+    pub fn enable_trajectory(&mut self) {
+        self.interval_digest.get_or_insert_with(LatencyDigest::new);
+    }
+
+    /// Close a snapshot interval, adding its percentiles to the trajectory
+    /// (no-op without one, or if the interval had no events)
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed_secs` - Time since the collection period began
+    pub fn close_interval(&mut self, elapsed_secs: u64) {
+        let Some(digest) = self.interval_digest.as_mut() else {
+            return;
+        };
+        if digest.count() > 0 {
+            let point = interval_percentiles(digest, elapsed_secs + self.resumed_secs);
+            self.trajectory.push(point);
+            *digest = LatencyDigest::new();
+        }
+    }
+
     /// Add a latency event to the collector
     ///
     /// # Arguments
     ///
     /// * `event` - Latency event from the eBPF program
     pub fn add_event(&mut self, event: &LatencyEvent) {
+        for event in self.dedup.push(event).into_iter().flatten() {
+            self.record_event(&event);
+        }
+    }
+
+    /// Add an event that passed deduplication
+    fn record_event(&mut self, event: &LatencyEvent) {
+        // Resets and timeouts carry no latency
+        if is_connection_error(event.event_type) {
+            self.add_connection_error(event);
+            return;
+        }
+
         // Convert nanoseconds to microseconds for easier handling
         let latency_us = event.latency_ns as f64 / 1000.0;
 
+        self.event_rate.add(event.timestamp_ns);
+        self.bursts.add(event.timestamp_ns);
+
+        // DNS latency is reported separately from TCP latency
+        if event.event_type == probe_common::constants::EVENT_TYPE_DNS {
+            self.dns_latencies.add(latency_us);
+            self.total_events += 1;
+            return;
+        }
+
+        // So are cleanups with the separate dedup policy
+        if event.event_type == probe_common::constants::EVENT_TYPE_CLEANUP && self.dedup.policy() == DedupPolicy::Separate {
+            self.cleanup_latencies.add(latency_us);
+            self.counters.add_event_type(event.event_type);
+            self.total_events += 1;
+            return;
+        }
+
         // Add to global latencies
         self.all_latencies.push(latency_us);
+        let attributed = self.attributed_key(&event.key);
+
+        // Add to per-connection latencies, up to the connection limit
+        let conn_id = self.connection_of(event);
+        let full = self
+            .connection_limit
+            .is_some_and(|limit| self.connection_latencies.len() >= limit);
+        match self.connection_latencies.get_mut(&conn_id) {
+            Some(connection) => connection.add(latency_us),
+            None if full => self.untracked_connection_events += 1,
+            None => {
+                let connection = self.connection_latencies.entry(conn_id).or_default();
+                connection.add(latency_us);
+                if attributed != event.key {
+                    connection.nat_destination = Some((attributed.daddr, attributed.dport));
+                }
+            }
+        }
+
+        // Add to per-namespace latencies
+        self.namespace_latencies
+            .entry(event.netns)
+            .or_default()
+            .add(latency_us);
+
+        // Add to per-process latencies, up to the process limit
+        let full = self
+            .process_limit
+            .is_some_and(|limit| self.process_latencies.len() >= limit);
+        match self.process_latencies.get_mut(&event.pid) {
+            Some(digest) => digest.add(latency_us),
+            None if full => self.untracked_process_events += 1,
+            None => self.process_latencies.entry(event.pid).or_default().add(latency_us),
+        }
+        if self.process_latencies.contains_key(&event.pid) {
+            if let Some(info) = self.process_cache.as_mut().and_then(|c| c.lookup(event.pid)) {
+                if self.process_names.get(&event.pid) != Some(info) {
+                    self.process_names.insert(event.pid, info.clone());
+                }
+            }
+        }
+
+        // Add to per-pod latencies
+        if let Some(pod) = self.pod_cache.as_mut().and_then(|c| c.lookup(event.pid)) {
+            match self.pod_latencies.get_mut(&pod.pod_uid) {
+                Some(digest) => digest.add(latency_us),
+                None => {
+                    let mut digest = LatencyDigest::new();
+                    digest.add(latency_us);
+                    self.pod_latencies.insert(pod.pod_uid.clone(), digest);
+                }
+            }
+            // Only a pod's first event clones its UID
+            match self.pod_details.get_mut(&pod.pod_uid) {
+                Some((qos_class, containers)) => {
+                    if *qos_class != pod.qos_class {
+                        *qos_class = pod.qos_class.clone();
+                    }
+                    if let Some(container) = &pod.container_id {
+                        if !containers.contains(container) {
+                            containers.insert(container.clone());
+                        }
+                    }
+                }
+                None => {
+                    let containers = pod.container_id.iter().cloned().collect();
+                    self.pod_details
+                        .insert(pod.pod_uid.clone(), (pod.qos_class.clone(), containers));
+                }
+            }
+        }
+
+        // Add to per-service latencies
+        let service = self
+            .services
+            .classify_connection(u16::from_be(attributed.sport), u16::from_be(attributed.dport));
+        if let Some(service) = service {
+            match self.service_latencies.get_mut(service) {
+                Some(digest) => digest.add(latency_us),
+                None => {
+                    let mut digest = LatencyDigest::new();
+                    digest.add(latency_us);
+                    self.service_latencies.insert(service.to_string(), digest);
+                }
+            }
+        }
 
-        // Add to per-connection latencies
-        let conn_str = connection_key_to_string(&event.key);
-        self.connection_latencies
-            .entry(conn_str)
-            .or_insert_with(Vec::new)
-            .push(latency_us);
+        // Add to per-protocol latencies
+        let protocol = protocol_label(event);
+        match self.protocol_latencies.get_mut(protocol) {
+            Some(latencies) => latencies.add(latency_us),
+            None => {
+                let mut latencies = ProtocolLatency::default();
+                latencies.add(latency_us);
+                self.protocol_latencies.insert(protocol.to_string(), latencies);
+            }
+        }
+
+        // Add to per-phase latencies
+        let phase = connection_phase(event.tcp_state);
+        match self.phase_latencies.get_mut(phase) {
+            Some(digest) => digest.add(latency_us),
+            None => {
+                let mut digest = LatencyDigest::new();
+                digest.add(latency_us);
+                self.phase_latencies.insert(phase.to_string(), digest);
+            }
+        }
+
+        // Add to per-class latencies
+        let class = traffic_class(&event.key);
+        match self.traffic_class_latencies.get_mut(class) {
+            Some(digest) => digest.add(latency_us),
+            None => {
+                let mut digest = LatencyDigest::new();
+                digest.add(latency_us);
+                self.traffic_class_latencies.insert(class.to_string(), digest);
+            }
+        }
+
+        // Add to per-locality latencies
+        if let Some(zones) = &self.zone_map {
+            let locality = zones.locality(&attributed);
+            match self.zone_latencies.get_mut(locality) {
+                Some(digest) => digest.add(latency_us),
+                None => {
+                    let mut digest = LatencyDigest::new();
+                    digest.add(latency_us);
+                    self.zone_latencies.insert(locality.to_string(), digest);
+                }
+            }
+        }
+
+        if let Some(digest) = self.interval_digest.as_mut() {
+            digest.add(latency_us);
+        }
+
+        // Add to the digests of the tenants the connection belongs to
+        if let Some(tenants) = &self.tenants {
+            for tenant in tenants.matching(event) {
+                match self.tenant_digests.get_mut(tenant) {
+                    Some(digest) => digest.add(latency_us),
+                    None => {
+                        let mut digest = LatencyDigest::new();
+                        digest.add(latency_us);
+                        self.tenant_digests.insert(tenant.to_string(), digest);
+                    }
+                }
+            }
+        }
+
+        // Add to per-status latencies when the response was classified
+        if event.http_status_class != probe_common::constants::HTTP_STATUS_UNKNOWN {
+            self.http_status_latencies
+                .entry(event.http_status_class)
+                .or_default()
+                .add(latency_us);
+        }
+
+        if let Some(tail) = &mut self.tail {
+            tail.add_event(event, latency_us);
+        }
 
         // Update histogram
-        self.histogram.add_sample(latency_us);
+        self.counters.add_sample(latency_us);
+        self.heatmap.add_sample(event.timestamp_ns, latency_us);
 
-        // Track event types
-        match event.event_type {
-            probe_common::constants::EVENT_TYPE_SEND => self.event_types.tcp_sendmsg += 1,
-            probe_common::constants::EVENT_TYPE_RECV => self.event_types.tcp_recvmsg += 1,
-            probe_common::constants::EVENT_TYPE_CLEANUP => self.event_types.tcp_cleanup_rbuf += 1,
-            _ => {}
+        // Backfill the requests a closed-loop load generator held back
+        if let Some(expected_interval_us) = self.expected_interval_us {
+            for missing_us in omission::backfill(latency_us, expected_interval_us) {
+                self.all_latencies.push(missing_us);
+                self.counters.add_sample(missing_us);
+                self.synthetic_samples += 1;
+            }
         }
 
+        // Track event types
+        self.counters.add_event_type(event.event_type);
+
         self.total_events += 1;
     }
 
+    /// Count a reset or retransmission timeout, overall and for the
+    /// service of the connection
+    fn add_connection_error(&mut self, event: &LatencyEvent) {
+        use probe_common::constants::*;
+
+        // Connections are tracked from their first latency event
+        if event.event_type == EVENT_TYPE_TIMEOUT {
+            let conn_id = self.connection_of(event);
+            if let Some(connection) = self.connection_latencies.get_mut(&conn_id) {
+                connection.timeouts += 1;
+            }
+        }
+
+        let attributed = self.attributed_key(&event.key);
+        let service = self
+            .services
+            .classify_connection(u16::from_be(attributed.sport), u16::from_be(attributed.dport));
+        let errors = &mut self.connection_errors;
+        let mut service = service.map(|service| errors.services.entry(service.to_string()).or_default());
+
+        match event.event_type {
+            EVENT_TYPE_RESET_RECEIVED => {
+                errors.resets_received += 1;
+                if let Some(service) = service.as_mut() {
+                    service.resets_received += 1;
+                }
+            }
+            EVENT_TYPE_RESET_SENT => {
+                errors.resets_sent += 1;
+                if let Some(service) = service.as_mut() {
+                    service.resets_sent += 1;
+                }
+            }
+            _ => {
+                errors.timeouts += 1;
+                if let Some(service) = service.as_mut() {
+                    service.timeouts += 1;
+                }
+            }
+        }
+    }
+
     /// Add a packet drop event to the collector
     ///
     /// # Arguments
@@ -76,6 +884,9 @@ impl MetricsCollector {
         use probe_common::constants::*;
 
         self.packet_drops.total_drops += 1;
+        if let Some(tail) = &mut self.tail {
+            tail.add_packet_drop(event);
+        }
 
         // Track by location
         let location = match event.drop_location {
@@ -99,7 +910,7 @@ impl MetricsCollector {
 
         // Track per-connection drops (if connection info is available)
         if event.key.saddr != 0 || event.key.daddr != 0 {
-            let conn_str = connection_key_to_string(&event.key);
+            let conn_str = self.connection_tuple(&event.key).to_string();
             *self.packet_drops.connections.entry(conn_str).or_insert(0) += 1;
         }
     }
@@ -124,10 +935,8 @@ impl MetricsCollector {
 
         // Track opened/closed
         match event.state {
-            CONN_STATE_ESTABLISHED => {
-                if event.start_time_ns > 0 {
-                    self.connection_states.total_opened += 1;
-                }
+            CONN_STATE_ESTABLISHED if event.start_time_ns > 0 => {
+                self.connection_states.total_opened += 1;
             }
             CONN_STATE_CLOSED => {
                 self.connection_states.total_closed += 1;
@@ -142,68 +951,434 @@ impl MetricsCollector {
         }
     }
 
-    /// Record a context switch event
-    pub fn add_context_switch(&mut self) {
-        self.context_switch_count += 1;
+    /// Add a reading of a connection's byte counters
+    ///
+    /// The kernel counters are cumulative, so the bytes since the previous
+    /// reading are added to this interval. Closed connections are forgotten
+    /// after their final reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - Connection state from the CONNECTION_STATES map
+    pub fn add_connection_bytes(&mut self, state: &kernel::ConnectionState) {
+        let tuple = ConnectionId::tuple(&state.key);
+        let (last_sent, last_received) = self.byte_counters.get(&tuple).copied().unwrap_or((0, 0));
+
+        // A counter below the last reading belongs to a new connection
+        // reusing the 4-tuple
+        let delta = |current: u64, last: u64| if current >= last { current - last } else { current };
+        let bytes = self.connection_bytes.entry(self.connection_tuple(&state.key)).or_default();
+        bytes.0 += delta(state.bytes_sent, last_sent);
+        bytes.1 += delta(state.bytes_received, last_received);
+
+        if state.state == probe_common::constants::CONN_STATE_CLOSED {
+            self.byte_counters.remove(&tuple);
+        } else {
+            self.byte_counters.insert(tuple, (state.bytes_sent, state.bytes_received));
+        }
     }
 
-    /// Generate aggregated metrics
+    /// Add a reading of the connections' congestion state
+    ///
+    /// The kernel counters are cumulative, so the records since the previous
+    /// reading are added to this interval. Connections missing from the
+    /// reading (evicted from the LRU map) are forgotten.
     ///
     /// # Arguments
     ///
-    /// * `elapsed_secs` - Duration of collection period in seconds
+    /// * `states` - Every entry of the CONGESTION map
+    pub fn add_congestion(&mut self, states: &[(ConnectionKey, kernel::CongestionState)]) {
+        let mut counters = HashMap::with_capacity(states.len());
+        for (key, state) in states {
+            let tuple = ConnectionId::tuple(key);
+            let current = (state.samples, state.srtt_sum_us, state.cwnd_collapses);
+            // Counters below the last reading belong to a new connection
+            // reusing the 4-tuple
+            let (samples, srtt_sum_us, collapses) = match self.congestion_counters.get(&tuple) {
+                Some(&last) if current.0 >= last.0 => (current.0 - last.0, current.1 - last.1, current.2 - last.2),
+                _ => current,
+            };
+            counters.insert(tuple, current);
+            if samples == 0 {
+                continue;
+            }
+
+            let congestion = self.connection_congestion.entry(self.connection_tuple(key)).or_default();
+            congestion.samples += samples;
+            congestion.srtt_sum_us += srtt_sum_us;
+            congestion.cwnd_collapses += collapses;
+            let (min_snd_cwnd, max_srtt_us) = match congestion.state {
+                Some(last) => (last.min_snd_cwnd.min(state.min_snd_cwnd), last.max_srtt_us.max(state.max_srtt_us)),
+                None => (state.min_snd_cwnd, state.max_srtt_us),
+            };
+            congestion.state = Some(CongestionReading {
+                snd_cwnd: state.snd_cwnd,
+                ssthresh: state.ssthresh,
+                srtt_us: state.srtt_us,
+                min_snd_cwnd,
+                max_srtt_us,
+            });
+        }
+        self.congestion_counters = counters;
+    }
+
+    /// Add a reading of the listeners' accept queue counters
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// LatencyMetrics with aggregated statistics
-    pub fn generate_metrics(&self, elapsed_secs: u64) -> LatencyMetrics {
-        // Calculate percentiles across all connections
-        let percentiles = calculate_percentiles(self.all_latencies.clone());
+    /// * `readings` - Every entry of the LISTENERS map
+    pub fn add_listeners(&mut self, readings: &[(kernel::ListenerKey, kernel::ListenerStats)]) {
+        self.listen_queues.add(readings);
+    }
 
-        // Generate per-connection metrics
-        let connection_metrics: HashMap<String, ConnectionMetrics> = self
-            .connection_latencies
-            .iter()
-            .map(|(key, samples)| {
-                let sum: f64 = samples.iter().sum();
-                let avg = sum / samples.len() as f64;
-                let min = samples
-                    .iter()
-                    .cloned()
-                    .fold(f64::INFINITY, f64::min);
-                let max = samples
-                    .iter()
-                    .cloned()
-                    .fold(f64::NEG_INFINITY, f64::max);
-                let std_dev = calculate_std_dev(samples, avg);
-
-                // Parse source and destination from key
-                let parts: Vec<&str> = key.split(" -> ").collect();
+    /// Record a context switch event
+    pub fn add_context_switch(&mut self, event: &kernel::ContextSwitchEvent) {
+        self.context_switch_count += 1;
+        if let Some(tail) = &mut self.tail {
+            tail.add_context_switch(event);
+        }
+    }
 
-                (
-                    key.clone(),
-                    ConnectionMetrics {
-                        source: parts[0].to_string(),
-                        destination: parts.get(1).unwrap_or(&"unknown").to_string(),
-                        events: samples.len() as u64,
-                        min_latency_us: min,
-                        max_latency_us: max,
-                        avg_latency_us: avg,
-                        std_dev_us: std_dev,
-                    },
-                )
-            })
-            .collect();
+    /// Record events the kernel dropped because a perf buffer was full
+    pub fn add_lost_events(&mut self, count: u64) {
+        self.lost_events += count;
+        self.counters.live().add_dropped_events(count);
+    }
 
-        // Calculate average connection duration
-        let avg_duration_seconds = if !self.connection_durations.is_empty() {
-            self.connection_durations.iter().sum::<f64>() / self.connection_durations.len() as f64
-        } else {
-            0.0
-        };
+    /// Record which eBPF programs are attached, for the report metadata
+    pub fn set_probes(&mut self, probes: Vec<ProbeAttachment>) {
+        self.probes = probes;
+    }
 
-        let mut connection_states = self.connection_states.clone();
-        connection_states.avg_duration_seconds = avg_duration_seconds;
+    /// Record the latest map occupancy readings
+    pub fn set_map_health(&mut self, maps: Vec<MapHealth>) {
+        self.map_health = maps;
+    }
+
+    /// Record the latest kernel discard counters
+    ///
+    /// Their `dropped_events` is ignored; reports take it from the lost
+    /// events the collector counted.
+    pub fn set_internal_counters(&mut self, counters: InternalCounters) {
+        self.counters.live().set_kernel_discards(&counters);
+        self.internal = counters;
+    }
+
+    /// Record the latest exporter thread reading
+    pub fn set_export_stats(&mut self, stats: Option<ExportStats>) {
+        self.export = stats;
+    }
+
+    /// Record the latest reader queue reading
+    pub fn set_channel_stats(&mut self, stats: ChannelStats) {
+        self.channel = Some(stats);
+    }
+
+    /// Record that the probe configuration was reloaded
+    ///
+    /// The marker notes how many events were collected beforehand, so
+    /// reports can separate benchmark phases.
+    pub fn record_config_change(&mut self, config: &ProbeConfig) {
+        self.config_changes.push(ConfigChange {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            events_before: self.total_events,
+            sample_rate: config.sample_rate,
+            event_sample_rates: config.event_sample_rates.clone(),
+            filter_services: config.filter_services.clone(),
+        });
+    }
+
+    /// Record that a fault was injected on an interface
+    pub fn record_fault_start(&mut self, interface: &str, netem: &str) {
+        self.fault_injections.push(FaultInjection {
+            interface: interface.to_string(),
+            netem: netem.to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            events_before: self.total_events,
+            ended_at: None,
+            events_until: None,
+        });
+    }
+
+    /// Record that the active fault on an interface was removed
+    pub fn record_fault_end(&mut self, interface: &str) {
+        let active = self
+            .fault_injections
+            .iter_mut()
+            .rev()
+            .find(|fault| fault.interface == interface && fault.ended_at.is_none());
+        if let Some(fault) = active {
+            fault.ended_at = Some(chrono::Utc::now().to_rfc3339());
+            fault.events_until = Some(self.total_events);
+        }
+    }
+
+    /// Generate aggregated metrics
+    ///
+    /// In rolling-window mode, the report covers the finished panes of the
+    /// window and the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed_secs` - Duration of collection period in seconds (of
+    ///   the current pane, in rolling-window mode)
+    ///
+    /// # Returns
+    ///
+    /// LatencyMetrics with aggregated statistics
+    pub fn generate_metrics(&self, elapsed_secs: u64) -> LatencyMetrics {
+        let metrics = self.interval_metrics(elapsed_secs);
+        match &self.window {
+            Some(window) => window.report(metrics),
+            None => metrics,
+        }
+    }
+
+    /// Aggregated metrics of the samples collected since the last rotation
+    fn interval_metrics(&self, elapsed_secs: u64) -> LatencyMetrics {
+        // Time collected before a resume counts towards the run
+        let elapsed_secs = elapsed_secs + self.resumed_secs;
+        let counts = self.counters.counts();
+
+        // Calculate percentiles across all connections
+        let percentiles = calculate_percentiles(self.all_latencies.clone());
+        let mut digest = LatencyDigest::new();
+        for &latency in &self.all_latencies {
+            digest.add(latency);
+        }
+
+        // Generate per-connection metrics
+        let connection_metrics: BTreeMap<String, ConnectionMetrics> = self
+            .connection_latencies
+            .iter()
+            .map(|(id, connection)| {
+                let digest = &connection.digest;
+                let percentiles = digest.percentiles();
+                // Byte counters are read per 4-tuple
+                let (bytes_sent, bytes_received) = self
+                    .connection_bytes
+                    .get(&id.without_cookie())
+                    .copied()
+                    .unwrap_or((0, 0));
+                let congestion = self
+                    .connection_congestion
+                    .get(&id.without_cookie())
+                    .and_then(IntervalCongestion::metrics);
+
+                (
+                    id.to_string(),
+                    ConnectionMetrics {
+                        source: id.source(),
+                        destination: id.destination(),
+                        cookie: (id.cookie != 0).then_some(id.cookie),
+                        events: digest.count(),
+                        min_latency_us: digest.min(),
+                        max_latency_us: digest.max(),
+                        avg_latency_us: digest.mean(),
+                        std_dev_us: digest.std_dev(),
+                        p50_us: percentiles.p50,
+                        p95_us: percentiles.p95,
+                        p99_us: percentiles.p99,
+                        jitter_us: connection.jitter.jitter_us(),
+                        bytes_sent,
+                        bytes_received,
+                        bytes_per_second: per_second(bytes_sent + bytes_received, elapsed_secs),
+                        timeouts: connection.timeouts,
+                        nat_destination: connection.nat_destination.map(|(addr, port)| {
+                            format!("{}:{}", std::net::Ipv4Addr::from(u32::from_be(addr)), u16::from_be(port))
+                        }),
+                        congestion,
+                        digest: Some(digest.clone()),
+                    },
+                )
+            })
+            .collect();
+
+        // Break down the events above p99
+        let tail = self.tail.as_ref().map(|tail| tail.breakdown(percentiles.p99));
+
+        let jitter = {
+            let events: u64 = connection_metrics.values().map(|c| c.events).sum();
+            let weighted: f64 = connection_metrics.values().map(|c| c.jitter_us * c.events as f64).sum();
+            JitterStats {
+                mean_us: if events > 0 { weighted / events as f64 } else { 0.0 },
+                max_us: connection_metrics.values().map(|c| c.jitter_us).fold(0.0, f64::max),
+            }
+        };
+
+        // Generate per-namespace metrics
+        let namespaces: BTreeMap<String, GroupMetrics> = self
+            .namespace_latencies
+            .iter()
+            .map(|(netns, digest)| {
+                let name = match netns {
+                    0 => "unknown".to_string(),
+                    inode => inode.to_string(),
+                };
+
+                (name, GroupMetrics::from_digest(digest))
+            })
+            .collect();
+
+        // Generate per-process metrics
+        let processes: BTreeMap<String, ProcessMetrics> = self
+            .process_latencies
+            .iter()
+            .map(|(pid, digest)| {
+                let info = self.process_names.get(pid);
+
+                (
+                    pid.to_string(),
+                    ProcessMetrics {
+                        comm: info.map(|i| i.comm.clone()),
+                        cmdline: info.map(|i| i.cmdline.clone()),
+                        events: digest.count(),
+                        avg_latency_us: digest.mean(),
+                        percentiles: digest.percentiles(),
+                        digest: Some(digest.clone()),
+                    },
+                )
+            })
+            .collect();
+
+        // Generate per-pod metrics
+        let pods: BTreeMap<String, PodMetrics> = self
+            .pod_latencies
+            .iter()
+            .map(|(uid, digest)| {
+                let (qos_class, containers) = self.pod_details.get(uid).cloned().unwrap_or_default();
+
+                (
+                    uid.clone(),
+                    PodMetrics {
+                        qos_class,
+                        containers: containers.into_iter().collect(),
+                        events: digest.count(),
+                        avg_latency_us: digest.mean(),
+                        percentiles: digest.percentiles(),
+                        digest: Some(digest.clone()),
+                    },
+                )
+            })
+            .collect();
+
+        // Generate per-service metrics
+        let services: BTreeMap<String, GroupMetrics> = self
+            .service_latencies
+            .iter()
+            .map(|(service, digest)| (service.clone(), GroupMetrics::from_digest(digest)))
+            .collect();
+
+        // Generate per-protocol metrics
+        let protocols: BTreeMap<String, ProtocolMetrics> = self
+            .protocol_latencies
+            .iter()
+            .map(|(protocol, latencies)| {
+                let digest = &latencies.digest;
+                (
+                    protocol.clone(),
+                    ProtocolMetrics {
+                        events: digest.count(),
+                        avg_latency_us: digest.mean(),
+                        percentiles: digest.percentiles(),
+                        histogram: latencies.histogram.clone(),
+                        digest: Some(digest.clone()),
+                    },
+                )
+            })
+            .collect();
+
+        // Generate per-phase metrics
+        let phases: BTreeMap<String, GroupMetrics> = self
+            .phase_latencies
+            .iter()
+            .map(|(phase, digest)| (phase.clone(), GroupMetrics::from_digest(digest)))
+            .collect();
+
+        // Generate per-class metrics
+        let traffic_classes: BTreeMap<String, GroupMetrics> = self
+            .traffic_class_latencies
+            .iter()
+            .map(|(class, digest)| (class.clone(), GroupMetrics::from_digest(digest)))
+            .collect();
+
+        // Generate per-locality metrics
+        let zones: BTreeMap<String, GroupMetrics> = self
+            .zone_latencies
+            .iter()
+            .map(|(locality, digest)| (locality.clone(), GroupMetrics::from_digest(digest)))
+            .collect();
+
+        // The interval in progress ends with the report
+        let mut trajectory = self.trajectory.clone();
+        if let Some(digest) = self.interval_digest.as_ref().filter(|digest| digest.count() > 0) {
+            trajectory.push(interval_percentiles(digest, elapsed_secs));
+        }
+
+        // Generate per-tenant metrics
+        let tenants: BTreeMap<String, TenantMetrics> = self
+            .tenant_digests
+            .iter()
+            .map(|(tenant, digest)| {
+                (
+                    tenant.clone(),
+                    TenantMetrics {
+                        events: digest.count(),
+                        avg_latency_us: digest.mean(),
+                        percentiles: digest.percentiles(),
+                        digest: Some(digest.clone()),
+                    },
+                )
+            })
+            .collect();
+
+        // Generate per-status metrics
+        let http_status: BTreeMap<String, GroupMetrics> = self
+            .http_status_latencies
+            .iter()
+            .map(|(&class, digest)| (http_status_label(class), GroupMetrics::from_digest(digest)))
+            .collect();
+
+        let dns_latency = DnsLatencyStats {
+            queries: self.dns_latencies.count(),
+            avg_latency_us: self.dns_latencies.mean(),
+            percentiles: self.dns_latencies.percentiles(),
+            digest: Some(self.dns_latencies.clone()),
+        };
+
+        let dedup = DedupStats {
+            policy: self.dedup.policy().to_string(),
+            duplicates: self.dedup.duplicates(),
+            cleanup: (self.dedup.policy() == DedupPolicy::Separate).then(|| CleanupLatencyStats {
+                events: self.cleanup_latencies.count(),
+                avg_latency_us: self.cleanup_latencies.mean(),
+                percentiles: self.cleanup_latencies.percentiles(),
+                digest: Some(self.cleanup_latencies.clone()),
+            }),
+        };
+
+        let mut errors = self.connection_errors.clone();
+        errors.resets_per_second = per_second(errors.resets_received + errors.resets_sent, elapsed_secs);
+        errors.timeouts_per_second = per_second(errors.timeouts, elapsed_secs);
+
+        let bytes_sent = self.connection_bytes.values().map(|b| b.0).sum();
+        let bytes_received = self.connection_bytes.values().map(|b| b.1).sum();
+        let throughput = ThroughputStats {
+            bytes_sent,
+            bytes_received,
+            sent_bytes_per_second: per_second(bytes_sent, elapsed_secs),
+            received_bytes_per_second: per_second(bytes_received, elapsed_secs),
+        };
+
+        // Calculate average connection duration
+        let avg_duration_seconds = if !self.connection_durations.is_empty() {
+            self.connection_durations.iter().sum::<f64>() / self.connection_durations.len() as f64
+        } else {
+            0.0
+        };
+
+        let mut connection_states = self.connection_states.clone();
+        connection_states.avg_duration_seconds = avg_duration_seconds;
         connection_states.active_connections = self.connection_latencies.len() as u64;
 
         let context_switches = ContextSwitchStats {
@@ -218,15 +1393,77 @@ impl MetricsCollector {
         LatencyMetrics {
             timestamp: chrono::Utc::now().to_rfc3339(),
             duration_seconds: elapsed_secs,
+            labels: BTreeMap::new(),
+            instance: self.instance.as_ref().map(InstanceId::to_string),
+            run_id: self.run_id.as_ref().map(RunId::to_string),
+            clock: self.clock_source.map(|source| ClockInfo {
+                sync: self.clock_sync.clone(),
+                ..source.capture()
+            }),
+            host: self.host.clone(),
+            features: self.features.clone(),
             total_events: self.total_events,
+            lost_events: self.lost_events,
             connections: connection_metrics,
-            histogram: self.histogram.clone(),
+            untracked_connection_events: self.untracked_connection_events,
+            untracked_process_events: self.untracked_process_events,
+            histogram: counts.histogram(),
             percentiles,
-            event_type_breakdown: self.event_types.clone(),
+            digest: Some(digest),
+            jitter,
+            tail,
+            event_type_breakdown: counts.event_types(),
+            dedup,
+            dns_latency,
+            errors,
+            throughput,
+            listeners: self.listen_queues.report(elapsed_secs),
+            event_rate: self.event_rate.clone(),
+            bursts: self.bursts.stats(self.burst_factor.unwrap_or(DEFAULT_BURST_FACTOR)),
+            heatmap: self.heatmap.clone(),
+            trajectory,
+            coordinated_omission: self.expected_interval_us.map(|expected_interval_us| {
+                OmissionCorrection {
+                    expected_interval_us,
+                    synthetic_samples: self.synthetic_samples,
+                }
+            }),
             packet_drops: self.packet_drops.clone(),
             connection_states,
             context_switches,
             xdp_stats: XdpPacketStats::default(),
+            latency_bounds: None,
+            config_changes: self.config_changes.clone(),
+            fault_injections: self.fault_injections.clone(),
+            client: None,
+            envoy: Vec::new(),
+            mesh: None,
+            namespaces,
+            processes,
+            pods,
+            services,
+            protocols,
+            traffic_classes,
+            zones,
+            tenants,
+            phases,
+            http_status,
+            probes: self.probes.clone(),
+            program_stats: Vec::new(),
+            health: ProbeHealth {
+                maps: self.map_health.clone(),
+                event_loss_ratio: if self.lost_events > 0 {
+                    self.lost_events as f64 / (self.total_events + self.lost_events) as f64
+                } else {
+                    0.0
+                },
+                internal: InternalCounters {
+                    dropped_events: self.lost_events,
+                    ..self.internal.clone()
+                },
+                export: self.export.clone(),
+                channel: self.channel.clone(),
+            },
         }
     }
 
@@ -235,25 +1472,109 @@ impl MetricsCollector {
         self.total_events
     }
 
+    /// Number of samples in the overall latency distribution
+    pub fn sample_count(&self) -> usize {
+        self.all_latencies.len()
+    }
+
+    /// Percentiles of the samples added since an earlier
+    /// [`sample_count`](Self::sample_count), for per-interval latency
+    ///
+    /// Empty if the collector was rotated since.
+    pub fn percentiles_since(&self, start: usize) -> Percentiles {
+        calculate_percentiles(self.all_latencies.get(start..).unwrap_or_default().to_vec())
+    }
+
     /// Get number of unique connections
     pub fn connection_count(&self) -> usize {
         self.connection_latencies.len()
     }
 
-    /// Get histogram reference
-    pub fn histogram(&self) -> &LatencyHistogram {
-        &self.histogram
+    /// Worst connections so far
+    ///
+    /// Ranks the per-connection digests without generating a report: the
+    /// sort key is estimated for every connection, and the other figures
+    /// only for the connections returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `sort` - What makes a connection worse
+    /// * `limit` - Most connections to return
+    pub fn top_connections(&self, sort: ConnectionSort, limit: usize) -> Vec<TopConnection> {
+        let rank = |connection: &ConnectionLatency| match sort {
+            ConnectionSort::P99 => connection.digest.percentile(99, 100),
+            ConnectionSort::Avg => connection.digest.mean(),
+            ConnectionSort::Events => connection.digest.count() as f64,
+            ConnectionSort::Retransmits => connection.timeouts as f64,
+        };
+        let mut ranked: Vec<(f64, &ConnectionId, &ConnectionLatency)> = self
+            .connection_latencies
+            .iter()
+            .map(|(id, connection)| (rank(connection), id, connection))
+            .collect();
+
+        // Worst first, ties by connection so the order is stable
+        let order = |a: &(f64, &ConnectionId, &ConnectionLatency), b: &(f64, &ConnectionId, &ConnectionLatency)| {
+            b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1))
+        };
+        if ranked.len() > limit {
+            ranked.select_nth_unstable_by(limit, order);
+            ranked.truncate(limit);
+        }
+        ranked.sort_unstable_by(order);
+
+        ranked
+            .into_iter()
+            .map(|(_, id, connection)| {
+                let digest = &connection.digest;
+                TopConnection {
+                    connection: id.to_string(),
+                    events: digest.count(),
+                    avg_latency_us: digest.mean(),
+                    p50_us: digest.percentile(50, 100),
+                    p99_us: digest.percentile(99, 100),
+                    max_latency_us: digest.max(),
+                    timeouts: connection.timeouts,
+                }
+            })
+            .collect()
+    }
+
+    /// Get the latency histogram
+    pub fn histogram(&self) -> LatencyHistogram {
+        self.counters.counts().histogram()
+    }
+
+    /// Get the event type breakdown
+    pub fn event_types(&self) -> EventTypeBreakdown {
+        self.counters.counts().event_types()
     }
 
-    /// Get event type breakdown reference
-    pub fn event_types(&self) -> &EventTypeBreakdown {
-        &self.event_types
+    /// Histogram and event type counters since the start of the run
+    ///
+    /// They are shared with the intervals that follow rotations, and read
+    /// without locking the collector.
+    pub fn live_counters(&self) -> Arc<LiveCounters> {
+        self.counters.live()
+    }
+}
+
+/// Trajectory point of a snapshot interval ending at `end_secs`
+fn interval_percentiles(digest: &LatencyDigest, end_secs: u64) -> IntervalPercentiles {
+    let percentiles = digest.percentiles();
+    IntervalPercentiles {
+        end_secs,
+        events: digest.count(),
+        p50: percentiles.p50,
+        p95: percentiles.p95,
+        p99: percentiles.p99,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing;
     use probe_common::types::ConnectionKey;
 
     #[test]
@@ -268,12 +1589,9 @@ mod tests {
         };
 
         let event = LatencyEvent {
-            key,
             timestamp_ns: 1000000,
-            latency_ns: 500000,  // 500 microseconds
             pid: 1234,
-            event_type: probe_common::constants::EVENT_TYPE_RECV,
-            _padding: [0; 3],
+            ..testing::event(key, 500000, probe_common::constants::EVENT_TYPE_RECV)
         };
 
         collector.add_event(&event);
@@ -294,16 +1612,13 @@ mod tests {
         };
 
         // Add events in different buckets
-        let latencies = vec![500, 2000, 7000, 30000, 75000, 150000]; // in microseconds
+        let latencies = [500, 2000, 7000, 30000, 75000, 150000]; // in microseconds
 
         for (i, &latency_us) in latencies.iter().enumerate() {
             let event = LatencyEvent {
-                key,
                 timestamp_ns: (i as u64 + 1) * 1000000,
-                latency_ns: latency_us * 1000, // Convert to nanoseconds
                 pid: 1234,
-                event_type: probe_common::constants::EVENT_TYPE_RECV,
-                _padding: [0; 3],
+                ..testing::event(key, latency_us * 1000, probe_common::constants::EVENT_TYPE_RECV)
             };
             collector.add_event(&event);
         }
@@ -316,4 +1631,824 @@ mod tests {
         assert_eq!(histogram.bucket_50_100ms, 1);
         assert_eq!(histogram.bucket_100ms_plus, 1);
     }
+
+    #[test]
+    fn test_connection_percentiles() {
+        let mut collector = MetricsCollector::new();
+        collector.set_connection_limit(1);
+
+        let event = |sport: u16, latency_us: u64| LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 1234,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100007f,
+                    daddr: 0x0100007f,
+                    sport: sport.to_be(),
+                    dport: 80u16.to_be(),
+                },
+                latency_us * 1000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+        for latency_us in 1..=100 {
+            collector.add_event(&event(40000, latency_us));
+        }
+        // Beyond the limit: counted, but not broken down
+        collector.add_event(&event(40001, 5000));
+
+        let metrics = collector.generate_metrics(10);
+        assert_eq!(metrics.total_events, 101);
+        assert_eq!(metrics.untracked_connection_events, 1);
+        assert_eq!(metrics.connections.len(), 1);
+
+        let connection = &metrics.connections["127.0.0.1:40000 -> 127.0.0.1:80"];
+        assert_eq!(connection.events, 100);
+        assert_eq!(connection.min_latency_us, 1.0);
+        assert_eq!(connection.max_latency_us, 100.0);
+        assert_eq!(connection.avg_latency_us, 50.5);
+        assert!((connection.p50_us - 50.0).abs() <= 0.5);
+        assert!((connection.p99_us - 99.0).abs() <= 1.0);
+        assert!((connection.std_dev_us - 28.866).abs() < 0.01);
+        // Latency rising by 1us per request: jitter converging on 1us
+        assert!((connection.jitter_us - 1.0).abs() < 0.01);
+        assert_eq!(metrics.jitter.max_us, connection.jitter_us);
+    }
+
+    #[test]
+    fn test_connection_cookie_identity() {
+        let mut collector = MetricsCollector::new();
+        let event = |cookie: u64, latency_us: u64| LatencyEvent {
+            cookie,
+            pid: 1,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100007f,
+                    daddr: 0x0100007f,
+                    sport: 40000u16.to_be(),
+                    dport: 80u16.to_be(),
+                },
+                latency_us * 1000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        // The port is recycled for a second connection
+        collector.add_event(&event(4097, 100));
+        collector.add_event(&event(4097, 300));
+        collector.add_event(&event(8193, 5000));
+        // No cookie: identified by the tuple alone
+        collector.add_event(&event(0, 200));
+
+        let metrics = collector.generate_metrics(10);
+        assert_eq!(metrics.connections.len(), 3);
+        let first = &metrics.connections["127.0.0.1:40000 -> 127.0.0.1:80 #4097"];
+        assert_eq!(first.events, 2);
+        assert_eq!(first.cookie, Some(4097));
+        assert_eq!(first.destination, "127.0.0.1:80");
+        assert_eq!(metrics.connections["127.0.0.1:40000 -> 127.0.0.1:80 #8193"].max_latency_us, 5000.0);
+        assert_eq!(metrics.connections["127.0.0.1:40000 -> 127.0.0.1:80"].cookie, None);
+    }
+
+    #[test]
+    fn test_connection_id() {
+        let key = ConnectionKey {
+            saddr: 0x0100000a,
+            daddr: 0x0200000a,
+            sport: 50000u16.to_be(),
+            dport: 0,
+        };
+
+        // Renders the report keys the strings were built as
+        let id = ConnectionId::tuple(&key);
+        assert_eq!(id.to_string(), connection_key_to_string(&key));
+        assert_eq!(id.to_string(), "10.0.0.1:50000 -> 10.0.0.2:0");
+        let cookie = ConnectionId { cookie: 7, ..id };
+        assert_eq!(cookie.to_string(), "10.0.0.1:50000 -> 10.0.0.2:0 #7");
+        assert_eq!(cookie.without_cookie(), id);
+
+        // Only normalized ports render as "*"
+        let normalized = ConnectionId::normalized(&key);
+        assert_eq!(normalized.key.sport, 0);
+        assert_eq!(normalized.to_string(), "10.0.0.1:* -> 10.0.0.2:*");
+        assert_eq!(normalized.source(), "10.0.0.1:*");
+        assert_ne!(normalized, ConnectionId::tuple(&normalized.key));
+
+        // Survives a checkpoint
+        let bytes = bincode::serialize(&cookie).unwrap();
+        assert_eq!(bincode::deserialize::<ConnectionId>(&bytes).unwrap(), cookie);
+    }
+
+    #[test]
+    fn test_port_normalization() {
+        let mut collector = MetricsCollector::new();
+        collector.set_port_normalization(true);
+        let event = |sport: u16, dport: u16, cookie: u64| LatencyEvent {
+            cookie,
+            pid: 1,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a,
+                    daddr: 0x0200000a,
+                    sport: sport.to_be(),
+                    dport: dport.to_be(),
+                },
+                100_000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        // Client connections from ephemeral ports, and the server's side
+        collector.add_event(&event(50000, 8080, 4097));
+        collector.add_event(&event(61000, 8080, 8193));
+        collector.add_event(&event(8080, 55555, 0));
+        // Ports below the dynamic range are kept
+        collector.add_event(&event(40000, 8080, 0));
+
+        // The setting carries over to the next interval
+        let metrics = collector.rotate().generate_metrics(10);
+        assert_eq!(metrics.connections.len(), 3);
+        let client = &metrics.connections["10.0.0.1:* -> 10.0.0.2:8080"];
+        assert_eq!(client.events, 2);
+        assert_eq!(client.cookie, None);
+        assert_eq!(client.source, "10.0.0.1:*");
+        assert_eq!(metrics.connections["10.0.0.1:8080 -> 10.0.0.2:*"].events, 1);
+        assert_eq!(metrics.connections["10.0.0.1:40000 -> 10.0.0.2:8080"].events, 1);
+
+        collector.add_event(&event(50002, 8080, 0));
+        assert!(collector.generate_metrics(10).connections.contains_key("10.0.0.1:* -> 10.0.0.2:8080"));
+    }
+
+    #[test]
+    fn test_config_change_markers() {
+        let mut collector = MetricsCollector::new();
+        let config = ProbeConfig {
+            sample_rate: 10,
+            filter_services: vec!["*:8080".to_string()],
+            ..Default::default()
+        };
+
+        collector.record_config_change(&config);
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.config_changes.len(), 1);
+        assert_eq!(metrics.config_changes[0].events_before, 0);
+        assert_eq!(metrics.config_changes[0].sample_rate, 10);
+    }
+
+    #[test]
+    fn test_probe_metadata() {
+        let mut collector = MetricsCollector::new();
+        collector.set_probes(vec![
+            ProbeAttachment::from_result("tcp_recvmsg", Ok("tcp_recvmsg_locked".to_string())),
+            ProbeAttachment::from_result("tcp_cleanup_rbuf", Err(anyhow::anyhow!("inlined"))),
+        ]);
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.probes.len(), 2);
+        assert_eq!(metrics.probes[0].attached_to.as_deref(), Some("tcp_recvmsg_locked"));
+        assert!(!metrics.probes[1].is_active());
+        assert_eq!(metrics.probes[1].error.as_deref(), Some("inlined"));
+    }
+
+    #[test]
+    fn test_probe_health() {
+        let mut collector = MetricsCollector::new();
+        collector.set_map_health(vec![
+            MapHealth::new("CONNECTION_START", 9216, 10240),
+            MapHealth::new("STATS", 8, 32),
+        ]);
+        collector.add_lost_events(25);
+        collector.set_internal_counters(InternalCounters {
+            invalid_sockets: 3,
+            invalid_latency: 2,
+            dropped_events: 0,
+        });
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.health.maps[0].fill_ratio, 0.9);
+        assert!(metrics.health.maps[0].is_near_full());
+        assert!(!metrics.health.maps[1].is_near_full());
+        assert_eq!(metrics.health.event_loss_ratio, 1.0);
+        assert_eq!(metrics.health.internal.invalid_sockets, 3);
+        assert_eq!(metrics.health.internal.dropped_events, 25);
+
+        // Readings carry over to the next interval
+        collector.rotate();
+        assert_eq!(collector.generate_metrics(60).health.internal.invalid_latency, 2);
+    }
+
+    #[test]
+    fn test_process_breakdown() {
+        let mut collector = MetricsCollector::new();
+        collector.set_probes(vec![ProbeAttachment::from_result(
+            "tcp_sendmsg",
+            Ok("tcp_sendmsg".to_string()),
+        )]);
+
+        for (pid, latency_us) in [(1200, 100), (1200, 300), (3400, 50)] {
+            collector.add_event(&LatencyEvent {
+                pid,
+                ..testing::event(
+                    ConnectionKey {
+                        saddr: 0x0100007f,
+                        daddr: 0x0100007f,
+                        sport: 0x5000,
+                        dport: 0x5000,
+                    },
+                    latency_us * 1000,
+                    probe_common::constants::EVENT_TYPE_SEND,
+                )
+            });
+        }
+
+        let finished = collector.rotate();
+        let metrics = finished.generate_metrics(60);
+        assert_eq!(metrics.processes.len(), 2);
+        assert_eq!(metrics.processes["1200"].events, 2);
+        assert_eq!(metrics.processes["1200"].avg_latency_us, 200.0);
+        assert_eq!(metrics.processes["3400"].comm, None);
+        assert_eq!(metrics.services["http"].events, 3);
+
+        // The new interval starts empty but keeps the probe metadata
+        let metrics = collector.generate_metrics(60);
+        assert!(metrics.processes.is_empty());
+        assert_eq!(metrics.probes.len(), 1);
+    }
+
+    #[test]
+    fn test_process_limit() {
+        let mut collector = MetricsCollector::new();
+        collector.set_process_limit(2);
+
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x5000,
+            dport: 0x5000,
+        };
+        for pid in [1200, 3400, 1200, 5600, 7800] {
+            collector.add_event(&LatencyEvent {
+                pid,
+                ..testing::event(key, 100_000, probe_common::constants::EVENT_TYPE_RECV)
+            });
+        }
+
+        // Beyond the limit: counted, but not broken down
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.total_events, 5);
+        assert_eq!(metrics.processes.len(), 2);
+        assert_eq!(metrics.processes["1200"].events, 2);
+        assert_eq!(metrics.processes["1200"].digest.as_ref().unwrap().count(), 2);
+        assert_eq!(metrics.untracked_process_events, 2);
+
+        // The limit carries over to the next interval
+        collector.rotate();
+        collector.add_event(&LatencyEvent { pid: 1, ..testing::event(key, 100_000, probe_common::constants::EVENT_TYPE_RECV) });
+        assert_eq!(collector.generate_metrics(60).processes.len(), 1);
+    }
+
+    #[test]
+    fn test_dns_latency() {
+        let mut collector = MetricsCollector::new();
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x3500007f,
+            sport: 0x3930,
+            dport: 53u16.to_be(),
+        };
+
+        for (event_type, latency_us) in [
+            (probe_common::constants::EVENT_TYPE_DNS, 2000),
+            (probe_common::constants::EVENT_TYPE_DNS, 4000),
+            (probe_common::constants::EVENT_TYPE_RECV, 100),
+        ] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                ..testing::event(key, latency_us * 1000, event_type)
+            });
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.total_events, 3);
+        assert_eq!(metrics.dns_latency.queries, 2);
+        assert_eq!(metrics.dns_latency.avg_latency_us, 3000.0);
+        // DNS samples stay out of the TCP latency distribution
+        assert_eq!(metrics.histogram.total_count(), 1);
+        assert_eq!(metrics.percentiles.p99, 100.0);
+    }
+
+    #[test]
+    fn test_separate_cleanup_latency() {
+        use probe_common::constants::*;
+
+        let mut collector = MetricsCollector::new();
+        collector.set_dedup_policy(DedupPolicy::Separate);
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 40000u16.to_be(),
+            dport: 80u16.to_be(),
+        };
+
+        for (event_type, latency_us) in [(EVENT_TYPE_RECV, 100), (EVENT_TYPE_CLEANUP, 120), (EVENT_TYPE_RECV, 300)] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                ..testing::event(key, latency_us * 1000, event_type)
+            });
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.total_events, 3);
+        assert_eq!(metrics.event_type_breakdown.tcp_cleanup_rbuf, 1);
+        assert_eq!(metrics.dedup.policy, "separate");
+        // The cleanup stays out of the overall latency
+        assert_eq!(metrics.histogram.total_count(), 2);
+        let cleanup = metrics.dedup.cleanup.unwrap();
+        assert_eq!(cleanup.events, 1);
+        assert_eq!(cleanup.avg_latency_us, 120.0);
+    }
+
+    #[test]
+    fn test_connection_errors() {
+        use probe_common::constants::*;
+
+        let mut collector = MetricsCollector::new();
+        let redis = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0200007f,
+            sport: 40000u16.to_be(),
+            dport: 6379u16.to_be(),
+        };
+        let unknown = ConnectionKey {
+            dport: 12345u16.to_be(),
+            ..redis
+        };
+
+        for (key, event_type) in [
+            (redis, EVENT_TYPE_RESET_RECEIVED),
+            (redis, EVENT_TYPE_RESET_RECEIVED),
+            (redis, EVENT_TYPE_TIMEOUT),
+            (unknown, EVENT_TYPE_RESET_SENT),
+            (redis, EVENT_TYPE_RECV),
+        ] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                ..testing::event(key, if event_type == EVENT_TYPE_RECV { 100_000 } else { 0 }, event_type)
+            });
+        }
+
+        let metrics = collector.generate_metrics(2);
+        let errors = &metrics.errors;
+        assert_eq!((errors.resets_received, errors.resets_sent, errors.timeouts), (2, 1, 1));
+        assert_eq!(errors.resets_per_second, 1.5);
+        assert_eq!(errors.timeouts_per_second, 0.5);
+        assert_eq!(
+            errors.services["redis"],
+            ServiceErrors {
+                resets_received: 2,
+                resets_sent: 0,
+                timeouts: 1,
+            }
+        );
+        assert_eq!(errors.services.len(), 1);
+
+        // Resets and timeouts stay out of the latency distribution
+        assert_eq!(metrics.total_events, 1);
+        assert_eq!(metrics.histogram.total_count(), 1);
+        assert_eq!(metrics.connections.len(), 1);
+    }
+
+    #[test]
+    fn test_protocol_breakdown() {
+        use probe_common::constants::*;
+
+        let mut collector = MetricsCollector::new();
+        let key = ConnectionKey {
+            saddr: 0x0100000a,
+            daddr: 0x0200000a,
+            sport: 0x3930,
+            dport: 443u16.to_be(),
+        };
+
+        // An event without a protocol (from an old recording) is labeled
+        // by its type
+        for (event_type, protocol, latency_us) in [
+            (EVENT_TYPE_QUIC, IPPROTO_UDP, 3000),
+            (EVENT_TYPE_QUIC, IPPROTO_UDP, 5000),
+            (EVENT_TYPE_RECV, IPPROTO_TCP, 100),
+            (EVENT_TYPE_RECV, 0, 200),
+        ] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                protocol,
+                ..testing::event(key, latency_us * 1000, event_type)
+            });
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.event_type_breakdown.quic, 2);
+        assert_eq!(metrics.protocols["quic"].events, 2);
+        assert_eq!(metrics.protocols["quic"].avg_latency_us, 4000.0);
+        assert_eq!(metrics.protocols["tcp"].events, 2);
+        assert_eq!(metrics.protocols["quic"].histogram.counts(), [0, 1, 1, 0, 0, 0]);
+        assert_eq!(metrics.protocols["tcp"].histogram.counts(), [2, 0, 0, 0, 0, 0]);
+        // QUIC flights are part of the overall latency
+        assert_eq!(metrics.histogram.total_count(), 4);
+    }
+
+    #[test]
+    fn test_phase_breakdown() {
+        use probe_common::constants::*;
+
+        let mut collector = MetricsCollector::new();
+        let key = ConnectionKey {
+            saddr: 0x0100000a,
+            daddr: 0x0200000a,
+            sport: 0x3930,
+            dport: 0x901f,
+        };
+
+        for (tcp_state, latency_us) in [
+            (TCP_STATE_ESTABLISHED, 100),
+            (TCP_STATE_ESTABLISHED, 300),
+            (TCP_STATE_SYN_RECV, 900),
+            (TCP_STATE_CLOSE_WAIT, 50),
+            (TCP_STATE_CLOSE, 70),
+        ] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                tcp_state,
+                ..testing::event(key, latency_us * 1000, EVENT_TYPE_RECV)
+            });
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.phases[PHASE_ESTABLISHED].events, 2);
+        assert_eq!(metrics.phases[PHASE_ESTABLISHED].avg_latency_us, 200.0);
+        assert_eq!(metrics.phases[PHASE_HANDSHAKE].events, 1);
+        assert_eq!(metrics.phases[PHASE_CLOSING].events, 2);
+        assert!(!metrics.phases.contains_key(PHASE_UNKNOWN));
+    }
+
+    #[test]
+    fn test_http_status_breakdown() {
+        let mut collector = MetricsCollector::new();
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x3930,
+            dport: 0x5000,
+        };
+
+        for (http_status_class, latency_us) in [(2, 100), (2, 300), (5, 2000), (0, 50)] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                http_status_class,
+                ..testing::event(key, latency_us * 1000, probe_common::constants::EVENT_TYPE_CLEANUP)
+            });
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.http_status.len(), 2);
+        assert_eq!(metrics.http_status["2xx"].events, 2);
+        assert_eq!(metrics.http_status["2xx"].avg_latency_us, 200.0);
+        assert_eq!(metrics.http_status["5xx"].percentiles.p50, 2000.0);
+        // Unclassified events still count towards the overall latency
+        assert_eq!(metrics.histogram.total_count(), 4);
+    }
+
+    #[test]
+    fn test_connection_throughput() {
+        use probe_common::constants::{CONN_STATE_CLOSED, CONN_STATE_ESTABLISHED};
+
+        let mut collector = MetricsCollector::new();
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x3930,
+            dport: 0x5000,
+        };
+        let state = |state, bytes_sent, bytes_received| kernel::ConnectionState {
+            key,
+            start_time_ns: 0,
+            close_time_ns: 0,
+            state,
+            bytes_sent,
+            bytes_received,
+            pid: 1,
+            _padding: [0; 4],
+        };
+
+        collector.add_event(&LatencyEvent {
+            pid: 1,
+            ..testing::event(key, 100_000, probe_common::constants::EVENT_TYPE_RECV)
+        });
+        collector.add_connection_bytes(&state(CONN_STATE_ESTABLISHED, 1000, 4000));
+
+        // Counters are cumulative; the next interval only sees the increase
+        let finished = collector.rotate();
+        let metrics = finished.generate_metrics(10);
+        let conn = &metrics.connections["127.0.0.1:12345 -> 127.0.0.1:80"];
+        assert_eq!(conn.bytes_sent, 1000);
+        assert_eq!(conn.bytes_received, 4000);
+        assert_eq!(conn.bytes_per_second, 500.0);
+        assert_eq!(metrics.throughput.received_bytes_per_second, 400.0);
+
+        collector.add_connection_bytes(&state(CONN_STATE_CLOSED, 1500, 4000));
+        let metrics = collector.generate_metrics(10);
+        assert_eq!(metrics.throughput.bytes_sent, 500);
+        assert_eq!(metrics.throughput.bytes_received, 0);
+        // Per-connection figures need a latency event in the interval
+        assert!(metrics.connections.is_empty());
+    }
+
+    #[test]
+    fn test_connection_congestion() {
+        let mut collector = MetricsCollector::new();
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x3930,
+            dport: 0x5000,
+        };
+        let event = LatencyEvent {
+            pid: 1,
+            ..testing::event(key, 100_000, probe_common::constants::EVENT_TYPE_TCP_PROBE)
+        };
+        let state = |samples, srtt_sum_us, cwnd_collapses, snd_cwnd, ssthresh| kernel::CongestionState {
+            samples,
+            srtt_sum_us,
+            cwnd_collapses,
+            snd_cwnd,
+            ssthresh,
+            srtt_us: 400,
+            min_snd_cwnd: snd_cwnd.min(10),
+            max_srtt_us: 900,
+            _padding: [0; 4],
+        };
+
+        // Still in slow start: no threshold yet
+        collector.add_event(&event);
+        collector.add_congestion(&[(key, state(4, 1000, 0, 40, INFINITE_SSTHRESH))]);
+        let finished = collector.rotate();
+        let congestion = finished.generate_metrics(10).connections["127.0.0.1:12345 -> 127.0.0.1:80"]
+            .congestion
+            .clone()
+            .unwrap();
+        assert_eq!((congestion.samples, congestion.snd_cwnd, congestion.ssthresh), (4, 40, None));
+        assert_eq!(congestion.avg_srtt_us, 250.0);
+
+        // The next interval only counts the records since the last reading
+        collector.add_event(&event);
+        collector.add_congestion(&[(key, state(10, 4000, 1, 8, 20))]);
+        let metrics = collector.generate_metrics(10);
+        let congestion = metrics.connections["127.0.0.1:12345 -> 127.0.0.1:80"].congestion.as_ref().unwrap();
+        assert_eq!(congestion.samples, 6);
+        assert_eq!(congestion.avg_srtt_us, 500.0);
+        assert_eq!(congestion.cwnd_collapses, 1);
+        assert_eq!((congestion.snd_cwnd, congestion.min_snd_cwnd, congestion.ssthresh), (8, 8, Some(20)));
+        assert_eq!(congestion.max_srtt_us, 900);
+
+        // Without new records there is nothing to report
+        let mut collector = MetricsCollector::new();
+        collector.add_event(&event);
+        let metrics = collector.generate_metrics(10);
+        assert!(metrics.connections["127.0.0.1:12345 -> 127.0.0.1:80"].congestion.is_none());
+    }
+
+    #[test]
+    fn test_event_rate_and_heatmap() {
+        let mut collector = MetricsCollector::new();
+        collector.set_rate_resolution(500);
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x3930,
+            dport: 0x5000,
+        };
+
+        // Two events in the first half second, none in the second, one in
+        // the third; the 900ms event arrived late from another CPU
+        for timestamp_ms in [1000, 1200, 900, 2100, 2600] {
+            collector.add_event(&LatencyEvent {
+                timestamp_ns: timestamp_ms * 1_000_000,
+                pid: 1,
+                ..testing::event(key, 100_000, probe_common::constants::EVENT_TYPE_RECV)
+            });
+        }
+
+        let metrics = collector.generate_metrics(2);
+        assert_eq!(metrics.heatmap.intervals.len(), 4);
+        assert_eq!(metrics.heatmap.intervals[0].bucket_0_1ms, 3);
+        assert_eq!(metrics.heatmap.intervals[1].total_count(), 0);
+        assert_eq!(metrics.event_rate.resolution_ms, 500);
+        assert_eq!(metrics.event_rate.events, vec![3, 0, 1, 1]);
+        assert_eq!(metrics.event_rate.rates()[0], 6.0);
+        // The dip shows up; the last (partial) bucket is left out
+        assert_eq!(metrics.event_rate.rate_range(), Some((0.0, 6.0)));
+
+        let next = collector.rotate();
+        assert_eq!(next.generate_metrics(2).event_rate.events.len(), 4);
+        assert_eq!(collector.generate_metrics(0).event_rate.resolution_ms, 500);
+        assert_eq!(collector.generate_metrics(0).event_rate.rate_range(), None);
+    }
+
+    #[test]
+    fn test_coordinated_omission() {
+        let mut collector = MetricsCollector::new();
+        collector.set_expected_interval(Duration::from_millis(1));
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x3930,
+            dport: 0x5000,
+        };
+
+        for latency_us in [200, 200, 4500] {
+            collector.add_event(&LatencyEvent {
+                pid: 1,
+                ..testing::event(key, latency_us * 1000, probe_common::constants::EVENT_TYPE_RECV)
+            });
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(
+            metrics.coordinated_omission,
+            Some(OmissionCorrection {
+                expected_interval_us: 1000.0,
+                synthetic_samples: 3,
+            })
+        );
+        // 3500, 2500 and 1500us were backfilled; events count what was measured
+        assert_eq!(metrics.total_events, 3);
+        assert_eq!(metrics.histogram.bucket_1_5ms, 4);
+        assert_eq!(metrics.percentiles.p50, 1500.0);
+        assert_eq!(metrics.connections.values().next().unwrap().events, 3);
+
+        assert!(MetricsCollector::new().generate_metrics(60).coordinated_omission.is_none());
+    }
+
+    #[test]
+    fn test_namespace_breakdown() {
+        let mut collector = MetricsCollector::new();
+
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x5000,
+            dport: 0x5000,
+        };
+
+        for (netns, latency_us) in [(4026531840, 100), (4026531840, 300), (4026532288, 50), (0, 10)] {
+            let event = LatencyEvent {
+                netns,
+                timestamp_ns: 1000000,
+                pid: 1234,
+                ..testing::event(key, latency_us * 1000, probe_common::constants::EVENT_TYPE_RECV)
+            };
+            collector.add_event(&event);
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.namespaces.len(), 3);
+
+        let host = &metrics.namespaces["4026531840"];
+        assert_eq!(host.events, 2);
+        assert_eq!(host.avg_latency_us, 200.0);
+        assert_eq!(metrics.namespaces["4026532288"].events, 1);
+        assert_eq!(metrics.namespaces["unknown"].events, 1);
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let event = |latency_us: u64| LatencyEvent {
+            timestamp_ns: 1000000,
+            pid: 1234,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100007f,
+                    daddr: 0x0100007f,
+                    sport: 0x409c,
+                    dport: 0x5000,
+                },
+                latency_us * 1000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        // Off unless enabled
+        let mut collector = MetricsCollector::new();
+        collector.add_event(&event(100));
+        collector.close_interval(10);
+        assert!(collector.generate_metrics(20).trajectory.is_empty());
+
+        let mut collector = MetricsCollector::new();
+        collector.enable_trajectory();
+        collector.add_event(&event(100));
+        collector.close_interval(10);
+        collector.close_interval(20);
+        collector.add_event(&event(1000));
+        collector.add_event(&event(1000));
+
+        // The interval in progress ends with the report
+        let first = collector.generate_metrics(25);
+        let points: Vec<_> = first.trajectory.iter().map(|p| (p.end_secs, p.events)).collect();
+        assert_eq!(points, [(10, 1), (25, 2)]);
+        assert!((first.trajectory[1].p99 - 1000.0).abs() <= 10.0);
+
+        // Consecutive intervals are laid end to end; parallel ones dropped
+        let mut second = collector.rotate().generate_metrics(25);
+        second.merge_following(&first);
+        let ends: Vec<_> = second.trajectory.iter().map(|p| p.end_secs).collect();
+        assert_eq!(ends, [10, 25, 35, 50]);
+        second.merge(&first);
+        assert!(second.trajectory.is_empty());
+    }
+
+    #[test]
+    fn test_tenant_breakdown() {
+        let mut collector = MetricsCollector::new();
+        collector.set_tenants(
+            crate::tenants::TenantSet::from_yaml("web:\n  ports: [80]\nhost:\n  namespaces: [4026531840]\n").unwrap(),
+        );
+
+        for (dport, netns, latency_us) in [(80u16, 4026531840, 100), (80, 0, 300), (443, 4026531840, 50), (443, 0, 10)] {
+            let event = LatencyEvent {
+                netns,
+                timestamp_ns: 1000000,
+                pid: 1234,
+                ..testing::event(
+                    ConnectionKey {
+                        saddr: 0x0100007f,
+                        daddr: 0x0100007f,
+                        sport: 0x409c,
+                        dport: dport.to_be(),
+                    },
+                    latency_us * 1000,
+                    probe_common::constants::EVENT_TYPE_RECV,
+                )
+            };
+            collector.add_event(&event);
+        }
+
+        let metrics = collector.generate_metrics(60);
+        assert_eq!(metrics.tenants.len(), 2);
+        assert_eq!(metrics.tenants["web"].events, 2);
+        assert_eq!(metrics.tenants["host"].events, 2);
+        assert!((metrics.tenants["web"].avg_latency_us - 200.0).abs() < 1e-9);
+
+        // Tenant percentiles stay accurate across merged reports
+        let mut merged = metrics.clone();
+        merged.merge(&metrics);
+        assert_eq!(merged.tenants["web"].events, 4);
+        assert_eq!(merged.tenants["web"].percentiles.p99, metrics.tenants["web"].percentiles.p99);
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let key = ConnectionKey {
+            saddr: 0x0100007f,
+            daddr: 0x0100007f,
+            sport: 0x5000,
+            dport: 0x5000,
+        };
+        let event = |second: u64, latency_us: u64| LatencyEvent {
+            timestamp_ns: second * 1_000_000_000,
+            pid: 1234,
+            ..testing::event(key, latency_us * 1000, probe_common::constants::EVENT_TYPE_RECV)
+        };
+
+        let mut collector = MetricsCollector::new();
+        collector.set_rolling_window(Duration::from_secs(60));
+        let pane_secs = collector.rolling_window().unwrap().pane_length().as_secs();
+        assert_eq!(pane_secs, 10);
+
+        // A slow first pane, then fast ones until it leaves the window
+        for pane in 0..6u64 {
+            let latency_us = if pane == 0 { 1000 } else { 100 };
+            for i in 0..10 {
+                collector.add_event(&event(pane * pane_secs + i, latency_us));
+            }
+            let metrics = collector.generate_metrics(pane_secs);
+            assert_eq!(metrics.total_events, 10 * (pane + 1).min(6));
+            assert_eq!(metrics.duration_seconds, pane_secs * (pane + 1));
+            collector.roll_window(pane_secs);
+        }
+        let metrics = collector.generate_metrics(0);
+        assert_eq!(metrics.total_events, 50);
+        assert_eq!(metrics.duration_seconds, 50);
+        assert!((metrics.percentiles.p99 - 100.0).abs() < 1.0);
+        // One bucket per second, laid end to end
+        assert_eq!(metrics.event_rate.events.len(), 50);
+
+        // A reset empties the window; a snapshot and reset reports it first
+        collector.add_event(&event(60, 100));
+        assert_eq!(collector.snapshot_and_reset(0).total_events, 51);
+        assert_eq!(collector.generate_metrics(0).total_events, 0);
+        collector.add_event(&event(61, 100));
+        collector.reset();
+        assert_eq!(collector.event_count(), 0);
+        assert!(collector.rolling_window().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,171 @@
+//! Compressed output files
+//!
+//! Hour-long runs produce hundreds of megabytes of JSON. Reports and
+//! recordings can be written through a gzip or zstd encoder instead; data
+//! is compressed as it is written, so memory use does not grow with the
+//! output. Recordings are decompressed transparently on replay, based on
+//! their file extension.
+
+use anyhow::{Context, Result};
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// zstd level used for output (the library default)
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression applied to output files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    /// Plain output
+    #[default]
+    None,
+    /// gzip (.gz)
+    Gzip,
+    /// Zstandard (.zst)
+    Zstd,
+}
+
+impl Compression {
+    /// Compression implied by a file name's extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Destination of an [`OutputWriter`]
+type Sink = Box<dyn Write + Send>;
+
+/// A file (or other sink) written through the configured encoder
+pub enum OutputWriter {
+    /// Uncompressed
+    Plain(BufWriter<Sink>),
+    /// gzip
+    Gzip(GzEncoder<BufWriter<Sink>>),
+    /// Zstandard
+    Zstd(zstd::Encoder<'static, BufWriter<Sink>>),
+}
+
+impl OutputWriter {
+    /// Create (or truncate) an output file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the output file
+    /// * `compression` - Encoder to write through
+    pub fn create(path: &Path, compression: Compression) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create output file: {:?}", path))?;
+        Self::new(Box::new(file), compression)
+    }
+
+    /// Write to any sink through an encoder
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Destination of the encoded output
+    /// * `compression` - Encoder to write through
+    pub fn new(sink: Sink, compression: Compression) -> Result<Self> {
+        let file = BufWriter::new(sink);
+
+        Ok(match compression {
+            Compression::None => OutputWriter::Plain(file),
+            Compression::Gzip => OutputWriter::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => OutputWriter::Zstd(
+                zstd::Encoder::new(file, ZSTD_LEVEL).context("Failed to start zstd encoder")?,
+            ),
+        })
+    }
+
+    /// Write the compression trailer and flush the file
+    ///
+    /// Must be called once all data is written; nothing may be written
+    /// afterwards.
+    pub fn finish(&mut self) -> Result<()> {
+        // Finished encoders may not be written to, even to flush, so the
+        // underlying file is flushed directly
+        let file = match self {
+            OutputWriter::Plain(file) => file,
+            OutputWriter::Gzip(encoder) => {
+                encoder.try_finish()?;
+                encoder.get_mut()
+            }
+            OutputWriter::Zstd(encoder) => {
+                encoder.do_finish()?;
+                encoder.get_mut()
+            }
+        };
+        file.flush().context("Failed to flush output file")
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(writer) => writer.write(buf),
+            OutputWriter::Gzip(writer) => writer.write(buf),
+            OutputWriter::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(writer) => writer.flush(),
+            OutputWriter::Gzip(writer) => writer.flush(),
+            OutputWriter::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Open a file for reading, decompressing `.gz` and `.zst` files
+pub fn open(path: &Path) -> Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Compression::Zstd => Box::new(BufReader::new(
+            zstd::Decoder::new(file).context("Failed to start zstd decoder")?,
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let line = "{\"latency_ns\":1000}\n";
+
+        for (name, compression) in [
+            ("events.jsonl", Compression::None),
+            ("events.jsonl.gz", Compression::Gzip),
+            ("events.jsonl.zst", Compression::Zstd),
+        ] {
+            let path = dir.path().join(name);
+            assert_eq!(Compression::from_path(&path), compression);
+
+            let mut writer = OutputWriter::create(&path, compression).unwrap();
+            for _ in 0..1000 {
+                writer.write_all(line.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut contents = String::new();
+            open(&path).unwrap().read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, line.repeat(1000));
+            if compression != Compression::None {
+                assert!(std::fs::metadata(&path).unwrap().len() < 1000);
+            }
+        }
+    }
+}
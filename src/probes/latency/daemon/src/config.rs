@@ -0,0 +1,365 @@
+//! Runtime probe configuration
+//!
+//! Settings that can change while the probe is running. They are written to
+//! the kernel CONFIG and SERVICE_FILTER maps, so a benchmark can switch
+//! phases (e.g. a different target service or sampling rate) without
+//! restarting the probe and losing collected data.
+//!
+//! ## Example
+//!
+//! ```yaml
+//! sample_rate: 10
+//! sample_mode: flow
+//! event_sample_rates:
+//!   recv: 100
+//!   send: 1
+//! filter_services:
+//!   - 10.96.0.15:8080
+//!   - "*:9080"
+//! ```
+
+use anyhow::{Context, Result};
+use log::debug;
+use probe_common::{constants::*, types::ServiceFilterKey};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+use tokio::time::interval;
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Event types that can be sampled at their own rate, by name
+///
+/// Resets and retransmission timeouts are never sampled.
+pub const SAMPLED_EVENT_TYPES: [(&str, u8); 6] = [
+    ("send", EVENT_TYPE_SEND),
+    ("recv", EVENT_TYPE_RECV),
+    ("cleanup", EVENT_TYPE_CLEANUP),
+    ("tcp_probe", EVENT_TYPE_TCP_PROBE),
+    ("dns", EVENT_TYPE_DNS),
+    ("quic", EVENT_TYPE_QUIC),
+];
+
+/// Event type of a name in SAMPLED_EVENT_TYPES
+pub fn sampled_event_type(name: &str) -> Result<u8> {
+    SAMPLED_EVENT_TYPES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, event_type)| *event_type)
+        .with_context(|| {
+            let names: Vec<_> = SAMPLED_EVENT_TYPES.iter().map(|(name, _)| *name).collect();
+            format!("Unknown event type '{}', expected one of {}", name, names.join(", "))
+        })
+}
+
+/// What sampling keeps
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleMode {
+    /// A random 1 in N events; every connection gets holes
+    #[default]
+    Event,
+    /// Every event of 1 in N connections, picked by hashing the connection,
+    /// so per-connection distributions are kept whole
+    Flow,
+}
+
+impl SampleMode {
+    /// Value of the kernel CONFIG_SAMPLE_MODE slot
+    pub fn config_value(self) -> u64 {
+        match self {
+            SampleMode::Event => SAMPLE_MODE_EVENT,
+            SampleMode::Flow => SAMPLE_MODE_FLOW,
+        }
+    }
+}
+
+impl std::fmt::Display for SampleMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SampleMode::Event => write!(f, "event"),
+            SampleMode::Flow => write!(f, "flow"),
+        }
+    }
+}
+
+/// Sampling rates of `--sample-rate`
+///
+/// A rate for every event type (`10`), rates of some event types
+/// (`recv=100,cleanup=100,send=1`), or both (`10,send=1`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleRates {
+    /// Rate of event types without their own
+    pub rate: u32,
+    /// Rates of single event types, by name
+    pub events: BTreeMap<String, u32>,
+}
+
+impl FromStr for SampleRates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut rates = Self {
+            rate: DEFAULT_SAMPLE_RATE,
+            events: BTreeMap::new(),
+        };
+        for part in s.split(',').map(str::trim) {
+            let parse = |rate: &str| {
+                rate.parse::<u32>()
+                    .with_context(|| format!("Invalid sampling rate '{}'", rate))
+            };
+            match part.split_once('=') {
+                Some((name, rate)) => {
+                    sampled_event_type(name)?;
+                    rates.events.insert(name.to_string(), parse(rate)?);
+                }
+                None => rates.rate = parse(part)?,
+            }
+        }
+        Ok(rates)
+    }
+}
+
+/// Reloadable probe settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProbeConfig {
+    /// Sampling rate (1 = capture all, 100 = capture 1 in 100)
+    pub sample_rate: u32,
+    /// Whether events or whole connections are sampled
+    pub sample_mode: SampleMode,
+    /// Sampling rates of single event types (see SAMPLED_EVENT_TYPES),
+    /// overriding sample_rate
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub event_sample_rates: BTreeMap<String, u32>,
+    /// Services to track (`IP:PORT`, `*:PORT` or `IP:*`); empty tracks
+    /// everything
+    pub filter_services: Vec<String>,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_mode: SampleMode::default(),
+            event_sample_rates: BTreeMap::new(),
+            filter_services: Vec::new(),
+        }
+    }
+}
+
+impl ProbeConfig {
+    /// Load configuration from a YAML (or JSON) file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {:?}", path))?;
+        let config: Self = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that all settings are usable
+    pub fn validate(&self) -> Result<()> {
+        if self.sample_rate == 0 {
+            anyhow::bail!("Sample rate must be >= 1");
+        }
+        for (name, &rate) in &self.event_sample_rates {
+            sampled_event_type(name)?;
+            if rate == 0 {
+                anyhow::bail!("Sample rate of {} events must be >= 1", name);
+            }
+        }
+        self.service_filter_keys()?;
+        Ok(())
+    }
+
+    /// This config with more services to track
+    ///
+    /// Used to merge services found at run time (e.g. the IPs of Kubernetes
+    /// pods) with the configured ones.
+    pub fn with_services(&self, services: &[String]) -> Self {
+        let mut config = self.clone();
+        config.filter_services.extend(services.iter().cloned());
+        config
+    }
+
+    /// Sampling rates of the event types with their own, by event type
+    pub fn event_sample_rates_by_type(&self) -> Result<BTreeMap<u8, u32>> {
+        self.event_sample_rates
+            .iter()
+            .map(|(name, &rate)| Ok((sampled_event_type(name)?, rate)))
+            .collect()
+    }
+
+    /// Sampling rates and mode, for logs
+    pub fn describe_sample_rates(&self) -> String {
+        let mut rates = vec![format!("1 in {}", self.sample_rate)];
+        for (name, rate) in &self.event_sample_rates {
+            rates.push(format!("{} 1 in {}", name, rate));
+        }
+        let sampled = self.sample_rate > 1 || self.event_sample_rates.values().any(|&rate| rate > 1);
+        if sampled && self.sample_mode == SampleMode::Flow {
+            return format!("{} (of connections)", rates.join(", "));
+        }
+        rates.join(", ")
+    }
+
+    /// Service filter entries in the kernel map representation
+    pub fn service_filter_keys(&self) -> Result<Vec<ServiceFilterKey>> {
+        self.filter_services
+            .iter()
+            .map(|spec| parse_service(spec))
+            .collect()
+    }
+}
+
+/// Parse a service specification into a filter key
+///
+/// Accepts `IP:PORT`, `*:PORT` / `:PORT` to match any address, or `IP:*`
+/// to match any port (port 0 in the kernel map).
+pub fn parse_service(spec: &str) -> Result<ServiceFilterKey> {
+    let (addr, port) = spec
+        .rsplit_once(':')
+        .with_context(|| format!("Invalid service '{}', expected IP:PORT", spec))?;
+    if matches!(addr, "" | "*") && port == "*" {
+        anyhow::bail!("Invalid service '{}', the address or port must be given", spec);
+    }
+
+    let addr: u32 = match addr {
+        "" | "*" => 0,
+        addr => addr
+            .parse::<Ipv4Addr>()
+            .with_context(|| format!("Invalid service address: {}", addr))?
+            .into(),
+    };
+    let port: u16 = match port {
+        "*" => 0,
+        port => port
+            .parse()
+            .with_context(|| format!("Invalid service port: {}", port))?,
+    };
+
+    Ok(ServiceFilterKey {
+        addr: addr.to_be(),
+        port: port.to_be(),
+        _padding: [0; 2],
+    })
+}
+
+/// Polls a config file for modifications
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Start watching `path` from its current modification time
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            modified: modified_time(path),
+        }
+    }
+
+    /// Path of the watched file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait until the file's modification time changes
+    pub async fn changed(&mut self) {
+        let mut ticker = interval(WATCH_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let modified = modified_time(&self.path);
+            if modified != self.modified {
+                debug!("Config file {:?} changed", self.path);
+                self.modified = modified;
+                return;
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_service() {
+        let key = parse_service("10.0.0.1:8080").unwrap();
+        assert_eq!(key.addr, 0x0100000a); // 10.0.0.1 in network byte order
+        assert_eq!(key.port, 0x901f); // 8080 in network byte order
+
+        assert_eq!(parse_service("*:9080").unwrap().addr, 0);
+        assert_eq!(parse_service(":9080").unwrap().port, 9080u16.to_be());
+
+        let key = parse_service("10.0.0.1:*").unwrap();
+        assert_eq!((key.addr, key.port), (0x0100000a, 0));
+        assert!(parse_service("*:*").is_err());
+
+        assert!(parse_service("10.0.0.1").is_err());
+        assert!(parse_service("10.0.0.1:http").is_err());
+        assert!(parse_service("not-an-ip:80").is_err());
+    }
+
+    #[test]
+    fn test_load_yaml_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("probe.yaml");
+
+        std::fs::write(&path, "sample_rate: 10\nfilter_services:\n  - \"*:8080\"\n").unwrap();
+        let config = ProbeConfig::load(&path).unwrap();
+        assert_eq!(config.sample_rate, 10);
+        assert_eq!(config.filter_services, vec!["*:8080".to_string()]);
+
+        // Missing fields fall back to defaults
+        std::fs::write(&path, "filter_services: []\n").unwrap();
+        assert_eq!(ProbeConfig::load(&path).unwrap(), ProbeConfig::default());
+
+        std::fs::write(&path, "sample_rate: 0\n").unwrap();
+        assert!(ProbeConfig::load(&path).is_err());
+
+        std::fs::write(&path, "event_sample_rates:\n  recv: 100\n").unwrap();
+        let config = ProbeConfig::load(&path).unwrap();
+        assert_eq!(config.event_sample_rates_by_type().unwrap(), BTreeMap::from([(EVENT_TYPE_RECV, 100)]));
+        std::fs::write(&path, "event_sample_rates:\n  reset: 100\n").unwrap();
+        assert!(ProbeConfig::load(&path).is_err());
+
+        std::fs::write(&path, "sample_mode: flow\n").unwrap();
+        assert_eq!(ProbeConfig::load(&path).unwrap().sample_mode, SampleMode::Flow);
+    }
+
+    #[test]
+    fn test_parse_sample_rates() {
+        let rates: SampleRates = "recv=100,cleanup=100,send=1".parse().unwrap();
+        assert_eq!(rates.rate, 1);
+        assert_eq!(rates.events.len(), 3);
+        assert_eq!(rates.events["recv"], 100);
+
+        let rates: SampleRates = "10, send=1".parse().unwrap();
+        assert_eq!((rates.rate, rates.events["send"]), (10, 1));
+        assert_eq!("5".parse::<SampleRates>().unwrap().events, BTreeMap::new());
+
+        assert!("recv=".parse::<SampleRates>().is_err());
+        assert!("timeout=10".parse::<SampleRates>().is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! Destinations behind NAT, from conntrack
+//!
+//! With kube-proxy, clients connect to a Service's ClusterIP and netfilter
+//! rewrites the destination to one of its pods (DNAT). The socket, and so
+//! every event, keeps the ClusterIP tuple, so per-service and per-zone
+//! attribution sees the Service port rather than the pod that answered.
+//!
+//! With `--conntrack`, the conntrack entry of each new connection is looked
+//! up over netlink (NETLINK_NETFILTER, IPCTNL_MSG_CT_GET) keyed by the
+//! event's tuple. If the entry's reply comes from another address or port,
+//! that is the real destination. Lookups are cached per tuple, so a
+//! connection costs one round trip to the kernel.
+//!
+//! Only the probe's network namespace is looked up, which is where
+//! kube-proxy translates pod traffic. Needs CAP_NET_ADMIN.
+
+use anyhow::{Context, Result};
+use probe_common::types::ConnectionKey;
+use std::{
+    collections::HashMap,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::{Duration, Instant},
+};
+
+/// Maximum number of cached lookups
+const MAX_ENTRIES: usize = 65536;
+
+/// How long a receive waits for the kernel
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// nfnetlink subsystem of conntrack
+const NFNL_SUBSYS_CTNETLINK: u16 = 1;
+/// Get a conntrack entry
+const IPCTNL_MSG_CT_GET: u16 = 1;
+/// Netlink error (or acknowledgement) message
+const NLMSG_ERROR: u16 = 2;
+/// Netlink request flag
+const NLM_F_REQUEST: u16 = 1;
+/// Attribute holding nested attributes
+const NLA_F_NESTED: u16 = 0x8000;
+/// Attribute type bits
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const CTA_TUPLE_ORIG: u16 = 1;
+const CTA_TUPLE_REPLY: u16 = 2;
+const CTA_TUPLE_IP: u16 = 1;
+const CTA_TUPLE_PROTO: u16 = 2;
+const CTA_IP_V4_SRC: u16 = 1;
+const CTA_IP_V4_DST: u16 = 2;
+const CTA_PROTO_NUM: u16 = 1;
+const CTA_PROTO_SRC_PORT: u16 = 2;
+const CTA_PROTO_DST_PORT: u16 = 3;
+
+/// Length of struct nlmsghdr
+const NLMSG_HEADER: usize = 16;
+/// Length of struct nfgenmsg
+const NFGENMSG: usize = 4;
+
+/// Append an attribute, padded to 4 bytes
+fn put_attr(buf: &mut Vec<u8>, attr_type: u16, payload: &[u8]) {
+    buf.extend_from_slice(&(4 + payload.len() as u16).to_ne_bytes());
+    buf.extend_from_slice(&attr_type.to_ne_bytes());
+    buf.extend_from_slice(payload);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// Append a nested attribute
+fn put_nested(buf: &mut Vec<u8>, attr_type: u16, nested: &[u8]) {
+    put_attr(buf, attr_type | NLA_F_NESTED, nested);
+}
+
+/// Request for the conntrack entry whose original direction is a TCP tuple
+///
+/// # Arguments
+///
+/// * `key` - Tuple of the connection as its socket sees it
+/// * `seq` - Sequence number of the request
+fn encode_get(key: &ConnectionKey, seq: u32) -> Vec<u8> {
+    let mut ip = Vec::new();
+    // Addresses and ports are already in network byte order
+    put_attr(&mut ip, CTA_IP_V4_SRC, &key.saddr.to_ne_bytes());
+    put_attr(&mut ip, CTA_IP_V4_DST, &key.daddr.to_ne_bytes());
+    let mut proto = Vec::new();
+    put_attr(&mut proto, CTA_PROTO_NUM, &[libc::IPPROTO_TCP as u8]);
+    put_attr(&mut proto, CTA_PROTO_SRC_PORT, &key.sport.to_ne_bytes());
+    put_attr(&mut proto, CTA_PROTO_DST_PORT, &key.dport.to_ne_bytes());
+    let mut tuple = Vec::new();
+    put_nested(&mut tuple, CTA_TUPLE_IP, &ip);
+    put_nested(&mut tuple, CTA_TUPLE_PROTO, &proto);
+
+    let mut msg = vec![0; NLMSG_HEADER];
+    // nfgenmsg: family, version, resource id
+    msg.extend_from_slice(&[libc::AF_INET as u8, 0, 0, 0]);
+    put_nested(&mut msg, CTA_TUPLE_ORIG, &tuple);
+
+    let len = msg.len() as u32;
+    msg[0..4].copy_from_slice(&len.to_ne_bytes());
+    msg[4..6].copy_from_slice(&((NFNL_SUBSYS_CTNETLINK << 8) | IPCTNL_MSG_CT_GET).to_ne_bytes());
+    msg[6..8].copy_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+    msg[8..12].copy_from_slice(&seq.to_ne_bytes());
+    msg
+}
+
+/// Attributes of a netlink attribute stream, as (type, payload)
+fn attrs(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = u16::from_ne_bytes(data.get(0..2)?.try_into().ok()?) as usize;
+        let attr_type = u16::from_ne_bytes(data.get(2..4)?.try_into().ok()?);
+        let payload = data.get(4..len)?;
+        data = data.get(len.next_multiple_of(4)..).unwrap_or(&[]);
+        Some((attr_type & NLA_TYPE_MASK, payload))
+    })
+}
+
+/// Tuple of a CTA_TUPLE_ORIG or CTA_TUPLE_REPLY attribute
+fn parse_tuple(data: &[u8]) -> Option<ConnectionKey> {
+    let mut key = ConnectionKey { saddr: 0, daddr: 0, sport: 0, dport: 0 };
+    for (attr_type, payload) in attrs(data) {
+        for (field, value) in attrs(payload) {
+            match (attr_type, field) {
+                (CTA_TUPLE_IP, CTA_IP_V4_SRC) => key.saddr = u32::from_ne_bytes(value.try_into().ok()?),
+                (CTA_TUPLE_IP, CTA_IP_V4_DST) => key.daddr = u32::from_ne_bytes(value.try_into().ok()?),
+                (CTA_TUPLE_PROTO, CTA_PROTO_SRC_PORT) => key.sport = u16::from_ne_bytes(value.try_into().ok()?),
+                (CTA_TUPLE_PROTO, CTA_PROTO_DST_PORT) => key.dport = u16::from_ne_bytes(value.try_into().ok()?),
+                _ => {}
+            }
+        }
+    }
+    Some(key)
+}
+
+/// Original and reply tuples of a conntrack answer
+///
+/// # Returns
+///
+/// The tuples, or None if there is no such entry
+fn parse_answer(msg: &[u8]) -> Result<Option<(ConnectionKey, ConnectionKey)>> {
+    let header = msg.get(..NLMSG_HEADER).context("Truncated netlink message")?;
+    let msg_type = u16::from_ne_bytes([header[4], header[5]]);
+    if msg_type == NLMSG_ERROR {
+        let errno = i32::from_ne_bytes(msg.get(16..20).context("Truncated netlink error")?.try_into()?);
+        return match -errno {
+            libc::ENOENT => Ok(None),
+            errno => Err(std::io::Error::from_raw_os_error(errno)).context("Conntrack lookup failed"),
+        };
+    }
+
+    let (mut orig, mut reply) = (None, None);
+    for (attr_type, payload) in attrs(msg.get(NLMSG_HEADER + NFGENMSG..).unwrap_or(&[])) {
+        match attr_type {
+            CTA_TUPLE_ORIG => orig = parse_tuple(payload),
+            CTA_TUPLE_REPLY => reply = parse_tuple(payload),
+            _ => {}
+        }
+    }
+    Ok(orig.zip(reply))
+}
+
+/// Real destination of a connection from its conntrack tuples
+///
+/// # Returns
+///
+/// The (address, port) the reply comes from, in network byte order, if
+/// the connection's destination was translated
+fn nat_destination(key: &ConnectionKey, orig: &ConnectionKey, reply: &ConnectionKey) -> Option<(u32, u16)> {
+    // An entry whose reply direction matched is the server side, which
+    // already sees the real addresses
+    (orig == key && (reply.saddr, reply.sport) != (key.daddr, key.dport)).then_some((reply.saddr, reply.sport))
+}
+
+/// Netlink socket querying conntrack
+struct Conntrack {
+    fd: OwnedFd,
+    seq: u32,
+    buf: Vec<u8>,
+}
+
+impl Conntrack {
+    /// Open a conntrack netlink socket
+    fn open() -> Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_NETFILTER) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to open a conntrack netlink socket");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: RECEIVE_TIMEOUT.as_micros() as libc::suseconds_t,
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set the conntrack socket timeout");
+        }
+
+        Ok(Self {
+            fd,
+            seq: 0,
+            buf: vec![0; 8192],
+        })
+    }
+
+    /// Look up the conntrack entry of a TCP connection
+    fn lookup(&mut self, key: &ConnectionKey) -> Result<Option<(ConnectionKey, ConnectionKey)>> {
+        self.seq = self.seq.wrapping_add(1);
+        let request = encode_get(key, self.seq);
+        let mut kernel: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        kernel.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                request.as_ptr() as *const libc::c_void,
+                request.len(),
+                0,
+                &kernel as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to send a conntrack request");
+        }
+
+        // Skip answers to earlier requests that timed out
+        loop {
+            let received = unsafe {
+                libc::recv(self.fd.as_raw_fd(), self.buf.as_mut_ptr() as *mut libc::c_void, self.buf.len(), 0)
+            };
+            if received < 0 {
+                return Err(std::io::Error::last_os_error()).context("Failed to receive a conntrack answer");
+            }
+            let answer = &self.buf[..received as usize];
+            if answer.get(8..12).map(|seq| u32::from_ne_bytes(seq.try_into().unwrap())) == Some(self.seq) {
+                return parse_answer(answer);
+            }
+        }
+    }
+}
+
+/// Cached entry of [`ConntrackCache`]
+struct CacheEntry {
+    destination: Option<(u32, u16)>,
+    expires: Instant,
+}
+
+/// Tuple to real destination cache backed by conntrack
+pub struct ConntrackCache {
+    conntrack: Conntrack,
+    ttl: Duration,
+    entries: HashMap<ConnectionKey, CacheEntry>,
+    failures: u64,
+}
+
+impl ConntrackCache {
+    /// Open a cache on the probe's network namespace
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a lookup is trusted before conntrack is asked
+    ///   again
+    pub fn open(ttl: Duration) -> Result<Self> {
+        Ok(Self {
+            conntrack: Conntrack::open()?,
+            ttl,
+            entries: HashMap::new(),
+            failures: 0,
+        })
+    }
+
+    /// Real destination of a connection, if it was translated
+    ///
+    /// # Returns
+    ///
+    /// The pod's (address, port) in network byte order, or None if the
+    /// destination was not translated or conntrack has no entry
+    pub fn destination(&mut self, key: &ConnectionKey) -> Option<(u32, u16)> {
+        let now = Instant::now();
+        if let Some(entry) = self.entries.get(key).filter(|entry| entry.expires > now) {
+            return entry.destination;
+        }
+
+        let destination = match self.conntrack.lookup(key) {
+            Ok(tuples) => tuples.and_then(|(orig, reply)| nat_destination(key, &orig, &reply)),
+            Err(e) => {
+                self.failures += 1;
+                if self.failures == 1 {
+                    log::warn!("⚠ Conntrack lookup failed, destinations may be NATed: {:#}", e);
+                }
+                None
+            }
+        };
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.retain(|_, entry| entry.expires > now);
+        }
+        self.entries.insert(
+            *key,
+            CacheEntry {
+                destination,
+                expires: now + self.ttl,
+            },
+        );
+        destination
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuple(saddr: [u8; 4], sport: u16, daddr: [u8; 4], dport: u16) -> ConnectionKey {
+        ConnectionKey {
+            saddr: u32::from_ne_bytes(saddr),
+            daddr: u32::from_ne_bytes(daddr),
+            sport: sport.to_be(),
+            dport: dport.to_be(),
+        }
+    }
+
+    /// Answer of the kernel, laid out like a request for the original tuple
+    /// followed by the reply tuple
+    fn answer(orig: &ConnectionKey, reply: &ConnectionKey) -> Vec<u8> {
+        let mut msg = encode_get(orig, 7);
+        let reply_request = encode_get(reply, 7);
+        let reply_tuple = &reply_request[NLMSG_HEADER + NFGENMSG + 4..];
+        put_nested(&mut msg, CTA_TUPLE_REPLY, reply_tuple);
+        msg
+    }
+
+    #[test]
+    fn test_conntrack_messages() {
+        // A pod connects to a ClusterIP, DNATed to a pod on another node
+        let client = tuple([10, 244, 1, 5], 41000, [10, 96, 0, 10], 80);
+        let reply = tuple([10, 244, 2, 7], 8080, [10, 244, 1, 5], 41000);
+
+        let request = encode_get(&client, 7);
+        assert_eq!(request.len() % 4, 0);
+        assert_eq!(u32::from_ne_bytes(request[0..4].try_into().unwrap()) as usize, request.len());
+        assert_eq!(u16::from_ne_bytes(request[4..6].try_into().unwrap()), 0x101);
+
+        let (orig, parsed_reply) = parse_answer(&answer(&client, &reply)).unwrap().unwrap();
+        assert_eq!((orig, parsed_reply), (client, reply));
+        assert_eq!(
+            nat_destination(&client, &orig, &parsed_reply),
+            Some((u32::from_ne_bytes([10, 244, 2, 7]), 8080u16.to_be()))
+        );
+
+        // Not translated, or seen from the server side
+        let direct = tuple([10, 96, 0, 10], 80, [10, 244, 1, 5], 41000);
+        assert_eq!(nat_destination(&client, &client, &direct), None);
+        assert_eq!(nat_destination(&reply, &client, &reply), None);
+
+        // No entry
+        let mut error = vec![0; NLMSG_HEADER + 4];
+        error[4..6].copy_from_slice(&NLMSG_ERROR.to_ne_bytes());
+        error[16..20].copy_from_slice(&(-libc::ENOENT).to_ne_bytes());
+        assert_eq!(parse_answer(&error).unwrap(), None);
+        error[16..20].copy_from_slice(&(-libc::EPERM).to_ne_bytes());
+        assert!(parse_answer(&error).is_err());
+    }
+}
@@ -0,0 +1,557 @@
+//! Controller mode
+//!
+//! `latency-probe controller` makes benchmark runs declarative. Deployed as
+//! a DaemonSet, it watches the LatencyProbeRun custom resources of its
+//! namespace (see workloads/kubernetes/latencyproberun-crd.yaml) and runs
+//! the probe for each of them on the nodes they select, with the options
+//! of their spec:
+//!
+//! ```yaml
+//! apiVersion: probes.service-mesh-benchmark.io/v1alpha1
+//! kind: LatencyProbeRun
+//! metadata:
+//!   name: istio-baseline
+//! spec:
+//!   duration: 300
+//!   filterServices: ["10.96.0.15:8080"]
+//!   exporters:
+//!     textfile-dir: /var/lib/node_exporter/textfile
+//! ```
+//!
+//! Each node writes its summary back to the resource, under
+//! `status.nodes.<node>`: the phase, run ID, event counts and percentiles.
+//! The nodes of a run share its run ID (the resource's name), so their
+//! reports can be merged afterwards.
+//!
+//! A node collects one run at a time, oldest first, and each run once: a
+//! run it has a status for is never repeated. Deleting a resource stops
+//! its collection, as Ctrl-C would. A run left `Running` by a controller
+//! that restarted is marked `Failed`.
+
+use crate::{kubernetes::KubeClient, run::RunId, types::LatencyMetrics};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+use tokio::process::{Child, Command};
+
+/// API group of LatencyProbeRun
+pub const GROUP: &str = "probes.service-mesh-benchmark.io";
+
+/// API version of LatencyProbeRun
+pub const VERSION: &str = "v1alpha1";
+
+/// Plural resource name of LatencyProbeRun
+pub const PLURAL: &str = "latencyproberuns";
+
+/// Probe options a spec may set under `exporters`
+pub const EXPORTER_OPTIONS: [&str; 12] = [
+    "textfile-dir",
+    "zabbix-server",
+    "zabbix-host",
+    "nats-url",
+    "nats-subject",
+    "nats-snapshot-subject",
+    "webhook-url",
+    "webhook-format",
+    "upload",
+    "upload-endpoint",
+    "upload-region",
+    "registry",
+];
+
+/// Spec of a LatencyProbeRun
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RunSpec {
+    /// Seconds to collect
+    pub duration: u64,
+    /// Nodes to collect on (every node if empty)
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    /// Services to track (`IP:PORT`, `*:PORT` or `IP:*`)
+    #[serde(default)]
+    pub filter_services: Vec<String>,
+    /// Only track the pods of this namespace
+    #[serde(default)]
+    pub pod_namespace: Option<String>,
+    /// Label selector narrowing `podNamespace`, e.g. `app=frontend`
+    #[serde(default)]
+    pub pod_selector: Option<String>,
+    /// Sampling, as `--sample-rate` (e.g. `100`)
+    #[serde(default)]
+    pub sample_rate: Option<String>,
+    /// Exporter options (see [`EXPORTER_OPTIONS`]) without their `--`
+    #[serde(default)]
+    pub exporters: BTreeMap<String, String>,
+    /// Further probe options, e.g. `["--tail-analysis"]`
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl RunSpec {
+    /// Whether a node collects this run
+    pub fn selects(&self, node: &str) -> bool {
+        self.nodes.is_empty() || self.nodes.iter().any(|selected| selected == node)
+    }
+
+    /// Arguments of the probe collecting this run
+    ///
+    /// # Arguments
+    ///
+    /// * `run_id` - ID of the run
+    /// * `output` - File the report is written to
+    pub fn probe_args(&self, run_id: &RunId, output: &Path) -> Result<Vec<String>> {
+        if self.duration == 0 {
+            anyhow::bail!("duration must be at least 1 second");
+        }
+        let mut args = vec![
+            "--duration".to_string(),
+            self.duration.to_string(),
+            "--output".to_string(),
+            output.display().to_string(),
+            "--format".to_string(),
+            "json".to_string(),
+            "--run-id".to_string(),
+            run_id.as_str().to_string(),
+        ];
+        for service in &self.filter_services {
+            args.extend(["--filter-service".to_string(), service.clone()]);
+        }
+        if let Some(namespace) = &self.pod_namespace {
+            args.extend(["--k8s-namespace".to_string(), namespace.clone()]);
+        }
+        if let Some(selector) = &self.pod_selector {
+            args.extend(["--k8s-selector".to_string(), selector.clone()]);
+        }
+        if let Some(rate) = &self.sample_rate {
+            args.extend(["--sample-rate".to_string(), rate.clone()]);
+        }
+        for (option, value) in &self.exporters {
+            if !EXPORTER_OPTIONS.contains(&option.as_str()) {
+                anyhow::bail!("Unknown exporter option '{}' (one of {})", option, EXPORTER_OPTIONS.join(", "));
+            }
+            args.extend([format!("--{}", option), value.clone()]);
+        }
+        args.extend(self.args.iter().cloned());
+        Ok(args)
+    }
+}
+
+/// Phase of a run on one node
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunPhase {
+    /// Collecting
+    Running,
+    /// Finished with a report
+    Succeeded,
+    /// Could not start, or failed
+    Failed,
+}
+
+/// Status of a run on one node
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStatus {
+    /// Phase of the run
+    pub phase: RunPhase,
+    /// ID of the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// When collection started (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    /// When collection finished (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Events captured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_events: Option<u64>,
+    /// Events lost in the ring buffer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lost_events: Option<u64>,
+    /// Median latency in microseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p50_us: Option<f64>,
+    /// 99th percentile latency in microseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p99_us: Option<f64>,
+    /// Report file on the node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    /// Why the run failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl NodeStatus {
+    /// Status of a run being collected
+    pub fn running(run_id: &RunId, started_at: &str) -> Self {
+        Self {
+            phase: RunPhase::Running,
+            run_id: Some(run_id.as_str().to_string()),
+            started_at: Some(started_at.to_string()),
+            finished_at: None,
+            total_events: None,
+            lost_events: None,
+            p50_us: None,
+            p99_us: None,
+            report: None,
+            message: None,
+        }
+    }
+
+    /// Status of a run that failed
+    pub fn failed(message: impl std::fmt::Display) -> Self {
+        Self {
+            phase: RunPhase::Failed,
+            run_id: None,
+            started_at: None,
+            finished_at: Some(chrono::Utc::now().to_rfc3339()),
+            total_events: None,
+            lost_events: None,
+            p50_us: None,
+            p99_us: None,
+            report: None,
+            message: Some(message.to_string()),
+        }
+    }
+
+    /// Status of a run that finished with a report
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Final report
+    /// * `report` - Where it was written
+    pub fn succeeded(metrics: &LatencyMetrics, report: &Path) -> Self {
+        Self {
+            phase: RunPhase::Succeeded,
+            run_id: metrics.run_id.clone(),
+            started_at: None,
+            finished_at: Some(metrics.timestamp.clone()),
+            total_events: Some(metrics.total_events),
+            lost_events: Some(metrics.lost_events),
+            p50_us: Some(metrics.percentiles.p50),
+            p99_us: Some(metrics.percentiles.p99),
+            report: Some(report.display().to_string()),
+            message: None,
+        }
+    }
+}
+
+/// LatencyProbeRun, as far as the controller uses it
+#[derive(Deserialize)]
+struct ProbeRun {
+    metadata: ObjectMeta,
+    #[serde(default)]
+    spec: serde_json::Value,
+    #[serde(default)]
+    status: RunStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectMeta {
+    name: String,
+    uid: String,
+    #[serde(default)]
+    creation_timestamp: String,
+}
+
+#[derive(Deserialize, Default)]
+struct RunStatus {
+    #[serde(default)]
+    nodes: BTreeMap<String, NodeStatus>,
+}
+
+#[derive(Deserialize)]
+struct ProbeRunList {
+    #[serde(default)]
+    items: Vec<ProbeRun>,
+}
+
+/// Collection in progress on this node
+struct Active {
+    name: String,
+    uid: String,
+    child: Child,
+    output: PathBuf,
+    started_at: String,
+}
+
+/// Controller of the LatencyProbeRuns of one node
+pub struct Controller {
+    client: KubeClient,
+    namespace: String,
+    node: String,
+    work_dir: PathBuf,
+    poll_interval: Duration,
+}
+
+impl Controller {
+    /// Create a controller
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - API client (the service account needs `list` on
+    ///   `latencyproberuns` and `patch` on `latencyproberuns/status`)
+    /// * `namespace` - Namespace of the watched resources
+    /// * `node` - Name of this node, as in a spec's `nodes`
+    /// * `work_dir` - Directory the reports are written to
+    /// * `poll_interval` - Time between lists of the resources
+    pub fn new(client: KubeClient, namespace: &str, node: &str, work_dir: &Path, poll_interval: Duration) -> Self {
+        Self {
+            client,
+            namespace: namespace.to_string(),
+            node: node.to_string(),
+            work_dir: work_dir.to_path_buf(),
+            poll_interval: poll_interval.max(Duration::from_secs(1)),
+        }
+    }
+
+    /// Watch the resources and collect their runs until the process ends
+    pub async fn run(self) -> Result<()> {
+        std::fs::create_dir_all(&self.work_dir)
+            .with_context(|| format!("Failed to create work directory: {:?}", self.work_dir))?;
+        info!("Watching {}.{} in {} on node {}", PLURAL, GROUP, self.namespace, self.node);
+
+        let mut active: Option<Active> = None;
+        let mut ticker = tokio::time::interval(self.poll_interval);
+        loop {
+            ticker.tick().await;
+            let runs = match self.list().await {
+                Ok(runs) => runs,
+                Err(e) => {
+                    warn!("{:#}", e);
+                    continue;
+                }
+            };
+
+            if let Some(current) = &mut active {
+                if !runs.iter().any(|run| run.metadata.uid == current.uid) {
+                    info!("LatencyProbeRun {} deleted, stopping its collection", current.name);
+                    if let Some(stopped) = active.take() {
+                        stop(stopped);
+                    }
+                } else {
+                    match current.child.try_wait() {
+                        Ok(Some(exit)) => {
+                            let status = finished(current, exit);
+                            info!("LatencyProbeRun {} {:?} on {}", current.name, status.phase, self.node);
+                            self.patch_status(&current.name, &status).await;
+                            active = None;
+                        }
+                        Ok(None) => continue,
+                        Err(e) => {
+                            warn!("Failed to check the probe of {}: {}", current.name, e);
+                            continue;
+                        }
+                    }
+                }
+            }
+            if active.is_some() {
+                continue;
+            }
+
+            // Nothing runs here, so a run still marked running lost its probe
+            for run in &runs {
+                if run.status.nodes.get(&self.node).is_some_and(|status| status.phase == RunPhase::Running) {
+                    let status = NodeStatus::failed("The controller restarted during the run");
+                    self.patch_status(&run.metadata.name, &status).await;
+                }
+            }
+
+            let mut pending: Vec<&ProbeRun> = runs
+                .iter()
+                .filter(|run| !run.status.nodes.contains_key(&self.node))
+                .collect();
+            pending.sort_by(|a, b| a.metadata.creation_timestamp.cmp(&b.metadata.creation_timestamp));
+            for run in pending {
+                let spec = match serde_json::from_value::<RunSpec>(run.spec.clone()) {
+                    Ok(spec) => spec,
+                    Err(e) => {
+                        self.patch_status(&run.metadata.name, &NodeStatus::failed(format!("Invalid spec: {}", e)))
+                            .await;
+                        continue;
+                    }
+                };
+                if !spec.selects(&self.node) {
+                    continue;
+                }
+                match self.start(run, &spec) {
+                    Ok(started) => {
+                        let status = NodeStatus::running(&run_id(run), &started.started_at);
+                        self.patch_status(&run.metadata.name, &status).await;
+                        active = Some(started);
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("LatencyProbeRun {} failed to start: {:#}", run.metadata.name, e);
+                        self.patch_status(&run.metadata.name, &NodeStatus::failed(format!("{:#}", e))).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start the probe of a run
+    fn start(&self, run: &ProbeRun, spec: &RunSpec) -> Result<Active> {
+        let run_id = run_id(run);
+        let output = self.work_dir.join(format!("{}.json", run.metadata.name));
+        let args = spec.probe_args(&run_id, &output)?;
+        let probe = std::env::current_exe().context("Failed to locate the probe binary")?;
+
+        info!("Starting LatencyProbeRun {} on {}: {:?}", run.metadata.name, self.node, args);
+        let child = Command::new(probe)
+            .args(&args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start the probe")?;
+        Ok(Active {
+            name: run.metadata.name.clone(),
+            uid: run.metadata.uid.clone(),
+            child,
+            output,
+            started_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// List the resources of the namespace
+    async fn list(&self) -> Result<Vec<ProbeRun>> {
+        let path = format!("/apis/{}/{}/namespaces/{}/{}", GROUP, VERSION, self.namespace, PLURAL);
+        let list: ProbeRunList = self
+            .client
+            .request(Method::GET, &path)?
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to list {} in {}", PLURAL, self.namespace))?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse the {} of {}", PLURAL, self.namespace))?;
+        Ok(list.items)
+    }
+
+    /// Write the status of this node into a resource (failures are logged)
+    ///
+    /// A merge patch only replaces this node's entry, so the nodes of a
+    /// run do not overwrite each other.
+    async fn patch_status(&self, name: &str, status: &NodeStatus) {
+        let path = format!("/apis/{}/{}/namespaces/{}/{}/{}/status", GROUP, VERSION, self.namespace, PLURAL, name);
+        let patch = serde_json::json!({ "status": { "nodes": { &self.node: status } } });
+        let result = match self.client.request(Method::PATCH, &path) {
+            Ok(request) => request
+                .header(reqwest::header::CONTENT_TYPE, "application/merge-patch+json")
+                .body(patch.to_string())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map(|_| ())
+                .map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to update the status of LatencyProbeRun {}: {:#}", name, e);
+        }
+    }
+}
+
+/// Stop a probe as Ctrl-C would, letting it detach and write its report
+fn stop(mut active: Active) {
+    if let Some(pid) = active.child.id() {
+        // SAFETY: signals the probe's own child process, which has not been reaped
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+    }
+    tokio::spawn(async move {
+        if let Err(e) = active.child.wait().await {
+            warn!("Failed to stop the probe of {}: {}", active.name, e);
+        }
+    });
+}
+
+/// ID of a run: the resource's name, or its UID if the name is no valid ID
+fn run_id(run: &ProbeRun) -> RunId {
+    run.metadata
+        .name
+        .parse()
+        .or_else(|_| run.metadata.uid.parse())
+        .unwrap_or_else(|_| RunId::generate())
+}
+
+/// Status of a run whose probe exited
+fn finished(active: &Active, exit: ExitStatus) -> NodeStatus {
+    if !exit.success() {
+        return NodeStatus {
+            started_at: Some(active.started_at.clone()),
+            ..NodeStatus::failed(format!("The probe exited with {}", exit))
+        };
+    }
+    let report = std::fs::read(&active.output)
+        .with_context(|| format!("Failed to read the report: {:?}", active.output))
+        .and_then(|json| serde_json::from_slice::<LatencyMetrics>(&json).context("Invalid report"));
+    match report {
+        Ok(metrics) => NodeStatus {
+            started_at: Some(active.started_at.clone()),
+            ..NodeStatus::succeeded(&metrics, &active.output)
+        },
+        Err(e) => NodeStatus {
+            started_at: Some(active.started_at.clone()),
+            ..NodeStatus::failed(format!("{:#}", e))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_spec() {
+        let spec: RunSpec = serde_json::from_value(serde_json::json!({
+            "duration": 300,
+            "nodes": ["node-a"],
+            "filterServices": ["10.96.0.15:8080"],
+            "podNamespace": "shop",
+            "podSelector": "app=frontend",
+            "exporters": {"textfile-dir": "/var/lib/node_exporter"},
+            "args": ["--tail-analysis"],
+        }))
+        .unwrap();
+        assert!(spec.selects("node-a") && !spec.selects("node-b"));
+
+        let run_id: RunId = "istio-baseline".parse().unwrap();
+        let args = spec.probe_args(&run_id, Path::new("/var/lib/runs/istio-baseline.json")).unwrap();
+        assert_eq!(
+            args.join(" "),
+            "--duration 300 --output /var/lib/runs/istio-baseline.json --format json --run-id istio-baseline \
+             --filter-service 10.96.0.15:8080 --k8s-namespace shop --k8s-selector app=frontend \
+             --textfile-dir /var/lib/node_exporter --tail-analysis"
+        );
+
+        // Specs are validated before anything runs
+        let spec = |value: serde_json::Value| serde_json::from_value::<RunSpec>(value);
+        assert!(spec(serde_json::json!({"duration": 60, "durration": 60})).is_err());
+        let unknown = spec(serde_json::json!({"duration": 60, "exporters": {"output": "/etc/passwd"}})).unwrap();
+        assert!(unknown.probe_args(&run_id, Path::new("out.json")).is_err());
+        let zero = spec(serde_json::json!({"duration": 0})).unwrap();
+        assert!(zero.probe_args(&run_id, Path::new("out.json")).is_err());
+        assert!(zero.selects("any-node"));
+
+        let metrics = LatencyMetrics {
+            run_id: Some("istio-baseline".to_string()),
+            total_events: 42,
+            ..Default::default()
+        };
+        let status = serde_json::to_value(NodeStatus::succeeded(&metrics, Path::new("out.json"))).unwrap();
+        assert_eq!(status["phase"], "Succeeded");
+        assert_eq!(status["totalEvents"], 42);
+        assert!(status.get("message").is_none());
+    }
+}
@@ -0,0 +1,319 @@
+//! Per-event correlation records for cross-node stitching (experimental)
+//!
+//! A request between pods on two nodes is seen twice: by the probe on the
+//! client's node, as a send and a receive on the client socket, and by the
+//! probe on the server's node on the server socket. Neither report alone
+//! gives the hop latency between them.
+//!
+//! With `--correlation`, every sampled event is written to a JSON Lines
+//! file with the correlation ID of its connection: the node ID and the
+//! socket cookie the eBPF programs read (`bpf_get_socket_cookie`), which
+//! together name a socket uniquely across the cluster. Each record also
+//! carries the flow, oriented from client to server, so both ends of a
+//! connection share it, and the event's wall clock time, so the records of
+//! several nodes can be put on one time line:
+//!
+//! ```text
+//! {"correlation_id":"node-a/1f3","flow":"10.0.1.5:41000 -> 10.0.2.7:8080","side":"client","wall_time_ns":...}
+//! {"correlation_id":"node-b/9c2","flow":"10.0.1.5:41000 -> 10.0.2.7:8080","side":"server","wall_time_ns":...}
+//! ```
+//!
+//! The server is the end with the lower port (clients use ephemeral
+//! ports). The socket sees NATed connections with the ClusterIP, so flows
+//! through kube-proxy only match when the client records the pod address
+//! (see crate::conntrack). Wall clock times are as close as the nodes'
+//! clocks are synchronized; the first line of the file records the clock
+//! and, if the time daemon answered, its offset (see crate::timesync).
+
+use crate::{
+    compress::{Compression, OutputWriter},
+    run::RunId,
+    types::{ClockInfo, LatencyEvent},
+};
+use anyhow::{Context, Result};
+use probe_common::constants::EVENT_TYPE_CLEANUP;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::SocketAddrV4,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// Maximum number of connections whose correlation is kept
+const MAX_CONNECTIONS: usize = 65536;
+
+/// End of the connection an event was seen on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    /// The socket that connected
+    Client,
+    /// The socket that accepted
+    Server,
+}
+
+/// One event with the correlation of its connection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CorrelationRecord {
+    /// Node ID and socket cookie, e.g. "node-a/1f3" (the 4-tuple in place
+    /// of the cookie if the socket has none)
+    pub correlation_id: String,
+    /// Node the event was seen on
+    pub node: String,
+    /// Flow from client to server, e.g. "10.0.1.5:41000 -> 10.0.2.7:8080"
+    pub flow: String,
+    /// End of the flow the event was seen on
+    pub side: Side,
+    /// Type of event (see EVENT_TYPE_* constants)
+    pub event_type: u8,
+    /// Kernel timestamp of the event (nanoseconds)
+    pub timestamp_ns: u64,
+    /// Wall clock time of the event (nanoseconds since the Unix epoch)
+    pub wall_time_ns: i64,
+    /// Measured latency (nanoseconds)
+    pub latency_ns: u64,
+    /// Process ID that triggered the event
+    pub pid: u32,
+    /// ID of the run the event was recorded in (None if unknown)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+/// First line of a correlation file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CorrelationMetadata {
+    /// Node the file was written on
+    pub node: String,
+    /// Clock of the node's events when the file was created
+    pub clock: ClockInfo,
+}
+
+/// Line of a correlation file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CorrelationLine {
+    /// An event
+    Record(CorrelationRecord),
+    /// The file's metadata
+    Metadata(CorrelationMetadata),
+}
+
+/// Correlation of a connection, computed at its first event
+#[derive(Clone)]
+struct Correlation {
+    id: Arc<str>,
+    flow: Arc<str>,
+    side: Side,
+}
+
+impl Correlation {
+    fn new(node: &str, event: &LatencyEvent) -> Self {
+        let local = SocketAddrV4::new(u32::from_be(event.key.saddr).into(), u16::from_be(event.key.sport));
+        let remote = SocketAddrV4::new(u32::from_be(event.key.daddr).into(), u16::from_be(event.key.dport));
+        let side = if (local.port(), local.ip()) < (remote.port(), remote.ip()) {
+            Side::Server
+        } else {
+            Side::Client
+        };
+        let flow = match side {
+            Side::Client => format!("{} -> {}", local, remote),
+            Side::Server => format!("{} -> {}", remote, local),
+        };
+        let id = match event.cookie {
+            0 => format!("{}/{}-{}", node, local, remote),
+            cookie => format!("{}/{:x}", node, cookie),
+        };
+        Self {
+            id: id.into(),
+            flow: flow.into(),
+            side,
+        }
+    }
+}
+
+/// Socket cookie and 4-tuple of a connection
+type SocketId = (u64, u32, u32, u16, u16);
+
+/// Writes correlation records to a JSON Lines file
+///
+/// Safe to share between reader tasks; register
+/// [`record`](Self::record) as an event callback.
+pub struct CorrelationWriter {
+    node: String,
+    clock: ClockInfo,
+    run_id: Option<RunId>,
+    /// Correlations of the open connections
+    connections: Mutex<HashMap<SocketId, Correlation>>,
+    writer: Mutex<OutputWriter>,
+}
+
+impl CorrelationWriter {
+    /// Create the file, truncating any existing one, and write its
+    /// metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the JSON Lines output file
+    /// * `compression` - Compression to apply
+    /// * `node` - ID of this node, unique in the cluster
+    /// * `clock` - Clock of event timestamps (see crate::clock)
+    pub fn create(path: &Path, compression: Compression, node: &str, clock: ClockInfo) -> Result<Self> {
+        let mut writer = OutputWriter::create(path, compression)
+            .with_context(|| format!("Failed to create correlation file: {:?}", path))?;
+        let metadata = CorrelationMetadata {
+            node: node.to_string(),
+            clock: clock.clone(),
+        };
+        serde_json::to_writer(&mut writer, &metadata)?;
+        writer.write_all(b"\n")?;
+
+        Ok(Self {
+            node: node.to_string(),
+            clock,
+            run_id: None,
+            connections: Mutex::new(HashMap::new()),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Stamp records with the ID of the run
+    pub fn with_run_id(mut self, run_id: RunId) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Correlation record of an event
+    ///
+    /// The correlation of a connection is kept until its cleanup event.
+    pub fn correlate(&self, event: &LatencyEvent) -> Result<CorrelationRecord> {
+        let mut connections = self
+            .connections
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Correlation lock poisoned"))?;
+
+        let id: SocketId = (event.cookie, event.key.saddr, event.key.daddr, event.key.sport, event.key.dport);
+        let correlation = match connections.get(&id) {
+            Some(correlation) => correlation.clone(),
+            None => {
+                let correlation = Correlation::new(&self.node, event);
+                if connections.len() >= MAX_CONNECTIONS {
+                    // Connections whose cleanup was not sampled
+                    connections.clear();
+                }
+                connections.insert(id, correlation.clone());
+                correlation
+            }
+        };
+        if event.event_type == EVENT_TYPE_CLEANUP {
+            connections.remove(&id);
+        }
+
+        Ok(CorrelationRecord {
+            correlation_id: correlation.id.to_string(),
+            node: self.node.clone(),
+            flow: correlation.flow.to_string(),
+            side: correlation.side,
+            event_type: event.event_type,
+            timestamp_ns: event.timestamp_ns,
+            wall_time_ns: self.clock.to_wall_ns(event.timestamp_ns),
+            latency_ns: event.latency_ns,
+            pid: event.pid,
+            run_id: self.run_id.as_ref().map(RunId::to_string),
+        })
+    }
+
+    /// Append the correlation record of one event
+    pub fn record(&self, event: &LatencyEvent) -> Result<()> {
+        let record = self.correlate(event)?;
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Correlation lock poisoned"))?;
+
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    /// Flush buffered records and end a compressed stream
+    ///
+    /// Records written afterwards do not reach the file.
+    pub fn finish(&self) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Correlation lock poisoned"))?;
+
+        writer.finish().context("Failed to finish correlation file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+    use probe_common::constants::{EVENT_TYPE_RECV, EVENT_TYPE_SEND};
+
+    #[test]
+    fn test_correlation_records() {
+        let event = |local: ([u8; 4], u16), remote: ([u8; 4], u16), cookie, event_type| LatencyEvent {
+            cookie,
+            timestamp_ns: 1_000,
+            pid: 7,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: u32::from_ne_bytes(local.0),
+                    daddr: u32::from_ne_bytes(remote.0),
+                    sport: local.1.to_be(),
+                    dport: remote.1.to_be(),
+                },
+                250_000,
+                event_type,
+            )
+        };
+        let client = ([10, 0, 1, 5], 41000);
+        let server = ([10, 0, 2, 7], 8080);
+        let dir = tempfile::tempdir().unwrap();
+
+        // Both ends of a connection share the flow, oriented client to server
+        let clock = |wall_offset_ns| ClockInfo {
+            wall_offset_ns,
+            ..Default::default()
+        };
+        let node_a = CorrelationWriter::create(&dir.path().join("a.jsonl"), Compression::None, "node-a", clock(500)).unwrap();
+        let node_b = CorrelationWriter::create(&dir.path().join("b.jsonl"), Compression::None, "node-b", clock(-200)).unwrap();
+        let sent = node_a.correlate(&event(client, server, 0x1f3, EVENT_TYPE_SEND)).unwrap();
+        let received = node_b.correlate(&event(server, client, 0x9c2, EVENT_TYPE_RECV)).unwrap();
+        assert_eq!((sent.correlation_id.as_str(), sent.side), ("node-a/1f3", Side::Client));
+        assert_eq!((received.correlation_id.as_str(), received.side), ("node-b/9c2", Side::Server));
+        assert_eq!(sent.flow, "10.0.1.5:41000 -> 10.0.2.7:8080");
+        assert_eq!(received.flow, sent.flow);
+        assert_eq!((sent.wall_time_ns, received.wall_time_ns), (1_500, 800));
+
+        // Sockets without a cookie are named by their tuple
+        let untagged = node_a.correlate(&event(client, server, 0, EVENT_TYPE_SEND)).unwrap();
+        assert_eq!(untagged.correlation_id, "node-a/10.0.1.5:41000-10.0.2.7:8080");
+
+        // A connection's correlation is forgotten at its cleanup
+        assert_eq!(node_a.connections.lock().unwrap().len(), 2);
+        node_a.correlate(&event(client, server, 0x1f3, EVENT_TYPE_CLEANUP)).unwrap();
+        assert_eq!(node_a.connections.lock().unwrap().len(), 1);
+
+        node_a.record(&event(client, server, 0x1f3, EVENT_TYPE_RECV)).unwrap();
+        node_a.finish().unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("a.jsonl")).unwrap();
+        let lines: Vec<CorrelationLine> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let CorrelationLine::Metadata(metadata) = &lines[0] else {
+            panic!("No metadata: {:?}", lines[0]);
+        };
+        assert_eq!((metadata.node.as_str(), metadata.clock.wall_offset_ns), ("node-a", 500));
+        let CorrelationLine::Record(record) = &lines[1] else {
+            panic!("No record: {:?}", lines[1]);
+        };
+        assert_eq!((record.correlation_id.as_str(), record.node.as_str()), ("node-a/1f3", "node-a"));
+    }
+}
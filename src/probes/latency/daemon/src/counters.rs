@@ -0,0 +1,292 @@
+//! Lock-free histogram, event-type and discard counters
+//!
+//! The `/metrics` endpoint (see crate::api) is scraped while events are
+//! collected. Locking the collector for a scrape would stall ingestion, so
+//! the latency histogram, the event-type counts and the counts of events
+//! the probe discarded live outside the lock, in atomic counters. Events
+//! reach the collector through a single aggregator task (see
+//! crate::backpressure), so one set of counters serves every writer.
+//!
+//! [`LiveCounters`] count from the start of the run, across rotations, as
+//! Prometheus expects of counters. The collector reports an
+//! [`IntervalCounters`] view of them, which subtracts the counts at the
+//! start of its interval.
+
+use crate::types::{EventTypeBreakdown, InternalCounters, LatencyHistogram, HISTOGRAM_BOUNDS_US};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Histogram bucket and event-type counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counts {
+    /// Samples per histogram bucket, lowest bucket first
+    pub buckets: [u64; 6],
+    /// Events per type (see [`EventTypeBreakdown::NAMES`])
+    pub event_types: [u64; 5],
+}
+
+impl Counts {
+    /// Histogram of the counts
+    pub fn histogram(&self) -> LatencyHistogram {
+        LatencyHistogram::from_counts(self.buckets)
+    }
+
+    /// Event-type breakdown of the counts
+    pub fn event_types(&self) -> EventTypeBreakdown {
+        EventTypeBreakdown::from_counts(self.event_types)
+    }
+
+    /// Counts added since an earlier reading
+    fn since(&self, earlier: &Counts) -> Counts {
+        Counts {
+            buckets: std::array::from_fn(|i| self.buckets[i].saturating_sub(earlier.buckets[i])),
+            event_types: std::array::from_fn(|i| self.event_types[i].saturating_sub(earlier.event_types[i])),
+        }
+    }
+}
+
+/// Counters since the start of the run, shared with the scrape endpoint
+pub struct LiveCounters {
+    buckets: [AtomicU64; 6],
+    event_types: [AtomicU64; 5],
+    /// Latest kernel discard readings (which cover the whole run)
+    invalid_sockets: AtomicU64,
+    invalid_latency: AtomicU64,
+    /// Events lost to full perf buffers
+    dropped_events: AtomicU64,
+}
+
+impl Default for LiveCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveCounters {
+    /// Create zeroed counters
+    pub fn new() -> Self {
+        Self {
+            buckets: Default::default(),
+            event_types: Default::default(),
+            invalid_sockets: AtomicU64::new(0),
+            invalid_latency: AtomicU64::new(0),
+            dropped_events: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a sample in its histogram bucket
+    ///
+    /// # Arguments
+    ///
+    /// * `latency_us` - Latency in microseconds
+    pub fn add_sample(&self, latency_us: f64) {
+        self.buckets[LatencyHistogram::bucket_index(latency_us)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count an event of a type; types without a counter are ignored
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - EVENT_TYPE_* constant
+    pub fn add_event_type(&self, event_type: u8) {
+        if let Some(index) = EventTypeBreakdown::index(event_type) {
+            self.event_types[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Add counts collected elsewhere
+    pub fn add(&self, counts: &Counts) {
+        for (counter, count) in self.buckets.iter().zip(counts.buckets) {
+            counter.fetch_add(count, Ordering::Relaxed);
+        }
+        for (counter, count) in self.event_types.iter().zip(counts.event_types) {
+            counter.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Record the latest kernel discard counters
+    ///
+    /// Their `dropped_events` is ignored; see
+    /// [`add_dropped_events`](Self::add_dropped_events).
+    pub fn set_kernel_discards(&self, counters: &InternalCounters) {
+        self.invalid_sockets.store(counters.invalid_sockets, Ordering::Relaxed);
+        self.invalid_latency.store(counters.invalid_latency, Ordering::Relaxed);
+    }
+
+    /// Count events lost to full perf buffers
+    pub fn add_dropped_events(&self, count: u64) {
+        self.dropped_events.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Events the probe discarded since it started
+    pub fn internal(&self) -> InternalCounters {
+        InternalCounters {
+            invalid_sockets: self.invalid_sockets.load(Ordering::Relaxed),
+            invalid_latency: self.invalid_latency.load(Ordering::Relaxed),
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Current counts
+    pub fn counts(&self) -> Counts {
+        Counts {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            event_types: std::array::from_fn(|i| self.event_types[i].load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Counters in the Prometheus text format
+    pub fn to_prometheus(&self) -> String {
+        let counts = self.counts();
+        let mut output = String::new();
+
+        output.push_str("# HELP latency_probe_live_histogram_bucket Latency histogram buckets since the probe started\n");
+        output.push_str("# TYPE latency_probe_live_histogram_bucket counter\n");
+        for (bound, count) in HISTOGRAM_BOUNDS_US.iter().zip(counts.buckets) {
+            output.push_str(&format!("latency_probe_live_histogram_bucket{{le=\"{}\"}} {}\n", bound, count));
+        }
+
+        output.push_str("# HELP latency_probe_live_events_by_type Events by type since the probe started\n");
+        output.push_str("# TYPE latency_probe_live_events_by_type counter\n");
+        for (name, count) in EventTypeBreakdown::NAMES.iter().zip(counts.event_types) {
+            output.push_str(&format!("latency_probe_live_events_by_type{{type=\"{}\"}} {}\n", name, count));
+        }
+
+        for (name, help, value) in self.internal().fields() {
+            output.push_str(&format!("# HELP latency_probe_internal_{}_total {}\n", name, help));
+            output.push_str(&format!("# TYPE latency_probe_internal_{}_total counter\n", name));
+            output.push_str(&format!("latency_probe_internal_{}_total {}\n", name, value));
+        }
+
+        output
+    }
+}
+
+/// Counts of one collection interval
+///
+/// Serializes to the interval's counts; deserializing starts new live
+/// counters holding them.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Counts", into = "Counts")]
+pub struct IntervalCounters {
+    live: Arc<LiveCounters>,
+    /// Live counts at the start of the interval
+    start: Counts,
+}
+
+impl From<Counts> for IntervalCounters {
+    fn from(counts: Counts) -> Self {
+        let live = LiveCounters::new();
+        live.add(&counts);
+        Self {
+            live: Arc::new(live),
+            start: Counts::default(),
+        }
+    }
+}
+
+impl From<IntervalCounters> for Counts {
+    fn from(counters: IntervalCounters) -> Self {
+        counters.counts()
+    }
+}
+
+impl IntervalCounters {
+    /// Live counters the interval is read from
+    pub fn live(&self) -> Arc<LiveCounters> {
+        Arc::clone(&self.live)
+    }
+
+    /// Count a sample in its histogram bucket
+    pub fn add_sample(&self, latency_us: f64) {
+        self.live.add_sample(latency_us);
+    }
+
+    /// Count an event of a type
+    pub fn add_event_type(&self, event_type: u8) {
+        self.live.add_event_type(event_type);
+    }
+
+    /// Counts since the start of the interval
+    pub fn counts(&self) -> Counts {
+        self.live.counts().since(&self.start)
+    }
+
+    /// Next interval, starting now on the same live counters
+    pub fn rotate(&self) -> Self {
+        Self {
+            live: Arc::clone(&self.live),
+            start: self.live.counts(),
+        }
+    }
+
+    /// Continue an interval restored from a checkpoint on these live
+    /// counters, which then include its counts
+    pub fn resume(&self, resumed: &IntervalCounters) -> Self {
+        let counts = resumed.counts();
+        self.live.add(&counts);
+        Self {
+            live: Arc::clone(&self.live),
+            start: self.live.counts().since(&counts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use probe_common::constants::{EVENT_TYPE_RECV, EVENT_TYPE_SEND};
+
+    #[test]
+    fn test_interval_counters() {
+        let counters = IntervalCounters::default();
+        let live = counters.live();
+
+        // Counts from several threads add up
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        counters.add_sample(500.0);
+                        counters.add_event_type(EVENT_TYPE_RECV);
+                    }
+                });
+            }
+        });
+        counters.add_sample(200_000.0);
+        counters.add_event_type(u8::MAX);
+        let counts = counters.counts();
+        assert_eq!(counts.buckets, [4000, 0, 0, 0, 0, 1]);
+        assert_eq!(counts.event_types().tcp_recvmsg, 4000);
+        assert_eq!(counts.event_types().counts().iter().sum::<u64>(), 4000);
+
+        // The next interval starts from zero, the live counters do not
+        let next = counters.rotate();
+        next.add_sample(2000.0);
+        next.add_event_type(EVENT_TYPE_SEND);
+        assert_eq!(next.counts().histogram().total_count(), 1);
+        assert_eq!(live.counts().buckets, [4000, 1, 0, 0, 0, 1]);
+        assert!(live.to_prometheus().contains("latency_probe_live_histogram_bucket{le=\"1000\"} 4000\n"));
+        assert!(live.to_prometheus().contains("latency_probe_live_events_by_type{type=\"tcp_sendmsg\"} 1\n"));
+
+        // Discards: kernel readings replace each other, lost events add up
+        live.set_kernel_discards(&InternalCounters { invalid_sockets: 3, invalid_latency: 1, dropped_events: 9 });
+        live.set_kernel_discards(&InternalCounters { invalid_sockets: 4, invalid_latency: 1, dropped_events: 9 });
+        live.add_dropped_events(2);
+        live.add_dropped_events(5);
+        assert_eq!(live.internal(), InternalCounters { invalid_sockets: 4, invalid_latency: 1, dropped_events: 7 });
+        assert!(live.to_prometheus().contains("latency_probe_internal_invalid_sockets_total 4\n"));
+
+        // A checkpointed interval continues on the live counters
+        let checkpoint = next.counts();
+        let bytes = bincode::serialize(&next).unwrap();
+        let restored: IntervalCounters = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.counts(), checkpoint);
+        let resumed = counters.resume(&restored);
+        assert_eq!(resumed.counts(), checkpoint);
+        assert_eq!(live.counts().buckets, [4000, 2, 0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Service (daemon) mode support
+//!
+//! PID file management, systemd readiness notification, and the signals a
+//! long-running probe reacts to:
+//!
+//! - `SIGHUP` - write the current report to a rotated file and start a new one
+//! - `SIGUSR1` - write a snapshot of the current report without resetting
+//! - `SIGUSR2` - hot-swap the eBPF object for the file at `--ebpf-object`
+//! - `SIGTERM` / `SIGINT` - write the final report and exit
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use log::debug;
+use std::{
+    fs,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+/// PID file removed when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the current process ID to `path`
+    pub fn create(path: &Path) -> Result<Self> {
+        fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write PID file: {:?}", path))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // May fail after dropping privileges; the stale file is harmless
+        if let Err(e) = fs::remove_file(&self.path) {
+            debug!("Failed to remove PID file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Send a state update to systemd (sd_notify protocol)
+///
+/// No-op unless the service manager set `NOTIFY_SOCKET`.
+///
+/// # Arguments
+///
+/// * `state` - Newline-separated assignments, e.g. `READY=1`
+///
+/// # Returns
+///
+/// True if the notification was sent
+pub fn notify(state: &str) -> Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => {
+            notify_socket(Path::new(&socket), state)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Send a state update to a specific notification socket
+fn notify_socket(socket: &Path, state: &str) -> Result<()> {
+    let sock = UnixDatagram::unbound()?;
+    let path = socket.to_string_lossy();
+
+    // '@' prefix denotes a Linux abstract socket
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        sock.send_to_addr(state.as_bytes(), &addr)
+    } else {
+        sock.send_to(state.as_bytes(), socket)
+    }
+    .with_context(|| format!("Failed to notify service manager at {}", path))?;
+
+    Ok(())
+}
+
+/// Path for a rotated report, e.g. `metrics.20240101T120000.json`
+///
+/// # Arguments
+///
+/// * `output` - Configured output path
+/// * `timestamp` - Rotation time
+pub fn rotated_path(output: &Path, timestamp: DateTime<Local>) -> PathBuf {
+    // Reports on stdout follow one another
+    if crate::output::is_stdout(output) {
+        return output.to_path_buf();
+    }
+    let stamp = timestamp.format("%Y%m%dT%H%M%S");
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match output.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, stamp, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, stamp),
+    };
+
+    output.with_file_name(name)
+}
+
+/// Signal received by a running daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonSignal {
+    /// SIGHUP: flush the report to a rotated file and reset
+    Rotate,
+    /// SIGUSR1: write an on-demand snapshot
+    Snapshot,
+    /// SIGUSR2: swap in a new build of the eBPF object
+    Swap,
+    /// SIGTERM or SIGINT: shut down
+    Shutdown,
+}
+
+/// Signal handlers installed in daemon mode
+pub struct DaemonSignals {
+    hangup: Signal,
+    user1: Signal,
+    user2: Signal,
+    terminate: Signal,
+    interrupt: Signal,
+}
+
+impl DaemonSignals {
+    /// Install handlers for SIGHUP, SIGUSR1, SIGUSR2, SIGTERM, and SIGINT
+    pub fn install() -> Result<Self> {
+        Ok(Self {
+            hangup: signal(SignalKind::hangup()).context("Failed to install SIGHUP handler")?,
+            user1: signal(SignalKind::user_defined1())
+                .context("Failed to install SIGUSR1 handler")?,
+            user2: signal(SignalKind::user_defined2())
+                .context("Failed to install SIGUSR2 handler")?,
+            terminate: signal(SignalKind::terminate())
+                .context("Failed to install SIGTERM handler")?,
+            interrupt: signal(SignalKind::interrupt())
+                .context("Failed to install SIGINT handler")?,
+        })
+    }
+
+    /// Wait for the next signal
+    pub async fn recv(&mut self) -> DaemonSignal {
+        tokio::select! {
+            _ = self.hangup.recv() => DaemonSignal::Rotate,
+            _ = self.user1.recv() => DaemonSignal::Snapshot,
+            _ = self.user2.recv() => DaemonSignal::Swap,
+            _ = self.terminate.recv() => DaemonSignal::Shutdown,
+            _ = self.interrupt.recv() => DaemonSignal::Shutdown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rotated_path() {
+        let timestamp = Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+
+        assert_eq!(
+            rotated_path(Path::new("/tmp/metrics.json"), timestamp),
+            PathBuf::from("/tmp/metrics.20240102T030405.json")
+        );
+        assert_eq!(
+            rotated_path(Path::new("report"), timestamp),
+            PathBuf::from("report.20240102T030405")
+        );
+    }
+
+    #[test]
+    fn test_pid_file_and_notify() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let pid_path = dir.path().join("probe.pid");
+        let pid_file = PidFile::create(&pid_path).unwrap();
+        let contents = fs::read_to_string(&pid_path).unwrap();
+        assert_eq!(contents.trim(), std::process::id().to_string());
+        drop(pid_file);
+        assert!(!pid_path.exists());
+
+        let socket_path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&socket_path).unwrap();
+        notify_socket(&socket_path, "READY=1").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+}
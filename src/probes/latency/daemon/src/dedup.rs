@@ -0,0 +1,212 @@
+//! Receive event deduplication
+//!
+//! tcp_cleanup_rbuf runs at the end of every tcp_recvmsg that copied data,
+//! so most reads are reported twice, by the tcp_recvmsg kprobe and then by
+//! the tcp_cleanup_rbuf kprobe, one right after the other on the same
+//! connection. Counting both skews the overall percentiles. The policy
+//! picks what counts of such a pair:
+//!
+//! - `recv`: the receive; a cleanup right after a receive on the same
+//!   connection is a duplicate. Cleanups without a receive (reads through
+//!   splice or tcp_read_sock) still count.
+//! - `cleanup`: the cleanup; a receive is held until the next event on its
+//!   connection, and dropped if that event is a cleanup. Without the
+//!   tcp_cleanup_rbuf kprobe every receive is released by the next one.
+//! - `separate`: both, with cleanups in a pool of their own, out of the
+//!   overall latency and its breakdowns.
+//! - `both`: both, in the overall latency.
+//!
+//! Connections are told apart by socket cookie when the kernel reports one,
+//! and by network namespace and 4-tuple otherwise.
+
+use crate::types::LatencyEvent;
+use probe_common::constants::{EVENT_TYPE_CLEANUP, EVENT_TYPE_RECV, MAX_CONNECTIONS};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Which of a receive and its cleanup count
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DedupPolicy {
+    /// Count the receive, drop the cleanup that follows it
+    Recv,
+    /// Count the cleanup, drop the receive before it
+    Cleanup,
+    /// Count both, with cleanups reported on their own
+    Separate,
+    /// Count both in the overall latency
+    #[default]
+    Both,
+}
+
+impl std::fmt::Display for DedupPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DedupPolicy::Recv => write!(f, "recv"),
+            DedupPolicy::Cleanup => write!(f, "cleanup"),
+            DedupPolicy::Separate => write!(f, "separate"),
+            DedupPolicy::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// Socket cookie, network namespace, and 4-tuple of an event
+type FlowId = (u64, u32, u32, u32, u16, u16);
+
+fn flow_id(event: &LatencyEvent) -> FlowId {
+    if event.cookie != 0 {
+        (event.cookie, 0, 0, 0, 0, 0)
+    } else {
+        let key = &event.key;
+        (0, event.netns, key.saddr, key.daddr, key.sport, key.dport)
+    }
+}
+
+/// Pairs receives with the cleanups that follow them
+///
+/// Serializes to the duplicate count only; receives held at a checkpoint
+/// are lost.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Deduplicator {
+    #[serde(skip)]
+    policy: DedupPolicy,
+    /// Last receive of each connection whose next event is not in yet
+    #[serde(skip)]
+    pending: HashMap<FlowId, LatencyEvent>,
+    /// Events dropped as duplicates
+    duplicates: u64,
+}
+
+impl Deduplicator {
+    /// Create a deduplicator applying a policy
+    pub fn new(policy: DedupPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Policy applied
+    pub fn policy(&self) -> DedupPolicy {
+        self.policy
+    }
+
+    /// Change the policy, keeping the duplicate count
+    pub fn set_policy(&mut self, policy: DedupPolicy) {
+        self.policy = policy;
+        self.pending.clear();
+    }
+
+    /// Events dropped as duplicates
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Start a new collection interval, carrying over the held receives
+    pub fn rotate(&mut self) -> Self {
+        Self {
+            policy: self.policy,
+            pending: std::mem::take(&mut self.pending),
+            duplicates: 0,
+        }
+    }
+
+    /// Pass an event through
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Event in the order the kernel reported it
+    ///
+    /// # Returns
+    ///
+    /// The events to record, in order: a receive this event releases, then
+    /// the event itself unless it is held or a duplicate
+    pub fn push(&mut self, event: &LatencyEvent) -> [Option<LatencyEvent>; 2] {
+        if matches!(self.policy, DedupPolicy::Both | DedupPolicy::Separate) {
+            return [Some(*event), None];
+        }
+
+        let id = flow_id(event);
+        let previous = self.pending.remove(&id);
+        let hold = event.event_type == EVENT_TYPE_RECV && self.pending.len() < MAX_CONNECTIONS as usize;
+        if hold {
+            self.pending.insert(id, *event);
+        }
+
+        let duplicate = previous.is_some() && event.event_type == EVENT_TYPE_CLEANUP;
+        if duplicate {
+            self.duplicates += 1;
+        }
+
+        match self.policy {
+            // The receive was recorded when it came in
+            DedupPolicy::Recv if duplicate => [None, None],
+            DedupPolicy::Recv => [Some(*event), None],
+            // The cleanup replaces the held receive
+            _ if duplicate => [Some(*event), None],
+            _ if hold => [previous, None],
+            _ => [previous, Some(*event)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+
+    fn event(sport: u16, event_type: u8, latency_ns: u64) -> LatencyEvent {
+        LatencyEvent {
+            pid: 1,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100007f,
+                    daddr: 0x0100007f,
+                    sport,
+                    dport: 80,
+                },
+                latency_ns,
+                event_type,
+            )
+        }
+    }
+
+    /// Latencies recorded for a sequence of events
+    fn recorded(policy: DedupPolicy, events: &[LatencyEvent]) -> (Vec<u64>, u64) {
+        let mut dedup = Deduplicator::new(policy);
+        let latencies = events
+            .iter()
+            .flat_map(|event| dedup.push(event))
+            .flatten()
+            .map(|event| event.latency_ns)
+            .collect();
+        (latencies, dedup.duplicates())
+    }
+
+    #[test]
+    fn test_dedup_policies() {
+        // A read reported twice, an unpaired cleanup, and a read on another
+        // connection interleaved with the pair of a third
+        let events = [
+            event(1, EVENT_TYPE_RECV, 100),
+            event(1, EVENT_TYPE_CLEANUP, 5),
+            event(1, EVENT_TYPE_CLEANUP, 200),
+            event(2, EVENT_TYPE_RECV, 300),
+            event(3, EVENT_TYPE_RECV, 400),
+            event(2, EVENT_TYPE_RECV, 500),
+            event(3, EVENT_TYPE_CLEANUP, 6),
+        ];
+
+        assert_eq!(recorded(DedupPolicy::Recv, &events), (vec![100, 200, 300, 400, 500], 2));
+        // Receive 500 is still waiting for its next event
+        assert_eq!(recorded(DedupPolicy::Cleanup, &events), (vec![5, 200, 300, 6], 2));
+        assert_eq!(recorded(DedupPolicy::Both, &events).0.len(), events.len());
+        assert_eq!(recorded(DedupPolicy::Separate, &events).1, 0);
+
+        // Held receives carry over to the next interval
+        let mut dedup = Deduplicator::new(DedupPolicy::Cleanup);
+        assert!(dedup.push(&events[0]).iter().all(Option::is_none));
+        let mut next = dedup.rotate();
+        assert_eq!(next.push(&events[2])[0].map(|e| e.latency_ns), Some(200));
+    }
+}
@@ -0,0 +1,219 @@
+//! Compact latency digests
+//!
+//! Keeping every sample of every connection does not scale to long runs
+//! with many connections. A digest keeps the count, sum, extremes, and a
+//! histogram with logarithmic buckets (as in DDSketch): each bucket spans
+//! 2% of its values, so percentiles are within 1% of the exact value, and
+//! a few hundred buckets cover nanoseconds to minutes.
+
+use crate::types::{percentile_rank, Percentiles};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Relative error of the percentiles estimated by a digest
+pub const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Ratio between the bounds of a bucket
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+/// Latency distribution summary of bounded size
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct LatencyDigest {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+    min: f64,
+    max: f64,
+    /// Samples of zero (or less), which have no logarithmic bucket
+    zero_count: u64,
+    /// Samples per bucket; bucket i holds values in (gamma^(i-1), gamma^i]
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl LatencyDigest {
+    /// Create an empty digest
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample
+    pub fn add(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+        self.sum_squares += value * value;
+
+        if value > 0.0 {
+            *self.buckets.entry(Self::bucket(value)).or_default() += 1;
+        } else {
+            self.zero_count += 1;
+        }
+    }
+
+    /// Add the samples of another digest
+    ///
+    /// The result is the digest of both sets of samples, with the same
+    /// accuracy, so digests of several nodes or runs can be combined
+    /// without the raw samples.
+    pub fn merge(&mut self, other: &LatencyDigest) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+        self.zero_count += other.zero_count;
+
+        for (&bucket, &count) in &other.buckets {
+            *self.buckets.entry(bucket).or_default() += count;
+        }
+    }
+
+    /// Number of samples
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Smallest sample (0 if empty)
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// Largest sample (0 if empty)
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Mean of the samples (0 if empty)
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+
+    /// Population standard deviation of the samples
+    pub fn std_dev(&self) -> f64 {
+        if self.count <= 1 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_squares / self.count as f64 - mean * mean).max(0.0).sqrt()
+    }
+
+    /// Number of samples above a value
+    ///
+    /// Samples sharing the value's bucket are not counted, so the count is
+    /// for values at least 2% above it.
+    pub fn count_above(&self, value: f64) -> u64 {
+        if value < 0.0 {
+            return self.count;
+        }
+        if value == 0.0 {
+            return self.count - self.zero_count;
+        }
+
+        let bucket = Self::bucket(value);
+        self.buckets.range(bucket + 1..).map(|(_, &count)| count).sum()
+    }
+
+    /// Bucket holding a positive value
+    fn bucket(value: f64) -> i32 {
+        (value.ln() / gamma().ln()).ceil() as i32
+    }
+
+    /// Estimated percentiles, ranked like
+    /// [`calculate_percentiles`](crate::types::calculate_percentiles)
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p50: self.percentile(50, 100),
+            p75: self.percentile(75, 100),
+            p90: self.percentile(90, 100),
+            p95: self.percentile(95, 100),
+            p99: self.percentile(99, 100),
+            p999: self.percentile(999, 1000),
+        }
+    }
+
+    /// Estimated percentile, as the fraction `numerator / denominator`
+    /// (e.g. 99 / 100 for p99; 0 for an empty digest)
+    pub fn percentile(&self, numerator: u64, denominator: u64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        self.value_at_rank(percentile_rank(self.count, numerator, denominator))
+    }
+
+    /// Estimated value of the sample at a rank (1 = smallest)
+    fn value_at_rank(&self, rank: u64) -> f64 {
+        let mut seen = self.zero_count;
+        if seen >= rank {
+            return self.min.min(0.0);
+        }
+
+        let gamma = gamma();
+        for (&bucket, &count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                // The value within the bucket with the lowest relative error
+                let estimate = 2.0 * gamma.powi(bucket) / (gamma + 1.0);
+                return estimate.clamp(self.min, self.max);
+            }
+        }
+
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::calculate_percentiles;
+
+    #[test]
+    fn test_digest_accuracy() {
+        let samples: Vec<f64> = (1..=10_000).map(|i| (i as f64).powf(1.5) / 10.0).collect();
+        let mut digest = LatencyDigest::new();
+        for &sample in &samples {
+            digest.add(sample);
+        }
+
+        let exact = calculate_percentiles(samples.clone());
+        let estimated = digest.percentiles();
+        for (exact, estimated) in [
+            (exact.p50, estimated.p50),
+            (exact.p90, estimated.p90),
+            (exact.p99, estimated.p99),
+            (exact.p999, estimated.p999),
+        ] {
+            assert!((estimated - exact).abs() <= exact * RELATIVE_ACCURACY, "{} vs {}", estimated, exact);
+        }
+
+        assert_eq!(digest.count(), 10_000);
+        assert_eq!(digest.min(), 0.1);
+        assert_eq!(digest.max(), 100_000.0);
+        assert!(digest.buckets.len() < 700);
+
+        // A single sample is reported exactly
+        let mut single = LatencyDigest::new();
+        single.add(123.4);
+        assert_eq!(single.percentiles().p99, 123.4);
+        assert_eq!(single.std_dev(), 0.0);
+    }
+}
@@ -0,0 +1,196 @@
+//! Options from environment variables
+//!
+//! Helm charts template environment variables far more easily than long
+//! argument lists, so every option can also be set as `LATENCY_PROBE_` plus
+//! its name in upper case, with `_` for `-`: `--duration 300` is
+//! `LATENCY_PROBE_DURATION=300`, and `--export-attempts` is
+//! `LATENCY_PROBE_EXPORT_ATTEMPTS`.
+//!
+//! - Arguments on the command line win over the environment.
+//! - Flags take `true` or `false`.
+//! - Repeatable options take a comma-separated list
+//!   (`LATENCY_PROBE_INTERFACE=eth0,eth1`).
+//! - Empty variables are ignored, as charts render unset values that way.
+//!
+//! Values are validated like arguments. A `LATENCY_PROBE_` variable that
+//! names no option is an error rather than silently ignored, so a typo in a
+//! chart cannot leave the probe running with its default. The exception
+//! are the variables kubelet adds to every pod of a namespace with a
+//! Service named `latency-probe` (`LATENCY_PROBE_SERVICE_HOST`,
+//! `LATENCY_PROBE_PORT`, ...), which are skipped.
+
+use anyhow::Result;
+use clap::{builder::Str, ArgAction, Command};
+use std::{collections::BTreeSet, ffi::OsString};
+
+/// Prefix of the variables
+pub const PREFIX: &str = "LATENCY_PROBE_";
+
+/// Variables read when building the probe, not options
+const BUILD_VARIABLES: [&str; 3] = [
+    "LATENCY_PROBE_EBPF_OBJECT",
+    "LATENCY_PROBE_EBPF_TOOLCHAIN",
+    "LATENCY_PROBE_EBPF_ARCH",
+];
+
+/// Name of the variable of an option
+///
+/// # Arguments
+///
+/// * `id` - ID of the option (its field name, e.g. `export_attempts`)
+pub fn variable(id: &str) -> String {
+    format!("{}{}", PREFIX, id.replace('-', "_").to_uppercase())
+}
+
+/// Let every option of a command be set from its variable
+///
+/// The variables are read here, so build the command just before parsing.
+/// Positional arguments and the options of subcommands are left alone,
+/// and empty variables are ignored.
+/// Repeatable options also accept a comma-separated list on the command
+/// line.
+pub fn with_env(command: Command) -> Command {
+    command.mut_args(|arg| {
+        if arg.is_positional() {
+            return arg;
+        }
+        // Charts render unset values as empty strings
+        let name = variable(arg.get_id().as_str());
+        if std::env::var_os(&name).is_some_and(|value| value.is_empty()) {
+            return arg;
+        }
+        let repeatable = matches!(arg.get_action(), ArgAction::Append) && arg.get_value_delimiter().is_none();
+        let arg = arg.env(Str::from(name));
+        if repeatable {
+            arg.value_delimiter(',')
+        } else {
+            arg
+        }
+    })
+}
+
+/// Reject `LATENCY_PROBE_` variables that name no option of a command
+///
+/// Service link variables set by kubelet are not options and are skipped.
+///
+/// # Arguments
+///
+/// * `command` - Command whose options may be set
+/// * `vars` - Environment variables (`std::env::vars_os()`)
+pub fn check(command: &Command, vars: impl IntoIterator<Item = (OsString, OsString)>) -> Result<()> {
+    let known: BTreeSet<String> = command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional())
+        .map(|arg| variable(arg.get_id().as_str()))
+        .collect();
+
+    let unknown: Vec<String> = vars
+        .into_iter()
+        .filter(|(name, value)| !is_service_link(&name.to_string_lossy(), &value.to_string_lossy()))
+        .map(|(name, _)| name.to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(PREFIX))
+        .filter(|name| !known.contains(name) && !BUILD_VARIABLES.contains(&name.as_str()))
+        .collect();
+    match unknown.as_slice() {
+        [] => Ok(()),
+        _ => anyhow::bail!(
+            "Unknown option in the environment: {} (not an option, or not built into this binary)",
+            unknown.join(", ")
+        ),
+    }
+}
+
+/// Whether a variable is one kubelet sets for a Service
+///
+/// A Service `svc` in the namespace gives every pod `SVC_SERVICE_HOST`,
+/// `SVC_SERVICE_PORT[_<name>]`, `SVC_PORT=tcp://<ip>:<port>` and
+/// `SVC_PORT_<port>_<protocol>[_PROTO|_PORT|_ADDR]`.
+fn is_service_link(name: &str, value: &str) -> bool {
+    if name.ends_with("_SERVICE_HOST") || name.ends_with("_SERVICE_PORT") || name.contains("_SERVICE_PORT_") {
+        return true;
+    }
+    if name.ends_with("_PORT") && ["tcp://", "udp://", "sctp://"].iter().any(|scheme| value.starts_with(scheme)) {
+        return true;
+    }
+    let parts: Vec<&str> = name.split('_').collect();
+    parts.windows(3).any(|window| {
+        window[0] == "PORT" && window[1].parse::<u16>().is_ok() && matches!(window[2], "TCP" | "UDP" | "SCTP")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    #[derive(Parser, Debug)]
+    struct TestArgs {
+        #[clap(long, default_value_t = 60)]
+        env_test_duration: u64,
+        #[clap(long)]
+        env_test_verbose: bool,
+        #[clap(long)]
+        env_test_interface: Vec<String>,
+        #[clap(long)]
+        env_test_output: Option<String>,
+    }
+
+    #[test]
+    fn test_options_from_env() {
+        std::env::set_var("LATENCY_PROBE_ENV_TEST_DURATION", "300");
+        std::env::set_var("LATENCY_PROBE_ENV_TEST_VERBOSE", "true");
+        std::env::set_var("LATENCY_PROBE_ENV_TEST_INTERFACE", "eth0,eth1");
+        std::env::set_var("LATENCY_PROBE_ENV_TEST_OUTPUT", "");
+
+        let command = with_env(TestArgs::command());
+        let parse = |argv: &[&str]| {
+            let matches = command.clone().try_get_matches_from(argv).unwrap();
+            TestArgs::from_arg_matches(&matches).unwrap()
+        };
+        let args = parse(&["probe"]);
+        assert_eq!(args.env_test_duration, 300);
+        assert!(args.env_test_verbose);
+        assert_eq!(args.env_test_interface, ["eth0", "eth1"]);
+        assert_eq!(args.env_test_output, None);
+
+        // The command line wins
+        let args = parse(&["probe", "--env-test-duration", "10", "--env-test-interface", "lo"]);
+        assert_eq!(args.env_test_duration, 10);
+        assert_eq!(args.env_test_interface, ["lo"]);
+
+        // Values are validated (variables are read when the command is built)
+        std::env::set_var("LATENCY_PROBE_ENV_TEST_DURATION", "soon");
+        assert!(with_env(TestArgs::command()).try_get_matches_from(["probe"]).is_err());
+
+        let var = |name: &str| (OsString::from(name), OsString::from("1"));
+        check(&command, [var("LATENCY_PROBE_ENV_TEST_OUTPUT"), var("LATENCY_PROBE_EBPF_ARCH"), var("HOME")]).unwrap();
+        let err = check(&command, [var("LATENCY_PROBE_ENV_TEST_DURATON")]).unwrap_err();
+        assert!(err.to_string().contains("LATENCY_PROBE_ENV_TEST_DURATON"));
+        assert_eq!(variable("export_attempts"), "LATENCY_PROBE_EXPORT_ATTEMPTS");
+    }
+
+    #[test]
+    fn test_service_links_skipped() {
+        let command = TestArgs::command();
+        let var = |name: &str, value: &str| (OsString::from(name), OsString::from(value));
+        // Service latency-probe with a port named metrics
+        check(
+            &command,
+            [
+                var("LATENCY_PROBE_SERVICE_HOST", "10.96.0.12"),
+                var("LATENCY_PROBE_SERVICE_PORT", "9090"),
+                var("LATENCY_PROBE_SERVICE_PORT_METRICS", "9090"),
+                var("LATENCY_PROBE_PORT", "tcp://10.96.0.12:9090"),
+                var("LATENCY_PROBE_PORT_9090_TCP", "tcp://10.96.0.12:9090"),
+                var("LATENCY_PROBE_PORT_9090_TCP_PROTO", "tcp"),
+                var("LATENCY_PROBE_PORT_9090_TCP_PORT", "9090"),
+                var("LATENCY_PROBE_PORT_9090_TCP_ADDR", "10.96.0.12"),
+                var("LATENCY_PROBE_METRICS_PORT", "udp://10.96.0.13:8125"),
+            ],
+        )
+        .unwrap();
+
+        // A misspelt port option is still rejected
+        assert!(check(&command, [var("LATENCY_PROBE_QUIK_PORT", "4433")]).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Envoy admin API scraping
+//!
+//! With the `envoy` feature and `--envoy-admin`, the histograms of local
+//! Envoy proxies are read from their admin API
+//! (`/stats?format=json&histogram_buckets=cumulative`) when collection
+//! starts and when it ends. The difference is what the proxy recorded
+//! during the run, reported next to the kernel-observed latency so the two
+//! can be cross-validated.
+//!
+//! Envoy keeps bucket counts rather than samples, so its percentiles are
+//! interpolated within its buckets as Prometheus' `histogram_quantile`
+//! does, and are only as precise as the buckets (0.5, 1, 5, 10, 25, 50,
+//! 100 ms and up by default). A proxy that restarted during the run, or
+//! could not be read at the start, is reported since its start.
+
+use crate::types::{ClientPercentiles, Percentiles, ProxyLatency};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+use std::{collections::BTreeMap, time::Duration};
+
+/// Histograms read by default (an Envoy stats filter regex)
+pub const DEFAULT_HISTOGRAMS: &str = "rq_time$";
+
+/// Timeout of an admin API request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Percentiles reported
+const PERCENTILES: [f64; 5] = [50.0, 90.0, 95.0, 99.0, 99.9];
+
+/// Cumulative count of one bucket
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    /// Upper bound in milliseconds
+    upper_bound: f64,
+    /// Samples at or below the bound since the proxy started
+    cumulative: u64,
+}
+
+#[derive(Deserialize)]
+struct Histogram {
+    name: String,
+    #[serde(default)]
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Deserialize)]
+struct StatsEntry {
+    #[serde(default)]
+    histograms: Option<Vec<Histogram>>,
+}
+
+#[derive(Deserialize)]
+struct Stats {
+    #[serde(default)]
+    stats: Vec<StatsEntry>,
+}
+
+/// Buckets of every histogram of an admin API response
+///
+/// # Arguments
+///
+/// * `json` - Body of `/stats?format=json&histogram_buckets=cumulative`
+fn parse_stats(json: &str) -> Result<BTreeMap<String, Vec<Bucket>>> {
+    let stats: Stats = serde_json::from_str(json).context("Invalid Envoy stats")?;
+    Ok(stats
+        .stats
+        .into_iter()
+        .filter_map(|entry| entry.histograms)
+        .flatten()
+        .map(|histogram| (histogram.name, histogram.buckets))
+        .collect())
+}
+
+/// Buckets of the samples recorded between two readings (the later
+/// reading alone if the counts went down, as when the proxy restarted)
+fn recorded(start: Option<&Vec<Bucket>>, end: &[Bucket]) -> Vec<Bucket> {
+    let Some(start) = start.filter(|start| {
+        start.len() == end.len()
+            && start.iter().zip(end).all(|(a, b)| a.upper_bound == b.upper_bound && a.cumulative <= b.cumulative)
+    }) else {
+        return end.to_vec();
+    };
+    start
+        .iter()
+        .zip(end)
+        .map(|(start, end)| Bucket {
+            upper_bound: end.upper_bound,
+            cumulative: end.cumulative - start.cumulative,
+        })
+        .collect()
+}
+
+/// Percentile of cumulative buckets in milliseconds, interpolated linearly
+/// within its bucket (None without samples)
+fn percentile(buckets: &[Bucket], percentile: f64) -> Option<f64> {
+    let total = buckets.last()?.cumulative;
+    if total == 0 {
+        return None;
+    }
+    let rank = total as f64 * percentile / 100.0;
+    let (mut lower, mut below) = (0.0, 0);
+    for bucket in buckets {
+        if bucket.cumulative as f64 >= rank {
+            let share = (rank - below as f64) / (bucket.cumulative - below).max(1) as f64;
+            return Some(lower + (bucket.upper_bound - lower) * share);
+        }
+        (lower, below) = (bucket.upper_bound, bucket.cumulative);
+    }
+    Some(lower)
+}
+
+/// Reads the histograms of Envoy proxies at the start and end of a run
+pub struct EnvoyScraper {
+    client: reqwest::Client,
+    admins: Vec<String>,
+    filter: String,
+    start: BTreeMap<String, BTreeMap<String, Vec<Bucket>>>,
+}
+
+impl EnvoyScraper {
+    /// Create a scraper
+    ///
+    /// # Arguments
+    ///
+    /// * `admins` - Admin API base URLs, e.g. `http://127.0.0.1:15000`
+    /// * `filter` - Envoy stats filter regex selecting the histograms
+    pub fn new(admins: &[String], filter: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            client,
+            admins: admins.iter().map(|admin| admin.trim_end_matches('/').to_string()).collect(),
+            filter: filter.to_string(),
+            start: BTreeMap::new(),
+        })
+    }
+
+    /// Read the histograms at the start of the run (failures are logged)
+    pub async fn start(&mut self) {
+        for admin in &self.admins {
+            match self.scrape(admin).await {
+                Ok(histograms) => {
+                    self.start.insert(admin.clone(), histograms);
+                }
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+
+    /// Read the histograms at the end of the run and compare what they
+    /// recorded with the kernel (failures are logged)
+    ///
+    /// # Arguments
+    ///
+    /// * `kernel` - Kernel-observed percentiles of the run
+    pub async fn finish(&self, kernel: &Percentiles) -> Vec<ProxyLatency> {
+        let mut latencies = Vec::new();
+        for admin in &self.admins {
+            let histograms = match self.scrape(admin).await {
+                Ok(histograms) => histograms,
+                Err(e) => {
+                    warn!("{:#}", e);
+                    continue;
+                }
+            };
+            let start = self.start.get(admin);
+            for (name, end) in histograms {
+                let buckets = recorded(start.and_then(|start| start.get(&name)), &end);
+                let samples = buckets.last().map_or(0, |bucket| bucket.cumulative);
+                if samples == 0 {
+                    continue;
+                }
+                let mut percentiles = ClientPercentiles::default();
+                for p in PERCENTILES {
+                    if let Some(ms) = percentile(&buckets, p) {
+                        percentiles.set(p, ms * 1000.0);
+                    }
+                }
+                latencies.push(ProxyLatency {
+                    admin: admin.clone(),
+                    histogram: name,
+                    samples,
+                    above_kernel: percentiles.above(kernel),
+                    percentiles,
+                });
+            }
+        }
+        latencies
+    }
+
+    /// Histograms of one proxy
+    async fn scrape(&self, admin: &str) -> Result<BTreeMap<String, Vec<Bucket>>> {
+        let body = self
+            .client
+            .get(format!("{}/stats", admin))
+            .query(&[("format", "json"), ("histogram_buckets", "cumulative"), ("filter", &self.filter)])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to read the Envoy stats of {}", admin))?
+            .text()
+            .await
+            .with_context(|| format!("Failed to read the Envoy stats of {}", admin))?;
+        parse_stats(&body).with_context(|| format!("Failed to parse the Envoy stats of {}", admin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envoy_histograms() {
+        let stats = |counts: [u64; 4]| {
+            let buckets: Vec<_> = [1.0, 5.0, 10.0, 50.0]
+                .iter()
+                .zip(counts)
+                .map(|(bound, count)| serde_json::json!({"upper_bound": bound, "interval": 0, "cumulative": count}))
+                .collect();
+            serde_json::json!({"stats": [
+                {"name": "cluster.outbound.upstream_rq_total", "value": 100},
+                {"histograms": [{"name": "cluster.outbound.upstream_rq_time", "buckets": buckets}]},
+            ]})
+            .to_string()
+        };
+        let start = parse_stats(&stats([10, 20, 20, 20])).unwrap();
+        let end = parse_stats(&stats([10, 70, 110, 120])).unwrap();
+        assert_eq!(end.len(), 1);
+
+        // 100 samples during the run: 50 in 1-5ms, 40 in 5-10ms, 10 in 10-50ms
+        let name = "cluster.outbound.upstream_rq_time";
+        let buckets = recorded(start.get(name), &end[name]);
+        assert_eq!(buckets.last().unwrap().cumulative, 100);
+        assert_eq!(percentile(&buckets, 50.0), Some(5.0));
+        assert_eq!(percentile(&buckets, 70.0), Some(7.5));
+        assert_eq!(percentile(&buckets, 95.0), Some(30.0));
+
+        // A restarted proxy counts since its restart
+        let restarted = parse_stats(&stats([1, 2, 2, 2])).unwrap();
+        assert_eq!(recorded(end.get(name), &restarted[name]), restarted[name]);
+        assert_eq!(percentile(&recorded(None, &[]), 50.0), None);
+    }
+}
@@ -2,19 +2,291 @@
 //!
 //! Handles reading events from per-CPU perf buffers and processing them asynchronously.
 
-use crate::{collector::MetricsCollector, types::{LatencyEvent, kernel::ContextSwitchEvent}};
-use anyhow::Result;
-use aya::{maps::{perf::AsyncPerfEventArray, MapData}, util::online_cpus};
+use crate::{
+    backpressure::{EventChannels, OverflowPolicy, DEFAULT_CHANNEL_CAPACITY},
+    collector::MetricsCollector,
+    config::SampleMode,
+    netns::NetnsResolver,
+    replay::{EventReader, ReplaySummary},
+    types::{is_connection_error, is_half_open, is_loopback, ConnectionKey, LatencyEvent, kernel::{ContextSwitchEvent, PacketDropEvent}},
+};
+use anyhow::{Context, Result};
+use aya::{
+    maps::{
+        perf::{AsyncPerfEventArray, AsyncPerfEventArrayBuffer},
+        MapData,
+    },
+    util::online_cpus,
+};
 use bytes::BytesMut;
 use log::{debug, info, warn};
-use std::{sync::Arc, time::Duration};
-use tokio::{sync::Mutex, time::interval};
+use std::{collections::BTreeMap, future::Future, path::Path, sync::Arc, time::Duration};
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::{mpsc, watch, Mutex},
+    time::interval,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Async stream of sampled latency events
+///
+/// Returned by [`EventProcessor::subscribe`]. Use `tokio_stream::StreamExt`
+/// to consume it.
+pub type EventStream = ReceiverStream<LatencyEvent>;
+
+/// Callback invoked for every sampled latency event
+pub type EventCallback = Arc<dyn Fn(&LatencyEvent) + Send + Sync>;
+
+/// Consumer of sampled events besides the built-in collector
+#[derive(Clone)]
+enum EventSubscriber {
+    /// Bounded channel backing an [`EventStream`]
+    Channel(mpsc::Sender<LatencyEvent>),
+    /// User-supplied callback
+    Callback(EventCallback),
+}
+
+impl EventSubscriber {
+    /// Deliver an event to this subscriber
+    ///
+    /// Channel subscribers use `try_send`, so a slow consumer loses events
+    /// instead of stalling the perf buffer readers.
+    fn dispatch(&self, event: &LatencyEvent) {
+        match self {
+            EventSubscriber::Channel(tx) => {
+                let _ = tx.try_send(*event);
+            }
+            EventSubscriber::Callback(callback) => callback(event),
+        }
+    }
+}
+
+/// Deterministic 1-in-N event sampler
+///
+/// Event types with their own rate are counted separately. In flow mode,
+/// connections are picked by hash as in the kernel.
+struct Sampler {
+    rate: u32,
+    event_rates: BTreeMap<u8, u32>,
+    mode: SampleMode,
+    /// Events since the last kept one, by event type (None = types at the
+    /// global rate)
+    counters: BTreeMap<Option<u8>, u32>,
+}
+
+impl Sampler {
+    fn new(rate: u32, event_rates: BTreeMap<u8, u32>, mode: SampleMode) -> Self {
+        Self {
+            rate,
+            event_rates,
+            mode,
+            counters: BTreeMap::new(),
+        }
+    }
+
+    /// Returns true for every `rate`-th event of a type, or for the events
+    /// of every `rate`-th connection in flow mode
+    fn sample(&mut self, event: &LatencyEvent) -> bool {
+        let event_type = event.event_type;
+        if self.mode == SampleMode::Flow {
+            let rate = self.event_rates.get(&event_type).copied().unwrap_or(self.rate);
+            return event.key.flow_hash().is_multiple_of(rate.max(1));
+        }
+        let (rate, counter) = match self.event_rates.get(&event_type) {
+            Some(&rate) => (rate, self.counters.entry(Some(event_type)).or_default()),
+            None => (self.rate, self.counters.entry(None).or_default()),
+        };
+        *counter += 1;
+        if *counter >= rate {
+            *counter = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Decode an event from a perf buffer record
+///
+/// # Returns
+///
+/// The event, or None if the record is too short to hold one
+pub fn decode_event<T: aya::Pod>(record: &[u8]) -> Option<T> {
+    if record.len() < std::mem::size_of::<T>() {
+        return None;
+    }
+    // SAFETY: the record holds at least size_of::<T>() bytes, and any bit
+    // pattern is a valid Pod value
+    Some(unsafe { (record.as_ptr() as *const T).read_unaligned() })
+}
+
+/// Check an event's namespace against a filter (empty = allow all)
+fn netns_allowed(filter: &[u32], netns: u32) -> bool {
+    filter.is_empty() || filter.contains(&netns)
+}
+
+/// Which connections to process, by whether they are on loopback
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoopbackFilter {
+    /// Loopback and external connections
+    #[default]
+    All,
+    /// External connections only (e.g. pod to pod)
+    Exclude,
+    /// Loopback connections only (e.g. sidecar to app)
+    Only,
+}
+
+impl LoopbackFilter {
+    /// Check whether events on a connection pass the filter
+    pub fn allows(self, key: &ConnectionKey) -> bool {
+        match self {
+            Self::All => true,
+            Self::Exclude => !is_loopback(key),
+            Self::Only => is_loopback(key),
+        }
+    }
+}
+
+/// How long a reader waits for more events after a short read
+const WAKEUP_BACKOFF: Duration = Duration::from_millis(1);
+
+/// Perf buffer sizing and read batching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfBufferOptions {
+    /// Pages per per-CPU buffer (power of two; None = aya default)
+    pub pages: Option<usize>,
+    /// Events read per `read_events` call
+    pub read_buffers: usize,
+    /// Events to accumulate before a reader wakes up again
+    ///
+    /// aya signals readiness for every event, so this is applied in
+    /// userspace: after reading fewer than this many events, a reader
+    /// pauses briefly to let more accumulate.
+    pub wakeup_events: usize,
+}
+
+impl Default for PerfBufferOptions {
+    fn default() -> Self {
+        Self {
+            pages: None,
+            read_buffers: 10,
+            wakeup_events: 1,
+        }
+    }
+}
+
+impl PerfBufferOptions {
+    /// Check that the options are accepted by the kernel and aya
+    pub fn validate(&self) -> Result<()> {
+        if let Some(pages) = self.pages {
+            if !pages.is_power_of_two() {
+                anyhow::bail!("Perf buffer pages must be a power of two, got {}", pages);
+            }
+        }
+        if self.read_buffers == 0 {
+            anyhow::bail!("Read buffers must be >= 1");
+        }
+        if self.wakeup_events == 0 {
+            anyhow::bail!("Wakeup events must be >= 1");
+        }
+        Ok(())
+    }
+
+    /// Pages per per-CPU buffer that hold at least `events` latency events
+    ///
+    /// Perf event arrays have one entry per CPU rather than per event, so
+    /// an event budget sizes the buffers instead of the map.
+    pub fn pages_for_events(events: usize) -> usize {
+        // Each record carries an 8-byte perf header
+        let record = std::mem::size_of::<LatencyEvent>() + 8;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+        (events * record).div_ceil(page_size).next_power_of_two()
+    }
+
+    /// Pause after a short read so the next one returns a larger batch
+    async fn coalesce(&self, read: usize) {
+        if read < self.wakeup_events {
+            tokio::time::sleep(WAKEUP_BACKOFF).await;
+        }
+    }
+}
+
+/// Where per-CPU perf buffer readers run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReaderPlacement {
+    /// Tasks on the main runtime, shared with the control plane
+    #[default]
+    Shared,
+    /// Tasks on a dedicated runtime with this many worker threads
+    Dedicated(usize),
+    /// One thread per CPU, pinned to the CPU whose buffer it reads
+    Pinned,
+}
+
+impl std::fmt::Display for ReaderPlacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReaderPlacement::Shared => write!(f, "shared runtime"),
+            ReaderPlacement::Dedicated(threads) => write!(f, "{} dedicated threads", threads),
+            ReaderPlacement::Pinned => write!(f, "pinned to CPUs"),
+        }
+    }
+}
+
+/// Runtime hosting dedicated readers
+///
+/// Shut down in the background on drop, since the processor is usually
+/// dropped from async code where blocking is not allowed.
+#[derive(Default)]
+struct ReaderRuntime(Option<Runtime>);
+
+impl ReaderRuntime {
+    fn handle(&self) -> Option<&Handle> {
+        self.0.as_ref().map(Runtime::handle)
+    }
+}
+
+impl Drop for ReaderRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Restrict the calling thread to a single CPU
+fn pin_current_thread(cpu_id: u32) -> std::io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu_id as usize, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
 
 /// Event processor that reads from perf buffers
 pub struct EventProcessor {
     collector: Arc<Mutex<MetricsCollector>>,
     sample_rate: u32,
+    event_sample_rates: BTreeMap<u8, u32>,
+    sample_mode: SampleMode,
     verbose: bool,
+    subscribers: Vec<EventSubscriber>,
+    netns_filter: Arc<Vec<u32>>,
+    loopback_filter: LoopbackFilter,
+    exclude_half_open: bool,
+    placement: ReaderPlacement,
+    reader_runtime: ReaderRuntime,
+    perf_options: PerfBufferOptions,
+    channel_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    /// Queues of the spawned latency readers
+    channels: std::sync::Mutex<Vec<EventChannels>>,
+    /// Bumped to stop the spawned readers
+    stop: watch::Sender<u64>,
 }
 
 impl EventProcessor {
@@ -29,13 +301,171 @@ impl EventProcessor {
         Self {
             collector,
             sample_rate,
+            event_sample_rates: BTreeMap::new(),
+            sample_mode: SampleMode::default(),
             verbose,
+            subscribers: Vec::new(),
+            netns_filter: Arc::new(Vec::new()),
+            loopback_filter: LoopbackFilter::default(),
+            exclude_half_open: false,
+            placement: ReaderPlacement::default(),
+            reader_runtime: ReaderRuntime::default(),
+            perf_options: PerfBufferOptions::default(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
+            channels: std::sync::Mutex::new(Vec::new()),
+            stop: watch::channel(0).0,
+        }
+    }
+
+    /// Sample these event types at their own rate instead of the processor's
+    ///
+    /// # Arguments
+    ///
+    /// * `rates` - Sampling rate by event type (EVENT_TYPE_*)
+    pub fn set_event_sample_rates(&mut self, rates: BTreeMap<u8, u32>) {
+        self.event_sample_rates = rates;
+    }
+
+    /// Sample whole connections instead of single events
+    pub fn set_sample_mode(&mut self, mode: SampleMode) {
+        self.sample_mode = mode;
+    }
+
+    /// Set perf buffer sizing and read batching
+    ///
+    /// Must be called before [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn set_perf_buffer_options(&mut self, options: PerfBufferOptions) -> Result<()> {
+        options.validate()?;
+        self.perf_options = options;
+        Ok(())
+    }
+
+    /// Size the per-CPU queues between readers and the collector, and
+    /// choose what happens to events that find one full
+    ///
+    /// Must be called before [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn set_backpressure(&mut self, capacity: usize, policy: OverflowPolicy) {
+        self.channel_capacity = capacity;
+        self.overflow_policy = policy;
+    }
+
+    /// Choose where per-CPU perf buffer readers run
+    ///
+    /// Starts the dedicated reader runtime if needed. Must be called before
+    /// [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn set_reader_placement(&mut self, placement: ReaderPlacement) -> Result<()> {
+        if let ReaderPlacement::Dedicated(threads) = placement {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(threads.max(1))
+                .thread_name("perf-reader")
+                .enable_all()
+                .build()
+                .context("Failed to build reader runtime")?;
+            self.reader_runtime = ReaderRuntime(Some(runtime));
+        }
+
+        self.placement = placement;
+        Ok(())
+    }
+
+    /// Only process events from the given network namespace inodes
+    ///
+    /// An empty list (the default) accepts every namespace. Must be called
+    /// before [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn set_netns_filter(&mut self, namespaces: Vec<u32>) {
+        self.netns_filter = Arc::new(namespaces);
+    }
+
+    /// Only process events on loopback, or on external, connections
+    ///
+    /// Must be called before [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn set_loopback_filter(&mut self, filter: LoopbackFilter) {
+        self.loopback_filter = filter;
+    }
+
+    /// Drop events on connections that have not completed the handshake
+    ///
+    /// Must be called before [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn set_exclude_half_open(&mut self, exclude: bool) {
+        self.exclude_half_open = exclude;
+    }
+
+    /// Subscribe to the stream of sampled latency events
+    ///
+    /// Every event that passes sampling is delivered to the returned stream
+    /// in addition to the built-in collector. Must be called before
+    /// [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Number of events buffered before new events are dropped
+    pub fn subscribe(&mut self, capacity: usize) -> EventStream {
+        let (tx, rx) = mpsc::channel(capacity);
+        self.subscribers.push(EventSubscriber::Channel(tx));
+        ReceiverStream::new(rx)
+    }
+
+    /// Register a callback invoked for every sampled latency event
+    ///
+    /// The callback runs on the perf reader task and must not block.
+    /// Must be called before [`spawn_cpu_readers`](Self::spawn_cpu_readers).
+    pub fn register_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&LatencyEvent) + Send + Sync + 'static,
+    {
+        self.subscribers
+            .push(EventSubscriber::Callback(Arc::new(callback)));
+    }
+
+    /// Replay recorded events through the processing pipeline
+    ///
+    /// Applies the same sampling, subscriber fan-out, and collection as the
+    /// per-CPU readers, without requiring eBPF privileges.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - JSON Lines recording produced by `EventRecorder`
+    ///
+    /// # Returns
+    ///
+    /// Summary of the events read from the recording
+    pub async fn replay(&self, path: &Path) -> Result<ReplaySummary> {
+        let mut summary = ReplaySummary::default();
+        let mut sampler = Sampler::new(self.sample_rate, self.event_sample_rates.clone(), self.sample_mode);
+
+        for event in EventReader::open(path)? {
+            let event = event?;
+            summary.observe(&event);
+
+            if !netns_allowed(&self.netns_filter, event.netns) || !self.loopback_filter.allows(&event.key) {
+                continue;
+            }
+            if self.exclude_half_open && is_half_open(&event) {
+                continue;
+            }
+
+            // Apply sampling; resets and timeouts are always kept
+            if !is_connection_error(event.event_type) && !sampler.sample(&event) {
+                continue;
+            }
+
+            for subscriber in &self.subscribers {
+                subscriber.dispatch(&event);
+            }
+
+            let mut collector = self.collector.lock().await;
+            collector.add_event(&event);
         }
+
+        Ok(summary)
     }
 
     /// Spawn per-CPU event readers
     ///
-    /// Creates a task for each CPU to read events from its perf buffer.
+    /// Creates a task for each CPU to read events from its perf buffer into
+    /// a bounded queue, and a task moving queued events into the collector
+    /// (see crate::backpressure).
     ///
     /// # Arguments
     ///
@@ -45,22 +475,33 @@ impl EventProcessor {
     ///
     /// Result indicating success or failure
     pub async fn spawn_cpu_readers(&self, mut perf_array: AsyncPerfEventArray<MapData>) -> Result<()> {
-        let cpus = online_cpus()?;
-        info!("Spawning event readers for {} CPUs", cpus.len());
+        let cpus = online_cpus().map_err(|(_, e)| e)?;
+        info!("Spawning event readers for {} CPUs ({})", cpus.len(), self.placement);
 
-        for cpu_id in cpus {
-            let mut buf = perf_array.open(cpu_id, None)?;
-            let collector_clone = Arc::clone(&self.collector);
+        let channels = EventChannels::new(cpus.len(), self.channel_capacity, self.overflow_policy);
+        tokio::spawn(channels.clone().aggregate(Arc::clone(&self.collector), self.stop.subscribe()));
+        self.channels.lock().unwrap_or_else(|e| e.into_inner()).push(channels.clone());
+
+        for (reader, cpu_id) in cpus.into_iter().enumerate() {
+            let queue = channels.queue(reader);
             let sample_rate = self.sample_rate;
+            let event_sample_rates = self.event_sample_rates.clone();
+            let sample_mode = self.sample_mode;
             let verbose = self.verbose;
+            let subscribers = self.subscribers.clone();
+            let netns_filter = Arc::clone(&self.netns_filter);
+            let loopback_filter = self.loopback_filter;
+            let exclude_half_open = self.exclude_half_open;
+            let options = self.perf_options;
 
-            tokio::spawn(async move {
+            self.spawn_reader(&mut perf_array, cpu_id, move |mut buf| async move {
                 // Pre-allocate buffers for reading events
-                let mut buffers = (0..10)
+                let mut buffers = (0..options.read_buffers)
                     .map(|_| BytesMut::with_capacity(std::mem::size_of::<LatencyEvent>()))
                     .collect::<Vec<_>>();
 
-                let mut sample_counter = 0u32;
+                let mut sampler = Sampler::new(sample_rate, event_sample_rates, sample_mode);
+                let mut resolver = NetnsResolver::new();
 
                 loop {
                     // Read events from the perf buffer
@@ -72,17 +513,32 @@ impl EventProcessor {
                         }
                     };
 
+                    if events.lost > 0 {
+                        queue.add_lost(events.lost as u64);
+                    }
+
                     // Process each event
                     for buf in buffers.iter_mut().take(events.read) {
-                        // Apply sampling
-                        sample_counter += 1;
-                        if sample_counter % sample_rate != 0 {
+                        let Some(mut event) = decode_event::<LatencyEvent>(buf) else {
+                            continue;
+                        };
+
+                        // Fall back to the process's namespace if the
+                        // kernel could not read the socket's
+                        if event.netns == 0 {
+                            event.netns = resolver.resolve(event.pid);
+                        }
+                        if !netns_allowed(&netns_filter, event.netns) || !loopback_filter.allows(&event.key) {
+                            continue;
+                        }
+                        if exclude_half_open && is_half_open(&event) {
                             continue;
                         }
 
-                        // Parse event from buffer
-                        let ptr = buf.as_ptr() as *const LatencyEvent;
-                        let event = unsafe { ptr.read_unaligned() };
+                        // Apply sampling; resets and timeouts are always kept
+                        if !is_connection_error(event.event_type) && !sampler.sample(&event) {
+                            continue;
+                        }
 
                         if verbose {
                             debug!(
@@ -94,28 +550,56 @@ impl EventProcessor {
                             );
                         }
 
-                        // Add to collector
-                        let mut collector = collector_clone.lock().await;
-                        collector.add_event(&event);
+                        // Forward to library subscribers
+                        for subscriber in &subscribers {
+                            subscriber.dispatch(&event);
+                        }
+
+                        queue.push(event).await;
+                    }
+                    if events.read > 0 || events.lost > 0 {
+                        queue.notify();
                     }
+
+                    options.coalesce(events.read).await;
                 }
-            });
+            })?;
         }
 
         Ok(())
     }
 
+    /// Move the events still queued by the latency readers into the
+    /// collector, before a final report
+    pub async fn flush(&self) {
+        let channels = self.channels.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for channels in channels {
+            channels.drain_into(&self.collector).await;
+        }
+    }
+
+    /// Stop every spawned reader, and move the events the latency readers
+    /// queued into the collector
+    ///
+    /// Readers of perf buffers that no longer receive events (those of a
+    /// swapped-out eBPF object) would otherwise wait on them until exit.
+    pub async fn stop_readers(&self) {
+        self.stop.send_modify(|generation| *generation += 1);
+        self.flush().await;
+        self.channels.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
     /// Spawn per-CPU event readers for context switch events
     pub async fn spawn_context_switch_readers(&self, mut perf_array: AsyncPerfEventArray<MapData>) -> Result<()> {
-        let cpus = online_cpus()?;
+        let cpus = online_cpus().map_err(|(_, e)| e)?;
         info!("Spawning context switch readers for {} CPUs", cpus.len());
 
         for cpu_id in cpus {
-            let mut buf = perf_array.open(cpu_id, None)?;
             let collector_clone = Arc::clone(&self.collector);
+            let options = self.perf_options;
 
-            tokio::spawn(async move {
-                let mut buffers = (0..10)
+            self.spawn_reader(&mut perf_array, cpu_id, move |mut buf| async move {
+                let mut buffers = (0..options.read_buffers)
                     .map(|_| BytesMut::with_capacity(std::mem::size_of::<ContextSwitchEvent>()))
                     .collect::<Vec<_>>();
 
@@ -128,20 +612,127 @@ impl EventProcessor {
                         }
                     };
 
-                    // Just count the events - we don't need to parse each one
                     if events.read > 0 {
                         let mut collector = collector_clone.lock().await;
-                        for _ in 0..events.read {
-                            collector.add_context_switch();
+                        for event in buffers.iter().take(events.read).filter_map(|buf| decode_event::<ContextSwitchEvent>(buf)) {
+                            collector.add_context_switch(&event);
+                        }
+                    }
+
+                    options.coalesce(events.read).await;
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Spawn per-CPU event readers for packet drop events
+    pub async fn spawn_packet_drop_readers(&self, mut perf_array: AsyncPerfEventArray<MapData>) -> Result<()> {
+        let cpus = online_cpus().map_err(|(_, e)| e)?;
+        info!("Spawning packet drop readers for {} CPUs", cpus.len());
+
+        for cpu_id in cpus {
+            let collector_clone = Arc::clone(&self.collector);
+            let options = self.perf_options;
+
+            self.spawn_reader(&mut perf_array, cpu_id, move |mut buf| async move {
+                let mut buffers = (0..options.read_buffers)
+                    .map(|_| BytesMut::with_capacity(std::mem::size_of::<PacketDropEvent>()))
+                    .collect::<Vec<_>>();
+
+                loop {
+                    let events = match buf.read_events(&mut buffers).await {
+                        Ok(events) => events,
+                        Err(e) => {
+                            warn!("Error reading packet drop events from CPU {}: {}", cpu_id, e);
+                            continue;
+                        }
+                    };
+
+                    if events.read > 0 {
+                        let mut collector = collector_clone.lock().await;
+                        for event in buffers.iter().take(events.read).filter_map(|buf| decode_event::<PacketDropEvent>(buf)) {
+                            collector.add_packet_drop(&event);
                         }
                     }
+
+                    options.coalesce(events.read).await;
                 }
-            });
+            })?;
         }
 
         Ok(())
     }
 
+    /// Open the perf buffer for `cpu_id` and run `reader` on it according
+    /// to the configured placement, until [`stop_readers`](Self::stop_readers)
+    ///
+    /// The buffer is opened inside the runtime that will poll it, so its
+    /// readiness notifications are driven by that runtime's reactor.
+    fn spawn_reader<F, Fut>(
+        &self,
+        perf_array: &mut AsyncPerfEventArray<MapData>,
+        cpu_id: u32,
+        reader: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(AsyncPerfEventArrayBuffer<MapData>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        match self.placement {
+            ReaderPlacement::Shared => {
+                let buf = perf_array.open(cpu_id, self.perf_options.pages)?;
+                tokio::spawn(self.stoppable(reader(buf)));
+            }
+            ReaderPlacement::Dedicated(_) => {
+                let runtime = self
+                    .reader_runtime
+                    .handle()
+                    .context("Reader runtime not started")?;
+                let buf = {
+                    let _guard = runtime.enter();
+                    perf_array.open(cpu_id, self.perf_options.pages)?
+                };
+                runtime.spawn(self.stoppable(reader(buf)));
+            }
+            ReaderPlacement::Pinned => {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .context("Failed to build reader runtime")?;
+                let buf = {
+                    let _guard = runtime.enter();
+                    perf_array.open(cpu_id, self.perf_options.pages)?
+                };
+                let task = self.stoppable(reader(buf));
+
+                std::thread::Builder::new()
+                    .name(format!("perf-reader-{}", cpu_id))
+                    .spawn(move || {
+                        if let Err(e) = pin_current_thread(cpu_id) {
+                            warn!("Failed to pin reader to CPU {}: {}", cpu_id, e);
+                        }
+                        runtime.block_on(task);
+                    })
+                    .with_context(|| format!("Failed to spawn reader thread for CPU {}", cpu_id))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run `task` until [`stop_readers`](Self::stop_readers)
+    fn stoppable(&self, task: impl Future<Output = ()>) -> impl Future<Output = ()> {
+        let mut stop = self.stop.subscribe();
+        async move {
+            tokio::select! {
+                _ = task => {}
+                _ = stop.changed() => {}
+            }
+        }
+    }
+
     /// Spawn progress reporter
     ///
     /// Creates a task that periodically reports collection progress.
@@ -172,6 +763,7 @@ impl EventProcessor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing;
 
     #[tokio::test]
     async fn test_event_processor_creation() {
@@ -179,6 +771,188 @@ mod tests {
         let processor = EventProcessor::new(collector, 1, false);
 
         assert_eq!(processor.sample_rate, 1);
-        assert_eq!(processor.verbose, false);
+        assert!(!processor.verbose);
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_receive_events() {
+        use probe_common::types::ConnectionKey;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use tokio_stream::StreamExt;
+
+        let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+        let mut processor = EventProcessor::new(collector, 1, false);
+
+        let mut stream = processor.subscribe(4);
+        let seen = Arc::new(AtomicU64::new(0));
+        let seen_clone = Arc::clone(&seen);
+        processor.register_callback(move |event| {
+            seen_clone.fetch_add(event.latency_ns, Ordering::Relaxed);
+        });
+
+        let event = LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 42,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100007f,
+                    daddr: 0x0100007f,
+                    sport: 0x5000,
+                    dport: 0x5000,
+                },
+                250_000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        for subscriber in &processor.subscribers {
+            subscriber.dispatch(&event);
+        }
+
+        let received = stream.next().await.expect("event delivered to stream");
+        assert_eq!(received.latency_ns, 250_000);
+        assert_eq!(seen.load(Ordering::Relaxed), 250_000);
+    }
+
+    #[test]
+    fn test_sampler_keeps_one_in_n() {
+        use probe_common::constants::{EVENT_TYPE_RECV, EVENT_TYPE_SEND};
+
+        let event = |event_type: u8, sport: u16| LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 42,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a,
+                    daddr: 0x0200000a,
+                    sport,
+                    dport: 0x901f,
+                },
+                250_000,
+                event_type,
+            )
+        };
+        let recv = event(EVENT_TYPE_RECV, 0x3930);
+
+        let mut sampler = Sampler::new(3, BTreeMap::new(), SampleMode::Event);
+        let kept = (0..9).filter(|_| sampler.sample(&recv)).count();
+        assert_eq!(kept, 3);
+
+        let mut all = Sampler::new(1, BTreeMap::new(), SampleMode::Event);
+        assert!((0..5).all(|_| all.sample(&recv)));
+
+        // Rare event types are not starved by a rate meant for frequent ones
+        let mut sampler = Sampler::new(1, BTreeMap::from([(EVENT_TYPE_RECV, 100)]), SampleMode::Event);
+        let recvs = (0..1000).filter(|_| sampler.sample(&recv)).count();
+        let send = event(EVENT_TYPE_SEND, 0x3930);
+        let sends = (0..10).filter(|_| sampler.sample(&send)).count();
+        assert_eq!((recvs, sends), (10, 10));
+
+        // Flow sampling keeps all or none of a connection's events, from
+        // either end, and about 1 in N connections
+        let mut sampler = Sampler::new(10, BTreeMap::new(), SampleMode::Flow);
+        let kept_flows = (0..10_000u16)
+            .filter(|&port| {
+                let kept = sampler.sample(&event(EVENT_TYPE_RECV, port));
+                assert!((0..5).all(|_| sampler.sample(&event(EVENT_TYPE_SEND, port)) == kept));
+                let mut reverse = event(EVENT_TYPE_RECV, 0x901f);
+                reverse.key = ConnectionKey {
+                    saddr: 0x0200000a,
+                    daddr: 0x0100000a,
+                    sport: 0x901f,
+                    dport: port,
+                };
+                assert_eq!(sampler.sample(&reverse), kept);
+                kept
+            })
+            .count();
+        assert!((800..1200).contains(&kept_flows), "{} flows kept", kept_flows);
+    }
+
+    #[test]
+    fn test_loopback_filter() {
+        let key = |saddr: [u8; 4], daddr: [u8; 4]| ConnectionKey {
+            saddr: u32::from_ne_bytes(saddr),
+            daddr: u32::from_ne_bytes(daddr),
+            sport: 0x3930,
+            dport: 0x901f,
+        };
+        let sidecar = key([127, 0, 0, 1], [127, 0, 0, 6]);
+        let pod = key([10, 0, 0, 1], [10, 0, 0, 2]);
+
+        assert!(LoopbackFilter::All.allows(&sidecar) && LoopbackFilter::All.allows(&pod));
+        assert!(!LoopbackFilter::Exclude.allows(&sidecar) && LoopbackFilter::Exclude.allows(&pod));
+        assert!(LoopbackFilter::Only.allows(&sidecar) && !LoopbackFilter::Only.allows(&pod));
+        assert_eq!(crate::types::traffic_class(&pod), crate::types::TRAFFIC_CLASS_EXTERNAL);
+    }
+
+    #[test]
+    fn test_decode_event() {
+        let key = ConnectionKey {
+            saddr: 0x0100000a,
+            daddr: 0x0200000a,
+            sport: 0x3930,
+            dport: 0x901f,
+        };
+        // Records are not aligned in the perf buffer
+        let mut record = vec![0u8];
+        record.extend_from_slice(&key.saddr.to_ne_bytes());
+        record.extend_from_slice(&key.daddr.to_ne_bytes());
+        record.extend_from_slice(&key.sport.to_ne_bytes());
+        record.extend_from_slice(&key.dport.to_ne_bytes());
+        assert_eq!(decode_event::<ConnectionKey>(&record[1..]), Some(key));
+        assert_eq!(decode_event::<ConnectionKey>(&record[2..]), None);
+    }
+
+    #[test]
+    fn test_pin_current_thread() {
+        std::thread::spawn(|| {
+            let cpu = unsafe { libc::sched_getcpu() } as u32;
+            pin_current_thread(cpu).unwrap();
+
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            let size = std::mem::size_of::<libc::cpu_set_t>();
+            assert_eq!(unsafe { libc::sched_getaffinity(0, size, &mut set) }, 0);
+            assert_eq!(unsafe { libc::CPU_COUNT(&set) }, 1);
+            assert!(unsafe { libc::CPU_ISSET(cpu as usize, &set) });
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dedicated_reader_runtime_drops_in_async_context() {
+        let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+        let mut processor = EventProcessor::new(collector, 1, false);
+
+        processor
+            .set_reader_placement(ReaderPlacement::Dedicated(2))
+            .unwrap();
+        let handle = processor.reader_runtime.handle().unwrap().clone();
+        assert_eq!(handle.spawn(async { 7 }).await.unwrap(), 7);
+
+        drop(processor);
+    }
+
+    #[test]
+    fn test_perf_buffer_options_validation() {
+        assert!(PerfBufferOptions::default().validate().is_ok());
+
+        let options = PerfBufferOptions {
+            pages: Some(64),
+            read_buffers: 128,
+            wakeup_events: 32,
+        };
+        assert!(options.validate().is_ok());
+
+        assert!(PerfBufferOptions { pages: Some(48), ..options }.validate().is_err());
+        assert!(PerfBufferOptions { read_buffers: 0, ..options }.validate().is_err());
+        assert!(PerfBufferOptions { wakeup_events: 0, ..options }.validate().is_err());
+
+        let pages = PerfBufferOptions::pages_for_events(100_000);
+        assert!(pages.is_power_of_two());
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        assert!(pages * page_size >= 100_000 * std::mem::size_of::<LatencyEvent>());
+        assert_eq!(PerfBufferOptions::pages_for_events(1), 1);
     }
 }
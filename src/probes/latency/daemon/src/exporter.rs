@@ -2,9 +2,15 @@
 //!
 //! Provides different exporters for metrics (JSON, Prometheus, etc.)
 
-use crate::types::LatencyMetrics;
+use crate::{
+    compress::{Compression, OutputWriter},
+    instance::INSTANCE_LABEL,
+    output::Output,
+    run::RUN_ID_LABEL,
+    types::{LatencyMetrics, Percentiles, ServiceErrors, HISTOGRAM_BOUNDS_US},
+};
 use anyhow::{Context, Result};
-use std::{fs::File, io::Write, path::PathBuf};
+use std::borrow::Cow;
 
 /// Trait for metrics exporters
 pub trait MetricsExporter {
@@ -27,14 +33,39 @@ pub enum ExporterType {
     Json,
     /// Prometheus format
     Prometheus,
+    /// OpenMetrics text format (see crate::openmetrics)
+    OpenMetrics,
     /// InfluxDB line protocol
     Influx,
+    /// Latency heatmap as CSV (one row per time bucket)
+    Heatmap,
+    /// Apache Arrow IPC file (see crate::arrow)
+    #[cfg(feature = "arrow")]
+    Arrow,
+    /// Protobuf LatencyMetrics message (see crate::proto)
+    #[cfg(feature = "proto")]
+    Protobuf,
+    /// Summary and top connections as aligned text tables
+    Table,
+    /// Summary and top connections as a Markdown document
+    Markdown,
 }
 
+/// Decimal places floats are rounded to in canonical JSON (nanoseconds,
+/// for the microsecond latencies)
+pub const CANONICAL_DECIMALS: i32 = 3;
+
 /// JSON exporter
+///
+/// Maps in the report are ordered by key, so identical runs produce the
+/// same document. Canonical output also sorts the fields of every object
+/// and rounds floats to CANONICAL_DECIMALS places, hiding the last-bit
+/// differences left by floating point summation.
 pub struct JsonExporter {
-    output_path: PathBuf,
+    output: Output,
     pretty: bool,
+    compression: Compression,
+    canonical: bool,
 }
 
 impl JsonExporter {
@@ -42,37 +73,84 @@ impl JsonExporter {
     ///
     /// # Arguments
     ///
-    /// * `output_path` - Path to output file
+    /// * `output` - Output file (`-` for stdout) or writer
     /// * `pretty` - Enable pretty-printing
-    pub fn new(output_path: PathBuf, pretty: bool) -> Self {
+    pub fn new(output: impl Into<Output>, pretty: bool) -> Self {
         Self {
-            output_path,
+            output: output.into(),
             pretty,
+            compression: Compression::None,
+            canonical: false,
+        }
+    }
+
+    /// Compress the report as it is written
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Round floats to CANONICAL_DECIMALS places, for reports compared
+    /// against golden files
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Serialize a value as configured
+    fn write<T: serde::Serialize>(&self, writer: &mut OutputWriter, value: &T) -> serde_json::Result<()> {
+        if self.pretty {
+            serde_json::to_writer_pretty(writer, value)
+        } else {
+            serde_json::to_writer(writer, value)
         }
     }
 }
 
+/// Round every float in a JSON document to CANONICAL_DECIMALS places
+///
+/// Negative zero becomes zero, so a rounded-away sign does not show up as a
+/// difference.
+pub fn canonicalize(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Number(number) if number.is_f64() => {
+            let scale = 10f64.powi(CANONICAL_DECIMALS);
+            let rounded = (number.as_f64().unwrap_or_default() * scale).round() / scale;
+            let rounded = if rounded == 0.0 { 0.0 } else { rounded };
+            if let Some(rounded) = serde_json::Number::from_f64(rounded) {
+                *number = rounded;
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(canonicalize),
+        serde_json::Value::Object(map) => map.values_mut().for_each(canonicalize),
+        _ => {}
+    }
+}
+
 impl MetricsExporter for JsonExporter {
     fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
-        let json = if self.pretty {
-            serde_json::to_string_pretty(metrics)?
-        } else {
-            serde_json::to_string(metrics)?
-        };
-
-        let mut file = File::create(&self.output_path)
-            .with_context(|| format!("Failed to create output file: {:?}", self.output_path))?;
+        // Serialize straight into the (compressing) writer rather than
+        // building the whole document in memory first
+        let mut writer = OutputWriter::new(self.output.create()?, self.compression)?;
 
-        file.write_all(json.as_bytes())
-            .with_context(|| format!("Failed to write to output file: {:?}", self.output_path))?;
+        if self.canonical {
+            let mut value = serde_json::to_value(metrics).context("Failed to serialize metrics")?;
+            canonicalize(&mut value);
+            self.write(&mut writer, &value)
+        } else {
+            self.write(&mut writer, metrics)
+        }
+        .with_context(|| format!("Failed to write to output {}", self.output))?;
 
-        Ok(())
+        writer
+            .finish()
+            .with_context(|| format!("Failed to write to output {}", self.output))
     }
 }
 
 /// Prometheus exporter
 pub struct PrometheusExporter {
-    output_path: PathBuf,
+    output: Output,
 }
 
 impl PrometheusExporter {
@@ -80,13 +158,13 @@ impl PrometheusExporter {
     ///
     /// # Arguments
     ///
-    /// * `output_path` - Path to output file
-    pub fn new(output_path: PathBuf) -> Self {
-        Self { output_path }
+    /// * `output` - Output file (`-` for stdout) or writer
+    pub fn new(output: impl Into<Output>) -> Self {
+        Self { output: output.into() }
     }
 
     /// Convert metrics to Prometheus format
-    fn to_prometheus_format(metrics: &LatencyMetrics) -> String {
+    pub fn to_prometheus_format(metrics: &LatencyMetrics) -> String {
         let mut output = String::new();
 
         // Total events
@@ -95,6 +173,12 @@ impl PrometheusExporter {
         output.push_str(&format!("latency_probe_events_total {}\n", metrics.total_events));
         output.push('\n');
 
+        // Lost events
+        output.push_str("# HELP latency_probe_lost_events_total Events lost to full perf buffers\n");
+        output.push_str("# TYPE latency_probe_lost_events_total counter\n");
+        output.push_str(&format!("latency_probe_lost_events_total {}\n", metrics.lost_events));
+        output.push('\n');
+
         // Duration
         output.push_str("# HELP latency_probe_duration_seconds Duration of collection period\n");
         output.push_str("# TYPE latency_probe_duration_seconds gauge\n");
@@ -112,6 +196,266 @@ impl PrometheusExporter {
         output.push_str(&format!("latency_probe_latency_microseconds{{percentile=\"0.999\"}} {}\n", metrics.percentiles.p999));
         output.push('\n');
 
+        // Microbursts
+        output.push_str("# HELP latency_probe_microbursts_total Runs of 10ms buckets with events above a multiple of the median rate\n");
+        output.push_str("# TYPE latency_probe_microbursts_total counter\n");
+        output.push_str(&format!("latency_probe_microbursts_total {}\n", metrics.bursts.bursts));
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_microburst_duration_milliseconds Time spent in microbursts\n");
+        output.push_str("# TYPE latency_probe_microburst_duration_milliseconds gauge\n");
+        output.push_str(&format!("latency_probe_microburst_duration_milliseconds{{stat=\"total\"}} {}\n", metrics.bursts.total_duration_ms));
+        output.push_str(&format!("latency_probe_microburst_duration_milliseconds{{stat=\"max\"}} {}\n", metrics.bursts.max_duration_ms));
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_microburst_rate_per_second Event rate at the median and at the peak of microbursts\n");
+        output.push_str("# TYPE latency_probe_microburst_rate_per_second gauge\n");
+        output.push_str(&format!("latency_probe_microburst_rate_per_second{{stat=\"median\"}} {}\n", metrics.bursts.median_rate));
+        output.push_str(&format!("latency_probe_microburst_rate_per_second{{stat=\"peak\"}} {}\n", metrics.bursts.peak_rate));
+        output.push('\n');
+
+        // Tail causes
+        if let Some(tail) = &metrics.tail {
+            output.push_str("# HELP latency_probe_tail_events Events above p99 by what coincided with them\n");
+            output.push_str("# TYPE latency_probe_tail_events gauge\n");
+            output.push_str(&format!("latency_probe_tail_events{{cause=\"packet_drop\"}} {}\n", tail.packet_drop));
+            output.push_str(&format!("latency_probe_tail_events{{cause=\"context_switch\"}} {}\n", tail.context_switch));
+            output.push_str(&format!("latency_probe_tail_events{{cause=\"unexplained\"}} {}\n", tail.unexplained));
+            output.push('\n');
+        }
+
+        // Jitter
+        output.push_str("# HELP latency_probe_jitter_microseconds RFC 3550 jitter between consecutive latencies of a connection\n");
+        output.push_str("# TYPE latency_probe_jitter_microseconds gauge\n");
+        output.push_str(&format!("latency_probe_jitter_microseconds{{stat=\"mean\"}} {}\n", metrics.jitter.mean_us));
+        output.push_str(&format!("latency_probe_jitter_microseconds{{stat=\"max\"}} {}\n", metrics.jitter.max_us));
+        output.push('\n');
+
+        // Per-namespace breakdown
+        output.push_str("# HELP latency_probe_netns_events_total Latency events by network namespace\n");
+        output.push_str("# TYPE latency_probe_netns_events_total counter\n");
+        for (netns, ns_metrics) in &metrics.namespaces {
+            output.push_str(&format!("latency_probe_netns_events_total{{netns=\"{}\"}} {}\n", label_value(netns), ns_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_netns_latency_microseconds Latency percentiles by network namespace\n");
+        output.push_str("# TYPE latency_probe_netns_latency_microseconds gauge\n");
+        for (netns, ns_metrics) in &metrics.namespaces {
+            output.push_str(&format!("latency_probe_netns_latency_microseconds{{netns=\"{}\",percentile=\"0.50\"}} {}\n", label_value(netns), ns_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_netns_latency_microseconds{{netns=\"{}\",percentile=\"0.99\"}} {}\n", label_value(netns), ns_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
+        // Per-pod breakdown
+        output.push_str("# HELP latency_probe_pod_events_total Latency events by pod\n");
+        output.push_str("# TYPE latency_probe_pod_events_total counter\n");
+        for (uid, pod) in &metrics.pods {
+            output.push_str(&format!(
+                "latency_probe_pod_events_total{{pod_uid=\"{}\",qos_class=\"{}\"}} {}\n",
+                label_value(uid), label_value(&pod.qos_class), pod.events
+            ));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_pod_latency_microseconds Latency percentiles by pod\n");
+        output.push_str("# TYPE latency_probe_pod_latency_microseconds gauge\n");
+        for (uid, pod) in &metrics.pods {
+            output.push_str(&format!("latency_probe_pod_latency_microseconds{{pod_uid=\"{}\",percentile=\"0.50\"}} {}\n", label_value(uid), pod.percentiles.p50));
+            output.push_str(&format!("latency_probe_pod_latency_microseconds{{pod_uid=\"{}\",percentile=\"0.99\"}} {}\n", label_value(uid), pod.percentiles.p99));
+        }
+        output.push('\n');
+
+        // DNS latency
+        output.push_str("# HELP latency_probe_dns_queries_total DNS queries that received a response\n");
+        output.push_str("# TYPE latency_probe_dns_queries_total counter\n");
+        output.push_str(&format!("latency_probe_dns_queries_total {}\n", metrics.dns_latency.queries));
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_dns_latency_microseconds DNS resolution latency percentiles\n");
+        output.push_str("# TYPE latency_probe_dns_latency_microseconds gauge\n");
+        output.push_str(&format!("latency_probe_dns_latency_microseconds{{percentile=\"0.50\"}} {}\n", metrics.dns_latency.percentiles.p50));
+        output.push_str(&format!("latency_probe_dns_latency_microseconds{{percentile=\"0.95\"}} {}\n", metrics.dns_latency.percentiles.p95));
+        output.push_str(&format!("latency_probe_dns_latency_microseconds{{percentile=\"0.99\"}} {}\n", metrics.dns_latency.percentiles.p99));
+        output.push('\n');
+
+        // Event rate range over the run
+        if let Some((min, max)) = metrics.event_rate.rate_range() {
+            output.push_str("# HELP latency_probe_event_rate_per_second Lowest and highest event rate over the time buckets\n");
+            output.push_str("# TYPE latency_probe_event_rate_per_second gauge\n");
+            output.push_str(&format!("latency_probe_event_rate_per_second{{stat=\"min\"}} {}\n", min));
+            output.push_str(&format!("latency_probe_event_rate_per_second{{stat=\"max\"}} {}\n", max));
+            output.push('\n');
+        }
+
+        // Throughput
+        output.push_str("# HELP latency_probe_bytes_total Bytes transferred across all connections\n");
+        output.push_str("# TYPE latency_probe_bytes_total counter\n");
+        output.push_str(&format!("latency_probe_bytes_total{{direction=\"sent\"}} {}\n", metrics.throughput.bytes_sent));
+        output.push_str(&format!("latency_probe_bytes_total{{direction=\"received\"}} {}\n", metrics.throughput.bytes_received));
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_throughput_bytes_per_second Bytes per second across all connections\n");
+        output.push_str("# TYPE latency_probe_throughput_bytes_per_second gauge\n");
+        output.push_str(&format!("latency_probe_throughput_bytes_per_second{{direction=\"sent\"}} {}\n", metrics.throughput.sent_bytes_per_second));
+        output.push_str(&format!("latency_probe_throughput_bytes_per_second{{direction=\"received\"}} {}\n", metrics.throughput.received_bytes_per_second));
+        output.push('\n');
+
+        // Per-service breakdown
+        output.push_str("# HELP latency_probe_service_events_total Latency events by service\n");
+        output.push_str("# TYPE latency_probe_service_events_total counter\n");
+        for (service, service_metrics) in &metrics.services {
+            output.push_str(&format!("latency_probe_service_events_total{{service=\"{}\"}} {}\n", label_value(service), service_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_service_latency_microseconds Latency percentiles by service\n");
+        output.push_str("# TYPE latency_probe_service_latency_microseconds gauge\n");
+        for (service, service_metrics) in &metrics.services {
+            output.push_str(&format!("latency_probe_service_latency_microseconds{{service=\"{}\",percentile=\"0.50\"}} {}\n", label_value(service), service_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_service_latency_microseconds{{service=\"{}\",percentile=\"0.99\"}} {}\n", label_value(service), service_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
+        // Per-protocol breakdown
+        output.push_str("# HELP latency_probe_protocol_events_total Latency events by transport protocol\n");
+        output.push_str("# TYPE latency_probe_protocol_events_total counter\n");
+        for (protocol, protocol_metrics) in &metrics.protocols {
+            output.push_str(&format!("latency_probe_protocol_events_total{{protocol=\"{}\"}} {}\n", label_value(protocol), protocol_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_protocol_latency_microseconds Latency percentiles by transport protocol\n");
+        output.push_str("# TYPE latency_probe_protocol_latency_microseconds gauge\n");
+        for (protocol, protocol_metrics) in &metrics.protocols {
+            output.push_str(&format!("latency_probe_protocol_latency_microseconds{{protocol=\"{}\",percentile=\"0.50\"}} {}\n", label_value(protocol), protocol_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_protocol_latency_microseconds{{protocol=\"{}\",percentile=\"0.99\"}} {}\n", label_value(protocol), protocol_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_protocol_histogram_bucket Latency histogram buckets by transport protocol\n");
+        output.push_str("# TYPE latency_probe_protocol_histogram_bucket gauge\n");
+        for (protocol, protocol_metrics) in &metrics.protocols {
+            for (bound, count) in HISTOGRAM_BOUNDS_US.iter().zip(protocol_metrics.histogram.counts()) {
+                output.push_str(&format!("latency_probe_protocol_histogram_bucket{{protocol=\"{}\",le=\"{}\"}} {}\n", label_value(protocol), bound, count));
+            }
+        }
+        output.push('\n');
+
+        // Per-class breakdown
+        output.push_str("# HELP latency_probe_traffic_class_events_total Latency events on loopback and external connections\n");
+        output.push_str("# TYPE latency_probe_traffic_class_events_total counter\n");
+        for (class, class_metrics) in &metrics.traffic_classes {
+            output.push_str(&format!("latency_probe_traffic_class_events_total{{class=\"{}\"}} {}\n", label_value(class), class_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_traffic_class_latency_microseconds Latency percentiles on loopback and external connections\n");
+        output.push_str("# TYPE latency_probe_traffic_class_latency_microseconds gauge\n");
+        for (class, class_metrics) in &metrics.traffic_classes {
+            output.push_str(&format!("latency_probe_traffic_class_latency_microseconds{{class=\"{}\",percentile=\"0.50\"}} {}\n", label_value(class), class_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_traffic_class_latency_microseconds{{class=\"{}\",percentile=\"0.99\"}} {}\n", label_value(class), class_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
+        // Per-locality breakdown
+        output.push_str("# HELP latency_probe_zone_events_total Latency events by zone locality\n");
+        output.push_str("# TYPE latency_probe_zone_events_total counter\n");
+        for (locality, zone_metrics) in &metrics.zones {
+            output.push_str(&format!("latency_probe_zone_events_total{{locality=\"{}\"}} {}\n", label_value(locality), zone_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_zone_latency_microseconds Latency percentiles by zone locality\n");
+        output.push_str("# TYPE latency_probe_zone_latency_microseconds gauge\n");
+        for (locality, zone_metrics) in &metrics.zones {
+            output.push_str(&format!("latency_probe_zone_latency_microseconds{{locality=\"{}\",percentile=\"0.50\"}} {}\n", label_value(locality), zone_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_zone_latency_microseconds{{locality=\"{}\",percentile=\"0.99\"}} {}\n", label_value(locality), zone_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
+        // Per-tenant breakdown
+        if !metrics.tenants.is_empty() {
+            output.push_str("# HELP latency_probe_tenant_events_total Latency events by tenant\n");
+            output.push_str("# TYPE latency_probe_tenant_events_total counter\n");
+            for (tenant, tenant_metrics) in &metrics.tenants {
+                output.push_str(&format!("latency_probe_tenant_events_total{{tenant=\"{}\"}} {}\n", label_value(tenant), tenant_metrics.events));
+            }
+            output.push('\n');
+
+            output.push_str("# HELP latency_probe_tenant_latency_microseconds Latency percentiles by tenant\n");
+            output.push_str("# TYPE latency_probe_tenant_latency_microseconds gauge\n");
+            for (tenant, tenant_metrics) in &metrics.tenants {
+                for (quantile, value) in [
+                    ("0.50", tenant_metrics.percentiles.p50),
+                    ("0.90", tenant_metrics.percentiles.p90),
+                    ("0.99", tenant_metrics.percentiles.p99),
+                    ("0.999", tenant_metrics.percentiles.p999),
+                ] {
+                    output.push_str(&format!("latency_probe_tenant_latency_microseconds{{tenant=\"{}\",percentile=\"{}\"}} {}\n", label_value(tenant), quantile, value));
+                }
+            }
+            output.push('\n');
+        }
+
+        // Per-phase breakdown
+        output.push_str("# HELP latency_probe_phase_events_total Latency events by connection phase\n");
+        output.push_str("# TYPE latency_probe_phase_events_total counter\n");
+        for (phase, phase_metrics) in &metrics.phases {
+            output.push_str(&format!("latency_probe_phase_events_total{{phase=\"{}\"}} {}\n", label_value(phase), phase_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_phase_latency_microseconds Latency percentiles by connection phase\n");
+        output.push_str("# TYPE latency_probe_phase_latency_microseconds gauge\n");
+        for (phase, phase_metrics) in &metrics.phases {
+            output.push_str(&format!("latency_probe_phase_latency_microseconds{{phase=\"{}\",percentile=\"0.50\"}} {}\n", label_value(phase), phase_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_phase_latency_microseconds{{phase=\"{}\",percentile=\"0.99\"}} {}\n", label_value(phase), phase_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
+        // Load generator latency
+        if let Some(client) = &metrics.client {
+            let reported = [
+                ("0.50", client.percentiles.p50),
+                ("0.90", client.percentiles.p90),
+                ("0.95", client.percentiles.p95),
+                ("0.99", client.percentiles.p99),
+                ("0.999", client.percentiles.p999),
+            ];
+            output.push_str("# HELP latency_probe_client_latency_microseconds Latency percentiles observed by the load generator\n");
+            output.push_str("# TYPE latency_probe_client_latency_microseconds gauge\n");
+            for (percentile, value) in reported.iter().filter_map(|&(p, v)| Some((p, v?))) {
+                output.push_str(&format!(
+                    "latency_probe_client_latency_microseconds{{tool=\"{}\",percentile=\"{}\"}} {}\n",
+                    label_value(&client.tool), percentile, value
+                ));
+            }
+            output.push('\n');
+
+            output.push_str("# HELP latency_probe_client_requests_total Requests sent by the load generator\n");
+            output.push_str("# TYPE latency_probe_client_requests_total counter\n");
+            output.push_str(&format!("latency_probe_client_requests_total{{tool=\"{}\"}} {}\n", label_value(&client.tool), client.requests));
+            output.push_str(&format!("latency_probe_client_errors_total{{tool=\"{}\"}} {}\n", label_value(&client.tool), client.errors));
+            output.push('\n');
+        }
+
+        // Per-status breakdown
+        output.push_str("# HELP latency_probe_http_status_events_total Latency events by HTTP status class of the response\n");
+        output.push_str("# TYPE latency_probe_http_status_events_total counter\n");
+        for (class, status_metrics) in &metrics.http_status {
+            output.push_str(&format!("latency_probe_http_status_events_total{{status_class=\"{}\"}} {}\n", label_value(class), status_metrics.events));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_http_status_latency_microseconds Latency percentiles by HTTP status class\n");
+        output.push_str("# TYPE latency_probe_http_status_latency_microseconds gauge\n");
+        for (class, status_metrics) in &metrics.http_status {
+            output.push_str(&format!("latency_probe_http_status_latency_microseconds{{status_class=\"{}\",percentile=\"0.50\"}} {}\n", label_value(class), status_metrics.percentiles.p50));
+            output.push_str(&format!("latency_probe_http_status_latency_microseconds{{status_class=\"{}\",percentile=\"0.99\"}} {}\n", label_value(class), status_metrics.percentiles.p99));
+        }
+        output.push('\n');
+
         // Histogram
         output.push_str("# HELP latency_probe_histogram_bucket Latency histogram buckets\n");
         output.push_str("# TYPE latency_probe_histogram_bucket gauge\n");
@@ -129,6 +473,8 @@ impl PrometheusExporter {
         output.push_str(&format!("latency_probe_events_by_type{{type=\"tcp_sendmsg\"}} {}\n", metrics.event_type_breakdown.tcp_sendmsg));
         output.push_str(&format!("latency_probe_events_by_type{{type=\"tcp_recvmsg\"}} {}\n", metrics.event_type_breakdown.tcp_recvmsg));
         output.push_str(&format!("latency_probe_events_by_type{{type=\"tcp_cleanup_rbuf\"}} {}\n", metrics.event_type_breakdown.tcp_cleanup_rbuf));
+        output.push_str(&format!("latency_probe_events_by_type{{type=\"tcp_probe\"}} {}\n", metrics.event_type_breakdown.tcp_probe));
+        output.push_str(&format!("latency_probe_events_by_type{{type=\"quic\"}} {}\n", metrics.event_type_breakdown.quic));
         output.push('\n');
 
         // Connection count
@@ -147,7 +493,7 @@ impl PrometheusExporter {
         output.push_str("# HELP latency_probe_packet_drops_by_location Packet drops by location\n");
         output.push_str("# TYPE latency_probe_packet_drops_by_location counter\n");
         for (location, count) in &metrics.packet_drops.drops_by_location {
-            output.push_str(&format!("latency_probe_packet_drops_by_location{{location=\"{}\"}} {}\n", location, count));
+            output.push_str(&format!("latency_probe_packet_drops_by_location{{location=\"{}\"}} {}\n", label_value(location), count));
         }
         output.push('\n');
 
@@ -155,7 +501,49 @@ impl PrometheusExporter {
         output.push_str("# HELP latency_probe_packet_drops_by_protocol Packet drops by protocol\n");
         output.push_str("# TYPE latency_probe_packet_drops_by_protocol counter\n");
         for (protocol, count) in &metrics.packet_drops.drops_by_protocol {
-            output.push_str(&format!("latency_probe_packet_drops_by_protocol{{protocol=\"{}\"}} {}\n", protocol, count));
+            output.push_str(&format!("latency_probe_packet_drops_by_protocol{{protocol=\"{}\"}} {}\n", label_value(protocol), count));
+        }
+        output.push('\n');
+
+        // Resets and retransmission timeouts
+        output.push_str("# HELP latency_probe_connection_resets_total TCP resets by direction\n");
+        output.push_str("# TYPE latency_probe_connection_resets_total counter\n");
+        output.push_str(&format!("latency_probe_connection_resets_total{{direction=\"received\"}} {}\n", metrics.errors.resets_received));
+        output.push_str(&format!("latency_probe_connection_resets_total{{direction=\"sent\"}} {}\n", metrics.errors.resets_sent));
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_retransmission_timeouts_total TCP retransmission timeouts\n");
+        output.push_str("# TYPE latency_probe_retransmission_timeouts_total counter\n");
+        output.push_str(&format!("latency_probe_retransmission_timeouts_total {}\n", metrics.errors.timeouts));
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_service_connection_resets_total TCP resets by service and direction\n");
+        output.push_str("# TYPE latency_probe_service_connection_resets_total counter\n");
+        for (service, errors) in &metrics.errors.services {
+            output.push_str(&format!("latency_probe_service_connection_resets_total{{service=\"{}\",direction=\"received\"}} {}\n", label_value(service), errors.resets_received));
+            output.push_str(&format!("latency_probe_service_connection_resets_total{{service=\"{}\",direction=\"sent\"}} {}\n", label_value(service), errors.resets_sent));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_service_retransmission_timeouts_total TCP retransmission timeouts by service\n");
+        output.push_str("# TYPE latency_probe_service_retransmission_timeouts_total counter\n");
+        for (service, errors) in &metrics.errors.services {
+            output.push_str(&format!("latency_probe_service_retransmission_timeouts_total{{service=\"{}\"}} {}\n", label_value(service), errors.timeouts));
+        }
+        output.push('\n');
+
+        // Accept queues
+        output.push_str("# HELP latency_probe_listen_overflows_total Connection requests dropped because an accept queue was full, by port\n");
+        output.push_str("# TYPE latency_probe_listen_overflows_total counter\n");
+        for (port, listener) in &metrics.listeners {
+            output.push_str(&format!("latency_probe_listen_overflows_total{{port=\"{}\"}} {}\n", port, listener.overflows));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_listen_syn_queue_full_total SYNs received while a SYN queue was full, by port\n");
+        output.push_str("# TYPE latency_probe_listen_syn_queue_full_total counter\n");
+        for (port, listener) in &metrics.listeners {
+            output.push_str(&format!("latency_probe_listen_syn_queue_full_total{{port=\"{}\"}} {}\n", port, listener.syn_queue_full));
         }
         output.push('\n');
 
@@ -184,7 +572,7 @@ impl PrometheusExporter {
         output.push_str("# HELP latency_probe_connection_states Connection state breakdown\n");
         output.push_str("# TYPE latency_probe_connection_states counter\n");
         for (state, count) in &metrics.connection_states.states_breakdown {
-            output.push_str(&format!("latency_probe_connection_states{{state=\"{}\"}} {}\n", state, count));
+            output.push_str(&format!("latency_probe_connection_states{{state=\"{}\"}} {}\n", label_value(state), count));
         }
         output.push('\n');
 
@@ -209,27 +597,165 @@ impl PrometheusExporter {
         output.push_str(&format!("latency_probe_xdp_packets{{protocol=\"ipv4\"}} {}\n", metrics.xdp_stats.ipv4_packets));
         output.push('\n');
 
-        output
+        // Probe health
+        output.push_str("# HELP latency_probe_map_fill_ratio Fraction of eBPF map capacity in use\n");
+        output.push_str("# TYPE latency_probe_map_fill_ratio gauge\n");
+        for map in &metrics.health.maps {
+            output.push_str(&format!("latency_probe_map_fill_ratio{{map=\"{}\"}} {}\n", label_value(&map.map), map.fill_ratio));
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_map_split_entries Entries of per-CPU maps with values on several CPUs\n");
+        output.push_str("# TYPE latency_probe_map_split_entries gauge\n");
+        for map in &metrics.health.maps {
+            if let Some(split) = map.split_entries {
+                output.push_str(&format!("latency_probe_map_split_entries{{map=\"{}\"}} {}\n", label_value(&map.map), split));
+            }
+        }
+        output.push('\n');
+
+        output.push_str("# HELP latency_probe_event_loss_ratio Fraction of events lost to full perf buffers\n");
+        output.push_str("# TYPE latency_probe_event_loss_ratio gauge\n");
+        output.push_str(&format!("latency_probe_event_loss_ratio {}\n", metrics.health.event_loss_ratio));
+        output.push('\n');
+
+        // Events the probe discarded itself
+        for (name, help, value) in metrics.health.internal.fields() {
+            output.push_str(&format!("# HELP latency_probe_internal_{}_total {}\n", name, help));
+            output.push_str(&format!("# TYPE latency_probe_internal_{}_total counter\n", name));
+            output.push_str(&format!("latency_probe_internal_{}_total {}\n", name, value));
+        }
+        output.push('\n');
+
+        // Queues between readers and the collector
+        if let Some(channel) = &metrics.health.channel {
+            output.push_str("# HELP latency_probe_channel_dropped_events_total Events dropped by the reader queue overflow policy\n");
+            output.push_str("# TYPE latency_probe_channel_dropped_events_total counter\n");
+            output.push_str(&format!("latency_probe_channel_dropped_events_total{{policy=\"{}\"}} {}\n", label_value(&channel.policy), channel.dropped_events));
+            output.push_str("# HELP latency_probe_channel_blocked_waits_total Times a reader waited for room in its queue\n");
+            output.push_str("# TYPE latency_probe_channel_blocked_waits_total counter\n");
+            output.push_str(&format!("latency_probe_channel_blocked_waits_total {}\n", channel.blocked_waits));
+            output.push_str("# HELP latency_probe_channel_max_depth Most events queued for one reader at once\n");
+            output.push_str("# TYPE latency_probe_channel_max_depth gauge\n");
+            output.push_str(&format!("latency_probe_channel_max_depth {}\n", channel.max_depth));
+            output.push('\n');
+        }
+
+        // Exporter thread
+        if let Some(export) = &metrics.health.export {
+            for (name, kind, help, value) in [
+                ("export_queue_depth", "gauge", "Reports waiting for the exporter thread", export.queue_depth as f64),
+                ("export_queue_max_depth", "gauge", "Most reports waiting for the exporter thread at once", export.max_queue_depth as f64),
+                ("export_dropped_snapshots_total", "counter", "Snapshots dropped because the export queue was full", export.dropped_snapshots as f64),
+                ("export_latency_seconds", "gauge", "Time the latest export took", export.last_latency_ms / 1000.0),
+                ("export_max_latency_seconds", "gauge", "Longest export", export.max_latency_ms / 1000.0),
+            ] {
+                output.push_str(&format!("# HELP latency_probe_{} {}\n", name, help));
+                output.push_str(&format!("# TYPE latency_probe_{} {}\n", name, kind));
+                output.push_str(&format!("latency_probe_{} {}\n", name, value));
+            }
+            output.push('\n');
+        }
+
+        // Receives reported twice
+        output.push_str("# HELP latency_probe_duplicate_events_total Events dropped as duplicates of a receive\n");
+        output.push_str("# TYPE latency_probe_duplicate_events_total counter\n");
+        output.push_str(&format!("latency_probe_duplicate_events_total{{policy=\"{}\"}} {}\n", label_value(&metrics.dedup.policy), metrics.dedup.duplicates));
+        output.push('\n');
+
+        if let Some(cleanup) = &metrics.dedup.cleanup {
+            output.push_str("# HELP latency_probe_cleanup_latency_microseconds tcp_cleanup_rbuf latency percentiles\n");
+            output.push_str("# TYPE latency_probe_cleanup_latency_microseconds gauge\n");
+            output.push_str(&format!("latency_probe_cleanup_latency_microseconds{{percentile=\"0.50\"}} {}\n", cleanup.percentiles.p50));
+            output.push_str(&format!("latency_probe_cleanup_latency_microseconds{{percentile=\"0.95\"}} {}\n", cleanup.percentiles.p95));
+            output.push_str(&format!("latency_probe_cleanup_latency_microseconds{{percentile=\"0.99\"}} {}\n", cleanup.percentiles.p99));
+            output.push('\n');
+        }
+
+        // Latencies rejected as measurement errors
+        if let Some(bounds) = &metrics.latency_bounds {
+            output.push_str("# HELP latency_probe_rejected_latencies_total Latencies rejected outside the validity bounds\n");
+            output.push_str("# TYPE latency_probe_rejected_latencies_total counter\n");
+            output.push_str(&format!("latency_probe_rejected_latencies_total{{bound=\"min\"}} {}\n", bounds.below_min));
+            output.push_str(&format!("latency_probe_rejected_latencies_total{{bound=\"max\"}} {}\n", bounds.above_max));
+            output.push('\n');
+
+            output.push_str("# HELP latency_probe_latency_bound_nanoseconds Latency validity bounds\n");
+            output.push_str("# TYPE latency_probe_latency_bound_nanoseconds gauge\n");
+            output.push_str(&format!("latency_probe_latency_bound_nanoseconds{{bound=\"min\"}} {}\n", bounds.min_latency_ns));
+            output.push_str(&format!("latency_probe_latency_bound_nanoseconds{{bound=\"max\"}} {}\n", bounds.max_latency_ns));
+            output.push('\n');
+        }
+
+        // eBPF program overhead
+        if !metrics.program_stats.is_empty() {
+            output.push_str("# HELP latency_probe_program_run_time_ns_total Time spent running each eBPF program in nanoseconds\n");
+            output.push_str("# TYPE latency_probe_program_run_time_ns_total counter\n");
+            for program in &metrics.program_stats {
+                output.push_str(&format!("latency_probe_program_run_time_ns_total{{program=\"{}\"}} {}\n", label_value(&program.program), program.run_time_ns));
+            }
+            output.push('\n');
+
+            output.push_str("# HELP latency_probe_program_runs_total Number of times each eBPF program ran\n");
+            output.push_str("# TYPE latency_probe_program_runs_total counter\n");
+            for program in &metrics.program_stats {
+                output.push_str(&format!("latency_probe_program_runs_total{{program=\"{}\"}} {}\n", label_value(&program.program), program.run_count));
+            }
+            output.push('\n');
+        }
+
+        let output = match &metrics.run_id {
+            Some(run_id) => with_label(&output, RUN_ID_LABEL, run_id),
+            None => output,
+        };
+        match &metrics.instance {
+            Some(instance) => with_label(&output, INSTANCE_LABEL, instance),
+            None => output,
+        }
+    }
+}
+
+/// Label value with backslashes, double quotes and line breaks escaped, as
+/// the Prometheus text format requires
+fn label_value(value: &str) -> Cow<'_, str> {
+    if !value.contains(['\\', '"', '\n']) {
+        return Cow::Borrowed(value);
+    }
+    Cow::Owned(value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Add a label to every sample of a Prometheus text document
+fn with_label(text: &str, name: &str, value: &str) -> String {
+    let label = format!("{}=\"{}\"", name, label_value(value));
+    let mut output = String::with_capacity(text.len() + text.lines().count() * (label.len() + 3));
+    for line in text.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            output.push_str(line);
+        } else if let Some(end) = line.find([' ', '{']) {
+            let (metric, rest) = line.split_at(end);
+            match rest.strip_prefix('{') {
+                Some(labels) => output.push_str(&format!("{}{{{},{}", metric, label, labels)),
+                None => output.push_str(&format!("{}{{{}}}{}", metric, label, rest)),
+            }
+        } else {
+            output.push_str(line);
+        }
+        output.push('\n');
     }
+    output
 }
 
 impl MetricsExporter for PrometheusExporter {
     fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
         let prometheus_data = Self::to_prometheus_format(metrics);
 
-        let mut file = File::create(&self.output_path)
-            .with_context(|| format!("Failed to create output file: {:?}", self.output_path))?;
-
-        file.write_all(prometheus_data.as_bytes())
-            .with_context(|| format!("Failed to write to output file: {:?}", self.output_path))?;
-
-        Ok(())
+        self.output.write_all(prometheus_data.as_bytes())
     }
 }
 
 /// InfluxDB line protocol exporter
 pub struct InfluxExporter {
-    output_path: PathBuf,
+    output: Output,
     measurement: String,
 }
 
@@ -238,25 +764,34 @@ impl InfluxExporter {
     ///
     /// # Arguments
     ///
-    /// * `output_path` - Path to output file
+    /// * `output` - Output file (`-` for stdout) or writer
     /// * `measurement` - Measurement name for InfluxDB
-    pub fn new(output_path: PathBuf, measurement: String) -> Self {
+    pub fn new(output: impl Into<Output>, measurement: String) -> Self {
         Self {
-            output_path,
+            output: output.into(),
             measurement,
         }
     }
 
     /// Convert metrics to InfluxDB line protocol
-    fn to_influx_format(metrics: &LatencyMetrics, measurement: &str) -> String {
+    pub fn to_influx_format(metrics: &LatencyMetrics, measurement: &str) -> String {
         let mut output = String::new();
+        // Tag every point with the instance name and run ID
+        let mut measurement = match &metrics.instance {
+            Some(instance) => format!("{},{}={}", measurement, INSTANCE_LABEL, tag_value(instance)),
+            None => measurement.to_string(),
+        };
+        if let Some(run_id) = &metrics.run_id {
+            measurement.push_str(&format!(",{}={}", RUN_ID_LABEL, tag_value(run_id)));
+        }
         let timestamp = chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0);
 
         // Global metrics
         output.push_str(&format!(
-            "{},type=summary total_events={}i,duration_seconds={}i,connections={}i {}\n",
+            "{},type=summary total_events={}i,lost_events={}i,duration_seconds={}i,connections={}i {}\n",
             measurement,
             metrics.total_events,
+            metrics.lost_events,
             metrics.duration_seconds,
             metrics.connections.len(),
             timestamp
@@ -275,6 +810,194 @@ impl InfluxExporter {
             timestamp
         ));
 
+        // Jitter
+        output.push_str(&format!(
+            "{},type=jitter mean={},max={} {}\n",
+            measurement, metrics.jitter.mean_us, metrics.jitter.max_us, timestamp
+        ));
+
+        // Per-namespace breakdown
+        for (netns, ns_metrics) in &metrics.namespaces {
+            output.push_str(&format!(
+                "{},type=netns,netns={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(netns),
+                ns_metrics.events,
+                ns_metrics.avg_latency_us,
+                ns_metrics.percentiles.p50,
+                ns_metrics.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        // Per-pod breakdown
+        for (uid, pod) in &metrics.pods {
+            output.push_str(&format!(
+                "{},type=pod,pod_uid={},qos_class={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(uid),
+                tag_value(&pod.qos_class),
+                pod.events,
+                pod.avg_latency_us,
+                pod.percentiles.p50,
+                pod.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        // DNS latency
+        output.push_str(&format!(
+            "{},type=dns queries={}i,avg={},p50={},p95={},p99={} {}\n",
+            measurement,
+            metrics.dns_latency.queries,
+            metrics.dns_latency.avg_latency_us,
+            metrics.dns_latency.percentiles.p50,
+            metrics.dns_latency.percentiles.p95,
+            metrics.dns_latency.percentiles.p99,
+            timestamp
+        ));
+
+        // Event rate, one point per bucket at the bucket's start time
+        let rate_start = chrono::DateTime::parse_from_rfc3339(&metrics.event_rate.start)
+            .ok()
+            .and_then(|start| start.timestamp_nanos_opt());
+        if let Some(rate_start) = rate_start {
+            let resolution_ns = metrics.event_rate.resolution_ms as i64 * 1_000_000;
+            for (i, (events, rate)) in metrics.event_rate.events.iter().zip(metrics.event_rate.rates()).enumerate() {
+                output.push_str(&format!(
+                    "{},type=rate events={}i,rate={} {}\n",
+                    measurement,
+                    events,
+                    rate,
+                    rate_start + i as i64 * resolution_ns
+                ));
+            }
+        }
+
+        // Throughput
+        output.push_str(&format!(
+            "{},type=throughput bytes_sent={}i,bytes_received={}i,sent_per_second={},received_per_second={} {}\n",
+            measurement,
+            metrics.throughput.bytes_sent,
+            metrics.throughput.bytes_received,
+            metrics.throughput.sent_bytes_per_second,
+            metrics.throughput.received_bytes_per_second,
+            timestamp
+        ));
+
+        // Per-service breakdown
+        for (service, service_metrics) in &metrics.services {
+            output.push_str(&format!(
+                "{},type=service,service={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(service),
+                service_metrics.events,
+                service_metrics.avg_latency_us,
+                service_metrics.percentiles.p50,
+                service_metrics.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        // Per-class breakdown
+        for (class, class_metrics) in &metrics.traffic_classes {
+            output.push_str(&format!(
+                "{},type=traffic_class,class={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(class),
+                class_metrics.events,
+                class_metrics.avg_latency_us,
+                class_metrics.percentiles.p50,
+                class_metrics.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        // Per-locality breakdown
+        for (locality, zone_metrics) in &metrics.zones {
+            output.push_str(&format!(
+                "{},type=zone,locality={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(locality),
+                zone_metrics.events,
+                zone_metrics.avg_latency_us,
+                zone_metrics.percentiles.p50,
+                zone_metrics.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        // Per-tenant breakdown
+        for (tenant, tenant_metrics) in &metrics.tenants {
+            output.push_str(&format!(
+                "{},type=tenant,tenant={} events={}i,avg={},p50={},p90={},p99={},p999={} {}\n",
+                measurement,
+                tag_value(tenant),
+                tenant_metrics.events,
+                tenant_metrics.avg_latency_us,
+                tenant_metrics.percentiles.p50,
+                tenant_metrics.percentiles.p90,
+                tenant_metrics.percentiles.p99,
+                tenant_metrics.percentiles.p999,
+                timestamp
+            ));
+        }
+
+        // Per-phase breakdown
+        for (phase, phase_metrics) in &metrics.phases {
+            output.push_str(&format!(
+                "{},type=phase,phase={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(phase),
+                phase_metrics.events,
+                phase_metrics.avg_latency_us,
+                phase_metrics.percentiles.p50,
+                phase_metrics.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        // Per-protocol breakdown
+        for (protocol, protocol_metrics) in &metrics.protocols {
+            output.push_str(&format!(
+                "{},type=protocol,protocol={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(protocol),
+                protocol_metrics.events,
+                protocol_metrics.avg_latency_us,
+                protocol_metrics.percentiles.p50,
+                protocol_metrics.percentiles.p99,
+                timestamp
+            ));
+            let histogram = &protocol_metrics.histogram;
+            output.push_str(&format!(
+                "{},type=protocol_histogram,protocol={} bucket_0_1ms={}i,bucket_1_5ms={}i,bucket_5_10ms={}i,bucket_10_50ms={}i,bucket_50_100ms={}i,bucket_100ms_plus={}i {}\n",
+                measurement,
+                tag_value(protocol),
+                histogram.bucket_0_1ms,
+                histogram.bucket_1_5ms,
+                histogram.bucket_5_10ms,
+                histogram.bucket_10_50ms,
+                histogram.bucket_50_100ms,
+                histogram.bucket_100ms_plus,
+                timestamp
+            ));
+        }
+
+        // Per-status breakdown
+        for (class, status_metrics) in &metrics.http_status {
+            output.push_str(&format!(
+                "{},type=http_status,status_class={} events={}i,avg={},p50={},p99={} {}\n",
+                measurement,
+                tag_value(class),
+                status_metrics.events,
+                status_metrics.avg_latency_us,
+                status_metrics.percentiles.p50,
+                status_metrics.percentiles.p99,
+                timestamp
+            ));
+        }
+
         // Histogram
         output.push_str(&format!(
             "{},type=histogram bucket_0_1ms={}i,bucket_1_5ms={}i,bucket_5_10ms={}i,bucket_10_50ms={}i,bucket_50_100ms={}i,bucket_100ms_plus={}i {}\n",
@@ -290,11 +1013,13 @@ impl InfluxExporter {
 
         // Event types
         output.push_str(&format!(
-            "{},type=events tcp_sendmsg={}i,tcp_recvmsg={}i,tcp_cleanup_rbuf={}i {}\n",
+            "{},type=events tcp_sendmsg={}i,tcp_recvmsg={}i,tcp_cleanup_rbuf={}i,tcp_probe={}i,quic={}i {}\n",
             measurement,
             metrics.event_type_breakdown.tcp_sendmsg,
             metrics.event_type_breakdown.tcp_recvmsg,
             metrics.event_type_breakdown.tcp_cleanup_rbuf,
+            metrics.event_type_breakdown.tcp_probe,
+            metrics.event_type_breakdown.quic,
             timestamp
         ));
 
@@ -310,7 +1035,7 @@ impl InfluxExporter {
         for (location, count) in &metrics.packet_drops.drops_by_location {
             output.push_str(&format!(
                 "{},type=packet_drop_location,location={} count={}i {}\n",
-                measurement, location, count, timestamp
+                measurement, tag_value(location), count, timestamp
             ));
         }
 
@@ -318,7 +1043,25 @@ impl InfluxExporter {
         for (protocol, count) in &metrics.packet_drops.drops_by_protocol {
             output.push_str(&format!(
                 "{},type=packet_drop_protocol,protocol={} count={}i {}\n",
-                measurement, protocol, count, timestamp
+                measurement, tag_value(protocol), count, timestamp
+            ));
+        }
+
+        // Resets and retransmission timeouts
+        output.push_str(&format!(
+            "{},type=errors resets_received={}i,resets_sent={}i,timeouts={}i,resets_per_second={},timeouts_per_second={} {}\n",
+            measurement,
+            metrics.errors.resets_received,
+            metrics.errors.resets_sent,
+            metrics.errors.timeouts,
+            metrics.errors.resets_per_second,
+            metrics.errors.timeouts_per_second,
+            timestamp
+        ));
+        for (service, errors) in &metrics.errors.services {
+            output.push_str(&format!(
+                "{},type=service_errors,service={} resets_received={}i,resets_sent={}i,timeouts={}i {}\n",
+                measurement, tag_value(service), errors.resets_received, errors.resets_sent, errors.timeouts, timestamp
             ));
         }
 
@@ -337,7 +1080,7 @@ impl InfluxExporter {
         for (state, count) in &metrics.connection_states.states_breakdown {
             output.push_str(&format!(
                 "{},type=connection_state,state={} count={}i {}\n",
-                measurement, state, count, timestamp
+                measurement, tag_value(state), count, timestamp
             ));
         }
 
@@ -364,28 +1107,830 @@ impl InfluxExporter {
             timestamp
         ));
 
-        output
+        // Probe health
+        output.push_str(&format!(
+            "{},type=health event_loss_ratio={} {}\n",
+            measurement, metrics.health.event_loss_ratio, timestamp
+        ));
+        for map in &metrics.health.maps {
+            output.push_str(&format!(
+                "{},type=map_health,map={} entries={}i,max_entries={}i,fill_ratio={} {}\n",
+                measurement, tag_value(&map.map), map.entries, map.max_entries, map.fill_ratio, timestamp
+            ));
+        }
+
+        output.push_str(&format!(
+            "{},type=dedup,policy={} duplicates={}i {}\n",
+            measurement, tag_value(&metrics.dedup.policy), metrics.dedup.duplicates, timestamp
+        ));
+        if let Some(cleanup) = &metrics.dedup.cleanup {
+            output.push_str(&format!(
+                "{},type=cleanup events={}i,avg={},p50={},p95={},p99={} {}\n",
+                measurement,
+                cleanup.events,
+                cleanup.avg_latency_us,
+                cleanup.percentiles.p50,
+                cleanup.percentiles.p95,
+                cleanup.percentiles.p99,
+                timestamp
+            ));
+        }
+
+        if let Some(bounds) = &metrics.latency_bounds {
+            output.push_str(&format!(
+                "{},type=latency_bounds min_ns={}i,max_ns={}i,below_min={}i,above_max={}i {}\n",
+                measurement, bounds.min_latency_ns, bounds.max_latency_ns, bounds.below_min, bounds.above_max, timestamp
+            ));
+        }
+
+        // eBPF program overhead
+        for program in &metrics.program_stats {
+            output.push_str(&format!(
+                "{},type=program,program={} run_time_ns={}i,run_count={}i,avg_run_time_ns={} {}\n",
+                measurement,
+                tag_value(&program.program),
+                program.run_time_ns,
+                program.run_count,
+                program.avg_run_time_ns,
+                timestamp
+            ));
+        }
+
+        representable_lines(&output)
+    }
+}
+
+/// Tag value with commas, equals signs and spaces escaped, as the line
+/// protocol requires; line breaks cannot be escaped and become spaces
+fn tag_value(value: &str) -> Cow<'_, str> {
+    if !value.contains([',', '=', ' ', '\\', '\n', '\r']) {
+        return Cow::Borrowed(value);
     }
+    let mut escaped = String::with_capacity(value.len() + 4);
+    for c in value.chars() {
+        match c {
+            ',' | '=' | ' ' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push_str("\\ "),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Split at the separators not escaped by a backslash
+fn split_unescaped(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if c == separator && !escaped {
+            parts.push(&text[start..i]);
+            start = i + 1;
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Drop what the line protocol cannot represent from a document
+///
+/// There is no representation for NaN and infinite fields or for empty tag
+/// values, and one of them gets the whole line rejected, so they are
+/// dropped. Lines left without fields are dropped.
+fn representable_lines(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for line in text.lines() {
+        // Series, fields and timestamp, separated by unescaped spaces
+        let [series, fields, timestamp] = split_unescaped(line, ' ')[..] else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+
+        let series: Vec<&str> = split_unescaped(series, ',')
+            .into_iter()
+            .enumerate()
+            .filter(|(i, tag)| *i == 0 || split_unescaped(tag, '=').get(1).is_some_and(|value| !value.is_empty()))
+            .map(|(_, tag)| tag)
+            .collect();
+        let fields: Vec<&str> = fields
+            .split(',')
+            .filter(|field| {
+                let value = field.rsplit_once('=').map_or("", |(_, value)| value);
+                !value.parse::<f64>().is_ok_and(|value| !value.is_finite())
+            })
+            .collect();
+        if fields.is_empty() {
+            continue;
+        }
+        output.push_str(&format!("{} {} {}\n", series.join(","), fields.join(","), timestamp));
+    }
+    output
 }
 
 impl MetricsExporter for InfluxExporter {
     fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
         let influx_data = Self::to_influx_format(metrics, &self.measurement);
 
-        let mut file = File::create(&self.output_path)
-            .with_context(|| format!("Failed to create output file: {:?}", self.output_path))?;
+        self.output.write_all(influx_data.as_bytes())
+    }
+}
 
-        file.write_all(influx_data.as_bytes())
-            .with_context(|| format!("Failed to write to output file: {:?}", self.output_path))?;
+/// Latency heatmap exporter
+///
+/// Writes one CSV row per time bucket: the bucket's start time, then the
+/// count in each latency bucket. Columns are named by their upper bound in
+/// microseconds, which Grafana's heatmap panel reads as bucket bounds
+/// (e.g. loaded with the CSV or Infinity data source).
+pub struct HeatmapExporter {
+    output: Output,
+}
 
-        Ok(())
+impl HeatmapExporter {
+    /// Create a new heatmap exporter
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Output file (`-` for stdout) or writer
+    pub fn new(output: impl Into<Output>) -> Self {
+        Self { output: output.into() }
     }
+
+    /// Convert the metrics' heatmap to CSV
+    ///
+    /// Rows start with the run ID if the report has one.
+    fn to_csv(metrics: &LatencyMetrics) -> String {
+        let run_id = metrics.run_id.as_deref().map(|run_id| format!("{},", run_id));
+        let mut output = format!(
+            "{}time,{}\n",
+            run_id.as_ref().map_or("", |_| "run_id,"),
+            HISTOGRAM_BOUNDS_US.join(",")
+        );
+
+        let heatmap = &metrics.heatmap;
+        let Ok(start) = chrono::DateTime::parse_from_rfc3339(&heatmap.start) else {
+            return output;
+        };
+        for (i, histogram) in heatmap.intervals.iter().enumerate() {
+            let time = start + chrono::Duration::milliseconds((i as u64 * heatmap.resolution_ms) as i64);
+            let counts: Vec<String> = histogram.counts().iter().map(u64::to_string).collect();
+            output.push_str(&format!(
+                "{}{},{}\n",
+                run_id.as_deref().unwrap_or_default(),
+                time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                counts.join(",")
+            ));
+        }
+
+        output
+    }
+}
+
+impl MetricsExporter for HeatmapExporter {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let csv = Self::to_csv(metrics);
+
+        self.output.write_all(csv.as_bytes())
+    }
+}
+
+/// Style of the summary exporter's output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryStyle {
+    /// Aligned plain text tables, for terminals and run logs
+    Table,
+    /// Markdown tables, for pasting into issues
+    Markdown,
+}
+
+/// Column of a summary table
+struct Column {
+    name: &'static str,
+    /// Right-align the column (numbers)
+    right: bool,
+}
+
+const fn left(name: &'static str) -> Column {
+    Column { name, right: false }
+}
+
+const fn right(name: &'static str) -> Column {
+    Column { name, right: true }
+}
+
+/// Most recent trajectory intervals drawn in the summary
+const SPARKLINE_POINTS: usize = 60;
+
+/// Human-readable summary exporter
+///
+/// Renders the summary, latency percentiles, histogram, per-service
+/// latency, and the connections with the most events as tables, either
+/// aligned for a terminal or as Markdown.
+pub struct SummaryExporter {
+    output: Output,
+    style: SummaryStyle,
+    top_connections: usize,
+}
+
+impl SummaryExporter {
+    /// Create a new summary exporter
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Output file (`-` for stdout) or writer
+    /// * `style` - Plain text or Markdown tables
+    /// * `top_connections` - Number of connections listed, busiest first
+    pub fn new(output: impl Into<Output>, style: SummaryStyle, top_connections: usize) -> Self {
+        Self {
+            output: output.into(),
+            style,
+            top_connections,
+        }
+    }
+
+    /// Render the summary document
+    fn to_summary(metrics: &LatencyMetrics, style: SummaryStyle, top_connections: usize) -> String {
+        let mut output = String::new();
+        let heading = |output: &mut String, title: &str| match style {
+            SummaryStyle::Markdown => output.push_str(&format!("## {}\n\n", title)),
+            SummaryStyle::Table => output.push_str(&format!("{}\n{}\n", title, "-".repeat(title.len()))),
+        };
+
+        match style {
+            SummaryStyle::Markdown => output.push_str("# Latency Probe Summary\n\n"),
+            SummaryStyle::Table => output.push_str("Latency Probe Summary\n=====================\n\n"),
+        }
+
+        let mut overview = vec![
+            vec!["Timestamp".to_string(), metrics.timestamp.clone()],
+            vec!["Duration".to_string(), format!("{} s", metrics.duration_seconds)],
+            vec!["Total events".to_string(), metrics.total_events.to_string()],
+            vec!["Lost events".to_string(), metrics.lost_events.to_string()],
+            vec!["Connections".to_string(), metrics.connections.len().to_string()],
+        ];
+        if !metrics.labels.is_empty() {
+            let labels: Vec<String> = metrics.labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            overview.insert(1, vec!["Labels".to_string(), labels.join(", ")]);
+        }
+        if let Some(instance) = &metrics.instance {
+            overview.insert(1, vec!["Instance".to_string(), instance.clone()]);
+        }
+        if let Some(run_id) = &metrics.run_id {
+            overview.insert(1, vec!["Run".to_string(), run_id.clone()]);
+        }
+        if let Some(host) = &metrics.host {
+            overview.push(vec!["Host".to_string(), format!("{} (kernel {})", host.arch, host.kernel)]);
+        }
+        if let Some(features) = &metrics.features {
+            let available = [
+                ("BTF", features.available.btf),
+                ("ringbuf", features.available.ringbuf),
+                ("fentry", features.available.fentry),
+            ]
+            .iter()
+            .filter(|(_, available)| *available)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+            overview.push(vec![
+                "BPF Features".to_string(),
+                format!(
+                    "{} buffer, {}{}; kernel has {}",
+                    features.used.event_buffer,
+                    features.used.attach,
+                    if features.used.btf_offsets { ", BTF offsets" } else { "" },
+                    if available.is_empty() { "none".to_string() } else { available.join(", ") }
+                ),
+            ]);
+        }
+        if let Some(clock) = &metrics.clock {
+            overview.push(vec![
+                "Clock".to_string(),
+                format!("{} (wall offset {} ns ± {} ns)", clock.source, clock.wall_offset_ns, clock.uncertainty_ns),
+            ]);
+        }
+        if let Some(bounds) = &metrics.latency_bounds {
+            overview.push(vec![
+                "Rejected latencies".to_string(),
+                format!(
+                    "{} below {} ns, {} above {} ns",
+                    bounds.below_min, bounds.min_latency_ns, bounds.above_max, bounds.max_latency_ns
+                ),
+            ]);
+        }
+        if metrics.dedup.duplicates > 0 || metrics.dedup.cleanup.is_some() {
+            let cleanup = metrics.dedup.cleanup.as_ref().map_or(String::new(), |cleanup| {
+                format!(", {} cleanups at p50 {:.2} µs, p99 {:.2} µs", cleanup.events, cleanup.percentiles.p50, cleanup.percentiles.p99)
+            });
+            overview.push(vec![
+                "Duplicate receives".to_string(),
+                format!("{} ({} policy){}", metrics.dedup.duplicates, metrics.dedup.policy, cleanup),
+            ]);
+        }
+        if let Some(correction) = &metrics.coordinated_omission {
+            overview.push(vec![
+                "Synthetic samples".to_string(),
+                correction.synthetic_samples.to_string(),
+            ]);
+        }
+        output.push_str(&render_table(style, &[left("Metric"), left("Value")], &overview));
+
+        let percentile_table = |p: &Percentiles| {
+            let rows: Vec<Vec<String>> = [
+                ("p50", p.p50),
+                ("p75", p.p75),
+                ("p90", p.p90),
+                ("p95", p.p95),
+                ("p99", p.p99),
+                ("p99.9", p.p999),
+            ]
+            .iter()
+            .map(|(name, value)| vec![name.to_string(), format!("{:.2}", value)])
+            .collect();
+            render_table(style, &[left("Percentile"), right("Latency (us)")], &rows)
+        };
+
+        heading(&mut output, "Latency Percentiles");
+        output.push_str(&percentile_table(&metrics.percentiles));
+
+        if metrics.trajectory.len() > 1 {
+            heading(&mut output, "Latency Over Time");
+            let recent = &metrics.trajectory[metrics.trajectory.len().saturating_sub(SPARKLINE_POINTS)..];
+            let rows: Vec<Vec<String>> = [
+                ("p50", recent.iter().map(|p| p.p50).collect::<Vec<_>>()),
+                ("p95", recent.iter().map(|p| p.p95).collect()),
+                ("p99", recent.iter().map(|p| p.p99).collect()),
+            ]
+            .iter()
+            .map(|(name, values)| {
+                let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                vec![name.to_string(), sparkline(values), format!("{:.2}", min), format!("{:.2}", max)]
+            })
+            .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Percentile"), left("Intervals"), right("Min (us)"), right("Max (us)")],
+                &rows,
+            ));
+        }
+
+        // Sidecar-to-app and pod-to-pod latency side by side
+        if metrics.traffic_classes.len() > 1 {
+            for (class, class_metrics) in &metrics.traffic_classes {
+                heading(
+                    &mut output,
+                    &format!("Latency on {} connections ({} events)", class, class_metrics.events),
+                );
+                output.push_str(&percentile_table(&class_metrics.percentiles));
+            }
+        }
+
+        heading(&mut output, "Microbursts");
+        let bursts = &metrics.bursts;
+        let rows = vec![
+            vec!["Bursts".to_string(), bursts.bursts.to_string()],
+            vec!["Time in bursts (ms)".to_string(), bursts.total_duration_ms.to_string()],
+            vec!["Longest burst (ms)".to_string(), bursts.max_duration_ms.to_string()],
+            vec!["Events in bursts".to_string(), bursts.events.to_string()],
+            vec!["Median rate (/s)".to_string(), format!("{:.0}", bursts.median_rate)],
+            vec!["Peak rate (/s)".to_string(), format!("{:.0}", bursts.peak_rate)],
+        ];
+        output.push_str(&render_table(style, &[left("Metric"), right("Value")], &rows));
+
+        if let Some(tail) = &metrics.tail {
+            heading(&mut output, &format!("Tail Causes (above {:.2} us)", tail.threshold_us));
+            let share = |events: u64| {
+                if tail.events > 0 {
+                    format!("{:.1}%", events as f64 * 100.0 / tail.events as f64)
+                } else {
+                    "-".to_string()
+                }
+            };
+            let rows: Vec<Vec<String>> = [
+                ("Packet drop", tail.packet_drop),
+                ("Context switch", tail.context_switch),
+                ("Unexplained", tail.unexplained),
+            ]
+            .iter()
+            .map(|&(cause, events)| vec![cause.to_string(), events.to_string(), share(events)])
+            .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Cause"), right("Events"), right("Share")],
+                &rows,
+            ));
+        }
+
+        if let Some(client) = &metrics.client {
+            heading(&mut output, &format!("Client vs Kernel ({})", client.tool));
+            let format_us = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string());
+            let rows: Vec<Vec<String>> = [
+                ("p50", client.percentiles.p50, metrics.percentiles.p50, client.above_kernel.p50),
+                ("p90", client.percentiles.p90, metrics.percentiles.p90, client.above_kernel.p90),
+                ("p95", client.percentiles.p95, metrics.percentiles.p95, client.above_kernel.p95),
+                ("p99", client.percentiles.p99, metrics.percentiles.p99, client.above_kernel.p99),
+                ("p99.9", client.percentiles.p999, metrics.percentiles.p999, client.above_kernel.p999),
+            ]
+            .iter()
+            .map(|&(name, client_us, kernel_us, above)| {
+                vec![name.to_string(), format_us(client_us), format!("{:.2}", kernel_us), format_us(above)]
+            })
+            .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Percentile"), right("Client (us)"), right("Kernel (us)"), right("Above kernel (us)")],
+                &rows,
+            ));
+        }
+
+        if let Some(mesh) = &metrics.mesh {
+            heading(&mut output, &format!("Mesh vs Kernel ({})", mesh.mesh));
+            let format_us = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string());
+            let rows: Vec<Vec<String>> = [
+                ("p50", mesh.percentiles.p50, metrics.percentiles.p50, mesh.above_kernel.p50),
+                ("p90", mesh.percentiles.p90, metrics.percentiles.p90, mesh.above_kernel.p90),
+                ("p95", mesh.percentiles.p95, metrics.percentiles.p95, mesh.above_kernel.p95),
+                ("p99", mesh.percentiles.p99, metrics.percentiles.p99, mesh.above_kernel.p99),
+                ("p99.9", mesh.percentiles.p999, metrics.percentiles.p999, mesh.above_kernel.p999),
+            ]
+            .iter()
+            .map(|&(name, mesh_us, kernel_us, above)| {
+                vec![name.to_string(), format_us(mesh_us), format!("{:.2}", kernel_us), format_us(above)]
+            })
+            .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Percentile"), right("Mesh (us)"), right("Kernel (us)"), right("Above kernel (us)")],
+                &rows,
+            ));
+        }
+
+        if !metrics.envoy.is_empty() {
+            heading(&mut output, "Envoy vs Kernel");
+            let format_us = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string());
+            let rows: Vec<Vec<String>> = metrics
+                .envoy
+                .iter()
+                .map(|proxy| {
+                    vec![
+                        format!("{} {}", proxy.admin, proxy.histogram),
+                        proxy.samples.to_string(),
+                        format_us(proxy.percentiles.p50),
+                        format_us(proxy.percentiles.p99),
+                        format_us(proxy.above_kernel.p50),
+                        format_us(proxy.above_kernel.p99),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[
+                    left("Histogram"),
+                    right("Samples"),
+                    right("p50 (us)"),
+                    right("p99 (us)"),
+                    right("p50 above kernel"),
+                    right("p99 above kernel"),
+                ],
+                &rows,
+            ));
+        }
+
+        heading(&mut output, "Jitter");
+        let jitter = vec![
+            vec!["Mean".to_string(), format!("{:.2}", metrics.jitter.mean_us)],
+            vec!["Max".to_string(), format!("{:.2}", metrics.jitter.max_us)],
+        ];
+        output.push_str(&render_table(style, &[left("Connections"), right("Jitter (us)")], &jitter));
+
+        heading(&mut output, "Histogram");
+        let buckets = ["0-1ms", "1-5ms", "5-10ms", "10-50ms", "50-100ms", "100ms+"];
+        let histogram: Vec<Vec<String>> = buckets
+            .iter()
+            .zip(metrics.histogram.counts())
+            .map(|(bucket, count)| vec![bucket.to_string(), count.to_string()])
+            .collect();
+        output.push_str(&render_table(style, &[left("Bucket"), right("Events")], &histogram));
+
+        if !metrics.services.is_empty() {
+            heading(&mut output, "Services");
+            let mut services: Vec<_> = metrics.services.iter().collect();
+            services.sort_by(|a, b| a.0.cmp(b.0));
+            let rows: Vec<Vec<String>> = services
+                .into_iter()
+                .map(|(service, s)| {
+                    vec![
+                        service.clone(),
+                        s.events.to_string(),
+                        format!("{:.2}", s.percentiles.p50),
+                        format!("{:.2}", s.percentiles.p99),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Service"), right("Events"), right("p50 (us)"), right("p99 (us)")],
+                &rows,
+            ));
+        }
+
+        let errors = &metrics.errors;
+        if errors.resets_received + errors.resets_sent + errors.timeouts > 0 {
+            heading(
+                &mut output,
+                &format!("Resets and Timeouts ({:.2} resets/s, {:.2} timeouts/s)", errors.resets_per_second, errors.timeouts_per_second),
+            );
+            let all = ("All".to_string(), ServiceErrors {
+                resets_received: errors.resets_received,
+                resets_sent: errors.resets_sent,
+                timeouts: errors.timeouts,
+            });
+            let rows: Vec<Vec<String>> = errors
+                .services
+                .iter()
+                .chain(std::iter::once((&all.0, &all.1)))
+                .map(|(service, e)| {
+                    vec![service.clone(), e.resets_received.to_string(), e.resets_sent.to_string(), e.timeouts.to_string()]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Service"), right("RST received"), right("RST sent"), right("Timeouts")],
+                &rows,
+            ));
+        }
+
+        let overflowing: Vec<_> = metrics
+            .listeners
+            .iter()
+            .filter(|(_, listener)| listener.overflows + listener.syn_queue_full > 0)
+            .collect();
+        if !overflowing.is_empty() {
+            heading(&mut output, "Accept Queue Overflows");
+            let rows: Vec<Vec<String>> = overflowing
+                .iter()
+                .map(|(port, listener)| {
+                    vec![
+                        port.to_string(),
+                        listener.requests.to_string(),
+                        listener.overflows.to_string(),
+                        listener.syn_queue_full.to_string(),
+                        format!("{}/{}", listener.max_backlog, listener.backlog_limit),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Port"), right("SYNs"), right("Overflows"), right("SYN queue full"), right("Max queue")],
+                &rows,
+            ));
+        }
+
+        if !metrics.pods.is_empty() {
+            heading(&mut output, "Pods");
+            let rows: Vec<Vec<String>> = metrics
+                .pods
+                .iter()
+                .map(|(uid, p)| {
+                    vec![
+                        uid.clone(),
+                        p.qos_class.clone(),
+                        p.containers.len().to_string(),
+                        p.events.to_string(),
+                        format!("{:.2}", p.percentiles.p50),
+                        format!("{:.2}", p.percentiles.p99),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[
+                    left("Pod UID"),
+                    left("QoS"),
+                    right("Containers"),
+                    right("Events"),
+                    right("p50 (us)"),
+                    right("p99 (us)"),
+                ],
+                &rows,
+            ));
+        }
+
+        if !metrics.zones.is_empty() {
+            heading(&mut output, "Zones");
+            let rows: Vec<Vec<String>> = metrics
+                .zones
+                .iter()
+                .map(|(locality, z)| {
+                    vec![
+                        locality.clone(),
+                        z.events.to_string(),
+                        format!("{:.2}", z.percentiles.p50),
+                        format!("{:.2}", z.percentiles.p99),
+                        format!("{:.2}", z.percentiles.p999),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Locality"), right("Events"), right("p50 (us)"), right("p99 (us)"), right("p99.9 (us)")],
+                &rows,
+            ));
+        }
+
+        if !metrics.tenants.is_empty() {
+            heading(&mut output, "Tenants");
+            let rows: Vec<Vec<String>> = metrics
+                .tenants
+                .iter()
+                .map(|(tenant, t)| {
+                    vec![
+                        tenant.clone(),
+                        t.events.to_string(),
+                        format!("{:.2}", t.percentiles.p50),
+                        format!("{:.2}", t.percentiles.p99),
+                        format!("{:.2}", t.percentiles.p999),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Tenant"), right("Events"), right("p50 (us)"), right("p99 (us)"), right("p99.9 (us)")],
+                &rows,
+            ));
+        }
+
+        // Only worth a section when some events were outside ESTABLISHED
+        if metrics.phases.len() > 1 {
+            heading(&mut output, "Connection Phases");
+            let rows: Vec<Vec<String>> = metrics
+                .phases
+                .iter()
+                .map(|(phase, p)| {
+                    vec![
+                        phase.clone(),
+                        p.events.to_string(),
+                        format!("{:.2}", p.percentiles.p50),
+                        format!("{:.2}", p.percentiles.p99),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Phase"), right("Events"), right("p50 (us)"), right("p99 (us)")],
+                &rows,
+            ));
+        }
+
+        // Only worth a section when TCP is not all there is
+        if metrics.protocols.len() > 1 {
+            heading(&mut output, "Protocols");
+            let rows: Vec<Vec<String>> = metrics
+                .protocols
+                .iter()
+                .map(|(protocol, p)| {
+                    vec![
+                        protocol.clone(),
+                        p.events.to_string(),
+                        format!("{:.2}", p.percentiles.p50),
+                        format!("{:.2}", p.percentiles.p99),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[left("Protocol"), right("Events"), right("p50 (us)"), right("p99 (us)")],
+                &rows,
+            ));
+        }
+
+        if top_connections > 0 && !metrics.connections.is_empty() {
+            let mut connections: Vec<_> = metrics.connections.iter().collect();
+            // Busiest first; ties in key order so the output is stable
+            connections.sort_by(|a, b| b.1.events.cmp(&a.1.events).then_with(|| a.0.cmp(b.0)));
+            connections.truncate(top_connections);
+
+            heading(
+                &mut output,
+                &format!("Top {} Connections by Events", connections.len()),
+            );
+            let rows: Vec<Vec<String>> = connections
+                .into_iter()
+                .map(|(_, c)| {
+                    vec![
+                        c.source.clone(),
+                        c.destination.clone(),
+                        c.events.to_string(),
+                        format!("{:.2}", c.avg_latency_us),
+                        format!("{:.2}", c.p50_us),
+                        format!("{:.2}", c.p99_us),
+                        format!("{:.2}", c.max_latency_us),
+                    ]
+                })
+                .collect();
+            output.push_str(&render_table(
+                style,
+                &[
+                    left("Source"),
+                    left("Destination"),
+                    right("Events"),
+                    right("Avg (us)"),
+                    right("p50 (us)"),
+                    right("p99 (us)"),
+                    right("Max (us)"),
+                ],
+                &rows,
+            ));
+        }
+
+        output
+    }
+}
+
+impl MetricsExporter for SummaryExporter {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let summary = Self::to_summary(metrics, self.style, self.top_connections);
+
+        self.output.write_all(summary.as_bytes())
+    }
+}
+
+/// Render values as a line of block characters, scaled from their minimum
+/// (lowest block) to their maximum (highest block)
+fn sparkline(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            let level = if max > min { (value - min) / (max - min) * 7.0 } else { 0.0 };
+            BLOCKS[level.round() as usize]
+        })
+        .collect()
+}
+
+/// Render rows as a table with padded columns, followed by a blank line
+fn render_table(style: SummaryStyle, columns: &[Column], rows: &[Vec<String>]) -> String {
+    let escape = |cell: &str| match style {
+        SummaryStyle::Markdown => cell.replace('|', "\\|"),
+        SummaryStyle::Table => cell.to_string(),
+    };
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| escape(cell)).collect())
+        .collect();
+
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([column.name.len(), 3])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let line = |cells: Vec<String>| match style {
+        SummaryStyle::Markdown => format!("| {} |\n", cells.join(" | ")),
+        SummaryStyle::Table => format!("{}\n", cells.join("  ").trim_end()),
+    };
+    let pad = |i: usize, cell: &str| {
+        if columns[i].right {
+            format!("{:>width$}", cell, width = widths[i])
+        } else {
+            format!("{:<width$}", cell, width = widths[i])
+        }
+    };
+
+    let mut output = line(columns.iter().enumerate().map(|(i, c)| pad(i, c.name)).collect());
+    output.push_str(&line(
+        columns
+            .iter()
+            .zip(&widths)
+            .map(|(column, &width)| match style {
+                SummaryStyle::Markdown if column.right => format!("{}:", "-".repeat(width - 1)),
+                _ => "-".repeat(width),
+            })
+            .collect(),
+    ));
+    for row in &rows {
+        output.push_str(&line(row.iter().enumerate().map(|(i, cell)| pad(i, cell)).collect()));
+    }
+    output.push('\n');
+    output
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
+    use std::collections::BTreeMap;
 
     fn create_test_metrics() -> LatencyMetrics {
         use crate::types::*;
@@ -394,7 +1939,7 @@ mod tests {
             timestamp: "2025-01-01T00:00:00Z".to_string(),
             duration_seconds: 60,
             total_events: 1000,
-            connections: HashMap::new(),
+            connections: BTreeMap::new(),
             histogram: LatencyHistogram::default(),
             percentiles: Percentiles {
                 p50: 100.0,
@@ -404,22 +1949,68 @@ mod tests {
                 p99: 500.0,
                 p999: 600.0,
             },
-            event_type_breakdown: EventTypeBreakdown::default(),
-            packet_drops: PacketDropStats::default(),
-            connection_states: ConnectionStateStats::default(),
-            context_switches: ContextSwitchStats::default(),
-            xdp_stats: XdpPacketStats::default(),
+            ..Default::default()
         }
     }
 
     #[test]
     fn test_prometheus_format() {
-        let metrics = create_test_metrics();
+        let mut metrics = create_test_metrics();
+        assert!(!PrometheusExporter::to_prometheus_format(&metrics).contains("latency_probe_export_"));
+        metrics.health.export = Some(crate::types::ExportStats {
+            queue_depth: 2,
+            max_latency_ms: 1500.0,
+            ..Default::default()
+        });
         let prometheus = PrometheusExporter::to_prometheus_format(&metrics);
 
         assert!(prometheus.contains("latency_probe_events_total 1000"));
         assert!(prometheus.contains("latency_probe_duration_seconds 60"));
         assert!(prometheus.contains("percentile=\"0.50\""));
+        assert!(prometheus.contains("latency_probe_event_loss_ratio 0"));
+        assert!(prometheus.contains("latency_probe_jitter_microseconds{stat=\"max\"} 0"));
+        assert!(prometheus.contains("latency_probe_microbursts_total 0"));
+        assert!(prometheus.contains("# TYPE latency_probe_internal_invalid_sockets_total counter"));
+        assert!(prometheus.contains("latency_probe_internal_dropped_events_total 0"));
+        assert!(prometheus.contains("latency_probe_export_queue_depth 2"));
+        assert!(prometheus.contains("latency_probe_export_max_latency_seconds 1.5"));
+    }
+
+    #[test]
+    fn test_instance_label() {
+        let mut metrics = create_test_metrics();
+        metrics.instance = Some("ns-a".to_string());
+
+        let prometheus = PrometheusExporter::to_prometheus_format(&metrics);
+        assert!(prometheus.contains("latency_probe_events_total{probe_instance=\"ns-a\"} 1000\n"));
+        assert!(prometheus.contains("{probe_instance=\"ns-a\",percentile=\"0.50\"}"));
+        assert!(prometheus.contains("# TYPE latency_probe_events_total counter\n"));
+
+        let influx = InfluxExporter::to_influx_format(&metrics, "latency");
+        assert!(influx.starts_with("latency,probe_instance=ns-a,type=summary "));
+    }
+
+    #[test]
+    fn test_run_id() {
+        let mut metrics = create_test_metrics();
+        metrics.instance = Some("ns-a".to_string());
+        metrics.run_id = Some("nightly-42".to_string());
+        metrics.heatmap = crate::types::LatencyHeatmap::new(1000);
+        metrics.heatmap.start = "2025-01-01T00:00:00Z".to_string();
+        metrics.heatmap.intervals = vec![crate::types::LatencyHistogram::from_samples(&[100.0])];
+
+        let prometheus = PrometheusExporter::to_prometheus_format(&metrics);
+        assert!(prometheus.contains("latency_probe_events_total{probe_instance=\"ns-a\",run_id=\"nightly-42\"} 1000\n"));
+
+        let influx = InfluxExporter::to_influx_format(&metrics, "latency");
+        assert!(influx.lines().all(|line| line.starts_with("latency,probe_instance=ns-a,run_id=nightly-42,type=")));
+
+        let table = SummaryExporter::to_summary(&metrics, SummaryStyle::Markdown, 0);
+        assert!(table.lines().any(|line| line.starts_with("| Run ") && line.contains("| nightly-42 ")));
+
+        let csv = HeatmapExporter::to_csv(&metrics);
+        assert!(csv.starts_with("run_id,time,1000,"));
+        assert!(csv.contains("\nnightly-42,2025-01-01T00:00:00.000Z,1,"));
     }
 
     #[test]
@@ -430,5 +2021,173 @@ mod tests {
         assert!(influx.contains("latency,type=summary"));
         assert!(influx.contains("total_events=1000i"));
         assert!(influx.contains("p50=100"));
+        assert!(influx.contains("latency,type=jitter mean=0,max=0 "));
+    }
+
+    #[test]
+    fn test_untrusted_values() {
+        let mut metrics = create_test_metrics();
+        metrics.percentiles.p99 = f64::NAN;
+        metrics.jitter.mean_us = f64::INFINITY;
+        metrics.jitter.max_us = f64::NEG_INFINITY;
+        metrics.services.insert(
+            "web \"api\", v2=a\\b\nc".to_string(),
+            crate::types::GroupMetrics {
+                events: 1,
+                ..Default::default()
+            },
+        );
+
+        let prometheus = PrometheusExporter::to_prometheus_format(&metrics);
+        assert!(prometheus.contains("latency_probe_service_events_total{service=\"web \\\"api\\\", v2=a\\\\b\\nc\"} 1\n"));
+        assert!(prometheus.contains("{service=\"web \\\"api\\\", v2=a\\\\b\\nc\",percentile=\"0.50\"} 0\n"));
+
+        let influx = InfluxExporter::to_influx_format(&metrics, "latency");
+        assert!(influx.contains("latency,type=service,service=web\\ \"api\"\\,\\ v2\\=a\\\\b\\ c events=1i,"));
+        // Non-finite fields are dropped, and lines left without fields
+        assert!(influx.contains("latency,type=percentiles p50=100,p75="));
+        assert!(!influx.contains("NaN") && !influx.contains("inf"));
+        assert!(!influx.contains("type=jitter"));
+
+        // Empty tag values are dropped
+        metrics.services.clear();
+        metrics.services.insert(String::new(), Default::default());
+        let influx = InfluxExporter::to_influx_format(&metrics, "latency");
+        assert!(influx.contains("latency,type=service events=0i,"));
+    }
+
+    #[test]
+    fn test_event_rate_format() {
+        let mut metrics = create_test_metrics();
+        metrics.event_rate = crate::types::EventRateSeries::new(1000);
+        for timestamp_ns in [0, 1_500_000_000, 1_600_000_000, 2_100_000_000] {
+            metrics.event_rate.add(timestamp_ns);
+        }
+
+        let prometheus = PrometheusExporter::to_prometheus_format(&metrics);
+        assert!(prometheus.contains("latency_probe_event_rate_per_second{stat=\"min\"} 1"));
+        assert!(prometheus.contains("latency_probe_event_rate_per_second{stat=\"max\"} 2"));
+
+        let influx = InfluxExporter::to_influx_format(&metrics, "latency");
+        assert_eq!(influx.matches("latency,type=rate ").count(), 3);
+        assert!(influx.contains("latency,type=rate events=2i,rate=2 "));
+    }
+
+    #[test]
+    fn test_heatmap_format() {
+        let mut metrics = create_test_metrics();
+        metrics.heatmap = crate::types::LatencyHeatmap::new(1000);
+        metrics.heatmap.add_sample(0, 500.0);
+        metrics.heatmap.add_sample(2_500_000_000, 7000.0);
+        metrics.heatmap.start = "2025-01-01T00:00:00Z".to_string();
+
+        let csv = HeatmapExporter::to_csv(&metrics);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], "time,1000,5000,10000,50000,100000,+Inf");
+        assert_eq!(rows[1], "2025-01-01T00:00:00.000Z,1,0,0,0,0,0");
+        assert_eq!(rows[2], "2025-01-01T00:00:01.000Z,0,0,0,0,0,0");
+        assert_eq!(rows[3], "2025-01-01T00:00:02.000Z,0,0,1,0,0,0");
+    }
+
+    #[test]
+    fn test_program_stats_format() {
+        let mut metrics = create_test_metrics();
+        metrics.program_stats = vec![
+            crate::types::ProgramStats::new("tcp_sendmsg", 1_500_000, 1000),
+            crate::types::ProgramStats::new("tcp_cleanup_rbuf", 0, 0),
+        ];
+        assert_eq!(metrics.program_stats[0].avg_run_time_ns, 1500.0);
+        assert_eq!(metrics.program_stats[1].avg_run_time_ns, 0.0);
+
+        let prometheus = PrometheusExporter::to_prometheus_format(&metrics);
+        assert!(prometheus.contains("latency_probe_program_run_time_ns_total{program=\"tcp_sendmsg\"} 1500000"));
+        assert!(prometheus.contains("latency_probe_program_runs_total{program=\"tcp_sendmsg\"} 1000"));
+
+        let influx = InfluxExporter::to_influx_format(&metrics, "latency");
+        assert!(influx.contains("latency,type=program,program=tcp_sendmsg run_time_ns=1500000i,run_count=1000i,avg_run_time_ns=1500"));
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = |metrics: &LatencyMetrics, name: &str| {
+            let path = dir.path().join(name);
+            JsonExporter::new(path.clone(), true)
+                .with_canonical(true)
+                .export(metrics)
+                .unwrap();
+            std::fs::read_to_string(path).unwrap()
+        };
+
+        let mut first = create_test_metrics();
+        let mut second = create_test_metrics();
+        for service in ["web", "db", "cache"] {
+            first.services.insert(service.to_string(), Default::default());
+        }
+        for service in ["cache", "web", "db"] {
+            second.services.insert(service.to_string(), Default::default());
+        }
+        first.percentiles.p50 = 0.1 + 0.2;
+        second.percentiles.p50 = 0.3;
+        first.throughput.sent_bytes_per_second = -0.0001;
+
+        let json = export(&first, "first.json");
+        assert_eq!(json, export(&second, "second.json"));
+        assert!(json.find("\"cache\"").unwrap() < json.find("\"db\"").unwrap());
+        assert!(json.contains("\"p50\": 0.3,"));
+        assert!(json.contains("\"sent_bytes_per_second\": 0.0\n"));
+    }
+
+    #[test]
+    fn test_summary_format() {
+        use crate::types::ConnectionMetrics;
+
+        let mut metrics = create_test_metrics();
+        for (key, events) in [("a", 5), ("b", 50), ("c", 20)] {
+            metrics.connections.insert(
+                key.to_string(),
+                ConnectionMetrics {
+                    source: format!("10.0.0.1:{}", events),
+                    destination: "10.0.0.2:80".to_string(),
+                    cookie: None,
+                    events,
+                    min_latency_us: 1000.0,
+                    max_latency_us: 1500.0,
+                    avg_latency_us: 1234.5,
+                    std_dev_us: 100.0,
+                    p50_us: 1200.0,
+                    p95_us: 1400.0,
+                    p99_us: 1450.0,
+                    jitter_us: 25.0,
+                    bytes_sent: 0,
+                    bytes_received: 0,
+                    bytes_per_second: 0.0,
+                    timeouts: 0,
+                    nat_destination: None,
+                    congestion: None,
+                    digest: None,
+                },
+            );
+        }
+
+        metrics.trajectory = [(10, 100.0), (20, 150.0), (30, 200.0)]
+            .iter()
+            .map(|&(end_secs, p99)| crate::types::IntervalPercentiles { end_secs, events: 10, p50: 50.0, p95: 90.0, p99 })
+            .collect();
+
+        let table = SummaryExporter::to_summary(&metrics, SummaryStyle::Table, 2);
+        assert!(table.contains("Top 2 Connections by Events\n---------------------------\n"));
+        assert!(table.contains("p99         ▁▅█          100.00    200.00\n"));
+        assert!(table.contains("Percentile  Latency (us)\n----------  ------------\np50               100.00\n"));
+        // Busiest connections first, and only the top N
+        let busiest = table.find("10.0.0.1:50").unwrap();
+        assert!(busiest < table.find("10.0.0.1:20").unwrap());
+        assert!(!table.contains("10.0.0.1:5 "));
+
+        let markdown = SummaryExporter::to_summary(&metrics, SummaryStyle::Markdown, 10);
+        assert!(markdown.starts_with("# Latency Probe Summary\n"));
+        assert!(markdown.contains("| Percentile | Latency (us) |\n| ---------- | -----------: |\n"));
+        assert!(markdown.contains("| Total events | 1000                 |"));
+        assert!(markdown.contains("## Top 3 Connections by Events"));
     }
 }
@@ -0,0 +1,291 @@
+//! Fault injection with tc netem
+//!
+//! For resilience benchmarks, `--inject` adds delay or packet loss to an
+//! interface for a window of the run, e.g.
+//!
+//! ```text
+//! --inject "delay=5ms loss=0.1% from=120s to=180s" --inject-interface eth0
+//! ```
+//!
+//! installs `tc qdisc add dev eth0 root netem delay 5ms loss 0.1%` two
+//! minutes into collection and removes it a minute later. Each window is
+//! recorded in the report's `fault_injections`, with the event counts at
+//! its edges, so latency changes can be attributed to the fault rather
+//! than the mesh. An installed qdisc is removed when the probe stops, even
+//! mid-window.
+
+use crate::{collector::MetricsCollector, omission::parse_interval};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::Mutex,
+    task::JoinHandle,
+    time::{sleep_until, Instant},
+};
+
+/// A netem fault and when it applies
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultSpec {
+    /// Added delay
+    pub delay: Option<Duration>,
+    /// Random variation of the delay
+    pub jitter: Option<Duration>,
+    /// Packet loss in percent
+    pub loss_pct: Option<f64>,
+    /// Offset from the start of collection at which the fault is installed
+    pub from: Duration,
+    /// Offset at which it is removed (None = until the probe stops)
+    pub to: Option<Duration>,
+}
+
+impl FaultSpec {
+    /// Parse `key=value` pairs separated by spaces or commas
+    ///
+    /// Keys are `delay`, `jitter` (with `delay`), `loss` (percent, with or
+    /// without `%`), `from` and `to` (offsets such as `120s`; `from`
+    /// defaults to the start of collection).
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut fault = Self {
+            delay: None,
+            jitter: None,
+            loss_pct: None,
+            from: Duration::ZERO,
+            to: None,
+        };
+
+        for pair in spec.split([' ', ',']).filter(|s| !s.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .with_context(|| format!("Invalid fault '{}', expected key=value", pair))?;
+            match key {
+                "delay" => fault.delay = Some(parse_interval(value)?),
+                "jitter" => fault.jitter = Some(parse_interval(value)?),
+                "loss" => {
+                    let loss: f64 = value
+                        .trim_end_matches('%')
+                        .parse()
+                        .with_context(|| format!("Invalid loss: {}", value))?;
+                    if !(0.0..=100.0).contains(&loss) {
+                        anyhow::bail!("Loss must be between 0% and 100%: {}", value);
+                    }
+                    fault.loss_pct = Some(loss);
+                }
+                "from" => fault.from = parse_offset(value)?,
+                "to" => fault.to = Some(parse_offset(value)?),
+                _ => anyhow::bail!("Unknown fault parameter '{}' (delay, jitter, loss, from, to)", key),
+            }
+        }
+
+        if fault.delay.is_none() && fault.loss_pct.is_none() {
+            anyhow::bail!("Fault '{}' injects nothing (set delay or loss)", spec);
+        }
+        if fault.jitter.is_some() && fault.delay.is_none() {
+            anyhow::bail!("Fault '{}' sets jitter without delay", spec);
+        }
+        if fault.to.is_some_and(|to| to <= fault.from) {
+            anyhow::bail!("Fault '{}' ends before it starts", spec);
+        }
+
+        Ok(fault)
+    }
+
+    /// netem parameters, as passed to tc (e.g. `delay 5ms 1ms loss 0.1%`)
+    pub fn netem(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(delay) = self.delay {
+            params.push(format!("delay {}us", delay.as_micros()));
+            if let Some(jitter) = self.jitter {
+                params.push(format!("{}us", jitter.as_micros()));
+            }
+        }
+        if let Some(loss) = self.loss_pct {
+            params.push(format!("loss {}%", loss));
+        }
+        params.join(" ")
+    }
+}
+
+/// Offset into the run: an interval such as `120s`, or `0`
+fn parse_offset(value: &str) -> Result<Duration> {
+    match value {
+        "0" | "0s" => Ok(Duration::ZERO),
+        value => parse_interval(value),
+    }
+}
+
+/// Order faults by start and check that their windows do not overlap
+///
+/// netem is installed as the root qdisc, so only one fault can be active
+/// on an interface at a time.
+pub fn schedule(mut faults: Vec<FaultSpec>) -> Result<Vec<FaultSpec>> {
+    faults.sort_by_key(|fault| fault.from);
+    for pair in faults.windows(2) {
+        if pair[0].to.is_none_or(|to| to > pair[1].from) {
+            anyhow::bail!(
+                "Fault windows overlap: '{}' from {:?} and '{}' from {:?}",
+                pair[0].netem(),
+                pair[0].from,
+                pair[1].netem(),
+                pair[1].from
+            );
+        }
+    }
+    Ok(faults)
+}
+
+/// Runs a fault schedule against one interface
+pub struct FaultInjector {
+    interface: String,
+    task: JoinHandle<()>,
+    /// Whether a netem qdisc may be installed
+    active: Arc<AtomicBool>,
+}
+
+impl FaultInjector {
+    /// Start injecting faults
+    ///
+    /// # Arguments
+    ///
+    /// * `interface` - Interface the qdisc is installed on
+    /// * `faults` - Faults ordered by start (see [`schedule`])
+    /// * `start` - Start of collection, which offsets are relative to
+    /// * `collector` - Collector the windows are recorded in
+    pub fn spawn(
+        interface: String,
+        faults: Vec<FaultSpec>,
+        start: Instant,
+        collector: Arc<Mutex<MetricsCollector>>,
+    ) -> Self {
+        let active = Arc::new(AtomicBool::new(false));
+
+        let task = {
+            let interface = interface.clone();
+            let active = Arc::clone(&active);
+            tokio::spawn(async move {
+                for fault in faults {
+                    sleep_until(start + fault.from).await;
+
+                    let netem = fault.netem();
+                    // Marked first, so stopping mid-command still cleans up
+                    active.store(true, Ordering::SeqCst);
+                    if let Err(e) = tc(&["qdisc", "add", "dev", &interface, "root", "netem"], &netem).await {
+                        active.store(false, Ordering::SeqCst);
+                        warn!("Failed to inject '{}' on {}: {:#}", netem, interface, e);
+                        continue;
+                    }
+                    info!("⚡ Injecting '{}' on {}", netem, interface);
+                    collector.lock().await.record_fault_start(&interface, &netem);
+
+                    let Some(to) = fault.to else {
+                        return;
+                    };
+                    sleep_until(start + to).await;
+                    remove(&interface, &collector).await;
+                    active.store(false, Ordering::SeqCst);
+                }
+            })
+        };
+
+        Self {
+            interface,
+            task,
+            active,
+        }
+    }
+
+    /// Stop the schedule and remove the qdisc if a fault is active
+    pub async fn stop(self, collector: &Arc<Mutex<MetricsCollector>>) {
+        self.task.abort();
+        if self.active.swap(false, Ordering::SeqCst) {
+            remove(&self.interface, collector).await;
+        }
+    }
+}
+
+impl Drop for FaultInjector {
+    /// Leave no fault behind if the probe exits on an error
+    fn drop(&mut self) {
+        self.task.abort();
+        if self.active.swap(false, Ordering::SeqCst) {
+            let _ = std::process::Command::new("tc")
+                .args(["qdisc", "del", "dev", &self.interface, "root"])
+                .status();
+        }
+    }
+}
+
+/// Remove the netem qdisc and record the end of the fault
+async fn remove(interface: &str, collector: &Arc<Mutex<MetricsCollector>>) {
+    match tc(&["qdisc", "del", "dev", interface, "root"], "").await {
+        Ok(()) => info!("⚡ Removed fault from {}", interface),
+        Err(e) => warn!("Failed to remove fault from {}: {:#}", interface, e),
+    }
+    collector.lock().await.record_fault_end(interface);
+}
+
+/// Run tc with fixed arguments followed by space-separated parameters
+async fn tc(args: &[&str], params: &str) -> Result<()> {
+    let status = tokio::process::Command::new("tc")
+        .args(args)
+        .args(params.split_whitespace())
+        .status()
+        .await
+        .context("Failed to run tc (is iproute2 installed?)")?;
+
+    if !status.success() {
+        anyhow::bail!("tc {} {} failed ({})", args.join(" "), params, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fault() {
+        let fault = FaultSpec::parse("delay=5ms loss=0.1% from=120s to=180s").unwrap();
+        assert_eq!(fault.delay, Some(Duration::from_millis(5)));
+        assert_eq!(fault.loss_pct, Some(0.1));
+        assert_eq!(fault.from, Duration::from_secs(120));
+        assert_eq!(fault.to, Some(Duration::from_secs(180)));
+        assert_eq!(fault.netem(), "delay 5000us loss 0.1%");
+
+        let fault = FaultSpec::parse("delay=10ms,jitter=2ms").unwrap();
+        assert_eq!(fault.from, Duration::ZERO);
+        assert_eq!(fault.to, None);
+        assert_eq!(fault.netem(), "delay 10000us 2000us");
+
+        assert!(FaultSpec::parse("from=10s to=20s").is_err());
+        assert!(FaultSpec::parse("loss=150%").is_err());
+        assert!(FaultSpec::parse("delay=5ms from=20s to=10s").is_err());
+        assert!(FaultSpec::parse("jitter=1ms loss=1").is_err());
+        assert!(FaultSpec::parse("delay=5").is_err());
+        assert!(FaultSpec::parse("rate=1mbit").is_err());
+
+        let faults = schedule(vec![
+            FaultSpec::parse("loss=1 from=60s").unwrap(),
+            FaultSpec::parse("delay=5ms from=10s to=60s").unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(faults[0].from, Duration::from_secs(10));
+
+        assert!(schedule(vec![
+            FaultSpec::parse("delay=5ms from=10s to=60s").unwrap(),
+            FaultSpec::parse("loss=1 from=30s").unwrap(),
+        ])
+        .is_err());
+        assert!(schedule(vec![
+            FaultSpec::parse("delay=5ms").unwrap(),
+            FaultSpec::parse("loss=1 from=30s").unwrap(),
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Liveness and readiness
+//!
+//! A probe can fail quietly: its programs detached, a perf buffer reader
+//! stuck, every export failing. With `--listen`, `GET /healthz` and
+//! `GET /readyz` (see crate::api) tell Kubernetes about it, so a wedged
+//! probe is restarted instead of collecting nothing for an hour:
+//!
+//! * live - events were seen in the last `--health-max-idle` seconds (always
+//!   live without it, or while the probes attach)
+//! * ready - live, the probes are attached, and fewer than
+//!   `--health-max-export-failures` exports in a row failed
+//!
+//! Event flow is read from the live counters (see crate::counters) when a
+//! check is made, so it costs the event path nothing. Kubelets check every
+//! few seconds, which is precise enough for an idle limit of minutes.
+
+use crate::{counters::LiveCounters, publish::ExportMonitor};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// Failed exports in a row after which a probe is not ready, by default
+pub const DEFAULT_MAX_EXPORT_FAILURES: u64 = 3;
+
+/// When a probe counts as unhealthy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthPolicy {
+    /// Longest time without events before the probe is not live (None to
+    /// never fail on idleness)
+    pub max_idle: Option<Duration>,
+    /// Failed exports in a row before the probe is not ready (0 to ignore
+    /// export failures)
+    pub max_export_failures: u64,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self {
+            max_idle: None,
+            max_export_failures: DEFAULT_MAX_EXPORT_FAILURES,
+        }
+    }
+}
+
+/// Event count when it last changed
+struct Flow {
+    events: u64,
+    changed: Instant,
+}
+
+/// Health of a running probe, shared with the API
+pub struct Health {
+    policy: HealthPolicy,
+    counters: Arc<LiveCounters>,
+    exports: Option<ExportMonitor>,
+    attached: OnceLock<Instant>,
+    flow: Mutex<Option<Flow>>,
+}
+
+/// Answer of /healthz and /readyz
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HealthStatus {
+    /// Whether the probe works (/healthz)
+    pub live: bool,
+    /// Whether the probe collects and exports (/readyz)
+    pub ready: bool,
+    /// Whether the probes are attached
+    pub attached: bool,
+    /// Events since the probe started
+    pub events: u64,
+    /// Seconds since events were last seen (since the probes attached if
+    /// none were)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_secs: Option<u64>,
+    /// Exports in a row that failed (None without exports)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failing_exports: Option<u64>,
+    /// What keeps the probe from being live or ready
+    pub problems: Vec<String>,
+}
+
+impl Health {
+    /// Track the health of a probe
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - When the probe counts as unhealthy
+    /// * `counters` - Live counters of its collector
+    /// * `exports` - Its export queue, if it has one
+    pub fn new(policy: HealthPolicy, counters: Arc<LiveCounters>, exports: Option<ExportMonitor>) -> Self {
+        Self {
+            policy,
+            counters,
+            exports,
+            attached: OnceLock::new(),
+            flow: Mutex::new(None),
+        }
+    }
+
+    /// Record that the probes are attached and events start flowing
+    pub fn set_attached(&self) {
+        self.attached.get_or_init(Instant::now);
+    }
+
+    /// Check the health of the probe
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time of the check
+    pub fn check(&self, now: Instant) -> HealthStatus {
+        let events: u64 = self.counters.counts().event_types.iter().sum();
+        let mut problems = Vec::new();
+
+        let attached = self.attached.get().copied();
+        let idle = attached.map(|attached| {
+            let mut flow = self.flow.lock().unwrap_or_else(|e| e.into_inner());
+            let flow = flow.get_or_insert(Flow {
+                events,
+                changed: attached,
+            });
+            if flow.events != events {
+                *flow = Flow { events, changed: now };
+            }
+            now.saturating_duration_since(flow.changed)
+        });
+        let live = match (idle, self.policy.max_idle) {
+            (Some(idle), Some(max_idle)) if idle > max_idle => {
+                problems.push(format!("No events for {} seconds", idle.as_secs()));
+                false
+            }
+            _ => true,
+        };
+        if attached.is_none() {
+            problems.push("Probes not attached yet".to_string());
+        }
+
+        let failing = self.exports.as_ref().map(ExportMonitor::failing);
+        let exporting = match failing {
+            Some(failing) if self.policy.max_export_failures > 0 && failing >= self.policy.max_export_failures => {
+                problems.push(format!("{} exports in a row failed", failing));
+                false
+            }
+            _ => true,
+        };
+
+        HealthStatus {
+            live,
+            ready: live && attached.is_some() && exporting,
+            attached: attached.is_some(),
+            events,
+            idle_secs: idle.map(|idle| idle.as_secs()),
+            failing_exports: failing,
+            problems,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use probe_common::constants::EVENT_TYPE_RECV;
+
+    #[test]
+    fn test_health_checks() {
+        let counters = Arc::new(LiveCounters::new());
+        let policy = HealthPolicy {
+            max_idle: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let health = Health::new(policy, Arc::clone(&counters), None);
+
+        // Starting up: live, not ready
+        let status = health.check(Instant::now());
+        assert!(status.live && !status.ready);
+        assert_eq!(status.problems, ["Probes not attached yet"]);
+
+        health.set_attached();
+        let start = Instant::now();
+        let status = health.check(start);
+        assert!(status.ready && status.problems.is_empty());
+        assert_eq!(status.failing_exports, None);
+
+        // Events keep it live; a minute without them does not
+        counters.add_event_type(EVENT_TYPE_RECV);
+        let status = health.check(start + Duration::from_secs(50));
+        assert!(status.ready);
+        assert_eq!((status.events, status.idle_secs), (1, Some(0)));
+        let status = health.check(start + Duration::from_secs(100));
+        assert!(status.ready);
+        let status = health.check(start + Duration::from_secs(111));
+        assert!(!status.live && !status.ready);
+        assert_eq!(status.problems, ["No events for 61 seconds"]);
+
+        // Without a limit, idleness is only reported
+        let health = Health::new(HealthPolicy::default(), counters, None);
+        health.set_attached();
+        let status = health.check(Instant::now() + Duration::from_secs(3600));
+        assert!(status.ready);
+        assert_eq!(status.idle_secs, Some(3600));
+    }
+}
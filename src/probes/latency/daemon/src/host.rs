@@ -0,0 +1,131 @@
+//! Host architecture and kernel
+//!
+//! eBPF objects are built for one architecture: kprobe programs read their
+//! arguments from `struct pt_regs`, whose layout differs between x86_64 and
+//! aarch64 (see `bpf_target_arch` in the kernel crate's build). The
+//! embedded object is built for the daemon's own architecture; objects
+//! given with `--ebpf-object` must match the node.
+//!
+//! Reports record the host they were collected on and the BPF features it
+//! offered, so runs across a mixed fleet can be told apart, and the socket
+//! fields the probes read are located here from the running kernel's BTF.
+
+use crate::{
+    btf::Btf,
+    types::{AvailableFeatures, HostInfo},
+};
+use anyhow::Result;
+use probe_common::types::SockCommonLayout;
+
+/// Architecture and kernel release of this node
+pub fn current() -> HostInfo {
+    let kernel = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|release| release.trim().to_string())
+        .unwrap_or_default();
+    HostInfo {
+        arch: std::env::consts::ARCH.to_string(),
+        kernel,
+    }
+}
+
+/// BPF features the running kernel supports
+///
+/// Read from the enums of the kernel's BTF, which list every map type and
+/// attach type it knows. Without BTF, ring buffers are assumed from the
+/// kernel version (5.8) and fentry, which needs BTF, is unavailable.
+///
+/// # Arguments
+///
+/// * `btf` - Kernel BTF, if available
+/// * `kernel` - Kernel release, e.g. "6.1.0-18-amd64"
+pub fn available_features(btf: Option<&Btf>, kernel: &str) -> AvailableFeatures {
+    match btf {
+        Some(btf) => AvailableFeatures {
+            btf: true,
+            ringbuf: btf.has_enumerator("bpf_map_type", "BPF_MAP_TYPE_RINGBUF"),
+            fentry: btf.has_enumerator("bpf_attach_type", "BPF_TRACE_FENTRY"),
+        },
+        None => AvailableFeatures {
+            btf: false,
+            ringbuf: kernel_version(kernel).is_some_and(|version| version >= (5, 8)),
+            fentry: false,
+        },
+    }
+}
+
+/// Major and minor version of a kernel release
+fn kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Offsets of the struct sock_common fields the probes read
+///
+/// # Returns
+///
+/// The layout, or an error naming every field that is missing or lies
+/// beyond the bytes the probes read
+pub fn sock_common_layout(btf: &Btf) -> Result<SockCommonLayout> {
+    let mut missing = Vec::new();
+    let mut offset = |member: &str| -> u8 {
+        let offset = btf.member_offset("sock_common", member).and_then(|offset| {
+            u8::try_from(offset).map_err(|_| anyhow::anyhow!("sock_common.{} at offset {}", member, offset))
+        });
+        offset.unwrap_or_else(|e| {
+            missing.push(format!("{:#}", e));
+            0
+        })
+    };
+    let layout = SockCommonLayout {
+        daddr: offset("skc_daddr"),
+        rcv_saddr: offset("skc_rcv_saddr"),
+        dport: offset("skc_dport"),
+        num: offset("skc_num"),
+        family: offset("skc_family"),
+        state: offset("skc_state"),
+    };
+    if !missing.is_empty() {
+        anyhow::bail!("{}", missing.join("; "));
+    }
+    if !layout.fits() {
+        anyhow::bail!("sock_common fields lie beyond the bytes the probes read: {:?}", layout);
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_layout() {
+        assert_eq!(current().arch, std::env::consts::ARCH);
+
+        let layout = SockCommonLayout::DEFAULT;
+        assert!(layout.fits());
+        assert_eq!(SockCommonLayout::unpack(layout.pack()), Some(layout));
+        assert_eq!(SockCommonLayout::unpack(0), None);
+
+        assert_eq!(kernel_version("6.1.0-18-amd64"), Some((6, 1)));
+        assert_eq!(kernel_version("5.10"), Some((5, 10)));
+        assert_eq!(kernel_version(""), None);
+        assert_eq!(
+            available_features(None, "5.4.0-150-generic"),
+            AvailableFeatures {
+                btf: false,
+                ringbuf: false,
+                fentry: false,
+            }
+        );
+        assert!(available_features(None, "5.15.0-91-generic").ringbuf);
+
+        // The default matches the running kernel, on hosts that expose BTF
+        if let Ok(btf) = Btf::from_sys_fs() {
+            assert_eq!(sock_common_layout(&btf).unwrap(), layout);
+            let features = available_features(Some(&btf), &current().kernel);
+            assert!(features.btf && features.ringbuf);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Several probes on one node
+//!
+//! Two daemons on the same node (e.g. one per namespace, or a long-running
+//! probe next to a benchmark's own) each load their own copy of the eBPF
+//! objects, but share everything outside them. `--instance` names a probe
+//! so those shared resources stay apart:
+//!
+//! - maps pinned with `--pin-dir` go to a subdirectory named after the
+//!   instance, and a second probe with the same name fails to start
+//!   instead of replacing the first one's pins
+//! - cgroup programs attach alongside those of other instances
+//! - reports carry a `probe_instance` label, and the node_exporter textfile
+//!   is named after the instance, so the series of each probe are told
+//!   apart on a shared scrape
+//!
+//! Kernel map names come from the object and are the same for every
+//! instance; `bpftool map show pinned <pin-dir>/<instance>/<map>` finds the
+//! maps of one instance.
+
+use anyhow::{Context, Result};
+use log::warn;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Label of the instance name in exported metrics
+pub const INSTANCE_LABEL: &str = "probe_instance";
+
+/// Pin subdirectory of a probe started without `--instance`
+pub const DEFAULT_INSTANCE: &str = "default";
+
+/// Longest instance name
+const MAX_INSTANCE_LEN: usize = 32;
+
+/// Name of a probe instance
+///
+/// Lowercase letters, digits and `-`, starting with a letter or digit, so
+/// it is valid as a path component, label value, and InfluxDB tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceId(String);
+
+impl InstanceId {
+    /// Instance name
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for InstanceId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let valid = s.len() <= MAX_INSTANCE_LEN
+            && s.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid {
+            anyhow::bail!(
+                "Invalid instance name '{}': use up to {} lowercase letters, digits and '-'",
+                s,
+                MAX_INSTANCE_LEN
+            );
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for InstanceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Pin directory of an instance
+///
+/// # Arguments
+///
+/// * `root` - Directory on a bpffs mount holding the pins of every instance
+/// * `instance` - Instance name (None for [`DEFAULT_INSTANCE`])
+pub fn pin_dir(root: &Path, instance: Option<&InstanceId>) -> PathBuf {
+    root.join(instance.map_or(DEFAULT_INSTANCE, InstanceId::as_str))
+}
+
+/// Pins of an instance's maps, removed when dropped
+///
+/// The maps stay alive while pinned, so the pins go with the probe.
+pub struct MapPins {
+    dir: PathBuf,
+    pins: Vec<PathBuf>,
+}
+
+impl MapPins {
+    /// Claim an instance's pin directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Pin directory (see [`pin_dir`]); created if missing
+    ///
+    /// # Returns
+    ///
+    /// Empty set of pins, or an error if the directory already holds pins,
+    /// i.e. another probe with the same instance name is running (or one
+    /// was killed; remove the directory to recover)
+    pub fn claim(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create pin directory {:?}", dir))?;
+        let in_use = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read pin directory {:?}", dir))?
+            .next()
+            .is_some();
+        if in_use {
+            anyhow::bail!(
+                "Pin directory {:?} is in use; is another probe running with the same --instance?",
+                dir
+            );
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            pins: Vec::new(),
+        })
+    }
+
+    /// Directory holding the pins
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path to pin a map at, remembered for removal
+    ///
+    /// Qualified names of maps in several objects (`<object>/<map>`) are
+    /// pinned as `<object>.<map>`.
+    pub fn add(&mut self, name: &str) -> &Path {
+        self.pins.push(self.path(name));
+        &self.pins[self.pins.len() - 1]
+    }
+
+    /// Path a map is pinned at
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name.replace('/', "."))
+    }
+
+    /// Number of pins
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Whether nothing is pinned
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+}
+
+impl Drop for MapPins {
+    fn drop(&mut self) {
+        for pin in &self.pins {
+            if let Err(e) = std::fs::remove_file(pin) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove pin {:?}: {}", pin, e);
+                }
+            }
+        }
+        if let Err(e) = std::fs::remove_dir(&self.dir) {
+            warn!("Failed to remove pin directory {:?}: {}", self.dir, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_pins() {
+        assert_eq!("ns-a".parse::<InstanceId>().unwrap().as_str(), "ns-a");
+        for invalid in ["", "-a", "Ns", "ns/a", "ns_a", &"a".repeat(33)] {
+            assert!(invalid.parse::<InstanceId>().is_err(), "{:?} accepted", invalid);
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        let id: InstanceId = "ns-a".parse().unwrap();
+        let dir = pin_dir(root.path(), Some(&id));
+        assert_eq!(pin_dir(root.path(), None), root.path().join("default"));
+
+        // A pinned instance cannot be claimed twice, and its pins go with it
+        let mut pins = MapPins::claim(&dir).unwrap();
+        std::fs::write(pins.add("EVENTS"), b"").unwrap();
+        assert_eq!(pins.path("drops/STATS"), dir.join("drops.STATS"));
+        assert!(MapPins::claim(&dir).is_err());
+        drop(pins);
+        assert!(!dir.exists());
+        assert!(MapPins::claim(&dir).is_ok());
+    }
+}
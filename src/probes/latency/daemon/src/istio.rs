@@ -0,0 +1,187 @@
+//! Istio telemetry comparison
+//!
+//! With the `istio` feature and `--istio-prometheus`, the latency Istio's
+//! proxies reported for the run is pulled from Prometheus when the run
+//! ends: the percentiles of `istio_request_duration_milliseconds` over the
+//! run window, from `histogram_quantile` of the bucket increases. They are
+//! reported next to the kernel-observed percentiles, so mesh-reported and
+//! probe-measured latency can be compared in one report.
+//!
+//! Prometheus scrapes every 15 or 30 seconds, so the last moments of the
+//! run may be missing and short runs compare poorly. Percentiles are
+//! interpolated within Istio's buckets (0.5, 1, 5, 10, 25, 50, 100 ms and
+//! up), so they are only as precise as the buckets.
+
+use crate::types::{ClientPercentiles, MeshLatency, Percentiles};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Series compared by default: requests as the server-side proxies report
+/// them
+pub const DEFAULT_SELECTOR: &str = r#"reporter="destination""#;
+
+/// Histogram Istio records request durations in
+const METRIC: &str = "istio_request_duration_milliseconds";
+
+/// Timeout of a Prometheus query
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Percentiles reported
+const PERCENTILES: [f64; 5] = [50.0, 90.0, 95.0, 99.0, 99.9];
+
+#[derive(Deserialize)]
+struct QueryResponse {
+    status: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    data: Option<QueryData>,
+}
+
+#[derive(Deserialize)]
+struct QueryData {
+    #[serde(default)]
+    result: Vec<Sample>,
+}
+
+#[derive(Deserialize)]
+struct Sample {
+    /// Timestamp and value (a string, "NaN" without data)
+    value: (f64, String),
+}
+
+/// Query of a percentile over a window
+///
+/// # Arguments
+///
+/// * `percentile` - Percentile (e.g. 99.9)
+/// * `selector` - Label matchers of the series, e.g. `reporter="destination"`
+/// * `window_secs` - Length of the window ending at the query time
+fn percentile_query(percentile: f64, selector: &str, window_secs: u64) -> String {
+    format!(
+        "histogram_quantile({}, sum by (le) (increase({}_bucket{{{}}}[{}s])))",
+        percentile / 100.0,
+        METRIC,
+        selector,
+        window_secs
+    )
+}
+
+/// Query of the requests over a window
+fn requests_query(selector: &str, window_secs: u64) -> String {
+    format!("sum(increase({}_count{{{}}}[{}s]))", METRIC, selector, window_secs)
+}
+
+/// Single value of an instant query (None if no series matched, or the
+/// value is not a number)
+fn parse_value(json: &str) -> Result<Option<f64>> {
+    let response: QueryResponse = serde_json::from_str(json).context("Invalid Prometheus response")?;
+    if response.status != "success" {
+        anyhow::bail!("Query failed: {}", response.error.unwrap_or(response.status));
+    }
+    Ok(response
+        .data
+        .and_then(|data| data.result.into_iter().next())
+        .and_then(|sample| sample.value.1.parse::<f64>().ok())
+        .filter(|value| value.is_finite()))
+}
+
+/// Pulls Istio's request latency from Prometheus
+pub struct IstioTelemetry {
+    client: reqwest::Client,
+    prometheus: String,
+    selector: String,
+}
+
+impl IstioTelemetry {
+    /// Create a puller
+    ///
+    /// # Arguments
+    ///
+    /// * `prometheus` - Prometheus base URL, e.g. `http://prometheus.istio-system:9090`
+    /// * `selector` - Label matchers of the compared series
+    pub fn new(prometheus: &str, selector: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            client,
+            prometheus: prometheus.trim_end_matches('/').to_string(),
+            selector: selector.to_string(),
+        })
+    }
+
+    /// Istio's latency over the run, compared with the kernel's
+    ///
+    /// # Arguments
+    ///
+    /// * `window_secs` - Length of the run, ending now
+    /// * `kernel` - Kernel-observed percentiles of the run
+    pub async fn compare(&self, window_secs: u64, kernel: &Percentiles) -> Result<MeshLatency> {
+        let window_secs = window_secs.max(1);
+        let mut percentiles = ClientPercentiles::default();
+        for p in PERCENTILES {
+            if let Some(ms) = self.query(&percentile_query(p, &self.selector, window_secs)).await? {
+                percentiles.set(p, ms * 1000.0);
+            }
+        }
+        let requests = self.query(&requests_query(&self.selector, window_secs)).await?;
+
+        Ok(MeshLatency {
+            mesh: "istio".to_string(),
+            selector: self.selector.clone(),
+            window_seconds: window_secs,
+            requests: requests.map(|requests| requests.round() as u64),
+            above_kernel: percentiles.above(kernel),
+            percentiles,
+        })
+    }
+
+    /// Run an instant query
+    async fn query(&self, query: &str) -> Result<Option<f64>> {
+        let body = self
+            .client
+            .get(format!("{}/api/v1/query", self.prometheus))
+            .query(&[("query", query)])
+            .send()
+            .await
+            .with_context(|| format!("Failed to query {}", self.prometheus))?
+            .text()
+            .await
+            .with_context(|| format!("Failed to read the answer of {}", self.prometheus))?;
+        parse_value(&body).with_context(|| format!("Failed to query {}: {}", self.prometheus, query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_istio_queries() {
+        assert_eq!(
+            percentile_query(99.0, DEFAULT_SELECTOR, 300),
+            r#"histogram_quantile(0.99, sum by (le) (increase(istio_request_duration_milliseconds_bucket{reporter="destination"}[300s])))"#
+        );
+        assert_eq!(
+            requests_query(r#"destination_service_name="frontend""#, 60),
+            r#"sum(increase(istio_request_duration_milliseconds_count{destination_service_name="frontend"}[60s]))"#
+        );
+
+        let answer = |value: &str| {
+            format!(
+                r#"{{"status":"success","data":{{"resultType":"vector","result":[{{"metric":{{}},"value":[1700000000.5,"{}"]}}]}}}}"#,
+                value
+            )
+        };
+        assert_eq!(parse_value(&answer("12.5")).unwrap(), Some(12.5));
+        // No requests in the window
+        assert_eq!(parse_value(&answer("NaN")).unwrap(), None);
+        assert_eq!(parse_value(r#"{"status":"success","data":{"resultType":"vector","result":[]}}"#).unwrap(), None);
+
+        let error = parse_value(r#"{"status":"error","errorType":"bad_data","error":"parse error"}"#).unwrap_err();
+        assert!(error.to_string().contains("parse error"));
+    }
+}
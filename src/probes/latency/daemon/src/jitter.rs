@@ -0,0 +1,66 @@
+//! Latency jitter
+//!
+//! Mesh proxies can trade a lower mean latency for latency that varies
+//! more from request to request, which percentiles over the whole run do
+//! not show. Jitter is estimated per connection as in RFC 3550 (section
+//! 6.4.1): a running average of the difference between consecutive
+//! latencies, smoothed with a gain of 1/16.
+
+use serde::{Deserialize, Serialize};
+
+/// Weight of each new difference in the running average
+const GAIN: f64 = 1.0 / 16.0;
+
+/// Interarrival jitter estimator for one connection
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct JitterEstimator {
+    /// Previous latency sample (None before the first)
+    last_us: Option<f64>,
+    /// Current estimate in microseconds
+    jitter_us: f64,
+}
+
+impl JitterEstimator {
+    /// Account for the next latency sample of the connection
+    pub fn add(&mut self, latency_us: f64) {
+        if let Some(last_us) = self.last_us {
+            let difference = (latency_us - last_us).abs();
+            self.jitter_us += (difference - self.jitter_us) * GAIN;
+        }
+        self.last_us = Some(latency_us);
+    }
+
+    /// Current jitter estimate in microseconds (0 before two samples)
+    pub fn jitter_us(&self) -> f64 {
+        self.jitter_us
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_estimate() {
+        // Constant latency has no jitter, whatever its level
+        let mut steady = JitterEstimator::default();
+        for _ in 0..100 {
+            steady.add(5000.0);
+        }
+        assert_eq!(steady.jitter_us(), 0.0);
+
+        // Alternating latency converges on the step between samples
+        let mut alternating = JitterEstimator::default();
+        for i in 0..1000 {
+            alternating.add(if i % 2 == 0 { 100.0 } else { 300.0 });
+        }
+        assert!((alternating.jitter_us() - 200.0).abs() < 0.01);
+
+        // One sample gives no estimate; the first difference is smoothed
+        let mut short = JitterEstimator::default();
+        short.add(100.0);
+        assert_eq!(short.jitter_us(), 0.0);
+        short.add(260.0);
+        assert_eq!(short.jitter_us(), 10.0);
+    }
+}
@@ -0,0 +1,167 @@
+//! Streaming JSON Lines output
+//!
+//! The end-of-run report is lost if the probe crashes or is killed. The
+//! stream is written while collection proceeds instead: one line per
+//! sampled event (optional) and one per periodic metrics snapshot, flushed
+//! at every snapshot, so at most one snapshot interval of data is lost.
+//!
+//! Each line carries a `type` field:
+//!
+//! ```text
+//! {"type":"event","source":"10.0.0.1:80","destination":"10.0.0.2:41234","latency_ns":512000,...}
+//! {"type":"snapshot","timestamp":"2025-01-01T00:00:10+00:00","total_events":1200,...}
+//! ```
+//!
+//! Event lines use the [`RecordedEvent`] fields; snapshot lines are complete
+//! [`LatencyMetrics`] reports, cumulative since the start of the run (or the
+//! last rotation).
+
+use crate::{
+    compress::{Compression, OutputWriter},
+    replay::RecordedEvent,
+    run::RunId,
+    types::{LatencyEvent, LatencyMetrics},
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{io::Write, path::Path, sync::Mutex};
+
+/// One line of the stream
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamRecord<'a> {
+    Event(RecordedEvent),
+    Snapshot(&'a LatencyMetrics),
+}
+
+/// Writes events and metrics snapshots to a JSON Lines file as they arrive
+///
+/// Safe to share between reader tasks; register
+/// [`write_event`](Self::write_event) as an event callback to stream events.
+pub struct JsonLinesWriter {
+    writer: Mutex<OutputWriter>,
+    run_id: Option<RunId>,
+}
+
+impl JsonLinesWriter {
+    /// Create the stream, truncating any existing file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the JSON Lines output file
+    /// * `compression` - Compression to apply (flushed at every snapshot)
+    pub fn create(path: &Path, compression: Compression) -> Result<Self> {
+        let writer = OutputWriter::create(path, compression)
+            .with_context(|| format!("Failed to create stream file: {:?}", path))?;
+
+        Ok(Self {
+            writer: Mutex::new(writer),
+            run_id: None,
+        })
+    }
+
+    /// Stamp event lines with the ID of the run (snapshots carry the ID
+    /// of their report)
+    pub fn with_run_id(mut self, run_id: RunId) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Append one event to the stream
+    ///
+    /// Events are buffered until the next snapshot.
+    pub fn write_event(&self, event: &LatencyEvent) -> Result<()> {
+        let mut recorded = RecordedEvent::from(event);
+        recorded.run_id = self.run_id.as_ref().map(RunId::to_string);
+        self.write(&StreamRecord::Event(recorded), false)
+    }
+
+    /// Append a metrics snapshot and flush the stream to disk
+    pub fn write_snapshot(&self, metrics: &LatencyMetrics) -> Result<()> {
+        self.write(&StreamRecord::Snapshot(metrics), true)
+    }
+
+    /// End the stream (writes the trailer of a compressed stream)
+    ///
+    /// Nothing written afterwards reaches the file.
+    pub fn finish(&self) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Stream lock poisoned"))?;
+
+        writer.finish().context("Failed to finish stream file")
+    }
+
+    fn write(&self, record: &StreamRecord, flush: bool) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Stream lock poisoned"))?;
+
+        serde_json::to_writer(&mut *writer, record)?;
+        writer.write_all(b"\n")?;
+        if flush {
+            writer.flush().context("Failed to flush stream file")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+
+    #[test]
+    fn test_stream_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stream.jsonl");
+        let stream = JsonLinesWriter::create(&path, Compression::None)
+            .unwrap()
+            .with_run_id("nightly-42".parse().unwrap());
+
+        stream
+            .write_event(&LatencyEvent {
+                timestamp_ns: 1_000,
+                pid: 1,
+                ..testing::event(
+                    ConnectionKey {
+                        saddr: 0x0100000a, // 10.0.0.1 in network byte order
+                        daddr: 0x0200000a,
+                        sport: 0x5000, // Port 80 in network byte order
+                        dport: 0x901f,
+                    },
+                    512_000,
+                    probe_common::constants::EVENT_TYPE_RECV,
+                )
+            })
+            .unwrap();
+        stream
+            .write_snapshot(&LatencyMetrics {
+                total_events: 1,
+                run_id: Some("nightly-42".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Flushed by the snapshot, before the stream is finished
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "event");
+        assert_eq!(lines[0]["source"], "10.0.0.1:80");
+        assert_eq!(lines[0]["latency_ns"], 512_000);
+        assert_eq!(lines[0]["run_id"], "nightly-42");
+        assert_eq!(lines[1]["type"], "snapshot");
+        assert_eq!(lines[1]["total_events"], 1);
+        assert_eq!(lines[1]["run_id"], "nightly-42");
+
+        stream.finish().unwrap();
+    }
+}
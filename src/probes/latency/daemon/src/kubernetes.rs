@@ -0,0 +1,289 @@
+//! Kubernetes pod filter
+//!
+//! With the `kubernetes` feature, `--k8s-namespace` (and optionally
+//! `--k8s-selector`) restrict the probe to the pods of one workload. Their
+//! IPs are listed from the API server with the in-cluster service account
+//! and written to the kernel SERVICE_FILTER map as `IP:*` entries. The list
+//! is polled for the whole run, so pods rescheduled during a benchmark (new
+//! IPs after a rollout, eviction or scale-out) stay in the filter.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    time::{interval_at, Instant},
+};
+
+/// Mount point of the pod's service account credentials
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Timeout of an API request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pods to track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PodSelector {
+    /// Namespace of the pods
+    pub namespace: String,
+    /// Label selector (e.g. `app=frontend`); None selects every pod
+    pub labels: Option<String>,
+}
+
+impl std::fmt::Display for PodSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.labels {
+            Some(labels) => write!(f, "{}/{}", self.namespace, labels),
+            None => write!(f, "{}/*", self.namespace),
+        }
+    }
+}
+
+/// Response of the pod list endpoint, reduced to the fields used
+#[derive(Deserialize)]
+struct PodList {
+    #[serde(default)]
+    items: Vec<Pod>,
+}
+
+#[derive(Deserialize)]
+struct Pod {
+    #[serde(default)]
+    spec: PodSpec,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PodSpec {
+    #[serde(default)]
+    host_network: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PodStatus {
+    #[serde(default)]
+    phase: String,
+    #[serde(default, rename = "podIPs")]
+    pod_ips: Vec<PodIp>,
+}
+
+#[derive(Deserialize)]
+struct PodIp {
+    ip: String,
+}
+
+/// Extract the IPv4 addresses of running pods from a pod list response
+///
+/// Host network pods are skipped: their IP is the node's, and would match
+/// every connection on it.
+pub fn parse_pod_ips(body: &str) -> Result<BTreeSet<Ipv4Addr>> {
+    let list: PodList = serde_json::from_str(body).context("Failed to parse pod list")?;
+
+    Ok(list
+        .items
+        .iter()
+        .filter(|pod| pod.status.phase == "Running" && !pod.spec.host_network)
+        .flat_map(|pod| &pod.status.pod_ips)
+        .filter_map(|ip| ip.ip.parse().ok())
+        .collect())
+}
+
+/// Service filter entries matching any port of the given pods
+pub fn pod_services(ips: &BTreeSet<Ipv4Addr>) -> Vec<String> {
+    ips.iter().map(|ip| format!("{}:*", ip)).collect()
+}
+
+/// Namespace of the pod the probe runs in, from its service account
+pub fn pod_namespace() -> Result<String> {
+    let path = Path::new(SERVICE_ACCOUNT_DIR).join("namespace");
+    let namespace =
+        std::fs::read_to_string(&path).with_context(|| format!("Failed to read the pod namespace: {:?}", path))?;
+    Ok(namespace.trim().to_string())
+}
+
+/// Client for the API server of the cluster the probe runs in
+pub struct KubeClient {
+    client: reqwest::Client,
+    base_url: String,
+    token_path: PathBuf,
+}
+
+impl KubeClient {
+    /// Create a client from the in-cluster environment
+    ///
+    /// Uses `KUBERNETES_SERVICE_HOST`/`KUBERNETES_SERVICE_PORT` and the
+    /// mounted service account, which needs `list` on `pods` in the
+    /// selected namespace.
+    pub fn in_cluster() -> Result<Self> {
+        let host = std::env::var("KUBERNETES_SERVICE_HOST")
+            .context("KUBERNETES_SERVICE_HOST not set (not running in a pod?)")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        let host = if host.contains(':') { format!("[{}]", host) } else { host };
+
+        Self::new(&format!("https://{}:{}", host, port), Path::new(SERVICE_ACCOUNT_DIR))
+    }
+
+    /// Create a client for an API server
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - API server URL
+    /// * `credentials` - Directory holding `token` and `ca.crt`
+    pub fn new(base_url: &str, credentials: &Path) -> Result<Self> {
+        let ca_path = credentials.join("ca.crt");
+        let ca = std::fs::read(&ca_path).with_context(|| format!("Failed to read CA: {:?}", ca_path))?;
+        let ca = reqwest::Certificate::from_pem(&ca).with_context(|| format!("Invalid CA: {:?}", ca_path))?;
+
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .add_root_certificate(ca)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token_path: credentials.join("token"),
+        })
+    }
+
+    /// Request to an API path, authorized with the service account token
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - HTTP method
+    /// * `path` - Path below the API server URL, e.g. `/api/v1/namespaces/default/pods`
+    pub(crate) fn request(&self, method: reqwest::Method, path: &str) -> Result<reqwest::RequestBuilder> {
+        // Projected tokens are rotated by the kubelet, so read it every time
+        let token = std::fs::read_to_string(&self.token_path)
+            .with_context(|| format!("Failed to read service account token: {:?}", self.token_path))?;
+
+        Ok(self
+            .client
+            .request(method, format!("{}{}", self.base_url, path))
+            .bearer_auth(token.trim()))
+    }
+
+    /// List the IPv4 addresses of the running pods matching a selector
+    pub async fn list_pod_ips(&self, selector: &PodSelector) -> Result<BTreeSet<Ipv4Addr>> {
+        let mut request = self.request(
+            reqwest::Method::GET,
+            &format!("/api/v1/namespaces/{}/pods", selector.namespace),
+        )?;
+        if let Some(labels) = &selector.labels {
+            request = request.query(&[("labelSelector", labels)]);
+        }
+
+        let body = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to list pods in {}", selector))?
+            .text()
+            .await
+            .context("Failed to read pod list")?;
+
+        parse_pod_ips(&body)
+    }
+}
+
+/// Poll the pods of a selector and send the new filter entries whenever
+/// their IPs change
+///
+/// An empty result keeps the previous filter, since an empty filter would
+/// track every connection; a rollout briefly having no running pods should
+/// not widen the filter.
+///
+/// # Arguments
+///
+/// * `client` - API client
+/// * `selector` - Pods to track
+/// * `current` - Pod IPs already applied
+/// * `interval_secs` - Seconds between polls
+///
+/// # Returns
+///
+/// A receiver of the service filter entries of each new pod IP set
+pub fn spawn_pod_watcher(
+    client: KubeClient,
+    selector: PodSelector,
+    mut current: BTreeSet<Ipv4Addr>,
+    interval_secs: u64,
+) -> mpsc::Receiver<Vec<String>> {
+    let (sender, receiver) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let period = Duration::from_secs(interval_secs.max(1));
+        let mut ticker = interval_at(Instant::now() + period, period);
+
+        loop {
+            ticker.tick().await;
+
+            let ips = match client.list_pod_ips(&selector).await {
+                Ok(ips) => ips,
+                Err(e) => {
+                    warn!("{:#}", e);
+                    continue;
+                }
+            };
+            if ips == current {
+                continue;
+            }
+            if ips.is_empty() {
+                warn!("No running pods match {}, keeping the previous filter", selector);
+                continue;
+            }
+
+            debug!("Pods of {} changed: {:?} -> {:?}", selector, current, ips);
+            info!("Pods of {} rescheduled, tracking {} pod IP(s)", selector, ips.len());
+            if sender.send(pod_services(&ips)).await.is_err() {
+                return;
+            }
+            current = ips;
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pod_ips() {
+        let body = r#"{
+            "kind": "PodList",
+            "items": [
+                {"spec": {}, "status": {"phase": "Running", "podIP": "10.244.1.5",
+                    "podIPs": [{"ip": "10.244.1.5"}, {"ip": "fd00::5"}]}},
+                {"spec": {}, "status": {"phase": "Running", "podIPs": [{"ip": "10.244.2.7"}]}},
+                {"spec": {}, "status": {"phase": "Pending"}},
+                {"spec": {}, "status": {"phase": "Succeeded", "podIPs": [{"ip": "10.244.1.9"}]}},
+                {"spec": {"hostNetwork": true}, "status": {"phase": "Running", "podIPs": [{"ip": "192.168.0.10"}]}}
+            ]
+        }"#;
+
+        let ips = parse_pod_ips(body).unwrap();
+        assert_eq!(
+            ips.iter().copied().collect::<Vec<_>>(),
+            vec![Ipv4Addr::new(10, 244, 1, 5), Ipv4Addr::new(10, 244, 2, 7)]
+        );
+        assert_eq!(pod_services(&ips), vec!["10.244.1.5:*", "10.244.2.7:*"]);
+        for service in pod_services(&ips) {
+            assert_eq!(crate::config::parse_service(&service).unwrap().port, 0);
+        }
+
+        assert!(parse_pod_ips(r#"{"items": []}"#).unwrap().is_empty());
+        assert!(parse_pod_ips("not json").is_err());
+    }
+}
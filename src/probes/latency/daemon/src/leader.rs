@@ -0,0 +1,411 @@
+//! Leader election
+//!
+//! With `--cluster-lease`, the probes of a DaemonSet elect the aggregator
+//! of the cluster (see crate::aggregate) with a Kubernetes Lease
+//! (coordination.k8s.io/v1), the lock client-go's leader election uses.
+//! Each probe is identified by the address the others push reports to.
+//!
+//! The leader renews the lease every third of its duration. The others
+//! take it over once it has gone unrenewed for a whole duration, so a
+//! leader that dies or loses the API server is replaced within about two
+//! durations. A leader that cannot renew stops aggregating after one
+//! duration by itself. Expiry is judged by when a probe saw the lease
+//! change, not by the leader's timestamps, so clock skew between nodes
+//! does not matter.
+//!
+//! The service account needs `get`, `create` and `update` on `leases` in
+//! the namespace of the lease.
+
+use crate::{
+    aggregate::Aggregate,
+    exporter::{JsonExporter, MetricsExporter},
+    kubernetes::KubeClient,
+    publish::{AsyncMetricsExporter, ExportFuture},
+    types::LatencyMetrics,
+};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Time a lease stays valid without renewal by default
+pub const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(15);
+
+/// Timeout of a push to the aggregator
+const PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Spec of a Lease, as far as leader election uses it
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaseSpec {
+    /// Identity of the leader
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holder_identity: Option<String>,
+    /// Seconds the lease stays valid without renewal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_duration_seconds: Option<u64>,
+    /// When the leader acquired the lease
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acquire_time: Option<String>,
+    /// When the leader last renewed the lease
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renew_time: Option<String>,
+    /// Times the lease changed hands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_transitions: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Lease {
+    metadata: LeaseMetadata,
+    #[serde(default)]
+    spec: LeaseSpec,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LeaseMetadata {
+    resource_version: String,
+}
+
+/// What a probe does with the lease
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Renew the lease it holds
+    Renew,
+    /// Take the lease, which is free or expired
+    Acquire,
+    /// Leave the lease to its holder
+    Follow(String),
+}
+
+/// Lease last seen, and when it was first seen that way
+struct Observed {
+    holder: Option<String>,
+    renew_time: Option<String>,
+    at: Instant,
+}
+
+/// Judge of the lease for one probe
+pub struct LeaseObserver {
+    identity: String,
+    duration: Duration,
+    observed: Option<Observed>,
+}
+
+impl LeaseObserver {
+    /// Judge the lease for a probe
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identity of the probe
+    /// * `duration` - Time a lease without a duration of its own stays valid
+    pub fn new(identity: &str, duration: Duration) -> Self {
+        Self {
+            identity: identity.to_string(),
+            duration,
+            observed: None,
+        }
+    }
+
+    /// Decide what to do with the lease
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Spec of the lease as just read
+    /// * `now` - Time it was read
+    pub fn decide(&mut self, spec: &LeaseSpec, now: Instant) -> Decision {
+        let changed = self
+            .observed
+            .as_ref()
+            .is_none_or(|seen| seen.holder != spec.holder_identity || seen.renew_time != spec.renew_time);
+        if changed {
+            self.observed = Some(Observed {
+                holder: spec.holder_identity.clone(),
+                renew_time: spec.renew_time.clone(),
+                at: now,
+            });
+        }
+        let unchanged_for = self.observed.as_ref().map_or(Duration::ZERO, |seen| now - seen.at);
+        let duration = spec.lease_duration_seconds.map_or(self.duration, Duration::from_secs);
+
+        match spec.holder_identity.as_deref() {
+            Some(holder) if holder == self.identity => Decision::Renew,
+            Some(holder) if !holder.is_empty() && unchanged_for < duration => Decision::Follow(holder.to_string()),
+            _ => Decision::Acquire,
+        }
+    }
+}
+
+/// Leader election of one probe
+pub struct LeaderElection {
+    client: KubeClient,
+    namespace: String,
+    name: String,
+    identity: String,
+    duration: Duration,
+    observer: LeaseObserver,
+}
+
+impl LeaderElection {
+    /// Take part in the election of a lease
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - API client
+    /// * `namespace` - Namespace of the lease
+    /// * `name` - Name of the lease, created if missing
+    /// * `identity` - Address other probes push reports to (HOST:PORT)
+    /// * `duration` - Time the lease stays valid without renewal
+    pub fn new(client: KubeClient, namespace: &str, name: &str, identity: &str, duration: Duration) -> Self {
+        let duration = duration.max(Duration::from_secs(3));
+        Self {
+            client,
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            identity: identity.to_string(),
+            duration,
+            observer: LeaseObserver::new(identity, duration),
+        }
+    }
+
+    /// Read the lease, and create, renew or take it over when due
+    ///
+    /// # Returns
+    ///
+    /// Identity of the leader (None if another probe changed the lease first)
+    async fn step(&mut self) -> Result<Option<String>> {
+        let leases = format!("/apis/coordination.k8s.io/v1/namespaces/{}/leases", self.namespace);
+        let path = format!("{}/{}", leases, self.name);
+        let response = self
+            .client
+            .request(Method::GET, &path)?
+            .send()
+            .await
+            .with_context(|| format!("Failed to read lease {}/{}", self.namespace, self.name))?;
+
+        let (method, path, resource_version, spec) = if response.status() == StatusCode::NOT_FOUND {
+            (Method::POST, leases, None, self.acquired(None))
+        } else {
+            let lease: Lease = response
+                .error_for_status()
+                .with_context(|| format!("Failed to read lease {}/{}", self.namespace, self.name))?
+                .json()
+                .await
+                .context("Failed to parse lease")?;
+            let spec = match self.observer.decide(&lease.spec, Instant::now()) {
+                Decision::Follow(holder) => return Ok(Some(holder)),
+                Decision::Renew => LeaseSpec {
+                    renew_time: Some(micro_time()),
+                    lease_duration_seconds: Some(self.duration.as_secs()),
+                    ..lease.spec
+                },
+                Decision::Acquire => self.acquired(Some(&lease.spec)),
+            };
+            (Method::PUT, path, Some(lease.metadata.resource_version), spec)
+        };
+
+        // The resource version makes the write fail if another probe wrote first
+        let lease = serde_json::json!({
+            "apiVersion": "coordination.k8s.io/v1",
+            "kind": "Lease",
+            "metadata": {
+                "name": self.name,
+                "namespace": self.namespace,
+                "resourceVersion": resource_version,
+            },
+            "spec": spec,
+        });
+        let response = self
+            .client
+            .request(method, &path)?
+            .json(&lease)
+            .send()
+            .await
+            .with_context(|| format!("Failed to write lease {}/{}", self.namespace, self.name))?;
+        match response.status() {
+            status if status.is_success() => Ok(Some(self.identity.clone())),
+            StatusCode::CONFLICT => Ok(None),
+            status => anyhow::bail!("Failed to write lease {}/{}: {}", self.namespace, self.name, status),
+        }
+    }
+
+    /// Spec of the lease taken by this probe
+    fn acquired(&self, previous: Option<&LeaseSpec>) -> LeaseSpec {
+        let now = micro_time();
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.duration.as_secs()),
+            acquire_time: Some(now.clone()),
+            renew_time: Some(now),
+            lease_transitions: Some(previous.map_or(0, |spec| spec.lease_transitions.unwrap_or(0) + 1)),
+        }
+    }
+
+    /// Take part in the election for the rest of the run
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate` - Aggregate told whether this probe leads
+    ///
+    /// # Returns
+    ///
+    /// A receiver of the identity of the leader (None while unknown)
+    pub fn spawn(mut self, aggregate: Arc<Aggregate>) -> watch::Receiver<Option<String>> {
+        let (sender, receiver) = watch::channel(None);
+
+        tokio::spawn(async move {
+            let period = self.duration / 3;
+            let mut renewed: Option<Instant> = None;
+            loop {
+                let current = sender.borrow().clone();
+                let leader = match self.step().await {
+                    Ok(leader) => leader,
+                    // Keep the known leader, but stop leading once the lease may have expired
+                    Err(e) => {
+                        warn!("Leader election: {:#}", e);
+                        let expired = renewed.is_none_or(|at| at.elapsed() >= self.duration);
+                        current.clone().filter(|leader| *leader != self.identity || !expired)
+                    }
+                };
+                let leading = leader.as_deref() == Some(self.identity.as_str());
+                if leading {
+                    renewed = Some(Instant::now());
+                    if current != leader {
+                        info!("Elected aggregator of the cluster (lease {}/{})", self.namespace, self.name);
+                    }
+                } else if leader != current {
+                    match &leader {
+                        Some(leader) => info!("Aggregator of the cluster: {}", leader),
+                        None => warn!("No aggregator of the cluster known"),
+                    }
+                }
+                aggregate.set_leading(leading);
+                sender.send_replace(leader);
+
+                if sender.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(period).await;
+            }
+        });
+
+        receiver
+    }
+}
+
+/// Current time in the MicroTime format of the Kubernetes API
+fn micro_time() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string()
+}
+
+/// Exporter handing reports to the aggregator of the cluster
+///
+/// On the aggregator, reports go into its [`Aggregate`], and the merged
+/// report is written to the output, if any. Other probes push them to the
+/// aggregator's HTTP API. Exports fail while no aggregator is known, and
+/// are retried like those of any destination.
+pub struct ClusterExporter {
+    identity: String,
+    leader: watch::Receiver<Option<String>>,
+    aggregate: Arc<Aggregate>,
+    output: Option<JsonExporter>,
+    client: reqwest::Client,
+}
+
+impl ClusterExporter {
+    /// Create the exporter of one probe
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identity of this probe in the election
+    /// * `leader` - Leader of the election (see [`LeaderElection::spawn`])
+    /// * `aggregate` - Aggregate served by this probe's HTTP API
+    /// * `output` - File the aggregator writes the merged report to
+    pub fn new(
+        identity: &str,
+        leader: watch::Receiver<Option<String>>,
+        aggregate: Arc<Aggregate>,
+        output: Option<PathBuf>,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(PUSH_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            identity: identity.to_string(),
+            leader,
+            aggregate,
+            output: output.map(|path| JsonExporter::new(path, true)),
+            client,
+        })
+    }
+}
+
+impl AsyncMetricsExporter for ClusterExporter {
+    fn export<'a>(&'a self, metrics: &'a LatencyMetrics) -> ExportFuture<'a> {
+        Box::pin(async move {
+            let leader = self.leader.borrow().clone();
+            match leader {
+                Some(leader) if leader == self.identity => {
+                    self.aggregate.submit(&self.identity, metrics.clone());
+                    match (&self.output, self.aggregate.merged()) {
+                        (Some(output), Some(merged)) => output.export(&merged),
+                        _ => Ok(()),
+                    }
+                }
+                // Identities are HOST:PORT, which need no escaping in a query
+                Some(leader) => self
+                    .client
+                    .post(format!("http://{}/reports?from={}", leader, self.identity))
+                    .json(metrics)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map(|_| ())
+                    .with_context(|| format!("Failed to push the report to the aggregator {}", leader)),
+                None => anyhow::bail!("No aggregator of the cluster elected yet"),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_decision() {
+        let mut election = LeaseObserver::new("10.0.0.1:9100", Duration::from_secs(15));
+
+        let lease = |holder: Option<&str>, renew_time: &str| LeaseSpec {
+            holder_identity: holder.map(str::to_string),
+            lease_duration_seconds: Some(15),
+            renew_time: Some(renew_time.to_string()),
+            ..Default::default()
+        };
+        let start = Instant::now();
+
+        // A free lease is taken, our own renewed
+        assert_eq!(election.decide(&LeaseSpec::default(), start), Decision::Acquire);
+        assert_eq!(election.decide(&lease(Some("10.0.0.1:9100"), "t0"), start), Decision::Renew);
+
+        // Another probe's lease is followed while it renews it
+        let other = Some("10.0.0.2:9100");
+        let follow = Decision::Follow("10.0.0.2:9100".to_string());
+        assert_eq!(election.decide(&lease(other, "t1"), start), follow);
+        assert_eq!(election.decide(&lease(other, "t1"), start + Duration::from_secs(10)), follow);
+        assert_eq!(election.decide(&lease(other, "t2"), start + Duration::from_secs(20)), follow);
+
+        // ... and taken over once unrenewed for its duration, whatever its timestamps say
+        assert_eq!(election.decide(&lease(other, "t2"), start + Duration::from_secs(34)), follow);
+        assert_eq!(election.decide(&lease(other, "t2"), start + Duration::from_secs(35)), Decision::Acquire);
+        assert_eq!(election.decide(&lease(Some(""), "t2"), start + Duration::from_secs(35)), Decision::Acquire);
+    }
+}
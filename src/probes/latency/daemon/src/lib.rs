@@ -2,14 +2,92 @@
 //!
 //! Provides reusable components for loading and managing the eBPF latency probe.
 
+pub mod aggregate;
+pub mod api;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod backpressure;
+pub mod btf;
+pub mod burst;
+pub mod checkpoint;
+pub mod clock;
 pub mod collector;
+pub mod compress;
+pub mod config;
+pub mod conntrack;
+pub mod correlation;
+#[cfg(feature = "kubernetes")]
+pub mod controller;
+pub mod counters;
+pub mod daemon;
+pub mod dedup;
+pub mod digest;
+#[cfg(feature = "envoy")]
+pub mod envoy;
+pub mod environment;
 pub mod events;
 pub mod exporter;
+pub mod faults;
+pub mod health;
+pub mod host;
+pub mod instance;
+#[cfg(feature = "istio")]
+pub mod istio;
+pub mod jitter;
+pub mod jsonl;
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+#[cfg(feature = "kubernetes")]
+pub mod leader;
+pub mod listen;
 pub mod loader;
+pub mod loadgen;
+pub mod merge;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod netns;
+#[cfg(feature = "webhook")]
+pub mod notifier;
+pub mod objects;
+pub mod omission;
+pub mod openmetrics;
+pub mod output;
+pub mod pods;
+pub mod privileges;
+pub mod process;
+#[cfg(feature = "proto")]
+pub mod proto;
+pub mod publish;
+pub mod registry;
+pub mod replay;
+pub mod run;
+pub mod scenario;
+#[cfg(feature = "seal")]
+pub mod seal;
+pub mod selftest;
+pub mod services;
+pub mod stitch;
+pub mod tail;
+pub mod tenants;
+pub mod timesync;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
+pub mod textfile;
+pub mod tracefs;
+pub mod trigger;
 pub mod types;
+#[cfg(feature = "upload")]
+pub mod upload;
+pub mod variance;
+pub mod verify;
+pub mod window;
+pub mod zabbix;
+pub mod zones;
 
 pub use collector::MetricsCollector;
-pub use events::EventProcessor;
+pub use config::ProbeConfig;
+pub use events::{EventCallback, EventProcessor, EventStream, PerfBufferOptions, ReaderPlacement};
 pub use exporter::{ExporterType, JsonExporter, MetricsExporter};
 pub use loader::ProbeLoader;
+pub use replay::{EventRecorder, RecordedEvent};
 pub use types::*;
@@ -0,0 +1,178 @@
+//! Accept queue overflow detection
+//!
+//! A listener whose application does not accept() fast enough fills its
+//! accept queue, and the kernel then drops connection requests
+//! (ListenOverflows in `nstat`). Clients see this as SYN retransmissions,
+//! adding a second or more to their connection time. Overloaded sidecar
+//! listeners show it before their latency does.
+//!
+//! The tcp_conn_request and tcp_v4/v6_syn_recv_sock kprobes read the
+//! accept queue length and limit of the listening socket at offsets
+//! resolved here from BTF, and count requests and overflows per listener in the
+//! LISTENERS map. [`ListenQueues`] turns readings of the map into counts
+//! per listening port.
+
+use crate::{
+    btf::Btf,
+    types::{per_second, ListenerMetrics},
+};
+use anyhow::Result;
+use probe_common::types::{ListenerKey, ListenerStats};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Kernel struct offsets needed to read the queues of a listening socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenQueueOffsets {
+    /// Offset of `sk_ack_backlog` in `struct sock`
+    pub ack_backlog: u32,
+    /// Offset of `sk_max_ack_backlog` in `struct sock`
+    pub max_ack_backlog: u32,
+    /// Offset of `icsk_accept_queue.qlen` in `struct inet_connection_sock`
+    pub syn_queue_len: Option<u32>,
+}
+
+impl ListenQueueOffsets {
+    /// Resolve the offsets from kernel BTF
+    pub fn from_btf(btf: &Btf) -> Result<Self> {
+        let syn_queue_len = btf
+            .member_offset("inet_connection_sock", "icsk_accept_queue")
+            .and_then(|queue| Ok(queue + btf.member_offset("request_sock_queue", "qlen")?))
+            .ok();
+        Ok(Self {
+            ack_backlog: btf.member_offset("sock", "sk_ack_backlog")?,
+            max_ack_backlog: btf.member_offset("sock", "sk_max_ack_backlog")?,
+            syn_queue_len,
+        })
+    }
+}
+
+/// Counters of one listener during an interval
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+struct IntervalListener {
+    requests: u64,
+    overflows: u64,
+    syn_queue_full: u64,
+    max_backlog: u32,
+    backlog_limit: u32,
+}
+
+/// Accept queue counters of the listeners, per interval
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ListenQueues {
+    /// Cumulative (requests, overflows, SYN queue full) counters at the
+    /// last reading, by (namespace, port)
+    last: HashMap<(u32, u16), (u64, u64, u64)>,
+    /// Counters since the interval started, by (namespace, port)
+    interval: HashMap<(u32, u16), IntervalListener>,
+}
+
+impl ListenQueues {
+    /// Add a reading of the LISTENERS map
+    ///
+    /// The kernel counters are cumulative, so the counts since the previous
+    /// reading are added to this interval. Listeners missing from the
+    /// reading (closed and evicted) are forgotten.
+    ///
+    /// # Arguments
+    ///
+    /// * `readings` - Every entry of the LISTENERS map
+    pub fn add(&mut self, readings: &[(ListenerKey, ListenerStats)]) {
+        let mut last = HashMap::with_capacity(readings.len());
+        for (key, stats) in readings {
+            let id = (key.netns, key.port);
+            let current = (stats.requests, stats.overflows, stats.syn_queue_full);
+            // Counters below the last reading belong to a new listener on
+            // the port
+            let (requests, overflows, syn_queue_full) = match self.last.get(&id) {
+                Some(&previous) if current.0 >= previous.0 && current.1 >= previous.1 && current.2 >= previous.2 => {
+                    (current.0 - previous.0, current.1 - previous.1, current.2 - previous.2)
+                }
+                _ => current,
+            };
+            last.insert(id, current);
+            if requests + overflows + syn_queue_full == 0 {
+                continue;
+            }
+
+            let listener = self.interval.entry(id).or_default();
+            listener.requests += requests;
+            listener.overflows += overflows;
+            listener.syn_queue_full += syn_queue_full;
+            listener.max_backlog = listener.max_backlog.max(stats.max_backlog);
+            listener.backlog_limit = stats.backlog_limit;
+        }
+        self.last = last;
+    }
+
+    /// Start a new interval, carrying over the last reading
+    pub fn rotate(&mut self) -> Self {
+        Self {
+            last: std::mem::take(&mut self.last),
+            interval: HashMap::new(),
+        }
+    }
+
+    /// Counters of the interval per listening port
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed_secs` - Length of the interval
+    pub fn report(&self, elapsed_secs: u64) -> BTreeMap<String, ListenerMetrics> {
+        let mut ports: BTreeMap<u16, ListenerMetrics> = BTreeMap::new();
+        for (&(_, port), listener) in &self.interval {
+            let metrics = ports.entry(port).or_default();
+            metrics.listeners += 1;
+            metrics.requests += listener.requests;
+            metrics.overflows += listener.overflows;
+            metrics.syn_queue_full += listener.syn_queue_full;
+            metrics.max_backlog = metrics.max_backlog.max(listener.max_backlog);
+            metrics.backlog_limit = metrics.backlog_limit.max(listener.backlog_limit);
+        }
+        ports
+            .into_iter()
+            .map(|(port, mut metrics)| {
+                metrics.overflows_per_second = per_second(metrics.overflows, elapsed_secs);
+                (port.to_string(), metrics)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_queues() {
+        let key = |netns, port| ListenerKey { netns, port, _padding: [0; 2] };
+        let stats = |requests, overflows, max_backlog| ListenerStats {
+            requests,
+            overflows,
+            syn_queue_full: 0,
+            max_backlog,
+            backlog_limit: 128,
+        };
+
+        // Two sidecars listening on the same port, in their own namespaces
+        let mut queues = ListenQueues::default();
+        queues.add(&[(key(1, 15006), stats(100, 0, 3)), (key(2, 15006), stats(50, 0, 1))]);
+        let report = queues.report(10);
+        assert_eq!(report.len(), 1);
+        assert_eq!((report["15006"].listeners, report["15006"].requests, report["15006"].overflows), (2, 150, 0));
+
+        // Only the increase counts towards the next interval
+        let mut queues = queues.rotate();
+        queues.add(&[(key(1, 15006), stats(400, 20, 129)), (key(2, 15006), stats(50, 0, 1))]);
+        let report = queues.report(10);
+        assert_eq!(report["15006"].listeners, 1);
+        assert_eq!((report["15006"].requests, report["15006"].overflows), (300, 20));
+        assert_eq!(report["15006"].overflows_per_second, 2.0);
+        assert_eq!((report["15006"].max_backlog, report["15006"].backlog_limit), (129, 128));
+
+        // A new listener reusing the port counts from zero
+        let mut queues = queues.rotate();
+        queues.add(&[(key(1, 15006), stats(5, 0, 0))]);
+        assert_eq!(queues.report(10)["15006"].requests, 5);
+    }
+}
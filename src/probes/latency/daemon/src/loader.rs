@@ -1,17 +1,47 @@
 //! eBPF program loader
 //!
 //! Handles loading the eBPF program and attaching kprobes, tracepoints, and XDP programs.
+//! The probe may be split across several objects (see [`crate::objects`]); the
+//! latency object is the primary one that the `attach_*` methods work on.
 
 use anyhow::{Context, Result};
 use aya::{
-    maps::{perf::AsyncPerfEventArray, HashMap as BpfHashMap, MapData},
-    programs::{KProbe, TracePoint, Xdp, XdpFlags},
-    Bpf,
+    maps::{
+        perf::AsyncPerfEventArray, Array, HashMap as BpfHashMap, Map, MapData, PerCpuHashMap,
+        ProgramArray, SockHash,
+    },
+    programs::{links::CgroupAttachMode, KProbe, Program, SkMsg, SockOps, TracePoint, Xdp, XdpFlags},
+    Ebpf, EbpfLoader,
 };
-use log::{info, warn};
-use std::path::PathBuf;
+use aya_log::EbpfLogger;
+use log::{debug, info, warn};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    clock::ClockSource,
+    config::{ProbeConfig, SAMPLED_EVENT_TYPES},
+    instance::{pin_dir, InstanceId, MapPins},
+    listen::ListenQueueOffsets,
+    netns::NetnsOffsets,
+    objects::{
+        qualify_names, AttachTarget, MapHandle, ObjectHandle, ObjectId, ObjectKind,
+        ObjectManifest, ProgramHandle,
+    },
+    tracefs::TcpProbeOffsets,
+    types::{kernel::{CongestionState, ConnectionState, ListenerKey, ListenerStats}, ConnectionKey, InternalCounters, LatencyBounds, MapHealth, ProbeAttachment, ProgramStats, XdpPacketStats},
+    verify,
+};
+use probe_common::types::{ServiceFilterKey, SockCommonLayout};
 
-use crate::types::XdpPacketStats;
+/// eBPF object compiled by build.rs (see the `embedded` feature)
+#[cfg(feature = "embedded")]
+static EMBEDDED_OBJECT: &[u8] = aya::include_bytes_aligned!(env!("LATENCY_PROBE_EBPF_OBJECT"));
 
 /// Result of attaching an optional eBPF program
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,9 +52,166 @@ pub enum AttachResult {
     NotFound,
 }
 
+/// Attempts per symbol before moving to the next fallback
+const ATTACH_ATTEMPTS: u32 = 3;
+
+/// Delay between attach attempts
+const ATTACH_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Kprobe program and the kernel symbols it can attach to
+#[derive(Debug, Clone, Copy)]
+pub struct KprobeSpec {
+    /// Program name in the eBPF object
+    pub program: &'static str,
+    /// Candidate symbols in order of preference
+    pub symbols: &'static [&'static str],
+    /// Abort the run if the program cannot be attached
+    pub required: bool,
+}
+
+/// Kprobes attached by [`ProbeLoader::attach_kprobes`]
+///
+/// Fallbacks take the socket as their first argument like the preferred
+/// symbol, so the programs read the same arguments either way.
+pub const KPROBES: &[KprobeSpec] = &[
+    KprobeSpec { program: "tcp_sendmsg", symbols: &["tcp_sendmsg", "tcp_sendmsg_locked"], required: true },
+    KprobeSpec { program: "tcp_recvmsg", symbols: &["tcp_recvmsg", "tcp_recvmsg_locked"], required: true },
+    // Inlined into tcp_recvmsg on some kernels
+    KprobeSpec { program: "tcp_cleanup_rbuf", symbols: &["tcp_cleanup_rbuf", "__tcp_cleanup_rbuf"], required: false },
+    // Removed in 5.19; the skb:kfree_skb tracepoint covers drops there
+    KprobeSpec { program: "tcp_drop", symbols: &["tcp_drop"], required: false },
+    KprobeSpec { program: "tcp_set_state", symbols: &["tcp_set_state"], required: false },
+    KprobeSpec { program: "tcp_v4_connect", symbols: &["tcp_v4_connect"], required: false },
+    KprobeSpec { program: "tcp_close", symbols: &["tcp_close", "__tcp_close"], required: false },
+    // Resets and retransmission timeouts
+    KprobeSpec { program: "tcp_reset", symbols: &["tcp_reset"], required: false },
+    KprobeSpec { program: "tcp_send_active_reset", symbols: &["tcp_send_active_reset"], required: false },
+    KprobeSpec { program: "tcp_retransmit_timer", symbols: &["tcp_retransmit_timer"], required: false },
+    // Accept queue overflows: SYN received, then handshake completed
+    KprobeSpec { program: "tcp_conn_request", symbols: &["tcp_conn_request"], required: false },
+    KprobeSpec { program: "tcp_v4_syn_recv_sock", symbols: &["tcp_v4_syn_recv_sock"], required: false },
+    KprobeSpec { program: "tcp_v6_syn_recv_sock", symbols: &["tcp_v6_syn_recv_sock"], required: false },
+    // DNS query latency: query sent, then response read by the application
+    KprobeSpec { program: "udp_sendmsg", symbols: &["udp_sendmsg"], required: false },
+    KprobeSpec { program: "skb_consume_udp", symbols: &["skb_consume_udp"], required: false },
+];
+
+/// Tail-called stages of the latency event pipeline, by PIPELINE index
+pub const PIPELINE_STAGES: &[(u32, &str)] = &[
+    (probe_common::constants::PIPELINE_STAGE_FILTER, "pipeline_filter"),
+    (probe_common::constants::PIPELINE_STAGE_SAMPLE, "pipeline_sample"),
+    (probe_common::constants::PIPELINE_STAGE_EMIT, "pipeline_emit"),
+];
+
+/// Tracepoint program and where it attaches
+#[derive(Debug, Clone, Copy)]
+pub struct TracepointSpec {
+    /// Program name in the eBPF object
+    pub program: &'static str,
+    /// Tracepoint category
+    pub category: &'static str,
+    /// Tracepoint name
+    pub name: &'static str,
+    /// Only attached with `--attach-mode tracepoint`
+    pub tracepoint_mode_only: bool,
+    /// Abort the run if the program cannot be attached (in its mode)
+    pub required: bool,
+}
+
+/// Tracepoints attached by [`ProbeLoader::attach_tracepoints`]
+pub const TRACEPOINTS: &[TracepointSpec] = &[
+    TracepointSpec { program: "kfree_skb_tracepoint", category: "skb", name: "kfree_skb", tracepoint_mode_only: false, required: false },
+    TracepointSpec { program: "sched_switch", category: "sched", name: "sched_switch", tracepoint_mode_only: false, required: false },
+    TracepointSpec { program: "tcp_probe", category: "tcp", name: "tcp_probe", tracepoint_mode_only: true, required: true },
+    TracepointSpec { program: "inet_sock_set_state", category: "sock", name: "inet_sock_set_state", tracepoint_mode_only: true, required: false },
+];
+
+/// How TCP latency is measured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AttachMode {
+    /// Kprobes on TCP functions: send-to-receive latency per connection
+    #[default]
+    Kprobe,
+    /// Stable tracepoints only: smoothed RTT per connection from
+    /// tcp:tcp_probe, state from sock:inet_sock_set_state
+    Tracepoint,
+}
+
+impl std::fmt::Display for AttachMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachMode::Kprobe => write!(f, "kprobe"),
+            AttachMode::Tracepoint => write!(f, "tracepoint"),
+        }
+    }
+}
+
+/// Maps holding an entry per connection, sized by [`MapSizes::connections`]
+pub const CONNECTION_MAPS: &[&str] = &[
+    "CONNECTION_START",
+    "CONNECTION_STATES",
+    "XDP_CONN_STATS",
+    "SOCK_HASH",
+    "HTTP_STATUS",
+    "CONGESTION",
+];
+
+/// Map sizes applied when loading eBPF objects
+///
+/// The sizes compiled into the object are only defaults; maps are resized
+/// before they are created, so nodes can be sized without rebuilding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapSizes {
+    /// Entries of each of the [`CONNECTION_MAPS`]
+    pub connections: u32,
+}
+
+impl Default for MapSizes {
+    fn default() -> Self {
+        Self {
+            connections: probe_common::constants::MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl MapSizes {
+    /// Load an eBPF object with these map sizes
+    ///
+    /// Maps the object does not define are skipped.
+    fn load(&self, data: &[u8]) -> Result<Ebpf, aya::EbpfError> {
+        let mut loader = EbpfLoader::new();
+        for name in CONNECTION_MAPS {
+            loader.set_max_entries(name, self.connections);
+        }
+        loader.load(data)
+    }
+}
+
+/// eBPF object with its handles
+struct LoadedObject {
+    handle: ObjectHandle,
+    ebpf: Ebpf,
+    attach: BTreeMap<String, AttachTarget>,
+}
+
 /// eBPF program loader and manager
 pub struct ProbeLoader {
-    ebpf: Bpf,
+    objects: Vec<LoadedObject>,
+    /// Index of the latency object
+    primary: usize,
+    /// Attach outcomes, in attach order
+    probes: Vec<ProbeAttachment>,
+    /// Keeps BPF run time stats enabled while held
+    stats_fd: Option<OwnedFd>,
+    /// Pipeline program array; the kernel clears its entries once no
+    /// userspace handle to it is open
+    pipeline: Option<ProgramArray<MapData>>,
+    /// Sizes the maps were created with
+    map_sizes: MapSizes,
+    /// Name of this probe among others on the node
+    instance: Option<InstanceId>,
+    /// Pinned maps, unpinned when the loader is dropped
+    pins: Option<MapPins>,
 }
 
 impl ProbeLoader {
@@ -33,28 +220,25 @@ impl ProbeLoader {
     /// # Arguments
     ///
     /// * `path` - Optional path to eBPF object file. If None, uses embedded bytecode.
+    /// * `sizes` - Map sizes to create the maps with
     ///
     /// # Returns
     ///
     /// ProbeLoader instance with loaded eBPF program
-    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+    pub fn load(path: Option<PathBuf>, sizes: &MapSizes) -> Result<Self> {
         info!("Loading eBPF program...");
 
         let ebpf = if let Some(obj_path) = path {
             info!("Loading eBPF object from: {:?}", obj_path);
             let data = std::fs::read(&obj_path)
                 .with_context(|| format!("Failed to read eBPF object file: {:?}", obj_path))?;
-            Bpf::load(&data).context("Failed to load eBPF program")?
+            sizes.load(&data).context("Failed to load eBPF program")?
         } else {
             // Try to load embedded bytecode
             #[cfg(feature = "embedded")]
             {
                 info!("Loading embedded eBPF program...");
-                let data = include_bytes!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/../../target/bpfel-unknown-none/release/latency-probe"
-                ));
-                Bpf::load(data).context("Failed to load embedded eBPF program")?
+                sizes.load(EMBEDDED_OBJECT).context("Failed to load embedded eBPF program")?
             }
             #[cfg(not(feature = "embedded"))]
             {
@@ -66,197 +250,656 @@ impl ProbeLoader {
 
         info!("eBPF program loaded successfully");
 
-        Ok(Self { ebpf })
+        Ok(Self::from_objects(
+            vec![("latency".to_string(), ObjectKind::Latency, ebpf, BTreeMap::new())],
+            *sizes,
+        ))
+    }
+
+    /// Load every object in a manifest
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - Objects to load; must include a latency object
+    /// * `sizes` - Map sizes to create the maps of every object with
+    ///
+    /// # Returns
+    ///
+    /// ProbeLoader with the first latency object as the primary object
+    pub fn load_manifest(manifest: &ObjectManifest, sizes: &MapSizes) -> Result<Self> {
+        manifest.validate()?;
+        info!("Loading {} eBPF objects...", manifest.objects.len());
+
+        let mut objects = Vec::with_capacity(manifest.objects.len());
+        for spec in &manifest.objects {
+            let data = std::fs::read(&spec.path)
+                .with_context(|| format!("Failed to read eBPF object file: {:?}", spec.path))?;
+            let ebpf = sizes
+                .load(&data)
+                .with_context(|| format!("Failed to load eBPF object '{}'", spec.name))?;
+            info!("  ✓ Loaded {} ({}) from {:?}", spec.name, spec.kind(), spec.path);
+            objects.push((spec.name.clone(), spec.kind(), ebpf, spec.attach.clone()));
+        }
+
+        Ok(Self::from_objects(objects, *sizes))
+    }
+
+    /// Build handles with collision-free names for loaded objects
+    fn from_objects(
+        objects: Vec<(String, ObjectKind, Ebpf, BTreeMap<String, AttachTarget>)>,
+        map_sizes: MapSizes,
+    ) -> Self {
+        let sorted = |mut names: Vec<String>| {
+            names.sort();
+            names
+        };
+        let program_names: Vec<_> = objects
+            .iter()
+            .map(|(name, _, ebpf, _)| {
+                (name.as_str(), sorted(ebpf.programs().map(|(p, _)| p.to_string()).collect()))
+            })
+            .collect();
+        let map_names: Vec<_> = objects
+            .iter()
+            .map(|(name, _, ebpf, _)| {
+                (name.as_str(), sorted(ebpf.maps().map(|(m, _)| m.to_string()).collect()))
+            })
+            .collect();
+        let qualified_programs = qualify_names(&program_names);
+        let qualified_maps = qualify_names(&map_names);
+
+        let handles: Vec<_> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, (name, kind, _, _))| {
+                let id = ObjectId(i);
+                ObjectHandle {
+                    id,
+                    name: name.clone(),
+                    kind: *kind,
+                    programs: program_names[i]
+                        .1
+                        .iter()
+                        .zip(&qualified_programs[i])
+                        .map(|(name, qualified)| ProgramHandle {
+                            object: id,
+                            name: name.clone(),
+                            qualified: qualified.clone(),
+                        })
+                        .collect(),
+                    maps: map_names[i]
+                        .1
+                        .iter()
+                        .zip(&qualified_maps[i])
+                        .map(|(name, qualified)| MapHandle {
+                            object: id,
+                            name: name.clone(),
+                            qualified: qualified.clone(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let objects: Vec<_> = objects
+            .into_iter()
+            .zip(handles)
+            .map(|((_, _, ebpf, attach), handle)| LoadedObject { handle, ebpf, attach })
+            .collect();
+        let primary = objects
+            .iter()
+            .position(|o| o.handle.kind == ObjectKind::Latency)
+            .unwrap_or(0);
+
+        Self {
+            objects,
+            primary,
+            probes: Vec::new(),
+            stats_fd: None,
+            pipeline: None,
+            map_sizes,
+            instance: None,
+            pins: None,
+        }
+    }
+
+    /// Name this probe, to run alongside other instances on the node
+    ///
+    /// See [`crate::instance`] for what the name keeps apart.
+    pub fn set_instance(&mut self, instance: InstanceId) {
+        self.instance = Some(instance);
+    }
+
+    /// Name of this probe (None if unnamed)
+    pub fn instance(&self) -> Option<&InstanceId> {
+        self.instance.as_ref()
+    }
+
+    /// Pin every map of every object, for bpftool and other readers
+    ///
+    /// Maps are pinned by qualified name in the instance's subdirectory of
+    /// `root`, and unpinned when the loader is dropped. Must be called
+    /// before maps are taken by readers.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - Directory on a bpffs mount (e.g. /sys/fs/bpf/latency-probe)
+    ///
+    /// # Returns
+    ///
+    /// Directory holding the pins, or an error if another probe with the
+    /// same instance name holds it
+    pub fn pin_maps(&mut self, root: &Path) -> Result<&Path> {
+        let mut pins = MapPins::claim(&pin_dir(root, self.instance.as_ref()))?;
+        for object in &self.objects {
+            for handle in &object.handle.maps {
+                let Some(map) = object.ebpf.map(&handle.name) else {
+                    continue;
+                };
+                let path = pins.add(&handle.qualified);
+                map.pin(path)
+                    .with_context(|| format!("Failed to pin {} at {:?}", handle.qualified, path))?;
+            }
+        }
+        debug!("Pinned {} maps in {:?}", pins.len(), pins.dir());
+        Ok(self.pins.insert(pins).dir())
+    }
+
+    /// Replace the latency object with a new build of it, without
+    /// stopping the run
+    ///
+    /// The new object must define the same programs and maps. Its programs
+    /// are attached where the running ones are, with the settings, filters,
+    /// STATS counters and connection states copied over, before the running
+    /// object is dropped; pinned maps are re-pinned at the same paths. The
+    /// swap is all or nothing: if the new object fails to load or attach,
+    /// the running one is kept. Only kprobe and tracepoint programs can be
+    /// swapped; the new object's perf buffers need new readers.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - eBPF object file to swap in
+    pub fn reload(&mut self, path: &Path) -> Result<()> {
+        info!("Hot-swapping the eBPF object from {:?}...", path);
+        let primary = self.primary;
+
+        // Where the running programs are attached
+        let running = &self.objects[primary].ebpf;
+        let targets = self
+            .probes
+            .iter()
+            .filter_map(|probe| Some((probe, running.program(&probe.program)?, probe.attached_to.as_deref()?)))
+            .map(|(probe, program, target)| match program {
+                Program::KProbe(_) | Program::TracePoint(_) => Ok((probe.program.clone(), recorded_target(target))),
+                _ => anyhow::bail!("{} cannot be hot-swapped; restart the probe to replace it", probe.program),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let data = std::fs::read(path).with_context(|| format!("Failed to read eBPF object file: {:?}", path))?;
+        let mut ebpf = self.map_sizes.load(&data).context("Failed to load the new eBPF object")?;
+        let handle = &self.objects[primary].handle;
+        let target = format!("ebpf::{}", handle.name);
+        let mut programs: Vec<_> = ebpf.programs().map(|(name, _)| name.to_string()).collect();
+        let mut maps: Vec<_> = ebpf.maps().map(|(name, _)| name.to_string()).collect();
+        programs.sort();
+        maps.sort();
+        if !programs.iter().eq(handle.programs.iter().map(|p| &p.name))
+            || !maps.iter().eq(handle.maps.iter().map(|m| &m.name))
+        {
+            anyhow::bail!("The new object defines other programs or maps; restart the probe to swap it in");
+        }
+
+        carry_state(&mut self.objects[primary].ebpf, &mut ebpf).context("Failed to copy the probe state")?;
+        if let Err(e) = EbpfLogger::init_with_logger(&mut ebpf, EbpfLogForwarder { target }) {
+            debug!("eBPF logging not available for the new object: {}", e);
+        }
+
+        // The old programs stay attached until the new ones are, so no
+        // event goes unmeasured; they detach when dropped at the end of
+        // this block
+        {
+            let previous = std::mem::replace(&mut self.objects[primary].ebpf, ebpf);
+            let previous_pipeline = self.pipeline.take();
+            if let Err(e) = self.attach_swapped(previous_pipeline.is_some(), &targets) {
+                // Dropping the new object detaches what it attached
+                self.objects[primary].ebpf = previous;
+                self.pipeline = previous_pipeline;
+                return Err(e).context("Failed to attach the new object; the running one was kept");
+            }
+        }
+
+        if let Some(pins) = &self.pins {
+            let object = &self.objects[primary];
+            for handle in &object.handle.maps {
+                let Some(map) = object.ebpf.map(&handle.name) else {
+                    continue;
+                };
+                let path = pins.path(&handle.qualified);
+                let repinned = std::fs::remove_file(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| map.pin(&path).map_err(anyhow::Error::from));
+                if let Err(e) = repinned {
+                    warn!("  ⚠ Failed to re-pin {} at {:?}: {}", handle.qualified, path, e);
+                }
+            }
+        }
+
+        info!("  ✓ Swapped in {:?} ({} programs attached)", path, targets.len());
+        Ok(())
+    }
+
+    /// Attach the programs of a swapped-in latency object
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline` - Whether to load the pipeline stages
+    /// * `targets` - Programs and where to attach them
+    fn attach_swapped(&mut self, pipeline: bool, targets: &[(String, AttachTarget)]) -> Result<()> {
+        if pipeline {
+            self.load_pipeline()?;
+        }
+        let ebpf = self.ebpf();
+        for (name, target) in targets {
+            let program = ebpf
+                .program_mut(name)
+                .with_context(|| format!("{} program not found in eBPF object", name))?;
+            attach_program(program, name, Some(target))
+                .with_context(|| format!("Failed to attach {} to {}", name, target))?;
+        }
+        Ok(())
+    }
+
+    /// Handles for every loaded object, in load order
+    pub fn objects(&self) -> impl Iterator<Item = &ObjectHandle> {
+        self.objects.iter().map(|o| &o.handle)
+    }
+
+    /// Handles and eBPF objects, in load order
+    pub fn objects_mut(&mut self) -> impl Iterator<Item = (&ObjectHandle, &mut Ebpf)> {
+        self.objects.iter_mut().map(|o| (&o.handle, &mut o.ebpf))
+    }
+
+    /// First object of the given kind
+    pub fn object(&self, kind: ObjectKind) -> Option<&ObjectHandle> {
+        self.objects().find(|o| o.kind == kind)
+    }
+
+    /// Look up a program by its qualified name
+    pub fn find_program(&self, qualified: &str) -> Option<&ProgramHandle> {
+        self.objects()
+            .flat_map(|o| &o.programs)
+            .find(|p| p.qualified == qualified)
+    }
+
+    /// Look up a map by its qualified name
+    pub fn find_map(&self, qualified: &str) -> Option<&MapHandle> {
+        self.objects().flat_map(|o| &o.maps).find(|m| m.qualified == qualified)
+    }
+
+    /// Program for a handle
+    pub fn program_mut(&mut self, handle: &ProgramHandle) -> Option<&mut Program> {
+        self.objects
+            .get_mut(handle.object.0)?
+            .ebpf
+            .program_mut(&handle.name)
+    }
+
+    /// Map for a handle
+    pub fn map_mut(&mut self, handle: &MapHandle) -> Option<&mut aya::maps::Map> {
+        self.objects.get_mut(handle.object.0)?.ebpf.map_mut(&handle.name)
+    }
+
+    /// Take ownership of a map for a handle
+    pub fn take_map(&mut self, handle: &MapHandle) -> Option<aya::maps::Map> {
+        self.objects.get_mut(handle.object.0)?.ebpf.take_map(&handle.name)
+    }
+
+    /// Attach the programs of every object other than the latency object
+    ///
+    /// Programs with an attach target in the manifest attach there; kprobe
+    /// programs without one attach to the kernel function of the same name.
+    /// Failures are logged and skipped, like the optional primary programs.
+    ///
+    /// # Returns
+    ///
+    /// Number of programs attached
+    pub fn attach_objects(&mut self) -> Result<usize> {
+        let mut attached = 0;
+
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i == self.primary {
+                continue;
+            }
+            info!("Attaching {} object '{}'...", object.handle.kind, object.handle.name);
+
+            for handle in &object.handle.programs {
+                let Some(program) = object.ebpf.program_mut(&handle.name) else {
+                    continue;
+                };
+                let result = match attach_program(program, &handle.name, object.attach.get(&handle.name)) {
+                    Ok(Some(target)) => {
+                        info!("  ✓ Attached {} to {}", handle.qualified, target);
+                        attached += 1;
+                        Ok(target.to_string())
+                    }
+                    Ok(None) => {
+                        warn!("  ⚠ {} has no attach target, skipping", handle.qualified);
+                        Err(anyhow::anyhow!("no attach target"))
+                    }
+                    Err(e) => {
+                        warn!("  ⚠ Failed to attach {}: {:#}", handle.qualified, e);
+                        Err(e)
+                    }
+                };
+                self.probes.push(ProbeAttachment::from_result(&handle.qualified, result));
+            }
+        }
+
+        Ok(attached)
     }
 
     /// Initialize eBPF logger
     ///
-    /// Enables kernel-side logging from the eBPF program.
-    /// Non-fatal if it fails.
+    /// Forwards `aya_log_ebpf` records from every object to the daemon's
+    /// logger under the `ebpf::<object>` target, so they can be filtered
+    /// with e.g. `RUST_LOG=ebpf=debug`. Objects built without aya-log are
+    /// skipped. Non-fatal if it fails.
     pub fn init_logger(&mut self) {
-        // eBPF logger temporarily disabled due to version mismatch
-        // TODO: Update to compatible aya-log version when available
-        warn!("eBPF logger not available in aya 0.12 - logging from eBPF program will not be captured");
+        for object in &mut self.objects {
+            let forwarder = EbpfLogForwarder {
+                target: format!("ebpf::{}", object.handle.name),
+            };
+            match EbpfLogger::init_with_logger(&mut object.ebpf, forwarder) {
+                Ok(_) => debug!("eBPF logging enabled for {}", object.handle.name),
+                Err(e) => debug!("eBPF logging not available for {}: {}", object.handle.name, e),
+            }
+        }
     }
 
     /// Attach kprobes to kernel functions
     ///
-    /// Attaches to:
-    /// - tcp_sendmsg
-    /// - tcp_recvmsg
-    /// - tcp_cleanup_rbuf
-    /// - tcp_drop
-    /// - tcp_set_state
-    /// - tcp_v4_connect
-    /// - tcp_close
+    /// Each program in [`KPROBES`] is attached to the first of its candidate
+    /// symbols that the kernel provides, retrying transient failures. Optional
+    /// programs that cannot be attached anywhere are skipped with a warning;
+    /// the outcome for every program is available from [`Self::probes`].
+    ///
+    /// # Arguments
+    ///
+    /// * `required` - Optional programs that must attach for this run
     ///
     /// # Returns
     ///
-    /// Result indicating success or failure
-    pub fn attach_kprobes(&mut self) -> Result<()> {
-        info!("Attaching kprobes for latency tracking...");
+    /// Error if a required program could not be attached
+    pub fn attach_kprobes(&mut self, required: &[String]) -> Result<()> {
+        info!("Attaching kprobes...");
 
-        // Attach tcp_sendmsg
-        let program: &mut KProbe = self
-            .ebpf
-            .program_mut("tcp_sendmsg")
-            .context("tcp_sendmsg program not found in eBPF object")?
-            .try_into()
-            .context("Failed to get tcp_sendmsg as KProbe")?;
-        program.load().context("Failed to load tcp_sendmsg")?;
-        program
-            .attach("tcp_sendmsg", 0)
-            .context("Failed to attach tcp_sendmsg kprobe")?;
-        info!("  ✓ Attached to tcp_sendmsg");
+        for name in required {
+            if !KPROBES.iter().any(|spec| spec.program == name) {
+                anyhow::bail!("Unknown kprobe '{}'", name);
+            }
+        }
 
-        // Attach tcp_recvmsg
-        let program: &mut KProbe = self
-            .ebpf
-            .program_mut("tcp_recvmsg")
-            .context("tcp_recvmsg program not found in eBPF object")?
-            .try_into()
-            .context("Failed to get tcp_recvmsg as KProbe")?;
-        program.load().context("Failed to load tcp_recvmsg")?;
-        program
-            .attach("tcp_recvmsg", 0)
-            .context("Failed to attach tcp_recvmsg kprobe")?;
-        info!("  ✓ Attached to tcp_recvmsg");
+        let kallsyms = std::fs::read_to_string("/proc/kallsyms").ok();
+        for spec in KPROBES {
+            let required = spec.required || required.iter().any(|r| r == spec.program);
+            let result = self.attach_kprobe(spec, kallsyms.as_deref());
+
+            match &result {
+                Ok(symbol) if symbol == spec.program => info!("  ✓ Attached to {}", symbol),
+                Ok(symbol) => info!("  ✓ Attached {} to fallback {}", spec.program, symbol),
+                Err(e) if required => {
+                    return Err(anyhow::anyhow!("{:#}", e))
+                        .with_context(|| format!("Failed to attach required kprobe {}", spec.program));
+                }
+                Err(e) => warn!("  ⚠ {} not attached (optional): {:#}", spec.program, e),
+            }
+            self.probes.push(ProbeAttachment::from_result(spec.program, result));
+        }
 
-        // Attach tcp_cleanup_rbuf
+        let active = self.probes.iter().filter(|p| p.is_active()).count();
+        info!("{}/{} kprobes attached", active, KPROBES.len());
+
+        Ok(())
+    }
+
+    /// Load one kprobe program and attach it to the first usable symbol
+    ///
+    /// # Returns
+    ///
+    /// The symbol the program was attached to
+    fn attach_kprobe(&mut self, spec: &KprobeSpec, kallsyms: Option<&str>) -> Result<String> {
         let program: &mut KProbe = self
-            .ebpf
-            .program_mut("tcp_cleanup_rbuf")
-            .context("tcp_cleanup_rbuf program not found in eBPF object")?
+            .ebpf()
+            .program_mut(spec.program)
+            .with_context(|| format!("{} program not found in eBPF object", spec.program))?
             .try_into()
-            .context("Failed to get tcp_cleanup_rbuf as KProbe")?;
-        program.load().context("Failed to load tcp_cleanup_rbuf")?;
+            .with_context(|| format!("Failed to get {} as KProbe", spec.program))?;
         program
-            .attach("tcp_cleanup_rbuf", 0)
-            .context("Failed to attach tcp_cleanup_rbuf kprobe")?;
-        info!("  ✓ Attached to tcp_cleanup_rbuf");
+            .load()
+            .with_context(|| format!("Failed to load {}", spec.program))?;
 
-        info!("Attaching kprobes for packet drop tracking...");
+        let mut errors = Vec::new();
+        for &symbol in spec.symbols {
+            // Skip symbols the kernel does not export (e.g. inlined functions)
+            if kallsyms.is_some_and(|k| !verify::has_kernel_symbol(k, symbol)) {
+                errors.push(format!("{}: not in /proc/kallsyms", symbol));
+                continue;
+            }
 
-        // Attach tcp_drop (may not exist on all kernels, so warn instead of error)
-        match self.ebpf.program_mut("tcp_drop") {
-            Some(prog) => {
-                let program: &mut KProbe = prog
-                    .try_into()
-                    .context("Failed to get tcp_drop as KProbe")?;
-                program.load().context("Failed to load tcp_drop")?;
-                match program.attach("tcp_drop", 0) {
-                    Ok(_) => info!("  ✓ Attached to tcp_drop"),
-                    Err(e) => warn!("  ⚠ Failed to attach tcp_drop (not available on this kernel): {}", e),
+            for attempt in 1..=ATTACH_ATTEMPTS {
+                match program.attach(symbol, 0) {
+                    Ok(_) => return Ok(symbol.to_string()),
+                    Err(e) if attempt < ATTACH_ATTEMPTS => {
+                        debug!("Attaching {} to {} failed (attempt {}): {}", spec.program, symbol, attempt, e);
+                        std::thread::sleep(ATTACH_RETRY_DELAY);
+                    }
+                    Err(e) => errors.push(format!("{}: {}", symbol, e)),
                 }
             }
-            None => warn!("  ⚠ tcp_drop program not found (optional)"),
         }
 
-        info!("Attaching kprobes for connection state tracking...");
+        anyhow::bail!("no usable attach point ({})", errors.join("; "))
+    }
 
-        // Attach tcp_set_state
-        let program: &mut KProbe = self
-            .ebpf
-            .program_mut("tcp_set_state")
-            .context("tcp_set_state program not found in eBPF object")?
-            .try_into()
-            .context("Failed to get tcp_set_state as KProbe")?;
-        program.load().context("Failed to load tcp_set_state")?;
-        program
-            .attach("tcp_set_state", 0)
-            .context("Failed to attach tcp_set_state kprobe")?;
-        info!("  ✓ Attached to tcp_set_state");
+    /// Load the pipeline stages and install them in the PIPELINE map
+    ///
+    /// Handlers tail call the stages to filter, sample and emit latency
+    /// events. Without them (or with an object that predates the
+    /// pipeline), handlers do the same work inline, so a failure here is
+    /// not fatal. Call before attaching kprobes, so that no event takes
+    /// the inline path.
+    pub fn load_pipeline(&mut self) -> Result<()> {
+        let map = self
+            .take_named_map("PIPELINE")
+            .context("PIPELINE map not found in eBPF object")?;
+        let mut stages = ProgramArray::try_from(map).context("Failed to get PIPELINE as ProgramArray")?;
 
-        // Attach tcp_v4_connect
-        let program: &mut KProbe = self
-            .ebpf
-            .program_mut("tcp_v4_connect")
-            .context("tcp_v4_connect program not found in eBPF object")?
-            .try_into()
-            .context("Failed to get tcp_v4_connect as KProbe")?;
-        program.load().context("Failed to load tcp_v4_connect")?;
-        program
-            .attach("tcp_v4_connect", 0)
-            .context("Failed to attach tcp_v4_connect kprobe")?;
-        info!("  ✓ Attached to tcp_v4_connect");
+        let ebpf = self.ebpf();
+        for &(index, name) in PIPELINE_STAGES {
+            let program: &mut KProbe = ebpf
+                .program_mut(name)
+                .with_context(|| format!("{} program not found in eBPF object", name))?
+                .try_into()
+                .with_context(|| format!("Failed to get {} as KProbe", name))?;
+            program
+                .load()
+                .with_context(|| format!("Failed to load {}", name))?;
+            let fd = program
+                .fd()
+                .with_context(|| format!("Failed to get the fd of {}", name))?;
+            stages
+                .set(index, fd, 0)
+                .with_context(|| format!("Failed to install {} in PIPELINE", name))?;
+        }
 
-        // Attach tcp_close
-        let program: &mut KProbe = self
-            .ebpf
-            .program_mut("tcp_close")
-            .context("tcp_close program not found in eBPF object")?
-            .try_into()
-            .context("Failed to get tcp_close as KProbe")?;
-        program.load().context("Failed to load tcp_close")?;
-        program
-            .attach("tcp_close", 0)
-            .context("Failed to attach tcp_close kprobe")?;
-        info!("  ✓ Attached to tcp_close");
+        self.pipeline = Some(stages);
+        info!("  ✓ Event pipeline loaded ({} stages)", PIPELINE_STAGES.len());
+        Ok(())
+    }
 
-        info!("All kprobes attached successfully");
+    /// Outcome of every probe attach attempted so far
+    pub fn probes(&self) -> &[ProbeAttachment] {
+        &self.probes
+    }
 
+    /// Enable in-kernel run time accounting for BPF programs
+    ///
+    /// Stats are collected for every BPF program on the host until the
+    /// loader is dropped, at the cost of two clock reads per program run.
+    ///
+    /// # Returns
+    ///
+    /// Error if the kernel does not support BPF_ENABLE_STATS (Linux < 5.8)
+    /// or the process lacks CAP_SYS_ADMIN
+    pub fn enable_program_stats(&mut self) -> Result<()> {
+        let fd = aya::sys::enable_stats(aya::sys::Stats::RunTime)
+            .context("Failed to enable BPF run time stats (requires Linux 5.8+ and CAP_SYS_ADMIN)")?;
+        self.stats_fd = Some(fd);
         Ok(())
     }
 
-    /// Attach tracepoints for packet drop tracking
+    /// True if BPF run time stats are being collected
+    pub fn program_stats_enabled(&self) -> bool {
+        self.stats_fd.is_some()
+    }
+
+    /// Read the run time and run count of every attached program
+    ///
+    /// # Returns
+    ///
+    /// Stats in attach order, or an empty list if stats are not enabled
+    pub fn read_program_stats(&self) -> Vec<ProgramStats> {
+        if self.stats_fd.is_none() {
+            return Vec::new();
+        }
+
+        let primary = &self.objects[self.primary].handle;
+        self.probes
+            .iter()
+            .filter(|probe| probe.is_active())
+            .filter_map(|probe| {
+                // Primary programs are recorded by name, others by qualified name
+                let handle = primary
+                    .programs
+                    .iter()
+                    .find(|p| p.name == probe.program)
+                    .or_else(|| self.find_program(&probe.program))?;
+                let program = self.objects[handle.object.0].ebpf.program(&handle.name)?;
+                match program.info() {
+                    Ok(info) => Some(ProgramStats::new(
+                        &probe.program,
+                        info.run_time().as_nanos() as u64,
+                        info.run_count(),
+                    )),
+                    Err(e) => {
+                        debug!("Failed to read info for {}: {}", probe.program, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Attach tracepoints
     ///
-    /// Attaches to:
-    /// - skb:kfree_skb
+    /// Attaches every program in [`TRACEPOINTS`] used by `mode`: packet
+    /// drops and context switches always, plus TCP RTT and state tracking
+    /// in tracepoint mode. Optional tracepoints missing on this kernel are
+    /// skipped with a warning; the outcome is available from [`Self::probes`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - How latency is measured
     ///
     /// # Returns
     ///
-    /// Result indicating success or failure
-    pub fn attach_tracepoints(&mut self) -> Result<()> {
+    /// Error if a required tracepoint could not be attached
+    pub fn attach_tracepoints(&mut self, mode: AttachMode) -> Result<()> {
         info!("Attaching tracepoints...");
 
-        // Attach kfree_skb tracepoint (may not exist on all kernels)
-        match self.ebpf.program_mut("kfree_skb_tracepoint") {
-            Some(prog) => {
-                let program: &mut TracePoint = prog
-                    .try_into()
-                    .context("Failed to get kfree_skb_tracepoint as TracePoint")?;
-                program.load().context("Failed to load kfree_skb_tracepoint")?;
-                match program.attach("skb", "kfree_skb") {
-                    Ok(_) => info!("  ✓ Attached to skb:kfree_skb tracepoint"),
-                    Err(e) => warn!("  ⚠ Failed to attach skb:kfree_skb tracepoint (not available on this kernel): {}", e),
-                }
+        for spec in TRACEPOINTS {
+            if spec.tracepoint_mode_only && mode != AttachMode::Tracepoint {
+                continue;
             }
-            None => warn!("  ⚠ kfree_skb_tracepoint program not found (optional)"),
-        }
 
-        // Attach sched_switch tracepoint for context switch tracking
-        match self.ebpf.program_mut("sched_switch") {
-            Some(prog) => {
-                let program: &mut TracePoint = prog
-                    .try_into()
-                    .context("Failed to get sched_switch as TracePoint")?;
-                program.load().context("Failed to load sched_switch")?;
-                match program.attach("sched", "sched_switch") {
-                    Ok(_) => info!("  ✓ Attached to sched:sched_switch tracepoint"),
-                    Err(e) => warn!("  ⚠ Failed to attach sched:sched_switch tracepoint: {}", e),
+            let result = match self.ebpf().program_mut(spec.program) {
+                Some(prog) => {
+                    let tracepoint: &mut TracePoint = prog
+                        .try_into()
+                        .with_context(|| format!("Failed to get {} as TracePoint", spec.program))?;
+                    tracepoint
+                        .load()
+                        .with_context(|| format!("Failed to load {}", spec.program))?;
+                    tracepoint
+                        .attach(spec.category, spec.name)
+                        .map(|_| format!("{}:{}", spec.category, spec.name))
+                        .map_err(anyhow::Error::from)
                 }
+                None => Err(anyhow::anyhow!("program not found in eBPF object")),
+            };
+
+            match &result {
+                Ok(target) => info!("  ✓ Attached to {} tracepoint", target),
+                Err(e) if spec.required => {
+                    return Err(anyhow::anyhow!("{:#}", e)).with_context(|| {
+                        format!("Failed to attach required tracepoint {}:{}", spec.category, spec.name)
+                    });
+                }
+                Err(e) => warn!("  ⚠ {} not attached (optional): {}", spec.program, e),
             }
-            None => warn!("  ⚠ sched_switch program not found (optional)"),
+            self.probes.push(ProbeAttachment::from_result(spec.program, result));
         }
 
         Ok(())
     }
 
-    /// Attach XDP program to network interface
+    /// Tell the tcp_probe program where its fields are
     ///
-    /// XDP is optional. Returns NotFound if not in eBPF object, Err on attach failure.
-    pub fn attach_xdp(&mut self, interface: &str, mode: XdpFlags) -> Result<AttachResult> {
+    /// Must be called before attaching in tracepoint mode; until then the
+    /// program discards every record. Congestion state is only tracked if
+    /// the record has a snd_cwnd field.
+    pub fn set_tcp_probe_offsets(&mut self, offsets: &TcpProbeOffsets) -> Result<()> {
+        use probe_common::constants::{
+            CONFIG_TCP_PROBE_DADDR_OFFSET, CONFIG_TCP_PROBE_SADDR_OFFSET,
+            CONFIG_TCP_PROBE_SKADDR_OFFSET, CONFIG_TCP_PROBE_SND_CWND_OFFSET,
+            CONFIG_TCP_PROBE_SRTT_OFFSET, CONFIG_TCP_PROBE_SSTHRESH_OFFSET,
+        };
+
+        let mut settings = config_map(self.ebpf())?;
+        settings.set(CONFIG_TCP_PROBE_SADDR_OFFSET, offsets.saddr as u64, 0)?;
+        settings.set(CONFIG_TCP_PROBE_DADDR_OFFSET, offsets.daddr as u64, 0)?;
+        settings.set(CONFIG_TCP_PROBE_SRTT_OFFSET, offsets.srtt as u64, 0)?;
+        settings.set(CONFIG_TCP_PROBE_SKADDR_OFFSET, offsets.skaddr.unwrap_or(0) as u64, 0)?;
+        settings.set(CONFIG_TCP_PROBE_SND_CWND_OFFSET, offsets.snd_cwnd.unwrap_or(0) as u64, 0)?;
+        settings.set(CONFIG_TCP_PROBE_SSTHRESH_OFFSET, offsets.ssthresh.unwrap_or(0) as u64, 0)?;
+
+        Ok(())
+    }
+
+    /// Attach XDP program to network interfaces
+    ///
+    /// The program is loaded once and attached to every interface, so hosts
+    /// with several NICs (or veth pairs into pod namespaces) are covered by
+    /// a single probe. XDP is optional. Returns NotFound if not in eBPF
+    /// object, Err on attach failure.
+    pub fn attach_xdp(&mut self, interfaces: &[String], mode: XdpFlags) -> Result<AttachResult> {
         info!("Attaching XDP program...");
 
-        match self.ebpf.program_mut("xdp_packet_monitor") {
+        match self.ebpf().program_mut("xdp_packet_monitor") {
             Some(prog) => {
                 let program: &mut Xdp = prog
                     .try_into()
                     .context("Failed to get xdp_packet_monitor as XDP")?;
                 program.load().context("Failed to load xdp_packet_monitor")?;
-                program.attach(interface, mode)
-                    .with_context(|| format!("Failed to attach XDP to interface '{}' - check permissions and interface exists", interface))?;
-                info!("  ✓ Attached XDP to {}", interface);
+                for interface in interfaces {
+                    program.attach(interface, mode)
+                        .with_context(|| format!("Failed to attach XDP to interface '{}' - check permissions and interface exists", interface))?;
+                    info!("  ✓ Attached XDP to {}", interface);
+                }
+                self.probes.push(ProbeAttachment::from_result(
+                    "xdp_packet_monitor",
+                    Ok(interfaces.join(",")),
+                ));
                 Ok(AttachResult::Attached)
             }
             None => {
@@ -266,6 +909,102 @@ impl ProbeLoader {
         }
     }
 
+    /// Attach the HTTP status sampling programs
+    ///
+    /// The sock_ops program is attached to a cgroup and adds the
+    /// established sockets of its processes to SOCK_HASH; the sk_msg
+    /// program runs on every message those sockets send. Latency events
+    /// then carry the status class of the last response on their
+    /// connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `cgroup` - cgroup v2 directory whose sockets are sampled, e.g.
+    ///   `/sys/fs/cgroup` for the whole node
+    pub fn attach_http_status(&mut self, cgroup: &Path) -> Result<()> {
+        info!("Attaching HTTP status sampling...");
+
+        // Named instances may sample the same cgroup
+        let mode = if self.instance.is_some() {
+            CgroupAttachMode::AllowMultiple
+        } else {
+            CgroupAttachMode::Single
+        };
+        let ebpf = self.ebpf();
+        let sock_hash: SockHash<_, ConnectionKey> = ebpf
+            .map("SOCK_HASH")
+            .context("SOCK_HASH map not found in eBPF object")?
+            .try_into()
+            .context("Failed to get SOCK_HASH as SockHash")?;
+        let sock_hash_fd = sock_hash
+            .fd()
+            .try_clone()
+            .context("Failed to duplicate SOCK_HASH fd")?;
+
+        let program: &mut SkMsg = ebpf
+            .program_mut("sk_msg_http_status")
+            .context("sk_msg_http_status program not found in eBPF object")?
+            .try_into()
+            .context("Failed to get sk_msg_http_status as SkMsg")?;
+        program.load().context("Failed to load sk_msg_http_status")?;
+        program
+            .attach(&sock_hash_fd)
+            .context("Failed to attach sk_msg_http_status to SOCK_HASH")?;
+
+        let cgroup_file = File::open(cgroup)
+            .with_context(|| format!("Failed to open cgroup {:?}", cgroup))?;
+        let program: &mut SockOps = ebpf
+            .program_mut("sock_ops_established")
+            .context("sock_ops_established program not found in eBPF object")?
+            .try_into()
+            .context("Failed to get sock_ops_established as SockOps")?;
+        program.load().context("Failed to load sock_ops_established")?;
+        program
+            .attach(&cgroup_file, mode)
+            .with_context(|| format!("Failed to attach sock_ops_established to cgroup {:?}", cgroup))?;
+
+        info!("  ✓ Sampling HTTP status of sockets in {:?}", cgroup);
+        self.probes.push(ProbeAttachment::from_result(
+            "sk_msg_http_status",
+            Ok(cgroup.display().to_string()),
+        ));
+
+        Ok(())
+    }
+
+    /// Assign socket cookies to the TCP sockets of a cgroup
+    ///
+    /// The kernel only generates a socket's cookie when one is first asked
+    /// for; without this, events carry cookies only for sockets another
+    /// program or `ss` already asked about. Attached alongside other
+    /// sock_ops programs on the cgroup, which keep running.
+    ///
+    /// # Arguments
+    ///
+    /// * `cgroup` - cgroup v2 directory (e.g. /sys/fs/cgroup for every socket)
+    pub fn attach_socket_cookies(&mut self, cgroup: &Path) -> Result<()> {
+        let cgroup_file = File::open(cgroup)
+            .with_context(|| format!("Failed to open cgroup {:?}", cgroup))?;
+        let program: &mut SockOps = self
+            .ebpf()
+            .program_mut("sock_ops_cookie")
+            .context("sock_ops_cookie program not found in eBPF object")?
+            .try_into()
+            .context("Failed to get sock_ops_cookie as SockOps")?;
+        program.load().context("Failed to load sock_ops_cookie")?;
+        program
+            .attach(&cgroup_file, CgroupAttachMode::AllowMultiple)
+            .with_context(|| format!("Failed to attach sock_ops_cookie to cgroup {:?}", cgroup))?;
+
+        info!("  ✓ Assigning socket cookies in {:?}", cgroup);
+        self.probes.push(ProbeAttachment::from_result(
+            "sock_ops_cookie",
+            Ok(cgroup.display().to_string()),
+        ));
+
+        Ok(())
+    }
+
     /// Get the perf event array for reading latency events
     ///
     /// # Returns
@@ -273,8 +1012,7 @@ impl ProbeLoader {
     /// AsyncPerfEventArray for reading latency events from the kernel
     pub fn get_perf_array(&mut self) -> Result<AsyncPerfEventArray<aya::maps::MapData>> {
         let map = self
-            .ebpf
-            .take_map("EVENTS")
+            .take_named_map("EVENTS")
             .context("EVENTS map not found in eBPF object")?;
 
         AsyncPerfEventArray::try_from(map)
@@ -288,8 +1026,7 @@ impl ProbeLoader {
     /// AsyncPerfEventArray for reading packet drop events from the kernel
     pub fn get_packet_drops_array(&mut self) -> Result<AsyncPerfEventArray<aya::maps::MapData>> {
         let map = self
-            .ebpf
-            .take_map("PACKET_DROPS")
+            .take_named_map("PACKET_DROPS")
             .context("PACKET_DROPS map not found in eBPF object")?;
 
         AsyncPerfEventArray::try_from(map)
@@ -299,19 +1036,259 @@ impl ProbeLoader {
     /// Get the perf event array for reading context switch events
     pub fn get_context_switch_array(&mut self) -> Result<AsyncPerfEventArray<MapData>> {
         let map = self
-            .ebpf
-            .take_map("CONTEXT_SWITCHES")
+            .take_named_map("CONTEXT_SWITCHES")
             .context("CONTEXT_SWITCHES map not found in eBPF object")?;
 
         AsyncPerfEventArray::try_from(map)
             .context("Failed to create AsyncPerfEventArray from CONTEXT_SWITCHES map")
     }
 
+    /// Write runtime configuration to the CONFIG and SERVICE_FILTER maps
+    ///
+    /// Safe to call while programs are attached. Filtering is disabled while
+    /// the filter map is rewritten, so no events are dropped by a partially
+    /// written filter. Applied to every loaded object that has a CONFIG map.
+    pub fn apply_config(&mut self, config: &ProbeConfig) -> Result<()> {
+        let filter_keys = config.service_filter_keys()?;
+        let event_rates = config.event_sample_rates_by_type()?;
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            write_config(&mut object.ebpf, config, &event_rates, &filter_keys)
+                .with_context(|| format!("Failed to configure object '{}'", object.handle.name))?;
+        }
+
+        info!(
+            "Applied config: sample rate {}, {} service filter(s)",
+            config.describe_sample_rates(),
+            filter_keys.len()
+        );
+
+        Ok(())
+    }
+
+    /// Enable in-kernel network namespace capture
+    ///
+    /// Writes the struct offsets the eBPF programs need to read a socket's
+    /// namespace inode. Without them, events carry netns 0.
+    pub fn set_netns_offsets(&mut self, offsets: &NetnsOffsets) -> Result<()> {
+        use probe_common::constants::{CONFIG_NETNS_INUM_OFFSET, CONFIG_NETNS_SKC_NET_OFFSET};
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            let mut settings = config_map(&mut object.ebpf)?;
+            settings.set(CONFIG_NETNS_INUM_OFFSET, offsets.inum as u64, 0)?;
+            settings.set(CONFIG_NETNS_SKC_NET_OFFSET, offsets.skc_net as u64, 0)?;
+        }
+
+        info!(
+            "  ✓ Network namespace capture enabled (skc_net @{}, inum @{})",
+            offsets.skc_net, offsets.inum
+        );
+        Ok(())
+    }
+
+    /// Tell the kernel programs where to read socket cookies
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset of `skc_cookie` in `struct sock_common`
+    pub fn set_cookie_offset(&mut self, offset: u32) -> Result<()> {
+        use probe_common::constants::CONFIG_SKC_COOKIE_OFFSET;
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            config_map(&mut object.ebpf)?.set(CONFIG_SKC_COOKIE_OFFSET, offset as u64, 0)?;
+        }
+
+        info!("  ✓ Socket cookies enabled (skc_cookie @{})", offset);
+        Ok(())
+    }
+
+    /// Enable accept queue tracking on listening sockets
+    ///
+    /// Writes the struct offsets the tcp_conn_request and
+    /// tcp_v4/v6_syn_recv_sock programs read the queues at. Without them,
+    /// the programs do nothing.
+    pub fn set_listen_queue_offsets(&mut self, offsets: &ListenQueueOffsets) -> Result<()> {
+        use probe_common::constants::{
+            CONFIG_SK_ACK_BACKLOG_OFFSET, CONFIG_SK_MAX_ACK_BACKLOG_OFFSET, CONFIG_SYN_QUEUE_LEN_OFFSET,
+        };
+
+        let mut settings = config_map(self.ebpf())?;
+        settings.set(CONFIG_SK_ACK_BACKLOG_OFFSET, offsets.ack_backlog as u64, 0)?;
+        settings.set(CONFIG_SK_MAX_ACK_BACKLOG_OFFSET, offsets.max_ack_backlog as u64, 0)?;
+        settings.set(CONFIG_SYN_QUEUE_LEN_OFFSET, offsets.syn_queue_len.unwrap_or(0) as u64, 0)?;
+
+        info!(
+            "  ✓ Accept queue tracking enabled (sk_ack_backlog @{}, sk_max_ack_backlog @{})",
+            offsets.ack_backlog, offsets.max_ack_backlog
+        );
+        Ok(())
+    }
+
+    /// Tell the kernel programs where the socket fields they read are
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - Offsets of the fields in `struct sock_common`
+    pub fn set_sock_common_layout(&mut self, layout: SockCommonLayout) -> Result<()> {
+        use probe_common::constants::CONFIG_SKC_LAYOUT;
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            config_map(&mut object.ebpf)?.set(CONFIG_SKC_LAYOUT, layout.pack(), 0)?;
+        }
+
+        if layout != SockCommonLayout::DEFAULT {
+            info!("  ✓ Socket fields at non-default offsets: {:?}", layout);
+        }
+        Ok(())
+    }
+
+    /// Enable QUIC flight tracking
+    ///
+    /// Datagrams sent to `port` are timed until the socket next reads a
+    /// datagram, like DNS queries. Needs the UDP kprobes (kprobe attach
+    /// mode).
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - UDP destination port of the QUIC servers
+    pub fn set_quic_port(&mut self, port: u16) -> Result<()> {
+        use probe_common::constants::CONFIG_QUIC_PORT;
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            config_map(&mut object.ebpf)?.set(CONFIG_QUIC_PORT, port as u64, 0)?;
+        }
+
+        info!("  ✓ QUIC tracking enabled (UDP port {})", port);
+        Ok(())
+    }
+
+    /// Select the clock the eBPF programs timestamp events with
+    ///
+    /// Set before attaching: latencies are differences of timestamps, so
+    /// the clock must not change while events are being timed.
+    pub fn set_clock_source(&mut self, source: ClockSource) -> Result<()> {
+        use probe_common::constants::CONFIG_CLOCK_SOURCE;
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            config_map(&mut object.ebpf)?.set(CONFIG_CLOCK_SOURCE, source.config_value(), 0)?;
+        }
+
+        if source != ClockSource::Monotonic {
+            info!("  ✓ Event timestamps from the {} clock", source);
+        }
+        Ok(())
+    }
+
+    /// Set the latency bounds outside of which the eBPF programs reject
+    /// measurements
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - Shortest latency accepted
+    /// * `max` - Longest latency accepted
+    pub fn set_latency_bounds(&mut self, min: Duration, max: Duration) -> Result<()> {
+        use probe_common::constants::{CONFIG_MAX_LATENCY_NS, CONFIG_MIN_LATENCY_NS};
+
+        if min >= max {
+            anyhow::bail!("Minimum latency {:?} must be below the maximum {:?}", min, max);
+        }
+
+        let primary = self.primary;
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if i != primary && object.ebpf.map("CONFIG").is_none() {
+                continue;
+            }
+            let mut config = config_map(&mut object.ebpf)?;
+            config.set(CONFIG_MIN_LATENCY_NS, min.as_nanos() as u64, 0)?;
+            config.set(CONFIG_MAX_LATENCY_NS, max.as_nanos() as u64, 0)?;
+        }
+
+        info!("  ✓ Latencies accepted from {:?} to {:?}", min, max);
+        Ok(())
+    }
+
+    /// Read the latency bounds in effect and the latencies each rejected
+    ///
+    /// The rejection counters are never reset, so they cover the whole run.
+    pub fn read_latency_bounds(&self) -> LatencyBounds {
+        use probe_common::constants::*;
+
+        let ebpf = &self.objects[self.primary].ebpf;
+        let config = |index: u32| -> u64 {
+            ebpf.map("CONFIG")
+                .and_then(|map| Array::<_, u64>::try_from(map).ok())
+                .and_then(|config| config.get(&index, 0).ok())
+                .unwrap_or(0)
+        };
+        let stat = |key: u32| -> u64 {
+            ebpf.map("STATS")
+                .and_then(|map| BpfHashMap::<_, u32, u64>::try_from(map).ok())
+                .and_then(|stats| stats.get(&key, 0).ok())
+                .unwrap_or(0)
+        };
+
+        let (min_latency_ns, max_latency_ns) = match config(CONFIG_MAX_LATENCY_NS) {
+            0 => (MIN_LATENCY_NS, MAX_LATENCY_NS),
+            max => (config(CONFIG_MIN_LATENCY_NS), max),
+        };
+        LatencyBounds {
+            min_latency_ns,
+            max_latency_ns,
+            below_min: stat(STAT_LATENCY_BELOW_MIN),
+            above_max: stat(STAT_LATENCY_ABOVE_MAX),
+        }
+    }
+
+    /// Read the kernel's discard counters from the STATS map
+    ///
+    /// Like the other STATS counters, they cover the whole run. The
+    /// returned `dropped_events` is 0; perf buffer losses are counted in
+    /// user space.
+    pub fn read_internal_counters(&self) -> InternalCounters {
+        use probe_common::constants::{STAT_INVALID_LATENCY, STAT_INVALID_SOCKETS};
+
+        let stats = self.objects[self.primary]
+            .ebpf
+            .map("STATS")
+            .and_then(|map| BpfHashMap::<_, u32, u64>::try_from(map).ok());
+        let stat = |key: u32| stats.as_ref().and_then(|stats| stats.get(&key, 0).ok()).unwrap_or(0);
+
+        InternalCounters {
+            invalid_sockets: stat(STAT_INVALID_SOCKETS),
+            invalid_latency: stat(STAT_INVALID_LATENCY),
+            dropped_events: 0,
+        }
+    }
+
     /// Read XDP statistics from the STATS BPF map
     pub fn read_xdp_stats(&mut self, elapsed_secs: u64) -> XdpPacketStats {
         use probe_common::constants::*;
 
-        let stats_map = match self.ebpf.map("STATS") {
+        let stats_map = match self.ebpf().map("STATS") {
             Some(map) => map,
             None => {
                 warn!("STATS map not found, returning empty XDP stats");
@@ -347,8 +1324,313 @@ impl ProbeLoader {
         }
     }
 
+    /// Read the occupancy of the hash maps the probe fills from the kernel
+    ///
+    /// CONNECTION_START fills with connections that never see a second
+    /// event; once full, new connections are not measured. Maps that cannot
+    /// be read are left out.
+    pub fn read_map_health(&self) -> Vec<MapHealth> {
+        use probe_common::constants::MAX_STATS;
+
+        let ebpf = &self.objects[self.primary].ebpf;
+        let mut health = Vec::new();
+
+        let connection_start = match ebpf.map("CONNECTION_START") {
+            // Built with the percpu-connection-start kernel feature
+            Some(map @ (Map::PerCpuHashMap(_) | Map::PerCpuLruHashMap(_))) => {
+                Some(PerCpuHashMap::<_, ConnectionKey, u64>::try_from(map).map(|map| {
+                    let (mut entries, mut split) = (0, 0);
+                    for (_, start_times) in map.iter().filter_map(Result::ok) {
+                        entries += 1;
+                        if start_times.iter().filter(|&&ns| ns != 0).count() > 1 {
+                            split += 1;
+                        }
+                    }
+                    let mut reading = MapHealth::new("CONNECTION_START", entries, self.map_sizes.connections);
+                    reading.split_entries = Some(split);
+                    reading
+                }))
+            }
+            Some(map) => Some(BpfHashMap::<_, ConnectionKey, u64>::try_from(map).map(|map| {
+                let entries = map.keys().filter(|k| k.is_ok()).count() as u64;
+                MapHealth::new("CONNECTION_START", entries, self.map_sizes.connections)
+            })),
+            None => None,
+        };
+        match connection_start {
+            Some(Ok(reading)) => health.push(reading),
+            Some(Err(e)) => debug!("Failed to read CONNECTION_START map: {}", e),
+            None => {}
+        }
+
+        match ebpf.map("STATS").map(BpfHashMap::<_, u32, u64>::try_from) {
+            Some(Ok(map)) => {
+                let entries = map.keys().filter(|k| k.is_ok()).count() as u64;
+                health.push(MapHealth::new("STATS", entries, MAX_STATS));
+            }
+            Some(Err(e)) => debug!("Failed to read STATS map: {}", e),
+            None => {}
+        }
+
+        health
+    }
+
+    /// Read the byte counters of tracked connections
+    ///
+    /// Entries of closed connections are removed once read, so the map
+    /// only holds live connections.
+    ///
+    /// # Returns
+    ///
+    /// The state of each connection in CONNECTION_STATES (empty if the map
+    /// cannot be read)
+    pub fn read_connection_states(&mut self) -> Vec<ConnectionState> {
+        use probe_common::constants::CONN_STATE_CLOSED;
+
+        let mut map = match self
+            .ebpf()
+            .map_mut("CONNECTION_STATES")
+            .map(BpfHashMap::<_, ConnectionKey, ConnectionState>::try_from)
+        {
+            Some(Ok(map)) => map,
+            Some(Err(e)) => {
+                debug!("Failed to read CONNECTION_STATES map: {}", e);
+                return Vec::new();
+            }
+            None => return Vec::new(),
+        };
+
+        let entries: Vec<(ConnectionKey, ConnectionState)> = map.iter().filter_map(Result::ok).collect();
+        for (key, _) in entries.iter().filter(|(_, state)| state.state == CONN_STATE_CLOSED) {
+            let _ = map.remove(key);
+        }
+
+        entries.into_iter().map(|(_, state)| state).collect()
+    }
+
+    /// Read the congestion state of connections seen by tcp:tcp_probe
+    ///
+    /// # Returns
+    ///
+    /// Each connection's 4-tuple and state (empty if the map cannot be read,
+    /// as when not attached with tracepoints)
+    pub fn read_congestion(&mut self) -> Vec<(ConnectionKey, CongestionState)> {
+        match self
+            .ebpf()
+            .map("CONGESTION")
+            .map(BpfHashMap::<_, ConnectionKey, CongestionState>::try_from)
+        {
+            Some(Ok(map)) => map.iter().filter_map(Result::ok).collect(),
+            Some(Err(e)) => {
+                debug!("Failed to read CONGESTION map: {}", e);
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Read the accept queue counters of listening sockets
+    ///
+    /// # Returns
+    ///
+    /// Each listener and its counters (empty if the map cannot be read)
+    pub fn read_listeners(&mut self) -> Vec<(ListenerKey, ListenerStats)> {
+        match self
+            .ebpf()
+            .map("LISTENERS")
+            .map(BpfHashMap::<_, ListenerKey, ListenerStats>::try_from)
+        {
+            Some(Ok(map)) => map.iter().filter_map(Result::ok).collect(),
+            Some(Err(e)) => {
+                debug!("Failed to read LISTENERS map: {}", e);
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
     /// Get reference to the eBPF object
-    pub fn ebpf(&mut self) -> &mut Bpf {
-        &mut self.ebpf
+    pub fn ebpf(&mut self) -> &mut Ebpf {
+        &mut self.objects[self.primary].ebpf
+    }
+
+    /// Take a map by its in-object name, preferring the latency object
+    fn take_named_map(&mut self, name: &str) -> Option<aya::maps::Map> {
+        if let Some(map) = self.ebpf().take_map(name) {
+            return Some(map);
+        }
+        self.objects.iter_mut().find_map(|o| o.ebpf.take_map(name))
+    }
+}
+
+/// Attach target recorded for a kprobe (`symbol`) or tracepoint
+/// (`category:name`)
+fn recorded_target(attached_to: &str) -> AttachTarget {
+    match attached_to.split_once(':') {
+        Some((category, name)) => AttachTarget::TracePoint(category.to_string(), name.to_string()),
+        None => AttachTarget::KProbe(attached_to.to_string()),
+    }
+}
+
+/// Copy the state a hot-swap keeps into a new object's maps
+///
+/// Settings (CONFIG, SERVICE_FILTER), the STATS counters and connection
+/// states carry over. In-flight timestamps do not: the old programs keep
+/// timing them until they detach.
+fn carry_state(from: &mut Ebpf, to: &mut Ebpf) -> Result<()> {
+    copy_hash_map::<u32, u64>(from, to, "STATS")?;
+    copy_hash_map::<ServiceFilterKey, u8>(from, to, "SERVICE_FILTER")?;
+    copy_hash_map::<ConnectionKey, ConnectionState>(from, to, "CONNECTION_STATES")?;
+    copy_hash_map::<ConnectionKey, CongestionState>(from, to, "CONGESTION")?;
+    copy_hash_map::<ListenerKey, ListenerStats>(from, to, "LISTENERS")?;
+
+    let settings = config_map(from)?;
+    let values: Vec<u64> = (0..settings.len()).map(|index| settings.get(&index, 0).unwrap_or(0)).collect();
+    let mut config = config_map(to)?;
+    for (index, value) in (0..config.len()).zip(values) {
+        config.set(index, value, 0)?;
+    }
+    Ok(())
+}
+
+/// Copy the entries of a hash map into the same map of another object
+///
+/// Entries removed by the kernel while being copied are skipped.
+fn copy_hash_map<K: aya::Pod, V: aya::Pod>(from: &Ebpf, to: &mut Ebpf, name: &str) -> Result<()> {
+    let (Some(source), Some(target)) = (from.map(name), to.map_mut(name)) else {
+        return Ok(());
+    };
+    let source =
+        BpfHashMap::<_, K, V>::try_from(source).with_context(|| format!("Failed to get {} as HashMap", name))?;
+    let mut target =
+        BpfHashMap::<_, K, V>::try_from(target).with_context(|| format!("Failed to get {} as HashMap", name))?;
+    for (key, value) in source.iter().filter_map(Result::ok) {
+        target
+            .insert(key, value, 0)
+            .with_context(|| format!("Failed to copy an entry of {}", name))?;
+    }
+    Ok(())
+}
+
+/// Passes eBPF log records to the global logger under a per-object target
+struct EbpfLogForwarder {
+    target: String,
+}
+
+impl log::Log for EbpfLogForwarder {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        let metadata = log::Metadata::builder()
+            .level(metadata.level())
+            .target(&self.target)
+            .build();
+        log::logger().enabled(&metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        log::logger().log(
+            &log::Record::builder()
+                .args(*record.args())
+                .level(record.level())
+                .target(&self.target)
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        log::logger().flush();
+    }
+}
+
+/// Write sampling and filter settings to one object's maps
+///
+/// Event types without their own rate get 0, so a reload that drops a rate
+/// reverts the type to the global one.
+fn write_config(
+    ebpf: &mut Ebpf,
+    config: &ProbeConfig,
+    event_rates: &BTreeMap<u8, u32>,
+    filter_keys: &[ServiceFilterKey],
+) -> Result<()> {
+    use probe_common::constants::{
+        CONFIG_EVENT_SAMPLE_RATE, CONFIG_FILTER_ENABLED, CONFIG_SAMPLE_MODE, CONFIG_SAMPLE_RATE,
+    };
+
+    let mut settings = config_map(ebpf)?;
+    settings.set(CONFIG_FILTER_ENABLED, 0, 0)?;
+    settings.set(CONFIG_SAMPLE_RATE, config.sample_rate as u64, 0)?;
+    settings.set(CONFIG_SAMPLE_MODE, config.sample_mode.config_value(), 0)?;
+    for (_, event_type) in SAMPLED_EVENT_TYPES {
+        let rate = event_rates.get(&event_type).copied().unwrap_or(0);
+        let written = settings.set(CONFIG_EVENT_SAMPLE_RATE + event_type as u32, rate as u64, 0);
+        // Objects built before per event type sampling have fewer slots
+        if written.is_err() && rate != 0 {
+            written.context("eBPF object does not support per event type sampling rates")?;
+        }
+    }
+
+    let mut filter: BpfHashMap<&mut MapData, ServiceFilterKey, u8> = BpfHashMap::try_from(
+        ebpf.map_mut("SERVICE_FILTER")
+            .context("SERVICE_FILTER map not found in eBPF object")?,
+    )
+    .context("Failed to open SERVICE_FILTER map")?;
+    let existing = filter.keys().collect::<Result<Vec<_>, _>>()?;
+    for key in &existing {
+        filter.remove(key)?;
     }
+    for key in filter_keys {
+        filter.insert(key, 1, 0)?;
+    }
+
+    if !filter_keys.is_empty() {
+        config_map(ebpf)?.set(CONFIG_FILTER_ENABLED, 1, 0)?;
+    }
+
+    Ok(())
+}
+
+/// Open an object's CONFIG map for writing
+fn config_map(ebpf: &mut Ebpf) -> Result<Array<&mut MapData, u64>> {
+    let map = ebpf
+        .map_mut("CONFIG")
+        .context("CONFIG map not found in eBPF object")?;
+
+    Array::try_from(map).context("Failed to open CONFIG map")
+}
+
+/// Load and attach a program from an additional object
+///
+/// # Returns
+///
+/// The target attached to, or None if the program has no known target
+fn attach_program(
+    program: &mut Program,
+    name: &str,
+    target: Option<&AttachTarget>,
+) -> Result<Option<AttachTarget>> {
+    let target = match (program, target) {
+        (Program::KProbe(p), target) => {
+            let function = match target {
+                Some(AttachTarget::KProbe(function)) => function.as_str(),
+                Some(other) => anyhow::bail!("kprobe program cannot attach to {}", other),
+                None => name,
+            };
+            p.load()?;
+            p.attach(function, 0)?;
+            AttachTarget::KProbe(function.to_string())
+        }
+        (Program::TracePoint(p), Some(AttachTarget::TracePoint(category, tracepoint))) => {
+            p.load()?;
+            p.attach(category, tracepoint)?;
+            AttachTarget::TracePoint(category.clone(), tracepoint.clone())
+        }
+        (Program::TracePoint(_), Some(other)) => {
+            anyhow::bail!("tracepoint program cannot attach to {}", other)
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(target))
 }
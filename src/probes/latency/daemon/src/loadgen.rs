@@ -0,0 +1,264 @@
+//! Load generator results
+//!
+//! Reads the JSON results of the load generator driving a benchmark, so
+//! client-observed latency can be compared with kernel-observed latency of
+//! the same run window. Supported formats, detected from their contents:
+//!
+//! - fortio: `fortio load -json results.json`
+//! - k6: `k6 run --summary-export results.json` (or a `handleSummary`
+//!   returning `JSON.stringify(data)`); add `p(99)` to
+//!   `--summary-trend-stats` for a p99
+//! - wrk2: the JSON written by the `done` script in docs/ebpf/probes.md
+//!
+//! The difference between the client's percentiles and the kernel's is the
+//! time spent above the socket layer: in the client, the sidecar proxies
+//! and the application. Kernel latency at the proxy ports (see
+//! crate::services) is included, so it can be split further.
+
+use crate::types::{ClientLatency, ClientPercentiles, LatencyMetrics, Percentiles};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+
+/// Prefix of the service names of sidecar proxy ports
+const PROXY_SERVICE_PREFIX: &str = "envoy";
+
+/// Latency reported by a load generator
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientResults {
+    /// Tool that produced the results ("fortio", "k6", "wrk2")
+    pub tool: String,
+    /// Requests sent
+    pub requests: u64,
+    /// Requests that failed
+    pub errors: u64,
+    /// ISO 8601 start of the load, if reported
+    pub started_at: Option<String>,
+    /// Length of the load in seconds, if reported
+    pub duration_seconds: Option<f64>,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles in microseconds
+    pub percentiles: ClientPercentiles,
+}
+
+impl ClientResults {
+    /// Read results from a JSON file, detecting the tool
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the load generator's JSON output
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read client results: {:?}", path))?;
+        Self::parse(&contents).with_context(|| format!("Failed to parse client results: {:?}", path))
+    }
+
+    /// Parse fortio, k6 or wrk2 JSON results
+    pub fn parse(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json).context("Invalid JSON")?;
+
+        if value.get("DurationHistogram").is_some() {
+            parse_fortio(&value)
+        } else if value.get("metrics").is_some() {
+            parse_k6(&value)
+        } else if value.get("latency").is_some() && value.get("requests").is_some() {
+            parse_wrk2(&value)
+        } else {
+            anyhow::bail!("Unrecognized load generator output (expected fortio, k6 or wrk2 JSON)")
+        }
+    }
+
+    /// Compare the results with the kernel-observed latency of a report
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Report of the same run window
+    pub fn compare(&self, metrics: &LatencyMetrics) -> ClientLatency {
+        ClientLatency {
+            tool: self.tool.clone(),
+            requests: self.requests,
+            errors: self.errors,
+            started_at: self.started_at.clone(),
+            duration_seconds: self.duration_seconds,
+            avg_latency_us: self.avg_latency_us,
+            percentiles: self.percentiles.clone(),
+            above_kernel: self.percentiles.above(&metrics.percentiles),
+            proxies: metrics
+                .services
+                .iter()
+                .filter(|(name, _)| name.starts_with(PROXY_SERVICE_PREFIX))
+                .map(|(name, service)| (name.clone(), service.percentiles.clone()))
+                .collect::<BTreeMap<String, Percentiles>>(),
+        }
+    }
+}
+
+/// Number at a JSON path, if present
+fn number(value: &Value, path: &[&str]) -> Option<f64> {
+    path.iter().try_fold(value, |value, key| value.get(key))?.as_f64()
+}
+
+/// fortio: latencies in seconds, duration in nanoseconds
+fn parse_fortio(value: &Value) -> Result<ClientResults> {
+    let histogram = &value["DurationHistogram"];
+    let requests = number(histogram, &["Count"]).context("fortio results without DurationHistogram.Count")? as u64;
+
+    let mut percentiles = ClientPercentiles::default();
+    for entry in histogram["Percentiles"].as_array().into_iter().flatten() {
+        let (Some(percentile), Some(seconds)) = (number(entry, &["Percentile"]), number(entry, &["Value"])) else {
+            continue;
+        };
+        percentiles.set(percentile, seconds * 1e6);
+    }
+
+    // HTTP status codes, or gRPC health states
+    let ok: u64 = value["RetCodes"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(code, _)| code.starts_with('2') || code.as_str() == "SERVING")
+        .filter_map(|(_, count)| count.as_u64())
+        .sum();
+    let errors = if value.get("RetCodes").is_some() {
+        requests.saturating_sub(ok)
+    } else {
+        0
+    };
+
+    Ok(ClientResults {
+        tool: "fortio".to_string(),
+        requests,
+        errors,
+        started_at: value["StartTime"].as_str().map(str::to_string),
+        duration_seconds: number(value, &["ActualDuration"]).map(|ns| ns / 1e9),
+        avg_latency_us: number(histogram, &["Avg"]).unwrap_or_default() * 1e6,
+        percentiles,
+    })
+}
+
+/// k6: latencies in milliseconds, under `values` in handleSummary data
+fn parse_k6(value: &Value) -> Result<ClientResults> {
+    let metric = |name: &str| {
+        let metric = &value["metrics"][name];
+        metric.get("values").unwrap_or(metric).clone()
+    };
+    let durations = metric("http_req_duration");
+    if durations.is_null() {
+        anyhow::bail!("k6 results without http_req_duration");
+    }
+
+    let mut percentiles = ClientPercentiles::default();
+    if let Some(median) = number(&durations, &["med"]) {
+        percentiles.set(50.0, median * 1e3);
+    }
+    for (key, ms) in durations.as_object().into_iter().flatten() {
+        let percentile = key
+            .strip_prefix("p(")
+            .and_then(|key| key.strip_suffix(')'))
+            .and_then(|p| p.parse::<f64>().ok());
+        if let (Some(percentile), Some(ms)) = (percentile, ms.as_f64()) {
+            percentiles.set(percentile, ms * 1e3);
+        }
+    }
+
+    // For rate metrics, "passes" counts true values: failed requests
+    let failed = metric("http_req_failed");
+
+    Ok(ClientResults {
+        tool: "k6".to_string(),
+        requests: number(&metric("http_reqs"), &["count"]).unwrap_or_default() as u64,
+        errors: number(&failed, &["passes"]).unwrap_or_default() as u64,
+        started_at: None,
+        duration_seconds: number(value, &["state", "testRunDurationMs"]).map(|ms| ms / 1e3),
+        avg_latency_us: number(&durations, &["avg"]).unwrap_or_default() * 1e3,
+        percentiles,
+    })
+}
+
+/// wrk2 `done` script output: latencies in microseconds
+fn parse_wrk2(value: &Value) -> Result<ClientResults> {
+    let mut percentiles = ClientPercentiles::default();
+    for (key, us) in value["latency"]["percentiles"].as_object().into_iter().flatten() {
+        if let (Ok(percentile), Some(us)) = (key.parse::<f64>(), us.as_f64()) {
+            percentiles.set(percentile, us);
+        }
+    }
+
+    Ok(ClientResults {
+        tool: "wrk2".to_string(),
+        requests: number(value, &["requests"]).context("wrk2 results without requests")? as u64,
+        errors: number(value, &["errors"]).unwrap_or_default() as u64,
+        started_at: None,
+        duration_seconds: number(value, &["duration_us"]).map(|us| us / 1e6),
+        avg_latency_us: number(value, &["latency", "mean"]).unwrap_or_default(),
+        percentiles,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_client_results() {
+        let fortio = ClientResults::parse(
+            r#"{"StartTime": "2025-01-01T00:00:00Z", "ActualDuration": 60000000000,
+                "DurationHistogram": {"Count": 1000, "Avg": 0.002,
+                    "Percentiles": [{"Percentile": 50, "Value": 0.0015}, {"Percentile": 99, "Value": 0.01}]},
+                "RetCodes": {"200": 990, "503": 10}}"#,
+        )
+        .unwrap();
+        assert_eq!(fortio.tool, "fortio");
+        assert_eq!((fortio.requests, fortio.errors), (1000, 10));
+        assert_eq!(fortio.duration_seconds, Some(60.0));
+        assert_eq!(fortio.percentiles.p50, Some(1500.0));
+        assert_eq!(fortio.percentiles.p99, Some(10000.0));
+        assert_eq!(fortio.percentiles.p90, None);
+
+        let k6 = ClientResults::parse(
+            r#"{"metrics": {
+                "http_req_duration": {"avg": 2.5, "med": 2.0, "p(90)": 4.0, "p(99)": 9.5},
+                "http_reqs": {"count": 500, "rate": 8.3},
+                "http_req_failed": {"passes": 3, "fails": 497, "value": 0.006}}}"#,
+        )
+        .unwrap();
+        assert_eq!(k6.tool, "k6");
+        assert_eq!((k6.requests, k6.errors), (500, 3));
+        assert_eq!(k6.percentiles.p50, Some(2000.0));
+        assert_eq!(k6.percentiles.p99, Some(9500.0));
+
+        // handleSummary data nests the statistics under "values"
+        let k6 = ClientResults::parse(
+            r#"{"state": {"testRunDurationMs": 30000}, "metrics": {
+                "http_req_duration": {"type": "trend", "values": {"avg": 1.0, "p(95)": 3.0}},
+                "http_reqs": {"values": {"count": 10}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(k6.percentiles.p95, Some(3000.0));
+        assert_eq!(k6.duration_seconds, Some(30.0));
+
+        let wrk2 = ClientResults::parse(
+            r#"{"requests": 200, "duration_us": 10000000, "errors": 1,
+                "latency": {"mean": 900.0, "percentiles": {"50": 800, "99": 4000, "99.9": 7000}}}"#,
+        )
+        .unwrap();
+        assert_eq!(wrk2.tool, "wrk2");
+        assert_eq!(wrk2.percentiles.p999, Some(7000.0));
+
+        let metrics = LatencyMetrics {
+            percentiles: Percentiles {
+                p50: 300.0,
+                p99: 1500.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let comparison = wrk2.compare(&metrics);
+        assert_eq!(comparison.above_kernel.p50, Some(500.0));
+        assert_eq!(comparison.above_kernel.p99, Some(2500.0));
+        assert_eq!(comparison.above_kernel.p90, None);
+
+        assert!(ClientResults::parse(r#"{"something": "else"}"#).is_err());
+    }
+}
@@ -9,8 +9,11 @@
 //! # Run for 60 seconds and export to JSON
 //! sudo ./latency-probe --duration 60 --output metrics.json
 //!
-//! # Run with XDP on a specific interface
-//! sudo ./latency-probe --duration 60 --interface enp0s6
+//! # Run with XDP on specific interfaces
+//! sudo ./latency-probe --duration 60 --interface enp0s6 --interface cni0
+//!
+//! # Only report sockets in one network namespace (inode from `ls -l /proc/<pid>/ns/net`)
+//! sudo ./latency-probe --duration 60 --netns 4026532288
 //!
 //! # Run with sampling (capture 1 in 100 events)
 //! sudo ./latency-probe --duration 60 --sample-rate 100
@@ -18,209 +21,2556 @@
 //! # Use external eBPF object file
 //! sudo ./latency-probe --ebpf-object path/to/latency-probe.o
 //!
+//! # Pin perf buffer readers to their CPUs at high event rates
+//! sudo ./latency-probe --duration 60 --pin-readers
+//!
+//! # Only track one service, with settings reloadable from a file
+//! sudo ./latency-probe --duration 0 --filter-service 10.96.0.15:8080
+//! sudo ./latency-probe --duration 0 --config probe.yaml
+//!
 //! # Export to Prometheus format
 //! sudo ./latency-probe --duration 60 --format prometheus --output metrics.prom
+//!
+//! # Record sampled events, then replay them later without root
+//! sudo ./latency-probe --duration 60 --record events.jsonl
+//! ./latency-probe --replay events.jsonl --format prometheus --output metrics.prom
+//!
+//! # Stream snapshots every 10 seconds so a crash does not lose the run
+//! sudo ./latency-probe --duration 0 --stream stream.jsonl --stream-events
+//!
+//! # Compress the report and recording of a long run
+//! sudo ./latency-probe --duration 3600 --compress zstd --output report.json.zst --record events.jsonl.zst
+//!
+//! # Keep a registry of runs, and list them later
+//! sudo ./latency-probe --duration 60 --output metrics.json --registry /var/lib/latency-probe/runs
+//! ./latency-probe --registry /var/lib/latency-probe/runs runs list
+//!
+//! # Run the phases of a benchmark scenario, one report per phase
+//! sudo ./latency-probe --output results.json scenario run scenario.yaml
+//!
+//! # Run as a service: PID file, sd_notify, SIGHUP rotation, SIGUSR1 snapshots
+//! sudo ./latency-probe --daemon --duration 0 --pid-file /run/latency-probe.pid
+//!
+//! # Check that the programs pass the verifier (e.g. in CI), without attaching
+//! sudo ./latency-probe --dry-run --ebpf-object path/to/latency-probe.o
+//!
+//! # Load several eBPF objects (latency, drops, custom) from a manifest
+//! sudo ./latency-probe --ebpf-manifest probes.yaml
+//!
+//! # Smoke-test the probes on a new kernel or node
+//! sudo ./latency-probe selftest --ebpf-object path/to/latency-probe.o
 //! ```
 
-use anyhow::Result;
-use clap::Parser;
-use latency_probe_userspace::{
-    collector::MetricsCollector,
-    events::EventProcessor,
-    exporter::{ExporterType, InfluxExporter, JsonExporter, MetricsExporter, PrometheusExporter},
-    loader::ProbeLoader,
-    types::LatencyMetrics,
-};
-use log::info;
-use std::{path::PathBuf, sync::Arc, time::Duration};
-use tokio::{
-    signal,
-    sync::Mutex,
-    time::{sleep, Instant},
-};
+use anyhow::{Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use chrono::Local;
+use latency_probe_userspace::{
+    api::{ApiServer, ApiState},
+    backpressure::{OverflowPolicy, DEFAULT_CHANNEL_CAPACITY},
+    btf::Btf,
+    clock::ClockSource,
+    checkpoint,
+    collector::MetricsCollector,
+    dedup::DedupPolicy,
+    compress::Compression,
+    config::{ConfigWatcher, ProbeConfig, SampleMode, SampleRates},
+    conntrack::ConntrackCache,
+    correlation::CorrelationWriter,
+    daemon::{self, DaemonSignal, DaemonSignals, PidFile},
+    environment,
+    events::{EventProcessor, LoopbackFilter, PerfBufferOptions, ReaderPlacement},
+    jsonl::JsonLinesWriter,
+    exporter::{
+        ExporterType, HeatmapExporter, InfluxExporter, JsonExporter, MetricsExporter,
+        PrometheusExporter, SummaryExporter, SummaryStyle,
+    },
+    faults::{self, FaultInjector, FaultSpec},
+    health::{Health, HealthPolicy, DEFAULT_MAX_EXPORT_FAILURES},
+    host,
+    instance::InstanceId,
+    listen::ListenQueueOffsets,
+    loader::{AttachMode, MapSizes, ProbeLoader},
+    loadgen::ClientResults,
+    netns::NetnsOffsets,
+    objects::ObjectManifest,
+    openmetrics::OpenMetricsExporter,
+    output,
+    omission,
+    privileges::{self, Credentials},
+    pods::PodCache,
+    process::ProcessCache,
+    publish::{Blocking, ExportQueue, Publisher, RetryPolicy, DEFAULT_EXPORT_QUEUE},
+    services::{self, ServiceClassifier},
+    registry::{RunRecord, RunRegistry},
+    replay::EventRecorder,
+    run::RunId,
+    scenario::{self, Scenario, ScenarioSummary},
+    selftest::{self, SelftestConfig},
+    stitch,
+    textfile::TextfileWriter,
+    tenants::TenantSet,
+    timesync,
+    tracefs::TcpProbeOffsets,
+    trigger::{CaptureDump, CaptureTrigger, TriggerAction, TriggerPolicy},
+    types::{
+        ClockInfo, ExportStats, KernelFeatures, LatencyBounds, LatencyMetrics, ProgramStats, UsedFeatures, XdpPacketStats,
+        DEFAULT_RATE_RESOLUTION_MS,
+    },
+    variance,
+    verify,
+    window::WINDOW_PANES,
+    zabbix::{self, ZabbixSender},
+    zones::ZoneMap,
+};
+use log::{debug, info, warn};
+#[cfg(feature = "arrow")]
+use latency_probe_userspace::arrow::{ArrowExporter, ArrowRecorder};
+#[cfg(feature = "envoy")]
+use latency_probe_userspace::envoy::{EnvoyScraper, DEFAULT_HISTOGRAMS};
+#[cfg(feature = "istio")]
+use latency_probe_userspace::istio::{self, IstioTelemetry};
+#[cfg(feature = "kubernetes")]
+use latency_probe_userspace::{
+    aggregate::Aggregate,
+    controller::Controller,
+    kubernetes::{self, KubeClient, PodSelector},
+    leader::{ClusterExporter, LeaderElection},
+};
+#[cfg(feature = "nats")]
+use latency_probe_userspace::nats::{NatsPublisher, NatsSubjects};
+#[cfg(feature = "webhook")]
+use latency_probe_userspace::notifier::{self, Notification, Notifier, SloPolicy, WebhookFormat};
+#[cfg(feature = "proto")]
+use latency_probe_userspace::proto::ProtobufExporter;
+#[cfg(feature = "seal")]
+use latency_probe_userspace::seal::{self, Sealer};
+#[cfg(feature = "upload")]
+use latency_probe_userspace::upload::{AccessKey, UploadTarget, Uploader};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    signal,
+    sync::{mpsc, Mutex},
+    time::{interval_at, sleep_until, Instant},
+};
+
+/// Network latency tracking probe using eBPF
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Duration to run the probe (in seconds, 0 = infinite)
+    #[clap(short, long, default_value_t = 60)]
+    duration: u64,
+
+    /// Output file for metrics (`-` for stdout, e.g. to pipe into jq)
+    #[clap(short, long, default_value = "latency-metrics.json")]
+    output: PathBuf,
+
+    /// Output format (json, prometheus, openmetrics, influx, heatmap, table,
+    /// markdown, or arrow/protobuf when built with the arrow/proto feature)
+    #[clap(short, long, default_value = "json")]
+    format: String,
+
+    /// Leave timestamps out of OpenMetrics samples (node_exporter's
+    /// textfile collector rejects files with timestamps)
+    #[clap(long)]
+    no_sample_timestamps: bool,
+
+    /// Round floats in JSON reports to a fixed precision, so identical runs
+    /// diff cleanly (keys are always sorted)
+    #[clap(long)]
+    canonical_json: bool,
+
+    /// Connections listed in table and markdown reports, busiest first
+    #[clap(long, default_value_t = 10)]
+    top_connections: usize,
+
+    /// Multiple of the median 10ms event rate above which events count as
+    /// a microburst
+    #[clap(long, default_value_t = latency_probe_userspace::burst::DEFAULT_BURST_FACTOR)]
+    burst_factor: f64,
+
+    /// Which of a receive reported by both tcp_recvmsg and tcp_cleanup_rbuf
+    /// counts: recv, cleanup, separate (both, cleanups in a pool of their
+    /// own), or both
+    #[clap(long, value_enum, default_value_t = DedupPolicy::Recv)]
+    dedup: DedupPolicy,
+
+    /// Break the events above p99 down by the packet drops and context
+    /// switches seen during them
+    #[clap(long)]
+    tail_analysis: bool,
+
+    /// Most connections to track: sizes the kernel connection maps, and
+    /// limits the connections with their own latency breakdown (events on
+    /// further connections only count towards the totals)
+    #[clap(long, default_value_t = probe_common::constants::MAX_CONNECTIONS as usize)]
+    max_connections: usize,
+
+    /// Most processes with their own latency breakdown (events of further
+    /// processes only count towards the totals)
+    #[clap(long, default_value_t = 4096)]
+    max_processes: usize,
+
+    /// Aggregate connections that differ only in an ephemeral port
+    /// (49152-65535), reporting the port as "*"
+    #[clap(long)]
+    normalize_ports: bool,
+
+    /// Latency events each per-CPU perf buffer holds before events are
+    /// dropped (sizes the buffers; overrides the aya default)
+    #[clap(long, conflicts_with_all = ["replay", "perf_pages"])]
+    max_events: Option<usize>,
+
+    /// Compress JSON reports and --record recordings as they are written
+    #[clap(long, value_enum, default_value_t = Compression::None)]
+    compress: Compression,
+
+    /// Sampling rate (1 = capture all, 100 = capture 1 in 100), for every
+    /// event type or per type (e.g. recv=100,cleanup=100,send=1, or
+    /// 10,send=1); types: send, recv, cleanup, tcp_probe, dns, quic
+    #[clap(short, long, default_value = "1")]
+    sample_rate: SampleRates,
+
+    /// Sample single events, or whole connections (keeps per-connection
+    /// statistics intact)
+    #[clap(long, value_enum, default_value_t = SampleMode::Event)]
+    sample_mode: SampleMode,
+
+    /// Network interface for XDP attachment (e.g., enp0s6, eth0; repeatable)
+    #[clap(short, long)]
+    interface: Vec<String>,
+
+    /// Only report events from this network namespace inode (repeatable)
+    #[clap(long)]
+    netns: Vec<u32>,
+
+    /// Leave out connections on loopback (e.g. sidecar to app on 127.0.0.1)
+    #[clap(long, conflicts_with = "only_loopback")]
+    exclude_loopback: bool,
+
+    /// Only report connections on loopback
+    #[clap(long)]
+    only_loopback: bool,
+
+    /// Leave out events on half-open connections (handshake not complete)
+    #[clap(long)]
+    exclude_half_open: bool,
+
+    /// Also time QUIC (HTTP/3) traffic: from the first datagram a socket
+    /// sends to --quic-port to the next datagram it reads
+    #[clap(long)]
+    quic: bool,
+
+    /// UDP port of the QUIC servers
+    #[clap(long, default_value_t = probe_common::constants::DEFAULT_QUIC_PORT, requires = "quic")]
+    quic_port: u16,
+
+    /// Only track this service (format: IP:PORT, *:PORT or IP:*, repeatable)
+    #[clap(long)]
+    filter_service: Vec<String>,
+
+    /// Only track the pods of this Kubernetes namespace, following their
+    /// IPs as they are rescheduled (uses the in-cluster service account)
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, conflicts_with = "replay")]
+    k8s_namespace: Option<String>,
+
+    /// Label selector narrowing --k8s-namespace, e.g. app=frontend
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, requires = "k8s_namespace")]
+    k8s_selector: Option<String>,
+
+    /// Seconds between pod list requests for --k8s-namespace
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, default_value_t = 5)]
+    k8s_poll_interval: u64,
+
+    /// Elect the aggregator of the cluster with this Kubernetes Lease; the
+    /// other probes push their reports to its --listen API
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, requires = "listen", conflicts_with = "replay")]
+    cluster_lease: Option<String>,
+
+    /// Namespace of --cluster-lease (default: the probe's own)
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, requires = "cluster_lease")]
+    cluster_namespace: Option<String>,
+
+    /// Address other probes reach this one's --listen API at (default:
+    /// $POD_IP and the --listen port)
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, value_name = "HOST:PORT", requires = "cluster_lease")]
+    cluster_advertise: Option<String>,
+
+    /// Seconds --cluster-lease stays valid without renewal; a failed
+    /// aggregator is replaced within about twice that
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, default_value_t = 15)]
+    cluster_lease_duration: u64,
+
+    /// File the aggregator writes the merged report of the cluster to on
+    /// every export (it is always served at /aggregate)
+    #[cfg(feature = "kubernetes")]
+    #[clap(long, requires = "cluster_lease")]
+    cluster_output: Option<PathBuf>,
+
+    /// Name the service on a port, e.g. 9080=reviews (repeatable; adds to
+    /// the built-in names such as 6379=redis)
+    #[clap(long)]
+    port_service: Vec<String>,
+
+    /// YAML file mapping ports to service names (port: name)
+    #[clap(long)]
+    service_map: Option<PathBuf>,
+
+    /// YAML file listing the networks of each availability zone (zone:
+    /// list of CIDRs), to report intra-zone and cross-zone latency
+    #[clap(long)]
+    zone_map: Option<PathBuf>,
+
+    /// YAML file naming a filter (cidrs, ports, namespaces) per tenant, to
+    /// report latency per tenant; the filters also narrow the kernel
+    /// service filter and the namespace filter where they can
+    #[clap(long)]
+    tenants: Option<PathBuf>,
+
+    /// Runtime config file (YAML) with sample_rate, event_sample_rates and
+    /// filter_services, reloaded on change or SIGHUP; overrides
+    /// --sample-rate and --filter-service
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Verbose logging
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Path to eBPF object file (if not embedded)
+    #[clap(long, global = true)]
+    ebpf_object: Option<PathBuf>,
+
+    /// Load every eBPF object in this directory (latency, drops,
+    /// conn-state, and custom objects, identified by file name)
+    #[clap(long, conflicts_with_all = ["ebpf_object", "ebpf_manifest"])]
+    ebpf_dir: Option<PathBuf>,
+
+    /// Load the eBPF objects listed in this manifest (YAML)
+    #[clap(long, conflicts_with = "ebpf_object")]
+    ebpf_manifest: Option<PathBuf>,
+
+    /// Pin each perf buffer reader to the CPU whose buffer it reads
+    #[clap(long, conflicts_with = "replay")]
+    pin_readers: bool,
+
+    /// Run perf buffer readers on a dedicated runtime with this many
+    /// threads, separate from the control plane
+    #[clap(long, conflicts_with_all = ["replay", "pin_readers"])]
+    reader_threads: Option<usize>,
+
+    /// Pages per per-CPU perf buffer (power of two)
+    #[clap(long, conflicts_with = "replay")]
+    perf_pages: Option<usize>,
+
+    /// Events read from a perf buffer per read
+    #[clap(long, conflicts_with = "replay", default_value_t = 10)]
+    read_buffers: usize,
+
+    /// Events to accumulate before a reader wakes up (higher batches reads
+    /// at the cost of latency in event delivery)
+    #[clap(long, conflicts_with = "replay", default_value_t = 1)]
+    wakeup_events: usize,
+
+    /// Latency events queued per CPU between the perf buffer readers and
+    /// the collector
+    #[clap(long, default_value_t = DEFAULT_CHANNEL_CAPACITY, conflicts_with = "replay")]
+    channel_capacity: usize,
+
+    /// What happens to a latency event that finds its queue full
+    #[clap(long, value_enum, default_value_t = OverflowPolicy::Block, conflicts_with = "replay")]
+    overflow_policy: OverflowPolicy,
+
+    /// Progress reporting interval in seconds
+    #[clap(long, default_value_t = 10)]
+    progress_interval: u64,
+
+    /// Interval in seconds between eBPF map occupancy checks
+    #[clap(long, default_value_t = 10)]
+    health_interval: u64,
+
+    /// Width in milliseconds of the time buckets of the event rate and
+    /// latency heatmap
+    #[clap(long, default_value_t = DEFAULT_RATE_RESOLUTION_MS)]
+    rate_resolution_ms: u64,
+
+    /// Correct for coordinated omission, given the interval at which the
+    /// load generator meant to send requests (e.g. 1ms)
+    #[clap(long)]
+    expected_interval: Option<String>,
+
+    /// Seconds to cache PID to process name and pod lookups (0 = report PIDs
+    /// only)
+    #[clap(long, default_value_t = 30)]
+    process_ttl: u64,
+
+    /// Attribute connections to their destination behind NAT (kube-proxy
+    /// ClusterIPs), looked up in conntrack (needs CAP_NET_ADMIN)
+    #[clap(long)]
+    conntrack: bool,
+
+    /// JSON results of the load generator (fortio, k6 or wrk2) for the same
+    /// window, compared with kernel latency in the report
+    #[clap(long)]
+    client_results: Option<PathBuf>,
+
+    /// Envoy admin API to read latency histograms from at the start and end
+    /// of the run, e.g. http://10.0.0.5:15000 (repeatable)
+    #[cfg(feature = "envoy")]
+    #[clap(long, conflicts_with = "replay")]
+    envoy_admin: Vec<String>,
+
+    /// Envoy histograms to read with --envoy-admin (an Envoy stats filter
+    /// regex)
+    #[cfg(feature = "envoy")]
+    #[clap(long, default_value = DEFAULT_HISTOGRAMS)]
+    envoy_histograms: String,
+
+    /// Prometheus server to pull Istio's request latency over the run from
+    /// when it ends, e.g. http://prometheus.istio-system:9090
+    #[cfg(feature = "istio")]
+    #[clap(long, conflicts_with = "replay")]
+    istio_prometheus: Option<String>,
+
+    /// Label matchers of the Istio series compared with --istio-prometheus
+    #[cfg(feature = "istio")]
+    #[clap(long, default_value = istio::DEFAULT_SELECTOR)]
+    istio_selector: String,
+
+    /// Record sampled events to a JSON Lines file for later replay
+    #[clap(long)]
+    record: Option<PathBuf>,
+
+    /// Stream metrics snapshots (and with --stream-events, sampled events)
+    /// to this JSON Lines file while collecting, so a crash mid-run does not
+    /// lose the whole run
+    #[clap(long)]
+    stream: Option<PathBuf>,
+
+    /// Write a line per sampled event to --stream
+    #[clap(long, requires = "stream")]
+    stream_events: bool,
+
+    /// Write sampled events with the correlation ID of their connection
+    /// (node ID and socket cookie) to a JSON Lines file, for stitching
+    /// with other nodes (experimental)
+    #[clap(long, conflicts_with = "replay")]
+    correlation: Option<PathBuf>,
+
+    /// ID of this node in correlation records (default: $NODE_NAME, else
+    /// the host name)
+    #[clap(long)]
+    node_id: Option<String>,
+
+    /// Interval in seconds between snapshots written to --stream (and sent
+    /// to Zabbix, NATS and the cluster aggregator, and written to
+    /// --textfile-dir)
+    #[clap(long, default_value_t = 10)]
+    stream_interval: u64,
+
+    /// Keep latency_probe.prom up to date in this node_exporter textfile
+    /// collector directory (e.g. /var/lib/node_exporter/textfile)
+    #[clap(long)]
+    textfile_dir: Option<PathBuf>,
+
+    /// Publish snapshots and the final report to this NATS server
+    /// (JetStream, e.g. nats://nats:4222)
+    #[cfg(feature = "nats")]
+    #[clap(long)]
+    nats_url: Option<String>,
+
+    /// JetStream subject for the final (and rotated) reports
+    #[cfg(feature = "nats")]
+    #[clap(long, default_value = "latency-probe.report")]
+    nats_subject: String,
+
+    /// JetStream subject for interval snapshots (every --stream-interval
+    /// seconds)
+    #[cfg(feature = "nats")]
+    #[clap(long, default_value = "latency-probe.snapshot")]
+    nats_snapshot_subject: String,
+
+    /// Send p50/p99 and event counts to this Zabbix server or proxy
+    /// (host[:port], default port 10051) with every snapshot and report
+    #[clap(long)]
+    zabbix_server: Option<String>,
+
+    /// Host name the Zabbix items belong to (default: this host's name)
+    #[clap(long, requires = "zabbix_server")]
+    zabbix_host: Option<String>,
+
+    /// Seconds each export to Zabbix, NATS, or --textfile-dir may take
+    /// before it is abandoned
+    #[clap(long, default_value_t = 10)]
+    export_timeout: u64,
+
+    /// Attempts at exporting a finished report to Zabbix, NATS, or
+    /// --textfile-dir (snapshots are exported once)
+    #[clap(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    export_attempts: u32,
+
+    /// Reports queued for the exporter thread; snapshots that find the
+    /// queue full are dropped
+    #[clap(long, default_value_t = DEFAULT_EXPORT_QUEUE, value_parser = clap::value_parser!(u32).range(1..))]
+    export_queue: u32,
+
+    /// POST a summary to this webhook when the run ends (and on SLO
+    /// breaches with --slo-p99-us)
+    #[cfg(feature = "webhook")]
+    #[clap(long)]
+    webhook_url: Option<String>,
+
+    /// Payload format of --webhook-url
+    #[cfg(feature = "webhook")]
+    #[clap(long, value_enum, default_value_t = WebhookFormat::Generic)]
+    webhook_format: WebhookFormat,
+
+    /// p99 latency SLO in microseconds, reported as pass/fail and alerted
+    /// on when consecutive intervals exceed it
+    #[cfg(feature = "webhook")]
+    #[clap(long, requires = "webhook_url")]
+    slo_p99_us: Option<f64>,
+
+    /// Consecutive breaching intervals before an SLO alert is sent
+    #[cfg(feature = "webhook")]
+    #[clap(long, default_value_t = 3)]
+    slo_intervals: u32,
+
+    /// Length in seconds of the intervals checked against the SLO
+    #[cfg(feature = "webhook")]
+    #[clap(long, default_value_t = 10)]
+    slo_check_interval: u64,
+
+    /// Upload the report, recordings and --stream file to this bucket when
+    /// the run ends (s3://bucket[/prefix] or gs://bucket[/prefix])
+    #[cfg(feature = "upload")]
+    #[clap(long, global = true)]
+    upload: Option<UploadTarget>,
+
+    /// S3-compatible endpoint of --upload (e.g. http://minio:9000)
+    #[cfg(feature = "upload")]
+    #[clap(long, global = true)]
+    upload_endpoint: Option<String>,
+
+    /// Region of the --upload bucket
+    #[cfg(feature = "upload")]
+    #[clap(long, default_value = "us-east-1", global = true)]
+    upload_region: String,
+
+    /// Another file to upload with the run's (repeatable)
+    #[cfg(feature = "upload")]
+    #[clap(long, requires = "upload")]
+    upload_file: Vec<PathBuf>,
+
+    /// Seconds each upload request may take (attempts as --export-attempts)
+    #[cfg(feature = "upload")]
+    #[clap(long, default_value_t = 300, global = true)]
+    upload_timeout: u64,
+
+    /// Sign the report, recordings and --stream file with this Ed25519 key
+    /// (PKCS#8 PEM), writing <file>.sig next to each
+    #[cfg(feature = "seal")]
+    #[clap(long, global = true)]
+    sign_key: Option<PathBuf>,
+
+    /// Encrypt the report, recordings and --stream file to this age
+    /// recipient (age1...), writing <file>.age next to each (repeatable)
+    #[cfg(feature = "seal")]
+    #[clap(long, global = true)]
+    encrypt_to: Vec<String>,
+
+    /// Capture every event for --trigger-duration seconds when the p99 of a
+    /// --trigger-interval exceeds this many microseconds (run with heavy
+    /// --sample-rate otherwise)
+    #[clap(long, conflicts_with = "replay")]
+    trigger_p99_us: Option<f64>,
+
+    /// Seconds a triggered capture lasts
+    #[clap(long, default_value_t = 30, requires = "trigger_p99_us")]
+    trigger_duration: u64,
+
+    /// Length in seconds of the intervals checked against --trigger-p99-us
+    #[clap(long, default_value_t = 5, requires = "trigger_p99_us")]
+    trigger_interval: u64,
+
+    /// Also record the raw events of each triggered capture to a JSON Lines
+    /// file in this directory, for --replay
+    #[clap(long, requires = "trigger_p99_us")]
+    trigger_dump: Option<PathBuf>,
+
+    /// Serve the HTTP API (e.g. /top, the worst connections so far) on this
+    /// address, e.g. 127.0.0.1:9100
+    #[clap(long, conflicts_with = "replay")]
+    listen: Option<SocketAddr>,
+
+    /// Fail /healthz after this many seconds without events, so Kubernetes
+    /// restarts a wedged probe (default: never)
+    #[clap(long, requires = "listen")]
+    health_max_idle: Option<u64>,
+
+    /// Fail /readyz after this many exports in a row failed (0: never)
+    #[clap(long, default_value_t = DEFAULT_MAX_EXPORT_FAILURES)]
+    health_max_export_failures: u64,
+
+    /// Periodically save the collected samples to this file, so a restarted
+    /// daemon can continue the run with --resume
+    #[clap(long, conflicts_with = "replay")]
+    checkpoint: Option<PathBuf>,
+
+    /// Interval in seconds between checkpoints
+    #[clap(long, default_value_t = 60)]
+    checkpoint_interval: u64,
+
+    /// Continue the run saved in this checkpoint (a new run is started if
+    /// the file does not exist yet)
+    #[clap(long, conflicts_with = "replay")]
+    resume: Option<PathBuf>,
+
+    /// Record sampled events to an Arrow IPC stream file (e.g. events.arrows)
+    #[cfg(feature = "arrow")]
+    #[clap(long, conflicts_with = "replay")]
+    record_arrow: Option<PathBuf>,
+
+    /// Replay events from a JSON Lines recording instead of loading eBPF
+    #[clap(long, conflicts_with_all = ["interface", "ebpf_object", "ebpf_dir", "ebpf_manifest", "filter_service", "config"])]
+    replay: Option<PathBuf>,
+
+    /// How to measure TCP latency: kprobes (send-to-receive latency) or
+    /// stable tracepoints only (smoothed RTT from tcp:tcp_probe)
+    #[clap(long, value_enum, default_value_t = AttachMode::Kprobe, conflicts_with = "replay")]
+    attach_mode: AttachMode,
+
+    /// Name of this probe, to run several on one node: labels its metrics
+    /// (probe_instance), names its textfile, and separates its pinned maps
+    #[clap(long)]
+    instance: Option<InstanceId>,
+
+    /// ID of the run, on reports, exports, recordings and log lines, to
+    /// join them later (a random UUID by default; a run continued with
+    /// --resume keeps its ID)
+    #[clap(long)]
+    run_id: Option<RunId>,
+
+    /// Pin every map under DIR/<instance> (DIR/default without --instance)
+    /// on a bpffs mount, e.g. /sys/fs/bpf/latency-probe, for bpftool and
+    /// other readers; pins are removed on exit
+    #[clap(long, value_name = "DIR", conflicts_with = "replay")]
+    pin_dir: Option<PathBuf>,
+
+    /// Kernel clock of event timestamps; reports include its offset to the
+    /// wall clock, so timestamps of several nodes can be aligned (tai needs
+    /// Linux 6.1 and an eBPF object built with the tai-clock feature)
+    #[clap(long, value_enum, default_value_t = ClockSource::Monotonic, conflicts_with = "replay")]
+    clock: ClockSource,
+
+    /// Shortest latency the kernel accepts; shorter ones are dropped as
+    /// measurement errors (e.g. 200ns for loopback on fast hosts)
+    #[clap(long, conflicts_with = "replay")]
+    min_latency: Option<String>,
+
+    /// Longest latency the kernel accepts; longer ones are dropped as
+    /// measurement errors
+    #[clap(long, conflicts_with = "replay")]
+    max_latency: Option<String>,
+
+    /// Fail if this optional kprobe (e.g. tcp_cleanup_rbuf) cannot be
+    /// attached, instead of continuing without it (repeatable)
+    #[clap(long, conflicts_with = "replay")]
+    require_probe: Vec<String>,
+
+    /// Sample responses sent by sockets in this cgroup v2 directory (e.g.
+    /// /sys/fs/cgroup) to break latency down by HTTP status class
+    #[clap(long, conflicts_with = "replay")]
+    http_status_cgroup: Option<PathBuf>,
+
+    /// Assign socket cookies to TCP sockets in this cgroup v2 directory
+    /// (e.g. /sys/fs/cgroup), so connections are told apart after their
+    /// ports are reused
+    #[clap(long, conflicts_with = "replay")]
+    socket_cookie_cgroup: Option<PathBuf>,
+
+    /// Do not enable in-kernel BPF run time stats (they are collected for
+    /// every BPF program on the host while the probe runs)
+    #[clap(long, conflicts_with = "replay")]
+    no_program_stats: bool,
+
+    /// Load and verify the eBPF programs, check attach points and maps,
+    /// print a summary, and exit without attaching
+    #[clap(long, conflicts_with = "replay")]
+    dry_run: bool,
+
+    /// Switch to this user (name, uid, or uid:gid) once probes are attached
+    #[clap(long)]
+    user: Option<String>,
+
+    /// Inject a fault with tc netem for a window of the run, e.g.
+    /// "delay=5ms loss=0.1% from=120s to=180s" (repeatable; needs root)
+    #[clap(long, requires = "inject_interface", conflicts_with_all = ["replay", "user", "retain_caps"])]
+    inject: Vec<String>,
+
+    /// Interface that --inject faults are installed on
+    #[clap(long)]
+    inject_interface: Option<String>,
+
+    /// Keep only the capabilities needed to read events (CAP_BPF/CAP_PERFMON)
+    /// once probes are attached
+    #[clap(long)]
+    retain_caps: bool,
+
+    /// Run as a service: notify systemd when ready, rotate the report on
+    /// SIGHUP, and write a snapshot on SIGUSR1
+    #[clap(long, conflicts_with = "replay")]
+    daemon: bool,
+
+    /// Report only the last MINUTES of collection instead of everything
+    /// since the start (or the last rotation), in every snapshot and report
+    /// (panes are not checkpointed)
+    #[clap(
+        long,
+        value_name = "MINUTES",
+        conflicts_with_all = ["replay", "checkpoint", "resume"],
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    window: Option<u64>,
+
+    /// Write the process ID to this file while running
+    #[clap(long)]
+    pid_file: Option<PathBuf>,
+
+    /// Directory of the run registry: every finished run adds a record of
+    /// its ID, percentiles and report path, listed by `runs list`
+    #[clap(long, global = true)]
+    registry: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate loopback TCP traffic and verify the probes observe it
+    Selftest {
+        /// Number of request/response round trips to generate
+        #[clap(long, default_value_t = 100)]
+        round_trips: usize,
+
+        /// Seconds to wait for events after traffic completes
+        #[clap(long, default_value_t = 5)]
+        timeout: u64,
+    },
+    /// Benchmark scenarios of several phases, reported separately
+    Scenario {
+        #[clap(subcommand)]
+        command: ScenarioCommand,
+    },
+    /// Past runs recorded in the --registry directory
+    Runs {
+        #[clap(subcommand)]
+        command: RunsCommand,
+    },
+    /// Analyze files written by earlier runs
+    Analyze {
+        #[clap(subcommand)]
+        command: AnalyzeCommand,
+    },
+    /// Compare the JSON reports of repeats of one benchmark and print the
+    /// run-to-run variance of each percentile as JSON
+    Compare {
+        /// Reports of the repeats
+        #[clap(long, num_args = 2.., required = true)]
+        repeat: Vec<PathBuf>,
+
+        /// Coefficient of variation (in percent) above which a percentile
+        /// is too noisy to support conclusions
+        #[clap(long, default_value_t = variance::DEFAULT_MAX_CV_PCT)]
+        max_cv: f64,
+    },
+    /// Upload files to the --upload bucket next to those of a run (e.g. an
+    /// HTML report rendered afterwards; name the run with --run-id)
+    #[cfg(feature = "upload")]
+    Upload {
+        /// Files to upload
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Run the LatencyProbeRun custom resources of a namespace on this node
+    #[cfg(feature = "kubernetes")]
+    Controller {
+        /// Namespace of the resources (default: the probe's own)
+        #[clap(long)]
+        namespace: Option<String>,
+
+        /// Name of this node (default: $NODE_NAME, else the host name)
+        #[clap(long)]
+        node: Option<String>,
+
+        /// Directory the reports of the runs are written to
+        #[clap(long, default_value = "/var/lib/latency-probe/runs")]
+        work_dir: PathBuf,
+
+        /// Seconds between lists of the resources
+        #[clap(long, default_value_t = 5)]
+        poll_interval: u64,
+    },
+    /// Check the <file>.sig signatures written with --sign-key
+    #[cfg(feature = "seal")]
+    Verify {
+        /// Ed25519 public key (PKCS#8 PEM)
+        #[clap(long)]
+        public_key: PathBuf,
+
+        /// Signed files
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RunsCommand {
+    /// List the recorded runs, oldest first
+    List,
+    /// Print the records of a run as JSON
+    Show {
+        /// Run ID, or a prefix of it that only one run starts with
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AnalyzeCommand {
+    /// Match the --correlation files of client and server nodes and print
+    /// the latency of each hop as JSON
+    Stitch {
+        /// Correlation files of the nodes
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Offset tolerated between the nodes' clocks when matching, in
+        /// microseconds
+        #[clap(long, default_value_t = stitch::DEFAULT_WINDOW_US)]
+        window_us: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ScenarioCommand {
+    /// Run the phases of a scenario file in order, writing a report per
+    /// phase and a comparison with the baseline
+    Run {
+        /// Scenario file (YAML)
+        file: PathBuf,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Every option can also come from a LATENCY_PROBE_* variable
+    let command = environment::with_env(Args::command());
+    let args = Args::from_arg_matches(&command.clone().get_matches()).unwrap_or_else(|e| e.exit());
+    environment::check(&command, std::env::vars_os())?;
+
+    // A resumed run keeps the ID it was started with
+    let resumed = match &args.resume {
+        Some(path) if path.exists() => Some(checkpoint::read(path)?),
+        _ => None,
+    };
+    let run_id = args
+        .run_id
+        .clone()
+        .or_else(|| resumed.as_ref().and_then(|saved| saved.run_id.clone()))
+        .unwrap_or_else(RunId::generate);
+
+    // Initialize logging; every line names the run
+    let log_run_id = run_id.clone();
+    env_logger::Builder::from_default_env()
+        .filter_level(if args.verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
+        .format(move |buf, record| {
+            let style = buf.default_level_style(record.level());
+            writeln!(
+                buf,
+                "[{} {style}{:<5}{style:#} {} run={}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                log_run_id,
+                record.args()
+            )
+        })
+        .init();
+
+    if let Some(Command::Runs { command }) = &args.command {
+        return show_runs(args.registry.as_deref(), command);
+    }
+    if let Some(Command::Analyze {
+        command: AnalyzeCommand::Stitch { files, window_us },
+    }) = &args.command
+    {
+        let report = stitch::stitch(&stitch::read_records(files)?, *window_us);
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    if let Some(Command::Compare { repeat, max_cv }) = &args.command {
+        let reports = repeat
+            .iter()
+            .map(|path| {
+                let metrics = variance::read_report(path)?;
+                let name = metrics.run_id.clone().unwrap_or_else(|| path.display().to_string());
+                Ok((name, metrics))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let report = variance::compare(&reports, *max_cv);
+        for metric in &report.noisy {
+            warn!("{} varies too much between repeats to support conclusions", metric);
+        }
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    #[cfg(feature = "upload")]
+    if let Some(Command::Upload { files }) = &args.command {
+        let uploader = uploader(&args)?.context("upload needs --upload s3://bucket[/prefix]")?;
+        #[cfg(feature = "seal")]
+        let files = &sealer(&args)?.seal_all(files)?;
+        let instance = args.instance.as_ref().map(InstanceId::as_str);
+        return uploader.upload_all(run_id.as_str(), instance, files).await;
+    }
+    #[cfg(feature = "kubernetes")]
+    if let Some(Command::Controller { namespace, node, work_dir, poll_interval }) = &args.command {
+        let namespace = match namespace {
+            Some(namespace) => namespace.clone(),
+            None => kubernetes::pod_namespace()?,
+        };
+        let node = node
+            .clone()
+            .or_else(|| std::env::var("NODE_NAME").ok())
+            .unwrap_or_else(zabbix::hostname);
+        let controller = Controller::new(
+            KubeClient::in_cluster()?,
+            &namespace,
+            &node,
+            work_dir,
+            Duration::from_secs(*poll_interval),
+        );
+        return controller.run().await;
+    }
+    #[cfg(feature = "seal")]
+    if let Some(Command::Verify { public_key, files }) = &args.command {
+        for file in files {
+            seal::verify(public_key, file)?;
+            println!("{}: signature OK", file.display());
+        }
+        return Ok(());
+    }
+
+    // Logs go to stderr; keep stdout for the report with --output -
+    if !output::is_stdout(&args.output) {
+        print_banner();
+    }
+
+    if let Some(Command::Selftest { round_trips, timeout }) = args.command {
+        return run_selftest(args.ebpf_object, round_trips, timeout).await;
+    }
+
+    if args.dry_run {
+        return run_dry_run(&args);
+    }
+
+    let scenario = match &args.command {
+        Some(Command::Scenario {
+            command: ScenarioCommand::Run { file },
+        }) => {
+            if let Some(flag) = scenario_conflict(&args) {
+                anyhow::bail!("Scenarios cannot be combined with {}", flag);
+            }
+            Some(Scenario::load(file)?)
+        }
+        _ => None,
+    };
+
+    info!("Starting eBPF latency probe...");
+    info!("   Run ID: {}", run_id);
+    info!(
+        "   Duration: {} seconds",
+        if args.duration == 0 {
+            "infinite".to_string()
+        } else {
+            args.duration.to_string()
+        }
+    );
+    info!("   Output: {:?}", args.output);
+    info!("   Format: {}", args.format);
+    if args.replay.is_none() {
+        info!("   Attach mode: {}", args.attach_mode);
+    }
+
+    // Runtime config (sampling and filters) from file or flags
+    let config = match args.config {
+        Some(ref path) => ProbeConfig::load(path)?,
+        None => ProbeConfig {
+            sample_rate: args.sample_rate.rate,
+            sample_mode: args.sample_mode,
+            event_sample_rates: args.sample_rate.events.clone(),
+            filter_services: args.filter_service.clone(),
+        },
+    };
+    config.validate()?;
+
+    info!("   Sample rate: {}", config.describe_sample_rates());
+    if !config.filter_services.is_empty() {
+        info!("   Services: {}", config.filter_services.join(", "));
+    }
+    if !args.interface.is_empty() {
+        info!("   XDP interfaces: {}", args.interface.join(", "));
+    }
+    if !args.netns.is_empty() {
+        info!("   Network namespaces: {:?}", args.netns);
+    }
+
+    // Parse export format
+    let export_format = match args.format.to_lowercase().as_str() {
+        "json" => ExporterType::Json,
+        "prometheus" | "prom" => ExporterType::Prometheus,
+        "openmetrics" | "om" => ExporterType::OpenMetrics,
+        "influx" | "influxdb" => ExporterType::Influx,
+        "heatmap" => ExporterType::Heatmap,
+        "table" => ExporterType::Table,
+        "markdown" | "md" => ExporterType::Markdown,
+        #[cfg(feature = "arrow")]
+        "arrow" => ExporterType::Arrow,
+        #[cfg(feature = "proto")]
+        "protobuf" | "proto" => ExporterType::Protobuf,
+        _ => anyhow::bail!(
+            "Unsupported format: {}. Use json, prometheus, openmetrics, influx, heatmap, table, or markdown",
+            args.format
+        ),
+    };
+
+    if args.compress != Compression::None && !matches!(export_format, ExporterType::Json) {
+        warn!("--compress only applies to JSON reports; {} is written uncompressed", args.format);
+    }
+
+    let mut report = ReportWriter {
+        format: export_format,
+        output: args.output.clone(),
+        compression: args.compress,
+        canonical_json: args.canonical_json,
+        sample_timestamps: !args.no_sample_timestamps,
+        top_connections: args.top_connections,
+        stream: None,
+        exports: None,
+        #[cfg(feature = "webhook")]
+        notifier: None,
+    };
+    let mut publisher = Publisher::new();
+    // Fail before collecting if the uploads could not be made
+    #[cfg(feature = "upload")]
+    let uploader = uploader(&args)?;
+    #[cfg(feature = "seal")]
+    let sealer = sealer(&args)?;
+
+    let policy = RetryPolicy {
+        attempts: args.export_attempts,
+        timeout: Duration::from_secs(args.export_timeout),
+        ..Default::default()
+    };
+
+    if let Some(server) = &args.zabbix_server {
+        let host = args.zabbix_host.clone().unwrap_or_else(zabbix::hostname);
+        info!("   Sending to Zabbix: {} (host {})", server, host);
+        publisher.add("Zabbix", Blocking::new(ZabbixSender::new(server, host)), policy);
+    }
+
+    if let Some(dir) = &args.textfile_dir {
+        let textfile = TextfileWriter::new(dir, args.instance.as_ref())?;
+        info!("   Writing node_exporter textfile: {:?}", textfile.path());
+        publisher.add("textfile", Blocking::new(textfile), policy);
+    }
+
+    #[cfg(feature = "webhook")]
+    if let Some(url) = &args.webhook_url {
+        let slo = args.slo_p99_us.map(|p99_threshold_us| SloPolicy {
+            p99_threshold_us,
+            consecutive_intervals: args.slo_intervals.max(1),
+        });
+        report.notifier = Some(Arc::new(Notifier::new(url.clone(), args.webhook_format, slo)?));
+    }
+
+    #[cfg(feature = "nats")]
+    if let Some(url) = &args.nats_url {
+        info!("   Publishing to NATS: {} ({})", url, args.nats_subject);
+        let subjects = NatsSubjects {
+            snapshot: args.nats_snapshot_subject.clone(),
+            report: args.nats_subject.clone(),
+        };
+        publisher.add("NATS", NatsPublisher::connect(url, subjects).await?, policy);
+    }
+
+    // One probe of the cluster aggregates the reports of all
+    #[cfg(feature = "kubernetes")]
+    let aggregate = match &args.cluster_lease {
+        Some(lease) => {
+            let identity = cluster_identity(&args)?;
+            let namespace = match &args.cluster_namespace {
+                Some(namespace) => namespace.clone(),
+                None => kubernetes::pod_namespace()?,
+            };
+            info!("   Cluster aggregation: lease {}/{} as {}", namespace, lease, identity);
+            let aggregate = Arc::new(Aggregate::new());
+            let duration = Duration::from_secs(args.cluster_lease_duration);
+            let election = LeaderElection::new(KubeClient::in_cluster()?, &namespace, lease, &identity, duration);
+            let leader = election.spawn(Arc::clone(&aggregate));
+            let exporter = ClusterExporter::new(&identity, leader, Arc::clone(&aggregate), args.cluster_output.clone())?;
+            publisher.add("cluster aggregator", exporter, policy);
+            Some(aggregate)
+        }
+        None => None,
+    };
+
+    // Network exports run on their own thread, away from the event readers
+    if !publisher.is_empty() {
+        report.exports = Some(ExportQueue::spawn(publisher, args.export_queue as usize)?);
+    }
+
+    // Create metrics collector. Recorded PIDs belong to the recording
+    // host, so only live runs resolve them to process names.
+    let mut collector = MetricsCollector::new();
+    collector.set_service_classifier(service_classifier(&args)?);
+    if let Some(path) = &args.zone_map {
+        collector.set_zone_map(ZoneMap::load_file(path)?);
+    }
+    let mut tenant_namespaces = None;
+    if let Some(path) = &args.tenants {
+        let tenants = TenantSet::load_file(path)?;
+        info!("   Tenants: {}", tenants.len());
+        tenant_namespaces = tenants.namespaces();
+        collector.set_tenants(tenants);
+    }
+    collector.set_rate_resolution(args.rate_resolution_ms);
+    collector.set_connection_limit(args.max_connections);
+    collector.set_process_limit(args.max_processes);
+    collector.set_port_normalization(args.normalize_ports);
+    collector.set_burst_factor(args.burst_factor);
+    collector.set_dedup_policy(args.dedup);
+    if let Some(minutes) = args.window {
+        info!("   Rolling window: {} minutes", minutes);
+        collector.set_rolling_window(Duration::from_secs(minutes * 60));
+    }
+    if let Some(instance) = &args.instance {
+        collector.set_instance(instance.clone());
+    }
+    collector.set_run_id(run_id.clone());
+    if args.tail_analysis {
+        collector.enable_tail_analysis();
+    }
+    if let Some(interval) = &args.expected_interval {
+        let interval = omission::parse_interval(interval)?;
+        info!("   Coordinated omission correction: expected interval {:?}", interval);
+        collector.set_expected_interval(interval);
+    }
+    if args.replay.is_none() && args.process_ttl > 0 {
+        collector.set_process_cache(ProcessCache::new(Duration::from_secs(args.process_ttl)));
+        collector.set_pod_cache(PodCache::new(Duration::from_secs(args.process_ttl)));
+    }
+    if args.replay.is_none() && args.conntrack {
+        match ConntrackCache::open(Duration::from_secs(args.process_ttl.max(1))) {
+            Ok(conntrack) => collector.set_conntrack(conntrack),
+            Err(e) => warn!("Destinations behind NAT not resolved: {:#}", e),
+        }
+    }
+    let clock_sync = if args.replay.is_none() { timesync::query() } else { None };
+    if let Some(sync) = &clock_sync {
+        info!(
+            "   Clock: {} reports an offset of {}ns (uncertainty {}ns)",
+            sync.daemon, sync.offset_ns, sync.uncertainty_ns
+        );
+    }
+    if args.replay.is_none() {
+        collector.set_clock_source(args.clock);
+        collector.set_host(host::current());
+    }
+    if let Some(sync) = &clock_sync {
+        collector.set_clock_sync(sync.clone());
+    }
+    if let Some(path) = &args.resume {
+        match resumed {
+            Some(saved) => {
+                info!(
+                    "   Resuming run from {:?} ({} seconds, written {})",
+                    path, saved.elapsed_secs, saved.written_at
+                );
+                collector.resume(saved.collector, saved.elapsed_secs);
+            }
+            None => info!("   No checkpoint at {:?}, starting a new run", path),
+        }
+    }
+    let counters = collector.live_counters();
+    let collector = Arc::new(Mutex::new(collector));
+    let health = Arc::new(Health::new(
+        HealthPolicy {
+            max_idle: args.health_max_idle.map(Duration::from_secs),
+            max_export_failures: args.health_max_export_failures,
+        },
+        Arc::clone(&counters),
+        report.exports.as_ref().map(ExportQueue::monitor),
+    ));
+    if let Some(addr) = args.listen {
+        let server = ApiServer::bind(addr).await?;
+        info!("   Serving the HTTP API on {}", server.local_addr()?);
+        let state = ApiState::new(Arc::clone(&collector), counters).with_health(Arc::clone(&health));
+        #[cfg(feature = "kubernetes")]
+        let state = match &aggregate {
+            Some(aggregate) => state.with_aggregate(Arc::clone(aggregate)),
+            None => state,
+        };
+        server.spawn(state);
+    }
+
+    // Create event processor. Live runs sample in the kernel, so userspace
+    // sampling only applies to replays.
+    let userspace_sample_rate = if args.replay.is_some() {
+        config.sample_rate
+    } else {
+        1
+    };
+    let mut processor = EventProcessor::new(Arc::clone(&collector), userspace_sample_rate, args.verbose);
+    if args.replay.is_some() {
+        processor.set_event_sample_rates(config.event_sample_rates_by_type()?);
+        processor.set_sample_mode(config.sample_mode);
+    }
+    // Without --netns, only the namespaces of the tenants are of interest
+    // (if every tenant lists its own)
+    if args.netns.is_empty() {
+        processor.set_netns_filter(tenant_namespaces.unwrap_or_default());
+    } else {
+        processor.set_netns_filter(args.netns.clone());
+    }
+    processor.set_loopback_filter(if args.exclude_loopback {
+        LoopbackFilter::Exclude
+    } else if args.only_loopback {
+        LoopbackFilter::Only
+    } else {
+        LoopbackFilter::All
+    });
+    processor.set_exclude_half_open(args.exclude_half_open);
+    processor.set_reader_placement(match args.reader_threads {
+        Some(threads) => ReaderPlacement::Dedicated(threads),
+        None if args.pin_readers => ReaderPlacement::Pinned,
+        None => ReaderPlacement::Shared,
+    })?;
+    processor.set_perf_buffer_options(PerfBufferOptions {
+        pages: args.perf_pages.or(args.max_events.map(PerfBufferOptions::pages_for_events)),
+        read_buffers: args.read_buffers,
+        wakeup_events: args.wakeup_events,
+    })?;
+    processor.set_backpressure(args.channel_capacity, args.overflow_policy);
+
+    // Record sampled events if requested
+    let recorder = match args.record {
+        Some(ref path) => {
+            info!("   Recording events to: {:?}", path);
+            let recorder = Arc::new(EventRecorder::create_with(path, args.compress)?.with_run_id(run_id.clone()));
+            let sink = Arc::clone(&recorder);
+            processor.register_callback(move |event| {
+                if let Err(e) = sink.record(event) {
+                    warn!("Failed to record event: {}", e);
+                }
+            });
+            Some(recorder)
+        }
+        None => None,
+    };
+    #[cfg(feature = "arrow")]
+    let arrow_recorder = match args.record_arrow {
+        Some(ref path) => {
+            info!("   Recording events to: {:?} (Arrow)", path);
+            let recorder = Arc::new(ArrowRecorder::create(path, Some(&run_id))?);
+            let sink = Arc::clone(&recorder);
+            processor.register_callback(move |event| {
+                if let Err(e) = sink.record(event) {
+                    warn!("Failed to record event: {}", e);
+                }
+            });
+            Some(recorder)
+        }
+        None => None,
+    };
+
+    // Write correlation records for cross-node stitching
+    let correlation = match args.correlation {
+        Some(ref path) => {
+            let node = args
+                .node_id
+                .clone()
+                .or_else(|| std::env::var("NODE_NAME").ok())
+                .unwrap_or_else(zabbix::hostname);
+            info!("   Writing correlation records to: {:?} (node {})", path, node);
+            let clock = ClockInfo {
+                sync: clock_sync.clone(),
+                ..args.clock.capture()
+            };
+            let writer =
+                Arc::new(CorrelationWriter::create(path, args.compress, &node, clock)?.with_run_id(run_id.clone()));
+            let sink = Arc::clone(&writer);
+            processor.register_callback(move |event| {
+                if let Err(e) = sink.record(event) {
+                    warn!("Failed to write correlation record: {}", e);
+                }
+            });
+            Some(writer)
+        }
+        None => None,
+    };
+
+    // Record the raw events of triggered captures
+    let dump = match args.trigger_dump {
+        Some(ref dir) => {
+            info!("   Recording triggered captures to: {:?}", dir);
+            let dump = CaptureDump::new(dir)?.with_run_id(run_id.clone());
+            let sink = dump.clone();
+            processor.register_callback(move |event| sink.record(event));
+            Some(dump)
+        }
+        None => None,
+    };
+
+    // Stream snapshots (and events) while collecting if requested
+    if let Some(ref path) = args.stream {
+        info!("   Streaming snapshots to: {:?}", path);
+        let stream = Arc::new(JsonLinesWriter::create(path, args.compress)?.with_run_id(run_id.clone()));
+        if args.stream_events {
+            let sink = Arc::clone(&stream);
+            processor.register_callback(move |event| {
+                if let Err(e) = sink.write_event(event) {
+                    warn!("Failed to stream event: {}", e);
+                }
+            });
+        }
+        report.stream = Some(stream);
+    }
+
+    #[cfg(feature = "envoy")]
+    let envoy = match args.envoy_admin.as_slice() {
+        [] => None,
+        admins => {
+            info!("   Envoy proxies: {}", admins.join(", "));
+            let mut scraper = EnvoyScraper::new(admins, &args.envoy_histograms)?;
+            scraper.start().await;
+            Some(scraper)
+        }
+    };
+    let live = match args.replay {
+        Some(ref path) => {
+            info!("Replaying events from {:?}", path);
+            let summary = processor.replay(path).await?;
+            info!("Replayed {} events", summary.events);
+            Some((summary.duration_seconds(), XdpPacketStats::default(), Vec::new(), None))
+        }
+        None => {
+            let _pid_file = args.pid_file.as_deref().map(PidFile::create).transpose()?;
+            match &scenario {
+                Some(scenario) => {
+                    let summary = run_scenario(&args, scenario, config, &processor, &collector, &report, &health).await?;
+                    print_scenario_summary(&summary);
+                    None
+                }
+                None => Some(collect_live(&args, config, &processor, &collector, &report, &health, dump.as_ref()).await?),
+            }
+        }
+    };
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+    if let Some(correlation) = correlation {
+        correlation.finish()?;
+    }
+    #[cfg(feature = "arrow")]
+    if let Some(recorder) = arrow_recorder {
+        recorder.finish()?;
+    }
+
+    // Scenario phases have written their own reports
+    let Some((elapsed, xdp_stats, program_stats, latency_bounds)) = live else {
+        return Ok(());
+    };
+
+    info!("Generating metrics report...");
+
+    // Generate final metrics
+    let collector = collector.lock().await;
+    let mut metrics = collector.generate_metrics(elapsed);
+    metrics.xdp_stats = xdp_stats;
+    metrics.program_stats = program_stats;
+    metrics.latency_bounds = latency_bounds;
+    if let Some(path) = &args.client_results {
+        let client = ClientResults::load(path)?;
+        if let Some(duration) = client.duration_seconds {
+            let probe = metrics.duration_seconds as f64;
+            if (duration - probe).abs() > probe.max(duration) * 0.1 {
+                warn!(
+                    "⚠ {} ran for {:.0}s but the probe collected for {:.0}s; latencies may not be comparable",
+                    client.tool, duration, probe
+                );
+            }
+        }
+        metrics.client = Some(client.compare(&metrics));
+    }
+    #[cfg(feature = "envoy")]
+    if let Some(envoy) = &envoy {
+        metrics.envoy = envoy.finish(&metrics.percentiles).await;
+    }
+    #[cfg(feature = "istio")]
+    if let Some(prometheus) = &args.istio_prometheus {
+        let telemetry = IstioTelemetry::new(prometheus, &args.istio_selector)?;
+        match telemetry.compare(metrics.duration_seconds, &metrics.percentiles).await {
+            Ok(mesh) => metrics.mesh = Some(mesh),
+            Err(e) => warn!("⚠ Istio latency not compared: {:#}", e),
+        }
+    }
+
+    // Export metrics based on format
+    // Every destination gets the final report, even if an earlier one failed
+    let written = report.write(&metrics, &args.output);
+    if let Err(e) = &written {
+        warn!("{:#}", e);
+    }
+    report.finish(&metrics).await?;
+    written?;
+
+    info!("Metrics written to {:?}", args.output);
+
+    if let Some(dir) = &args.registry {
+        let report_path = (!output::is_stdout(&args.output)).then_some(args.output.as_path());
+        match RunRecord::from_metrics(&metrics, report_path).and_then(|record| RunRegistry::new(dir).add(&record)) {
+            Ok(path) => info!("Run recorded in {:?}", path),
+            Err(e) => warn!("Failed to record the run: {:#}", e),
+        }
+    }
+
+    // Sign and encrypt before anything leaves the node
+    #[cfg(feature = "seal")]
+    sealer.seal_all(&run_artifacts(&args))?;
+
+    // The node may not outlive the run
+    #[cfg(feature = "upload")]
+    if let Some(uploader) = &uploader {
+        let files = run_artifacts(&args);
+        #[cfg(feature = "seal")]
+        let files: Vec<PathBuf> = files.iter().flat_map(|path| sealer.published(path)).collect();
+        let instance = args.instance.as_ref().map(InstanceId::as_str);
+        uploader.upload_all(run_id.as_str(), instance, &files).await?;
+    }
+
+    // Print summary
+    print_summary(&metrics);
+
+    Ok(())
+}
+
+/// Built-in service names plus those from --service-map and --port-service
+fn service_classifier(args: &Args) -> Result<ServiceClassifier> {
+    let mut classifier = ServiceClassifier::new();
+    if let Some(ref path) = args.service_map {
+        classifier.load_file(path)?;
+    }
+    for spec in &args.port_service {
+        let (port, name) = services::parse_port_service(spec)?;
+        classifier.insert(port, &name);
+    }
+    Ok(classifier)
+}
+
+/// Output format and default destination for metrics reports, and where
+/// snapshots go during collection
+struct ReportWriter {
+    format: ExporterType,
+    output: PathBuf,
+    compression: Compression,
+    canonical_json: bool,
+    sample_timestamps: bool,
+    top_connections: usize,
+    stream: Option<Arc<JsonLinesWriter>>,
+    exports: Option<ExportQueue>,
+    #[cfg(feature = "webhook")]
+    notifier: Option<Arc<Notifier>>,
+}
+
+impl ReportWriter {
+    /// Export metrics to `path` in the configured format
+    fn write(&self, metrics: &LatencyMetrics, path: &Path) -> Result<()> {
+        let path = path.to_path_buf();
+        match self.format {
+            ExporterType::Json => JsonExporter::new(path, true)
+                .with_compression(self.compression)
+                .with_canonical(self.canonical_json)
+                .export(metrics),
+            ExporterType::Prometheus => PrometheusExporter::new(path).export(metrics),
+            ExporterType::OpenMetrics => {
+                OpenMetricsExporter::new(path).with_timestamps(self.sample_timestamps).export(metrics)
+            }
+            ExporterType::Influx => {
+                InfluxExporter::new(path, "latency_probe".to_string()).export(metrics)
+            }
+            ExporterType::Heatmap => HeatmapExporter::new(path).export(metrics),
+            ExporterType::Table => {
+                SummaryExporter::new(path, SummaryStyle::Table, self.top_connections).export(metrics)
+            }
+            ExporterType::Markdown => {
+                SummaryExporter::new(path, SummaryStyle::Markdown, self.top_connections).export(metrics)
+            }
+            #[cfg(feature = "arrow")]
+            ExporterType::Arrow => ArrowExporter::new(path).export(metrics),
+            #[cfg(feature = "proto")]
+            ExporterType::Protobuf => ProtobufExporter::new(path).export(metrics),
+        }
+    }
+
+    /// Whether snapshots are taken during collection (--stream, Zabbix,
+    /// --textfile-dir, or NATS)
+    fn takes_snapshots(&self) -> bool {
+        self.stream.is_some() || self.exports.is_some()
+    }
+
+    /// Append an interval snapshot to the stream, and queue it for Zabbix,
+    /// NATS and the textfile
+    ///
+    /// Failures are logged; collection continues.
+    fn snapshot(&self, metrics: &LatencyMetrics) {
+        if let Some(Err(e)) = self.stream.as_ref().map(|s| s.write_snapshot(metrics)) {
+            warn!("Failed to stream snapshot: {:#}", e);
+        }
+        if let Some(exports) = &self.exports {
+            exports.snapshot(metrics.clone());
+        }
+    }
+
+    /// Send a finished (rotated or final) report to Zabbix and NATS, and
+    /// write it to the textfile
+    ///
+    /// Every destination is tried, with the --export-timeout and
+    /// --export-attempts policy, before a failure is returned.
+    async fn publish(&self, metrics: &LatencyMetrics) -> Result<()> {
+        match &self.exports {
+            Some(exports) => exports.publish(metrics.clone()).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Queue depth and latency of the exporter thread, if there is one
+    fn export_stats(&self) -> Option<ExportStats> {
+        self.exports.as_ref().map(ExportQueue::stats)
+    }
+
+    /// Publish the final report, end the stream with it, and notify the
+    /// webhook (failures to notify are only logged)
+    ///
+    /// A failing stream does not keep the report from the other
+    /// destinations.
+    async fn finish(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let streamed = match &self.stream {
+            Some(stream) => stream.write_snapshot(metrics).and_then(|_| stream.finish()),
+            None => Ok(()),
+        };
+        if let Err(e) = &streamed {
+            warn!("{:#}", e);
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(notifier) = &self.notifier {
+            let notification = Notification::run_complete(metrics, notifier.slo());
+            if let Err(e) = notifier.send(&notification).await {
+                warn!("{:#}", e);
+            }
+        }
+        let published = self.publish(metrics).await;
+        streamed.and(published)
+    }
+}
+
+/// Load and attach the eBPF program, then collect events until the
+/// configured duration elapses or the probe is interrupted
+///
+/// In daemon mode, SIGHUP and SIGUSR1 write intermediate reports while
+/// collection continues. With `--config`, the config file is reloaded when
+/// it changes or on SIGHUP. With `--stream`, Zabbix, `--textfile-dir`, or
+/// NATS, a snapshot is taken every `--stream-interval` seconds. With
+/// `--checkpoint`, the collector is saved every `--checkpoint-interval`
+/// seconds and at shutdown. With `--trigger-p99-us`, every event is
+/// captured (and recorded to `dump`) for a while after a slow interval.
+///
+/// Returns the elapsed collection time in seconds (since the last rotation),
+/// the XDP statistics read from the STATS map, the in-kernel run time of
+/// each attached program, and the latency bounds with their rejections.
+async fn collect_live(
+    args: &Args,
+    config: ProbeConfig,
+    processor: &EventProcessor,
+    collector: &Arc<Mutex<MetricsCollector>>,
+    report: &ReportWriter,
+    health: &Health,
+    dump: Option<&CaptureDump>,
+) -> Result<(u64, XdpPacketStats, Vec<ProgramStats>, Option<LatencyBounds>)> {
+    let faults = args.inject.iter().map(|spec| FaultSpec::parse(spec)).collect::<Result<_>>()?;
+    let faults = faults::schedule(faults)?;
+
+    let mut loader = attach_probe(args, collector).await?;
+
+    // Track the pods of a Kubernetes workload and the tenants on top of the
+    // configured services, the pods updated by a poller as they are
+    // rescheduled
+    let mut config = config;
+    let tenant_services = tenant_services(collector).await;
+    let mut pod_services: Vec<String> = tenant_services.clone();
+    let mut pod_updates: Option<mpsc::Receiver<Vec<String>>> = None;
+    #[cfg(feature = "kubernetes")]
+    if let Some(namespace) = &args.k8s_namespace {
+        let selector = PodSelector {
+            namespace: namespace.clone(),
+            labels: args.k8s_selector.clone(),
+        };
+        let client = KubeClient::in_cluster()?;
+        let ips = client.list_pod_ips(&selector).await?;
+        if ips.is_empty() {
+            anyhow::bail!("No running pods match {}", selector);
+        }
+        info!("   Pods of {}: {} IP(s)", selector, ips.len());
+        pod_services = [tenant_services.clone(), kubernetes::pod_services(&ips)].concat();
+        pod_updates = Some(kubernetes::spawn_pod_watcher(client, selector, ips, args.k8s_poll_interval));
+    }
+
+    // Configure sampling and filters before events start flowing
+    loader.apply_config(&config.with_services(&pod_services))?;
+    if report.takes_snapshots() {
+        collector.lock().await.enable_trajectory();
+    }
+
+    info!("Collecting metrics...");
+    start_readers(args, &mut loader, processor).await?;
+    finish_startup(args, processor, collector, report, health)?;
+
+    if args.daemon {
+        daemon::notify(&format!("READY=1\nMAINPID={}", std::process::id()))?;
+    }
+
+    // Run for specified duration or until interrupted
+    let start_time = Instant::now();
+    let deadline = (args.duration > 0).then(|| start_time + Duration::from_secs(args.duration));
+    let mut signals = if args.daemon || args.config.is_some() {
+        Some(DaemonSignals::install()?)
+    } else {
+        None
+    };
+    let mut interval_start = start_time;
+    let stats_period = Duration::from_secs(args.progress_interval);
+    let mut stats_ticker = interval_at(start_time + stats_period, stats_period);
+    let health_period = Duration::from_secs(args.health_interval);
+    let mut health_ticker = interval_at(start_time + health_period, health_period);
+    let stream_period = Duration::from_secs(args.stream_interval);
+    let mut stream_ticker = interval_at(start_time + stream_period, stream_period);
+    let checkpoint_period = Duration::from_secs(args.checkpoint_interval);
+    let mut checkpoint_ticker = interval_at(start_time + checkpoint_period, checkpoint_period);
+    let window_period = Duration::from_secs(args.window.unwrap_or(1) * 60) / WINDOW_PANES;
+    let mut window_ticker = interval_at(start_time + window_period, window_period);
+    let trigger_period = Duration::from_secs(args.trigger_interval.max(1));
+    let mut trigger_ticker = interval_at(start_time + trigger_period, trigger_period);
+    let mut trigger = match args.trigger_p99_us {
+        Some(p99_threshold_us) => {
+            let policy = TriggerPolicy {
+                p99_threshold_us,
+                duration: Duration::from_secs(args.trigger_duration),
+            };
+            info!("   Capturing every event for {:?} when p99 exceeds {}us", policy.duration, p99_threshold_us);
+            Some(CaptureTrigger::new(policy, &*collector.lock().await))
+        }
+        None => None,
+    };
+    let mut watcher = args.config.as_deref().map(ConfigWatcher::new);
+    let injector = args.inject_interface.as_ref().filter(|_| !faults.is_empty()).map(|interface| {
+        info!("   Injecting {} fault(s) on {}", faults.len(), interface);
+        FaultInjector::spawn(interface.clone(), faults, start_time, Arc::clone(collector))
+    });
+
+    loop {
+        tokio::select! {
+            _ = wait_until(deadline) => {
+                info!("Duration reached, shutting down...");
+                break;
+            }
+            _ = signal::ctrl_c(), if signals.is_none() => {
+                info!("Interrupted, shutting down...");
+                break;
+            }
+            _ = stats_ticker.tick(), if loader.program_stats_enabled() => {
+                log_program_stats(&loader.read_program_stats());
+            }
+            _ = health_ticker.tick() => {
+                check_map_health(&loader, collector).await;
+                read_connection_counters(&mut loader, collector).await;
+                collector.lock().await.set_export_stats(report.export_stats());
+            }
+            _ = stream_ticker.tick(), if report.takes_snapshots() => {
+                let mut collector = collector.lock().await;
+                collector.close_interval(interval_start.elapsed().as_secs());
+                let metrics = snapshot(&collector, &mut loader, interval_start, start_time);
+                report.snapshot(&metrics);
+            }
+            _ = window_ticker.tick(), if args.window.is_some() => {
+                collector.lock().await.roll_window(interval_start.elapsed().as_secs());
+                interval_start = Instant::now();
+            }
+            _ = trigger_ticker.tick(), if trigger.is_some() => {
+                let Some(trigger) = trigger.as_mut() else { continue };
+                let action = trigger.observe(&*collector.lock().await, Instant::now().into_std());
+                capture(action, &config, &pod_services, &mut loader, collector, dump).await;
+            }
+            _ = checkpoint_ticker.tick(), if args.checkpoint.is_some() => {
+                save_checkpoint(&args.checkpoint, collector, interval_start).await;
+            }
+            _ = config_changed(&mut watcher) => {
+                reload_config(&args.config, &mut config, &pod_services, &mut loader, collector).await;
+            }
+            Some(services) = next_pod_update(&mut pod_updates) => {
+                pod_services = [tenant_services.clone(), services].concat();
+                apply_config(&config, &pod_services, &mut loader, collector).await;
+            }
+            received = next_signal(&mut signals) => match received {
+                DaemonSignal::Rotate if !args.daemon => {
+                    reload_config(&args.config, &mut config, &pod_services, &mut loader, collector).await;
+                }
+                DaemonSignal::Rotate => {
+                    daemon::notify("RELOADING=1")?;
+                    reload_config(&args.config, &mut config, &pod_services, &mut loader, collector).await;
+                    read_connection_counters(&mut loader, collector).await;
+                    let path = daemon::rotated_path(&report.output, Local::now());
+                    let finished = collector.lock().await.rotate();
+                    let metrics = snapshot(&finished, &mut loader, interval_start, start_time);
+                    interval_start = Instant::now();
+                    report.write(&metrics, &path)?;
+                    if let Err(e) = report.publish(&metrics).await {
+                        warn!("Failed to publish rotated report: {:#}", e);
+                    }
+                    info!("Report rotated to {:?}", path);
+                    daemon::notify("READY=1")?;
+                }
+                DaemonSignal::Snapshot => {
+                    let metrics = snapshot(
+                        &*collector.lock().await,
+                        &mut loader,
+                        interval_start,
+                        start_time,
+                    );
+                    report.write(&metrics, &report.output)?;
+                    info!("Snapshot written to {:?}", report.output);
+                }
+                DaemonSignal::Swap => swap_object(args, &mut loader, processor).await,
+                DaemonSignal::Shutdown => {
+                    info!("Received shutdown signal, shutting down...");
+                    break;
+                }
+            }
+        }
+    }
+
+    if args.daemon {
+        daemon::notify("STOPPING=1")?;
+    }
+    if let Some(injector) = injector {
+        injector.stop(collector).await;
+    }
+    if let Some(Err(e)) = dump.map(CaptureDump::stop) {
+        warn!("Failed to finish the capture recording: {:#}", e);
+    }
+
+    let elapsed = interval_start.elapsed().as_secs();
+
+    // Final occupancy and counter readings for the report
+    processor.flush().await;
+    check_map_health(&loader, collector).await;
+    read_connection_counters(&mut loader, collector).await;
+    collector.lock().await.set_export_stats(report.export_stats());
+
+    // Let a restart continue from where this run stopped
+    save_checkpoint(&args.checkpoint, collector, interval_start).await;
+
+    // Read XDP stats from BPF STATS map before generating metrics. The
+    // kernel counters are never reset, so rates use the full run time.
+    let xdp_stats = loader.read_xdp_stats(start_time.elapsed().as_secs());
+
+    Ok((elapsed, xdp_stats, loader.read_program_stats(), Some(loader.read_latency_bounds())))
+}
+
+/// Load the eBPF program(s) and attach them as configured, leaving
+/// sampling and filters to the caller
+async fn attach_probe(args: &Args, collector: &Arc<Mutex<MetricsCollector>>) -> Result<ProbeLoader> {
+    // The embedded object reads CLOCK_TAI only if built for it
+    let embedded = args.ebpf_object.is_none() && args.ebpf_dir.is_none() && args.ebpf_manifest.is_none();
+    if args.clock == ClockSource::Tai && embedded && !cfg!(feature = "tai-clock") {
+        anyhow::bail!("--clock tai needs the embedded eBPF object built with the tai-clock feature");
+    }
+
+    // Load eBPF program(s)
+    let mut loader = load_probe(args)?;
+    if let Some(dir) = &args.pin_dir {
+        info!("   Pinned maps in {:?}", loader.pin_maps(dir)?);
+    }
+    loader.set_clock_source(args.clock)?;
+    if args.min_latency.is_some() || args.max_latency.is_some() {
+        let bound = |spec: &Option<String>, default_ns: u64| match spec {
+            Some(spec) => omission::parse_interval(spec),
+            None => Ok(Duration::from_nanos(default_ns)),
+        };
+        loader.set_latency_bounds(
+            bound(&args.min_latency, probe_common::constants::MIN_LATENCY_NS)?,
+            bound(&args.max_latency, probe_common::constants::MAX_LATENCY_NS)?,
+        )?;
+    }
+
+    // Measure in-kernel overhead from the first attached program on
+    if !args.no_program_stats {
+        if let Err(e) = loader.enable_program_stats() {
+            warn!("  ⚠ eBPF program run time not available: {:#}", e);
+        }
+    }
+
+    // Initialize eBPF logger (optional)
+    loader.init_logger();
+
+    // Filter, sample and emit events in tail-called stages
+    if let Err(e) = loader.load_pipeline() {
+        warn!("  ⚠ Event pipeline not loaded, handlers emit events inline: {:#}", e);
+    }
+
+    match args.attach_mode {
+        // Attach kprobes, falling back to alternate symbols where needed
+        AttachMode::Kprobe => loader.attach_kprobes(&args.require_probe)?,
+        // Tracepoint mode reads tcp_probe fields at kernel-specific offsets
+        AttachMode::Tracepoint => loader.set_tcp_probe_offsets(&TcpProbeOffsets::from_tracefs()?)?,
+    }
+
+    // Attach tracepoints (kfree_skb + sched_switch, and TCP tracepoints
+    // in tracepoint mode)
+    loader.attach_tracepoints(args.attach_mode)?;
+
+    // Attach programs from additional objects
+    if loader.objects().count() > 1 {
+        loader.attach_objects()?;
+    }
+
+    // Attach XDP if interfaces specified
+    if !args.interface.is_empty() {
+        use aya::programs::XdpFlags;
+        loader.attach_xdp(&args.interface, XdpFlags::default())?;
+    }
+
+    // Classify HTTP responses if a cgroup to sample is given
+    if let Some(cgroup) = &args.http_status_cgroup {
+        loader.attach_http_status(cgroup)?;
+    }
+    if let Some(cgroup) = &args.socket_cookie_cgroup {
+        loader.attach_socket_cookies(cgroup)?;
+    }
+
+    // Record which probes are active in the report metadata
+    collector.lock().await.set_probes(loader.probes().to_vec());
+
+    // Kernel type information for the offsets below, parsed once; each
+    // feature that needs a member the kernel lacks falls back on its own
+    let btf = Btf::from_sys_fs();
+    if let Err(e) = &btf {
+        warn!("  ⚠ Kernel BTF unavailable: {:#}", e);
+    }
+    let kernel_btf = || btf.as_ref().map_err(|_| anyhow::anyhow!("no kernel BTF"));
+
+    // Tag events with the socket's network namespace when the kernel
+    // layout is known; otherwise userspace uses the triggering process's
+    let mut btf_offsets = false;
+    match kernel_btf().and_then(NetnsOffsets::from_btf) {
+        Ok(offsets) => {
+            loader.set_netns_offsets(&offsets)?;
+            btf_offsets = true;
+        }
+        Err(e) => warn!("  ⚠ Socket namespaces unavailable, using process network namespaces: {:#}", e),
+    }
+
+    // Identify connections by socket cookie when the kernel layout is
+    // known; otherwise by 4-tuple
+    match kernel_btf().and_then(|btf| btf.member_offset("sock_common", "skc_cookie")) {
+        Ok(offset) => {
+            loader.set_cookie_offset(offset)?;
+            btf_offsets = true;
+        }
+        Err(e) => warn!("  ⚠ Socket cookies unavailable, identifying connections by 4-tuple: {:#}", e),
+    }
+
+    // Count accept queue overflows when the listener layout is known
+    if args.attach_mode == AttachMode::Kprobe {
+        match kernel_btf().and_then(ListenQueueOffsets::from_btf) {
+            Ok(offsets) => {
+                loader.set_listen_queue_offsets(&offsets)?;
+                btf_offsets = true;
+            }
+            Err(e) => warn!("  ⚠ Accept queue overflows not tracked: {:#}", e),
+        }
+    }
+
+    // Read socket addresses at the running kernel's offsets
+    match kernel_btf().and_then(host::sock_common_layout) {
+        Ok(layout) => {
+            loader.set_sock_common_layout(layout)?;
+            btf_offsets = true;
+        }
+        Err(e) => warn!("  ⚠ sock_common layout not in BTF, using the default: {:#}", e),
+    }
 
-/// Network latency tracking probe using eBPF
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
-struct Args {
-    /// Duration to run the probe (in seconds, 0 = infinite)
-    #[clap(short, long, default_value_t = 60)]
-    duration: u64,
+    // Record the BPF features of the node and the ones in use
+    collector.lock().await.set_features(KernelFeatures {
+        available: host::available_features(btf.as_ref().ok(), &host::current().kernel),
+        used: UsedFeatures {
+            event_buffer: "perf".to_string(),
+            attach: args.attach_mode.to_string(),
+            btf_offsets,
+        },
+    });
 
-    /// Output file for metrics
-    #[clap(short, long, default_value = "latency-metrics.json")]
-    output: PathBuf,
+    if args.quic {
+        loader.set_quic_port(args.quic_port)?;
+    }
 
-    /// Output format (json, prometheus, influx)
-    #[clap(short, long, default_value = "json")]
-    format: String,
+    Ok(loader)
+}
 
-    /// Sampling rate (1 = capture all, 100 = capture 1 in 100)
-    #[clap(short, long, default_value_t = 1)]
-    sample_rate: u32,
+/// Open the perf event arrays and spawn the per-CPU readers
+async fn start_readers(args: &Args, loader: &mut ProbeLoader, processor: &EventProcessor) -> Result<()> {
+    // Get perf event arrays
+    let perf_array = loader.get_perf_array()?;
+    let context_switch_array = loader.get_context_switch_array()?;
 
-    /// Network interface for XDP attachment (e.g., enp0s6, eth0)
-    #[clap(short, long)]
-    interface: Option<String>,
+    // Spawn per-CPU event readers for latency events
+    processor.spawn_cpu_readers(perf_array).await?;
 
-    /// Filter by specific service (format: IP:PORT) - NOT YET IMPLEMENTED
-    #[clap(long)]
-    filter_service: Option<String>,
+    // Spawn per-CPU event readers for context switch events
+    processor.spawn_context_switch_readers(context_switch_array).await?;
 
-    /// Verbose logging
-    #[clap(short, long)]
-    verbose: bool,
+    // Packet drops are only needed to explain tail latency
+    if args.tail_analysis {
+        processor.spawn_packet_drop_readers(loader.get_packet_drops_array()?).await?;
+    }
 
-    /// Path to eBPF object file (if not embedded)
-    #[clap(long)]
-    ebpf_object: Option<PathBuf>,
+    Ok(())
+}
 
-    /// Progress reporting interval in seconds
-    #[clap(long, default_value_t = 10)]
-    progress_interval: u64,
+/// Swap in the eBPF object at --ebpf-object and read its perf buffers
+///
+/// Failures are logged; the running object is kept when the swap fails.
+/// The readers of the replaced object's buffers are stopped before the new
+/// object's are started. Loading and attaching need root, so a probe that
+/// dropped its privileges refuses to swap.
+async fn swap_object(args: &Args, loader: &mut ProbeLoader, processor: &EventProcessor) {
+    let Some(path) = &args.ebpf_object else {
+        warn!("Hot-swapping needs --ebpf-object; ignoring SIGUSR2");
+        return;
+    };
+    if args.user.is_some() || args.retain_caps {
+        warn!("Hot-swapping needs root, dropped by --user/--retain-caps; ignoring SIGUSR2");
+        return;
+    }
+    if let Err(e) = loader.reload(path) {
+        warn!("Hot-swap failed: {:#}", e);
+        return;
+    }
+    processor.stop_readers().await;
+    if let Err(e) = start_readers(args, loader, processor).await {
+        warn!("Failed to read events from the swapped-in object: {:#}", e);
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+/// Track the services of the tenants on top of the configured services
+async fn tenant_services(collector: &Mutex<MetricsCollector>) -> Vec<String> {
+    collector
+        .lock()
+        .await
+        .tenants()
+        .and_then(TenantSet::filter_services)
+        .unwrap_or_default()
+}
 
-    // Initialize logging
-    env_logger::Builder::from_default_env()
-        .filter_level(if args.verbose {
-            log::LevelFilter::Debug
-        } else {
-            log::LevelFilter::Info
-        })
-        .init();
+/// Finish starting up once the readers run: report progress, watch the
+/// SLO, and drop root
+///
+/// # Arguments
+///
+/// * `args` - Command-line arguments
+/// * `processor` - Event processor whose readers were started
+/// * `collector` - Collector shared with the SLO monitor
+/// * `report` - Report writer holding the webhook notifier
+/// * `health` - Health state, marked attached
+#[cfg_attr(not(feature = "webhook"), allow(unused_variables))]
+fn finish_startup(
+    args: &Args,
+    processor: &EventProcessor,
+    collector: &Arc<Mutex<MetricsCollector>>,
+    report: &ReportWriter,
+    health: &Health,
+) -> Result<()> {
+    health.set_attached();
 
-    print_banner();
+    // Spawn progress reporter
+    processor.spawn_progress_reporter(args.progress_interval);
 
-    info!("Starting eBPF latency probe...");
-    info!(
-        "   Duration: {} seconds",
-        if args.duration == 0 {
-            "infinite".to_string()
+    // Alert when live latency breaches the SLO
+    #[cfg(feature = "webhook")]
+    if let Some(notifier) = &report.notifier {
+        notifier::spawn_slo_monitor(Arc::clone(notifier), Arc::clone(collector), args.slo_check_interval);
+    }
+
+    // Everything is loaded and open; root is no longer needed
+    if args.user.is_some() || args.retain_caps {
+        info!("Dropping privileges...");
+        let user = args.user.as_deref().map(Credentials::lookup).transpose()?;
+        let retain = if args.retain_caps {
+            privileges::required_capabilities(!args.interface.is_empty())
         } else {
-            args.duration.to_string()
-        }
-    );
-    info!("   Output: {:?}", args.output);
-    info!("   Format: {}", args.format);
-    info!("   Sample rate: 1 in {}", args.sample_rate);
-    if let Some(ref iface) = args.interface {
-        info!("   XDP interface: {}", iface);
+            Vec::new()
+        };
+        privileges::drop_privileges(user.as_ref(), &retain)?;
     }
+    Ok(())
+}
 
-    // Validate sample rate
-    if args.sample_rate == 0 {
-        anyhow::bail!("Sample rate must be >= 1");
+/// Find an option a scenario cannot honour
+///
+/// Scenarios run their phases back to back and report each on its own, so
+/// options that span the whole run (replays, checkpoints, rolling windows,
+/// interval snapshots, triggered captures, fault injection, and the
+/// signals and config reloads of a service) do not apply.
+///
+/// # Returns
+///
+/// The first such option given, if any
+fn scenario_conflict(args: &Args) -> Option<&'static str> {
+    #[cfg(feature = "kubernetes")]
+    if args.k8s_namespace.is_some() {
+        return Some("--k8s-namespace");
     }
+    [
+        (args.replay.is_some(), "--replay"),
+        (args.checkpoint.is_some(), "--checkpoint"),
+        (args.resume.is_some(), "--resume"),
+        (args.window.is_some(), "--window"),
+        (args.stream.is_some(), "--stream"),
+        (args.trigger_p99_us.is_some(), "--trigger-p99-us"),
+        (!args.inject.is_empty(), "--inject"),
+        (args.daemon, "--daemon"),
+        (args.config.is_some(), "--config"),
+    ]
+    .into_iter()
+    .find_map(|(given, flag)| given.then_some(flag))
+}
 
-    // Parse export format
-    let export_format = match args.format.to_lowercase().as_str() {
-        "json" => ExporterType::Json,
-        "prometheus" | "prom" => ExporterType::Prometheus,
-        "influx" | "influxdb" => ExporterType::Influx,
-        _ => anyhow::bail!(
-            "Unsupported format: {}. Use json, prometheus, or influx",
-            args.format
-        ),
-    };
+/// Attach the probes, then run the phases of a scenario
+///
+/// Each phase runs its setup commands, applies its settings, and waits out
+/// its warm-up before measuring; events from setup and warm-up are
+/// discarded. The phase's report is written to the output path with the
+/// phase name added, and the comparison of all phases to
+/// `<output>.comparison.json`. An interrupt ends the current phase early
+/// and skips the remaining ones. Options a scenario cannot honour are
+/// rejected up front (see `scenario_conflict`).
+async fn run_scenario(
+    args: &Args,
+    scenario: &Scenario,
+    config: ProbeConfig,
+    processor: &EventProcessor,
+    collector: &Arc<Mutex<MetricsCollector>>,
+    report: &ReportWriter,
+    health: &Health,
+) -> Result<ScenarioSummary> {
+    let mut loader = attach_probe(args, collector).await?;
+    let services = tenant_services(collector).await;
+    loader.apply_config(&config.with_services(&services))?;
 
-    // Load eBPF program
-    let mut loader = ProbeLoader::load(args.ebpf_object.clone())?;
+    info!("Running scenario '{}' ({} phases)...", scenario.name, scenario.phases.len());
+    start_readers(args, &mut loader, processor).await?;
+    finish_startup(args, processor, collector, report, health)?;
 
-    // Initialize eBPF logger (optional)
-    loader.init_logger();
+    let start_time = Instant::now();
+    let mut reports = Vec::new();
+    for (i, phase) in scenario.phases.iter().enumerate() {
+        info!("Phase {}/{}: {} ({}s)", i + 1, scenario.phases.len(), phase.name, phase.duration);
+        phase.run_setup().await?;
+
+        let phase_config = phase.config(&config).with_services(&services);
+        loader.apply_config(&phase_config)?;
+        if phase.warmup > 0 {
+            info!("  Warming up for {}s", phase.warmup);
+            if sleep_or_interrupt(Duration::from_secs(phase.warmup)).await {
+                warn!("Interrupted during warm-up, skipping the remaining phases");
+                break;
+            }
+        }
 
-    // Attach kprobes
-    loader.attach_kprobes()?;
+        // Events from setup and warm-up are not part of the phase
+        collector.lock().await.rotate();
+        collector.lock().await.record_config_change(&phase_config);
+        let phase_start = Instant::now();
+        let interrupted = sleep_or_interrupt(Duration::from_secs(phase.duration)).await;
 
-    // Attach tracepoints (kfree_skb + sched_switch)
-    loader.attach_tracepoints()?;
+        check_map_health(&loader, collector).await;
+        read_connection_counters(&mut loader, collector).await;
+        collector.lock().await.set_export_stats(report.export_stats());
+        let finished = collector.lock().await.rotate();
+        let mut metrics = snapshot(&finished, &mut loader, phase_start, start_time);
+        metrics.labels = phase.report_labels(&scenario.name);
 
-    // Attach XDP if interface specified
-    if let Some(ref iface) = args.interface {
-        use aya::programs::XdpFlags;
-        loader.attach_xdp(iface, XdpFlags::default())?;
+        let path = scenario::phase_path(&report.output, &phase.name);
+        report.write(&metrics, &path)?;
+        if let Err(e) = report.publish(&metrics).await {
+            warn!("Failed to publish phase report: {:#}", e);
+        }
+        info!(
+            "  {} events, p50 {:.2}us, p99 {:.2}us, written to {:?}",
+            metrics.total_events, metrics.percentiles.p50, metrics.percentiles.p99, path
+        );
+        reports.push(metrics);
+
+        if interrupted {
+            warn!("Interrupted, skipping the remaining phases");
+            break;
+        }
     }
 
-    // Get perf event arrays
-    let perf_array = loader.get_perf_array()?;
-    let context_switch_array = loader.get_context_switch_array()?;
+    if let Some(stream) = &report.stream {
+        stream.finish()?;
+    }
 
-    info!("Collecting metrics...");
+    let summary = ScenarioSummary::compare(scenario, &reports);
+    let mut path = scenario::phase_path(&report.output, scenario::COMPARISON);
+    if !output::is_stdout(&path) {
+        path.set_extension("json");
+    }
+    summary.write(&path)?;
+    info!("Phase comparison written to {:?}", path);
 
-    // Create metrics collector
-    let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+    Ok(summary)
+}
 
-    // Create event processor
-    let processor = EventProcessor::new(Arc::clone(&collector), args.sample_rate, args.verbose);
+/// Sleep for `duration`, or until interrupted
+///
+/// # Returns
+///
+/// Whether the probe was interrupted
+async fn sleep_or_interrupt(duration: Duration) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => false,
+        _ = signal::ctrl_c() => true,
+    }
+}
 
-    // Spawn per-CPU event readers for latency events
-    processor.spawn_cpu_readers(perf_array).await?;
+/// Print the phases of a scenario against its baseline
+fn print_scenario_summary(summary: &ScenarioSummary) {
+    let change = |pct: Option<f64>| pct.map(|pct| format!("{:+.1}%", pct)).unwrap_or_default();
 
-    // Spawn per-CPU event readers for context switch events
-    processor.spawn_context_switch_readers(context_switch_array).await?;
+    info!("");
+    info!("============================================");
+    info!("        Scenario: {}", summary.scenario);
+    info!("============================================");
+    info!("");
+    info!("  Compared with phase '{}':", summary.baseline);
+    for phase in &summary.phases {
+        info!(
+            "  {:<16} {:>8} events, p50 {:>10.2}us {:>8}, p99 {:>10.2}us {:>8}",
+            phase.phase,
+            phase.total_events,
+            phase.p50_us,
+            change(phase.p50_change_pct),
+            phase.p99_us,
+            change(phase.p99_change_pct)
+        );
+    }
+}
 
-    // Spawn progress reporter
-    processor.spawn_progress_reporter(args.progress_interval);
+/// Run the loopback self-test and fail if the probes did not observe it
+async fn run_selftest(ebpf_object: Option<PathBuf>, round_trips: usize, timeout: u64) -> Result<()> {
+    info!("Running loopback self-test...");
 
-    // Run for specified duration or until interrupted
-    let start_time = Instant::now();
-    let duration = if args.duration > 0 {
-        Some(Duration::from_secs(args.duration))
-    } else {
-        None
+    let config = SelftestConfig {
+        round_trips,
+        timeout: Duration::from_secs(timeout),
+        ..Default::default()
     };
+    let report = selftest::run(ebpf_object, &config).await?;
 
-    if let Some(d) = duration {
-        tokio::select! {
-            _ = sleep(d) => {
-                info!("Duration reached, shutting down...");
-            }
-            _ = signal::ctrl_c() => {
-                info!("Interrupted, shutting down...");
-            }
+    info!("  Events observed:  {}", report.events);
+    if report.events > 0 {
+        info!(
+            "  Latency range:    {:.2} - {:.2} us",
+            report.min_latency_ns as f64 / 1000.0,
+            report.max_latency_ns as f64 / 1000.0
+        );
+    }
+
+    let failures = report.failures();
+    if !failures.is_empty() {
+        for failure in &failures {
+            warn!("  ✗ {}", failure);
         }
+        anyhow::bail!("Self-test failed");
+    }
+
+    info!("  ✓ Self-test passed");
+
+    Ok(())
+}
+
+/// Load the eBPF object(s) from --ebpf-manifest, --ebpf-dir, or --ebpf-object
+fn load_probe(args: &Args) -> Result<ProbeLoader> {
+    let sizes = MapSizes {
+        connections: u32::try_from(args.max_connections)
+            .ok()
+            .filter(|&n| n > 0)
+            .context("--max-connections must be between 1 and 4294967295")?,
+    };
+    let mut loader = if let Some(path) = &args.ebpf_manifest {
+        ProbeLoader::load_manifest(&ObjectManifest::load(path)?, &sizes)?
+    } else if let Some(dir) = &args.ebpf_dir {
+        ProbeLoader::load_manifest(&ObjectManifest::from_dir(dir)?, &sizes)?
     } else {
-        signal::ctrl_c().await?;
-        info!("Interrupted, shutting down...");
+        ProbeLoader::load(args.ebpf_object.clone(), &sizes)?
+    };
+    if let Some(instance) = &args.instance {
+        loader.set_instance(instance.clone());
     }
+    Ok(loader)
+}
 
-    let elapsed = start_time.elapsed().as_secs();
+/// Load the eBPF object through the verifier and report without attaching
+fn run_dry_run(args: &Args) -> Result<()> {
+    info!("Verifying eBPF object (dry run)...");
 
-    info!("Generating metrics report...");
+    let mut loader = load_probe(args)?;
+    let report = verify::verify(&mut loader);
+    report.print();
+
+    if !report.passed() {
+        anyhow::bail!("Dry run failed with {} error(s)", report.errors.len());
+    }
 
-    // Read XDP stats from BPF STATS map before generating metrics
-    let xdp_stats = loader.read_xdp_stats(elapsed);
+    info!("  ✓ Dry run passed");
 
-    // Generate final metrics
+    Ok(())
+}
+
+/// Generate metrics for the interval that began at `interval_start`
+fn snapshot(
+    collector: &MetricsCollector,
+    loader: &mut ProbeLoader,
+    interval_start: Instant,
+    start_time: Instant,
+) -> LatencyMetrics {
+    let mut metrics = collector.generate_metrics(interval_start.elapsed().as_secs());
+    metrics.xdp_stats = loader.read_xdp_stats(start_time.elapsed().as_secs());
+    metrics.program_stats = loader.read_program_stats();
+    metrics.latency_bounds = Some(loader.read_latency_bounds());
+    metrics
+}
+
+/// Read map occupancy and the kernel discard counters, warn about nearly
+/// full maps, and record the readings for the report
+async fn check_map_health(loader: &ProbeLoader, collector: &Arc<Mutex<MetricsCollector>>) {
+    let maps = loader.read_map_health();
+    for map in maps.iter().filter(|m| m.is_near_full()) {
+        warn!(
+            "⚠ {} is {:.0}% full ({}/{} entries); new entries may be dropped",
+            map.map,
+            map.fill_ratio * 100.0,
+            map.entries,
+            map.max_entries
+        );
+    }
+    let internal = loader.read_internal_counters();
     let mut collector = collector.lock().await;
-    let mut metrics = collector.generate_metrics(elapsed);
-    metrics.xdp_stats = xdp_stats;
+    collector.set_map_health(maps);
+    collector.set_internal_counters(internal);
+}
 
-    // Export metrics based on format
-    match export_format {
-        ExporterType::Json => {
-            let exporter = JsonExporter::new(args.output.clone(), true);
-            exporter.export(&metrics)?;
+/// Write a checkpoint of the collector if `--checkpoint` was given
+async fn save_checkpoint(
+    path: &Option<PathBuf>,
+    collector: &Arc<Mutex<MetricsCollector>>,
+    interval_start: Instant,
+) {
+    let Some(path) = path else {
+        return;
+    };
+
+    let collector = collector.lock().await;
+    match checkpoint::write(path, &collector, interval_start.elapsed().as_secs()) {
+        Ok(()) => debug!("Checkpoint written to {:?}", path),
+        Err(e) => warn!("Failed to write checkpoint: {:#}", e),
+    }
+}
+
+/// Add the connections' byte counters and congestion state, and the
+/// listeners' accept queue counters, to the collector
+async fn read_connection_counters(loader: &mut ProbeLoader, collector: &Arc<Mutex<MetricsCollector>>) {
+    let states = loader.read_connection_states();
+    let congestion = loader.read_congestion();
+    let listeners = loader.read_listeners();
+    let mut collector = collector.lock().await;
+    for state in &states {
+        collector.add_connection_bytes(state);
+    }
+    collector.add_congestion(&congestion);
+    collector.add_listeners(&listeners);
+}
+
+/// Log the in-kernel run time of each attached program
+fn log_program_stats(stats: &[ProgramStats]) {
+    for program in stats {
+        info!(
+            "⏱  {}: {} runs, avg {:.0}ns, total {:.3}ms",
+            program.program,
+            program.run_count,
+            program.avg_run_time_ns,
+            program.run_time_ns as f64 / 1_000_000.0
+        );
+    }
+}
+
+/// Reload the config file and apply it to the running probe
+///
+/// Invalid configs are logged and ignored so a typo does not end the run.
+async fn reload_config(
+    path: &Option<PathBuf>,
+    config: &mut ProbeConfig,
+    pod_services: &[String],
+    loader: &mut ProbeLoader,
+    collector: &Arc<Mutex<MetricsCollector>>,
+) {
+    let Some(path) = path else {
+        return;
+    };
+
+    *config = match ProbeConfig::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Ignoring invalid config {:?}: {:#}", path, e);
+            return;
         }
-        ExporterType::Prometheus => {
-            let exporter = PrometheusExporter::new(args.output.clone());
-            exporter.export(&metrics)?;
+    };
+
+    if apply_config(config, pod_services, loader, collector).await {
+        info!("Reloaded config from {:?}", path);
+    }
+}
+
+/// Apply a config, with the services of tracked pods and tenants added, to
+/// the running probe and record the change
+///
+/// # Returns
+///
+/// Whether the config was applied (failures are logged)
+async fn apply_config(
+    config: &ProbeConfig,
+    pod_services: &[String],
+    loader: &mut ProbeLoader,
+    collector: &Arc<Mutex<MetricsCollector>>,
+) -> bool {
+    let config = config.with_services(pod_services);
+
+    match loader.apply_config(&config) {
+        Ok(()) => {
+            collector.lock().await.record_config_change(&config);
+            true
         }
-        ExporterType::Influx => {
-            let exporter = InfluxExporter::new(args.output.clone(), "latency_probe".to_string());
-            exporter.export(&metrics)?;
+        Err(e) => {
+            warn!("Failed to apply config: {:#}", e);
+            false
         }
     }
+}
 
-    info!("Metrics written to {:?}", args.output);
+/// Start or stop a triggered capture of every event
+///
+/// A capture applies the config with sampling turned off, and ends by
+/// applying the config again. Failures are logged.
+async fn capture(
+    action: TriggerAction,
+    config: &ProbeConfig,
+    pod_services: &[String],
+    loader: &mut ProbeLoader,
+    collector: &Arc<Mutex<MetricsCollector>>,
+    dump: Option<&CaptureDump>,
+) {
+    match action {
+        TriggerAction::None => {}
+        TriggerAction::Start(p99) => {
+            info!("⚠ Interval p99 {:.1}us exceeded the trigger, capturing every event", p99);
+            let full = ProbeConfig {
+                sample_rate: 1,
+                event_sample_rates: BTreeMap::new(),
+                ..config.clone()
+            };
+            apply_config(&full, pod_services, loader, collector).await;
+            match dump.map(CaptureDump::start) {
+                Some(Ok(path)) => info!("   Recording the capture to {:?}", path),
+                Some(Err(e)) => warn!("Failed to record the capture: {:#}", e),
+                None => {}
+            }
+        }
+        TriggerAction::Stop => {
+            if let Some(Err(e)) = dump.map(CaptureDump::stop) {
+                warn!("Failed to finish the capture recording: {:#}", e);
+            }
+            if apply_config(config, pod_services, loader, collector).await {
+                info!("Capture ended, sampling restored ({})", config.describe_sample_rates());
+            }
+        }
+    }
+}
 
-    // Print summary
-    print_summary(&metrics);
+/// Wait for new pod filter entries, or forever without a pod filter
+async fn next_pod_update(updates: &mut Option<mpsc::Receiver<Vec<String>>>) -> Option<Vec<String>> {
+    match updates {
+        Some(updates) => updates.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the config file to change, or forever without one
+async fn config_changed(watcher: &mut Option<ConfigWatcher>) {
+    match watcher {
+        Some(watcher) => watcher.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `deadline`, or forever if there is none
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next daemon signal, or forever outside daemon mode
+async fn next_signal(signals: &mut Option<DaemonSignals>) -> DaemonSignal {
+    match signals {
+        Some(signals) => signals.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Files a run publishes: the report (unless on stdout), recordings,
+/// correlation records, the --stream file and the --upload-files
+#[cfg(any(feature = "seal", feature = "upload"))]
+fn run_artifacts(args: &Args) -> Vec<PathBuf> {
+    let report_path = Some(&args.output).filter(|path| !output::is_stdout(path));
+    let files = [report_path, args.record.as_ref(), args.stream.as_ref(), args.correlation.as_ref()]
+        .into_iter()
+        .flatten()
+        .cloned();
+    #[cfg(feature = "arrow")]
+    let files = files.chain(args.record_arrow.clone());
+    #[cfg(feature = "upload")]
+    let files = files.chain(args.upload_file.iter().cloned());
+    files.collect()
+}
 
+/// Sealer of --sign-key and --encrypt-to (leaves files as they are without
+/// either)
+#[cfg(feature = "seal")]
+fn sealer(args: &Args) -> Result<Sealer> {
+    let mut sealer = Sealer::new().with_recipients(&args.encrypt_to)?;
+    if let Some(path) = &args.sign_key {
+        sealer = sealer.with_signing_key(path)?;
+    }
+    if !sealer.is_empty() {
+        info!("   Sealing artifacts: signed={} recipients={}", args.sign_key.is_some(), args.encrypt_to.len());
+    }
+    Ok(sealer)
+}
+
+/// Identity of this probe in the election of --cluster-lease: the address
+/// its API is reached at
+#[cfg(feature = "kubernetes")]
+fn cluster_identity(args: &Args) -> Result<String> {
+    if let Some(address) = &args.cluster_advertise {
+        return Ok(address.clone());
+    }
+    let port = args.listen.context("--cluster-lease needs --listen")?.port();
+    let ip = std::env::var("POD_IP")
+        .context("--cluster-lease needs --cluster-advertise, or POD_IP set from the downward API")?;
+    Ok(match ip.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+        _ => format!("{}:{}", ip, port),
+    })
+}
+
+/// Uploader of --upload (None without it)
+#[cfg(feature = "upload")]
+fn uploader(args: &Args) -> Result<Option<Uploader>> {
+    let Some(target) = &args.upload else {
+        return Ok(None);
+    };
+    let policy = RetryPolicy {
+        attempts: args.export_attempts,
+        timeout: Duration::from_secs(args.upload_timeout),
+        ..Default::default()
+    };
+    let credentials = AccessKey::from_env().context("--upload needs credentials")?;
+    let uploader = Uploader::new(target.clone(), args.upload_endpoint.as_deref(), &args.upload_region, credentials, policy)?;
+    info!("   Uploading to: {}", target);
+    Ok(Some(uploader))
+}
+
+/// Print the runs of the registry (`runs list` and `runs show`)
+fn show_runs(registry: Option<&Path>, command: &RunsCommand) -> Result<()> {
+    let registry = RunRegistry::new(registry.context("runs needs --registry DIR")?);
+    match command {
+        RunsCommand::List => {
+            println!(
+                "{:<36}  {:<25}  {:>8}  {:>10}  {:>10}  {:>10}  REPORT",
+                "RUN ID", "FINISHED", "SECONDS", "EVENTS", "P50 (US)", "P99 (US)"
+            );
+            for record in registry.list()? {
+                let run = match &record.instance {
+                    Some(instance) => format!("{}@{}", record.run_id, instance),
+                    None => record.run_id.clone(),
+                };
+                println!(
+                    "{:<36}  {:<25}  {:>8}  {:>10}  {:>10.2}  {:>10.2}  {}",
+                    run,
+                    record.finished_at,
+                    record.duration_seconds,
+                    record.total_events,
+                    record.percentiles.p50,
+                    record.percentiles.p99,
+                    record.report.as_ref().map_or("-".into(), |path| path.display().to_string())
+                );
+            }
+        }
+        RunsCommand::Show { id } => {
+            let records = registry.find(id)?;
+            println!("{}", serde_json::to_string_pretty(&records)?);
+        }
+    }
     Ok(())
 }
 
@@ -242,9 +2592,59 @@ fn print_summary(metrics: &LatencyMetrics) {
     info!("============================================");
     info!("");
     info!("  Total events:       {}", metrics.total_events);
+    if metrics.lost_events > 0 {
+        info!("  Lost events:        {}", metrics.lost_events);
+    }
+    if let Some(bounds) = metrics.latency_bounds.as_ref().filter(|b| b.below_min + b.above_max > 0) {
+        info!(
+            "  Rejected latencies: {} below {}ns, {} above {}ns",
+            bounds.below_min, bounds.min_latency_ns, bounds.above_max, bounds.max_latency_ns
+        );
+    }
     info!("  Unique connections: {}", metrics.connections.len());
+    if metrics.untracked_connection_events > 0 {
+        info!(
+            "  Untracked events:   {} (over --max-connections)",
+            metrics.untracked_connection_events
+        );
+    }
+    if metrics.untracked_process_events > 0 {
+        info!(
+            "  Untracked events:   {} (over --max-processes)",
+            metrics.untracked_process_events
+        );
+    }
     info!("  Duration:           {} seconds", metrics.duration_seconds);
     info!("");
+    if let Some(client) = &metrics.client {
+        let row = |name: &str, client_us: Option<f64>, kernel_us: f64| match client_us {
+            Some(client_us) => info!(
+                "    {:<5} client {:>10.2}us, kernel {:>10.2}us, above kernel {:>10.2}us",
+                name,
+                client_us,
+                kernel_us,
+                client_us - kernel_us
+            ),
+            None => info!("    {:<5} not reported by {}", name, client.tool),
+        };
+        info!("  Client Latency ({}, {} requests, {} errors):", client.tool, client.requests, client.errors);
+        row("p50", client.percentiles.p50, metrics.percentiles.p50);
+        row("p99", client.percentiles.p99, metrics.percentiles.p99);
+        info!("");
+    }
+    if !metrics.fault_injections.is_empty() {
+        info!("  Injected Faults:");
+        for fault in &metrics.fault_injections {
+            info!(
+                "    {} on {}: events {}..{}",
+                fault.netem,
+                fault.interface,
+                fault.events_before,
+                fault.events_until.map(|n| n.to_string()).unwrap_or_else(|| "end".to_string())
+            );
+        }
+        info!("");
+    }
     info!("  Latency Percentiles (us):");
     info!("    p50:  {:>10.2}", metrics.percentiles.p50);
     info!("    p75:  {:>10.2}", metrics.percentiles.p75);
@@ -253,6 +2653,11 @@ fn print_summary(metrics: &LatencyMetrics) {
     info!("    p99:  {:>10.2}", metrics.percentiles.p99);
     info!("    p999: {:>10.2}", metrics.percentiles.p999);
     info!("");
+    info!(
+        "  Jitter (us): mean {:.2}, max {:.2}",
+        metrics.jitter.mean_us, metrics.jitter.max_us
+    );
+    info!("");
     info!("  Histogram:");
     info!("    0-1ms:       {:>8}", metrics.histogram.bucket_0_1ms);
     info!("    1-5ms:       {:>8}", metrics.histogram.bucket_1_5ms);
@@ -261,6 +2666,229 @@ fn print_summary(metrics: &LatencyMetrics) {
     info!("    50-100ms:    {:>8}", metrics.histogram.bucket_50_100ms);
     info!("    100ms+:      {:>8}", metrics.histogram.bucket_100ms_plus);
     info!("");
+    if metrics.namespaces.len() > 1 {
+        info!("  Network Namespaces:");
+        let mut namespaces: Vec<_> = metrics.namespaces.iter().collect();
+        namespaces.sort_by(|a, b| a.0.cmp(b.0));
+        for (netns, ns_metrics) in namespaces {
+            info!(
+                "    {:<12} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                netns, ns_metrics.events, ns_metrics.percentiles.p50, ns_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if metrics.bursts.bursts > 0 {
+        info!(
+            "  Microbursts: {} over {}ms (longest {}ms), peak {:.0}/s vs median {:.0}/s",
+            metrics.bursts.bursts,
+            metrics.bursts.total_duration_ms,
+            metrics.bursts.max_duration_ms,
+            metrics.bursts.peak_rate,
+            metrics.bursts.median_rate
+        );
+        info!("");
+    }
+    if let Some(tail) = &metrics.tail {
+        info!("  Tail Causes ({} events above {:.2}us):", tail.events, tail.threshold_us);
+        info!("    packet drop:    {:>8}", tail.packet_drop);
+        info!("    context switch: {:>8}", tail.context_switch);
+        info!("    unexplained:    {:>8}", tail.unexplained);
+        info!("");
+    }
+    if let Some(correction) = &metrics.coordinated_omission {
+        info!(
+            "  Coordinated omission: corrected for a {:.0}us request interval ({} synthetic samples)",
+            correction.expected_interval_us, correction.synthetic_samples
+        );
+        info!("");
+    }
+    if let Some((min, max)) = metrics.event_rate.rate_range() {
+        info!(
+            "  Event Rate ({}ms buckets): min {:.1}/s, max {:.1}/s",
+            metrics.event_rate.resolution_ms, min, max
+        );
+        info!("");
+    }
+    if metrics.throughput.bytes_sent > 0 || metrics.throughput.bytes_received > 0 {
+        info!("  Throughput:");
+        info!(
+            "    sent:     {:>12} bytes ({:.1} bytes/s)",
+            metrics.throughput.bytes_sent, metrics.throughput.sent_bytes_per_second
+        );
+        info!(
+            "    received: {:>12} bytes ({:.1} bytes/s)",
+            metrics.throughput.bytes_received, metrics.throughput.received_bytes_per_second
+        );
+        info!("");
+    }
+    if metrics.dns_latency.queries > 0 {
+        info!("  DNS Latency (microseconds, {} queries):", metrics.dns_latency.queries);
+        info!("    p50:  {:>10.2}", metrics.dns_latency.percentiles.p50);
+        info!("    p95:  {:>10.2}", metrics.dns_latency.percentiles.p95);
+        info!("    p99:  {:>10.2}", metrics.dns_latency.percentiles.p99);
+        info!("");
+    }
+    if let Some(cleanup) = &metrics.dedup.cleanup {
+        info!("  Cleanup Latency (microseconds, {} events):", cleanup.events);
+        info!("    p50:  {:>10.2}", cleanup.percentiles.p50);
+        info!("    p95:  {:>10.2}", cleanup.percentiles.p95);
+        info!("    p99:  {:>10.2}", cleanup.percentiles.p99);
+        info!("");
+    }
+    if metrics.dedup.duplicates > 0 {
+        info!("  Duplicate receives: {} ({} policy)", metrics.dedup.duplicates, metrics.dedup.policy);
+        info!("");
+    }
+    let errors = &metrics.errors;
+    if errors.resets_received + errors.resets_sent + errors.timeouts > 0 {
+        info!("  Resets and Timeouts:");
+        info!(
+            "    resets:   {:>8} received, {:>8} sent ({:.2}/s)",
+            errors.resets_received, errors.resets_sent, errors.resets_per_second
+        );
+        info!("    timeouts: {:>8} ({:.2}/s)", errors.timeouts, errors.timeouts_per_second);
+        for (service, service_errors) in &errors.services {
+            info!(
+                "    {:<16} {:>8} resets, {:>8} timeouts",
+                service,
+                service_errors.resets(),
+                service_errors.timeouts
+            );
+        }
+        info!("");
+    }
+    if !metrics.http_status.is_empty() {
+        info!("  HTTP Status:");
+        let mut classes: Vec<_> = metrics.http_status.iter().collect();
+        classes.sort_by(|a, b| a.0.cmp(b.0));
+        for (class, status_metrics) in classes {
+            info!(
+                "    {:<16} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                class,
+                status_metrics.events,
+                status_metrics.percentiles.p50,
+                status_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if !metrics.services.is_empty() {
+        info!("  Services:");
+        let mut services: Vec<_> = metrics.services.iter().collect();
+        services.sort_by(|a, b| b.1.events.cmp(&a.1.events).then_with(|| a.0.cmp(b.0)));
+        for (service, service_metrics) in services {
+            info!(
+                "    {:<16} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                service,
+                service_metrics.events,
+                service_metrics.percentiles.p50,
+                service_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if metrics.traffic_classes.len() > 1 {
+        for (class, class_metrics) in &metrics.traffic_classes {
+            info!("  Latency on {} connections (microseconds, {} events):", class, class_metrics.events);
+            info!("    p50:  {:>10.2}", class_metrics.percentiles.p50);
+            info!("    p95:  {:>10.2}", class_metrics.percentiles.p95);
+            info!("    p99:  {:>10.2}", class_metrics.percentiles.p99);
+            info!("");
+        }
+    }
+    if !metrics.zones.is_empty() {
+        info!("  Zones:");
+        for (locality, zone_metrics) in &metrics.zones {
+            info!(
+                "    {:<16} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                locality,
+                zone_metrics.events,
+                zone_metrics.percentiles.p50,
+                zone_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if !metrics.tenants.is_empty() {
+        info!("  Tenants:");
+        for (tenant, tenant_metrics) in &metrics.tenants {
+            info!(
+                "    {:<16} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                tenant,
+                tenant_metrics.events,
+                tenant_metrics.percentiles.p50,
+                tenant_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if metrics.phases.len() > 1 {
+        info!("  Connection Phases:");
+        for (phase, phase_metrics) in &metrics.phases {
+            info!(
+                "    {:<16} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                phase,
+                phase_metrics.events,
+                phase_metrics.percentiles.p50,
+                phase_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if metrics.protocols.len() > 1 {
+        info!("  Protocols:");
+        for (protocol, protocol_metrics) in &metrics.protocols {
+            info!(
+                "    {:<16} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                protocol,
+                protocol_metrics.events,
+                protocol_metrics.percentiles.p50,
+                protocol_metrics.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if !metrics.processes.is_empty() {
+        info!("  Top Processes:");
+        let mut processes: Vec<_> = metrics.processes.iter().collect();
+        processes.sort_by(|a, b| b.1.events.cmp(&a.1.events).then_with(|| a.0.cmp(b.0)));
+        for (pid, process) in processes.into_iter().take(10) {
+            info!(
+                "    {:<16} {:>8} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                process.comm.as_deref().unwrap_or("?"),
+                pid,
+                process.events,
+                process.percentiles.p50,
+                process.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    if !metrics.pods.is_empty() {
+        info!("  Top Pods:");
+        let mut pods: Vec<_> = metrics.pods.iter().collect();
+        pods.sort_by(|a, b| b.1.events.cmp(&a.1.events).then_with(|| a.0.cmp(b.0)));
+        for (uid, pod) in pods.into_iter().take(10) {
+            info!(
+                "    {} {:<10} {:>8} events, p50 {:>10.2}us, p99 {:>10.2}us",
+                uid, pod.qos_class, pod.events, pod.percentiles.p50, pod.percentiles.p99
+            );
+        }
+        info!("");
+    }
+    let inactive: Vec<_> = metrics.probes.iter().filter(|p| !p.is_active()).collect();
+    if !inactive.is_empty() {
+        info!(
+            "  Probes: {}/{} active",
+            metrics.probes.len() - inactive.len(),
+            metrics.probes.len()
+        );
+        for probe in inactive {
+            info!("    inactive: {}", probe.program);
+        }
+        info!("");
+    }
     info!("  Event Type Breakdown:");
     info!(
         "    tcp_sendmsg:      {:>8}",
@@ -274,6 +2902,18 @@ fn print_summary(metrics: &LatencyMetrics) {
         "    tcp_cleanup_rbuf: {:>8}",
         metrics.event_type_breakdown.tcp_cleanup_rbuf
     );
+    if metrics.event_type_breakdown.tcp_probe > 0 {
+        info!(
+            "    tcp_probe (RTT):  {:>8}",
+            metrics.event_type_breakdown.tcp_probe
+        );
+    }
+    if metrics.event_type_breakdown.quic > 0 {
+        info!(
+            "    quic:             {:>8}",
+            metrics.event_type_breakdown.quic
+        );
+    }
     info!("");
     info!("  Context Switches:");
     info!("    total:            {:>8}", metrics.context_switches.total_switches);
@@ -284,5 +2924,32 @@ fn print_summary(metrics: &LatencyMetrics) {
     info!("    tcp:              {:>8}", metrics.xdp_stats.tcp_packets);
     info!("    per second:       {:>8.1}", metrics.xdp_stats.packets_per_second);
     info!("");
+    if !metrics.health.maps.is_empty() {
+        info!("  Probe Health:");
+        for map in &metrics.health.maps {
+            info!(
+                "    {:<24} {:>8}/{} entries ({:.1}%)",
+                map.map,
+                map.entries,
+                map.max_entries,
+                map.fill_ratio * 100.0
+            );
+            if let Some(split) = map.split_entries {
+                info!("    {:<24} {:>8} split across CPUs", "", split);
+            }
+        }
+        info!("    event loss:       {:>7.2}%", metrics.health.event_loss_ratio * 100.0);
+        info!("");
+    }
+    if !metrics.program_stats.is_empty() {
+        info!("  eBPF Program Overhead:");
+        for program in &metrics.program_stats {
+            info!(
+                "    {:<24} {:>10} runs, avg {:>8.0}ns",
+                program.program, program.run_count, program.avg_run_time_ns
+            );
+        }
+        info!("");
+    }
     info!("============================================");
 }
@@ -0,0 +1,688 @@
+//! Merging reports
+//!
+//! Combines the reports of several nodes of one run (or of repeated runs)
+//! into one, without the raw samples. Counts add up; rates are recomputed
+//! over the longest duration, so reports are treated as covering the same
+//! window. The report, every connection, DNS and cleanup latencies, and
+//! every per-group breakdown carry a
+//! [`LatencyDigest`](crate::digest::LatencyDigest), so their percentiles
+//! are recomputed from the merged digests and are accurate to 1%.
+//! Percentiles of reports written before digests are averages weighted by
+//! events, an approximation that is close when the merged distributions are
+//! alike. Interval percentiles (the trajectory) cannot be combined, and are
+//! dropped.
+
+use crate::{digest::LatencyDigest, types::*};
+use std::collections::BTreeMap;
+
+/// Average of two values weighted by their event counts
+fn weighted(value: f64, events: u64, other: f64, other_events: u64) -> f64 {
+    let total = events + other_events;
+    if total == 0 {
+        return value;
+    }
+    (value * events as f64 + other * other_events as f64) / total as f64
+}
+
+/// Percentiles weighted by event counts
+fn weighted_percentiles(p: &Percentiles, events: u64, other: &Percentiles, other_events: u64) -> Percentiles {
+    Percentiles {
+        p50: weighted(p.p50, events, other.p50, other_events),
+        p75: weighted(p.p75, events, other.p75, other_events),
+        p90: weighted(p.p90, events, other.p90, other_events),
+        p95: weighted(p.p95, events, other.p95, other_events),
+        p99: weighted(p.p99, events, other.p99, other_events),
+        p999: weighted(p.p999, events, other.p999, other_events),
+    }
+}
+
+/// Per-group breakdown entry (events, average and percentiles)
+trait Group: Clone {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles);
+
+    /// Merge the fields specific to the group
+    fn merge_details(&mut self, _other: &Self) {}
+
+    fn merge(&mut self, other: &Self) {
+        let mut theirs = other.clone();
+        let (other_events, other_avg, other_percentiles) = theirs.parts();
+        let (events, avg, percentiles) = self.parts();
+
+        *avg = weighted(*avg, *events, *other_avg, *other_events);
+        *percentiles = weighted_percentiles(percentiles, *events, other_percentiles, *other_events);
+        *events += *other_events;
+        self.merge_details(other);
+    }
+}
+
+/// Merge the digests of a group, recomputing its percentiles when both
+/// sides have one
+fn merge_digest(digest: &mut Option<LatencyDigest>, percentiles: &mut Percentiles, other: &Option<LatencyDigest>) {
+    match (digest.as_mut(), other) {
+        (Some(digest), Some(theirs)) => {
+            digest.merge(theirs);
+            *percentiles = digest.percentiles();
+        }
+        _ => *digest = None,
+    }
+}
+
+impl Group for DnsLatencyStats {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.queries, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+impl Group for CleanupLatencyStats {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.events, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+impl Group for GroupMetrics {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.events, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+impl Group for ProcessMetrics {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.events, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        if self.comm.is_none() {
+            self.comm = other.comm.clone();
+        }
+        if self.cmdline.is_none() {
+            self.cmdline = other.cmdline.clone();
+        }
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+impl Group for PodMetrics {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.events, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        for container in &other.containers {
+            if !self.containers.contains(container) {
+                self.containers.push(container.clone());
+            }
+        }
+        self.containers.sort();
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+impl Group for ProtocolMetrics {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.events, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        self.histogram.merge(&other.histogram);
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+impl Group for TenantMetrics {
+    fn parts(&mut self) -> (&mut u64, &mut f64, &mut Percentiles) {
+        (&mut self.events, &mut self.avg_latency_us, &mut self.percentiles)
+    }
+
+    fn merge_details(&mut self, other: &Self) {
+        merge_digest(&mut self.digest, &mut self.percentiles, &other.digest);
+    }
+}
+
+/// Merge a breakdown, combining the groups both reports have
+fn merge_groups<T: Group>(groups: &mut BTreeMap<String, T>, other: &BTreeMap<String, T>) {
+    for (key, group) in other {
+        match groups.get_mut(key) {
+            Some(ours) => ours.merge(group),
+            None => {
+                groups.insert(key.clone(), group.clone());
+            }
+        }
+    }
+}
+
+/// Add up counters keyed by name
+fn merge_counts(counts: &mut BTreeMap<String, u64>, other: &BTreeMap<String, u64>) {
+    for (key, count) in other {
+        *counts.entry(key.clone()).or_default() += count;
+    }
+}
+
+/// Merge the metrics of a connection seen in both reports
+fn merge_connection(c: &mut ConnectionMetrics, other: &ConnectionMetrics) {
+    let (n, m) = (c.events, other.events);
+    let avg = weighted(c.avg_latency_us, n, other.avg_latency_us, m);
+
+    // Pooled variance from each side's mean and variance
+    let square = |std_dev: f64, mean: f64| std_dev * std_dev + mean * mean;
+    let variance = weighted(square(c.std_dev_us, c.avg_latency_us), n, square(other.std_dev_us, other.avg_latency_us), m)
+        - avg * avg;
+
+    c.min_latency_us = c.min_latency_us.min(other.min_latency_us);
+    c.max_latency_us = c.max_latency_us.max(other.max_latency_us);
+    c.std_dev_us = variance.max(0.0).sqrt();
+    c.avg_latency_us = avg;
+    match (c.digest.as_mut(), &other.digest) {
+        (Some(digest), Some(theirs)) => {
+            digest.merge(theirs);
+            let percentiles = digest.percentiles();
+            c.p50_us = percentiles.p50;
+            c.p95_us = percentiles.p95;
+            c.p99_us = percentiles.p99;
+        }
+        _ => {
+            c.digest = None;
+            c.p50_us = weighted(c.p50_us, n, other.p50_us, m);
+            c.p95_us = weighted(c.p95_us, n, other.p95_us, m);
+            c.p99_us = weighted(c.p99_us, n, other.p99_us, m);
+        }
+    }
+    c.jitter_us = weighted(c.jitter_us, n, other.jitter_us, m);
+    c.events += m;
+    c.bytes_sent += other.bytes_sent;
+    c.bytes_received += other.bytes_received;
+    c.bytes_per_second += other.bytes_per_second;
+    c.timeouts += other.timeouts;
+}
+
+/// Add another series bucket by bucket, aligned by their first bucket
+fn merge_series<T: Clone + Default>(ours: &mut Vec<T>, other: &[T], mut add: impl FnMut(&mut T, &T)) {
+    if ours.len() < other.len() {
+        ours.resize(other.len(), T::default());
+    }
+    for (bucket, theirs) in ours.iter_mut().zip(other) {
+        add(bucket, theirs);
+    }
+}
+
+/// Lay the buckets of a later series after ours, aligned by the kernel
+/// timestamps of their first events
+fn append_series<T: Clone + Default>(
+    ours: &mut Vec<T>,
+    start_ns: u64,
+    resolution_ms: u64,
+    later: &[T],
+    later_start_ns: u64,
+    mut add: impl FnMut(&mut T, &T),
+) {
+    let offset = (later_start_ns.saturating_sub(start_ns) / (resolution_ms.max(1) * 1_000_000)) as usize;
+    if ours.len() < offset + later.len() {
+        ours.resize(offset + later.len(), T::default());
+    }
+    for (bucket, theirs) in ours[offset..].iter_mut().zip(later) {
+        add(bucket, theirs);
+    }
+}
+
+impl LatencyMetrics {
+    /// Merge another report into this one
+    ///
+    /// See the [module documentation](crate::merge) for how each part is
+    /// combined. Labels are kept where both reports agree; the load
+    /// generator comparison and the clock, which describe a single report,
+    /// are dropped unless identical.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Report of another node or run
+    pub fn merge(&mut self, other: &LatencyMetrics) {
+        let duration = self.duration_seconds.max(other.duration_seconds);
+        let (samples, other_samples) = (self.histogram.total_count(), other.histogram.total_count());
+
+        // Overall percentiles, before the counts they are weighted by change
+        match (&mut self.digest, &other.digest) {
+            (Some(digest), Some(theirs)) => {
+                digest.merge(theirs);
+                self.percentiles = digest.percentiles();
+            }
+            _ if other_samples == 0 => {}
+            _ if samples == 0 => {
+                self.digest = other.digest.clone();
+                self.percentiles = other.percentiles.clone();
+            }
+            _ => {
+                self.digest = None;
+                self.percentiles = weighted_percentiles(&self.percentiles, samples, &other.percentiles, other_samples);
+            }
+        }
+        self.jitter.mean_us = weighted(self.jitter.mean_us, self.total_events, other.jitter.mean_us, other.total_events);
+        self.jitter.max_us = self.jitter.max_us.max(other.jitter.max_us);
+
+        if !other.timestamp.is_empty() && (self.timestamp.is_empty() || other.timestamp < self.timestamp) {
+            self.timestamp = other.timestamp.clone();
+        }
+        self.duration_seconds = duration;
+        self.labels.retain(|key, value| other.labels.get(key) == Some(value));
+        if self.instance != other.instance {
+            self.instance = None;
+        }
+        if self.run_id != other.run_id {
+            self.run_id = None;
+        }
+        if self.clock != other.clock {
+            self.clock = None;
+        }
+        if self.host != other.host {
+            self.host = None;
+        }
+        if self.features != other.features {
+            self.features = None;
+        }
+        self.client = None;
+        self.envoy.clear();
+        self.mesh = None;
+        // Interval percentiles of two probes cannot be combined
+        if other_samples > 0 {
+            self.trajectory = if samples == 0 { other.trajectory.clone() } else { Vec::new() };
+        }
+
+        self.total_events += other.total_events;
+        self.lost_events += other.lost_events;
+        self.untracked_connection_events += other.untracked_connection_events;
+        self.untracked_process_events += other.untracked_process_events;
+        self.histogram.merge(&other.histogram);
+
+        for (key, connection) in &other.connections {
+            match self.connections.get_mut(key) {
+                Some(ours) => merge_connection(ours, connection),
+                None => {
+                    self.connections.insert(key.clone(), connection.clone());
+                }
+            }
+        }
+
+        self.tail = match (self.tail.take(), &other.tail) {
+            (Some(mut tail), Some(theirs)) => {
+                tail.threshold_us = tail.threshold_us.max(theirs.threshold_us);
+                tail.events += theirs.events;
+                tail.packet_drop += theirs.packet_drop;
+                tail.context_switch += theirs.context_switch;
+                tail.unexplained += theirs.unexplained;
+                Some(tail)
+            }
+            _ => None,
+        };
+
+        let types = &mut self.event_type_breakdown;
+        types.tcp_sendmsg += other.event_type_breakdown.tcp_sendmsg;
+        types.tcp_recvmsg += other.event_type_breakdown.tcp_recvmsg;
+        types.tcp_cleanup_rbuf += other.event_type_breakdown.tcp_cleanup_rbuf;
+        types.tcp_probe += other.event_type_breakdown.tcp_probe;
+        types.quic += other.event_type_breakdown.quic;
+
+        self.dns_latency.merge(&other.dns_latency);
+
+        let errors = &mut self.errors;
+        errors.resets_received += other.errors.resets_received;
+        errors.resets_sent += other.errors.resets_sent;
+        errors.timeouts += other.errors.timeouts;
+        errors.resets_per_second = per_second(errors.resets_received + errors.resets_sent, duration);
+        errors.timeouts_per_second = per_second(errors.timeouts, duration);
+        for (service, theirs) in &other.errors.services {
+            let ours = errors.services.entry(service.clone()).or_default();
+            ours.resets_received += theirs.resets_received;
+            ours.resets_sent += theirs.resets_sent;
+            ours.timeouts += theirs.timeouts;
+        }
+
+        let throughput = &mut self.throughput;
+        throughput.bytes_sent += other.throughput.bytes_sent;
+        throughput.bytes_received += other.throughput.bytes_received;
+        throughput.sent_bytes_per_second = per_second(throughput.bytes_sent, duration);
+        throughput.received_bytes_per_second = per_second(throughput.bytes_received, duration);
+
+        for (port, theirs) in &other.listeners {
+            let ours = self.listeners.entry(port.clone()).or_default();
+            ours.listeners += theirs.listeners;
+            ours.requests += theirs.requests;
+            ours.overflows += theirs.overflows;
+            ours.overflows_per_second = per_second(ours.overflows, duration);
+            ours.syn_queue_full += theirs.syn_queue_full;
+            ours.max_backlog = ours.max_backlog.max(theirs.max_backlog);
+            ours.backlog_limit = ours.backlog_limit.max(theirs.backlog_limit);
+        }
+
+        if self.event_rate.resolution_ms == other.event_rate.resolution_ms {
+            merge_series(&mut self.event_rate.events, &other.event_rate.events, |ours, theirs| *ours += theirs);
+        }
+        if self.heatmap.resolution_ms == other.heatmap.resolution_ms {
+            merge_series(&mut self.heatmap.intervals, &other.heatmap.intervals, LatencyHistogram::merge);
+        }
+
+        // Bursts stay relative to each report's own median rate
+        let bursts = &mut self.bursts;
+        bursts.bursts += other.bursts.bursts;
+        bursts.total_duration_ms += other.bursts.total_duration_ms;
+        bursts.max_duration_ms = bursts.max_duration_ms.max(other.bursts.max_duration_ms);
+        bursts.peak_rate = bursts.peak_rate.max(other.bursts.peak_rate);
+        bursts.events += other.bursts.events;
+
+        match (&mut self.coordinated_omission, &other.coordinated_omission) {
+            (Some(correction), Some(theirs)) => correction.synthetic_samples += theirs.synthetic_samples,
+            (None, Some(theirs)) => self.coordinated_omission = Some(theirs.clone()),
+            _ => {}
+        }
+
+        let drops = &mut self.packet_drops;
+        drops.total_drops += other.packet_drops.total_drops;
+        merge_counts(&mut drops.drops_by_location, &other.packet_drops.drops_by_location);
+        merge_counts(&mut drops.drops_by_protocol, &other.packet_drops.drops_by_protocol);
+        merge_counts(&mut drops.connections, &other.packet_drops.connections);
+
+        let states = &mut self.connection_states;
+        states.avg_duration_seconds = weighted(
+            states.avg_duration_seconds,
+            states.total_closed,
+            other.connection_states.avg_duration_seconds,
+            other.connection_states.total_closed,
+        );
+        states.total_opened += other.connection_states.total_opened;
+        states.total_closed += other.connection_states.total_closed;
+        states.active_connections += other.connection_states.active_connections;
+        merge_counts(&mut states.states_breakdown, &other.connection_states.states_breakdown);
+
+        let switches = &mut self.context_switches;
+        switches.total_switches += other.context_switches.total_switches;
+        switches.switches_per_second = per_second(switches.total_switches, duration);
+
+        // Bounds that differ between reports merge to the widest
+        match (&mut self.latency_bounds, &other.latency_bounds) {
+            (Some(bounds), Some(theirs)) => {
+                bounds.min_latency_ns = bounds.min_latency_ns.min(theirs.min_latency_ns);
+                bounds.max_latency_ns = bounds.max_latency_ns.max(theirs.max_latency_ns);
+                bounds.below_min += theirs.below_min;
+                bounds.above_max += theirs.above_max;
+            }
+            (None, Some(theirs)) => self.latency_bounds = Some(theirs.clone()),
+            _ => {}
+        }
+
+        // Reports deduplicated differently keep the first policy
+        let dedup = &mut self.dedup;
+        if dedup.policy.is_empty() {
+            dedup.policy = other.dedup.policy.clone();
+        }
+        dedup.duplicates += other.dedup.duplicates;
+        match (&mut dedup.cleanup, &other.dedup.cleanup) {
+            (Some(cleanup), Some(theirs)) => cleanup.merge(theirs),
+            (None, Some(theirs)) => dedup.cleanup = Some(theirs.clone()),
+            _ => {}
+        }
+
+        let xdp = &mut self.xdp_stats;
+        xdp.total_packets += other.xdp_stats.total_packets;
+        xdp.ipv4_packets += other.xdp_stats.ipv4_packets;
+        xdp.tcp_packets += other.xdp_stats.tcp_packets;
+        xdp.udp_packets += other.xdp_stats.udp_packets;
+        xdp.icmp_packets += other.xdp_stats.icmp_packets;
+        xdp.other_packets += other.xdp_stats.other_packets;
+        xdp.packets_per_second = per_second(xdp.total_packets, duration);
+
+        self.config_changes.extend(other.config_changes.iter().cloned());
+        self.fault_injections.extend(other.fault_injections.iter().cloned());
+
+        merge_groups(&mut self.namespaces, &other.namespaces);
+        merge_groups(&mut self.processes, &other.processes);
+        merge_groups(&mut self.pods, &other.pods);
+        merge_groups(&mut self.services, &other.services);
+        merge_groups(&mut self.protocols, &other.protocols);
+        merge_groups(&mut self.traffic_classes, &other.traffic_classes);
+        merge_groups(&mut self.zones, &other.zones);
+        merge_groups(&mut self.tenants, &other.tenants);
+        merge_groups(&mut self.phases, &other.phases);
+        merge_groups(&mut self.http_status, &other.http_status);
+
+        for probe in &other.probes {
+            if !self.probes.contains(probe) {
+                self.probes.push(probe.clone());
+            }
+        }
+        for stats in &other.program_stats {
+            match self.program_stats.iter_mut().find(|ours| ours.program == stats.program) {
+                Some(ours) => {
+                    *ours = ProgramStats::new(
+                        &stats.program,
+                        ours.run_time_ns + stats.run_time_ns,
+                        ours.run_count + stats.run_count,
+                    )
+                }
+                None => self.program_stats.push(stats.clone()),
+            }
+        }
+
+        // The fullest reading of each map
+        for map in &other.health.maps {
+            match self.health.maps.iter_mut().find(|ours| ours.map == map.map) {
+                Some(ours) if ours.fill_ratio < map.fill_ratio => *ours = map.clone(),
+                Some(_) => {}
+                None => self.health.maps.push(map.clone()),
+            }
+        }
+        let internal = &mut self.health.internal;
+        internal.invalid_sockets += other.health.internal.invalid_sockets;
+        internal.invalid_latency += other.health.internal.invalid_latency;
+        internal.dropped_events += other.health.internal.dropped_events;
+        if other.health.export.is_some() {
+            self.health.export = other.health.export.clone();
+        }
+        if let Some(channel) = &other.health.channel {
+            match &mut self.health.channel {
+                // Inputs of a merge cover separate runs or nodes
+                Some(ours) => {
+                    ours.dropped_events += channel.dropped_events;
+                    ours.blocked_waits += channel.blocked_waits;
+                    ours.max_depth = ours.max_depth.max(channel.max_depth);
+                }
+                None => self.health.channel = Some(channel.clone()),
+            }
+        }
+        self.health.event_loss_ratio = if self.lost_events > 0 {
+            self.lost_events as f64 / (self.total_events + self.lost_events) as f64
+        } else {
+            0.0
+        };
+    }
+
+    /// Merge the report of the interval that follows this one
+    ///
+    /// Unlike [`merge`](Self::merge), the intervals add up: rates are over
+    /// their combined duration, and the event rate and heatmap buckets of
+    /// `later` are laid after this report's instead of over them.
+    ///
+    /// # Arguments
+    ///
+    /// * `later` - Report of the following interval, from the same probe
+    pub fn merge_following(&mut self, later: &LatencyMetrics) {
+        let mut event_rate = std::mem::take(&mut self.event_rate);
+        let mut heatmap = std::mem::take(&mut self.heatmap);
+        let mut trajectory = std::mem::take(&mut self.trajectory);
+        let offset_secs = self.duration_seconds;
+        // The same sockets listen in both intervals
+        let listeners: BTreeMap<String, u64> =
+            self.listeners.iter().map(|(port, listener)| (port.clone(), listener.listeners)).collect();
+        let internal = self.health.internal.clone();
+        self.duration_seconds += later.duration_seconds;
+        self.merge(later);
+        // Kernel counters cover the run so far, so the later reading wins
+        self.health.internal.invalid_sockets = internal.invalid_sockets.max(later.health.internal.invalid_sockets);
+        self.health.internal.invalid_latency = internal.invalid_latency.max(later.health.internal.invalid_latency);
+        for (port, count) in listeners {
+            if let Some(listener) = self.listeners.get_mut(&port) {
+                listener.listeners = count.max(later.listeners.get(&port).map_or(0, |theirs| theirs.listeners));
+            }
+        }
+
+        trajectory.extend(later.trajectory.iter().map(|point| IntervalPercentiles {
+            end_secs: point.end_secs + offset_secs,
+            ..point.clone()
+        }));
+        self.trajectory = trajectory;
+
+        if event_rate.events.is_empty() {
+            event_rate = later.event_rate.clone();
+        } else if event_rate.resolution_ms == later.event_rate.resolution_ms {
+            let (start_ns, resolution_ms) = (event_rate.start_ns, event_rate.resolution_ms);
+            let series = &later.event_rate;
+            append_series(&mut event_rate.events, start_ns, resolution_ms, &series.events, series.start_ns, |ours, theirs| {
+                *ours += theirs
+            });
+        }
+        if heatmap.intervals.is_empty() {
+            heatmap = later.heatmap.clone();
+        } else if heatmap.resolution_ms == later.heatmap.resolution_ms {
+            let (start_ns, resolution_ms) = (heatmap.start_ns, heatmap.resolution_ms);
+            let series = &later.heatmap;
+            append_series(
+                &mut heatmap.intervals,
+                start_ns,
+                resolution_ms,
+                &series.intervals,
+                series.start_ns,
+                LatencyHistogram::merge,
+            );
+        }
+        self.event_rate = event_rate;
+        self.heatmap = heatmap;
+    }
+
+    /// Merge reports into one
+    ///
+    /// # Returns
+    ///
+    /// The merged report, or None if there are no reports
+    pub fn merged(reports: &[LatencyMetrics]) -> Option<LatencyMetrics> {
+        let (first, rest) = reports.split_first()?;
+        let mut merged = first.clone();
+        for report in rest {
+            merged.merge(report);
+        }
+        Some(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::collector::MetricsCollector;
+
+    #[test]
+    fn test_merge_reports() {
+        let event = |saddr: u32, i: u64, latency_ns: u64| LatencyEvent {
+            timestamp_ns: i * 1_000_000,
+            pid: 1234,
+            ..testing::event(
+                ConnectionKey {
+                    saddr,
+                    daddr: 0x0200000a,
+                    sport: 0x5000,
+                    dport: 0x901f,
+                },
+                latency_ns,
+                kernel::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        let mut node_a = MetricsCollector::new();
+        let mut node_b = MetricsCollector::new();
+        let mut all = Vec::new();
+        for i in 1..=1000u64 {
+            node_a.add_event(&event(0x0100000a, i, i * 1_000));
+            node_b.add_event(&event(0x0300000a, i, i * 5_000));
+            all.extend([i as f64, i as f64 * 5.0]);
+        }
+
+        let mut a = node_a.generate_metrics(10);
+        a.labels.insert("mesh".to_string(), "istio".to_string());
+        a.labels.insert("node".to_string(), "a".to_string());
+        let mut b = node_b.generate_metrics(12);
+        b.labels.insert("mesh".to_string(), "istio".to_string());
+        b.labels.insert("node".to_string(), "b".to_string());
+        let bounds = |min_latency_ns: u64, below_min: u64| LatencyBounds {
+            min_latency_ns,
+            max_latency_ns: 60_000_000_000,
+            below_min,
+            above_max: 1,
+        };
+        a.latency_bounds = Some(bounds(1_000, 5));
+        b.latency_bounds = Some(bounds(200, 2));
+
+        let merged = LatencyMetrics::merged(&[a.clone(), b.clone()]).unwrap();
+        assert_eq!(merged.total_events, 2000);
+        assert_eq!(merged.latency_bounds, Some(LatencyBounds { above_max: 2, ..bounds(200, 7) }));
+        assert_eq!(merged.duration_seconds, 12);
+        assert_eq!(merged.histogram.total_count(), 2000);
+        assert_eq!(merged.histogram.bucket_0_1ms, a.histogram.bucket_0_1ms + b.histogram.bucket_0_1ms);
+        assert_eq!(merged.connections.len(), 2);
+        assert_eq!(merged.labels.len(), 1);
+        assert_eq!(merged.labels["mesh"], "istio");
+
+        // Percentiles from the merged digest match those of all samples
+        let exact = calculate_percentiles(all);
+        for (exact, estimated) in [(exact.p50, merged.percentiles.p50), (exact.p99, merged.percentiles.p99)] {
+            assert!((estimated - exact).abs() <= exact * 0.02, "{} vs {}", estimated, exact);
+        }
+        assert_eq!(merged.digest.as_ref().unwrap().count(), 2000);
+
+        // Groups both reports have are combined
+        let protocol = &merged.protocols["tcp"];
+        assert_eq!(protocol.events, 2000);
+        let expected = (a.protocols["tcp"].avg_latency_us + b.protocols["tcp"].avg_latency_us) / 2.0;
+        assert!((protocol.avg_latency_us - expected).abs() < 1e-6);
+        assert_eq!(protocol.digest.as_ref().unwrap().count(), 2000);
+        assert!((protocol.percentiles.p99 - exact.p99).abs() <= exact.p99 * 0.02);
+
+        // A connection both reports saw keeps accurate percentiles
+        let mut node_c = MetricsCollector::new();
+        for i in 1..=1000u64 {
+            node_c.add_event(&event(0x0100000a, i, i * 5_000));
+        }
+        let mut same = a.clone();
+        same.merge(&node_c.generate_metrics(10));
+        let connection = same.connections.values().next().unwrap();
+        assert_eq!(connection.events, 2000);
+        assert_eq!(connection.digest.as_ref().unwrap().count(), 2000);
+        assert!((connection.p99_us - exact.p99).abs() <= exact.p99 * 0.02, "{} vs {}", connection.p99_us, exact.p99);
+
+        // Without digests, percentiles are weighted by sample counts
+        a.digest = None;
+        let mut approximate = a.clone();
+        approximate.merge(&b);
+        assert!(approximate.digest.is_none());
+        assert_eq!(approximate.percentiles.p50, (a.percentiles.p50 + b.percentiles.p50) / 2.0);
+
+        // Kernel discards of separate probes add up; a later interval's
+        // reading of the same probe replaces the earlier one
+        a.health.internal.invalid_sockets = 2;
+        b.health.internal.invalid_sockets = 3;
+        let mut nodes = a.clone();
+        nodes.merge(&b);
+        assert_eq!(nodes.health.internal.invalid_sockets, 5);
+        let mut intervals = a.clone();
+        intervals.merge_following(&b);
+        assert_eq!(intervals.health.internal.invalid_sockets, 3);
+
+        assert!(LatencyMetrics::merged(&[]).is_none());
+    }
+}
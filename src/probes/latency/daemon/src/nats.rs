@@ -0,0 +1,123 @@
+//! NATS JetStream publishing
+//!
+//! With the `nats` feature, interval snapshots and the final report are
+//! published as JSON to JetStream subjects, for orchestrators that collect
+//! results from many nodes. Each publish waits for the stream's
+//! acknowledgement; unacknowledged publishes fail and are retried by the
+//! publisher's policy (see crate::publish), at-least-once. A `Nats-Msg-Id`
+//! header lets the stream drop the duplicates a retry can produce, within
+//! its deduplication window.
+//!
+//! The subjects must be bound to a stream, e.g.:
+//!
+//! ```bash
+//! nats stream add LATENCY --subjects 'latency-probe.>'
+//! ```
+
+use crate::{
+    publish::{AsyncMetricsExporter, ExportFuture},
+    types::LatencyMetrics,
+};
+use anyhow::{Context, Result};
+use async_nats::jetstream::{self, context::Publish};
+use bytes::Bytes;
+
+/// Subjects reports are published to
+#[derive(Debug, Clone)]
+pub struct NatsSubjects {
+    /// Subject for periodic interval snapshots
+    pub snapshot: String,
+    /// Subject for the final report (and rotated reports)
+    pub report: String,
+}
+
+/// Publishes reports to NATS JetStream
+pub struct NatsPublisher {
+    context: jetstream::Context,
+    subjects: NatsSubjects,
+}
+
+impl NatsPublisher {
+    /// Connect to a NATS server
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Server URL (e.g. nats://nats.benchmark:4222)
+    /// * `subjects` - Subjects to publish to
+    pub async fn connect(url: &str, subjects: NatsSubjects) -> Result<Self> {
+        let client = async_nats::connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to NATS at {}", url))?;
+
+        Ok(Self {
+            context: jetstream::new(client),
+            subjects,
+        })
+    }
+
+    /// Publish an interval snapshot
+    pub async fn publish_snapshot(&self, metrics: &LatencyMetrics) -> Result<()> {
+        self.publish(&self.subjects.snapshot, metrics).await
+    }
+
+    /// Publish a final or rotated report
+    pub async fn publish_report(&self, metrics: &LatencyMetrics) -> Result<()> {
+        self.publish(&self.subjects.report, metrics).await
+    }
+
+    /// Publish a report and wait for JetStream to acknowledge it
+    async fn publish(&self, subject: &str, metrics: &LatencyMetrics) -> Result<()> {
+        let payload = Bytes::from(serde_json::to_vec(metrics)?);
+        let publish = Publish::build().payload(payload).message_id(message_id(subject, metrics));
+        self.publish_acked(subject, publish)
+            .await
+            .with_context(|| format!("Failed to publish to {}", subject))
+    }
+
+    /// Publish one message and wait for its acknowledgement
+    async fn publish_acked(&self, subject: &str, publish: Publish) -> Result<()> {
+        self.context
+            .send_publish(subject.to_string(), publish)
+            .await?
+            .await?;
+        Ok(())
+    }
+}
+
+impl AsyncMetricsExporter for NatsPublisher {
+    fn export<'a>(&'a self, metrics: &'a LatencyMetrics) -> ExportFuture<'a> {
+        Box::pin(self.publish_report(metrics))
+    }
+
+    fn export_snapshot<'a>(&'a self, metrics: &'a LatencyMetrics) -> ExportFuture<'a> {
+        Box::pin(self.publish_snapshot(metrics))
+    }
+}
+
+/// Deduplication ID of a report: the same report published twice (by a
+/// retry) gets the same ID
+fn message_id(subject: &str, metrics: &LatencyMetrics) -> String {
+    format!("{}/{}/{}", subject, std::process::id(), metrics.timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_id() {
+        let snapshot = LatencyMetrics {
+            timestamp: "2025-01-01T00:00:10+00:00".to_string(),
+            ..Default::default()
+        };
+        let next = LatencyMetrics {
+            timestamp: "2025-01-01T00:00:20+00:00".to_string(),
+            ..Default::default()
+        };
+
+        // Retries reuse the ID; other reports and subjects do not
+        assert_eq!(message_id("s", &snapshot), message_id("s", &snapshot.clone()));
+        assert_ne!(message_id("s", &snapshot), message_id("s", &next));
+        assert_ne!(message_id("s", &snapshot), message_id("r", &snapshot));
+    }
+}
@@ -0,0 +1,111 @@
+//! Network namespace identification
+//!
+//! Events carry the inode of the socket's network namespace, read in the
+//! kernel using struct offsets resolved here from BTF. When the offsets are
+//! unavailable, userspace falls back to the namespace of the process that
+//! triggered the event (`/proc/<pid>/ns/net`).
+
+use crate::btf::Btf;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Maximum number of cached PID to namespace lookups
+const RESOLVER_CACHE_SIZE: usize = 4096;
+
+/// Kernel struct offsets needed to read a socket's namespace inode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetnsOffsets {
+    /// Offset of `skc_net` in `struct sock_common` (and `struct sock`)
+    pub skc_net: u32,
+    /// Offset of `ns.inum` in `struct net`
+    pub inum: u32,
+}
+
+impl NetnsOffsets {
+    /// Resolve the offsets from kernel BTF
+    pub fn from_btf(btf: &Btf) -> Result<Self> {
+        Ok(Self {
+            skc_net: btf.member_offset("sock_common", "skc_net")?,
+            inum: btf.member_offset("net", "ns")? + btf.member_offset("ns_common", "inum")?,
+        })
+    }
+}
+
+/// Parse a namespace link target such as `net:[4026531840]`
+///
+/// # Returns
+///
+/// The namespace inode, or None if the target is not a network namespace
+pub fn parse_ns_link(target: &str) -> Option<u32> {
+    target
+        .strip_prefix("net:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// Network namespace inode of a process
+///
+/// # Arguments
+///
+/// * `pid` - Process ID
+///
+/// # Returns
+///
+/// The namespace inode, or None if the process is gone or not accessible
+pub fn netns_inode(pid: u32) -> Option<u32> {
+    let target = std::fs::read_link(format!("/proc/{}/ns/net", pid)).ok()?;
+    parse_ns_link(&target.to_string_lossy())
+}
+
+/// Caching PID to network namespace resolver
+///
+/// Used for events the kernel could not attribute to a namespace.
+#[derive(Default)]
+pub struct NetnsResolver {
+    cache: HashMap<u32, u32>,
+}
+
+impl NetnsResolver {
+    /// Create an empty resolver
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Namespace inode for `pid` (0 if it cannot be determined)
+    pub fn resolve(&mut self, pid: u32) -> u32 {
+        if let Some(&inode) = self.cache.get(&pid) {
+            return inode;
+        }
+
+        // PIDs are reused over long runs; start over rather than track age
+        if self.cache.len() >= RESOLVER_CACHE_SIZE {
+            self.cache.clear();
+        }
+
+        let inode = netns_inode(pid).unwrap_or(0);
+        self.cache.insert(pid, inode);
+        inode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ns_link() {
+        assert_eq!(parse_ns_link("net:[4026531840]"), Some(4026531840));
+        assert_eq!(parse_ns_link("mnt:[4026531841]"), None);
+        assert_eq!(parse_ns_link("net:[abc]"), None);
+    }
+
+    #[test]
+    fn test_resolve_own_namespace() {
+        let mut resolver = NetnsResolver::new();
+        let inode = resolver.resolve(std::process::id());
+
+        assert_eq!(Some(inode), netns_inode(std::process::id()));
+        assert_eq!(resolver.resolve(u32::MAX), 0);
+    }
+}
@@ -0,0 +1,363 @@
+//! Webhook notifications
+//!
+//! With the `webhook` feature, a summary is POSTed to a webhook (Slack,
+//! Teams, or any endpoint accepting JSON) when a run ends, and when the p99
+//! latency of consecutive check intervals exceeds an SLO threshold, so
+//! long runs do not have to be watched.
+
+use crate::{
+    collector::MetricsCollector,
+    run::RunId,
+    types::{LatencyMetrics, Percentiles},
+};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::Mutex,
+    time::{interval_at, Instant},
+};
+
+/// Timeout of a webhook request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Payload format expected by the webhook
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum WebhookFormat {
+    /// The notification as a JSON object
+    #[default]
+    Generic,
+    /// Slack incoming webhook ({"text": ...})
+    Slack,
+    /// Microsoft Teams incoming webhook ({"text": ...})
+    Teams,
+}
+
+/// Latency objective that notifications report against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SloPolicy {
+    /// Highest acceptable p99 latency in microseconds
+    pub p99_threshold_us: f64,
+    /// Consecutive breaching intervals before an alert is sent
+    pub consecutive_intervals: u32,
+}
+
+/// Counts consecutive check intervals whose p99 exceeds the SLO
+///
+/// One alert is raised per breach: after alerting, the tracker waits for an
+/// interval within the SLO before it can alert again.
+#[derive(Debug)]
+pub struct BreachTracker {
+    policy: SloPolicy,
+    consecutive: u32,
+    alerted: bool,
+}
+
+impl BreachTracker {
+    /// Create a tracker for an SLO
+    pub fn new(policy: SloPolicy) -> Self {
+        Self {
+            policy,
+            consecutive: 0,
+            alerted: false,
+        }
+    }
+
+    /// Account for one check interval
+    ///
+    /// # Arguments
+    ///
+    /// * `percentiles` - Latency percentiles of the interval
+    /// * `samples` - Samples in the interval (intervals without samples
+    ///   end a breach)
+    ///
+    /// # Returns
+    ///
+    /// The number of consecutive breaching intervals, when an alert is due
+    pub fn observe(&mut self, percentiles: &Percentiles, samples: usize) -> Option<u32> {
+        if samples == 0 || percentiles.p99 <= self.policy.p99_threshold_us {
+            self.consecutive = 0;
+            self.alerted = false;
+            return None;
+        }
+
+        self.consecutive += 1;
+        if self.alerted || self.consecutive < self.policy.consecutive_intervals {
+            return None;
+        }
+        self.alerted = true;
+        Some(self.consecutive)
+    }
+}
+
+/// Why a notification was sent
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// The run ended
+    RunComplete,
+    /// Live p99 exceeded the SLO for consecutive intervals
+    SloBreach,
+}
+
+/// Summary sent to the webhook
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    /// Why the notification was sent
+    pub kind: NotificationKind,
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    /// Events covered (whole run, or the breaching interval)
+    pub total_events: u64,
+    /// Median latency in microseconds
+    pub p50_us: f64,
+    /// 99th percentile latency in microseconds
+    pub p99_us: f64,
+    /// SLO p99 threshold in microseconds (None if no SLO is set)
+    pub slo_p99_us: Option<f64>,
+    /// Whether p99 met the SLO (None if no SLO is set)
+    pub slo_passed: Option<bool>,
+    /// Consecutive breaching intervals (SLO breaches only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consecutive_breaches: Option<u32>,
+    /// ID of the run (see crate::run)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+impl Notification {
+    /// Notification for a finished run
+    pub fn run_complete(metrics: &LatencyMetrics, slo: Option<&SloPolicy>) -> Self {
+        Self {
+            kind: NotificationKind::RunComplete,
+            timestamp: metrics.timestamp.clone(),
+            total_events: metrics.total_events,
+            p50_us: metrics.percentiles.p50,
+            p99_us: metrics.percentiles.p99,
+            slo_p99_us: slo.map(|slo| slo.p99_threshold_us),
+            slo_passed: slo.map(|slo| metrics.percentiles.p99 <= slo.p99_threshold_us),
+            consecutive_breaches: None,
+            run_id: metrics.run_id.clone(),
+        }
+    }
+
+    /// Notification for an SLO breach during the run
+    ///
+    /// # Arguments
+    ///
+    /// * `percentiles` - Latency percentiles of the last interval
+    /// * `samples` - Samples in the last interval
+    /// * `slo` - The breached SLO
+    /// * `consecutive` - Consecutive breaching intervals
+    pub fn slo_breach(
+        percentiles: &Percentiles,
+        samples: usize,
+        slo: &SloPolicy,
+        consecutive: u32,
+    ) -> Self {
+        Self {
+            kind: NotificationKind::SloBreach,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            total_events: samples as u64,
+            p50_us: percentiles.p50,
+            p99_us: percentiles.p99,
+            slo_p99_us: Some(slo.p99_threshold_us),
+            slo_passed: Some(false),
+            consecutive_breaches: Some(consecutive),
+            run_id: None,
+        }
+    }
+
+    /// One-line summary for chat webhooks
+    pub fn text(&self) -> String {
+        let mut text = match self.kind {
+            NotificationKind::RunComplete => format!(
+                "Latency probe run complete: {} events, p50 {:.1}us, p99 {:.1}us",
+                self.total_events, self.p50_us, self.p99_us
+            ),
+            NotificationKind::SloBreach => format!(
+                "Latency SLO breached for {} consecutive intervals: p99 {:.1}us \
+                 (p50 {:.1}us, {} events in the last interval)",
+                self.consecutive_breaches.unwrap_or_default(),
+                self.p99_us,
+                self.p50_us,
+                self.total_events
+            ),
+        };
+
+        if let (Some(threshold), Some(passed)) = (self.slo_p99_us, self.slo_passed) {
+            let verdict = if passed { "PASS" } else { "FAIL" };
+            text.push_str(&format!(" | SLO p99 <= {:.1}us: {}", threshold, verdict));
+        }
+        if let Some(run_id) = &self.run_id {
+            text.push_str(&format!(" | run {}", run_id));
+        }
+        text
+    }
+
+    /// Request body for a webhook format
+    pub fn body(&self, format: WebhookFormat) -> serde_json::Value {
+        match format {
+            WebhookFormat::Generic => serde_json::to_value(self).unwrap_or_default(),
+            WebhookFormat::Slack | WebhookFormat::Teams => serde_json::json!({ "text": self.text() }),
+        }
+    }
+}
+
+/// Sends notifications to a webhook
+pub struct Notifier {
+    client: reqwest::Client,
+    url: String,
+    format: WebhookFormat,
+    slo: Option<SloPolicy>,
+}
+
+impl Notifier {
+    /// Create a notifier
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Webhook URL
+    /// * `format` - Payload format the webhook expects
+    /// * `slo` - SLO to report against and alert on (None = completion only)
+    pub fn new(url: String, format: WebhookFormat, slo: Option<SloPolicy>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            client,
+            url,
+            format,
+            slo,
+        })
+    }
+
+    /// SLO that breaches are tracked against, if any
+    pub fn slo(&self) -> Option<&SloPolicy> {
+        self.slo.as_ref()
+    }
+
+    /// POST a notification to the webhook
+    pub async fn send(&self, notification: &Notification) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .json(&notification.body(self.format))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to send webhook notification")?;
+
+        Ok(())
+    }
+}
+
+/// Check the p99 of each interval against the notifier's SLO, and notify
+/// the webhook when it is breached
+///
+/// Does nothing if the notifier has no SLO.
+///
+/// # Arguments
+///
+/// * `notifier` - Webhook to alert
+/// * `collector` - Collector whose new samples make up each interval
+/// * `interval_secs` - Length of the check intervals in seconds
+pub fn spawn_slo_monitor(
+    notifier: Arc<Notifier>,
+    collector: Arc<Mutex<MetricsCollector>>,
+    interval_secs: u64,
+) {
+    let Some(slo) = notifier.slo().copied() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let period = Duration::from_secs(interval_secs.max(1));
+        let mut ticker = interval_at(Instant::now() + period, period);
+        let mut tracker = BreachTracker::new(slo);
+        let mut mark = collector.lock().await.sample_count();
+
+        loop {
+            ticker.tick().await;
+
+            let (percentiles, samples, run_id) = {
+                let collector = collector.lock().await;
+                let count = collector.sample_count();
+                // A rotation empties the collector; start over from zero
+                let start = if count < mark { 0 } else { mark };
+                mark = count;
+                (
+                    collector.percentiles_since(start),
+                    count - start,
+                    collector.run_id().map(RunId::to_string),
+                )
+            };
+
+            if let Some(consecutive) = tracker.observe(&percentiles, samples) {
+                info!("⚠ p99 {:.1}us exceeded the SLO for {} intervals", percentiles.p99, consecutive);
+                let mut notification = Notification::slo_breach(&percentiles, samples, &slo, consecutive);
+                notification.run_id = run_id;
+                if let Err(e) = notifier.send(&notification).await {
+                    warn!("{:#}", e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slo_breach_tracking() {
+        let slo = SloPolicy {
+            p99_threshold_us: 1000.0,
+            consecutive_intervals: 2,
+        };
+        let slow = Percentiles {
+            p50: 400.0,
+            p99: 1500.0,
+            ..Default::default()
+        };
+        let fast = Percentiles {
+            p99: 800.0,
+            ..Default::default()
+        };
+
+        let mut tracker = BreachTracker::new(slo);
+        assert_eq!(tracker.observe(&slow, 10), None);
+        assert_eq!(tracker.observe(&fast, 10), None);
+        assert_eq!(tracker.observe(&slow, 10), None);
+        assert_eq!(tracker.observe(&slow, 10), Some(2));
+        // One alert per breach, until an interval meets the SLO again
+        assert_eq!(tracker.observe(&slow, 10), None);
+        assert_eq!(tracker.observe(&slow, 0), None);
+        assert_eq!(tracker.observe(&slow, 10), None);
+        assert_eq!(tracker.observe(&slow, 10), Some(2));
+
+        let breach = Notification::slo_breach(&slow, 10, &slo, 2);
+        assert_eq!(
+            breach.text(),
+            "Latency SLO breached for 2 consecutive intervals: p99 1500.0us (p50 400.0us, 10 events in the last interval) | SLO p99 <= 1000.0us: FAIL"
+        );
+        assert_eq!(breach.body(WebhookFormat::Slack)["text"], breach.text());
+
+        let mut metrics = LatencyMetrics {
+            total_events: 100,
+            run_id: Some("nightly-42".to_string()),
+            ..Default::default()
+        };
+        metrics.percentiles.p99 = 900.0;
+        let complete = Notification::run_complete(&metrics, Some(&slo));
+        assert_eq!(complete.slo_passed, Some(true));
+        assert!(complete.text().ends_with("| SLO p99 <= 1000.0us: PASS | run nightly-42"));
+        let body = complete.body(WebhookFormat::Generic);
+        assert_eq!(body["kind"], "run_complete");
+        assert_eq!(body["total_events"], 100);
+        assert_eq!(body["run_id"], "nightly-42");
+        assert!(body.get("consecutive_breaches").is_none());
+    }
+}
@@ -0,0 +1,409 @@
+//! Multi-object probe layout
+//!
+//! The probe can be split across several eBPF objects (latency, drops,
+//! connection state, or user-supplied programs) listed in a manifest or
+//! found in a directory. Program and map names that appear in more than one
+//! object are qualified with the object name (e.g. `drops/CONFIG`), so every
+//! program and map has a unique handle.
+//!
+//! ## Example manifest
+//!
+//! ```yaml
+//! objects:
+//!   - name: latency
+//!     path: latency-probe
+//!   - name: retransmits
+//!     path: /opt/probes/retransmits.o
+//!     kind: custom
+//!     attach:
+//!       trace_retransmit: kprobe:tcp_retransmit_skb
+//!       trace_rst: tracepoint:tcp:tcp_send_reset
+//! ```
+//!
+//! Relative paths are resolved against the manifest's directory.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// ELF magic, used to pick eBPF objects out of a directory
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Role of an eBPF object
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObjectKind {
+    /// Latency kprobes and the EVENTS map (required)
+    Latency,
+    /// Packet drop tracking
+    Drops,
+    /// Connection state tracking
+    ConnState,
+    /// User-supplied programs
+    Custom,
+}
+
+impl ObjectKind {
+    /// Infer the kind from an object or file name
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_ascii_lowercase().replace('_', "-");
+        if name.starts_with("latency") {
+            ObjectKind::Latency
+        } else if name.starts_with("drop") {
+            ObjectKind::Drops
+        } else if name.starts_with("conn-state") {
+            ObjectKind::ConnState
+        } else {
+            ObjectKind::Custom
+        }
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectKind::Latency => write!(f, "latency"),
+            ObjectKind::Drops => write!(f, "drops"),
+            ObjectKind::ConnState => write!(f, "conn-state"),
+            ObjectKind::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// Where a program from an additional object attaches
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum AttachTarget {
+    /// Kernel function (`kprobe:<function>`)
+    KProbe(String),
+    /// Tracepoint (`tracepoint:<category>:<name>`)
+    TracePoint(String, String),
+}
+
+impl FromStr for AttachTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split(':').collect::<Vec<_>>().as_slice() {
+            ["kprobe", function] if !function.is_empty() => {
+                Ok(AttachTarget::KProbe(function.to_string()))
+            }
+            ["tracepoint", category, name] if !category.is_empty() && !name.is_empty() => Ok(
+                AttachTarget::TracePoint(category.to_string(), name.to_string()),
+            ),
+            _ => anyhow::bail!(
+                "Invalid attach target '{}' (expected kprobe:FUNCTION or tracepoint:CATEGORY:NAME)",
+                s
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for AttachTarget {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<AttachTarget> for String {
+    fn from(target: AttachTarget) -> Self {
+        target.to_string()
+    }
+}
+
+impl fmt::Display for AttachTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttachTarget::KProbe(function) => write!(f, "kprobe:{}", function),
+            AttachTarget::TracePoint(category, name) => {
+                write!(f, "tracepoint:{}:{}", category, name)
+            }
+        }
+    }
+}
+
+/// One eBPF object in a manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSpec {
+    /// Unique object name, used to qualify colliding program and map names
+    pub name: String,
+    /// Path to the object file
+    pub path: PathBuf,
+    /// Object role (inferred from the name if omitted)
+    #[serde(default)]
+    pub kind: Option<ObjectKind>,
+    /// Attach targets by program name. Kprobe programs without an entry
+    /// attach to the kernel function of the same name.
+    #[serde(default)]
+    pub attach: BTreeMap<String, AttachTarget>,
+}
+
+impl ObjectSpec {
+    /// Object role, explicit or inferred from the name
+    pub fn kind(&self) -> ObjectKind {
+        self.kind
+            .unwrap_or_else(|| ObjectKind::from_name(&self.name))
+    }
+}
+
+/// Set of eBPF objects making up the probe
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectManifest {
+    /// Objects in load order
+    pub objects: Vec<ObjectSpec>,
+}
+
+impl ObjectManifest {
+    /// Load a manifest from a YAML (or JSON) file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read object manifest: {:?}", path))?;
+        let mut manifest: Self = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse object manifest: {:?}", path))?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        for object in &mut manifest.objects {
+            if object.path.is_relative() {
+                object.path = base.join(&object.path);
+            }
+        }
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Build a manifest from every ELF file in a directory
+    ///
+    /// Objects are named after their file stem and loaded in name order.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory containing eBPF object files
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read eBPF object directory: {:?}", dir))?;
+
+        let mut objects = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_file() || !is_elf(&path) {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            objects.push(ObjectSpec {
+                name,
+                path,
+                kind: None,
+                attach: BTreeMap::new(),
+            });
+        }
+        objects.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let manifest = Self { objects };
+        manifest
+            .validate()
+            .with_context(|| format!("No usable eBPF objects in {:?}", dir))?;
+        Ok(manifest)
+    }
+
+    /// Check that the manifest describes a usable probe
+    pub fn validate(&self) -> Result<()> {
+        if self.objects.is_empty() {
+            anyhow::bail!("Object manifest is empty");
+        }
+
+        let mut names = HashSet::new();
+        for object in &self.objects {
+            if object.name.is_empty() || object.name.contains('/') {
+                anyhow::bail!("Invalid object name '{}'", object.name);
+            }
+            if !names.insert(object.name.as_str()) {
+                anyhow::bail!("Duplicate object name '{}'", object.name);
+            }
+        }
+
+        if !self.objects.iter().any(|o| o.kind() == ObjectKind::Latency) {
+            anyhow::bail!("Object manifest has no latency object");
+        }
+        Ok(())
+    }
+}
+
+/// Index of a loaded object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub usize);
+
+/// Program within a loaded object
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProgramHandle {
+    /// Object the program belongs to
+    pub object: ObjectId,
+    /// Name inside the object
+    pub name: String,
+    /// Name unique across all objects
+    pub qualified: String,
+}
+
+/// Map within a loaded object
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MapHandle {
+    /// Object the map belongs to
+    pub object: ObjectId,
+    /// Name inside the object
+    pub name: String,
+    /// Name unique across all objects
+    pub qualified: String,
+}
+
+/// Programs and maps of one loaded object
+#[derive(Debug, Clone)]
+pub struct ObjectHandle {
+    /// Object index
+    pub id: ObjectId,
+    /// Object name from the manifest
+    pub name: String,
+    /// Object role
+    pub kind: ObjectKind,
+    /// Programs, sorted by name
+    pub programs: Vec<ProgramHandle>,
+    /// Maps, sorted by name
+    pub maps: Vec<MapHandle>,
+}
+
+/// Make names unique across objects
+///
+/// Names that occur in exactly one object are kept as is; names shared by
+/// several objects become `<object>/<name>` in every object.
+///
+/// # Arguments
+///
+/// * `objects` - Object names with the names they contain
+///
+/// # Returns
+///
+/// Qualified names, in the same order as the input
+pub fn qualify_names(objects: &[(&str, Vec<String>)]) -> Vec<Vec<String>> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (_, names) in objects {
+        for name in names {
+            *counts.entry(name.as_str()).or_default() += 1;
+        }
+    }
+
+    objects
+        .iter()
+        .map(|(object, names)| {
+            names
+                .iter()
+                .map(|name| {
+                    if counts[name.as_str()] > 1 {
+                        format!("{}/{}", object, name)
+                    } else {
+                        name.clone()
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn is_elf(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map(|_| magic == ELF_MAGIC)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qualify_names() {
+        let objects = [
+            ("latency", vec!["CONFIG".to_string(), "EVENTS".to_string()]),
+            (
+                "drops",
+                vec!["CONFIG".to_string(), "PACKET_DROPS".to_string()],
+            ),
+        ];
+
+        let qualified = qualify_names(&objects);
+        assert_eq!(qualified[0], ["latency/CONFIG", "EVENTS"]);
+        assert_eq!(qualified[1], ["drops/CONFIG", "PACKET_DROPS"]);
+    }
+
+    #[test]
+    fn test_manifest_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("probes.yaml");
+        std::fs::write(
+            &path,
+            "objects:\n\
+             - name: latency\n  path: latency-probe\n\
+             - name: retransmits\n  path: /opt/retransmits.o\n  attach:\n    trace_rst: tracepoint:tcp:tcp_send_reset\n",
+        )
+        .unwrap();
+
+        let manifest = ObjectManifest::load(&path).unwrap();
+        assert_eq!(manifest.objects[0].path, dir.path().join("latency-probe"));
+        assert_eq!(manifest.objects[0].kind(), ObjectKind::Latency);
+        assert_eq!(manifest.objects[1].kind(), ObjectKind::Custom);
+        assert_eq!(
+            manifest.objects[1].attach["trace_rst"],
+            AttachTarget::TracePoint("tcp".into(), "tcp_send_reset".into())
+        );
+
+        std::fs::write(&path, "objects:\n- name: drops\n  path: drops.o\n").unwrap();
+        assert!(ObjectManifest::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("latency-probe"), b"\x7fELF....").unwrap();
+        std::fs::write(dir.path().join("conn_state.o"), b"\x7fELF....").unwrap();
+        std::fs::write(dir.path().join("README.md"), b"not an object").unwrap();
+
+        let manifest = ObjectManifest::from_dir(dir.path()).unwrap();
+        let kinds: Vec<_> = manifest
+            .objects
+            .iter()
+            .map(|o| (o.name.as_str(), o.kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("conn_state", ObjectKind::ConnState),
+                ("latency-probe", ObjectKind::Latency)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attach_target_parse() {
+        assert_eq!(
+            "kprobe:tcp_retransmit_skb".parse::<AttachTarget>().unwrap(),
+            AttachTarget::KProbe("tcp_retransmit_skb".into())
+        );
+        assert!("kprobe:".parse::<AttachTarget>().is_err());
+        assert!("uprobe:foo".parse::<AttachTarget>().is_err());
+    }
+}
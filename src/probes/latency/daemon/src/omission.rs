@@ -0,0 +1,81 @@
+//! Coordinated omission correction
+//!
+//! A closed-loop load generator waits for each response before sending the
+//! next request, so a slow response also delays the requests that should
+//! have been sent while it was outstanding. Those requests are never
+//! measured, and the latency distribution looks better than what users at
+//! a fixed request rate would see.
+//!
+//! Given the interval at which requests were meant to be sent, each sample
+//! longer than the interval is backfilled with the samples the missing
+//! requests would have recorded (HdrHistogram's `recordValueWithExpectedInterval`):
+//! `L - I`, `L - 2I`, ... down to `I`.
+
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+/// Synthetic samples added for a single measured sample at most
+pub const MAX_BACKFILL: usize = 10_000;
+
+/// Parse an interval with a unit suffix (`ns`, `us`, `ms` or `s`), e.g. `1ms`
+pub fn parse_interval(spec: &str) -> Result<Duration> {
+    let spec = spec.trim();
+    let split = spec
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .with_context(|| format!("Missing unit in interval '{}' (ns, us, ms, or s)", spec))?;
+    let (value, unit) = spec.split_at(split);
+    let value: f64 = value
+        .parse()
+        .with_context(|| format!("Invalid interval: {}", spec))?;
+
+    let nanos = match unit {
+        "ns" => value,
+        "us" => value * 1e3,
+        "ms" => value * 1e6,
+        "s" => value * 1e9,
+        _ => anyhow::bail!("Unknown unit '{}' in interval '{}' (ns, us, ms, or s)", unit, spec),
+    };
+    if nanos < 1.0 {
+        anyhow::bail!("Interval must be at least 1ns: {}", spec);
+    }
+
+    Ok(Duration::from_nanos(nanos as u64))
+}
+
+/// Samples the requests omitted behind a slow one would have recorded
+///
+/// # Arguments
+///
+/// * `latency_us` - Measured latency in microseconds
+/// * `expected_interval_us` - Interval between requests in microseconds
+///
+/// # Returns
+///
+/// `latency - interval`, `latency - 2 * interval`, ... while at least one
+/// interval, capped at MAX_BACKFILL samples
+pub fn backfill(latency_us: f64, expected_interval_us: f64) -> impl Iterator<Item = f64> {
+    (1..=MAX_BACKFILL)
+        .map(move |i| latency_us - i as f64 * expected_interval_us)
+        .take_while(move |&missing| missing >= expected_interval_us)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backfill() {
+        assert_eq!(parse_interval("1ms").unwrap(), Duration::from_millis(1));
+        assert_eq!(parse_interval("250us").unwrap(), Duration::from_micros(250));
+        assert_eq!(parse_interval("0.5s").unwrap(), Duration::from_millis(500));
+        assert!(parse_interval("1").is_err());
+        assert!(parse_interval("1m").is_err());
+        assert!(parse_interval("0ms").is_err());
+
+        // A 4.5ms response at a 1ms interval hid requests that would have
+        // waited 3.5, 2.5 and 1.5ms
+        assert_eq!(backfill(4500.0, 1000.0).collect::<Vec<_>>(), vec![3500.0, 2500.0, 1500.0]);
+        assert_eq!(backfill(900.0, 1000.0).count(), 0);
+        assert_eq!(backfill(60_000_000.0, 1.0).count(), MAX_BACKFILL);
+    }
+}
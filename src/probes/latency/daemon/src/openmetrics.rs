@@ -0,0 +1,503 @@
+//! OpenMetrics report output
+//!
+//! Writes reports in the OpenMetrics 1.0 text format: counters are typed as
+//! counters with `_total` samples and a `_created` sample at the start of
+//! collection, latency distributions are histograms (cumulative buckets)
+//! and summaries (percentiles), values are in base units (seconds, bytes),
+//! and the document ends with `# EOF`.
+//!
+//! Samples carry the report's timestamp unless disabled, which is needed
+//! for node_exporter's textfile collector: it rejects files with
+//! client-side timestamps.
+
+use crate::{
+    exporter::MetricsExporter,
+    instance::INSTANCE_LABEL,
+    output::Output,
+    run::RUN_ID_LABEL,
+    types::{LatencyMetrics, Percentiles, HISTOGRAM_BOUNDS_US},
+};
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// Microseconds in a second
+const US_PER_SECOND: f64 = 1e6;
+
+/// OpenMetrics exporter
+pub struct OpenMetricsExporter {
+    output: Output,
+    timestamps: bool,
+}
+
+impl OpenMetricsExporter {
+    /// Create a new OpenMetrics exporter
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Output file (`-` for stdout) or writer
+    pub fn new(output: impl Into<Output>) -> Self {
+        Self {
+            output: output.into(),
+            timestamps: true,
+        }
+    }
+
+    /// Set whether samples carry the report's timestamp
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Convert metrics to the OpenMetrics text format
+    pub fn to_openmetrics_format(metrics: &LatencyMetrics, timestamps: bool) -> String {
+        let end = chrono::DateTime::parse_from_rfc3339(&metrics.timestamp)
+            .map(|end| end.timestamp_millis() as f64 / 1000.0)
+            .unwrap_or_default();
+        let mut out = Writer {
+            output: String::new(),
+            timestamp: (timestamps && end > 0.0).then(|| format!("{:.3}", end)),
+            created: format!("{:.3}", (end - metrics.duration_seconds as f64).max(0.0)),
+            report_labels: [(INSTANCE_LABEL, &metrics.instance), (RUN_ID_LABEL, &metrics.run_id)]
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, value.clone()?)))
+                .collect(),
+        };
+
+        if !metrics.labels.is_empty() {
+            out.family("latency_probe_run", "info", None, "Labels describing the run");
+            let labels: Vec<(String, &str)> = metrics
+                .labels
+                .iter()
+                .map(|(key, value)| (label_name(key), value.as_str()))
+                .collect();
+            let labels: Vec<(&str, &str)> = labels.iter().map(|(key, value)| (key.as_str(), *value)).collect();
+            out.sample("latency_probe_run_info", &labels, 1.0);
+        }
+
+        out.counter("latency_probe_events", None, "Latency events captured", &[], metrics.total_events as f64);
+        out.counter("latency_probe_lost_events", None, "Events lost to full perf buffers", &[], metrics.lost_events as f64);
+
+        out.family("latency_probe_collection_duration_seconds", "gauge", Some("seconds"), "Duration of the collection period");
+        out.sample("latency_probe_collection_duration_seconds", &[], metrics.duration_seconds as f64);
+
+        // Histogram of every sample, with the sum when the report has a digest
+        let name = "latency_probe_latency_seconds";
+        out.family(name, "histogram", Some("seconds"), "Latency across all connections");
+        let mut cumulative = 0;
+        for (bound, count) in HISTOGRAM_BOUNDS_US.iter().zip(metrics.histogram.counts()) {
+            cumulative += count;
+            let le = match bound.parse::<f64>() {
+                Ok(us) if us.is_finite() => format!("{}", us / US_PER_SECOND),
+                _ => "+Inf".to_string(),
+            };
+            out.sample(&format!("{}_bucket", name), &[("le", &le)], cumulative as f64);
+        }
+        out.sample(&format!("{}_count", name), &[], cumulative as f64);
+        if let Some(digest) = &metrics.digest {
+            out.sample(&format!("{}_sum", name), &[], digest.mean() * digest.count() as f64 / US_PER_SECOND);
+        }
+        out.created(name, &[]);
+
+        let avg_latency_us = metrics.digest.as_ref().map_or(f64::NAN, |digest| digest.mean());
+        out.summaries(
+            "latency_probe_latency_percentiles_seconds",
+            "Latency percentiles across all connections",
+            None,
+            vec![("", metrics.histogram.total_count(), avg_latency_us, &metrics.percentiles)],
+        );
+
+        out.summaries(
+            "latency_probe_dns_latency_seconds",
+            "DNS resolution latency",
+            None,
+            vec![("", metrics.dns_latency.queries, metrics.dns_latency.avg_latency_us, &metrics.dns_latency.percentiles)],
+        );
+
+        if let Some(cleanup) = &metrics.dedup.cleanup {
+            out.summaries(
+                "latency_probe_cleanup_latency_seconds",
+                "tcp_cleanup_rbuf latency, kept out of the overall latency",
+                None,
+                vec![("", cleanup.events, cleanup.avg_latency_us, &cleanup.percentiles)],
+            );
+        }
+
+        out.family("latency_probe_jitter_seconds", "gauge", Some("seconds"), "RFC 3550 jitter between consecutive latencies of a connection");
+        out.sample("latency_probe_jitter_seconds", &[("stat", "mean")], metrics.jitter.mean_us / US_PER_SECOND);
+        out.sample("latency_probe_jitter_seconds", &[("stat", "max")], metrics.jitter.max_us / US_PER_SECOND);
+
+        // Per-group breakdowns
+        let groups = [
+            ("namespace", "netns", "network namespace", entries(&metrics.namespaces, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("pod", "pod_uid", "pod", entries(&metrics.pods, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("service", "service", "service", entries(&metrics.services, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("protocol", "protocol", "transport protocol", entries(&metrics.protocols, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("traffic_class", "class", "traffic class", entries(&metrics.traffic_classes, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("zone", "locality", "zone locality", entries(&metrics.zones, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("tenant", "tenant", "tenant", entries(&metrics.tenants, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("phase", "phase", "connection phase", entries(&metrics.phases, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+            ("http_status", "status_class", "HTTP status class", entries(&metrics.http_status, |m| (m.events, m.avg_latency_us, &m.percentiles))),
+        ];
+        for (group, label, description, entries) in groups {
+            if !entries.is_empty() {
+                out.summaries(
+                    &format!("latency_probe_{}_latency_seconds", group),
+                    &format!("Latency by {}", description),
+                    Some(label),
+                    entries,
+                );
+            }
+        }
+
+        let event_types = &metrics.event_type_breakdown;
+        out.family("latency_probe_events_by_type", "counter", None, "Latency events by type");
+        for (event_type, count) in [
+            ("tcp_sendmsg", event_types.tcp_sendmsg),
+            ("tcp_recvmsg", event_types.tcp_recvmsg),
+            ("tcp_cleanup_rbuf", event_types.tcp_cleanup_rbuf),
+            ("tcp_probe", event_types.tcp_probe),
+            ("quic", event_types.quic),
+        ] {
+            out.sample("latency_probe_events_by_type_total", &[("type", event_type)], count as f64);
+            out.created("latency_probe_events_by_type", &[("type", event_type)]);
+        }
+
+        out.family("latency_probe_transferred_bytes", "counter", Some("bytes"), "Bytes transferred across all connections");
+        for (direction, bytes) in [("sent", metrics.throughput.bytes_sent), ("received", metrics.throughput.bytes_received)] {
+            out.sample("latency_probe_transferred_bytes_total", &[("direction", direction)], bytes as f64);
+            out.created("latency_probe_transferred_bytes", &[("direction", direction)]);
+        }
+
+        out.family("latency_probe_connections", "gauge", None, "Unique connections in the report");
+        out.sample("latency_probe_connections", &[], metrics.connections.len() as f64);
+        out.family("latency_probe_active_connections", "gauge", None, "Connections open at the end of collection");
+        out.sample("latency_probe_active_connections", &[], metrics.connection_states.active_connections as f64);
+        out.counter("latency_probe_opened_connections", None, "Connections opened", &[], metrics.connection_states.total_opened as f64);
+        out.counter("latency_probe_closed_connections", None, "Connections closed", &[], metrics.connection_states.total_closed as f64);
+
+        out.counter("latency_probe_packet_drops", None, "Packets dropped by the kernel", &[], metrics.packet_drops.total_drops as f64);
+        if !metrics.packet_drops.drops_by_location.is_empty() {
+            out.family("latency_probe_location_packet_drops", "counter", None, "Packets dropped by the kernel, by location");
+            for (location, count) in &metrics.packet_drops.drops_by_location {
+                out.sample("latency_probe_location_packet_drops_total", &[("location", location)], *count as f64);
+                out.created("latency_probe_location_packet_drops", &[("location", location)]);
+            }
+        }
+
+        out.family("latency_probe_connection_resets", "counter", None, "TCP resets, by direction");
+        for (direction, count) in [("received", metrics.errors.resets_received), ("sent", metrics.errors.resets_sent)] {
+            out.sample("latency_probe_connection_resets_total", &[("direction", direction)], count as f64);
+            out.created("latency_probe_connection_resets", &[("direction", direction)]);
+        }
+        out.counter("latency_probe_retransmission_timeouts", None, "TCP retransmission timeouts", &[], metrics.errors.timeouts as f64);
+        if !metrics.errors.services.is_empty() {
+            out.family("latency_probe_service_connection_resets", "counter", None, "TCP resets, by service and direction");
+            for (service, errors) in &metrics.errors.services {
+                for (direction, count) in [("received", errors.resets_received), ("sent", errors.resets_sent)] {
+                    let labels = [("service", service.as_str()), ("direction", direction)];
+                    out.sample("latency_probe_service_connection_resets_total", &labels, count as f64);
+                    out.created("latency_probe_service_connection_resets", &labels);
+                }
+            }
+            out.family("latency_probe_service_retransmission_timeouts", "counter", None, "TCP retransmission timeouts, by service");
+            for (service, errors) in &metrics.errors.services {
+                out.sample("latency_probe_service_retransmission_timeouts_total", &[("service", service)], errors.timeouts as f64);
+                out.created("latency_probe_service_retransmission_timeouts", &[("service", service)]);
+            }
+        }
+
+        out.counter("latency_probe_context_switches", None, "Context switches", &[], metrics.context_switches.total_switches as f64);
+
+        out.family("latency_probe_map_fill_ratio", "gauge", None, "Fraction of eBPF map capacity in use");
+        for map in &metrics.health.maps {
+            out.sample("latency_probe_map_fill_ratio", &[("map", &map.map)], map.fill_ratio);
+        }
+        out.family("latency_probe_event_loss_ratio", "gauge", None, "Fraction of events lost to full perf buffers");
+        out.sample("latency_probe_event_loss_ratio", &[], metrics.health.event_loss_ratio);
+        for (name, help, value) in metrics.health.internal.fields() {
+            out.counter(&format!("latency_probe_internal_{}", name), None, help, &[], value as f64);
+        }
+        if let Some(channel) = &metrics.health.channel {
+            out.counter(
+                "latency_probe_channel_dropped_events",
+                None,
+                "Events dropped by the reader queue overflow policy",
+                &[("policy", &channel.policy)],
+                channel.dropped_events as f64,
+            );
+            out.counter(
+                "latency_probe_channel_blocked_waits",
+                None,
+                "Times a reader waited for room in its queue",
+                &[],
+                channel.blocked_waits as f64,
+            );
+            out.family("latency_probe_channel_max_depth", "gauge", None, "Most events queued for one reader at once");
+            out.sample("latency_probe_channel_max_depth", &[], channel.max_depth as f64);
+        }
+        if let Some(export) = &metrics.health.export {
+            for (name, unit, help, value) in [
+                ("latency_probe_export_queue_depth", None, "Reports waiting for the exporter thread", export.queue_depth as f64),
+                ("latency_probe_export_queue_max_depth", None, "Most reports waiting for the exporter thread at once", export.max_queue_depth as f64),
+                ("latency_probe_export_latency_seconds", Some("seconds"), "Time the latest export took", export.last_latency_ms / 1000.0),
+                ("latency_probe_export_max_latency_seconds", Some("seconds"), "Longest export", export.max_latency_ms / 1000.0),
+            ] {
+                out.family(name, "gauge", unit, help);
+                out.sample(name, &[], value);
+            }
+            out.counter(
+                "latency_probe_export_dropped_snapshots",
+                None,
+                "Snapshots dropped because the export queue was full",
+                &[],
+                export.dropped_snapshots as f64,
+            );
+        }
+        out.counter(
+            "latency_probe_duplicate_events",
+            None,
+            "Events dropped as duplicates of a receive",
+            &[("policy", &metrics.dedup.policy)],
+            metrics.dedup.duplicates as f64,
+        );
+        if let Some(bounds) = &metrics.latency_bounds {
+            out.family("latency_probe_rejected_latencies", "counter", None, "Latencies rejected outside the validity bounds");
+            for (bound, count) in [("min", bounds.below_min), ("max", bounds.above_max)] {
+                out.sample("latency_probe_rejected_latencies_total", &[("bound", bound)], count as f64);
+                out.created("latency_probe_rejected_latencies", &[("bound", bound)]);
+            }
+            out.family("latency_probe_latency_bound_seconds", "gauge", Some("seconds"), "Latency validity bounds");
+            for (bound, ns) in [("min", bounds.min_latency_ns), ("max", bounds.max_latency_ns)] {
+                out.sample("latency_probe_latency_bound_seconds", &[("bound", bound)], ns as f64 / 1e9);
+            }
+        }
+
+        if !metrics.program_stats.is_empty() {
+            out.family("latency_probe_program_run_seconds", "counter", Some("seconds"), "Time spent running each eBPF program");
+            for program in &metrics.program_stats {
+                out.sample("latency_probe_program_run_seconds_total", &[("program", &program.program)], program.run_time_ns as f64 / 1e9);
+            }
+            out.family("latency_probe_program_runs", "counter", None, "Number of times each eBPF program ran");
+            for program in &metrics.program_stats {
+                out.sample("latency_probe_program_runs_total", &[("program", &program.program)], program.run_count as f64);
+            }
+        }
+
+        out.output.push_str("# EOF\n");
+        out.output
+    }
+}
+
+impl MetricsExporter for OpenMetricsExporter {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        self.output
+            .write_all(Self::to_openmetrics_format(metrics, self.timestamps).as_bytes())
+    }
+}
+
+/// Key, events, average latency in microseconds and percentiles of a
+/// breakdown entry
+type Entry<'a> = (&'a str, u64, f64, &'a Percentiles);
+
+/// Entries of a per-group breakdown
+fn entries<'a, T>(groups: &'a BTreeMap<String, T>, parts: fn(&'a T) -> (u64, f64, &'a Percentiles)) -> Vec<Entry<'a>> {
+    groups
+        .iter()
+        .map(|(key, group)| {
+            let (events, avg_latency_us, percentiles) = parts(group);
+            (key.as_str(), events, avg_latency_us, percentiles)
+        })
+        .collect()
+}
+
+/// Builds the text of one document
+struct Writer {
+    output: String,
+    /// Timestamp appended to samples (None = no timestamps)
+    timestamp: Option<String>,
+    /// Value of `_created` samples: the start of collection
+    created: String,
+    /// Instance name and run ID, labelling every sample
+    report_labels: Vec<(&'static str, String)>,
+}
+
+impl Writer {
+    /// Start a metric family
+    fn family(&mut self, name: &str, kind: &str, unit: Option<&str>, help: &str) {
+        self.output.push_str(&format!("# TYPE {} {}\n", name, kind));
+        if let Some(unit) = unit {
+            self.output.push_str(&format!("# UNIT {} {}\n", name, unit));
+        }
+        self.output.push_str(&format!("# HELP {} {}\n", name, help));
+    }
+
+    /// Write a sample
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.output.push_str(name);
+        self.labels(labels);
+        self.output.push(' ');
+        self.output.push_str(&number(value));
+        if let Some(timestamp) = &self.timestamp {
+            self.output.push(' ');
+            self.output.push_str(timestamp);
+        }
+        self.output.push('\n');
+    }
+
+    /// Write the `_created` sample of a counter, histogram or summary
+    fn created(&mut self, family: &str, labels: &[(&str, &str)]) {
+        let created = self.created.clone();
+        self.output.push_str(&format!("{}_created", family));
+        self.labels(labels);
+        self.output.push_str(&format!(" {}", created));
+        if let Some(timestamp) = &self.timestamp {
+            self.output.push_str(&format!(" {}", timestamp));
+        }
+        self.output.push('\n');
+    }
+
+    fn labels(&mut self, labels: &[(&str, &str)]) {
+        let labels: Vec<String> = self
+            .report_labels
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .chain(labels.iter().copied())
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect();
+        if labels.is_empty() {
+            return;
+        }
+        self.output.push_str(&format!("{{{}}}", labels.join(",")));
+    }
+
+    /// Write a counter family with a single sample
+    fn counter(&mut self, name: &str, unit: Option<&str>, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.family(name, "counter", unit, help);
+        self.sample(&format!("{}_total", name), labels, value);
+        self.created(name, labels);
+    }
+
+    /// Write a summary family of latency percentiles
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Family name, ending in `_seconds`
+    /// * `help` - Description
+    /// * `label` - Label holding the key of each entry (None for a single
+    ///   unlabeled entry)
+    /// * `entries` - Entries of the breakdown
+    fn summaries(&mut self, name: &str, help: &str, label: Option<&str>, entries: Vec<Entry>) {
+        self.family(name, "summary", Some("seconds"), help);
+        for (key, events, avg_latency_us, p) in entries {
+            let mut labels: Vec<(&str, &str)> = label.map(|label| (label, key)).into_iter().collect();
+            for (quantile, value) in [("0.5", p.p50), ("0.9", p.p90), ("0.95", p.p95), ("0.99", p.p99), ("0.999", p.p999)] {
+                labels.push(("quantile", quantile));
+                self.sample(name, &labels, value / US_PER_SECOND);
+                labels.pop();
+            }
+            self.sample(&format!("{}_count", name), &labels, events as f64);
+            if avg_latency_us.is_finite() {
+                self.sample(&format!("{}_sum", name), &labels, avg_latency_us * events as f64 / US_PER_SECOND);
+            }
+            self.created(name, &labels);
+        }
+    }
+}
+
+/// Format a value (integers without a fraction)
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Label name for an arbitrary key (invalid characters become `_`)
+fn label_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{GroupMetrics, LatencyHistogram};
+
+    #[test]
+    fn test_openmetrics_format() {
+        let mut metrics = LatencyMetrics {
+            timestamp: "2025-01-01T00:01:00Z".to_string(),
+            duration_seconds: 60,
+            total_events: 3,
+            histogram: LatencyHistogram {
+                bucket_0_1ms: 2,
+                bucket_100ms_plus: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        metrics.labels.insert("mesh-type".to_string(), "istio \"ambient\"".to_string());
+        metrics.services.insert(
+            "frontend".to_string(),
+            GroupMetrics {
+                events: 3,
+                avg_latency_us: 500.0,
+                percentiles: Percentiles {
+                    p50: 250.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        let text = OpenMetricsExporter::to_openmetrics_format(&metrics, true);
+        assert!(text.ends_with("# EOF\n"));
+        assert!(text.contains("# TYPE latency_probe_events counter\n"));
+        assert!(text.contains("latency_probe_events_total 3 1735689660.000\n"));
+        assert!(text.contains("latency_probe_events_created 1735689600.000 1735689660.000\n"));
+        assert!(text.contains("latency_probe_run_info{mesh_type=\"istio \\\"ambient\\\"\"} 1"));
+
+        // Cumulative buckets in seconds
+        assert!(text.contains("latency_probe_latency_seconds_bucket{le=\"0.001\"} 2 "));
+        assert!(text.contains("latency_probe_latency_seconds_bucket{le=\"0.1\"} 2 "));
+        assert!(text.contains("latency_probe_latency_seconds_bucket{le=\"+Inf\"} 3 "));
+
+        assert!(text.contains("latency_probe_service_latency_seconds{service=\"frontend\",quantile=\"0.5\"} 0.00025 "));
+        assert!(text.contains("latency_probe_service_latency_seconds_count{service=\"frontend\"} 3 "));
+        assert!(text.contains("latency_probe_service_latency_seconds_sum{service=\"frontend\"} 0.0015 "));
+
+        // Without timestamps, for node_exporter's textfile collector
+        let text = OpenMetricsExporter::to_openmetrics_format(&metrics, false);
+        assert!(text.contains("latency_probe_events_total 3\n"));
+        assert!(text.contains("latency_probe_events_created 1735689600.000\n"));
+
+        // Families are not repeated
+        let mut families: Vec<&str> = text.lines().filter(|line| line.starts_with("# TYPE ")).collect();
+        let count = families.len();
+        families.sort();
+        families.dedup();
+        assert_eq!(families.len(), count);
+
+        // The instance name and run ID label every sample
+        metrics.instance = Some("ns-a".to_string());
+        metrics.run_id = Some("nightly-42".to_string());
+        let text = OpenMetricsExporter::to_openmetrics_format(&metrics, false);
+        assert!(text.contains("latency_probe_events_total{probe_instance=\"ns-a\",run_id=\"nightly-42\"} 3\n"));
+        assert!(text.contains("{probe_instance=\"ns-a\",run_id=\"nightly-42\",le=\"0.001\"} 2\n"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Report destinations
+//!
+//! Exporters write to an [`Output`]: a file, replaced on every export, or
+//! standard output, selected with the path `-` (`--output -`), for piping
+//! reports into jq or other tools. Logs go to stderr, so they do not mix
+//! with the report. Library users can also hand exporters any `Write`
+//! implementation through [`Output::writer`].
+//!
+//! Each export to standard output or a writer appends a whole document, so
+//! rotated and per-phase reports written there follow one another.
+
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Path selecting standard output
+pub const STDOUT_PATH: &str = "-";
+
+/// Whether a path selects standard output
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new(STDOUT_PATH)
+}
+
+/// Destination of an exporter
+#[derive(Clone)]
+pub enum Output {
+    /// File, created or truncated on every export
+    File(PathBuf),
+    /// Standard output
+    Stdout,
+    /// Any writer, shared by the exporters it is handed to
+    Writer(Arc<Mutex<dyn Write + Send>>),
+}
+
+impl Output {
+    /// Destination writing to any writer
+    pub fn writer(writer: impl Write + Send + 'static) -> Self {
+        Output::Writer(Arc::new(Mutex::new(writer)))
+    }
+
+    /// Open the destination for one export
+    pub fn create(&self) -> Result<Box<dyn Write + Send>> {
+        Ok(match self {
+            Output::File(path) => Box::new(
+                File::create(path).with_context(|| format!("Failed to create output file: {:?}", path))?,
+            ),
+            Output::Stdout => Box::new(io::stdout()),
+            Output::Writer(writer) => Box::new(SharedWriter(writer.clone())),
+        })
+    }
+
+    /// Write a whole report to the destination
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Encoded report
+    pub fn write_all(&self, data: &[u8]) -> Result<()> {
+        let mut writer = self.create()?;
+        writer
+            .write_all(data)
+            .and_then(|_| writer.flush())
+            .with_context(|| format!("Failed to write to output {}", self))
+    }
+}
+
+impl From<PathBuf> for Output {
+    fn from(path: PathBuf) -> Self {
+        if is_stdout(&path) {
+            Output::Stdout
+        } else {
+            Output::File(path)
+        }
+    }
+}
+
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::File(path) => write!(f, "{:?}", path),
+            Output::Stdout => write!(f, "stdout"),
+            Output::Writer(_) => write!(f, "writer"),
+        }
+    }
+}
+
+/// Writer locked for each call
+struct SharedWriter(Arc<Mutex<dyn Write + Send>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_destinations() {
+        assert!(matches!(Output::from(PathBuf::from("-")), Output::Stdout));
+        assert!(matches!(Output::from(PathBuf::from("./-")), Output::File(_)));
+
+        // Exports to a writer follow one another
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let output = Output::Writer(buffer.clone());
+        output.write_all(b"first\n").unwrap();
+        output.write_all(b"second\n").unwrap();
+        assert_eq!(buffer.lock().unwrap().as_slice(), b"first\nsecond\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.txt");
+        let output = Output::from(path.clone());
+        output.write_all(b"old report").unwrap();
+        output.write_all(b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    }
+}
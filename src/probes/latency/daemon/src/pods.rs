@@ -0,0 +1,218 @@
+//! Pod attribution from cgroup paths
+//!
+//! The kubelet places every container in a cgroup named after its pod UID
+//! and container ID, so /proc/<pid>/cgroup is enough to attribute an event
+//! to a pod, without access to the Kubernetes API (e.g. on air-gapped
+//! clusters). Both cgroup drivers are understood:
+//!
+//! ```text
+//! systemd:  /kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod<uid>.slice/cri-containerd-<id>.scope
+//! cgroupfs: /kubepods/burstable/pod<uid>/<id>
+//! ```
+//!
+//! Lookups are cached per PID for a TTL, like process names (see
+//! crate::process).
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Entries kept before expired ones are purged
+const MAX_ENTRIES: usize = 65536;
+
+/// Characters of a container ID shown in reports, as in `crictl ps`
+const SHORT_CONTAINER_ID: usize = 13;
+
+/// Pod and container of a process
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PodInfo {
+    /// Pod UID
+    pub pod_uid: String,
+    /// QoS class ("guaranteed", "burstable", "besteffort")
+    pub qos_class: String,
+    /// Container ID, shortened (None for processes in the pod's own cgroup)
+    pub container_id: Option<String>,
+}
+
+/// Parse the contents of /proc/<pid>/cgroup
+///
+/// # Returns
+///
+/// The pod of the first Kubernetes cgroup path, or None if the process is
+/// not in a pod
+pub fn parse_cgroup(contents: &str) -> Option<PodInfo> {
+    contents
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .find_map(parse_cgroup_path)
+}
+
+/// Parse one cgroup path (see the module docs for the layouts)
+fn parse_cgroup_path(path: &str) -> Option<PodInfo> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let pod_index = segments.iter().position(|s| pod_uid(s).is_some())?;
+    let pod_segment = segments[pod_index];
+
+    let qos_class = ["burstable", "besteffort"]
+        .into_iter()
+        .find(|qos| pod_segment.contains(qos) || segments[..pod_index].iter().any(|s| s.contains(qos)))
+        .unwrap_or("guaranteed");
+
+    Some(PodInfo {
+        pod_uid: pod_uid(pod_segment)?,
+        qos_class: qos_class.to_string(),
+        container_id: segments.get(pod_index + 1).and_then(|s| container_id(s)),
+    })
+}
+
+/// Pod UID of a pod cgroup segment
+///
+/// `kubepods-burstable-pod<uid>.slice` (with `_` for `-`) or `pod<uid>`
+fn pod_uid(segment: &str) -> Option<String> {
+    let segment = segment.strip_suffix(".slice").unwrap_or(segment);
+    let uid = match segment.rfind("-pod") {
+        Some(i) => &segment[i + 4..],
+        None => segment.strip_prefix("pod")?,
+    };
+    let uid = uid.replace('_', "-");
+
+    // UIDs are 36-character UUIDs
+    let valid = uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    valid.then_some(uid)
+}
+
+/// Short container ID of a container cgroup segment
+///
+/// `cri-containerd-<id>.scope`, `crio-<id>.scope`, `docker-<id>.scope`,
+/// or a bare `<id>`
+fn container_id(segment: &str) -> Option<String> {
+    let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+    let id = segment.rsplit('-').next()?;
+
+    let valid = id.len() >= SHORT_CONTAINER_ID && id.chars().all(|c| c.is_ascii_hexdigit());
+    valid.then(|| id[..SHORT_CONTAINER_ID].to_string())
+}
+
+/// A resolved (or unresolvable) PID
+struct CacheEntry {
+    info: Option<PodInfo>,
+    expires: Instant,
+}
+
+/// PID to pod cache backed by /proc
+pub struct PodCache {
+    root: PathBuf,
+    ttl: Duration,
+    entries: HashMap<u32, CacheEntry>,
+}
+
+impl PodCache {
+    /// Create a cache reading from /proc
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a lookup is trusted before /proc is read again
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_root("/proc", ttl)
+    }
+
+    /// Create a cache reading from another procfs mount (e.g. the host's
+    /// /proc mounted into a container)
+    pub fn with_root(root: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            root: root.into(),
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Look up the pod of a process, reading /proc if the cached entry is
+    /// missing or stale
+    ///
+    /// # Returns
+    ///
+    /// The pod, or None if the process has exited or is not in a pod
+    pub fn lookup(&mut self, pid: u32) -> Option<&PodInfo> {
+        let now = Instant::now();
+        let fresh = self.entries.get(&pid).is_some_and(|entry| entry.expires > now);
+
+        if !fresh {
+            if self.entries.len() >= MAX_ENTRIES {
+                self.entries.retain(|_, entry| entry.expires > now);
+            }
+            self.entries.insert(
+                pid,
+                CacheEntry {
+                    info: read_pod(&self.root, pid),
+                    expires: now + self.ttl,
+                },
+            );
+        }
+
+        self.entries.get(&pid)?.info.as_ref()
+    }
+}
+
+/// Read a process's pod from procfs
+fn read_pod(root: &Path, pid: u32) -> Option<PodInfo> {
+    // PID 0 is the idle task, e.g. softirq work on an idle CPU
+    if pid == 0 {
+        return None;
+    }
+
+    let contents = std::fs::read_to_string(root.join(pid.to_string()).join("cgroup")).ok()?;
+    parse_cgroup(&contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup() {
+        let uid = "5f1c2a3b-0d4e-4f6a-8b9c-0123456789ab";
+        let id = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+        // systemd driver, cgroup v2
+        let info = parse_cgroup(&format!(
+            "0::/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice/cri-containerd-{}.scope\n",
+            uid.replace('-', "_"),
+            id
+        ))
+        .unwrap();
+        assert_eq!(info.pod_uid, uid);
+        assert_eq!(info.qos_class, "burstable");
+        assert_eq!(info.container_id.as_deref(), Some("0123456789abc"));
+
+        // cgroupfs driver, cgroup v1, guaranteed pod
+        let info = parse_cgroup(&format!(
+            "12:pids:/kubepods/pod{}/{}\n11:cpu,cpuacct:/kubepods/pod{}/{}\n",
+            uid, id, uid, id
+        ))
+        .unwrap();
+        assert_eq!(info.pod_uid, uid);
+        assert_eq!(info.qos_class, "guaranteed");
+
+        // Pod-level cgroup without a container (e.g. the pause process)
+        let info = parse_cgroup(&format!("0::/kubepods/besteffort/pod{}\n", uid)).unwrap();
+        assert_eq!(info.qos_class, "besteffort");
+        assert_eq!(info.container_id, None);
+
+        assert_eq!(parse_cgroup("0::/system.slice/sshd.service\n"), None);
+        assert_eq!(parse_cgroup("0::/kubepods/podnot-a-uid/abc\n"), None);
+
+        // Served from the cache until the entry expires
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("42");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("cgroup"), format!("0::/kubepods/pod{}/{}\n", uid, id)).unwrap();
+        let mut cache = PodCache::with_root(root.path(), Duration::from_secs(60));
+        assert_eq!(cache.lookup(42).unwrap().pod_uid, uid);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(cache.lookup(42).is_some());
+        assert!(cache.lookup(0).is_none());
+    }
+}
@@ -0,0 +1,454 @@
+//! Privilege dropping after attach
+//!
+//! Loading programs and opening maps requires root, but reading perf buffers
+//! through already-open file descriptors does not. Long-running deployments
+//! can switch to an unprivileged user and/or shed every capability except
+//! the few the probe still needs once everything is attached.
+//!
+//! Capabilities belong to threads, not processes: `capset` and
+//! `PR_SET_KEEPCAPS` only change the calling thread, and by the time
+//! everything is attached the tokio workers and perf readers are running.
+//! The drop therefore runs those steps on every thread of the process, by
+//! signalling each thread listed in /proc/self/task (libc's `setuid` and
+//! `setgid` already do the same). Threads started afterwards inherit the
+//! reduced set from the thread that starts them.
+
+use anyhow::{Context, Result};
+use aya::util::KernelVersion;
+use log::info;
+use std::{
+    collections::HashSet,
+    ffi::{CStr, CString},
+    path::Path,
+    sync::atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// `_LINUX_CAPABILITY_VERSION_3` from linux/capability.h
+const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Maximum number of threads a step is run on
+const MAX_THREADS: usize = 4096;
+
+/// How long threads are given to run a step
+const THREAD_STEP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Step run by each thread when signalled
+static THREAD_STEP: AtomicUsize = AtomicUsize::new(0);
+/// Capabilities kept by [`restrict_caps`]
+static RETAIN_MASK: [AtomicU32; 2] = [AtomicU32::new(0), AtomicU32::new(0)];
+/// Number of threads that ran the step, and the IDs of the first ones
+static THREAD_RESPONSES: AtomicUsize = AtomicUsize::new(0);
+static RESPONDED: [AtomicI32; MAX_THREADS] = [const { AtomicI32::new(0) }; MAX_THREADS];
+/// Number of threads the step failed on
+static THREAD_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Linux capabilities the probe may retain after attach
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Needed to detach XDP programs on shutdown
+    NetAdmin = 12,
+    /// Needed for perf events on kernels without CAP_PERFMON
+    SysAdmin = 21,
+    /// Needed for perf event access (Linux 5.8+)
+    Perfmon = 38,
+    /// Needed for map access when unprivileged BPF is disabled (Linux 5.8+)
+    Bpf = 39,
+}
+
+/// User and group to switch to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// User ID
+    pub uid: u32,
+    /// Primary group ID
+    pub gid: u32,
+}
+
+impl Credentials {
+    /// Resolve a user specification
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - User name, numeric `uid`, or numeric `uid:gid`
+    pub fn lookup(spec: &str) -> Result<Self> {
+        if let Some((uid, gid)) = spec.split_once(':') {
+            return Ok(Self {
+                uid: uid.parse().with_context(|| format!("Invalid uid: {}", uid))?,
+                gid: gid.parse().with_context(|| format!("Invalid gid: {}", gid))?,
+            });
+        }
+
+        if let Ok(uid) = spec.parse::<u32>() {
+            // Numeric uid without a group uses the same id for both
+            return Ok(Self { uid, gid: uid });
+        }
+
+        lookup_user(spec)
+    }
+}
+
+/// `struct __user_cap_header_struct`
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+/// `struct __user_cap_data_struct`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Split a capability set into the two 32-bit words used by capset(2)
+fn capability_mask(caps: &[Capability]) -> [u32; 2] {
+    let mut mask = [0u32; 2];
+    for cap in caps {
+        let bit = *cap as u32;
+        mask[(bit / 32) as usize] |= 1 << (bit % 32);
+    }
+    mask
+}
+
+/// Capabilities the probe needs after attach on the running kernel
+///
+/// Kernels before 5.8 have no CAP_BPF/CAP_PERFMON, so CAP_SYS_ADMIN is kept
+/// instead. CAP_NET_ADMIN is added when an XDP program must be detached on
+/// shutdown.
+pub fn required_capabilities(xdp: bool) -> Vec<Capability> {
+    let split_caps = KernelVersion::current()
+        .map(|version| version >= KernelVersion::new(5, 8, 0))
+        .unwrap_or(true);
+
+    let mut caps = if split_caps {
+        vec![Capability::Bpf, Capability::Perfmon]
+    } else {
+        vec![Capability::SysAdmin]
+    };
+    if xdp {
+        caps.push(Capability::NetAdmin);
+    }
+    caps
+}
+
+/// Drop privileges after programs are loaded and maps are opened
+///
+/// Switches to `user` if given, then limits the permitted and effective
+/// capability sets to `retain`. Either step may be used on its own: with no
+/// user the process stays root but loses all other capabilities. Applies to
+/// every thread of the process; the signal handlers of the other threads
+/// must not be blocked.
+///
+/// # Arguments
+///
+/// * `user` - Optional credentials to switch to
+/// * `retain` - Capabilities to keep
+pub fn drop_privileges(user: Option<&Credentials>, retain: &[Capability]) -> Result<()> {
+    let mask = capability_mask(retain);
+    for (word, bits) in RETAIN_MASK.iter().zip(mask) {
+        word.store(bits, Ordering::SeqCst);
+    }
+
+    if let Some(creds) = user {
+        // Keep permitted capabilities across setuid so they can be re-raised
+        if !retain.is_empty() {
+            on_every_thread(keep_caps).context("Failed to set PR_SET_KEEPCAPS")?;
+        }
+        os_result(unsafe { libc::setgroups(0, std::ptr::null()) })
+            .context("Failed to clear supplementary groups")?;
+        os_result(unsafe { libc::setgid(creds.gid) })
+            .with_context(|| format!("Failed to set gid {}", creds.gid))?;
+        os_result(unsafe { libc::setuid(creds.uid) })
+            .with_context(|| format!("Failed to set uid {}", creds.uid))?;
+        info!("  ✓ Switched to uid={} gid={}", creds.uid, creds.gid);
+    }
+
+    on_every_thread(restrict_caps).context("Failed to restrict capabilities")?;
+
+    if retain.is_empty() {
+        info!("  ✓ Dropped all capabilities");
+    } else {
+        info!("  ✓ Retained capabilities: {:?}", retain);
+    }
+
+    Ok(())
+}
+
+/// Keep the permitted capabilities of the calling thread across setuid
+fn keep_caps() -> bool {
+    unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) == 0 }
+}
+
+/// Limit the permitted and effective capabilities of the calling thread to
+/// RETAIN_MASK
+fn restrict_caps() -> bool {
+    let header = CapHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapData::default(); 2];
+    for (word, bits) in data.iter_mut().zip(&RETAIN_MASK) {
+        word.effective = bits.load(Ordering::SeqCst);
+        word.permitted = word.effective;
+    }
+    unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) == 0 }
+}
+
+/// Signal handler running THREAD_STEP on the signalled thread
+extern "C" fn run_thread_step(_signal: libc::c_int) {
+    let errno = unsafe { *libc::__errno_location() };
+    let step: fn() -> bool = unsafe { std::mem::transmute(THREAD_STEP.load(Ordering::SeqCst)) };
+    if !step() {
+        THREAD_FAILURES.fetch_add(1, Ordering::SeqCst);
+    }
+    let slot = THREAD_RESPONSES.fetch_add(1, Ordering::SeqCst);
+    if let Some(responded) = RESPONDED.get(slot) {
+        responded.store(unsafe { libc::gettid() }, Ordering::SeqCst);
+    }
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// Run a per-thread step on every thread of the process
+///
+/// The step runs on the calling thread directly and in a signal handler on
+/// the others, so it may only make async-signal-safe calls.
+fn on_every_thread(step: fn() -> bool) -> Result<()> {
+    if !step() {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    THREAD_STEP.store(step as usize, Ordering::SeqCst);
+    THREAD_RESPONSES.store(0, Ordering::SeqCst);
+    THREAD_FAILURES.store(0, Ordering::SeqCst);
+
+    // glibc reserves the first real-time signals for itself
+    let signal = libc::SIGRTMIN() + 4;
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = run_thread_step as extern "C" fn(libc::c_int) as usize;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+    os_result(unsafe { libc::sigaction(signal, &action, &mut previous) })
+        .context("Failed to install thread signal handler")?;
+
+    let result = signal_threads(signal);
+    unsafe { libc::sigaction(signal, &previous, std::ptr::null_mut()) };
+    result
+}
+
+/// Signal every other thread and wait until each ran the step or exited
+fn signal_threads(signal: libc::c_int) -> Result<()> {
+    let pid = unsafe { libc::getpid() };
+    let mut seen = HashSet::from([unsafe { libc::gettid() }]);
+    let mut signalled = Vec::new();
+
+    // Threads may start threads before they are signalled, so list the
+    // threads again until no new one appears
+    loop {
+        let mut new = false;
+        for entry in std::fs::read_dir("/proc/self/task").context("Failed to list threads")? {
+            let Some(tid) = entry?
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<i32>().ok())
+            else {
+                continue;
+            };
+            if !seen.insert(tid) {
+                continue;
+            }
+            new = true;
+            if signalled.len() >= MAX_THREADS {
+                anyhow::bail!("More than {} threads", MAX_THREADS);
+            }
+            // Threads that exited since the listing are not signalled
+            if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) } == 0 {
+                signalled.push(tid);
+            }
+        }
+
+        let deadline = Instant::now() + THREAD_STEP_TIMEOUT;
+        loop {
+            let responses = THREAD_RESPONSES.load(Ordering::SeqCst).min(MAX_THREADS);
+            let responded: HashSet<i32> = RESPONDED[..responses]
+                .iter()
+                .map(|tid| tid.load(Ordering::SeqCst))
+                .collect();
+            let pending = signalled
+                .iter()
+                .filter(|tid| {
+                    !responded.contains(tid)
+                        && Path::new(&format!("/proc/self/task/{}", tid)).exists()
+                })
+                .count();
+            if pending == 0 {
+                break;
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "{} threads did not respond within {:?}",
+                    pending,
+                    THREAD_STEP_TIMEOUT
+                );
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        if !new {
+            break;
+        }
+    }
+
+    let failures = THREAD_FAILURES.load(Ordering::SeqCst);
+    if failures > 0 {
+        anyhow::bail!("Failed on {} of {} threads", failures, signalled.len() + 1);
+    }
+    Ok(())
+}
+
+/// Convert a libc return code into an io::Result
+fn os_result(ret: libc::c_int) -> std::io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Look up a user name in the system password database
+fn lookup_user(name: &str) -> Result<Credentials> {
+    let c_name = CString::new(name).context("User name contains a NUL byte")?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+
+    let ret = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if ret != 0 {
+        return Err(std::io::Error::from_raw_os_error(ret))
+            .with_context(|| format!("Failed to look up user '{}'", name));
+    }
+    if result.is_null() {
+        anyhow::bail!("Unknown user: {}", name);
+    }
+
+    let resolved = unsafe { CStr::from_ptr(passwd.pw_name) };
+    info!("Resolved user {:?} to uid={}", resolved, passwd.pw_uid);
+
+    Ok(Credentials {
+        uid: passwd.pw_uid,
+        gid: passwd.pw_gid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_mask() {
+        assert_eq!(capability_mask(&[]), [0, 0]);
+        assert_eq!(capability_mask(&[Capability::NetAdmin]), [1 << 12, 0]);
+        assert_eq!(
+            capability_mask(&[Capability::Bpf, Capability::Perfmon]),
+            [0, (1 << 7) | (1 << 6)]
+        );
+    }
+
+    #[test]
+    fn test_numeric_credentials() {
+        assert_eq!(
+            Credentials::lookup("1000:2000").unwrap(),
+            Credentials { uid: 1000, gid: 2000 }
+        );
+        assert_eq!(
+            Credentials::lookup("65534").unwrap(),
+            Credentials { uid: 65534, gid: 65534 }
+        );
+        assert!(Credentials::lookup("1000:staff").is_err());
+    }
+
+    /// Effective capabilities of each thread of the process
+    fn effective_capabilities() -> Vec<(String, u64)> {
+        std::fs::read_dir("/proc/self/task")
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let status = std::fs::read_to_string(entry.path().join("status")).unwrap();
+                let caps = status
+                    .lines()
+                    .find_map(|line| line.strip_prefix("CapEff:"))
+                    .map(|caps| u64::from_str_radix(caps.trim(), 16).unwrap())
+                    .unwrap();
+                (entry.file_name().to_string_lossy().into_owned(), caps)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_drop_on_every_thread() {
+        // Dropping is irreversible, so it runs in a child test process
+        if std::env::var_os("PRIVILEGES_TEST_CHILD").is_none() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "privileges::tests::test_drop_on_every_thread",
+                    "--exact",
+                    "--nocapture",
+                ])
+                .env("PRIVILEGES_TEST_CHILD", "1")
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(
+                output.status.success(),
+                "{}{}",
+                stdout,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert!(stdout.contains("1 passed"), "{}", stdout);
+            return;
+        }
+
+        // Threads started before the drop, like the runtime's workers
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let stopped = std::sync::Arc::new(std::sync::Mutex::new(stopped));
+        let threads: Vec<_> = (0..3)
+            .map(|_| {
+                let stopped = std::sync::Arc::clone(&stopped);
+                std::thread::spawn(move || {
+                    let _ = stopped.lock().unwrap().recv();
+                })
+            })
+            .collect();
+
+        let permitted = effective_capabilities()[0].1;
+        let retain: &[Capability] = if permitted & (1 << 12) != 0 {
+            &[Capability::NetAdmin]
+        } else {
+            &[]
+        };
+        let expected = capability_mask(retain)[0] as u64;
+        drop_privileges(None, retain).unwrap();
+
+        let caps = effective_capabilities();
+        assert!(caps.len() >= 4, "{:?}", caps);
+        assert!(caps.iter().all(|&(_, caps)| caps == expected), "{:?}", caps);
+
+        drop(stop);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Process name resolution
+//!
+//! Events carry the PID of the process that triggered them. Names are read
+//! from /proc the first time a PID is seen and cached for a TTL, so a busy
+//! process costs one lookup per TTL rather than one per event, and a reused
+//! PID is picked up once its entry expires.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Entries kept before expired ones are purged
+const MAX_ENTRIES: usize = 65536;
+
+/// Name and command line of a process
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Short name from /proc/<pid>/comm
+    pub comm: String,
+    /// Arguments from /proc/<pid>/cmdline, space separated (empty for kernel threads)
+    pub cmdline: String,
+}
+
+/// A resolved (or unresolvable) PID
+struct CacheEntry {
+    info: Option<ProcessInfo>,
+    expires: Instant,
+}
+
+/// PID to process name cache backed by /proc
+pub struct ProcessCache {
+    root: PathBuf,
+    ttl: Duration,
+    entries: HashMap<u32, CacheEntry>,
+}
+
+impl ProcessCache {
+    /// Create a cache reading from /proc
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a lookup is trusted before /proc is read again
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_root("/proc", ttl)
+    }
+
+    /// Create a cache reading from another procfs mount (e.g. the host's
+    /// /proc mounted into a container)
+    pub fn with_root(root: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            root: root.into(),
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Look up a process, reading /proc if the cached entry is missing or stale
+    ///
+    /// # Returns
+    ///
+    /// The process info, or None if the process has exited or is the idle task
+    pub fn lookup(&mut self, pid: u32) -> Option<&ProcessInfo> {
+        let now = Instant::now();
+        let fresh = self.entries.get(&pid).is_some_and(|entry| entry.expires > now);
+
+        if !fresh {
+            if self.entries.len() >= MAX_ENTRIES {
+                self.entries.retain(|_, entry| entry.expires > now);
+            }
+            self.entries.insert(
+                pid,
+                CacheEntry {
+                    info: read_process(&self.root, pid),
+                    expires: now + self.ttl,
+                },
+            );
+        }
+
+        self.entries.get(&pid)?.info.as_ref()
+    }
+}
+
+/// Read a process's name and command line from procfs
+fn read_process(root: &Path, pid: u32) -> Option<ProcessInfo> {
+    // PID 0 is the idle task, e.g. softirq work on an idle CPU
+    if pid == 0 {
+        return None;
+    }
+
+    let dir = root.join(pid.to_string());
+    let comm = std::fs::read_to_string(dir.join("comm")).ok()?;
+    let cmdline = std::fs::read(dir.join("cmdline"))
+        .map(|raw| {
+            raw.split(|&b| b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+
+    Some(ProcessInfo {
+        comm: comm.trim_end().to_string(),
+        cmdline,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("42");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("comm"), "envoy\n").unwrap();
+        std::fs::write(dir.join("cmdline"), b"/usr/local/bin/envoy\0-c\0envoy.yaml\0").unwrap();
+
+        let mut cache = ProcessCache::with_root(root.path(), Duration::from_secs(60));
+        let info = cache.lookup(42).unwrap();
+        assert_eq!(info.comm, "envoy");
+        assert_eq!(info.cmdline, "/usr/local/bin/envoy -c envoy.yaml");
+
+        // Served from the cache until the entry expires
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(cache.lookup(42).is_some());
+
+        assert!(cache.lookup(0).is_none());
+        assert!(cache.lookup(7).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+//! Protobuf report output
+//!
+//! With the `proto` feature, reports can be written as a
+//! `latency_probe.v1.LatencyMetrics` message (see
+//! common/proto/latency.proto), a compact binary alternative to JSON that
+//! shares its schema with the event message.
+
+use crate::{exporter::MetricsExporter, output::Output, types::*};
+use anyhow::Result;
+use probe_common::proto as pb;
+use prost::Message;
+
+impl From<&Percentiles> for pb::Percentiles {
+    fn from(p: &Percentiles) -> Self {
+        Self {
+            p50: p.p50,
+            p75: p.p75,
+            p90: p.p90,
+            p95: p.p95,
+            p99: p.p99,
+            p999: p.p999,
+        }
+    }
+}
+
+impl From<&LatencyHistogram> for pb::LatencyHistogram {
+    fn from(histogram: &LatencyHistogram) -> Self {
+        Self {
+            upper_bounds_us: HISTOGRAM_BOUNDS_US
+                .iter()
+                .filter_map(|bound| bound.parse::<f64>().ok())
+                .filter(|bound| bound.is_finite())
+                .collect(),
+            counts: histogram.counts().to_vec(),
+        }
+    }
+}
+
+impl From<&ConnectionMetrics> for pb::ConnectionMetrics {
+    fn from(c: &ConnectionMetrics) -> Self {
+        Self {
+            source: c.source.clone(),
+            destination: c.destination.clone(),
+            events: c.events,
+            min_latency_us: c.min_latency_us,
+            max_latency_us: c.max_latency_us,
+            avg_latency_us: c.avg_latency_us,
+            std_dev_us: c.std_dev_us,
+            bytes_sent: c.bytes_sent,
+            bytes_received: c.bytes_received,
+            p50_us: c.p50_us,
+            p95_us: c.p95_us,
+            p99_us: c.p99_us,
+            jitter_us: c.jitter_us,
+            cookie: c.cookie.unwrap_or(0),
+        }
+    }
+}
+
+/// Group metrics message from the fields every per-group breakdown shares
+fn group(events: u64, avg_latency_us: f64, percentiles: &Percentiles) -> pb::GroupMetrics {
+    pb::GroupMetrics {
+        events,
+        avg_latency_us,
+        percentiles: Some(percentiles.into()),
+        histogram: None,
+    }
+}
+
+impl From<&LatencyMetrics> for pb::LatencyMetrics {
+    fn from(metrics: &LatencyMetrics) -> Self {
+        Self {
+            timestamp: metrics.timestamp.clone(),
+            duration_seconds: metrics.duration_seconds,
+            labels: metrics.labels.clone().into_iter().collect(),
+            total_events: metrics.total_events,
+            lost_events: metrics.lost_events,
+            percentiles: Some((&metrics.percentiles).into()),
+            jitter: Some(pb::Jitter {
+                mean_us: metrics.jitter.mean_us,
+                max_us: metrics.jitter.max_us,
+            }),
+            histogram: Some((&metrics.histogram).into()),
+            event_type_breakdown: Some(pb::EventTypeBreakdown {
+                tcp_sendmsg: metrics.event_type_breakdown.tcp_sendmsg,
+                tcp_recvmsg: metrics.event_type_breakdown.tcp_recvmsg,
+                tcp_cleanup_rbuf: metrics.event_type_breakdown.tcp_cleanup_rbuf,
+                tcp_probe: metrics.event_type_breakdown.tcp_probe,
+                quic: metrics.event_type_breakdown.quic,
+            }),
+            connections: metrics
+                .connections
+                .iter()
+                .map(|(key, c)| (key.clone(), c.into()))
+                .collect(),
+            dns_latency: Some(group(
+                metrics.dns_latency.queries,
+                metrics.dns_latency.avg_latency_us,
+                &metrics.dns_latency.percentiles,
+            )),
+            throughput: Some(pb::Throughput {
+                bytes_sent: metrics.throughput.bytes_sent,
+                bytes_received: metrics.throughput.bytes_received,
+                sent_bytes_per_second: metrics.throughput.sent_bytes_per_second,
+                received_bytes_per_second: metrics.throughput.received_bytes_per_second,
+            }),
+            namespaces: metrics
+                .namespaces
+                .iter()
+                .map(|(k, n)| (k.clone(), group(n.events, n.avg_latency_us, &n.percentiles)))
+                .collect(),
+            processes: metrics
+                .processes
+                .iter()
+                .map(|(k, p)| (k.clone(), group(p.events, p.avg_latency_us, &p.percentiles)))
+                .collect(),
+            pods: metrics
+                .pods
+                .iter()
+                .map(|(k, p)| (k.clone(), group(p.events, p.avg_latency_us, &p.percentiles)))
+                .collect(),
+            services: metrics
+                .services
+                .iter()
+                .map(|(k, s)| (k.clone(), group(s.events, s.avg_latency_us, &s.percentiles)))
+                .collect(),
+            protocols: metrics
+                .protocols
+                .iter()
+                .map(|(k, p)| {
+                    let mut protocol = group(p.events, p.avg_latency_us, &p.percentiles);
+                    protocol.histogram = Some((&p.histogram).into());
+                    (k.clone(), protocol)
+                })
+                .collect(),
+            traffic_classes: metrics
+                .traffic_classes
+                .iter()
+                .map(|(k, c)| (k.clone(), group(c.events, c.avg_latency_us, &c.percentiles)))
+                .collect(),
+            zones: metrics
+                .zones
+                .iter()
+                .map(|(k, z)| (k.clone(), group(z.events, z.avg_latency_us, &z.percentiles)))
+                .collect(),
+            tenants: metrics
+                .tenants
+                .iter()
+                .map(|(k, t)| (k.clone(), group(t.events, t.avg_latency_us, &t.percentiles)))
+                .collect(),
+            phases: metrics
+                .phases
+                .iter()
+                .map(|(k, p)| (k.clone(), group(p.events, p.avg_latency_us, &p.percentiles)))
+                .collect(),
+            http_status: metrics
+                .http_status
+                .iter()
+                .map(|(k, s)| (k.clone(), group(s.events, s.avg_latency_us, &s.percentiles)))
+                .collect(),
+            synthetic_samples: metrics
+                .coordinated_omission
+                .as_ref()
+                .map_or(0, |correction| correction.synthetic_samples),
+            clock: metrics.clock.as_ref().map(|clock| pb::Clock {
+                source: clock.source.clone(),
+                wall_offset_ns: clock.wall_offset_ns,
+                uncertainty_ns: clock.uncertainty_ns,
+                captured_at: clock.captured_at.clone(),
+            }),
+            errors: Some(pb::ConnectionErrors {
+                resets_received: metrics.errors.resets_received,
+                resets_sent: metrics.errors.resets_sent,
+                timeouts: metrics.errors.timeouts,
+            }),
+            service_errors: metrics
+                .errors
+                .services
+                .iter()
+                .map(|(k, e)| {
+                    let errors = pb::ConnectionErrors {
+                        resets_received: e.resets_received,
+                        resets_sent: e.resets_sent,
+                        timeouts: e.timeouts,
+                    };
+                    (k.clone(), errors)
+                })
+                .collect(),
+            latency_bounds: metrics.latency_bounds.as_ref().map(|bounds| pb::LatencyBounds {
+                min_latency_ns: bounds.min_latency_ns,
+                max_latency_ns: bounds.max_latency_ns,
+                below_min: bounds.below_min,
+                above_max: bounds.above_max,
+            }),
+            dedup_policy: metrics.dedup.policy.clone(),
+            duplicate_events: metrics.dedup.duplicates,
+            cleanup_latency: metrics
+                .dedup
+                .cleanup
+                .as_ref()
+                .map(|cleanup| group(cleanup.events, cleanup.avg_latency_us, &cleanup.percentiles)),
+            instance: metrics.instance.clone().unwrap_or_default(),
+            run_id: metrics.run_id.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Protobuf exporter
+///
+/// Writes the report as a single encoded `LatencyMetrics` message.
+pub struct ProtobufExporter {
+    output: Output,
+}
+
+impl ProtobufExporter {
+    /// Create a new protobuf exporter
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Output file (`-` for stdout) or writer
+    pub fn new(output: impl Into<Output>) -> Self {
+        Self { output: output.into() }
+    }
+}
+
+impl MetricsExporter for ProtobufExporter {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let message = pb::LatencyMetrics::from(metrics);
+
+        self.output.write_all(&message.encode_to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_protobuf_round_trip() {
+        let event = LatencyEvent {
+            netns: 7,
+            timestamp_ns: 1_000,
+            pid: 42,
+            http_status_class: 2,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a, // 10.0.0.1 in network byte order
+                    daddr: 0x0200000a,
+                    sport: 0x5000, // Port 80 in network byte order
+                    dport: 0x901f,
+                },
+                512_000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+        let encoded = pb::LatencyEvent::from(&event).encode_to_vec();
+        let decoded = pb::LatencyEvent::decode(encoded.as_slice()).unwrap();
+        assert_eq!(decoded.key.as_ref().unwrap().saddr, 0x0a000001);
+        assert_eq!(decoded.key.as_ref().unwrap().sport, 80);
+        let restored = LatencyEvent::from(&decoded);
+        assert_eq!(restored.key.dport, event.key.dport);
+        assert_eq!(restored.http_status_class, 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pb");
+        let mut metrics = LatencyMetrics {
+            total_events: 3,
+            run_id: Some("nightly-42".to_string()),
+            ..Default::default()
+        };
+        metrics.histogram.add_sample(2_000.0);
+        metrics.services.insert("redis".to_string(), GroupMetrics::default());
+        ProtobufExporter::new(path.clone()).export(&metrics).unwrap();
+
+        let report = pb::LatencyMetrics::decode(std::fs::read(&path).unwrap().as_slice()).unwrap();
+        assert_eq!(report.total_events, 3);
+        assert_eq!(report.run_id, "nightly-42");
+        let histogram = report.histogram.unwrap();
+        assert_eq!(histogram.upper_bounds_us.len(), 5);
+        assert_eq!(histogram.counts, vec![0, 1, 0, 0, 0, 0]);
+        assert!(report.services.contains_key("redis"));
+    }
+}
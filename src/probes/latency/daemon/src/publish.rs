@@ -0,0 +1,521 @@
+//! Delivery of reports to several destinations
+//!
+//! Snapshots and finished reports go to every configured destination: the
+//! node_exporter textfile, Zabbix, NATS. A file write is quick, but a
+//! network exporter can stall on a slow or unreachable server, so
+//! exporters are driven as futures ([`AsyncMetricsExporter`]) with a
+//! [`RetryPolicy`] each: every attempt is bounded by a timeout, failed
+//! attempts of a finished report are retried with exponential backoff, and
+//! a destination that still fails does not keep the report from the others.
+//!
+//! Synchronous [`MetricsExporter`]s are adapted with [`Blocking`], which
+//! runs them on the blocking thread pool so they never stall the runtime.
+//! A blocking call cannot be cancelled, so an attempt that timed out keeps
+//! running, and further attempts fail until it returns rather than pile up
+//! beside it.
+//!
+//! An [`ExportQueue`] runs a publisher on a thread with its own runtime, so
+//! exports never compete with the perf buffer readers for worker threads.
+//! The collection loop hands reports over a bounded channel: a snapshot
+//! that finds the queue full is dropped (the next one supersedes it), a
+//! finished report waits for room.
+
+use crate::{
+    exporter::MetricsExporter,
+    types::{ExportStats, LatencyMetrics},
+};
+use anyhow::{Context, Result};
+use log::warn;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// Reports the export queue holds by default
+pub const DEFAULT_EXPORT_QUEUE: u32 = 16;
+
+/// Future of an asynchronous export
+pub type ExportFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Exporter that may wait on I/O
+pub trait AsyncMetricsExporter: Send + Sync {
+    /// Export a finished (rotated or final) report
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Report to export
+    fn export<'a>(&'a self, metrics: &'a LatencyMetrics) -> ExportFuture<'a>;
+
+    /// Export an interval snapshot (by default, like a report)
+    fn export_snapshot<'a>(&'a self, metrics: &'a LatencyMetrics) -> ExportFuture<'a> {
+        self.export(metrics)
+    }
+}
+
+/// Synchronous exporter run on the blocking thread pool, one call at a
+/// time
+pub struct Blocking<E> {
+    exporter: Arc<E>,
+    running: Arc<AtomicBool>,
+}
+
+impl<E: MetricsExporter + Send + Sync + 'static> Blocking<E> {
+    /// Adapt a synchronous exporter
+    pub fn new(exporter: E) -> Self {
+        Self {
+            exporter: Arc::new(exporter),
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Marks a blocking export as finished when dropped, even on a panic
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl<E: MetricsExporter + Send + Sync + 'static> AsyncMetricsExporter for Blocking<E> {
+    fn export<'a>(&'a self, metrics: &'a LatencyMetrics) -> ExportFuture<'a> {
+        let exporter = self.exporter.clone();
+        let metrics = metrics.clone();
+        Box::pin(async move {
+            if self.running.swap(true, Ordering::AcqRel) {
+                anyhow::bail!("The previous export is still running");
+            }
+            let running = Running(self.running.clone());
+            tokio::task::spawn_blocking(move || {
+                let _running = running;
+                exporter.export(&metrics)
+            })
+            .await
+            .context("Exporter panicked")?
+        })
+    }
+}
+
+/// How long an exporter may take, and how often it is tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts at a finished report (snapshots get one)
+    pub attempts: u32,
+    /// Time limit of each attempt
+    pub timeout: Duration,
+    /// Delay before the first retry, doubled for each further one
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            timeout: Duration::from_secs(10),
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Export with this policy
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Destination name, for logs and errors
+    /// * `attempt` - Starts one attempt
+    /// * `attempts` - Attempts to make
+    pub(crate) async fn run<'a>(&self, name: &str, attempt: impl Fn() -> ExportFuture<'a>, attempts: u32) -> Result<()> {
+        let mut delay = self.backoff;
+        let mut tried = 1;
+        loop {
+            let result = match tokio::time::timeout(self.timeout, attempt()).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("Timed out after {:?}", self.timeout)),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if tried < attempts => {
+                    warn!("Exporting to {} failed (attempt {}): {:#}", name, tried, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    tried += 1;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to export to {} after {} attempts", name, tried))
+                }
+            }
+        }
+    }
+}
+
+/// Exporter with its name and policy
+struct Destination {
+    name: String,
+    exporter: Box<dyn AsyncMetricsExporter>,
+    policy: RetryPolicy,
+}
+
+/// Destinations of snapshots and finished reports
+#[derive(Default)]
+pub struct Publisher {
+    destinations: Vec<Destination>,
+}
+
+impl Publisher {
+    /// Create a publisher without destinations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a destination
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Destination name, for logs and errors
+    /// * `exporter` - Exporter to the destination
+    /// * `policy` - Timeout and retries of the exporter
+    pub fn add(&mut self, name: impl Into<String>, exporter: impl AsyncMetricsExporter + 'static, policy: RetryPolicy) {
+        self.destinations.push(Destination {
+            name: name.into(),
+            exporter: Box::new(exporter),
+            policy,
+        });
+    }
+
+    /// Whether there are no destinations
+    pub fn is_empty(&self) -> bool {
+        self.destinations.is_empty()
+    }
+
+    /// Export an interval snapshot to every destination, once each
+    ///
+    /// Failures are logged; the next snapshot supersedes this one anyway.
+    ///
+    /// # Returns
+    ///
+    /// Whether every destination took the snapshot
+    pub async fn snapshot(&self, metrics: &LatencyMetrics) -> bool {
+        let mut exported = true;
+        for destination in &self.destinations {
+            let attempt = || destination.exporter.export_snapshot(metrics);
+            if let Err(e) = destination.policy.run(&destination.name, attempt, 1).await {
+                warn!("{:#}", e);
+                exported = false;
+            }
+        }
+        exported
+    }
+
+    /// Export a finished report to every destination
+    ///
+    /// # Returns
+    ///
+    /// An error naming the destinations that failed every attempt, once
+    /// all destinations were tried
+    pub async fn publish(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let mut failed = Vec::new();
+        for destination in &self.destinations {
+            let attempt = || destination.exporter.export(metrics);
+            if let Err(e) = destination.policy.run(&destination.name, attempt, destination.policy.attempts).await {
+                warn!("{:#}", e);
+                failed.push(destination.name.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            anyhow::bail!("Failed to export the report to {}", failed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Work handed to the exporter thread
+enum ExportJob {
+    Snapshot(Box<LatencyMetrics>),
+    Publish(Box<LatencyMetrics>, oneshot::Sender<Result<()>>),
+}
+
+/// Counters shared with the exporter thread
+#[derive(Default)]
+struct QueueCounters {
+    max_depth: AtomicU64,
+    dropped_snapshots: AtomicU64,
+    exports: AtomicU64,
+    failing: AtomicU64,
+    last_latency_us: AtomicU64,
+    max_latency_us: AtomicU64,
+}
+
+/// Publisher running on a dedicated exporter thread
+pub struct ExportQueue {
+    jobs: mpsc::Sender<ExportJob>,
+    counters: Arc<QueueCounters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ExportQueue {
+    /// Start the exporter thread
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher` - Destinations to export to
+    /// * `capacity` - Reports the queue holds before snapshots are dropped
+    pub fn spawn(publisher: Publisher, capacity: usize) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to build the exporter runtime")?;
+        let (jobs, mut queue) = mpsc::channel(capacity.max(1));
+        let counters = Arc::new(QueueCounters::default());
+
+        let thread_counters = Arc::clone(&counters);
+        let thread = std::thread::Builder::new()
+            .name("exporter".to_string())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    while let Some(job) = queue.recv().await {
+                        let started = Instant::now();
+                        let (exported, finished) = match job {
+                            ExportJob::Snapshot(metrics) => (publisher.snapshot(&metrics).await, None),
+                            ExportJob::Publish(metrics, done) => {
+                                let result = publisher.publish(&metrics).await;
+                                (result.is_ok(), Some((done, result)))
+                            }
+                        };
+
+                        let latency_us = started.elapsed().as_micros() as u64;
+                        thread_counters.exports.fetch_add(1, Ordering::Relaxed);
+                        thread_counters.last_latency_us.store(latency_us, Ordering::Relaxed);
+                        thread_counters.max_latency_us.fetch_max(latency_us, Ordering::Relaxed);
+                        if exported {
+                            thread_counters.failing.store(0, Ordering::Relaxed);
+                        } else {
+                            thread_counters.failing.fetch_add(1, Ordering::Relaxed);
+                        }
+                        if let Some((done, result)) = finished {
+                            // The sender may have stopped waiting
+                            let _ = done.send(result);
+                        }
+                    }
+                })
+            })
+            .context("Failed to start the exporter thread")?;
+
+        Ok(Self {
+            jobs,
+            counters,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue an interval snapshot, dropping it if the queue is full
+    pub fn snapshot(&self, metrics: LatencyMetrics) {
+        match self.jobs.try_send(ExportJob::Snapshot(Box::new(metrics))) {
+            Ok(()) => self.record_depth(),
+            Err(_) => {
+                self.counters.dropped_snapshots.fetch_add(1, Ordering::Relaxed);
+                warn!("Export queue full, snapshot dropped");
+            }
+        }
+    }
+
+    /// Queue a finished report and wait until every destination was tried
+    ///
+    /// # Returns
+    ///
+    /// The result of [`Publisher::publish`]
+    pub async fn publish(&self, metrics: LatencyMetrics) -> Result<()> {
+        let (done, result) = oneshot::channel();
+        self.jobs
+            .send(ExportJob::Publish(Box::new(metrics), done))
+            .await
+            .map_err(|_| anyhow::anyhow!("Exporter thread stopped"))?;
+        self.record_depth();
+        result.await.context("Exporter thread stopped")?
+    }
+
+    /// Queue depth and export latency so far
+    pub fn stats(&self) -> ExportStats {
+        let counters = &self.counters;
+        ExportStats {
+            queue_depth: self.depth(),
+            max_queue_depth: counters.max_depth.load(Ordering::Relaxed),
+            queue_capacity: self.jobs.max_capacity() as u64,
+            dropped_snapshots: counters.dropped_snapshots.load(Ordering::Relaxed),
+            exports: counters.exports.load(Ordering::Relaxed),
+            last_latency_ms: counters.last_latency_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            max_latency_ms: counters.max_latency_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+
+    /// Handle reading the health of the exports from other tasks
+    pub fn monitor(&self) -> ExportMonitor {
+        ExportMonitor {
+            counters: Arc::clone(&self.counters),
+        }
+    }
+
+    /// Reports waiting in the queue
+    fn depth(&self) -> u64 {
+        (self.jobs.max_capacity() - self.jobs.capacity()) as u64
+    }
+
+    fn record_depth(&self) {
+        self.counters.max_depth.fetch_max(self.depth(), Ordering::Relaxed);
+    }
+}
+
+/// Health of the exports of an [`ExportQueue`]
+#[derive(Clone)]
+pub struct ExportMonitor {
+    counters: Arc<QueueCounters>,
+}
+
+impl ExportMonitor {
+    /// Exports in a row that some destination failed (0 after a success)
+    pub fn failing(&self) -> u64 {
+        self.counters.failing.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ExportQueue {
+    /// Let queued reports go out before the thread stops
+    fn drop(&mut self) {
+        let (closed, _) = mpsc::channel(1);
+        drop(std::mem::replace(&mut self.jobs, closed));
+        if let Some(Err(e)) = self.thread.take().map(JoinHandle::join) {
+            warn!("Exporter thread panicked: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    /// Fails a number of times, or hangs, before succeeding
+    struct Flaky {
+        failures: u32,
+        hang: bool,
+        calls: Arc<AtomicU32>,
+    }
+
+    impl AsyncMetricsExporter for Flaky {
+        fn export<'a>(&'a self, _metrics: &'a LatencyMetrics) -> ExportFuture<'a> {
+            Box::pin(async move {
+                let call = self.calls.fetch_add(1, Ordering::SeqCst);
+                if self.hang {
+                    std::future::pending::<()>().await;
+                }
+                if call < self.failures {
+                    anyhow::bail!("attempt {} failed", call + 1);
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_retries() {
+        let policy = RetryPolicy {
+            timeout: Duration::from_millis(50),
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let calls: Vec<_> = (0..3).map(|_| Arc::new(AtomicU32::new(0))).collect();
+        let mut publisher = Publisher::new();
+        publisher.add("hanging", Flaky { failures: 0, hang: true, calls: calls[0].clone() }, policy);
+        publisher.add("flaky", Flaky { failures: 2, hang: false, calls: calls[1].clone() }, policy);
+        publisher.add("down", Flaky { failures: u32::MAX, hang: false, calls: calls[2].clone() }, policy);
+
+        // Destinations after a failing one still get the report
+        let error = publisher.publish(&LatencyMetrics::default()).await.unwrap_err();
+        assert_eq!(error.to_string(), "Failed to export the report to hanging, down");
+        let counts: Vec<_> = calls.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(counts, vec![3, 3, 3]);
+
+        // Snapshots are tried once
+        assert!(!publisher.snapshot(&LatencyMetrics::default()).await);
+        assert_eq!(calls[2].load(Ordering::SeqCst), 4);
+    }
+
+    /// Blocks for a while on every call
+    struct Slow {
+        delay: Duration,
+        calls: Arc<AtomicU32>,
+    }
+
+    impl MetricsExporter for Slow {
+        fn export(&self, _metrics: &LatencyMetrics) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(self.delay);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocking_retries_wait_for_the_previous_call() {
+        let policy = RetryPolicy {
+            timeout: Duration::from_millis(20),
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let calls = Arc::new(AtomicU32::new(0));
+        let slow = Blocking::new(Slow { delay: Duration::from_millis(200), calls: calls.clone() });
+        let metrics = LatencyMetrics::default();
+
+        // Retries fail fast while the timed-out call still blocks
+        let error = policy.run("slow", || slow.export(&metrics), policy.attempts).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("still running"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Once it returns, the exporter is called again
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let fast = RetryPolicy { timeout: Duration::from_secs(1), ..policy };
+        fast.run("slow", || slow.export(&metrics), 1).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_export_queue() {
+        let policy = RetryPolicy {
+            attempts: 1,
+            timeout: Duration::from_millis(200),
+            backoff: Duration::from_millis(1),
+        };
+        let calls = Arc::new(AtomicU32::new(0));
+        let mut publisher = Publisher::new();
+        publisher.add("hanging", Flaky { failures: 0, hang: true, calls: calls.clone() }, policy);
+        let queue = ExportQueue::spawn(publisher, 1).unwrap();
+
+        // The exporter thread is busy with the first snapshot and the
+        // second fills the queue, so the third is dropped
+        for _ in 0..3 {
+            queue.snapshot(LatencyMetrics::default());
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let stats = queue.stats();
+        assert_eq!(stats.dropped_snapshots, 1);
+        assert_eq!(stats.queue_capacity, 1);
+        assert_eq!(stats.max_queue_depth, 1);
+
+        // Finished reports wait for room and return the export result
+        let error = queue.publish(LatencyMetrics::default()).await.unwrap_err();
+        assert!(error.to_string().contains("hanging"));
+        let stats = queue.stats();
+        assert_eq!(stats.exports, 3);
+        assert_eq!(stats.queue_depth, 0);
+        assert_eq!(queue.monitor().failing(), 3);
+        assert!(stats.max_latency_ms >= 200.0);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,194 @@
+//! Run registry
+//!
+//! Reports end up wherever `--output` pointed, and after a few dozen
+//! experiments it is hard to tell which run was which. With `--registry
+//! DIR`, every run that finishes adds a record to the directory: its ID,
+//! labels, duration, event counts, percentiles, and where the report went.
+//! `latency-probe runs list` and `runs show <id>` read them back.
+//!
+//! Each record is a JSON file named after the run ID, so the registry can
+//! be shared between nodes (one file per run and instance), synced, or
+//! pruned with ordinary tools.
+
+use crate::types::{LatencyMetrics, Percentiles};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Summary of a finished run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunRecord {
+    /// ID of the run (see crate::run)
+    pub run_id: String,
+    /// When the final report was generated (RFC 3339)
+    pub finished_at: String,
+    /// Collection time in seconds
+    pub duration_seconds: u64,
+    /// Name of the probe instance (None if unnamed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Labels of the report
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Events captured
+    pub total_events: u64,
+    /// Events lost in the ring buffer
+    pub lost_events: u64,
+    /// Latency percentiles in microseconds
+    pub percentiles: Percentiles,
+    /// Where the report was written (None for stdout)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<PathBuf>,
+}
+
+impl RunRecord {
+    /// Record of a final report
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Final report of the run, with a run ID
+    /// * `report` - Where the report was written (None for stdout)
+    pub fn from_metrics(metrics: &LatencyMetrics, report: Option<&Path>) -> Result<Self> {
+        let run_id = metrics.run_id.clone().context("Report has no run ID")?;
+        Ok(Self {
+            run_id,
+            finished_at: metrics.timestamp.clone(),
+            duration_seconds: metrics.duration_seconds,
+            instance: metrics.instance.clone(),
+            labels: metrics.labels.clone(),
+            total_events: metrics.total_events,
+            lost_events: metrics.lost_events,
+            percentiles: metrics.percentiles.clone(),
+            report: report.map(|path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())),
+        })
+    }
+
+    /// File name of the record (the run ID, plus the instance if named;
+    /// neither may contain `@`)
+    fn file_name(&self) -> String {
+        match &self.instance {
+            Some(instance) => format!("{}@{}.json", self.run_id, instance),
+            None => format!("{}.json", self.run_id),
+        }
+    }
+}
+
+/// Directory of run records
+pub struct RunRegistry {
+    dir: PathBuf,
+}
+
+impl RunRegistry {
+    /// Open a registry directory, which need not exist yet
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Add (or replace) the record of a run
+    ///
+    /// The record is written to a temporary file and renamed into place,
+    /// so readers never see half a record.
+    pub fn add(&self, record: &RunRecord) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create run registry: {:?}", self.dir))?;
+
+        let path = self.dir.join(record.file_name());
+        let tmp_path = self.dir.join(format!(".{}.tmp", record.file_name()));
+        let json = serde_json::to_vec_pretty(record).context("Failed to serialize run record")?;
+        std::fs::write(&tmp_path, json).with_context(|| format!("Failed to write run record: {:?}", tmp_path))?;
+        std::fs::rename(&tmp_path, &path).with_context(|| format!("Failed to write run record: {:?}", path))?;
+        Ok(path)
+    }
+
+    /// Every record, oldest first (an empty list if the directory does not
+    /// exist)
+    pub fn list(&self) -> Result<Vec<RunRecord>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read run registry: {:?}", self.dir)),
+        };
+
+        let mut records = Vec::new();
+        for entry in entries {
+            let path = entry.with_context(|| format!("Failed to read run registry: {:?}", self.dir))?.path();
+            let is_record = path.extension().is_some_and(|ext| ext == "json")
+                && !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if !is_record {
+                continue;
+            }
+            let contents =
+                std::fs::read(&path).with_context(|| format!("Failed to read run record: {:?}", path))?;
+            let record: RunRecord =
+                serde_json::from_slice(&contents).with_context(|| format!("Invalid run record: {:?}", path))?;
+            records.push(record);
+        }
+        records.sort_by(|a, b| (&a.finished_at, &a.run_id).cmp(&(&b.finished_at, &b.run_id)));
+        Ok(records)
+    }
+
+    /// Records of one run (one per instance)
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Run ID, or a prefix of it that only one run starts with
+    pub fn find(&self, id: &str) -> Result<Vec<RunRecord>> {
+        let records: Vec<RunRecord> = self
+            .list()?
+            .into_iter()
+            .filter(|record| record.run_id.starts_with(id))
+            .collect();
+
+        let mut run_ids: Vec<&str> = records.iter().map(|record| record.run_id.as_str()).collect();
+        run_ids.sort_unstable();
+        run_ids.dedup();
+        match run_ids.as_slice() {
+            [] => anyhow::bail!("No run {} in {:?}", id, self.dir),
+            [_] => Ok(records),
+            _ if run_ids.contains(&id) => Ok(records.into_iter().filter(|record| record.run_id == id).collect()),
+            _ => anyhow::bail!("Run ID prefix {} matches {} runs: {}", id, run_ids.len(), run_ids.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = RunRegistry::new(dir.path().join("runs"));
+        assert!(registry.list().unwrap().is_empty());
+
+        let record = |run_id: &str, finished_at: &str, instance: Option<&str>| {
+            let metrics = LatencyMetrics {
+                run_id: Some(run_id.to_string()),
+                timestamp: finished_at.to_string(),
+                instance: instance.map(str::to_string),
+                total_events: 10,
+                ..Default::default()
+            };
+            RunRecord::from_metrics(&metrics, Some(Path::new("report.json"))).unwrap()
+        };
+        registry.add(&record("b-run", "2025-01-02T00:00:00+00:00", None)).unwrap();
+        registry.add(&record("a-run", "2025-01-03T00:00:00+00:00", Some("node-1"))).unwrap();
+        registry.add(&record("a-run", "2025-01-03T00:00:00+00:00", Some("node-2"))).unwrap();
+        registry.add(&record("a-run-2", "2025-01-01T00:00:00+00:00", None)).unwrap();
+
+        let listed: Vec<String> = registry.list().unwrap().into_iter().map(|r| r.run_id).collect();
+        assert_eq!(listed, ["a-run-2", "b-run", "a-run", "a-run"]);
+        assert!(registry.list().unwrap()[0].report.as_ref().unwrap().is_absolute());
+
+        assert_eq!(registry.find("b").unwrap().len(), 1);
+        // An exact ID wins over the longer IDs it prefixes
+        assert_eq!(registry.find("a-run").unwrap().len(), 2);
+        assert!(registry.find("a-").is_err());
+        assert!(registry.find("c").is_err());
+
+        assert!(RunRecord::from_metrics(&LatencyMetrics::default(), None).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Event recording and replay
+//!
+//! Records sampled latency events to JSON Lines files and reads them back,
+//! so the collector and exporters can be exercised on machines without
+//! eBPF privileges. Recordings may be gzip or zstd compressed (see
+//! crate::compress).
+
+use crate::{
+    compress::{self, Compression, OutputWriter},
+    run::RunId,
+    types::{ConnectionKey, LatencyEvent},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, Lines, Write},
+    net::SocketAddrV4,
+    path::Path,
+    sync::Mutex,
+};
+
+/// JSON Lines representation of a latency event
+///
+/// Addresses are stored as "ip:port" strings so recordings stay readable
+/// and can be written by hand for tests.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordedEvent {
+    /// Source address:port
+    pub source: String,
+    /// Destination address:port
+    pub destination: String,
+    /// Timestamp when event occurred (nanoseconds)
+    pub timestamp_ns: u64,
+    /// Measured latency (nanoseconds)
+    pub latency_ns: u64,
+    /// Process ID that triggered the event
+    pub pid: u32,
+    /// Type of event (see EVENT_TYPE_* constants)
+    pub event_type: u8,
+    /// Network namespace inode (0 if unknown)
+    #[serde(default)]
+    pub netns: u32,
+    /// Socket cookie (0 if unknown)
+    #[serde(default)]
+    pub cookie: u64,
+    /// HTTP status class of the last response (0 if unknown)
+    #[serde(default)]
+    pub http_status_class: u8,
+    /// TCP state of the socket (0 if unknown)
+    #[serde(default)]
+    pub tcp_state: u8,
+    /// SYN/FIN/RST indicators
+    #[serde(default)]
+    pub tcp_flags: u8,
+    /// Transport protocol number (0 if unknown)
+    #[serde(default)]
+    pub protocol: u8,
+    /// ID of the run the event was recorded in (None if unknown)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+impl From<&LatencyEvent> for RecordedEvent {
+    fn from(event: &LatencyEvent) -> Self {
+        let source = SocketAddrV4::new(
+            u32::from_be(event.key.saddr).into(),
+            u16::from_be(event.key.sport),
+        );
+        let destination = SocketAddrV4::new(
+            u32::from_be(event.key.daddr).into(),
+            u16::from_be(event.key.dport),
+        );
+
+        Self {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            timestamp_ns: event.timestamp_ns,
+            latency_ns: event.latency_ns,
+            pid: event.pid,
+            event_type: event.event_type,
+            netns: event.netns,
+            cookie: event.cookie,
+            http_status_class: event.http_status_class,
+            tcp_state: event.tcp_state,
+            tcp_flags: event.tcp_flags,
+            protocol: event.protocol,
+            run_id: None,
+        }
+    }
+}
+
+impl TryFrom<&RecordedEvent> for LatencyEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(recorded: &RecordedEvent) -> Result<Self> {
+        let source: SocketAddrV4 = recorded
+            .source
+            .parse()
+            .with_context(|| format!("Invalid source address: {}", recorded.source))?;
+        let destination: SocketAddrV4 = recorded
+            .destination
+            .parse()
+            .with_context(|| format!("Invalid destination address: {}", recorded.destination))?;
+
+        Ok(LatencyEvent {
+            key: ConnectionKey {
+                saddr: u32::from(*source.ip()).to_be(),
+                daddr: u32::from(*destination.ip()).to_be(),
+                sport: source.port().to_be(),
+                dport: destination.port().to_be(),
+            },
+            netns: recorded.netns,
+            cookie: recorded.cookie,
+            timestamp_ns: recorded.timestamp_ns,
+            latency_ns: recorded.latency_ns,
+            pid: recorded.pid,
+            event_type: recorded.event_type,
+            http_status_class: recorded.http_status_class,
+            tcp_state: recorded.tcp_state,
+            tcp_flags: recorded.tcp_flags,
+            protocol: recorded.protocol,
+            _padding: [0; 7],
+        })
+    }
+}
+
+/// Writes latency events to a JSON Lines file
+///
+/// Safe to share between reader tasks; register it with
+/// [`EventProcessor::register_callback`](crate::events::EventProcessor::register_callback).
+pub struct EventRecorder {
+    writer: Mutex<OutputWriter>,
+    run_id: Option<RunId>,
+}
+
+impl EventRecorder {
+    /// Create a recorder, truncating any existing file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the JSON Lines output file
+    pub fn create(path: &Path) -> Result<Self> {
+        Self::create_with(path, Compression::None)
+    }
+
+    /// Create a recorder that compresses events as they are written
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the output file (conventionally ending in .gz or .zst)
+    /// * `compression` - Compression to apply
+    pub fn create_with(path: &Path, compression: Compression) -> Result<Self> {
+        let writer = OutputWriter::create(path, compression)
+            .with_context(|| format!("Failed to create recording file: {:?}", path))?;
+
+        Ok(Self {
+            writer: Mutex::new(writer),
+            run_id: None,
+        })
+    }
+
+    /// Stamp recorded events with the ID of the run
+    pub fn with_run_id(mut self, run_id: RunId) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Append one event to the recording
+    pub fn record(&self, event: &LatencyEvent) -> Result<()> {
+        let mut recorded = RecordedEvent::from(event);
+        recorded.run_id = self.run_id.as_ref().map(RunId::to_string);
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Recorder lock poisoned"))?;
+
+        serde_json::to_writer(&mut *writer, &recorded)?;
+        writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    /// Flush buffered events to disk
+    pub fn flush(&self) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Recorder lock poisoned"))?;
+
+        writer.flush().context("Failed to flush recording file")
+    }
+
+    /// Flush buffered events and end a compressed stream
+    ///
+    /// Events recorded afterwards are not written.
+    pub fn finish(&self) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Recorder lock poisoned"))?;
+
+        writer.finish().context("Failed to finish recording file")
+    }
+}
+
+/// Reads latency events back from a JSON Lines recording
+pub struct EventReader {
+    lines: Lines<Box<dyn BufRead + Send>>,
+    line_number: usize,
+}
+
+impl EventReader {
+    /// Open a recording for reading
+    ///
+    /// Files ending in .gz or .zst are decompressed as they are read.
+    pub fn open(path: &Path) -> Result<Self> {
+        let reader = compress::open(path)
+            .with_context(|| format!("Failed to open recording file: {:?}", path))?;
+
+        Ok(Self {
+            lines: reader.lines(),
+            line_number: 0,
+        })
+    }
+}
+
+impl Iterator for EventReader {
+    type Item = Result<LatencyEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line_number += 1;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let line_number = self.line_number;
+            let event = serde_json::from_str::<RecordedEvent>(&line)
+                .map_err(anyhow::Error::from)
+                .and_then(|recorded| LatencyEvent::try_from(&recorded))
+                .with_context(|| format!("Invalid event on line {}", line_number));
+
+            return Some(event);
+        }
+    }
+}
+
+/// Summary of a completed replay
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReplaySummary {
+    /// Events read from the recording (before sampling)
+    pub events: u64,
+    /// Earliest event timestamp (nanoseconds)
+    pub first_timestamp_ns: u64,
+    /// Latest event timestamp (nanoseconds)
+    pub last_timestamp_ns: u64,
+}
+
+impl ReplaySummary {
+    /// Account for an event read from the recording
+    pub fn observe(&mut self, event: &LatencyEvent) {
+        if self.events == 0 || event.timestamp_ns < self.first_timestamp_ns {
+            self.first_timestamp_ns = event.timestamp_ns;
+        }
+        self.last_timestamp_ns = self.last_timestamp_ns.max(event.timestamp_ns);
+        self.events += 1;
+    }
+
+    /// Time spanned by the recording, rounded up to whole seconds
+    pub fn duration_seconds(&self) -> u64 {
+        let span_ns = self.last_timestamp_ns - self.first_timestamp_ns;
+        span_ns.div_ceil(1_000_000_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_recorded_event_round_trip() {
+        let event = LatencyEvent {
+            netns: 4026531840,
+            cookie: 4097,
+            timestamp_ns: 1_000_000,
+            pid: 1234,
+            http_status_class: 5,
+            tcp_state: probe_common::constants::TCP_STATE_CLOSE_WAIT,
+            tcp_flags: probe_common::constants::TCP_FLAG_FIN,
+            protocol: probe_common::constants::IPPROTO_TCP,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a, // 10.0.0.1 in network byte order
+                    daddr: 0x0100007f, // 127.0.0.1 in network byte order
+                    sport: 0x5000,     // Port 80 in network byte order
+                    dport: 0x901f,     // Port 8080 in network byte order
+                },
+                500_000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+
+        let recorded = RecordedEvent::from(&event);
+        assert_eq!(recorded.source, "10.0.0.1:80");
+        assert_eq!(recorded.destination, "127.0.0.1:8080");
+
+        let restored = LatencyEvent::try_from(&recorded).unwrap();
+        assert_eq!(restored.key.saddr, event.key.saddr);
+        assert_eq!(restored.key.dport, event.key.dport);
+        assert_eq!(restored.latency_ns, event.latency_ns);
+        assert_eq!(restored.netns, event.netns);
+        assert_eq!(restored.http_status_class, 5);
+        assert_eq!(restored.tcp_state, event.tcp_state);
+        assert_eq!(restored.tcp_flags, event.tcp_flags);
+        assert_eq!(restored.protocol, event.protocol);
+        assert_eq!(restored.cookie, 4097);
+    }
+
+    #[test]
+    fn test_replay_summary_duration() {
+        let mut summary = ReplaySummary::default();
+        let mut event = LatencyEvent::try_from(&RecordedEvent {
+            source: "10.0.0.1:80".to_string(),
+            destination: "10.0.0.2:8080".to_string(),
+            timestamp_ns: 5_000_000_000,
+            latency_ns: 1_000,
+            pid: 1,
+            event_type: 1,
+            netns: 0,
+            cookie: 0,
+            http_status_class: 0,
+            tcp_state: 0,
+            tcp_flags: 0,
+            protocol: 0,
+            run_id: None,
+        })
+        .unwrap();
+
+        summary.observe(&event);
+        event.timestamp_ns = 7_500_000_000;
+        summary.observe(&event);
+
+        assert_eq!(summary.events, 2);
+        assert_eq!(summary.duration_seconds(), 3);
+    }
+}
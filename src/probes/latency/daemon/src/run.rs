@@ -0,0 +1,106 @@
+//! Run identifiers
+//!
+//! A run leaves artifacts in several places: the report, the exports to
+//! Prometheus, InfluxDB, Zabbix or NATS, the event recordings, and the
+//! logs. Each run gets an ID (a random UUID unless `--run-id` names it),
+//! and every one of them carries it, so they can be joined afterwards:
+//!
+//! - reports have a `run_id` field; Prometheus and OpenMetrics samples
+//!   have a `run_id` label, and InfluxDB points a `run_id` tag
+//! - recorded and streamed events have a `run_id` field (a schema metadata
+//!   key in Arrow recordings)
+//! - log lines show `run=<id>`
+//!
+//! A run resumed from a checkpoint keeps the ID it was started with.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Label (and field) name of the run ID in exported metrics
+pub const RUN_ID_LABEL: &str = "run_id";
+
+/// Longest run ID
+const MAX_RUN_ID_LEN: usize = 64;
+
+/// Identifier of a run
+///
+/// ASCII letters, digits, `-`, `_` and `.`, so it is valid as a label
+/// value, InfluxDB tag, and file name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RunId(String);
+
+impl RunId {
+    /// New random ID (a version 4 UUID)
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Run ID
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for RunId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let valid = !s.is_empty()
+            && s.len() <= MAX_RUN_ID_LEN
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            anyhow::bail!(
+                "Invalid run ID '{}': use up to {} letters, digits, '-', '_' and '.'",
+                s,
+                MAX_RUN_ID_LEN
+            );
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for RunId {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<RunId> for String {
+    fn from(run_id: RunId) -> Self {
+        run_id.0
+    }
+}
+
+impl std::fmt::Display for RunId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_id() {
+        let generated = RunId::generate();
+        assert_eq!(generated.as_str().len(), 36);
+        assert_ne!(generated, RunId::generate());
+        assert_eq!(generated.as_str().parse::<RunId>().unwrap(), generated);
+
+        assert!("nightly-2025.01.01_a".parse::<RunId>().is_ok());
+        assert!("".parse::<RunId>().is_err());
+        assert!("a b".parse::<RunId>().is_err());
+        assert!("a\"b".parse::<RunId>().is_err());
+        assert!("x".repeat(65).parse::<RunId>().is_err());
+
+        // Checkpoints hold the ID as a string
+        let bytes = bincode::serialize(&generated).unwrap();
+        assert_eq!(bincode::deserialize::<RunId>(&bytes).unwrap(), generated);
+        assert!(bincode::deserialize::<RunId>(&bincode::serialize("a b").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! Benchmark scenarios
+//!
+//! A scenario sequences the phases of a service mesh benchmark (e.g. a
+//! baseline without the mesh, the same load with sidecars injected, then
+//! with faults or more replicas) in one `latency-probe scenario run`. The
+//! probes stay attached for the whole scenario; each phase runs its setup
+//! commands, applies its own sampling and filters, and gets its own report
+//! labeled with the phase name. The phases are then compared against the
+//! baseline.
+//!
+//! ## Example
+//!
+//! ```yaml
+//! name: istio-rollout
+//! phases:
+//!   - name: baseline
+//!     duration: 120
+//!     filter_services: ["*:8080"]
+//!     labels: { mesh: none }
+//!   - name: mesh
+//!     setup:
+//!       - kubectl label namespace shop istio-injection=enabled --overwrite
+//!       - kubectl rollout restart deploy -n shop && kubectl rollout status deploy -n shop
+//!     warmup: 30
+//!     duration: 120
+//!     labels: { mesh: istio }
+//!   - name: scale-out
+//!     setup:
+//!       - kubectl scale deploy/frontend -n shop --replicas 6
+//!     warmup: 30
+//!     duration: 120
+//!     labels: { mesh: istio, replicas: "6" }
+//! ```
+
+use crate::{
+    config::ProbeConfig,
+    output::Output,
+    types::LatencyMetrics,
+};
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// Label holding the scenario name in phase reports
+pub const LABEL_SCENARIO: &str = "scenario";
+
+/// Label holding the phase name in phase reports
+pub const LABEL_PHASE: &str = "phase";
+
+/// Name in the path of the comparison report, which phases cannot use
+pub const COMPARISON: &str = "comparison";
+
+/// A sequence of benchmark phases
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scenario {
+    /// Scenario name
+    pub name: String,
+    /// Phase the others are compared against (default: the first)
+    #[serde(default)]
+    pub baseline: Option<String>,
+    /// Phases, in the order they run
+    pub phases: Vec<Phase>,
+}
+
+/// One phase of a scenario
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Phase {
+    /// Phase name, also used in report file names
+    pub name: String,
+    /// Seconds of measurement
+    pub duration: u64,
+    /// Seconds to wait after setup before measuring (e.g. for pods to
+    /// become ready and connection pools to fill)
+    #[serde(default)]
+    pub warmup: u64,
+    /// Shell commands run in order before the phase (any failure aborts
+    /// the scenario)
+    #[serde(default)]
+    pub setup: Vec<String>,
+    /// Sampling rate for this phase (default: the probe's)
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    /// Sampling rates of single event types for this phase (default: the
+    /// probe's)
+    #[serde(default)]
+    pub event_sample_rates: Option<BTreeMap<String, u32>>,
+    /// Services tracked in this phase (default: the probe's)
+    #[serde(default)]
+    pub filter_services: Option<Vec<String>>,
+    /// Labels attached to the phase's report
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Scenario {
+    /// Load a scenario from a YAML (or JSON) file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the scenario file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario: {:?}", path))?;
+        let scenario: Self = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse scenario: {:?}", path))?;
+
+        scenario
+            .validate()
+            .with_context(|| format!("Invalid scenario: {:?}", path))?;
+        Ok(scenario)
+    }
+
+    /// Check that the phases can run and be told apart
+    pub fn validate(&self) -> Result<()> {
+        if self.phases.is_empty() {
+            anyhow::bail!("Scenario has no phases");
+        }
+
+        let mut names = HashSet::new();
+        for phase in &self.phases {
+            let valid_name = !phase.name.is_empty()
+                && phase
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                anyhow::bail!("Invalid phase name '{}' (use letters, digits, - and _)", phase.name);
+            }
+            if phase.name == COMPARISON || !names.insert(phase.name.as_str()) {
+                anyhow::bail!("Duplicate phase name '{}'", phase.name);
+            }
+            if phase.duration == 0 {
+                anyhow::bail!("Phase '{}' has no duration", phase.name);
+            }
+            phase
+                .config(&ProbeConfig::default())
+                .validate()
+                .with_context(|| format!("Invalid settings in phase '{}'", phase.name))?;
+        }
+
+        if let Some(baseline) = &self.baseline {
+            if !names.contains(baseline.as_str()) {
+                anyhow::bail!("Baseline phase '{}' not found", baseline);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Name of the phase the others are compared against
+    pub fn baseline(&self) -> &str {
+        self.baseline.as_deref().unwrap_or(&self.phases[0].name)
+    }
+}
+
+impl Phase {
+    /// Probe settings for this phase
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - Settings of the probe, used for anything the phase does
+    ///   not set
+    pub fn config(&self, base: &ProbeConfig) -> ProbeConfig {
+        ProbeConfig {
+            sample_rate: self.sample_rate.unwrap_or(base.sample_rate),
+            sample_mode: base.sample_mode,
+            event_sample_rates: self
+                .event_sample_rates
+                .clone()
+                .unwrap_or_else(|| base.event_sample_rates.clone()),
+            filter_services: self
+                .filter_services
+                .clone()
+                .unwrap_or_else(|| base.filter_services.clone()),
+        }
+    }
+
+    /// Labels of the phase's report, with the scenario and phase names
+    pub fn report_labels(&self, scenario: &str) -> BTreeMap<String, String> {
+        let mut labels = self.labels.clone();
+        labels.insert(LABEL_SCENARIO.to_string(), scenario.to_string());
+        labels.insert(LABEL_PHASE.to_string(), self.name.clone());
+        labels
+    }
+
+    /// Run the setup commands with `sh -c`, stopping at the first failure
+    pub async fn run_setup(&self) -> Result<()> {
+        for command in &self.setup {
+            info!("  $ {}", command);
+            let status = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .status()
+                .await
+                .with_context(|| format!("Failed to run setup command: {}", command))?;
+
+            if !status.success() {
+                anyhow::bail!("Setup command of phase '{}' failed ({}): {}", self.name, status, command);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Report path of a phase: the output path with the phase name before the
+/// extension (results.json -> results.baseline.json)
+pub fn phase_path(output: &Path, phase: &str) -> PathBuf {
+    // Reports on stdout follow one another
+    if crate::output::is_stdout(output) {
+        return output.to_path_buf();
+    }
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match output.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, phase, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, phase),
+    };
+
+    output.with_file_name(name)
+}
+
+/// Latency of one phase relative to the baseline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PhaseComparison {
+    /// Phase name
+    pub phase: String,
+    /// Labels of the phase's report
+    pub labels: BTreeMap<String, String>,
+    /// Seconds of measurement
+    pub duration_seconds: u64,
+    /// Events measured
+    pub total_events: u64,
+    /// Median latency in microseconds
+    pub p50_us: f64,
+    /// 99th percentile latency in microseconds
+    pub p99_us: f64,
+    /// 99.9th percentile latency in microseconds
+    pub p999_us: f64,
+    /// Change of the median from the baseline, in percent (None for the
+    /// baseline itself, or a baseline without events)
+    pub p50_change_pct: Option<f64>,
+    /// Change of p99 from the baseline, in percent
+    pub p99_change_pct: Option<f64>,
+}
+
+/// Phase-by-phase comparison of a scenario run
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScenarioSummary {
+    /// Scenario name
+    pub scenario: String,
+    /// Phase the others are compared against
+    pub baseline: String,
+    /// Phases, in the order they ran (fewer than the scenario's if the run
+    /// was interrupted)
+    pub phases: Vec<PhaseComparison>,
+}
+
+impl ScenarioSummary {
+    /// Compare the reports of each phase with the baseline's
+    ///
+    /// # Arguments
+    ///
+    /// * `scenario` - Scenario that was run
+    /// * `reports` - Report of each phase that ran, labeled with
+    ///   [`LABEL_PHASE`]
+    pub fn compare(scenario: &Scenario, reports: &[LatencyMetrics]) -> Self {
+        let phase_of = |metrics: &LatencyMetrics| metrics.labels.get(LABEL_PHASE).cloned().unwrap_or_default();
+        let baseline = reports
+            .iter()
+            .find(|metrics| phase_of(metrics) == scenario.baseline())
+            .map(|metrics| &metrics.percentiles);
+
+        let change = |value: f64, base: Option<f64>| match base {
+            Some(base) if base > 0.0 => Some((value - base) / base * 100.0),
+            _ => None,
+        };
+
+        let phases = reports
+            .iter()
+            .map(|metrics| {
+                let phase = phase_of(metrics);
+                let base = baseline.filter(|_| phase != scenario.baseline());
+                PhaseComparison {
+                    labels: metrics.labels.clone(),
+                    duration_seconds: metrics.duration_seconds,
+                    total_events: metrics.total_events,
+                    p50_us: metrics.percentiles.p50,
+                    p99_us: metrics.percentiles.p99,
+                    p999_us: metrics.percentiles.p999,
+                    p50_change_pct: change(metrics.percentiles.p50, base.map(|p| p.p50)),
+                    p99_change_pct: change(metrics.percentiles.p99, base.map(|p| p.p99)),
+                    phase,
+                }
+            })
+            .collect();
+
+        Self {
+            scenario: scenario.name.clone(),
+            baseline: scenario.baseline().to_string(),
+            phases,
+        }
+    }
+
+    /// Write the comparison as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize scenario summary")?;
+        Output::from(path.to_path_buf())
+            .write_all(json.as_bytes())
+            .context("Failed to write scenario summary")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenario_comparison() {
+        let scenario: Scenario = serde_yaml::from_str(
+            "name: rollout\n\
+             phases:\n\
+             \x20 - name: baseline\n\
+             \x20   duration: 60\n\
+             \x20   labels: { mesh: none }\n\
+             \x20 - name: mesh\n\
+             \x20   duration: 60\n\
+             \x20   warmup: 10\n\
+             \x20   sample_rate: 10\n\
+             \x20   setup: [\"true\"]\n",
+        )
+        .unwrap();
+        scenario.validate().unwrap();
+        assert_eq!(scenario.baseline(), "baseline");
+
+        let base = ProbeConfig {
+            sample_rate: 1,
+            filter_services: vec!["*:8080".to_string()],
+            ..Default::default()
+        };
+        let config = scenario.phases[1].config(&base);
+        assert_eq!(config.sample_rate, 10);
+        assert_eq!(config.filter_services, base.filter_services);
+
+        let report = |phase: &Phase, p50: f64, p99: f64| {
+            LatencyMetrics {
+                labels: phase.report_labels(&scenario.name),
+                percentiles: crate::types::Percentiles {
+                    p50,
+                    p99,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        };
+        let reports = [
+            report(&scenario.phases[0], 100.0, 400.0),
+            report(&scenario.phases[1], 150.0, 1000.0),
+        ];
+        assert_eq!(reports[0].labels["mesh"], "none");
+        assert_eq!(reports[1].labels[LABEL_SCENARIO], "rollout");
+
+        let summary = ScenarioSummary::compare(&scenario, &reports);
+        assert_eq!(summary.phases[0].p99_change_pct, None);
+        assert_eq!(summary.phases[1].p50_change_pct, Some(50.0));
+        assert_eq!(summary.phases[1].p99_change_pct, Some(150.0));
+
+        assert_eq!(
+            phase_path(Path::new("/tmp/results.json"), "mesh"),
+            PathBuf::from("/tmp/results.mesh.json")
+        );
+
+        let mut invalid = scenario.clone();
+        invalid.phases[1].name = "baseline".to_string();
+        assert!(invalid.validate().is_err());
+        invalid.phases[1].name = "with space".to_string();
+        assert!(invalid.validate().is_err());
+        invalid.phases.clear();
+        assert!(invalid.validate().is_err());
+    }
+}
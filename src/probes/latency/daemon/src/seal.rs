@@ -0,0 +1,220 @@
+//! Signing and encryption of exported artifacts
+//!
+//! With the `seal` feature, the files a run publishes (the report,
+//! recordings, the `--stream` file, and the `--upload-file`s) can be made
+//! tamper-evident and confidential before they leave the cluster:
+//!
+//! - `--encrypt-to age1...` (repeatable) writes an [age] encrypted copy,
+//!   `<file>.age`, that any of the recipients can decrypt
+//! - `--sign-key key.pem` signs each published file (the encrypted copy if
+//!   there is one) with an Ed25519 key, writing the raw 64 byte signature
+//!   to `<file>.sig`
+//!
+//! The originals are left in place; only the sealed files are uploaded.
+//! Keys are PKCS#8 PEM files, as written by `openssl genpkey -algorithm
+//! ed25519`, so signatures can be checked with `latency-probe verify` or
+//! with OpenSSL alone.
+//!
+//! [age]: https://age-encryption.org
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    Signature, Signer, SigningKey, Verifier, VerifyingKey,
+};
+use log::info;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Extension of encrypted copies
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Extension of signatures
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// Signs and encrypts files (does nothing without a key or recipients)
+#[derive(Default)]
+pub struct Sealer {
+    signing_key: Option<SigningKey>,
+    recipients: Vec<age::x25519::Recipient>,
+}
+
+impl Sealer {
+    /// Create a sealer that leaves files as they are
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sign with the Ed25519 key of a PKCS#8 PEM file
+    pub fn with_signing_key(mut self, path: &Path) -> Result<Self> {
+        let pem = std::fs::read_to_string(path).with_context(|| format!("Failed to read signing key: {:?}", path))?;
+        let key = SigningKey::from_pkcs8_pem(&pem)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Invalid Ed25519 signing key: {:?}", path))?;
+        self.signing_key = Some(key);
+        Ok(self)
+    }
+
+    /// Encrypt to age recipients (`age1...` public keys)
+    pub fn with_recipients(mut self, recipients: &[String]) -> Result<Self> {
+        for recipient in recipients {
+            let parsed = recipient
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid age recipient '{}': {}", recipient, e))?;
+            self.recipients.push(parsed);
+        }
+        Ok(self)
+    }
+
+    /// Whether files are left as they are
+    pub fn is_empty(&self) -> bool {
+        self.signing_key.is_none() && self.recipients.is_empty()
+    }
+
+    /// Encrypt and sign files
+    ///
+    /// # Returns
+    ///
+    /// The files to publish in place of `paths` (see [`published`](Self::published))
+    pub fn seal_all(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        for path in paths {
+            self.seal(path)?;
+        }
+        Ok(paths.iter().flat_map(|path| self.published(path)).collect())
+    }
+
+    /// Files to publish in place of a sealed file: its encrypted copy (or
+    /// the file itself), and the signature of that
+    pub fn published(&self, path: &Path) -> Vec<PathBuf> {
+        let data = if self.recipients.is_empty() {
+            path.to_path_buf()
+        } else {
+            with_extension(path, ENCRYPTED_EXTENSION)
+        };
+        match self.signing_key {
+            Some(_) => vec![with_extension(&data, SIGNATURE_EXTENSION), data],
+            None => vec![data],
+        }
+    }
+
+    /// Encrypt and sign one file
+    fn seal(&self, path: &Path) -> Result<()> {
+        let mut data = path.to_path_buf();
+        if !self.recipients.is_empty() {
+            data = with_extension(path, ENCRYPTED_EXTENSION);
+            self.encrypt(path, &data)?;
+            info!("Encrypted {:?} to {:?}", path, data);
+        }
+        if let Some(key) = &self.signing_key {
+            let contents = std::fs::read(&data).with_context(|| format!("Failed to read {:?} for signing", data))?;
+            let signature_path = with_extension(&data, SIGNATURE_EXTENSION);
+            std::fs::write(&signature_path, key.sign(&contents).to_bytes())
+                .with_context(|| format!("Failed to write signature: {:?}", signature_path))?;
+            info!("Signed {:?} in {:?}", data, signature_path);
+        }
+        Ok(())
+    }
+
+    fn encrypt(&self, path: &Path, encrypted: &Path) -> Result<()> {
+        let recipients = self.recipients.iter().map(|recipient| recipient as &dyn age::Recipient);
+        let encryptor = age::Encryptor::with_recipients(recipients).context("Failed to encrypt")?;
+
+        let mut input = BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+        let output = File::create(encrypted).with_context(|| format!("Failed to create {:?}", encrypted))?;
+        let mut writer = encryptor
+            .wrap_output(BufWriter::new(output))
+            .with_context(|| format!("Failed to write {:?}", encrypted))?;
+        std::io::copy(&mut input, &mut writer)
+            .and_then(|_| writer.finish())
+            .and_then(|mut output| output.flush())
+            .with_context(|| format!("Failed to encrypt {:?} to {:?}", path, encrypted))
+    }
+}
+
+/// Check the signature of a file
+///
+/// # Arguments
+///
+/// * `public_key` - PKCS#8 PEM file of the Ed25519 public key
+/// * `path` - Signed file; the signature is read from `<path>.sig`
+pub fn verify(public_key: &Path, path: &Path) -> Result<()> {
+    let pem =
+        std::fs::read_to_string(public_key).with_context(|| format!("Failed to read public key: {:?}", public_key))?;
+    let key = VerifyingKey::from_public_key_pem(&pem)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| format!("Invalid Ed25519 public key: {:?}", public_key))?;
+
+    let signature_path = with_extension(path, SIGNATURE_EXTENSION);
+    let signature = std::fs::read(&signature_path)
+        .with_context(|| format!("Failed to read signature: {:?}", signature_path))?;
+    let signature = Signature::from_slice(&signature).with_context(|| format!("Invalid signature: {:?}", signature_path))?;
+    let contents = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    key.verify(&contents, &signature)
+        .with_context(|| format!("Signature of {:?} does not match", path))
+}
+
+/// `path` with `.extension` appended (`report.json` -> `report.json.sig`)
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey, EncodePublicKey};
+    use std::io::Read;
+
+    #[test]
+    fn test_seal() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let private_pem = dir.path().join("key.pem");
+        let public_pem = dir.path().join("key.pub.pem");
+        std::fs::write(&private_pem, key.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes()).unwrap();
+        std::fs::write(&public_pem, key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap()).unwrap();
+
+        let report = dir.path().join("report.json");
+        std::fs::write(&report, b"{\"total_events\": 1}").unwrap();
+
+        // Signing only
+        let signer = Sealer::new().with_signing_key(&private_pem).unwrap();
+        let published = signer.seal_all(std::slice::from_ref(&report)).unwrap();
+        assert_eq!(published, [dir.path().join("report.json.sig"), report.clone()]);
+        verify(&public_pem, &report).unwrap();
+        std::fs::write(&report, b"{\"total_events\": 2}").unwrap();
+        assert!(verify(&public_pem, &report).is_err());
+
+        // Encrypted, then signed
+        let identity = age::x25519::Identity::generate();
+        let sealer = Sealer::new()
+            .with_signing_key(&private_pem)
+            .unwrap()
+            .with_recipients(&[identity.to_public().to_string()])
+            .unwrap();
+        let published = sealer.seal_all(std::slice::from_ref(&report)).unwrap();
+        let encrypted = dir.path().join("report.json.age");
+        assert_eq!(published, [dir.path().join("report.json.age.sig"), encrypted.clone()]);
+        verify(&public_pem, &encrypted).unwrap();
+
+        let decryptor = age::Decryptor::new(File::open(&encrypted).unwrap()).unwrap();
+        let mut decrypted = String::new();
+        decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap()
+            .read_to_string(&mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, "{\"total_events\": 2}");
+
+        assert!(Sealer::new().is_empty());
+        assert!(Sealer::new().with_recipients(&["age1nope".to_string()]).is_err());
+        assert!(Sealer::new().with_signing_key(&report).is_err());
+        assert_eq!(Sealer::new().published(&report), [report]);
+    }
+}
@@ -0,0 +1,247 @@
+//! Loopback self-test
+//!
+//! Generates TCP traffic against a local echo server with the probes
+//! attached and checks that latency events are observed for it. Intended as
+//! a smoke test for new kernels and nodes before running real benchmarks.
+
+use crate::{
+    collector::MetricsCollector,
+    events::EventProcessor,
+    loader::{MapSizes, ProbeLoader},
+    types::LatencyEvent,
+};
+use anyhow::{Context, Result};
+use log::info;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    time::{timeout, Instant},
+};
+use tokio_stream::StreamExt;
+
+/// Loopback address in network byte order, as stored in ConnectionKey
+const LOOPBACK_BE: u32 = 0x7f00_0001u32.to_be();
+
+/// Self-test parameters
+#[derive(Debug, Clone)]
+pub struct SelftestConfig {
+    /// Number of request/response round trips to generate
+    pub round_trips: usize,
+    /// Payload size per request (bytes)
+    pub payload_size: usize,
+    /// How long to wait for events after traffic completes
+    pub timeout: Duration,
+    /// Latencies above this are reported as implausible for loopback
+    pub max_latency: Duration,
+}
+
+impl Default for SelftestConfig {
+    fn default() -> Self {
+        Self {
+            round_trips: 100,
+            payload_size: 512,
+            timeout: Duration::from_secs(5),
+            max_latency: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Outcome of a self-test run
+#[derive(Debug, Default, Clone)]
+pub struct SelftestReport {
+    /// Port the echo server listened on
+    pub port: u16,
+    /// Events observed for the echo connection
+    pub events: u64,
+    /// Events with zero latency
+    pub zero_latency: u64,
+    /// Events above the configured maximum latency
+    pub implausible: u64,
+    /// Smallest observed latency (nanoseconds)
+    pub min_latency_ns: u64,
+    /// Largest observed latency (nanoseconds)
+    pub max_latency_ns: u64,
+}
+
+impl SelftestReport {
+    fn new(port: u16) -> Self {
+        Self {
+            port,
+            ..Default::default()
+        }
+    }
+
+    /// Check whether an event belongs to the loopback echo connection
+    pub fn matches(&self, event: &LatencyEvent) -> bool {
+        let key = &event.key;
+        let port = self.port.to_be();
+
+        key.saddr == LOOPBACK_BE
+            && key.daddr == LOOPBACK_BE
+            && (key.sport == port || key.dport == port)
+    }
+
+    /// Account for an event observed on the echo connection
+    pub fn observe(&mut self, event: &LatencyEvent, max_latency: Duration) {
+        if self.events == 0 || event.latency_ns < self.min_latency_ns {
+            self.min_latency_ns = event.latency_ns;
+        }
+        self.max_latency_ns = self.max_latency_ns.max(event.latency_ns);
+        self.events += 1;
+
+        if event.latency_ns == 0 {
+            self.zero_latency += 1;
+        } else if event.latency_ns > max_latency.as_nanos() as u64 {
+            self.implausible += 1;
+        }
+    }
+
+    /// Problems found by the self-test (empty when it passed)
+    pub fn failures(&self) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        if self.events == 0 {
+            failures.push(format!(
+                "no latency events observed for 127.0.0.1:{}",
+                self.port
+            ));
+        }
+        if self.zero_latency > 0 {
+            failures.push(format!("{} events reported zero latency", self.zero_latency));
+        }
+        if self.implausible > 0 {
+            failures.push(format!(
+                "{} events exceeded the maximum plausible latency",
+                self.implausible
+            ));
+        }
+
+        failures
+    }
+
+    /// True when no failures were found
+    pub fn passed(&self) -> bool {
+        self.failures().is_empty()
+    }
+}
+
+/// Run the loopback self-test
+///
+/// # Arguments
+///
+/// * `ebpf_object` - Optional path to the eBPF object file
+/// * `config` - Self-test parameters
+///
+/// # Returns
+///
+/// Report of the events observed for the echo connection
+pub async fn run(ebpf_object: Option<PathBuf>, config: &SelftestConfig) -> Result<SelftestReport> {
+    let mut loader = ProbeLoader::load(ebpf_object, &MapSizes::default())?;
+    loader.attach_kprobes(&[])?;
+
+    let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+    let mut processor = EventProcessor::new(collector, 1, false);
+    let mut stream = processor.subscribe(config.round_trips * 8 + 64);
+    processor.spawn_cpu_readers(loader.get_perf_array()?).await?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("Failed to bind loopback echo server")?;
+    let port = listener.local_addr()?.port();
+    info!("Echo server listening on 127.0.0.1:{}", port);
+
+    tokio::spawn(async move {
+        if let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = vec![0u8; 64 * 1024];
+            while let Ok(n) = socket.read(&mut buf).await {
+                if n == 0 || socket.write_all(&buf[..n]).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    generate_traffic(port, config)
+        .await
+        .context("Failed to generate loopback traffic")?;
+
+    let mut report = SelftestReport::new(port);
+    let deadline = Instant::now() + config.timeout;
+
+    // Collect events until the timeout elapses
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match timeout(remaining, stream.next()).await {
+            Ok(Some(event)) if report.matches(&event) => {
+                report.observe(&event, config.max_latency);
+            }
+            Ok(Some(_)) => {}
+            _ => break,
+        }
+    }
+
+    Ok(report)
+}
+
+/// Send `round_trips` payloads to the echo server and wait for each reply
+async fn generate_traffic(port: u16, config: &SelftestConfig) -> Result<()> {
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await?;
+    let payload = vec![0x5au8; config.payload_size];
+    let mut reply = vec![0u8; config.payload_size];
+
+    for _ in 0..config.round_trips {
+        client.write_all(&payload).await?;
+        client.read_exact(&mut reply).await?;
+    }
+
+    info!("Completed {} round trips", config.round_trips);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use probe_common::{constants::EVENT_TYPE_RECV, types::ConnectionKey};
+
+    fn loopback_event(sport: u16, dport: u16, latency_ns: u64) -> LatencyEvent {
+        LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 1234,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: LOOPBACK_BE,
+                    daddr: LOOPBACK_BE,
+                    sport: sport.to_be(),
+                    dport: dport.to_be(),
+                },
+                latency_ns,
+                EVENT_TYPE_RECV,
+            )
+        }
+    }
+
+    #[test]
+    fn test_report_checks_latencies() {
+        let max_latency = Duration::from_secs(1);
+        let mut report = SelftestReport::new(9000);
+        assert!(!report.passed());
+
+        let event = loopback_event(45000, 9000, 40_000);
+        assert!(report.matches(&event));
+        assert!(!report.matches(&loopback_event(45000, 9001, 40_000)));
+
+        report.observe(&event, max_latency);
+        report.observe(&loopback_event(9000, 45000, 25_000), max_latency);
+        assert!(report.passed());
+        assert_eq!(report.min_latency_ns, 25_000);
+        assert_eq!(report.max_latency_ns, 40_000);
+
+        report.observe(&loopback_event(45000, 9000, 2_000_000_000), max_latency);
+        report.observe(&loopback_event(45000, 9000, 0), max_latency);
+        assert_eq!(report.failures().len(), 2);
+    }
+}
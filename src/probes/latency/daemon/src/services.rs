@@ -0,0 +1,142 @@
+//! Service classification by port
+//!
+//! Connections are reported as address tuples, which say little about what
+//! was being measured. Well-known ports (and any the user names) map to a
+//! service name, so reports can break latency down per service.
+//!
+//! ## Example service map
+//!
+//! ```yaml
+//! 8080: frontend
+//! 9080: reviews
+//! ```
+
+use anyhow::{Context, Result};
+use std::{collections::HashMap, path::Path};
+
+/// Ports of common services, used unless overridden
+pub const WELL_KNOWN_PORTS: &[(u16, &str)] = &[
+    (53, "dns"),
+    (80, "http"),
+    (443, "https"),
+    (2379, "etcd"),
+    (3306, "mysql"),
+    (4222, "nats"),
+    (5432, "postgres"),
+    (5672, "amqp"),
+    (6379, "redis"),
+    (8080, "http"),
+    (8443, "https"),
+    (9042, "cassandra"),
+    (9092, "kafka"),
+    (9200, "elasticsearch"),
+    (11211, "memcached"),
+    (15001, "envoy-outbound"),
+    (15006, "envoy-inbound"),
+    (27017, "mongodb"),
+    (50051, "grpc"),
+];
+
+/// Maps ports to service names
+#[derive(Debug, Clone)]
+pub struct ServiceClassifier {
+    ports: HashMap<u16, String>,
+}
+
+impl Default for ServiceClassifier {
+    fn default() -> Self {
+        Self {
+            ports: WELL_KNOWN_PORTS
+                .iter()
+                .map(|&(port, name)| (port, name.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl ServiceClassifier {
+    /// Create a classifier with the well-known ports
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name a port, replacing any built-in name
+    pub fn insert(&mut self, port: u16, name: &str) {
+        self.ports.insert(port, name.to_string());
+    }
+
+    /// Add the entries of a YAML service map (port: name)
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the service map file
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read service map: {:?}", path))?;
+        let ports: HashMap<u16, String> = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse service map: {:?}", path))?;
+
+        self.ports.extend(ports);
+        Ok(())
+    }
+
+    /// Service listening on a port, if known
+    pub fn classify(&self, port: u16) -> Option<&str> {
+        self.ports.get(&port).map(String::as_str)
+    }
+
+    /// Service of a connection
+    ///
+    /// The destination port is tried first. For events on the server side of
+    /// a connection the destination is the client's ephemeral port, so the
+    /// source port is tried next.
+    ///
+    /// # Arguments
+    ///
+    /// * `sport` - Source port (host byte order)
+    /// * `dport` - Destination port (host byte order)
+    pub fn classify_connection(&self, sport: u16, dport: u16) -> Option<&str> {
+        self.classify(dport).or_else(|| self.classify(sport))
+    }
+}
+
+/// Parse a `PORT=NAME` service mapping
+pub fn parse_port_service(spec: &str) -> Result<(u16, String)> {
+    let (port, name) = spec
+        .split_once('=')
+        .with_context(|| format!("Invalid port service '{}', expected PORT=NAME", spec))?;
+    let port: u16 = port
+        .parse()
+        .with_context(|| format!("Invalid port: {}", port))?;
+    if name.is_empty() {
+        anyhow::bail!("Missing service name for port {}", port);
+    }
+
+    Ok((port, name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let mut classifier = ServiceClassifier::new();
+        assert_eq!(classifier.classify(6379), Some("redis"));
+        assert_eq!(classifier.classify(9080), None);
+
+        let (port, name) = parse_port_service("9080=reviews").unwrap();
+        classifier.insert(port, &name);
+        classifier.insert(8080, "frontend");
+        assert_eq!(classifier.classify(9080), Some("reviews"));
+        assert_eq!(classifier.classify(8080), Some("frontend"));
+
+        // Server-side events: destination is the client's ephemeral port
+        assert_eq!(classifier.classify_connection(6379, 41234), Some("redis"));
+        assert_eq!(classifier.classify_connection(41234, 41235), None);
+
+        assert!(parse_port_service("9080").is_err());
+        assert!(parse_port_service("http=reviews").is_err());
+        assert!(parse_port_service("9080=").is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! Cross-node latency stitching
+//!
+//! `latency-probe analyze stitch` reads the correlation records (see
+//! crate::correlation) of the client and server nodes and matches the two
+//! ends of each request, breaking the latency the client sees down into
+//! hops:
+//!
+//! ```text
+//! client send ──request network──▶ server receive ──server──▶ server send ──response network──▶ client receive
+//! ```
+//!
+//! A receive event on the client carries the time since the request was
+//! sent, so each one gives the request's send and response times. It is
+//! matched with the first receive on the server end of the same flow
+//! between the two, give or take `--window-us` for the offset between the
+//! nodes' clocks. The server's time and the response's network time are
+//! only split when send events were recorded on the server (see
+//! EVENT_TYPE_SEND); otherwise they are reported together.
+//!
+//! One-way times are only as accurate as the nodes' clocks are
+//! synchronized. The offset each node's time daemon reported (see
+//! crate::timesync) is removed from its times, and matches whose one-way
+//! hops are shorter than the combined uncertainty of the two clocks are
+//! counted as `uncertain`. Matches with a negative hop, a sign of
+//! uncorrected clock offset, are counted as `skewed`.
+
+use crate::{
+    compress,
+    correlation::{CorrelationLine, CorrelationRecord, Side},
+    types::{calculate_percentiles, ClockSync, Percentiles},
+};
+use anyhow::{Context, Result};
+use probe_common::constants::{EVENT_TYPE_RECV, EVENT_TYPE_SEND};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::BufRead,
+    path::Path,
+};
+
+/// Offset tolerated between the clocks of two nodes by default
+pub const DEFAULT_WINDOW_US: u64 = 1000;
+
+/// Latency of each hop, in microseconds
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct HopBreakdown {
+    /// Requests matched on both ends
+    pub requests: u64,
+    /// Client send to client receive, as the client saw it
+    pub round_trip: Percentiles,
+    /// Client send to server receive
+    pub request_network: Percentiles,
+    /// Server receive to client receive
+    pub server_and_response: Percentiles,
+    /// Server receive to server send (None without server send events)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<Percentiles>,
+    /// Server send to client receive (None without server send events)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_network: Option<Percentiles>,
+}
+
+/// Requests matched between a client and a server socket
+#[derive(Serialize, Debug, Clone)]
+pub struct StitchedConnection {
+    /// Flow from client to server
+    pub flow: String,
+    /// Correlation ID of the client socket
+    pub client: String,
+    /// Correlation ID of the server socket
+    pub server: String,
+    /// Combined uncertainty of the two nodes' clocks (microseconds, None
+    /// if either is unknown)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_uncertainty_us: Option<f64>,
+    /// Whether the clock uncertainty exceeds the median request network hop
+    pub uncertain: bool,
+    /// Latency of each hop
+    pub hops: HopBreakdown,
+}
+
+/// Result of `analyze stitch`
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct StitchReport {
+    /// Clock offset tolerated when matching (microseconds)
+    pub window_us: u64,
+    /// Receive events on client sockets
+    pub client_receives: u64,
+    /// Receive events on server sockets
+    pub server_receives: u64,
+    /// Client receives matched with a server receive
+    pub matched: u64,
+    /// Client receives without a server receive in their window
+    pub unmatched: u64,
+    /// Matches with a negative hop (clock offset larger than the hop)
+    pub skewed: u64,
+    /// Matches with a one-way hop shorter than the clock uncertainty
+    pub uncertain: u64,
+    /// Clock offsets removed, by node
+    pub clocks: BTreeMap<String, ClockSync>,
+    /// Latency of each hop over every match
+    pub hops: HopBreakdown,
+    /// Matches per pair of sockets, most requests first
+    pub connections: Vec<StitchedConnection>,
+}
+
+/// Samples of each hop, in microseconds
+#[derive(Default)]
+struct HopSamples {
+    round_trip: Vec<f64>,
+    request_network: Vec<f64>,
+    server_and_response: Vec<f64>,
+    server: Vec<f64>,
+    response_network: Vec<f64>,
+}
+
+impl HopSamples {
+    fn breakdown(self) -> HopBreakdown {
+        let optional = |samples: Vec<f64>| (!samples.is_empty()).then(|| calculate_percentiles(samples));
+        HopBreakdown {
+            requests: self.round_trip.len() as u64,
+            round_trip: calculate_percentiles(self.round_trip),
+            request_network: calculate_percentiles(self.request_network),
+            server_and_response: calculate_percentiles(self.server_and_response),
+            server: optional(self.server),
+            response_network: optional(self.response_network),
+        }
+    }
+}
+
+/// Correlation records of several nodes
+#[derive(Debug, Default)]
+pub struct Recordings {
+    /// Events of every node
+    pub records: Vec<CorrelationRecord>,
+    /// Synchronization of each node's clock, if its time daemon answered
+    pub clocks: BTreeMap<String, ClockSync>,
+}
+
+impl Recordings {
+    /// Time of an event on the reference clock (nanoseconds since the
+    /// Unix epoch)
+    fn time_ns(&self, record: &CorrelationRecord) -> i64 {
+        record.wall_time_ns - self.clocks.get(&record.node).map_or(0, |sync| sync.offset_ns)
+    }
+
+    /// Combined uncertainty of the clocks of two nodes (None if either is
+    /// unknown)
+    fn uncertainty_ns(&self, a: &str, b: &str) -> Option<i64> {
+        let uncertainty = |node: &str| self.clocks.get(node).map(|sync| sync.uncertainty_ns as i64);
+        Some(uncertainty(a)? + uncertainty(b)?)
+    }
+}
+
+/// Events of one flow
+#[derive(Default)]
+struct FlowEvents<'a> {
+    client_receives: Vec<&'a CorrelationRecord>,
+    server_receives: Vec<&'a CorrelationRecord>,
+    server_sends: Vec<&'a CorrelationRecord>,
+}
+
+/// Read the correlation records and clocks of several files
+///
+/// Files ending in .gz or .zst are decompressed as they are read.
+///
+/// # Arguments
+///
+/// * `paths` - Correlation files written with --correlation
+pub fn read_records(paths: &[impl AsRef<Path>]) -> Result<Recordings> {
+    let mut recordings = Recordings::default();
+    for path in paths {
+        let path = path.as_ref();
+        let reader = compress::open(path)?;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read {:?}", path))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid correlation record at {:?} line {}", path, number + 1))?;
+            match line {
+                CorrelationLine::Record(record) => recordings.records.push(record),
+                CorrelationLine::Metadata(metadata) => {
+                    if let Some(sync) = metadata.clock.sync {
+                        recordings.clocks.insert(metadata.node, sync);
+                    }
+                }
+            }
+        }
+    }
+    Ok(recordings)
+}
+
+/// Match the client and server ends of the requests in correlation records
+///
+/// # Arguments
+///
+/// * `recordings` - Correlation records of the client and server nodes
+/// * `window_us` - Clock offset tolerated between nodes, after the offsets
+///   their time daemons reported are removed (microseconds)
+pub fn stitch(recordings: &Recordings, window_us: u64) -> StitchReport {
+    let window_ns = window_us as i64 * 1000;
+    let mut flows: BTreeMap<&str, FlowEvents> = BTreeMap::new();
+    for record in &recordings.records {
+        let flow = flows.entry(record.flow.as_str()).or_default();
+        match (record.side, record.event_type) {
+            (Side::Client, EVENT_TYPE_RECV) => flow.client_receives.push(record),
+            (Side::Server, EVENT_TYPE_RECV) => flow.server_receives.push(record),
+            (Side::Server, EVENT_TYPE_SEND) => flow.server_sends.push(record),
+            _ => {}
+        }
+    }
+
+    let mut report = StitchReport {
+        window_us,
+        clocks: recordings.clocks.clone(),
+        ..Default::default()
+    };
+    let mut total = HopSamples::default();
+    let mut connections: HashMap<(&str, &str, &str), (HopSamples, Option<i64>)> = HashMap::new();
+    for (flow, mut events) in flows {
+        for events in [&mut events.client_receives, &mut events.server_receives, &mut events.server_sends] {
+            events.sort_by_key(|record| recordings.time_ns(record));
+        }
+        report.client_receives += events.client_receives.len() as u64;
+        report.server_receives += events.server_receives.len() as u64;
+
+        // Both lists are in time order, so each search starts after the
+        // last server receive matched
+        let mut next_server = 0;
+        for client in &events.client_receives {
+            let response_ns = recordings.time_ns(client);
+            let request_ns = response_ns - client.latency_ns as i64;
+            let unmatched = &events.server_receives[next_server..];
+            let skipped = unmatched.partition_point(|server| recordings.time_ns(server) < request_ns - window_ns);
+            let Some(server) = unmatched
+                .get(skipped)
+                .filter(|server| recordings.time_ns(server) <= response_ns + window_ns)
+            else {
+                report.unmatched += 1;
+                continue;
+            };
+            next_server += skipped + 1;
+            report.matched += 1;
+
+            let received_ns = recordings.time_ns(server);
+            let sent_ns = events
+                .server_sends
+                .iter()
+                .map(|send| recordings.time_ns(send))
+                .find(|&sent| sent >= received_ns && sent <= response_ns + window_ns);
+            let hops = [
+                Some(received_ns - request_ns),
+                Some(response_ns - received_ns),
+                sent_ns.map(|sent| sent - received_ns),
+                sent_ns.map(|sent| response_ns - sent),
+            ];
+            if hops.iter().flatten().any(|&hop| hop < 0) {
+                report.skewed += 1;
+            }
+            let uncertainty_ns = recordings.uncertainty_ns(&client.node, &server.node);
+            let one_way = [hops[0], hops[3]];
+            if let Some(uncertainty) = uncertainty_ns {
+                if one_way.iter().flatten().any(|hop| hop.abs() < uncertainty) {
+                    report.uncertain += 1;
+                }
+            }
+
+            let (connection, _) = connections
+                .entry((flow, client.correlation_id.as_str(), server.correlation_id.as_str()))
+                .or_insert_with(|| (HopSamples::default(), uncertainty_ns));
+            for samples in [&mut total, connection] {
+                samples.round_trip.push(client.latency_ns as f64 / 1000.0);
+                samples.request_network.push((received_ns - request_ns) as f64 / 1000.0);
+                samples.server_and_response.push((response_ns - received_ns) as f64 / 1000.0);
+                if let Some(sent) = sent_ns {
+                    samples.server.push((sent - received_ns) as f64 / 1000.0);
+                    samples.response_network.push((response_ns - sent) as f64 / 1000.0);
+                }
+            }
+        }
+    }
+
+    report.hops = total.breakdown();
+    report.connections = connections
+        .into_iter()
+        .map(|((flow, client, server), (samples, uncertainty_ns))| {
+            let hops = samples.breakdown();
+            let clock_uncertainty_us = uncertainty_ns.map(|uncertainty| uncertainty as f64 / 1000.0);
+            StitchedConnection {
+                flow: flow.to_string(),
+                client: client.to_string(),
+                server: server.to_string(),
+                uncertain: clock_uncertainty_us.is_some_and(|uncertainty| uncertainty > hops.request_network.p50.abs()),
+                clock_uncertainty_us,
+                hops,
+            }
+        })
+        .collect();
+    report
+        .connections
+        .sort_by(|a, b| b.hops.requests.cmp(&a.hops.requests).then_with(|| a.flow.cmp(&b.flow)));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stitch_requests() {
+        let flow = "10.0.1.5:41000 -> 10.0.2.7:8080";
+        let record = |node: &str, side, event_type, wall_time_ns, latency_ns| CorrelationRecord {
+            correlation_id: format!("{}/1", node),
+            node: node.to_string(),
+            flow: flow.to_string(),
+            side,
+            event_type,
+            timestamp_ns: 0,
+            wall_time_ns,
+            latency_ns,
+            pid: 1,
+            run_id: None,
+        };
+
+        // Two requests sent at 1ms and 10ms: 100us to the server, 300us
+        // there, 100us back
+        let records = vec![
+            record("node-a", Side::Client, EVENT_TYPE_RECV, 1_500_000, 500_000),
+            record("node-b", Side::Server, EVENT_TYPE_RECV, 1_100_000, 90_000),
+            record("node-b", Side::Server, EVENT_TYPE_SEND, 1_400_000, 0),
+            record("node-a", Side::Client, EVENT_TYPE_RECV, 10_500_000, 500_000),
+            record("node-b", Side::Server, EVENT_TYPE_RECV, 10_100_000, 8_700_000),
+            record("node-b", Side::Server, EVENT_TYPE_SEND, 10_400_000, 0),
+            // A request the server never saw
+            record("node-a", Side::Client, EVENT_TYPE_RECV, 50_000_000, 1_000_000),
+        ];
+        let mut recordings = Recordings {
+            records,
+            clocks: BTreeMap::new(),
+        };
+        let report = stitch(&recordings, DEFAULT_WINDOW_US);
+        assert_eq!((report.client_receives, report.server_receives), (3, 2));
+        assert_eq!((report.matched, report.unmatched, report.skewed), (2, 1, 0));
+        assert_eq!(report.hops.request_network.p99, 100.0);
+        assert_eq!(report.hops.server_and_response.p50, 400.0);
+        assert_eq!(report.hops.server.as_ref().unwrap().p50, 300.0);
+        assert_eq!(report.hops.response_network.as_ref().unwrap().p50, 100.0);
+        assert_eq!(report.connections.len(), 1);
+        assert_eq!((report.connections[0].client.as_str(), report.connections[0].server.as_str()), ("node-a/1", "node-b/1"));
+
+        assert!(report.connections[0].clock_uncertainty_us.is_none());
+
+        // With clock uncertainties of 20us and 30us, the 100us hops are
+        // still measured
+        let sync = |offset_ns, uncertainty_ns| ClockSync {
+            daemon: "chrony".to_string(),
+            reference: None,
+            offset_ns,
+            uncertainty_ns,
+        };
+        recordings.clocks.insert("node-a".to_string(), sync(0, 20_000));
+        recordings.clocks.insert("node-b".to_string(), sync(0, 30_000));
+        let report = stitch(&recordings, DEFAULT_WINDOW_US);
+        assert_eq!((report.uncertain, report.connections[0].uncertain), (0, false));
+        assert_eq!(report.connections[0].clock_uncertainty_us, Some(50.0));
+
+        // A server clock 150us behind makes the request hop negative; without
+        // server sends, the server and response hops are reported together
+        let mut recordings = Recordings {
+            records: vec![
+                record("node-a", Side::Client, EVENT_TYPE_RECV, 1_500_000, 500_000),
+                record("node-b", Side::Server, EVENT_TYPE_RECV, 950_000, 0),
+            ],
+            clocks: BTreeMap::new(),
+        };
+        let report = stitch(&recordings, DEFAULT_WINDOW_US);
+        assert_eq!((report.matched, report.skewed), (1, 1));
+        assert_eq!(report.hops.request_network.p50, -50.0);
+        assert!(report.hops.server.is_none());
+
+        // Outside the window, nothing matches
+        assert_eq!(stitch(&recordings, 10).matched, 0);
+
+        // The offset chrony reported is removed, but its uncertainty
+        // exceeds the corrected hop
+        recordings.clocks.insert("node-a".to_string(), sync(0, 50_000));
+        recordings.clocks.insert("node-b".to_string(), sync(-150_000, 200_000));
+        let report = stitch(&recordings, DEFAULT_WINDOW_US);
+        assert_eq!((report.skewed, report.uncertain), (0, 1));
+        assert_eq!(report.hops.request_network.p50, 100.0);
+        assert!(report.connections[0].uncertain);
+    }
+}
@@ -0,0 +1,174 @@
+//! Tail latency decomposition
+//!
+//! With `--tail-analysis`, each latency event is checked against what else
+//! happened during its latency window (from `timestamp - latency` to
+//! `timestamp`):
+//!
+//! - **packet_drop**: the kernel dropped a packet of the same connection
+//! - **context_switch**: the event's process was switched off its CPU
+//!
+//! Events are summarized per combination of factors, so the report can
+//! tell, for the events above p99, how many coincided with each factor.
+//! Factors are only seen if their event was read before the latency event;
+//! events from another CPU's buffer that arrive later are missed.
+//! Retransmits and socket queue depth are not captured by the probe yet.
+
+use crate::{
+    digest::LatencyDigest,
+    types::{kernel, LatencyEvent, TailBreakdown},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A packet of the connection was dropped during the latency window
+const FACTOR_PACKET_DROP: usize = 1 << 0;
+/// The process was switched out during the latency window
+const FACTOR_CONTEXT_SWITCH: usize = 1 << 1;
+/// Number of factor combinations
+const COMBINATIONS: usize = 4;
+
+/// 4-tuple of a connection, in network byte order
+type ConnectionTuple = (u32, u32, u16, u16);
+
+fn tuple(key: &kernel::ConnectionKey) -> ConnectionTuple {
+    (key.saddr, key.daddr, key.sport, key.dport)
+}
+
+/// Correlates latency events with drops and context switches
+#[derive(Serialize, Deserialize, Default)]
+pub struct TailAnalyzer {
+    /// Time of the last drop per connection
+    last_drop_ns: HashMap<ConnectionTuple, u64>,
+    /// Time each PID was last switched out
+    last_switch_ns: HashMap<u32, u64>,
+    /// Latency of the events with each combination of factors
+    by_factors: [LatencyDigest; COMBINATIONS],
+}
+
+impl TailAnalyzer {
+    /// Create an analyzer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a packet drop
+    pub fn add_packet_drop(&mut self, event: &kernel::PacketDropEvent) {
+        // Drops without connection information cannot be attributed
+        if event.key.saddr != 0 || event.key.daddr != 0 {
+            self.last_drop_ns.insert(tuple(&event.key), event.timestamp_ns);
+        }
+    }
+
+    /// Record a context switch
+    pub fn add_context_switch(&mut self, event: &kernel::ContextSwitchEvent) {
+        if event.prev_pid != 0 {
+            self.last_switch_ns.insert(event.prev_pid, event.timestamp_ns);
+        }
+    }
+
+    /// Record a latency event with the factors seen in its window
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Latency event from the eBPF program
+    /// * `latency_us` - Latency of the event in microseconds
+    pub fn add_event(&mut self, event: &LatencyEvent, latency_us: f64) {
+        let start_ns = event.timestamp_ns.saturating_sub(event.latency_ns);
+        let in_window = |ns: Option<&u64>| ns.is_some_and(|&ns| ns >= start_ns && ns <= event.timestamp_ns);
+
+        let mut factors = 0;
+        if in_window(self.last_drop_ns.get(&tuple(&event.key))) {
+            factors |= FACTOR_PACKET_DROP;
+        }
+        if in_window(self.last_switch_ns.get(&event.pid)) {
+            factors |= FACTOR_CONTEXT_SWITCH;
+        }
+
+        self.by_factors[factors].add(latency_us);
+    }
+
+    /// Factors of the events above a latency threshold
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold_us` - Tail threshold in microseconds (usually p99)
+    pub fn breakdown(&self, threshold_us: f64) -> TailBreakdown {
+        let mut breakdown = TailBreakdown {
+            threshold_us,
+            ..Default::default()
+        };
+
+        for (factors, digest) in self.by_factors.iter().enumerate() {
+            let events = digest.count_above(threshold_us);
+            breakdown.events += events;
+            if factors == 0 {
+                breakdown.unexplained += events;
+            }
+            if factors & FACTOR_PACKET_DROP != 0 {
+                breakdown.packet_drop += events;
+            }
+            if factors & FACTOR_CONTEXT_SWITCH != 0 {
+                breakdown.context_switch += events;
+            }
+        }
+
+        breakdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+
+    #[test]
+    fn test_tail_breakdown() {
+        let key = ConnectionKey {
+            saddr: 0x0100000a,
+            daddr: 0x0200000a,
+            sport: 0x5000,
+            dport: 0x901f,
+        };
+        let event = |timestamp_ns: u64, latency_us: u64, pid: u32| LatencyEvent {
+            timestamp_ns,
+            pid,
+            ..testing::event(key, latency_us * 1000, probe_common::constants::EVENT_TYPE_RECV)
+        };
+
+        let mut analyzer = TailAnalyzer::new();
+        for i in 0..100 {
+            analyzer.add_event(&event(1_000_000 * i, 100, 1), 100.0);
+        }
+
+        // A drop 5ms into a 10ms request
+        analyzer.add_packet_drop(&kernel::PacketDropEvent {
+            key,
+            timestamp_ns: 205_000_000,
+            drop_reason: 0,
+            drop_location: probe_common::constants::DROP_LOCATION_STACK,
+            protocol: probe_common::constants::IPPROTO_TCP,
+            _padding: [0; 2],
+        });
+        analyzer.add_event(&event(210_000_000, 10_000, 1), 10_000.0);
+
+        // A context switch of another process, then of this one
+        for pid in [2, 1] {
+            analyzer.add_context_switch(&kernel::ContextSwitchEvent {
+                timestamp_ns: 305_000_000,
+                prev_pid: pid,
+                next_pid: 0,
+            });
+        }
+        analyzer.add_event(&event(310_000_000, 10_000, 1), 10_000.0);
+
+        // Slow, but nothing seen in its window
+        analyzer.add_event(&event(500_000_000, 10_000, 1), 10_000.0);
+
+        let breakdown = analyzer.breakdown(100.0);
+        assert_eq!(breakdown.events, 3);
+        assert_eq!(breakdown.packet_drop, 1);
+        assert_eq!(breakdown.context_switch, 1);
+        assert_eq!(breakdown.unexplained, 1);
+    }
+}
@@ -0,0 +1,259 @@
+//! Per-tenant latency
+//!
+//! A cluster shared by several tenants would otherwise need a probe per
+//! tenant, each with its own filter, to report the latency of each. A
+//! tenants file names a filter per tenant instead; one probe keeps a digest
+//! per tenant and reports the percentiles of every tenant in one run.
+//!
+//! A tenant's filter lists CIDRs, ports and network namespace inodes; each
+//! list left out matches anything. A connection belongs to a tenant if one
+//! of its ends is in a listed CIDR on a listed port, and its namespace is
+//! listed. Tenants may overlap: an event counts for every tenant it
+//! belongs to.
+//!
+//! When every tenant's ends can be written as kernel service filter
+//! entries (ports, or /32 addresses), they are added to the service
+//! filter, so the kernel drops the events of no tenant. Namespaces are
+//! added to the userspace namespace filter in the same way.
+//!
+//! ## Example tenants file
+//!
+//! ```yaml
+//! payments:
+//!   cidrs: [10.1.0.0/16]
+//!   ports: [8080, 8443]
+//! checkout:
+//!   cidrs: [10.96.0.15]
+//!   ports: [9080]
+//! batch:
+//!   namespaces: [4026532288]
+//! ```
+
+use crate::{types::LatencyEvent, zones::Network};
+use anyhow::{Context, Result};
+use probe_common::constants::MAX_SERVICE_FILTERS;
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Filter of one tenant, as written in the tenants file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TenantSpec {
+    #[serde(default)]
+    cidrs: Vec<String>,
+    #[serde(default)]
+    ports: Vec<u16>,
+    #[serde(default)]
+    namespaces: Vec<u32>,
+}
+
+/// A named filter
+#[derive(Debug, Clone)]
+struct Tenant {
+    name: String,
+    networks: Vec<Network>,
+    ports: Vec<u16>,
+    namespaces: Vec<u32>,
+}
+
+impl Tenant {
+    /// Whether a connection end (host byte order) matches
+    fn matches_end(&self, addr: u32, port: u16) -> bool {
+        (self.networks.is_empty() || self.networks.iter().any(|network| network.contains(addr)))
+            && (self.ports.is_empty() || self.ports.contains(&port))
+    }
+
+    fn matches(&self, event: &LatencyEvent) -> bool {
+        let key = &event.key;
+        (self.namespaces.is_empty() || self.namespaces.contains(&event.netns))
+            && (self.matches_end(u32::from_be(key.saddr), u16::from_be(key.sport))
+                || self.matches_end(u32::from_be(key.daddr), u16::from_be(key.dport)))
+    }
+
+    /// Service filter entries matching at least the tenant's ends (None if
+    /// the ends cannot be written as entries)
+    fn filter_services(&self) -> Option<Vec<String>> {
+        let addresses: Option<Vec<String>> = self
+            .networks
+            .iter()
+            .map(|network| network.single_address().map(|addr| addr.to_string()))
+            .collect();
+        let ports: Vec<String> = self.ports.iter().map(u16::to_string).collect();
+
+        match (addresses, ports.is_empty()) {
+            (Some(addresses), false) if !addresses.is_empty() => Some(
+                addresses
+                    .iter()
+                    .flat_map(|addr| ports.iter().map(move |port| format!("{}:{}", addr, port)))
+                    .collect(),
+            ),
+            (Some(addresses), true) if !addresses.is_empty() => {
+                Some(addresses.iter().map(|addr| format!("{}:*", addr)).collect())
+            }
+            // Wider networks match on the port alone
+            (_, false) => Some(ports.iter().map(|port| format!("*:{}", port)).collect()),
+            _ => None,
+        }
+    }
+}
+
+/// Tenants and their filters
+#[derive(Debug, Clone, Default)]
+pub struct TenantSet {
+    tenants: Vec<Tenant>,
+}
+
+impl TenantSet {
+    /// Read a YAML tenants file (tenant: filter)
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the tenants file
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tenants file: {:?}", path))?;
+        Self::from_yaml(&contents).with_context(|| format!("Invalid tenants file: {:?}", path))
+    }
+
+    /// Parse tenants from YAML (see the module documentation)
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let specs: BTreeMap<String, TenantSpec> = serde_yaml::from_str(yaml)?;
+
+        let mut tenants = Vec::with_capacity(specs.len());
+        for (name, spec) in specs {
+            if spec.cidrs.is_empty() && spec.ports.is_empty() && spec.namespaces.is_empty() {
+                anyhow::bail!("Tenant '{}' has no cidrs, ports or namespaces", name);
+            }
+            let networks = spec
+                .cidrs
+                .iter()
+                .map(|cidr| Network::parse(cidr))
+                .collect::<Result<_>>()
+                .with_context(|| format!("Invalid CIDR of tenant '{}'", name))?;
+            tenants.push(Tenant {
+                name,
+                networks,
+                ports: spec.ports,
+                namespaces: spec.namespaces,
+            });
+        }
+        Ok(Self { tenants })
+    }
+
+    /// Number of tenants
+    pub fn len(&self) -> usize {
+        self.tenants.len()
+    }
+
+    /// Whether there are no tenants
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    /// Names of the tenants an event belongs to
+    pub fn matching(&self, event: &LatencyEvent) -> impl Iterator<Item = &str> + '_ {
+        let event = *event;
+        self.tenants
+            .iter()
+            .filter(move |tenant| tenant.matches(&event))
+            .map(|tenant| tenant.name.as_str())
+    }
+
+    /// Kernel service filter entries covering every tenant
+    ///
+    /// # Returns
+    ///
+    /// Entries in the `filter_services` syntax, or None if a tenant's ends
+    /// cannot be written as entries (e.g. a /16 without ports), or there
+    /// are more than the kernel map holds
+    pub fn filter_services(&self) -> Option<Vec<String>> {
+        let mut services = Vec::new();
+        for tenant in &self.tenants {
+            services.extend(tenant.filter_services()?);
+        }
+        services.sort();
+        services.dedup();
+        (!services.is_empty() && services.len() <= MAX_SERVICE_FILTERS as usize).then_some(services)
+    }
+
+    /// Network namespaces covering every tenant (None if a tenant matches
+    /// any namespace)
+    pub fn namespaces(&self) -> Option<Vec<u32>> {
+        let mut namespaces = Vec::new();
+        for tenant in &self.tenants {
+            if tenant.namespaces.is_empty() {
+                return None;
+            }
+            namespaces.extend(&tenant.namespaces);
+        }
+        namespaces.sort_unstable();
+        namespaces.dedup();
+        (!namespaces.is_empty()).then_some(namespaces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+
+    fn event(saddr: [u8; 4], sport: u16, daddr: [u8; 4], dport: u16, netns: u32) -> LatencyEvent {
+        LatencyEvent {
+            netns,
+            pid: 1,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: u32::from_ne_bytes(saddr),
+                    daddr: u32::from_ne_bytes(daddr),
+                    sport: sport.to_be(),
+                    dport: dport.to_be(),
+                },
+                1_000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        }
+    }
+
+    #[test]
+    fn test_tenants() {
+        let tenants = TenantSet::from_yaml(
+            "payments:\n  cidrs: [10.1.0.0/16]\n  ports: [8080]\n\
+             checkout:\n  cidrs: [10.96.0.15]\n  ports: [9080, 9443]\n\
+             batch:\n  namespaces: [4026532288]\n",
+        )
+        .unwrap();
+        assert_eq!(tenants.len(), 3);
+
+        let names = |event: &LatencyEvent| tenants.matching(event).collect::<Vec<_>>();
+        assert_eq!(names(&event([10, 0, 0, 1], 40000, [10, 1, 2, 3], 8080, 0)), ["payments"]);
+        // Both ends of the same side must match
+        assert!(names(&event([10, 1, 2, 3], 40000, [10, 0, 0, 1], 8080, 0)).is_empty());
+        assert_eq!(names(&event([10, 96, 0, 15], 9443, [10, 0, 0, 1], 40000, 0)), ["checkout"]);
+        assert_eq!(
+            names(&event([10, 0, 0, 1], 40000, [10, 1, 0, 9], 8080, 4026532288)),
+            ["batch", "payments"]
+        );
+
+        // Namespaces cannot be filtered in the kernel, so batch leaves the
+        // service filter open, and the others the namespace filter
+        assert_eq!(tenants.filter_services(), None);
+        assert_eq!(tenants.namespaces(), None);
+
+        let tenants = TenantSet::from_yaml(
+            "payments:\n  cidrs: [10.1.0.0/16]\n  ports: [8080]\n\
+             checkout:\n  cidrs: [10.96.0.15]\n  ports: [9080, 9443]\n\
+             admin:\n  cidrs: [10.0.0.5]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tenants.filter_services().unwrap(),
+            ["*:8080", "10.0.0.5:*", "10.96.0.15:9080", "10.96.0.15:9443"]
+        );
+        assert!(TenantSet::from_yaml("wide:\n  cidrs: [10.0.0.0/8]\n").unwrap().filter_services().is_none());
+
+        assert!(TenantSet::from_yaml("empty: {}\n").is_err());
+        assert!(TenantSet::from_yaml("bad:\n  cidrs: [10.0.0.0/33]\n").is_err());
+        assert!(TenantSet::from_yaml("typo:\n  port: [80]\n").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Synthetic event generation for tests
+//!
+//! Produces realistic, reproducible LatencyEvent streams without a kernel,
+//! for exercising the collector and exporters end-to-end.
+//!
+//! Only compiled with the `test-support` feature, and for the crate's own
+//! unit tests.
+
+use crate::types::{ConnectionKey, LatencyEvent};
+use probe_common::constants::{EVENT_TYPE_CLEANUP, EVENT_TYPE_RECV, IPPROTO_TCP};
+
+/// Latency distribution for generated events
+#[derive(Debug, Clone, Copy)]
+pub enum LatencyDistribution {
+    /// Every event has the same latency
+    Constant { latency_ns: u64 },
+    /// Latency drawn uniformly from `[min_ns, max_ns]`
+    Uniform { min_ns: u64, max_ns: u64 },
+    /// Log-normal latency around `median_ns` (typical for network RTTs)
+    LogNormal { median_ns: u64, sigma: f64 },
+    /// Mostly fast events with a fraction of slow outliers
+    Bimodal {
+        fast_ns: u64,
+        slow_ns: u64,
+        slow_fraction: f64,
+    },
+}
+
+impl LatencyDistribution {
+    fn sample(&self, rng: &mut SplitMix64) -> u64 {
+        match *self {
+            LatencyDistribution::Constant { latency_ns } => latency_ns,
+            LatencyDistribution::Uniform { min_ns, max_ns } => {
+                min_ns + rng.next_u64() % (max_ns - min_ns + 1)
+            }
+            LatencyDistribution::LogNormal { median_ns, sigma } => {
+                (median_ns as f64 * (sigma * rng.next_gaussian()).exp()) as u64
+            }
+            LatencyDistribution::Bimodal {
+                fast_ns,
+                slow_ns,
+                slow_fraction,
+            } => {
+                if rng.next_f64() < slow_fraction {
+                    slow_ns
+                } else {
+                    fast_ns
+                }
+            }
+        }
+    }
+}
+
+/// Configuration for a synthetic event stream
+#[derive(Debug, Clone)]
+pub struct SyntheticConfig {
+    /// Total number of events to generate
+    pub events: usize,
+    /// Number of distinct connections events are spread across
+    pub connections: usize,
+    /// Latency distribution
+    pub distribution: LatencyDistribution,
+    /// Mean gap between events (nanoseconds)
+    pub mean_interval_ns: u64,
+    /// Probability that an event starts a burst
+    pub burst_probability: f64,
+    /// Events emitted back-to-back within a burst
+    pub burst_size: usize,
+    /// Timestamp of the first event (nanoseconds)
+    pub start_timestamp_ns: u64,
+    /// PRNG seed; identical seeds produce identical streams
+    pub seed: u64,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        Self {
+            events: 1_000,
+            connections: 10,
+            distribution: LatencyDistribution::LogNormal {
+                median_ns: 500_000,
+                sigma: 0.5,
+            },
+            mean_interval_ns: 1_000_000,
+            burst_probability: 0.0,
+            burst_size: 10,
+            start_timestamp_ns: 1_000_000_000,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// Iterator over synthetic latency events
+pub struct SyntheticEventGenerator {
+    config: SyntheticConfig,
+    rng: SplitMix64,
+    emitted: usize,
+    timestamp_ns: u64,
+    burst_remaining: usize,
+}
+
+impl SyntheticEventGenerator {
+    /// Create a generator for the given configuration
+    pub fn new(config: SyntheticConfig) -> Self {
+        let rng = SplitMix64::new(config.seed);
+        let timestamp_ns = config.start_timestamp_ns;
+
+        Self {
+            config,
+            rng,
+            emitted: 0,
+            timestamp_ns,
+            burst_remaining: 0,
+        }
+    }
+
+    /// Connection key for the n-th synthetic connection
+    ///
+    /// Clients live in 10.0.0.0/16 and talk to 10.1.0.1:8080.
+    pub fn connection_key(index: usize) -> ConnectionKey {
+        let client = 0x0a00_0000u32 | (index as u32 & 0xffff);
+        let server = 0x0a01_0001u32;
+
+        ConnectionKey {
+            saddr: client.to_be(),
+            daddr: server.to_be(),
+            sport: (40_000 + (index % 20_000) as u16).to_be(),
+            dport: 8080u16.to_be(),
+        }
+    }
+
+    fn advance_clock(&mut self) {
+        if self.burst_remaining > 0 {
+            self.burst_remaining -= 1;
+            self.timestamp_ns += 1_000;
+            return;
+        }
+
+        if self.rng.next_f64() < self.config.burst_probability {
+            self.burst_remaining = self.config.burst_size.saturating_sub(1);
+        }
+
+        // Exponential inter-arrival times around the configured mean
+        let gap = -(1.0 - self.rng.next_f64()).ln() * self.config.mean_interval_ns as f64;
+        self.timestamp_ns += gap.max(1.0) as u64;
+    }
+}
+
+impl Iterator for SyntheticEventGenerator {
+    type Item = LatencyEvent;
+
+    fn next(&mut self) -> Option<LatencyEvent> {
+        if self.emitted >= self.config.events {
+            return None;
+        }
+
+        self.advance_clock();
+        let connection = self.rng.next_u64() as usize % self.config.connections.max(1);
+        let event_type = if self.rng.next_u64() & 3 == 0 {
+            EVENT_TYPE_CLEANUP
+        } else {
+            EVENT_TYPE_RECV
+        };
+
+        self.emitted += 1;
+
+        Some(LatencyEvent {
+            timestamp_ns: self.timestamp_ns,
+            pid: 1000 + connection as u32,
+            protocol: IPPROTO_TCP,
+            ..event(
+                Self::connection_key(connection),
+                self.config.distribution.sample(&mut self.rng).max(1),
+                event_type,
+            )
+        })
+    }
+}
+
+/// A single event with every other field zeroed
+///
+/// Tests set the fields they care about with struct update syntax:
+/// `LatencyEvent { pid: 42, ..testing::event(key, 1_000, EVENT_TYPE_RECV) }`.
+/// The protocol is left unset, which is labeled by the event type.
+pub fn event(key: ConnectionKey, latency_ns: u64, event_type: u8) -> LatencyEvent {
+    LatencyEvent {
+        key,
+        netns: 0,
+        cookie: 0,
+        timestamp_ns: 0,
+        latency_ns,
+        pid: 0,
+        event_type,
+        http_status_class: 0,
+        tcp_state: 0,
+        tcp_flags: 0,
+        protocol: 0,
+        _padding: [0; 7],
+    }
+}
+
+/// Small deterministic PRNG (SplitMix64)
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal sample (Box-Muller)
+    fn next_gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
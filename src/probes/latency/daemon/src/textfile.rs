@@ -0,0 +1,113 @@
+//! node_exporter textfile collector output
+//!
+//! With `--textfile-dir`, the probe keeps a `latency_probe.prom` file in
+//! the directory node_exporter's textfile collector reads
+//! (`--collector.textfile.directory`), refreshed with every snapshot, so an
+//! existing node_exporter scrape picks up the probe's metrics without a new
+//! scrape config. The file is in the Prometheus text format without
+//! timestamps, which the collector rejects.
+//!
+//! Each refresh is written to a temporary file in the same directory and
+//! renamed over the previous one, so a scrape never reads a partial file.
+//! The temporary file does not end in `.prom`, so it is never collected.
+//! Named instances (see crate::instance) write `latency_probe_<instance>.prom`,
+//! so several probes can share the directory.
+
+use crate::{
+    exporter::{MetricsExporter, PrometheusExporter},
+    instance::InstanceId,
+    types::LatencyMetrics,
+};
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Name of the metrics file in the textfile directory
+pub const TEXTFILE_NAME: &str = "latency_probe.prom";
+
+/// Writes metrics for node_exporter's textfile collector
+pub struct TextfileWriter {
+    path: PathBuf,
+    tmp_path: PathBuf,
+}
+
+impl TextfileWriter {
+    /// Create a writer for a textfile collector directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory read by node_exporter's textfile collector
+    /// * `instance` - Name of the probe instance (None if unnamed)
+    pub fn new(dir: &Path, instance: Option<&InstanceId>) -> Result<Self> {
+        if !dir.is_dir() {
+            anyhow::bail!("Textfile directory does not exist: {:?}", dir);
+        }
+        let name = match instance {
+            Some(instance) => TEXTFILE_NAME.replace(".prom", &format!("_{}.prom", instance)),
+            None => TEXTFILE_NAME.to_string(),
+        };
+        Ok(Self {
+            path: dir.join(&name),
+            tmp_path: dir.join(format!(".{}.{}", name, std::process::id())),
+        })
+    }
+
+    /// Path of the metrics file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replace the metrics file with the metrics of a report
+    pub fn write(&self, metrics: &LatencyMetrics) -> Result<()> {
+        let mut file = File::create(&self.tmp_path)
+            .with_context(|| format!("Failed to create textfile: {:?}", self.tmp_path))?;
+        file.write_all(PrometheusExporter::to_prometheus_format(metrics).as_bytes())
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("Failed to write textfile: {:?}", self.tmp_path))?;
+
+        std::fs::rename(&self.tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace textfile: {:?}", self.path))
+    }
+}
+
+impl MetricsExporter for TextfileWriter {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        self.write(metrics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_textfile_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = TextfileWriter::new(dir.path(), None).unwrap();
+
+        let mut metrics = LatencyMetrics {
+            total_events: 5,
+            ..Default::default()
+        };
+        writer.write(&metrics).unwrap();
+        metrics.total_events = 9;
+        writer.write(&metrics).unwrap();
+
+        let contents = std::fs::read_to_string(writer.path()).unwrap();
+        assert!(contents.contains("latency_probe_events_total 9\n"));
+
+        // Only the metrics file is left behind
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(files, vec![TEXTFILE_NAME]);
+
+        assert!(TextfileWriter::new(&dir.path().join("missing"), None).is_err());
+
+        // Named instances share the directory
+        let instance = "ns-a".parse().unwrap();
+        let writer = TextfileWriter::new(dir.path(), Some(&instance)).unwrap();
+        assert_eq!(writer.path(), dir.path().join("latency_probe_ns-a.prom"));
+    }
+}
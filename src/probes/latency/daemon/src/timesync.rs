@@ -0,0 +1,160 @@
+//! Clock synchronization status of the node
+//!
+//! Wall clock times (see crate::clock) of two nodes only agree as closely
+//! as their clocks are synchronized. The time daemon knows how far off it
+//! believes the clock is, so live runs ask it when they start:
+//!
+//! * chrony - `chronyc -c tracking`: the system time offset, and root delay
+//!   / 2 + root dispersion (chrony's bound on the error) as uncertainty
+//! * ptp4l - `pmc -u -b 0 'GET TIME_STATUS_NP'`: the offset of the PTP
+//!   hardware clock from the grandmaster, and its magnitude as uncertainty
+//!   (phc2sys adds its own, usually smaller, error on the system clock)
+//!
+//! The result is recorded in reports and correlation files, and `analyze
+//! stitch` removes each node's offset before matching events.
+
+use crate::types::ClockSync;
+use log::debug;
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// How long a time daemon is given to answer
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Query the local time daemon (chrony, then ptp4l)
+///
+/// # Returns
+///
+/// The clock's offset and its uncertainty, or None if neither daemon
+/// answered
+pub fn query() -> Option<ClockSync> {
+    run("chronyc", &["-c", "tracking"])
+        .and_then(|output| parse_chrony_tracking(&output))
+        .or_else(|| run("pmc", &["-u", "-b", "0", "GET TIME_STATUS_NP"]).and_then(|output| parse_pmc_time_status(&output)))
+}
+
+/// Parse the CSV output of `chronyc -c tracking`
+///
+/// Fields: reference ID, reference name, stratum, reference time, system
+/// time (seconds, positive when the clock is slow), last offset, RMS
+/// offset, frequency, residual frequency, skew, root delay, root
+/// dispersion, update interval, leap status.
+fn parse_chrony_tracking(output: &str) -> Option<ClockSync> {
+    let fields: Vec<&str> = output.lines().next()?.split(',').collect();
+    if fields.len() < 12 {
+        return None;
+    }
+    let seconds = |index: usize| fields[index].trim().parse::<f64>().ok().filter(|value| value.is_finite());
+    let correction = seconds(4)?;
+    let root_delay = seconds(10)?;
+    let root_dispersion = seconds(11)?;
+    // An unsynchronized chrony reports a zero reference ID
+    if fields[0].trim_start_matches('0').is_empty() {
+        return None;
+    }
+
+    Some(ClockSync {
+        daemon: "chrony".to_string(),
+        reference: Some(fields[1].to_string()).filter(|name| !name.is_empty()),
+        offset_ns: (-correction * 1e9).round() as i64,
+        uncertainty_ns: ((root_delay.abs() / 2.0 + root_dispersion.abs()) * 1e9).round() as u64,
+    })
+}
+
+/// Parse the answer of `pmc 'GET TIME_STATUS_NP'`
+fn parse_pmc_time_status(output: &str) -> Option<ClockSync> {
+    let value = |name: &str| {
+        output.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            (parts.next() == Some(name)).then(|| parts.next()).flatten()
+        })
+    };
+    if value("gmPresent")? != "true" {
+        return None;
+    }
+    let offset_ns: i64 = value("master_offset")?.parse().ok()?;
+
+    Some(ClockSync {
+        daemon: "ptp4l".to_string(),
+        reference: value("gmIdentity").map(str::to_string),
+        offset_ns,
+        uncertainty_ns: offset_ns.unsigned_abs(),
+    })
+}
+
+/// Run a command and return its output, if it succeeds in time
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("{} not available: {}", program, e);
+            return None;
+        }
+    };
+
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(Some(status)) => {
+                debug!("{} failed: {}", program, status);
+                return None;
+            }
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            _ => {
+                debug!("{} did not answer within {:?}", program, QUERY_TIMEOUT);
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+
+    let mut output = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_daemon_answers() {
+        // Clock 12.5us slow, 1ms root delay, 200us root dispersion
+        let chrony = "A29FC87B,ntp1.example.com,3,1700000000.123456789,0.000012500,0.000001000,0.000020000,-12.345,0.001,0.050,0.001000000,0.000200000,64.2,Normal\n";
+        assert_eq!(
+            parse_chrony_tracking(chrony),
+            Some(ClockSync {
+                daemon: "chrony".to_string(),
+                reference: Some("ntp1.example.com".to_string()),
+                offset_ns: -12_500,
+                uncertainty_ns: 700_000,
+            })
+        );
+        assert_eq!(parse_chrony_tracking("00000000,,0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,1.0,1.0,0.0,Not synchronised"), None);
+        assert_eq!(parse_chrony_tracking("506 Cannot talk to daemon"), None);
+
+        let pmc = "sending: GET TIME_STATUS_NP
+	90e2ba.fffe.0e2c78-0 seq 0 RESPONSE MANAGEMENT TIME_STATUS_NP
+		master_offset              -14
+		ingress_time               1700000000123456789
+		cumulativeScaledRateOffset +0.000000000
+		gmPresent                  true
+		gmIdentity                 90e2ba.fffe.0e2c78
+";
+        let sync = parse_pmc_time_status(pmc).unwrap();
+        assert_eq!((sync.offset_ns, sync.uncertainty_ns), (-14, 14));
+        assert_eq!(sync.reference.as_deref(), Some("90e2ba.fffe.0e2c78"));
+        assert_eq!(parse_pmc_time_status(&pmc.replace("true", "false")), None);
+    }
+}
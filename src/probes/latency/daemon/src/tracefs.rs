@@ -0,0 +1,160 @@
+//! Tracepoint record layouts from tracefs
+//!
+//! Tracepoint fields are a more stable interface than kernel function
+//! arguments, but fields are still added between kernel versions, shifting
+//! the offsets of later ones. This module reads the `format` file the
+//! kernel publishes for each tracepoint so the eBPF programs can be told
+//! where each field is.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// tracefs mount points, newest first
+pub const TRACEFS_ROOTS: &[&str] = &["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// Read the record format of a tracepoint
+///
+/// # Arguments
+///
+/// * `category` - Tracepoint category, e.g. `tcp`
+/// * `name` - Tracepoint name, e.g. `tcp_probe`
+pub fn read_format(category: &str, name: &str) -> Result<String> {
+    TRACEFS_ROOTS
+        .iter()
+        .map(|root| Path::new(root).join("events").join(category).join(name).join("format"))
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .with_context(|| {
+            format!(
+                "Tracepoint {}:{} not found (is tracefs mounted at {}?)",
+                category, name, TRACEFS_ROOTS[0]
+            )
+        })
+}
+
+/// Check whether the kernel provides a tracepoint
+pub fn has_tracepoint(category: &str, name: &str) -> bool {
+    TRACEFS_ROOTS
+        .iter()
+        .any(|root| Path::new(root).join("events").join(category).join(name).exists())
+}
+
+/// Offset of a field in a tracepoint record
+///
+/// Parses lines such as `field:__u16 sport; offset:64; size:2;` (tab
+/// separated). Offsets include the common header, matching what eBPF
+/// tracepoint programs read.
+///
+/// # Returns
+///
+/// The byte offset, or None if the record has no such field
+pub fn field_offset(format: &str, field: &str) -> Option<u32> {
+    format.lines().find_map(|line| {
+        let mut parts = line.trim().split(';');
+        let declaration = parts.next()?.trim().strip_prefix("field:")?;
+
+        // Last word of the declaration, without any array suffix
+        let name = declaration.split('[').next()?.rsplit(' ').next()?;
+        if name != field {
+            return None;
+        }
+
+        parts
+            .find_map(|part| part.trim().strip_prefix("offset:"))?
+            .parse()
+            .ok()
+    })
+}
+
+/// Field offsets in the tcp:tcp_probe record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpProbeOffsets {
+    /// Local address (`struct sockaddr_in6` sized)
+    pub saddr: u32,
+    /// Remote address
+    pub daddr: u32,
+    /// Smoothed RTT in microseconds
+    pub srtt: u32,
+    /// Socket pointer (absent on older kernels)
+    pub skaddr: Option<u32>,
+    /// Congestion window in segments
+    pub snd_cwnd: Option<u32>,
+    /// Slow start threshold in segments
+    pub ssthresh: Option<u32>,
+}
+
+impl TcpProbeOffsets {
+    /// Resolve the offsets for the running kernel
+    pub fn from_tracefs() -> Result<Self> {
+        Self::parse(&read_format("tcp", "tcp_probe")?)
+    }
+
+    /// Resolve the offsets from a tcp_probe format description
+    pub fn parse(format: &str) -> Result<Self> {
+        let required = |field: &str| {
+            field_offset(format, field)
+                .with_context(|| format!("tcp:tcp_probe has no {} field", field))
+        };
+
+        Ok(Self {
+            saddr: required("saddr")?,
+            daddr: required("daddr")?,
+            srtt: required("srtt")?,
+            skaddr: field_offset(format, "skaddr"),
+            snd_cwnd: field_offset(format, "snd_cwnd"),
+            ssthresh: field_offset(format, "ssthresh"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TCP_PROBE_FORMAT: &str = "\
+name: tcp_probe
+ID: 1465
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:__u8 saddr[sizeof(struct sockaddr_in6)];\toffset:8;\tsize:28;\tsigned:0;
+\tfield:__u8 daddr[sizeof(struct sockaddr_in6)];\toffset:36;\tsize:28;\tsigned:0;
+\tfield:__u16 sport;\toffset:64;\tsize:2;\tsigned:0;
+\tfield:__u16 dport;\toffset:66;\tsize:2;\tsigned:0;
+\tfield:__u16 family;\toffset:68;\tsize:2;\tsigned:0;
+\tfield:__u32 mark;\toffset:72;\tsize:4;\tsigned:0;
+\tfield:__u16 data_len;\toffset:76;\tsize:2;\tsigned:0;
+\tfield:__u32 snd_nxt;\toffset:80;\tsize:4;\tsigned:0;
+\tfield:__u32 snd_una;\toffset:84;\tsize:4;\tsigned:0;
+\tfield:__u32 snd_cwnd;\toffset:88;\tsize:4;\tsigned:0;
+\tfield:__u32 ssthresh;\toffset:92;\tsize:4;\tsigned:0;
+\tfield:__u32 snd_wnd;\toffset:96;\tsize:4;\tsigned:0;
+\tfield:__u32 srtt;\toffset:100;\tsize:4;\tsigned:0;
+\tfield:__u32 rcv_wnd;\toffset:104;\tsize:4;\tsigned:0;
+\tfield:__u64 sock_cookie;\toffset:112;\tsize:8;\tsigned:0;
+
+print fmt: \"family=%s src=%pISpc dest=%pISpc\", ...
+";
+
+    #[test]
+    fn test_tcp_probe_offsets() {
+        let offsets = TcpProbeOffsets::parse(TCP_PROBE_FORMAT).unwrap();
+
+        assert_eq!(
+            offsets,
+            TcpProbeOffsets {
+                saddr: 8,
+                daddr: 36,
+                srtt: 100,
+                skaddr: None,
+                snd_cwnd: Some(88),
+                ssthresh: Some(92),
+            }
+        );
+        assert_eq!(field_offset(TCP_PROBE_FORMAT, "common_pid"), Some(4));
+        assert_eq!(field_offset(TCP_PROBE_FORMAT, "snd"), None);
+        assert!(TcpProbeOffsets::parse("name: tcp_probe\n").is_err());
+    }
+}
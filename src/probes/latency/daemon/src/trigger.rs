@@ -0,0 +1,223 @@
+//! Latency-triggered detailed capture
+//!
+//! Capturing every event costs perf buffer bandwidth and CPU for the whole
+//! run, but heavy sampling leaves few events to diagnose a latency spike
+//! with. In trigger mode (`--trigger-p99-us`), the probe samples as
+//! configured and checks the p99 of every check interval; when it exceeds
+//! the threshold, sampling switches to every event for the capture
+//! duration, then back. With `--trigger-dump`, the raw events of each
+//! capture are also recorded (see [`CaptureDump`]), for replay.
+//!
+//! Each switch is applied like a config reload, so it shows in the report's
+//! `config_changes`.
+
+use crate::{collector::MetricsCollector, replay::EventRecorder, run::RunId, types::LatencyEvent};
+use anyhow::Result;
+use log::warn;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// When to capture every event, and for how long
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriggerPolicy {
+    /// p99 of a check interval above which capture starts (microseconds)
+    pub p99_threshold_us: f64,
+    /// How long a capture lasts
+    pub duration: Duration,
+}
+
+/// What to do after a check interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerAction {
+    /// Keep the current sampling
+    None,
+    /// Start capturing every event; the p99 that triggered it
+    Start(f64),
+    /// Return to the configured sampling
+    Stop,
+}
+
+/// Decides when detailed captures start and end
+pub struct CaptureTrigger {
+    policy: TriggerPolicy,
+    /// Samples in the collector at the last check
+    mark: usize,
+    /// End of the running capture
+    capturing_until: Option<Instant>,
+}
+
+impl CaptureTrigger {
+    /// Create a trigger, checking intervals from the collector's current
+    /// samples on
+    pub fn new(policy: TriggerPolicy, collector: &MetricsCollector) -> Self {
+        Self {
+            policy,
+            mark: collector.sample_count(),
+            capturing_until: None,
+        }
+    }
+
+    /// Whether a capture is running
+    pub fn is_capturing(&self) -> bool {
+        self.capturing_until.is_some()
+    }
+
+    /// Account for one check interval
+    ///
+    /// # Arguments
+    ///
+    /// * `collector` - Collector holding the interval's samples
+    /// * `now` - End of the interval
+    pub fn observe(&mut self, collector: &MetricsCollector, now: Instant) -> TriggerAction {
+        let count = collector.sample_count();
+        // A rotation empties the collector; start over from zero
+        let start = if count < self.mark { 0 } else { self.mark };
+        self.mark = count;
+
+        match self.capturing_until {
+            Some(until) if now >= until => {
+                self.capturing_until = None;
+                TriggerAction::Stop
+            }
+            Some(_) => TriggerAction::None,
+            None if count > start => {
+                let p99 = collector.percentiles_since(start).p99;
+                if p99 <= self.policy.p99_threshold_us {
+                    return TriggerAction::None;
+                }
+                self.capturing_until = Some(now + self.policy.duration);
+                TriggerAction::Start(p99)
+            }
+            None => TriggerAction::None,
+        }
+    }
+}
+
+/// Recording of the raw events of triggered captures
+///
+/// Register [`record`](Self::record) as an event callback; events are only
+/// written while a capture runs.
+#[derive(Clone)]
+pub struct CaptureDump {
+    dir: PathBuf,
+    recorder: Arc<RwLock<Option<EventRecorder>>>,
+    run_id: Option<RunId>,
+}
+
+impl CaptureDump {
+    /// Record captures to files in a directory
+    pub fn new(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            anyhow::bail!("Trigger dump directory does not exist: {:?}", dir);
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            recorder: Arc::new(RwLock::new(None)),
+            run_id: None,
+        })
+    }
+
+    /// Stamp captured events with the ID of the run
+    pub fn with_run_id(mut self, run_id: RunId) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Record an event, if a capture is running
+    pub fn record(&self, event: &LatencyEvent) {
+        let recorder = self.recorder.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(Err(e)) = recorder.as_ref().map(|r| r.record(event)) {
+            warn!("Failed to record event: {}", e);
+        }
+    }
+
+    /// Start recording a capture
+    ///
+    /// # Returns
+    ///
+    /// Path of the recording (`trigger-<timestamp>.jsonl`)
+    pub fn start(&self) -> Result<PathBuf> {
+        let path = self
+            .dir
+            .join(format!("trigger-{}.jsonl", chrono::Local::now().format("%Y%m%dT%H%M%S")));
+        let mut recorder = EventRecorder::create(&path)?;
+        if let Some(run_id) = &self.run_id {
+            recorder = recorder.with_run_id(run_id.clone());
+        }
+        *self.recorder.write().unwrap_or_else(|e| e.into_inner()) = Some(recorder);
+        Ok(path)
+    }
+
+    /// Stop recording and flush the capture
+    pub fn stop(&self) -> Result<()> {
+        let recorder = self.recorder.write().unwrap_or_else(|e| e.into_inner()).take();
+        match recorder {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::types::ConnectionKey;
+
+    #[test]
+    fn test_capture_trigger() {
+        let event = |latency_us: u64| LatencyEvent {
+            timestamp_ns: 1_000_000,
+            pid: 42,
+            ..testing::event(
+                ConnectionKey {
+                    saddr: 0x0100000a,
+                    daddr: 0x0200000a,
+                    sport: 0x3930,
+                    dport: 0x901f,
+                },
+                latency_us * 1000,
+                probe_common::constants::EVENT_TYPE_RECV,
+            )
+        };
+        let policy = TriggerPolicy {
+            p99_threshold_us: 1000.0,
+            duration: Duration::from_secs(30),
+        };
+
+        let mut collector = MetricsCollector::new();
+        for _ in 0..100 {
+            collector.add_event(&event(5000));
+        }
+        // Samples from before the trigger was created do not count
+        let mut trigger = CaptureTrigger::new(policy, &collector);
+        let start = Instant::now();
+        assert_eq!(trigger.observe(&collector, start), TriggerAction::None);
+
+        for _ in 0..100 {
+            collector.add_event(&event(100));
+        }
+        assert_eq!(trigger.observe(&collector, start), TriggerAction::None);
+
+        collector.add_event(&event(5000));
+        assert_eq!(trigger.observe(&collector, start), TriggerAction::Start(5000.0));
+        assert!(trigger.is_capturing());
+        collector.add_event(&event(5000));
+        assert_eq!(trigger.observe(&collector, start + Duration::from_secs(10)), TriggerAction::None);
+        assert_eq!(trigger.observe(&collector, start + Duration::from_secs(30)), TriggerAction::Stop);
+        assert!(!trigger.is_capturing());
+
+        // Only events during a capture are dumped
+        let dir = tempfile::tempdir().unwrap();
+        let dump = CaptureDump::new(dir.path()).unwrap();
+        dump.record(&event(1));
+        let path = dump.start().unwrap();
+        dump.record(&event(2));
+        dump.stop().unwrap();
+        dump.record(&event(3));
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+}
@@ -9,8 +9,9 @@
 //! - **Kernel Types**: Types shared with eBPF programs (from probe_common)
 //! - **Userspace Types**: Types used only in userspace for aggregation and export
 
+use crate::digest::LatencyDigest;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 // ============================================================================
 // Kernel Types (from eBPF programs)
@@ -22,7 +23,7 @@ pub mod kernel {
     //! These are re-exported from the probe-common crate and must
     //! maintain binary compatibility with the eBPF programs.
 
-    pub use probe_common::types::{ConnectionKey, LatencyEvent, PacketDropEvent, ConnectionState, ContextSwitchEvent};
+    pub use probe_common::types::{ConnectionKey, LatencyEvent, PacketDropEvent, ConnectionState, CongestionState, ContextSwitchEvent, ListenerKey, ListenerStats};
     pub use probe_common::constants;
 }
 
@@ -30,22 +31,97 @@ pub mod kernel {
 pub use kernel::{ConnectionKey, LatencyEvent};
 
 /// Aggregated metrics for export
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct LatencyMetrics {
     /// ISO 8601 timestamp when metrics were collected
     pub timestamp: String,
     /// Duration of collection period in seconds
     pub duration_seconds: u64,
+    /// Labels describing the run (e.g. the scenario phase)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Name of the probe instance (see crate::instance)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// ID of the run the report belongs to (see crate::run)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Clock of the event timestamps and its offset to the wall clock
+    /// (None for replays)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockInfo>,
+    /// Node the events were collected on (None for replays)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<HostInfo>,
+    /// BPF features of the node, and which the probe used (None for replays)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<KernelFeatures>,
     /// Total number of events captured
     pub total_events: u64,
+    /// Events lost because a perf buffer was full
+    #[serde(default)]
+    pub lost_events: u64,
     /// Per-connection metrics
-    pub connections: HashMap<String, ConnectionMetrics>,
+    pub connections: BTreeMap<String, ConnectionMetrics>,
+    /// Events on connections beyond --max-connections, which are not in
+    /// the per-connection metrics
+    #[serde(default)]
+    pub untracked_connection_events: u64,
+    /// Events of processes beyond --max-processes, which are not in the
+    /// per-process metrics
+    #[serde(default)]
+    pub untracked_process_events: u64,
     /// Latency histogram across all connections
     pub histogram: LatencyHistogram,
     /// Latency percentiles across all connections
     pub percentiles: Percentiles,
+    /// Digest of the latencies across all connections, so reports can be
+    /// merged without their samples (see [`LatencyMetrics::merge`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+    /// Variation between consecutive latencies of a connection
+    #[serde(default)]
+    pub jitter: JitterStats,
+    /// What coincided with the events above p99 (None unless
+    /// --tail-analysis is enabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<TailBreakdown>,
     /// Breakdown by event type
     pub event_type_breakdown: EventTypeBreakdown,
+    /// Receives reported by both tcp_recvmsg and tcp_cleanup_rbuf, and
+    /// how they were counted
+    #[serde(default)]
+    pub dedup: DedupStats,
+    /// DNS resolution latency (not included in the TCP latency above)
+    #[serde(default)]
+    pub dns_latency: DnsLatencyStats,
+    /// Connection resets and retransmission timeouts
+    #[serde(default)]
+    pub errors: ConnectionErrorStats,
+    /// Bytes transferred during the collection period
+    #[serde(default)]
+    pub throughput: ThroughputStats,
+    /// Accept queues of listening sockets, keyed by port (only ports that
+    /// received connection requests)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub listeners: BTreeMap<String, ListenerMetrics>,
+    /// Events per time bucket over the collection period
+    #[serde(default)]
+    pub event_rate: EventRateSeries,
+    /// Short bursts of events above the median rate
+    #[serde(default)]
+    pub bursts: BurstStats,
+    /// Latency histogram per time bucket over the collection period
+    #[serde(default)]
+    pub heatmap: LatencyHeatmap,
+    /// Percentiles of each snapshot interval, oldest first (empty unless
+    /// snapshots are taken), for plotting latency over time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trajectory: Vec<IntervalPercentiles>,
+    /// Coordinated omission correction applied to the histogram and
+    /// percentiles (None if the latencies are as measured)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coordinated_omission: Option<OmissionCorrection>,
     /// Packet drop statistics
     pub packet_drops: PacketDropStats,
     /// Connection state statistics
@@ -54,6 +130,682 @@ pub struct LatencyMetrics {
     pub context_switches: ContextSwitchStats,
     /// XDP packet statistics
     pub xdp_stats: XdpPacketStats,
+    /// Latency validity bounds and rejected events (None for replays)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_bounds: Option<LatencyBounds>,
+    /// Runtime configuration changes during the collection period
+    #[serde(default)]
+    pub config_changes: Vec<ConfigChange>,
+    /// Faults injected during collection
+    #[serde(default)]
+    pub fault_injections: Vec<FaultInjection>,
+    /// Latency observed by the load generator in the same window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientLatency>,
+    /// Latency recorded by Envoy proxies in the same window
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub envoy: Vec<ProxyLatency>,
+    /// Latency reported by the mesh's telemetry for the same window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshLatency>,
+    /// Per network namespace metrics, keyed by namespace inode
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, GroupMetrics>,
+    /// Per process metrics, keyed by PID
+    #[serde(default)]
+    pub processes: BTreeMap<String, ProcessMetrics>,
+    /// Per pod metrics, keyed by pod UID (resolved from the cgroups of
+    /// event PIDs; empty for replays)
+    #[serde(default)]
+    pub pods: BTreeMap<String, PodMetrics>,
+    /// Per service metrics, keyed by service name (classified by port)
+    #[serde(default)]
+    pub services: BTreeMap<String, GroupMetrics>,
+    /// Per transport protocol metrics, keyed by protocol ("tcp", "udp",
+    /// "quic")
+    #[serde(default)]
+    pub protocols: BTreeMap<String, ProtocolMetrics>,
+    /// Per traffic class metrics, keyed by class ("loopback", "external")
+    #[serde(default)]
+    pub traffic_classes: BTreeMap<String, GroupMetrics>,
+    /// Intra-zone and cross-zone metrics, keyed by locality (empty
+    /// without --zone-map)
+    #[serde(default)]
+    pub zones: BTreeMap<String, GroupMetrics>,
+    /// Per tenant metrics, keyed by tenant name (empty without --tenants)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tenants: BTreeMap<String, TenantMetrics>,
+    /// Per connection phase metrics, keyed by phase ("handshake",
+    /// "established", "closing", "unknown")
+    #[serde(default)]
+    pub phases: BTreeMap<String, GroupMetrics>,
+    /// Per HTTP status class metrics, keyed by class (e.g. "5xx"); only
+    /// events on connections with a classified response are included
+    #[serde(default)]
+    pub http_status: BTreeMap<String, GroupMetrics>,
+    /// Which eBPF programs were attached, and where
+    #[serde(default)]
+    pub probes: Vec<ProbeAttachment>,
+    /// In-kernel run time of each attached program (empty if BPF stats are unavailable)
+    #[serde(default)]
+    pub program_stats: Vec<ProgramStats>,
+    /// Map occupancy and event loss
+    #[serde(default)]
+    pub health: ProbeHealth,
+}
+
+/// Map fill ratio above which the probe warns that a map is nearly full
+pub const MAP_FILL_WARNING: f64 = 0.8;
+
+/// Probe health indicators
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ProbeHealth {
+    /// Occupancy of the monitored eBPF maps, from the latest check
+    pub maps: Vec<MapHealth>,
+    /// Fraction of events lost to full perf buffers
+    pub event_loss_ratio: f64,
+    /// Events the probe itself discarded
+    #[serde(default)]
+    pub internal: InternalCounters,
+    /// Exporter thread, if reports go to Zabbix, NATS or a textfile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<ExportStats>,
+    /// Queues between the perf buffer readers and the collector (live
+    /// runs only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<ChannelStats>,
+}
+
+/// Queues between the perf buffer readers and the collector (see
+/// crate::backpressure)
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// What happens to events that find a queue full ("drop-newest",
+    /// "drop-oldest", "block")
+    pub policy: String,
+    /// Events each per-CPU queue holds
+    pub capacity: u64,
+    /// Events dropped by the policy (whole run)
+    pub dropped_events: u64,
+    /// Times a reader waited for room (block policy)
+    pub blocked_waits: u64,
+    /// Most events queued for one reader at once
+    pub max_depth: u64,
+}
+
+/// Queue and latency of the exporter thread (see crate::publish)
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ExportStats {
+    /// Reports waiting at the latest reading
+    pub queue_depth: u64,
+    /// Most reports waiting at once
+    pub max_queue_depth: u64,
+    /// Reports the queue holds (--export-queue)
+    pub queue_capacity: u64,
+    /// Snapshots dropped because the queue was full
+    pub dropped_snapshots: u64,
+    /// Snapshots and reports exported
+    pub exports: u64,
+    /// Time the latest export took, to every destination (milliseconds)
+    pub last_latency_ms: f64,
+    /// Longest export (milliseconds)
+    pub max_latency_ms: f64,
+}
+
+/// Events discarded inside the probe rather than measured
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct InternalCounters {
+    /// Kernel events whose socket could not be read (whole run)
+    pub invalid_sockets: u64,
+    /// Kernel latencies rejected as implausible (whole run)
+    pub invalid_latency: u64,
+    /// Events lost to full perf buffers before reaching the daemon
+    pub dropped_events: u64,
+}
+
+impl InternalCounters {
+    /// Name, description and value of each counter
+    pub fn fields(&self) -> [(&'static str, &'static str, u64); 3] {
+        [
+            ("invalid_sockets", "Kernel events whose socket could not be read", self.invalid_sockets),
+            ("invalid_latency", "Kernel latencies rejected as implausible", self.invalid_latency),
+            ("dropped_events", "Events lost to full perf buffers", self.dropped_events),
+        ]
+    }
+}
+
+/// Occupancy of one eBPF hash map
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapHealth {
+    /// Map name in the eBPF object
+    pub map: String,
+    /// Entries currently in the map
+    pub entries: u64,
+    /// Capacity of the map
+    pub max_entries: u32,
+    /// entries / max_entries
+    pub fill_ratio: f64,
+    /// Entries with a value on more than one CPU (per-CPU maps only);
+    /// for CONNECTION_START, flows that moved between CPUs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_entries: Option<u64>,
+}
+
+impl MapHealth {
+    /// Build a map occupancy reading
+    ///
+    /// # Arguments
+    ///
+    /// * `map` - Map name
+    /// * `entries` - Entries currently in the map
+    /// * `max_entries` - Capacity of the map
+    pub fn new(map: &str, entries: u64, max_entries: u32) -> Self {
+        Self {
+            map: map.to_string(),
+            entries,
+            max_entries,
+            fill_ratio: if max_entries > 0 {
+                entries as f64 / max_entries as f64
+            } else {
+                0.0
+            },
+            split_entries: None,
+        }
+    }
+
+    /// True if the map is full enough that new entries may soon be rejected
+    pub fn is_near_full(&self) -> bool {
+        self.fill_ratio > MAP_FILL_WARNING
+    }
+}
+
+/// Outcome of attaching one eBPF program
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProbeAttachment {
+    /// Program name in the eBPF object
+    pub program: String,
+    /// Kernel symbol or tracepoint the program is attached to (None if inactive)
+    pub attached_to: Option<String>,
+    /// Why the program is not attached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProbeAttachment {
+    /// Record the result of an attach attempt
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - Program name
+    /// * `result` - Attach point on success
+    pub fn from_result(program: &str, result: anyhow::Result<String>) -> Self {
+        match result {
+            Ok(target) => Self {
+                program: program.to_string(),
+                attached_to: Some(target),
+                error: None,
+            },
+            Err(e) => Self {
+                program: program.to_string(),
+                attached_to: None,
+                error: Some(format!("{:#}", e)),
+            },
+        }
+    }
+
+    /// True if the program is attached
+    pub fn is_active(&self) -> bool {
+        self.attached_to.is_some()
+    }
+}
+
+/// In-kernel overhead of one eBPF program
+///
+/// Counters are cumulative since BPF run time stats were enabled, which is
+/// normally when the programs were attached.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgramStats {
+    /// Program name (qualified when loaded from several objects)
+    pub program: String,
+    /// Total time spent running the program in nanoseconds
+    pub run_time_ns: u64,
+    /// Number of times the program ran
+    pub run_count: u64,
+    /// Average run time per invocation in nanoseconds
+    pub avg_run_time_ns: f64,
+}
+
+impl ProgramStats {
+    /// Build stats from the kernel's cumulative counters
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - Program name
+    /// * `run_time_ns` - Total run time in nanoseconds
+    /// * `run_count` - Number of runs
+    pub fn new(program: &str, run_time_ns: u64, run_count: u64) -> Self {
+        Self {
+            program: program.to_string(),
+            run_time_ns,
+            run_count,
+            avg_run_time_ns: if run_count > 0 {
+                run_time_ns as f64 / run_count as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Marker recorded when the probe configuration is reloaded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    /// ISO 8601 timestamp of the change
+    pub timestamp: String,
+    /// Events collected before the change took effect
+    pub events_before: u64,
+    /// New sampling rate
+    pub sample_rate: u32,
+    /// New sampling rates of single event types
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub event_sample_rates: BTreeMap<String, u32>,
+    /// New service filter (empty = all services)
+    pub filter_services: Vec<String>,
+}
+
+/// Clock of event timestamps (see crate::clock)
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClockInfo {
+    /// Kernel clock ("monotonic", "boottime", "tai")
+    pub source: String,
+    /// Wall clock (CLOCK_REALTIME) minus the source clock, in nanoseconds
+    pub wall_offset_ns: i64,
+    /// Bound on the error of the offset, in nanoseconds
+    pub uncertainty_ns: u64,
+    /// ISO 8601 timestamp of the capture
+    pub captured_at: String,
+    /// Synchronization of the wall clock, as the time daemon reported it
+    /// when the run started (None if no daemon answered)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<ClockSync>,
+}
+
+impl ClockInfo {
+    /// Convert an event timestamp to nanoseconds since the Unix epoch
+    pub fn to_wall_ns(&self, timestamp_ns: u64) -> i64 {
+        timestamp_ns as i64 + self.wall_offset_ns
+    }
+}
+
+/// Offset of a node's wall clock from its time reference (see
+/// crate::timesync)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClockSync {
+    /// Time daemon that reported it ("chrony", "ptp4l")
+    pub daemon: String,
+    /// NTP server or PTP grandmaster followed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Wall clock minus reference time, in nanoseconds
+    pub offset_ns: i64,
+    /// Bound on the error of the offset, in nanoseconds
+    pub uncertainty_ns: u64,
+}
+
+/// Node a run was collected on (see crate::host)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HostInfo {
+    /// CPU architecture ("x86_64", "aarch64")
+    pub arch: String,
+    /// Kernel release (`uname -r`)
+    pub kernel: String,
+}
+
+/// BPF features of a node, and which the probe used (see crate::host)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KernelFeatures {
+    /// What the kernel supports
+    pub available: AvailableFeatures,
+    /// What the probe ran with
+    pub used: UsedFeatures,
+}
+
+/// BPF features a kernel supports
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct AvailableFeatures {
+    /// Kernel BTF at /sys/kernel/btf/vmlinux
+    pub btf: bool,
+    /// BPF ring buffer maps
+    pub ringbuf: bool,
+    /// fentry/fexit programs
+    pub fentry: bool,
+}
+
+/// BPF features the probe ran with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UsedFeatures {
+    /// Buffer events reach userspace through ("perf")
+    pub event_buffer: String,
+    /// How the latency programs attach ("kprobe", "tracepoint")
+    pub attach: String,
+    /// Whether kernel struct offsets were resolved from BTF, the probe's
+    /// counterpart of CO-RE relocations
+    pub btf_offsets: bool,
+}
+
+/// Window during which a fault was injected (see crate::faults)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FaultInjection {
+    /// Interface the netem qdisc was installed on
+    pub interface: String,
+    /// netem parameters (e.g. "delay 5000us loss 0.1%")
+    pub netem: String,
+    /// ISO 8601 timestamp of the installation
+    pub started_at: String,
+    /// Events collected before the fault took effect
+    pub events_before: u64,
+    /// ISO 8601 timestamp of the removal (None if still active)
+    pub ended_at: Option<String>,
+    /// Events collected before the fault was removed
+    pub events_until: Option<u64>,
+}
+
+/// Latency percentiles reported by a load generator
+///
+/// Tools report different percentiles, so each may be missing.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ClientPercentiles {
+    /// 50th percentile (median)
+    pub p50: Option<f64>,
+    /// 90th percentile
+    pub p90: Option<f64>,
+    /// 95th percentile
+    pub p95: Option<f64>,
+    /// 99th percentile
+    pub p99: Option<f64>,
+    /// 99.9th percentile
+    pub p999: Option<f64>,
+}
+
+impl ClientPercentiles {
+    /// Set a percentile (50, 90, 95, 99 or 99.9); others are ignored
+    pub fn set(&mut self, percentile: f64, value: f64) {
+        let slot = match percentile {
+            50.0 => &mut self.p50,
+            90.0 => &mut self.p90,
+            95.0 => &mut self.p95,
+            99.0 => &mut self.p99,
+            99.9 => &mut self.p999,
+            _ => return,
+        };
+        *slot = Some(value);
+    }
+
+    /// These percentiles minus kernel-observed ones, where present
+    ///
+    /// # Arguments
+    ///
+    /// * `kernel` - Kernel-observed percentiles of the same window
+    pub fn above(&self, kernel: &Percentiles) -> ClientPercentiles {
+        let above = |value: Option<f64>, kernel: f64| value.map(|value| value - kernel);
+        ClientPercentiles {
+            p50: above(self.p50, kernel.p50),
+            p90: above(self.p90, kernel.p90),
+            p95: above(self.p95, kernel.p95),
+            p99: above(self.p99, kernel.p99),
+            p999: above(self.p999, kernel.p999),
+        }
+    }
+}
+
+/// Client-observed latency compared with kernel-observed latency (see
+/// crate::loadgen)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientLatency {
+    /// Load generator ("fortio", "k6", "wrk2")
+    pub tool: String,
+    /// Requests sent
+    pub requests: u64,
+    /// Requests that failed
+    pub errors: u64,
+    /// ISO 8601 start of the load, if reported
+    pub started_at: Option<String>,
+    /// Length of the load in seconds, if reported
+    pub duration_seconds: Option<f64>,
+    /// Average client latency in microseconds
+    pub avg_latency_us: f64,
+    /// Client latency percentiles in microseconds
+    pub percentiles: ClientPercentiles,
+    /// Client minus kernel latency at each percentile: time spent in the
+    /// client, the proxies and the application
+    pub above_kernel: ClientPercentiles,
+    /// Kernel latency percentiles at the sidecar proxy ports
+    #[serde(default)]
+    pub proxies: BTreeMap<String, Percentiles>,
+}
+
+/// Latency recorded by an Envoy proxy compared with kernel-observed
+/// latency (see crate::envoy)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProxyLatency {
+    /// Admin API the histogram was read from
+    pub admin: String,
+    /// Envoy histogram, e.g. `cluster.outbound|8080||frontend.upstream_rq_time`
+    pub histogram: String,
+    /// Samples recorded during the run
+    pub samples: u64,
+    /// Percentiles in microseconds, interpolated within Envoy's buckets
+    pub percentiles: ClientPercentiles,
+    /// Envoy minus kernel latency at each percentile
+    pub above_kernel: ClientPercentiles,
+}
+
+/// Latency reported by the mesh's telemetry compared with kernel-observed
+/// latency (see crate::istio)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MeshLatency {
+    /// Mesh ("istio")
+    pub mesh: String,
+    /// Label matchers of the compared series
+    pub selector: String,
+    /// Length of the window queried, ending with the run
+    pub window_seconds: u64,
+    /// Requests in the window, if any series matched
+    pub requests: Option<u64>,
+    /// Percentiles in microseconds
+    pub percentiles: ClientPercentiles,
+    /// Mesh minus kernel latency at each percentile
+    pub above_kernel: ClientPercentiles,
+}
+
+/// Metrics for one group of a per-group breakdown (e.g. a network
+/// namespace or a service)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GroupMetrics {
+    /// Number of events in this group
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles within this group
+    pub percentiles: Percentiles,
+    /// Digest of the group's latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+impl GroupMetrics {
+    /// Metrics of the latencies in a digest
+    pub fn from_digest(digest: &LatencyDigest) -> Self {
+        Self {
+            events: digest.count(),
+            avg_latency_us: digest.mean(),
+            percentiles: digest.percentiles(),
+            digest: Some(digest.clone()),
+        }
+    }
+}
+
+/// Metrics for one transport protocol
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ProtocolMetrics {
+    /// Number of events of this protocol
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles for this protocol
+    pub percentiles: Percentiles,
+    /// Latency histogram for this protocol
+    #[serde(default)]
+    pub histogram: LatencyHistogram,
+    /// Digest of the protocol's latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+/// Report label of an event's transport protocol
+///
+/// QUIC flights are labeled "quic" rather than "udp". Events without a
+/// protocol (recorded before the field existed) are labeled by their type.
+pub fn protocol_label(event: &LatencyEvent) -> &'static str {
+    use kernel::constants::*;
+
+    match (event.protocol, event.event_type) {
+        (_, EVENT_TYPE_QUIC) => "quic",
+        (IPPROTO_TCP, _) => "tcp",
+        (IPPROTO_UDP, _) | (0, EVENT_TYPE_DNS) => "udp",
+        (0, _) => "tcp",
+        _ => "other",
+    }
+}
+
+/// Whether an event type reports a reset or timeout rather than a latency
+pub fn is_connection_error(event_type: u8) -> bool {
+    matches!(
+        event_type,
+        kernel::constants::EVENT_TYPE_RESET_RECEIVED
+            | kernel::constants::EVENT_TYPE_RESET_SENT
+            | kernel::constants::EVENT_TYPE_TIMEOUT
+    )
+}
+
+/// Traffic class of connections within the host (e.g. sidecar to app)
+pub const TRAFFIC_CLASS_LOOPBACK: &str = "loopback";
+
+/// Traffic class of connections leaving the host's loopback (e.g. pod to pod)
+pub const TRAFFIC_CLASS_EXTERNAL: &str = "external";
+
+/// Check whether a connection is on loopback (either end in 127.0.0.0/8)
+pub fn is_loopback(key: &ConnectionKey) -> bool {
+    use std::net::Ipv4Addr;
+
+    Ipv4Addr::from(u32::from_be(key.saddr)).is_loopback()
+        || Ipv4Addr::from(u32::from_be(key.daddr)).is_loopback()
+}
+
+/// Report label of a connection's traffic class
+pub fn traffic_class(key: &ConnectionKey) -> &'static str {
+    if is_loopback(key) {
+        TRAFFIC_CLASS_LOOPBACK
+    } else {
+        TRAFFIC_CLASS_EXTERNAL
+    }
+}
+
+/// Phase of connections still opening (SYN outstanding)
+pub const PHASE_HANDSHAKE: &str = "handshake";
+
+/// Phase of established connections
+pub const PHASE_ESTABLISHED: &str = "established";
+
+/// Phase of connections shutting down (FIN exchanged, reset, or closed)
+pub const PHASE_CLOSING: &str = "closing";
+
+/// Phase of events without a TCP state (UDP, tracepoint events)
+pub const PHASE_UNKNOWN: &str = "unknown";
+
+/// Report label of the connection phase of a TCP state
+pub fn connection_phase(tcp_state: u8) -> &'static str {
+    use kernel::constants::*;
+
+    match tcp_state {
+        TCP_STATE_ESTABLISHED => PHASE_ESTABLISHED,
+        TCP_STATE_SYN_SENT | TCP_STATE_SYN_RECV | TCP_STATE_NEW_SYN_RECV => PHASE_HANDSHAKE,
+        TCP_STATE_FIN_WAIT1
+        | TCP_STATE_FIN_WAIT2
+        | TCP_STATE_TIME_WAIT
+        | TCP_STATE_CLOSE
+        | TCP_STATE_CLOSE_WAIT
+        | TCP_STATE_LAST_ACK
+        | TCP_STATE_CLOSING => PHASE_CLOSING,
+        _ => PHASE_UNKNOWN,
+    }
+}
+
+/// Check whether an event is on a half-open connection (handshake not
+/// complete)
+pub fn is_half_open(event: &LatencyEvent) -> bool {
+    connection_phase(event.tcp_state) == PHASE_HANDSHAKE
+}
+
+/// Metrics for one tenant (see crate::tenants)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TenantMetrics {
+    /// Number of events on the tenant's connections
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles for this tenant
+    pub percentiles: Percentiles,
+    /// Digest of the tenant's latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+/// Report label of an HTTP status class (e.g. 5 -> "5xx")
+pub fn http_status_label(class: u8) -> String {
+    format!("{}xx", class)
+}
+
+/// Metrics for a single process
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ProcessMetrics {
+    /// Process name (None if it exited before it could be resolved)
+    pub comm: Option<String>,
+    /// Process command line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<String>,
+    /// Number of events triggered by this process
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles for this process
+    pub percentiles: Percentiles,
+    /// Digest of the process's latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+/// Metrics for a single pod
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PodMetrics {
+    /// QoS class ("guaranteed", "burstable", "besteffort")
+    pub qos_class: String,
+    /// Short IDs of the containers whose processes triggered events
+    pub containers: Vec<String>,
+    /// Number of events triggered by processes in this pod
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles for this pod
+    pub percentiles: Percentiles,
+    /// Digest of the pod's latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
 }
 
 /// Metrics for a single connection
@@ -63,6 +815,9 @@ pub struct ConnectionMetrics {
     pub source: String,
     /// Destination address:port
     pub destination: String,
+    /// Socket cookie, if the connection was identified by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookie: Option<u64>,
     /// Number of events for this connection
     pub events: u64,
     /// Minimum latency in microseconds
@@ -73,8 +828,127 @@ pub struct ConnectionMetrics {
     pub avg_latency_us: f64,
     /// Standard deviation in microseconds
     pub std_dev_us: f64,
+    /// Median latency in microseconds (estimated within 1%)
+    #[serde(default)]
+    pub p50_us: f64,
+    /// 95th percentile latency in microseconds (estimated within 1%)
+    #[serde(default)]
+    pub p95_us: f64,
+    /// 99th percentile latency in microseconds (estimated within 1%)
+    #[serde(default)]
+    pub p99_us: f64,
+    /// RFC 3550 interarrival jitter in microseconds
+    #[serde(default)]
+    pub jitter_us: f64,
+    /// Bytes sent on this connection during the collection period
+    #[serde(default)]
+    pub bytes_sent: u64,
+    /// Bytes read from this connection during the collection period
+    #[serde(default)]
+    pub bytes_received: u64,
+    /// Bytes per second in both directions
+    #[serde(default)]
+    pub bytes_per_second: f64,
+    /// Retransmission timeouts on this connection
+    #[serde(default)]
+    pub timeouts: u64,
+    /// Destination address:port behind NAT, from conntrack (with
+    /// --conntrack, if the destination was translated)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nat_destination: Option<String>,
+    /// Congestion control state from tcp:tcp_probe (tracepoint mode only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub congestion: Option<CongestionMetrics>,
+    /// Digest of the connection's latencies, so merged reports keep
+    /// accurate percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+/// Congestion control state of a connection during a collection period
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CongestionMetrics {
+    /// tcp:tcp_probe records seen
+    pub samples: u64,
+    /// Congestion window at the last record (segments)
+    pub snd_cwnd: u32,
+    /// Smallest congestion window since the connection was first seen (segments)
+    pub min_snd_cwnd: u32,
+    /// Slow start threshold at the last record (None while in initial slow start)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssthresh: Option<u32>,
+    /// Smoothed RTT at the last record in microseconds
+    pub srtt_us: u32,
+    /// Average smoothed RTT over the period in microseconds
+    pub avg_srtt_us: f64,
+    /// Largest smoothed RTT since the connection was first seen in microseconds
+    pub max_srtt_us: u32,
+    /// Times the congestion window fell to half or less during the period
+    pub cwnd_collapses: u64,
+}
+
+/// Order of the worst connections (see
+/// [`MetricsCollector::top_connections`](crate::collector::MetricsCollector::top_connections))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionSort {
+    /// Highest p99 latency first
+    #[default]
+    P99,
+    /// Highest average latency first
+    Avg,
+    /// Most events first
+    Events,
+    /// Most retransmission timeouts first
+    Retransmits,
+}
+
+impl std::str::FromStr for ConnectionSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p99" => Ok(Self::P99),
+            "avg" => Ok(Self::Avg),
+            "events" => Ok(Self::Events),
+            "retransmits" => Ok(Self::Retransmits),
+            _ => anyhow::bail!("Unknown sort order {:?} (expected p99, avg, events or retransmits)", s),
+        }
+    }
 }
 
+impl std::fmt::Display for ConnectionSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::P99 => "p99",
+            Self::Avg => "avg",
+            Self::Events => "events",
+            Self::Retransmits => "retransmits",
+        })
+    }
+}
+
+/// One of the worst connections so far
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopConnection {
+    /// Connection key, as in the report's `connections`
+    pub connection: String,
+    /// Number of events on the connection
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Median latency in microseconds (estimated within 1%)
+    pub p50_us: f64,
+    /// 99th percentile latency in microseconds (estimated within 1%)
+    pub p99_us: f64,
+    /// Maximum latency in microseconds
+    pub max_latency_us: f64,
+    /// Retransmission timeouts
+    pub timeouts: u64,
+}
+
+/// Upper bounds of the latency histogram buckets in microseconds
+pub const HISTOGRAM_BOUNDS_US: [&str; 6] = ["1000", "5000", "10000", "50000", "100000", "+Inf"];
+
 /// Latency histogram buckets
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct LatencyHistogram {
@@ -99,22 +973,86 @@ pub struct LatencyHistogram {
 }
 
 impl LatencyHistogram {
+    /// Histogram of a set of samples
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Latencies in microseconds
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let mut histogram = Self::default();
+        for &sample in samples {
+            histogram.add_sample(sample);
+        }
+        histogram
+    }
+
     /// Add a sample to the appropriate bucket
     ///
     /// # Arguments
     ///
     /// * `latency_us` - Latency in microseconds
     pub fn add_sample(&mut self, latency_us: f64) {
-        match latency_us {
-            l if l < 1000.0 => self.bucket_0_1ms += 1,
-            l if l < 5000.0 => self.bucket_1_5ms += 1,
-            l if l < 10000.0 => self.bucket_5_10ms += 1,
-            l if l < 50000.0 => self.bucket_10_50ms += 1,
-            l if l < 100000.0 => self.bucket_50_100ms += 1,
+        match Self::bucket_index(latency_us) {
+            0 => self.bucket_0_1ms += 1,
+            1 => self.bucket_1_5ms += 1,
+            2 => self.bucket_5_10ms += 1,
+            3 => self.bucket_10_50ms += 1,
+            4 => self.bucket_50_100ms += 1,
             _ => self.bucket_100ms_plus += 1,
         }
     }
 
+    /// Index of the bucket a sample falls in, lowest bucket first
+    ///
+    /// # Arguments
+    ///
+    /// * `latency_us` - Latency in microseconds
+    pub fn bucket_index(latency_us: f64) -> usize {
+        match latency_us {
+            l if l < 1000.0 => 0,
+            l if l < 5000.0 => 1,
+            l if l < 10000.0 => 2,
+            l if l < 50000.0 => 3,
+            l if l < 100000.0 => 4,
+            _ => 5,
+        }
+    }
+
+    /// Histogram of bucket counts, lowest bucket first
+    pub fn from_counts(counts: [u64; 6]) -> Self {
+        let [bucket_0_1ms, bucket_1_5ms, bucket_5_10ms, bucket_10_50ms, bucket_50_100ms, bucket_100ms_plus] = counts;
+        Self {
+            bucket_0_1ms,
+            bucket_1_5ms,
+            bucket_5_10ms,
+            bucket_10_50ms,
+            bucket_50_100ms,
+            bucket_100ms_plus,
+        }
+    }
+
+    /// Bucket counts, lowest bucket first (see HISTOGRAM_BOUNDS_US)
+    pub fn counts(&self) -> [u64; 6] {
+        [
+            self.bucket_0_1ms,
+            self.bucket_1_5ms,
+            self.bucket_5_10ms,
+            self.bucket_10_50ms,
+            self.bucket_50_100ms,
+            self.bucket_100ms_plus,
+        ]
+    }
+
+    /// Add the counts of another histogram
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        self.bucket_0_1ms += other.bucket_0_1ms;
+        self.bucket_1_5ms += other.bucket_1_5ms;
+        self.bucket_5_10ms += other.bucket_5_10ms;
+        self.bucket_10_50ms += other.bucket_10_50ms;
+        self.bucket_50_100ms += other.bucket_50_100ms;
+        self.bucket_100ms_plus += other.bucket_100ms_plus;
+    }
+
     /// Get total count across all buckets
     pub fn total_count(&self) -> u64 {
         self.bucket_0_1ms
@@ -152,6 +1090,403 @@ pub struct EventTypeBreakdown {
     pub tcp_recvmsg: u64,
     /// Count of tcp_cleanup_rbuf events
     pub tcp_cleanup_rbuf: u64,
+    /// Count of tcp:tcp_probe RTT events (tracepoint attach mode)
+    #[serde(default)]
+    pub tcp_probe: u64,
+    /// Count of QUIC flight events (--quic)
+    #[serde(default)]
+    pub quic: u64,
+}
+
+impl EventTypeBreakdown {
+    /// Names of the event types, in the order of [`counts`](Self::counts)
+    pub const NAMES: [&'static str; 5] = ["tcp_sendmsg", "tcp_recvmsg", "tcp_cleanup_rbuf", "tcp_probe", "quic"];
+
+    /// Index of an event type in [`counts`](Self::counts), if it is counted
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - EVENT_TYPE_* constant
+    pub fn index(event_type: u8) -> Option<usize> {
+        use probe_common::constants::*;
+
+        match event_type {
+            EVENT_TYPE_SEND => Some(0),
+            EVENT_TYPE_RECV => Some(1),
+            EVENT_TYPE_CLEANUP => Some(2),
+            EVENT_TYPE_TCP_PROBE => Some(3),
+            EVENT_TYPE_QUIC => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Breakdown of counts in the order of [`NAMES`](Self::NAMES)
+    pub fn from_counts(counts: [u64; 5]) -> Self {
+        let [tcp_sendmsg, tcp_recvmsg, tcp_cleanup_rbuf, tcp_probe, quic] = counts;
+        Self {
+            tcp_sendmsg,
+            tcp_recvmsg,
+            tcp_cleanup_rbuf,
+            tcp_probe,
+            quic,
+        }
+    }
+
+    /// Counts in the order of [`NAMES`](Self::NAMES)
+    pub fn counts(&self) -> [u64; 5] {
+        [self.tcp_sendmsg, self.tcp_recvmsg, self.tcp_cleanup_rbuf, self.tcp_probe, self.quic]
+    }
+}
+
+/// DNS query to response latency
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DnsLatencyStats {
+    /// Queries that received a response
+    pub queries: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles
+    pub percentiles: Percentiles,
+    /// Digest of the query latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+/// Receive event deduplication (see crate::dedup)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DedupStats {
+    /// Policy applied ("recv", "cleanup", "separate", or "both")
+    pub policy: String,
+    /// Events dropped as duplicates of another event
+    pub duplicates: u64,
+    /// Latency of tcp_cleanup_rbuf events, kept out of the overall latency
+    /// (separate policy only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cleanup: Option<CleanupLatencyStats>,
+}
+
+/// Latency of tcp_cleanup_rbuf events
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct CleanupLatencyStats {
+    /// Number of cleanup events
+    pub events: u64,
+    /// Average latency in microseconds
+    pub avg_latency_us: f64,
+    /// Latency percentiles
+    pub percentiles: Percentiles,
+    /// Digest of the cleanup latencies, so merged reports keep accurate
+    /// percentiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<LatencyDigest>,
+}
+
+/// Connection resets and retransmission timeouts
+///
+/// Mesh retries can hide failed requests from the application; these are
+/// the kernel's view of them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ConnectionErrorStats {
+    /// RSTs received from peers
+    pub resets_received: u64,
+    /// RSTs sent to abort connections
+    pub resets_sent: u64,
+    /// Retransmission timeouts (the peer did not acknowledge in time)
+    pub timeouts: u64,
+    /// Resets sent and received per second
+    pub resets_per_second: f64,
+    /// Retransmission timeouts per second
+    pub timeouts_per_second: f64,
+    /// Per service counts, keyed by service name (classified by port)
+    pub services: BTreeMap<String, ServiceErrors>,
+}
+
+/// Resets and timeouts on the connections of one service
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ServiceErrors {
+    /// RSTs received from peers
+    pub resets_received: u64,
+    /// RSTs sent to abort connections
+    pub resets_sent: u64,
+    /// Retransmission timeouts
+    pub timeouts: u64,
+}
+
+impl ServiceErrors {
+    /// Resets in either direction
+    pub fn resets(&self) -> u64 {
+        self.resets_received + self.resets_sent
+    }
+}
+
+/// Latency jitter across connections (see crate::jitter)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct JitterStats {
+    /// Mean of the per-connection jitter, weighted by events, in
+    /// microseconds
+    pub mean_us: f64,
+    /// Highest per-connection jitter in microseconds
+    pub max_us: f64,
+}
+
+/// Factors seen during the latency window of tail events (see crate::tail)
+///
+/// An event can coincide with several factors, so the factor counts can add
+/// up to more than `events`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TailBreakdown {
+    /// Latency above which events count as tail (p99) in microseconds
+    pub threshold_us: f64,
+    /// Events above the threshold
+    pub events: u64,
+    /// Tail events during which a packet of the connection was dropped
+    pub packet_drop: u64,
+    /// Tail events during which the process was switched out
+    pub context_switch: u64,
+    /// Tail events without any factor seen
+    pub unexplained: u64,
+}
+
+/// Microbursts: runs of short buckets with far more events than the median
+/// bucket (see crate::burst)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct BurstStats {
+    /// Bucket width in milliseconds
+    pub resolution_ms: u64,
+    /// Multiple of the median rate that makes a burst
+    pub factor: f64,
+    /// Median event rate in events per second
+    pub median_rate: f64,
+    /// Rate above which a bucket is in a burst, in events per second
+    pub threshold_rate: f64,
+    /// Number of bursts
+    pub bursts: u64,
+    /// Time spent in bursts in milliseconds
+    pub total_duration_ms: u64,
+    /// Longest burst in milliseconds
+    pub max_duration_ms: u64,
+    /// Highest rate within a burst in events per second
+    pub peak_rate: f64,
+    /// Events in bursts
+    pub events: u64,
+}
+
+/// Default width of an event rate bucket in milliseconds
+pub const DEFAULT_RATE_RESOLUTION_MS: u64 = 1000;
+
+/// Time buckets kept before later events are left out of the event rate
+/// and heatmap (a day at the default resolution)
+pub const MAX_RATE_BUCKETS: usize = 86_400;
+
+/// Event rate over time
+///
+/// Events are bucketed by their kernel timestamp, so dips in the rate
+/// (e.g. while a proxy hot-restarts) show up instead of being averaged
+/// over the whole run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventRateSeries {
+    /// Bucket width in milliseconds
+    pub resolution_ms: u64,
+    /// Wall-clock time the first event was received (RFC 3339, empty if none)
+    pub start: String,
+    /// Events in each bucket
+    pub events: Vec<u64>,
+    /// Kernel timestamp of the first event (nanoseconds), for matching
+    /// buckets to recorded events
+    #[serde(default)]
+    pub start_ns: u64,
+}
+
+impl Default for EventRateSeries {
+    fn default() -> Self {
+        Self::new(DEFAULT_RATE_RESOLUTION_MS)
+    }
+}
+
+impl EventRateSeries {
+    /// Create an empty series
+    ///
+    /// # Arguments
+    ///
+    /// * `resolution_ms` - Bucket width in milliseconds (at least 1)
+    pub fn new(resolution_ms: u64) -> Self {
+        Self {
+            resolution_ms: resolution_ms.max(1),
+            start: String::new(),
+            events: Vec::new(),
+            start_ns: 0,
+        }
+    }
+
+    /// Count an event
+    ///
+    /// Events from other CPUs can arrive slightly out of order; any earlier
+    /// than the first event are counted in the first bucket.
+    pub fn add(&mut self, timestamp_ns: u64) {
+        if self.events.is_empty() {
+            self.start = chrono::Utc::now().to_rfc3339();
+            self.start_ns = timestamp_ns;
+        }
+
+        let Some(bucket) = time_bucket(self.start_ns, timestamp_ns, self.resolution_ms) else {
+            return;
+        };
+        if bucket >= self.events.len() {
+            self.events.resize(bucket + 1, 0);
+        }
+        self.events[bucket] += 1;
+    }
+
+    /// Events per second in each bucket
+    pub fn rates(&self) -> Vec<f64> {
+        let seconds = self.resolution_ms as f64 / 1000.0;
+        self.events.iter().map(|&count| count as f64 / seconds).collect()
+    }
+
+    /// Lowest and highest rate in events per second
+    ///
+    /// The last bucket is still filling, so it is left out unless it is
+    /// the only one.
+    ///
+    /// # Returns
+    ///
+    /// (min, max), or None if no events were seen
+    pub fn rate_range(&self) -> Option<(f64, f64)> {
+        let rates = self.rates();
+        let complete = match rates.len() {
+            0 | 1 => &rates[..],
+            n => &rates[..n - 1],
+        };
+
+        complete.iter().fold(None, |range, &rate| match range {
+            None => Some((rate, rate)),
+            Some((min, max)) => Some((f64::min(min, rate), f64::max(max, rate))),
+        })
+    }
+}
+
+/// Index of the time bucket holding a timestamp
+///
+/// # Returns
+///
+/// The bucket, or None past MAX_RATE_BUCKETS
+fn time_bucket(start_ns: u64, timestamp_ns: u64, resolution_ms: u64) -> Option<usize> {
+    let bucket = (timestamp_ns.saturating_sub(start_ns) / (resolution_ms * 1_000_000)) as usize;
+    (bucket < MAX_RATE_BUCKETS).then_some(bucket)
+}
+
+/// Latency percentiles of one snapshot interval
+///
+/// Unlike the report's percentiles, which cover the whole collection
+/// period, these cover only the events since the previous snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IntervalPercentiles {
+    /// End of the interval, in seconds since the collection period began
+    pub end_secs: u64,
+    /// Events in the interval
+    pub events: u64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+/// Latency distribution over time
+///
+/// One histogram per time bucket (same buckets as the event rate), for
+/// latency heatmaps: time on X, histogram bucket on Y, count as value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LatencyHeatmap {
+    /// Time bucket width in milliseconds
+    pub resolution_ms: u64,
+    /// Wall-clock time the first event was received (RFC 3339, empty if none)
+    pub start: String,
+    /// Latency histogram of each time bucket
+    pub intervals: Vec<LatencyHistogram>,
+    /// Kernel timestamp of the first event (nanoseconds), for matching
+    /// buckets to recorded events
+    #[serde(default)]
+    pub start_ns: u64,
+}
+
+impl Default for LatencyHeatmap {
+    fn default() -> Self {
+        Self::new(DEFAULT_RATE_RESOLUTION_MS)
+    }
+}
+
+impl LatencyHeatmap {
+    /// Create an empty heatmap
+    ///
+    /// # Arguments
+    ///
+    /// * `resolution_ms` - Time bucket width in milliseconds (at least 1)
+    pub fn new(resolution_ms: u64) -> Self {
+        Self {
+            resolution_ms: resolution_ms.max(1),
+            start: String::new(),
+            intervals: Vec::new(),
+            start_ns: 0,
+        }
+    }
+
+    /// Add a latency sample at a kernel timestamp
+    pub fn add_sample(&mut self, timestamp_ns: u64, latency_us: f64) {
+        if self.intervals.is_empty() {
+            self.start = chrono::Utc::now().to_rfc3339();
+            self.start_ns = timestamp_ns;
+        }
+
+        let Some(bucket) = time_bucket(self.start_ns, timestamp_ns, self.resolution_ms) else {
+            return;
+        };
+        if bucket >= self.intervals.len() {
+            self.intervals.resize(bucket + 1, LatencyHistogram::default());
+        }
+        self.intervals[bucket].add_sample(latency_us);
+    }
+}
+
+/// Record of a coordinated omission correction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OmissionCorrection {
+    /// Expected interval between requests in microseconds
+    pub expected_interval_us: f64,
+    /// Synthetic samples added to the distribution
+    pub synthetic_samples: u64,
+}
+
+/// Bytes transferred across all connections
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ThroughputStats {
+    /// Bytes sent (tcp_sendmsg)
+    pub bytes_sent: u64,
+    /// Bytes read by applications (tcp_cleanup_rbuf)
+    pub bytes_received: u64,
+    /// Bytes sent per second
+    pub sent_bytes_per_second: f64,
+    /// Bytes received per second
+    pub received_bytes_per_second: f64,
+}
+
+/// Accept queues of the listeners on one port during a collection period
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ListenerMetrics {
+    /// Listening sockets on the port (one per network namespace)
+    pub listeners: u64,
+    /// Connection requests (SYNs) received
+    pub requests: u64,
+    /// Requests dropped because an accept queue was full
+    pub overflows: u64,
+    /// Overflows per second
+    pub overflows_per_second: f64,
+    /// SYNs received while a SYN queue was full (dropped unless SYN
+    /// cookies are enabled)
+    pub syn_queue_full: u64,
+    /// Longest accept queue seen since the probe started
+    pub max_backlog: u32,
+    /// Accept queue limit (the listen() backlog, capped by
+    /// net.core.somaxconn)
+    pub backlog_limit: u32,
 }
 
 /// Packet drop statistics
@@ -160,11 +1495,11 @@ pub struct PacketDropStats {
     /// Total packet drops
     pub total_drops: u64,
     /// Drops by location
-    pub drops_by_location: HashMap<String, u64>,
+    pub drops_by_location: BTreeMap<String, u64>,
     /// Drops by protocol
-    pub drops_by_protocol: HashMap<String, u64>,
+    pub drops_by_protocol: BTreeMap<String, u64>,
     /// Per-connection drop counts
-    pub connections: HashMap<String, u64>,
+    pub connections: BTreeMap<String, u64>,
 }
 
 /// Connection state statistics
@@ -179,7 +1514,7 @@ pub struct ConnectionStateStats {
     /// Average connection duration in seconds
     pub avg_duration_seconds: f64,
     /// Connection states breakdown
-    pub states_breakdown: HashMap<String, u64>,
+    pub states_breakdown: BTreeMap<String, u64>,
 }
 
 /// Context switch statistics
@@ -210,6 +1545,22 @@ pub struct XdpPacketStats {
     pub packets_per_second: f64,
 }
 
+/// Latency validity bounds and the events rejected by each
+///
+/// Latencies outside the bounds are taken for measurement errors and
+/// dropped in the kernel, so they appear in no other statistic.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct LatencyBounds {
+    /// Smallest latency accepted, in nanoseconds
+    pub min_latency_ns: u64,
+    /// Largest latency accepted, in nanoseconds
+    pub max_latency_ns: u64,
+    /// Latencies rejected below the minimum since the probe was loaded
+    pub below_min: u64,
+    /// Latencies rejected above the maximum since the probe was loaded
+    pub above_max: u64,
+}
+
 /// Calculate percentiles from a sorted vector of samples
 ///
 /// # Arguments
@@ -224,28 +1575,40 @@ pub fn calculate_percentiles(mut samples: Vec<f64>) -> Percentiles {
         return Percentiles::default();
     }
 
-    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-    let len = samples.len();
-
-    let percentile = |p: usize| {
-        let index = std::cmp::min((len * p / 100).saturating_sub(1), len - 1);
-        samples[index]
+    samples.sort_by(f64::total_cmp);
+    let len = samples.len() as u64;
+    let percentile = |numerator: u64, denominator: u64| {
+        samples[(percentile_rank(len, numerator, denominator) - 1) as usize]
     };
 
     Percentiles {
-        p50: percentile(50),
-        p75: percentile(75),
-        p90: percentile(90),
-        p95: percentile(95),
-        p99: percentile(99),
-        p999: {
-            // Calculate 99.9th percentile (999 out of 1000)
-            let index = std::cmp::min(((len * 999) / 1000).saturating_sub(1), len - 1);
-            samples[index]
-        },
+        p50: percentile(50, 100),
+        p75: percentile(75, 100),
+        p90: percentile(90, 100),
+        p95: percentile(95, 100),
+        p99: percentile(99, 100),
+        p999: percentile(999, 1000),
     }
 }
 
+/// Nearest rank of a percentile
+///
+/// The smallest rank at or below which lie at least `numerator /
+/// denominator` of the samples (e.g. 99 / 100 for p99).
+///
+/// # Arguments
+///
+/// * `len` - Number of samples (at least 1)
+/// * `numerator` - Numerator of the fraction
+/// * `denominator` - Denominator of the fraction
+///
+/// # Returns
+///
+/// Rank from 1 (smallest sample) to `len`
+pub fn percentile_rank(len: u64, numerator: u64, denominator: u64) -> u64 {
+    (len * numerator).div_ceil(denominator).clamp(1, len.max(1))
+}
+
 /// Calculate standard deviation
 ///
 /// # Arguments
@@ -269,6 +1632,19 @@ pub fn calculate_std_dev(samples: &[f64], mean: f64) -> f64 {
     variance.sqrt()
 }
 
+/// Rate of a count over a collection period
+///
+/// # Returns
+///
+/// Count per second, or 0 for an empty period
+pub fn per_second(count: u64, elapsed_secs: u64) -> f64 {
+    if elapsed_secs > 0 {
+        count as f64 / elapsed_secs as f64
+    } else {
+        0.0
+    }
+}
+
 /// Convert ConnectionKey to string representation
 ///
 /// # Arguments
@@ -279,16 +1655,147 @@ pub fn calculate_std_dev(samples: &[f64], mean: f64) -> f64 {
 ///
 /// String in format "saddr:sport -> daddr:dport"
 pub fn connection_key_to_string(key: &ConnectionKey) -> String {
-    use std::net::Ipv4Addr;
+    ConnectionId::tuple(key).to_string()
+}
 
-    let saddr = Ipv4Addr::from(u32::from_be(key.saddr));
-    let daddr = Ipv4Addr::from(u32::from_be(key.daddr));
+/// Whether a port is in the dynamic (ephemeral) range
+pub fn is_ephemeral_port(port: u16) -> bool {
+    use kernel::constants::{PORT_DYNAMIC_END, PORT_DYNAMIC_START};
 
-    format!(
-        "{}:{} -> {}:{}",
-        saddr,
-        u16::from_be(key.sport),
-        daddr,
-        u16::from_be(key.dport)
-    )
+    (PORT_DYNAMIC_START..=PORT_DYNAMIC_END).contains(&port)
+}
+
+/// Convert ConnectionKey to string representation, with ephemeral ports
+/// replaced by "*"
+///
+/// Connections between a client and a server differ only in the client's
+/// ephemeral port, so their normalized keys are the same.
+///
+/// # Arguments
+///
+/// * `key` - Connection key from eBPF
+///
+/// # Returns
+///
+/// String in format "saddr:sport -> daddr:dport", e.g.
+/// "10.0.0.1:* -> 10.0.0.2:8080"
+pub fn normalized_key_to_string(key: &ConnectionKey) -> String {
+    ConnectionId::normalized(key).to_string()
+}
+
+/// Identity of the connection an event belongs to
+///
+/// 4-tuples are reused once ports are recycled, so connections with a
+/// socket cookie are told apart by it ("<tuple> #<cookie>"); the others
+/// fall back to the bare 4-tuple.
+pub fn connection_id(event: &LatencyEvent) -> String {
+    ConnectionId::of(event).to_string()
+}
+
+/// Serde mirror of [`ConnectionKey`], which the no_std common crate cannot
+/// derive itself
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ConnectionKey")]
+struct ConnectionKeyDef {
+    saddr: u32,
+    daddr: u32,
+    sport: u16,
+    dport: u16,
+}
+
+/// Key of a connection in the per-connection breakdown
+///
+/// Events are attributed to their connection on the raw key, without
+/// formatting anything; the report key ("<tuple>", "<tuple> #<cookie>",
+/// or with "*" ports when normalized) is only rendered, through `Display`,
+/// when metrics are exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ConnectionId {
+    /// 4-tuple, in network byte order; normalized ephemeral ports are 0
+    #[serde(with = "ConnectionKeyDef")]
+    pub key: ConnectionKey,
+    /// Socket cookie (0 if the connection is identified by its 4-tuple)
+    pub cookie: u64,
+    /// Whether ephemeral ports were dropped (see [`normalized_key_to_string`])
+    pub normalized: bool,
+}
+
+impl ConnectionId {
+    /// Connection of an event, told apart by its socket cookie
+    pub fn of(event: &LatencyEvent) -> Self {
+        Self {
+            key: event.key,
+            cookie: event.cookie,
+            normalized: false,
+        }
+    }
+
+    /// Connection identified by its 4-tuple alone
+    pub fn tuple(key: &ConnectionKey) -> Self {
+        Self {
+            key: *key,
+            cookie: 0,
+            normalized: false,
+        }
+    }
+
+    /// Connections of a 4-tuple, whatever their ephemeral ports
+    pub fn normalized(key: &ConnectionKey) -> Self {
+        let port = |port: u16| if is_ephemeral_port(u16::from_be(port)) { 0 } else { port };
+        Self {
+            key: ConnectionKey {
+                sport: port(key.sport),
+                dport: port(key.dport),
+                ..*key
+            },
+            cookie: 0,
+            normalized: true,
+        }
+    }
+
+    /// The same connection, identified by its 4-tuple alone
+    pub fn without_cookie(self) -> Self {
+        Self { cookie: 0, ..self }
+    }
+
+    /// Source endpoint, e.g. "10.0.0.1:40000"
+    pub fn source(&self) -> String {
+        Endpoint(self.key.saddr, self.key.sport, self.normalized).to_string()
+    }
+
+    /// Destination endpoint, e.g. "10.0.0.2:8080"
+    pub fn destination(&self) -> String {
+        Endpoint(self.key.daddr, self.key.dport, self.normalized).to_string()
+    }
+}
+
+impl std::fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} -> {}",
+            Endpoint(self.key.saddr, self.key.sport, self.normalized),
+            Endpoint(self.key.daddr, self.key.dport, self.normalized)
+        )?;
+        if self.cookie != 0 {
+            write!(f, " #{}", self.cookie)?;
+        }
+        Ok(())
+    }
+}
+
+/// Address and port in network byte order, and whether a zero port is a
+/// normalized one
+#[derive(Clone, Copy)]
+struct Endpoint(u32, u16, bool);
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Endpoint(addr, port, normalized) = *self;
+        let addr = std::net::Ipv4Addr::from(u32::from_be(addr));
+        match u16::from_be(port) {
+            0 if normalized => write!(f, "{}:*", addr),
+            port => write!(f, "{}:{}", addr, port),
+        }
+    }
 }
@@ -0,0 +1,192 @@
+//! Run-to-run variance of repeated benchmarks
+//!
+//! One run of a benchmark is one sample: the same setup run again gives a
+//! somewhat different p99. A difference between two configurations only
+//! supports a conclusion if it is larger than that noise, so benchmarks
+//! are repeated and their spread reported with the result.
+//!
+//! `latency-probe compare --repeat run1.json run2.json run3.json` takes the
+//! reports of repeats of one setup and gives, for each percentile (overall
+//! and per service seen in every repeat), the mean, the sample standard
+//! deviation and the coefficient of variation (CV = standard deviation /
+//! mean). Metrics whose CV exceeds `--max-cv` (5% by default, a common
+//! bound in benchmarking practice) are flagged as too noisy to compare:
+//! run more repeats, run longer, or quiet the nodes first. Tail
+//! percentiles are naturally noisier than the median.
+
+use crate::{
+    compress,
+    types::{LatencyMetrics, Percentiles},
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Coefficient of variation above which a metric is too noisy, in percent
+pub const DEFAULT_MAX_CV_PCT: f64 = 5.0;
+
+/// Spread of one metric across repeats
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MetricVariance {
+    /// Metric, e.g. "p99" or "services.redis.p99"
+    pub metric: String,
+    /// Value of each repeat, in microseconds
+    pub values: Vec<f64>,
+    /// Mean of the values
+    pub mean: f64,
+    /// Sample standard deviation of the values
+    pub stddev: f64,
+    /// Coefficient of variation in percent (None if the mean is 0)
+    pub cv_pct: Option<f64>,
+    /// Whether the CV exceeds the bound
+    pub noisy: bool,
+}
+
+/// Result of `compare --repeat`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VarianceReport {
+    /// Repeats compared (run IDs, or file names of reports without one)
+    pub runs: Vec<String>,
+    /// Coefficient of variation above which a metric is noisy, in percent
+    pub max_cv_pct: f64,
+    /// Spread of each metric
+    pub metrics: Vec<MetricVariance>,
+    /// Metrics too noisy to support conclusions
+    pub noisy: Vec<String>,
+}
+
+/// Read a JSON report (.gz and .zst files are decompressed)
+pub fn read_report(path: &Path) -> Result<LatencyMetrics> {
+    let reader = compress::open(path)?;
+    serde_json::from_reader(reader).with_context(|| format!("Invalid report: {:?}", path))
+}
+
+/// Named percentiles of a report section
+fn percentiles(prefix: &str, percentiles: &Percentiles) -> [(String, f64); 6] {
+    [
+        ("p50", percentiles.p50),
+        ("p75", percentiles.p75),
+        ("p90", percentiles.p90),
+        ("p95", percentiles.p95),
+        ("p99", percentiles.p99),
+        ("p999", percentiles.p999),
+    ]
+    .map(|(name, value)| (format!("{}{}", prefix, name), value))
+}
+
+/// Spread of a metric's values
+fn variance(metric: String, values: Vec<f64>, max_cv_pct: f64) -> MetricVariance {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let stddev = if values.len() > 1 {
+        (values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    let cv_pct = (mean != 0.0).then(|| stddev * 100.0 / mean.abs());
+    MetricVariance {
+        metric,
+        values,
+        mean,
+        stddev,
+        noisy: cv_pct.is_some_and(|cv| cv > max_cv_pct),
+        cv_pct,
+    }
+}
+
+/// Compare the reports of repeats of one benchmark
+///
+/// # Arguments
+///
+/// * `reports` - Report of each repeat, with the name it is listed under
+/// * `max_cv_pct` - Coefficient of variation above which a metric is noisy
+pub fn compare(reports: &[(String, LatencyMetrics)], max_cv_pct: f64) -> VarianceReport {
+    // Metrics in the order they are reported, with each repeat's value
+    let mut metrics: Vec<(String, Vec<f64>)> = Vec::new();
+    let mut add = |values: Vec<[(String, f64); 6]>| {
+        let Some(first) = values.first() else {
+            return;
+        };
+        for (i, (name, _)) in first.iter().enumerate() {
+            metrics.push((name.clone(), values.iter().map(|repeat| repeat[i].1).collect()));
+        }
+    };
+    add(reports.iter().map(|(_, metrics)| percentiles("", &metrics.percentiles)).collect());
+
+    // Services seen in every repeat
+    let services: BTreeMap<&str, usize> = reports
+        .iter()
+        .flat_map(|(_, metrics)| metrics.services.keys())
+        .fold(BTreeMap::new(), |mut counts, service| {
+            *counts.entry(service.as_str()).or_default() += 1;
+            counts
+        });
+    for (service, _) in services.into_iter().filter(|&(_, count)| count == reports.len()) {
+        add(reports
+            .iter()
+            .map(|(_, metrics)| percentiles(&format!("services.{}.", service), &metrics.services[service].percentiles))
+            .collect());
+    }
+
+    let metrics: Vec<MetricVariance> = metrics
+        .into_iter()
+        .map(|(metric, values)| variance(metric, values, max_cv_pct))
+        .collect();
+    VarianceReport {
+        runs: reports.iter().map(|(name, _)| name.clone()).collect(),
+        max_cv_pct,
+        noisy: metrics.iter().filter(|metric| metric.noisy).map(|metric| metric.metric.clone()).collect(),
+        metrics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::GroupMetrics;
+
+    #[test]
+    fn test_repeat_variance() {
+        let report = |p50, p99, redis: Option<f64>| LatencyMetrics {
+            percentiles: Percentiles {
+                p50,
+                p99,
+                ..Default::default()
+            },
+            services: redis
+                .map(|p99| {
+                    let service = GroupMetrics {
+                        percentiles: Percentiles {
+                            p99,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    };
+                    BTreeMap::from([("redis".to_string(), service)])
+                })
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let reports = vec![
+            ("run1".to_string(), report(100.0, 900.0, Some(50.0))),
+            ("run2".to_string(), report(102.0, 1000.0, Some(50.0))),
+            ("run3".to_string(), report(98.0, 1100.0, None)),
+        ];
+        let result = compare(&reports, DEFAULT_MAX_CV_PCT);
+        assert_eq!(result.runs, ["run1", "run2", "run3"]);
+
+        // A stable median, a noisy tail
+        let p50 = result.metrics.iter().find(|metric| metric.metric == "p50").unwrap();
+        assert_eq!((p50.mean, p50.stddev, p50.cv_pct), (100.0, 2.0, Some(2.0)));
+        assert!(!p50.noisy);
+        let p99 = result.metrics.iter().find(|metric| metric.metric == "p99").unwrap();
+        assert_eq!(p99.cv_pct, Some(10.0));
+        assert_eq!(result.noisy, ["p99"]);
+
+        // No CV without a mean; services missing from a repeat are left out
+        assert_eq!(result.metrics.iter().find(|metric| metric.metric == "p75").unwrap().cv_pct, None);
+        assert!(result.metrics.iter().all(|metric| !metric.metric.starts_with("services.")));
+        let result = compare(&reports[..2], DEFAULT_MAX_CV_PCT);
+        assert_eq!(result.metrics.iter().filter(|metric| metric.metric.starts_with("services.redis.")).count(), 6);
+    }
+}
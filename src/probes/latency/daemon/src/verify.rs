@@ -0,0 +1,317 @@
+//! Load-only verification of the eBPF object
+//!
+//! Loads every program through the kernel verifier and checks that attach
+//! points and maps are usable, without attaching anything. Intended for CI
+//! and pre-deployment checks, where verifier regressions should fail fast.
+
+use crate::{
+    loader::{ProbeLoader, KPROBES, TRACEPOINTS},
+    objects::ObjectKind,
+    tracefs,
+};
+use aya::{
+    maps::{Map, MapData},
+    programs::Program,
+};
+use log::{error, info, warn};
+
+/// Where a program attaches in the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachPoint {
+    /// Kernel function, with fallbacks in order of preference
+    KProbe(&'static [&'static str]),
+    /// Tracepoint category and name
+    TracePoint(&'static str, &'static str),
+    /// Network interface chosen at runtime
+    Xdp,
+}
+
+/// Program expected in the eBPF object
+#[derive(Clone)]
+struct ExpectedProgram {
+    name: &'static str,
+    attach: AttachPoint,
+    required: bool,
+}
+
+/// Programs of the latency object besides those in [`KPROBES`] and
+/// [`TRACEPOINTS`], mirroring `ProbeLoader::attach_*`
+const EXPECTED_PROGRAMS: &[ExpectedProgram] = &[
+    ExpectedProgram { name: "xdp_packet_monitor", attach: AttachPoint::Xdp, required: false },
+];
+
+/// Verification result for one program
+#[derive(Debug, Clone)]
+pub struct ProgramReport {
+    /// Program name in the object
+    pub name: String,
+    /// Verifier error, if loading failed
+    pub error: Option<String>,
+    /// Instructions processed by the verifier
+    pub verified_instructions: u32,
+    /// JIT-compiled size in bytes
+    pub jitted_bytes: u32,
+}
+
+/// Size of one map as created by the kernel
+#[derive(Debug, Clone)]
+pub struct MapReport {
+    /// Map name in the object
+    pub name: String,
+    /// Map kind, e.g. `HashMap`
+    pub kind: &'static str,
+    /// Maximum number of entries
+    pub max_entries: u32,
+    /// Key size in bytes
+    pub key_size: u32,
+    /// Value size in bytes
+    pub value_size: u32,
+}
+
+impl MapReport {
+    /// Upper bound on the memory used by entries (excludes kernel overhead)
+    pub fn entry_bytes(&self) -> u64 {
+        self.max_entries as u64 * (self.key_size + self.value_size) as u64
+    }
+}
+
+/// Outcome of a dry run
+#[derive(Debug, Default)]
+pub struct VerificationReport {
+    /// Programs loaded through the verifier
+    pub programs: Vec<ProgramReport>,
+    /// Maps created from the object
+    pub maps: Vec<MapReport>,
+    /// Problems that would prevent a live run
+    pub errors: Vec<String>,
+    /// Problems that disable optional features
+    pub warnings: Vec<String>,
+}
+
+impl VerificationReport {
+    /// True if a live run would start successfully
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Log the report
+    pub fn print(&self) {
+        info!("Programs:");
+        for program in &self.programs {
+            match program.error {
+                None => info!(
+                    "  ✓ {:<22} {:>7} insns verified, {:>7} bytes jitted",
+                    program.name, program.verified_instructions, program.jitted_bytes
+                ),
+                Some(ref e) => error!("  ✗ {:<22} {}", program.name, e),
+            }
+        }
+
+        info!("Maps:");
+        for map in &self.maps {
+            info!(
+                "  {:<22} {:<15} {:>7} entries × {:>3}+{:<3} bytes (~{} KiB)",
+                map.name,
+                map.kind,
+                map.max_entries,
+                map.key_size,
+                map.value_size,
+                map.entry_bytes().div_ceil(1024)
+            );
+        }
+
+        for warning in &self.warnings {
+            warn!("  ⚠ {}", warning);
+        }
+        for e in &self.errors {
+            error!("  ✗ {}", e);
+        }
+    }
+}
+
+/// Load every program and inspect maps without attaching anything
+///
+/// # Arguments
+///
+/// * `loader` - Freshly loaded probe (programs not yet loaded); every
+///   object is verified, and program and map names are qualified on collision
+pub fn verify(loader: &mut ProbeLoader) -> VerificationReport {
+    let mut report = VerificationReport::default();
+
+    for (object, ebpf) in loader.objects_mut() {
+        for handle in &object.programs {
+            let Some(program) = ebpf.program_mut(&handle.name) else {
+                continue;
+            };
+            let result = load_program(program);
+            if result.is_err() {
+                report.errors.push(format!("{} rejected by the verifier", handle.qualified));
+            }
+
+            let info = result.as_ref().ok().and_then(|_| program_info(program));
+            report.programs.push(ProgramReport {
+                name: handle.qualified.clone(),
+                error: result.err(),
+                verified_instructions: info.map_or(0, |(insns, _)| insns),
+                jitted_bytes: info.map_or(0, |(_, jitted)| jitted),
+            });
+        }
+
+        for handle in &object.maps {
+            let Some(map) = ebpf.map(&handle.name) else {
+                continue;
+            };
+            let Some((kind, data)) = map_data(map) else {
+                report
+                    .warnings
+                    .push(format!("map {} has an unrecognized type", handle.qualified));
+                continue;
+            };
+            match data.info() {
+                Ok(map_info) => report.maps.push(MapReport {
+                    name: handle.qualified.clone(),
+                    kind,
+                    max_entries: map_info.max_entries(),
+                    key_size: map_info.key_size(),
+                    value_size: map_info.value_size(),
+                }),
+                Err(e) => report
+                    .errors
+                    .push(format!("map {} could not be inspected: {}", handle.qualified, e)),
+            }
+        }
+    }
+
+    let primary_programs: Vec<String> = loader
+        .object(ObjectKind::Latency)
+        .map(|o| o.programs.iter().map(|p| p.name.clone()).collect())
+        .unwrap_or_default();
+    let kprobes = KPROBES.iter().map(|spec| ExpectedProgram {
+        name: spec.program,
+        attach: AttachPoint::KProbe(spec.symbols),
+        required: spec.required,
+    });
+    // The attach mode is chosen at runtime, so tracepoint-mode programs
+    // are never required here
+    let tracepoints = TRACEPOINTS.iter().map(|spec| ExpectedProgram {
+        name: spec.program,
+        attach: AttachPoint::TracePoint(spec.category, spec.name),
+        required: spec.required && !spec.tracepoint_mode_only,
+    });
+    for expected in kprobes.chain(tracepoints).chain(EXPECTED_PROGRAMS.iter().cloned()) {
+        let present = primary_programs.iter().any(|p| p == expected.name);
+        let problem = if !present {
+            Some(format!("program {} not found in eBPF object", expected.name))
+        } else {
+            check_attach_point(expected.attach).err()
+        };
+
+        if let Some(problem) = problem {
+            if expected.required {
+                report.errors.push(problem);
+            } else {
+                report.warnings.push(format!("{} (optional)", problem));
+            }
+        }
+    }
+
+    report.programs.sort_by(|a, b| a.name.cmp(&b.name));
+    report.maps.sort_by(|a, b| a.name.cmp(&b.name));
+    report
+}
+
+/// Load a program through the verifier
+///
+/// Returns the verifier error (including its log) on failure.
+fn load_program(program: &mut Program) -> Result<(), String> {
+    let result = match program {
+        Program::KProbe(p) => p.load(),
+        Program::TracePoint(p) => p.load(),
+        Program::Xdp(p) => p.load(),
+        _ => return Err("unsupported program type".to_string()),
+    };
+
+    result.map_err(|e| format!("{:#}", anyhow::Error::new(e)))
+}
+
+/// Verified instruction count and jitted size of a loaded program
+fn program_info(program: &Program) -> Option<(u32, u32)> {
+    let info = match program {
+        Program::KProbe(p) => p.info(),
+        Program::TracePoint(p) => p.info(),
+        Program::Xdp(p) => p.info(),
+        _ => return None,
+    }
+    .ok()?;
+
+    Some((info.verified_instruction_count().unwrap_or_default(), info.size_jitted()))
+}
+
+/// Map kind and its underlying data, for the map types the probe uses
+fn map_data(map: &Map) -> Option<(&'static str, &MapData)> {
+    match map {
+        Map::Array(data) => Some(("Array", data)),
+        Map::HashMap(data) => Some(("HashMap", data)),
+        Map::PerCpuHashMap(data) => Some(("PerCpuHashMap", data)),
+        Map::PerCpuArray(data) => Some(("PerCpuArray", data)),
+        Map::LruHashMap(data) => Some(("LruHashMap", data)),
+        Map::PerfEventArray(data) => Some(("PerfEventArray", data)),
+        Map::ProgramArray(data) => Some(("ProgramArray", data)),
+        _ => None,
+    }
+}
+
+/// Check that the kernel provides an attach point
+fn check_attach_point(attach: AttachPoint) -> Result<(), String> {
+    match attach {
+        AttachPoint::KProbe(functions) => {
+            let symbols = std::fs::read_to_string("/proc/kallsyms")
+                .map_err(|e| format!("cannot read /proc/kallsyms: {}", e))?;
+            if functions.iter().any(|f| has_kernel_symbol(&symbols, f)) {
+                Ok(())
+            } else {
+                Err(format!("kernel function {} not found", functions.join(" or ")))
+            }
+        }
+        AttachPoint::TracePoint(category, name) => {
+            if tracefs::has_tracepoint(category, name) {
+                Ok(())
+            } else {
+                Err(format!("tracepoint {}:{} not found", category, name))
+            }
+        }
+        // Interfaces are only known at runtime
+        AttachPoint::Xdp => Ok(()),
+    }
+}
+
+/// Check a `/proc/kallsyms` listing for a function symbol
+pub(crate) fn has_kernel_symbol(kallsyms: &str, function: &str) -> bool {
+    kallsyms.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        let kind = fields.nth(1).unwrap_or("");
+        let name = fields.next().unwrap_or("");
+        name == function && matches!(kind, "T" | "t")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_kernel_symbol() {
+        let kallsyms = "\
+ffffffff81a2b3c0 T tcp_sendmsg
+ffffffff81a2b4d0 t tcp_cleanup_rbuf
+ffffffff82c00000 D tcp_hashinfo
+ffffffffc0001000 t tcp_drop\t[some_module]
+";
+
+        assert!(has_kernel_symbol(kallsyms, "tcp_sendmsg"));
+        assert!(has_kernel_symbol(kallsyms, "tcp_cleanup_rbuf"));
+        assert!(has_kernel_symbol(kallsyms, "tcp_drop"));
+        assert!(!has_kernel_symbol(kallsyms, "tcp_hashinfo"));
+        assert!(!has_kernel_symbol(kallsyms, "tcp_send"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Rolling-window reports
+//!
+//! A long-running probe's reports cover everything since it started (or
+//! since the last rotation), so a regression a day into a week-long run
+//! barely moves the percentiles. In rolling-window mode (`--window`),
+//! reports cover the last minutes instead: every [`WINDOW_PANES`]th of the
+//! window the collector's samples are rotated into a pane, kept as a report
+//! with its digest, and a report is the panes merged with the samples since
+//! the last one (see [`LatencyMetrics::merge_following`]). Percentiles,
+//! overall, per connection and per group, come from the merged digests and
+//! are accurate to 1% (see [`crate::merge`]).
+//!
+//! Panes live in memory only, so rolling windows cannot be combined with
+//! checkpoints.
+//!
+//! The window slides a pane at a time, so a report covers between
+//! `(WINDOW_PANES - 1) / WINDOW_PANES` of the window and the whole window.
+
+use crate::types::LatencyMetrics;
+use std::{collections::VecDeque, time::Duration};
+
+/// Panes a window is divided into
+pub const WINDOW_PANES: u32 = 6;
+
+/// Finished panes of a rolling window
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    window: Duration,
+    panes: VecDeque<LatencyMetrics>,
+}
+
+impl RollingWindow {
+    /// Create an empty window
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Time covered by reports
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            panes: VecDeque::with_capacity(WINDOW_PANES as usize),
+        }
+    }
+
+    /// Time covered by reports
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Time between two panes
+    pub fn pane_length(&self) -> Duration {
+        self.window / WINDOW_PANES
+    }
+
+    /// Add a finished pane, dropping the oldest that left the window
+    ///
+    /// The pane being collected is part of the window too, so the window
+    /// keeps one pane fewer than [`WINDOW_PANES`].
+    pub fn push(&mut self, pane: LatencyMetrics) {
+        if self.panes.len() + 1 >= WINDOW_PANES as usize {
+            self.panes.pop_front();
+        }
+        self.panes.push_back(pane);
+    }
+
+    /// Number of finished panes
+    pub fn len(&self) -> usize {
+        self.panes.len()
+    }
+
+    /// Whether no pane finished yet
+    pub fn is_empty(&self) -> bool {
+        self.panes.is_empty()
+    }
+
+    /// Report of the window
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - Report of the pane being collected
+    pub fn report(&self, current: LatencyMetrics) -> LatencyMetrics {
+        let mut panes = self.panes.iter();
+        let Some(first) = panes.next() else {
+            return current;
+        };
+        let mut report = first.clone();
+        for pane in panes {
+            report.merge_following(pane);
+        }
+        report.merge_following(&current);
+        report
+    }
+}
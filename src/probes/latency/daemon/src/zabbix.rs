@@ -0,0 +1,283 @@
+//! Zabbix sender
+//!
+//! Sends report values to a Zabbix server or proxy with the sender (trapper)
+//! protocol, as `zabbix_sender` would, so nodes can be monitored in Zabbix
+//! without an intermediate script. The host must have trapper items with
+//! these keys:
+//!
+//! | Key | Value |
+//! |-----|-------|
+//! | `latency_probe.events` | Total events |
+//! | `latency_probe.lost_events` | Events lost to full perf buffers |
+//! | `latency_probe.connections` | Connections seen |
+//! | `latency_probe.p50`, `.p90`, `.p99`, `.p999` | Latency percentiles (us) |
+//! | `latency_probe.service.p99[<service>]` | p99 per service (us) |
+
+use crate::{exporter::MetricsExporter, types::LatencyMetrics};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Default Zabbix trapper port
+pub const DEFAULT_PORT: u16 = 10051;
+
+/// Protocol header: "ZBXD" and the protocol flags (no compression)
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+
+/// Largest response accepted from the server
+const MAX_RESPONSE_LEN: u64 = 1 << 20;
+
+/// Connect, read, and write timeout
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One value for a Zabbix item
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ZabbixItem {
+    /// Host name as configured in Zabbix
+    pub host: String,
+    /// Item key
+    pub key: String,
+    /// Value
+    pub value: String,
+    /// Unix time of the value
+    pub clock: i64,
+}
+
+#[derive(Serialize)]
+struct SenderRequest<'a> {
+    request: &'static str,
+    data: &'a [ZabbixItem],
+}
+
+#[derive(Deserialize)]
+struct SenderResponse {
+    response: String,
+    #[serde(default)]
+    info: String,
+}
+
+/// Sends report values to a Zabbix server
+pub struct ZabbixSender {
+    server: String,
+    host: String,
+}
+
+impl ZabbixSender {
+    /// Create a sender
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - Zabbix server or proxy (host or host:port)
+    /// * `host` - Host name the items belong to in Zabbix
+    pub fn new(server: &str, host: String) -> Self {
+        let server = if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{}:{}", server, DEFAULT_PORT)
+        };
+
+        Self { server, host }
+    }
+
+    /// Item values for a report
+    pub fn items(&self, metrics: &LatencyMetrics) -> Vec<ZabbixItem> {
+        let clock = chrono::DateTime::parse_from_rfc3339(&metrics.timestamp)
+            .map(|t| t.timestamp())
+            .unwrap_or_else(|_| chrono::Utc::now().timestamp());
+        let item = |key: String, value: String| ZabbixItem {
+            host: self.host.clone(),
+            key,
+            value,
+            clock,
+        };
+
+        let p = &metrics.percentiles;
+        let mut items = vec![
+            item(
+                "latency_probe.events".into(),
+                metrics.total_events.to_string(),
+            ),
+            item(
+                "latency_probe.lost_events".into(),
+                metrics.lost_events.to_string(),
+            ),
+            item(
+                "latency_probe.connections".into(),
+                metrics.connections.len().to_string(),
+            ),
+            item("latency_probe.p50".into(), format!("{:.2}", p.p50)),
+            item("latency_probe.p90".into(), format!("{:.2}", p.p90)),
+            item("latency_probe.p99".into(), format!("{:.2}", p.p99)),
+            item("latency_probe.p999".into(), format!("{:.2}", p.p999)),
+        ];
+
+        let mut services: Vec<_> = metrics.services.iter().collect();
+        services.sort_by(|a, b| a.0.cmp(b.0));
+        for (service, stats) in services {
+            items.push(item(
+                format!("latency_probe.service.p99[{}]", service),
+                format!("{:.2}", stats.percentiles.p99),
+            ));
+        }
+
+        items
+    }
+
+    /// Send the values of a report
+    ///
+    /// # Returns
+    ///
+    /// The server's summary (e.g. "processed: 7; failed: 0; total: 7; ...")
+    pub fn send(&self, metrics: &LatencyMetrics) -> Result<String> {
+        let items = self.items(metrics);
+        let request = serde_json::to_vec(&SenderRequest {
+            request: "sender data",
+            data: &items,
+        })?;
+
+        let addr = self
+            .server
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve Zabbix server {}", self.server))?
+            .next()
+            .with_context(|| format!("No address for Zabbix server {}", self.server))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+            .with_context(|| format!("Failed to connect to Zabbix server {}", self.server))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        stream
+            .write_all(&encode(&request))
+            .with_context(|| format!("Failed to send to Zabbix server {}", self.server))?;
+        let response: SenderResponse = serde_json::from_slice(&decode(&mut stream)?)
+            .context("Invalid response from Zabbix server")?;
+
+        if response.response != "success" {
+            anyhow::bail!("Zabbix server rejected the values: {}", response.info);
+        }
+        if failed_items(&response.info) > 0 {
+            anyhow::bail!(
+                "Zabbix server did not accept all values ({}); check the trapper items of host {}",
+                response.info,
+                self.host
+            );
+        }
+
+        Ok(response.info)
+    }
+}
+
+impl MetricsExporter for ZabbixSender {
+    fn export(&self, metrics: &LatencyMetrics) -> Result<()> {
+        self.send(metrics).map(|_| ())
+    }
+}
+
+/// Frame a payload: header, little-endian length, payload
+fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER.len() + 8 + payload.len());
+    packet.extend_from_slice(HEADER);
+    packet.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Read a framed payload
+fn decode(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut header = [0u8; 13];
+    reader
+        .read_exact(&mut header)
+        .context("Failed to read Zabbix response header")?;
+    if &header[..4] != b"ZBXD" {
+        anyhow::bail!("Invalid Zabbix response header");
+    }
+
+    let len = u64::from_le_bytes(header[5..].try_into()?);
+    if len > MAX_RESPONSE_LEN {
+        anyhow::bail!("Zabbix response too large ({} bytes)", len);
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader
+        .read_exact(&mut payload)
+        .context("Failed to read Zabbix response")?;
+
+    Ok(payload)
+}
+
+/// Failed item count from a response summary (0 if absent)
+fn failed_items(info: &str) -> u64 {
+    info.split(';')
+        .filter_map(|field| field.trim().strip_prefix("failed:"))
+        .find_map(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Name of this host, the default Zabbix host name
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: buf is valid for buf.len() bytes
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return "localhost".to_string();
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::GroupMetrics;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_zabbix_sender() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request: serde_json::Value =
+                serde_json::from_slice(&decode(&mut stream).unwrap()).unwrap();
+            let response = br#"{"response":"success","info":"processed: 8; failed: 0; total: 8; seconds spent: 0.0001"}"#;
+            stream.write_all(&encode(response)).unwrap();
+            request
+        });
+
+        let mut metrics = LatencyMetrics {
+            timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+            total_events: 42,
+            ..Default::default()
+        };
+        metrics.percentiles.p99 = 1234.5;
+        metrics
+            .services
+            .insert("redis".to_string(), GroupMetrics::default());
+
+        let sender = ZabbixSender::new(&server, "node-1".to_string());
+        let info = sender.send(&metrics).unwrap();
+        assert!(info.starts_with("processed: 8"));
+
+        let request = handle.join().unwrap();
+        assert_eq!(request["request"], "sender data");
+        let data = request["data"].as_array().unwrap();
+        assert_eq!(data.len(), 8);
+        assert_eq!(data[0]["host"], "node-1");
+        assert_eq!(data[0]["key"], "latency_probe.events");
+        assert_eq!(data[0]["value"], "42");
+        assert_eq!(data[0]["clock"], 1735689600);
+        assert_eq!(data[5]["value"], "1234.50");
+        assert_eq!(data[7]["key"], "latency_probe.service.p99[redis]");
+
+        assert_eq!(failed_items("processed: 6; failed: 2; total: 8"), 2);
+        assert_eq!(
+            ZabbixSender::new("zabbix", String::new()).server,
+            "zabbix:10051"
+        );
+    }
+}
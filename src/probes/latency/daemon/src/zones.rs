@@ -0,0 +1,174 @@
+//! Zone-aware latency
+//!
+//! In multi-zone clusters, hops between zones add more latency than the
+//! mesh does, and dominate the tail. Given the address ranges of each zone,
+//! connections are classed as intra-zone (both ends in one zone),
+//! cross-zone, or unmapped (an end in no listed range, e.g. loopback).
+//!
+//! ## Example zone map
+//!
+//! ```yaml
+//! us-east-1a:
+//!   - 10.0.0.0/20
+//! us-east-1b:
+//!   - 10.0.16.0/20
+//!   - 10.1.0.5/32
+//! ```
+
+use crate::types::ConnectionKey;
+use anyhow::{Context, Result};
+use std::{collections::BTreeMap, net::Ipv4Addr, path::Path};
+
+/// Locality of a connection with both ends in one zone
+pub const LOCALITY_INTRA_ZONE: &str = "intra-zone";
+
+/// Locality of a connection between two zones
+pub const LOCALITY_CROSS_ZONE: &str = "cross-zone";
+
+/// Locality of a connection with an end outside every zone
+pub const LOCALITY_UNMAPPED: &str = "unmapped";
+
+/// An IPv4 network in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Network {
+    addr: u32,
+    prefix_len: u32,
+}
+
+impl Network {
+    /// Parse `ADDR/LEN` (a bare address is a /32)
+    pub(crate) fn parse(spec: &str) -> Result<Self> {
+        let (addr, prefix_len) = match spec.split_once('/') {
+            Some((addr, len)) => (
+                addr,
+                len.parse::<u32>()
+                    .ok()
+                    .filter(|&len| len <= 32)
+                    .with_context(|| format!("Invalid prefix length in '{}'", spec))?,
+            ),
+            None => (spec, 32),
+        };
+        let addr: Ipv4Addr = addr
+            .parse()
+            .with_context(|| format!("Invalid address in '{}'", spec))?;
+
+        Ok(Self {
+            addr: u32::from(addr) & Self::mask(prefix_len),
+            prefix_len,
+        })
+    }
+
+    fn mask(prefix_len: u32) -> u32 {
+        u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0)
+    }
+
+    pub(crate) fn contains(&self, addr: u32) -> bool {
+        addr & Self::mask(self.prefix_len) == self.addr
+    }
+
+    /// The address of a /32 network
+    pub(crate) fn single_address(&self) -> Option<Ipv4Addr> {
+        (self.prefix_len == 32).then(|| Ipv4Addr::from(self.addr))
+    }
+}
+
+/// Maps addresses to availability zones
+#[derive(Debug, Clone, Default)]
+pub struct ZoneMap {
+    /// Networks and their zones, most specific first
+    networks: Vec<(Network, String)>,
+}
+
+impl ZoneMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a YAML zone map (zone: list of CIDRs)
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the zone map file
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read zone map: {:?}", path))?;
+        let zones: BTreeMap<String, Vec<String>> = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse zone map: {:?}", path))?;
+
+        let mut map = Self::new();
+        for (zone, networks) in zones {
+            for network in networks {
+                map.insert(&network, &zone)?;
+            }
+        }
+        Ok(map)
+    }
+
+    /// Add a network to a zone
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - IPv4 network in CIDR notation, or a single address
+    /// * `zone` - Zone name
+    pub fn insert(&mut self, network: &str, zone: &str) -> Result<()> {
+        let network = Network::parse(network)?;
+        self.networks.push((network, zone.to_string()));
+        // Longest prefix wins, so a node address can override its subnet
+        self.networks.sort_by_key(|(network, _)| std::cmp::Reverse(network.prefix_len));
+        Ok(())
+    }
+
+    /// Zone of an address, if it is in a listed network
+    pub fn zone_of(&self, addr: Ipv4Addr) -> Option<&str> {
+        let addr = u32::from(addr);
+        self.networks
+            .iter()
+            .find(|(network, _)| network.contains(addr))
+            .map(|(_, zone)| zone.as_str())
+    }
+
+    /// Locality of a connection (see the LOCALITY_* constants)
+    pub fn locality(&self, key: &ConnectionKey) -> &'static str {
+        let saddr = Ipv4Addr::from(u32::from_be(key.saddr));
+        let daddr = Ipv4Addr::from(u32::from_be(key.daddr));
+
+        match (self.zone_of(saddr), self.zone_of(daddr)) {
+            (Some(source), Some(destination)) if source == destination => LOCALITY_INTRA_ZONE,
+            (Some(_), Some(_)) => LOCALITY_CROSS_ZONE,
+            _ => LOCALITY_UNMAPPED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_locality() {
+        let mut zones = ZoneMap::new();
+        zones.insert("10.0.0.0/20", "us-east-1a").unwrap();
+        zones.insert("10.0.16.0/20", "us-east-1b").unwrap();
+        // A single address placed in another zone than its subnet
+        zones.insert("10.0.0.9", "us-east-1b").unwrap();
+
+        assert_eq!(zones.zone_of("10.0.15.255".parse().unwrap()), Some("us-east-1a"));
+        assert_eq!(zones.zone_of("10.0.0.9".parse().unwrap()), Some("us-east-1b"));
+        assert_eq!(zones.zone_of("10.0.32.1".parse().unwrap()), None);
+
+        let key = |saddr: [u8; 4], daddr: [u8; 4]| ConnectionKey {
+            saddr: u32::from_ne_bytes(saddr),
+            daddr: u32::from_ne_bytes(daddr),
+            sport: 0x3930,
+            dport: 0x901f,
+        };
+        assert_eq!(zones.locality(&key([10, 0, 0, 1], [10, 0, 1, 2])), LOCALITY_INTRA_ZONE);
+        assert_eq!(zones.locality(&key([10, 0, 0, 1], [10, 0, 16, 2])), LOCALITY_CROSS_ZONE);
+        assert_eq!(zones.locality(&key([10, 0, 0, 1], [10, 0, 0, 9])), LOCALITY_CROSS_ZONE);
+        assert_eq!(zones.locality(&key([127, 0, 0, 1], [127, 0, 0, 1])), LOCALITY_UNMAPPED);
+
+        assert!(zones.insert("10.0.0.0/33", "bad").is_err());
+        assert!(zones.insert("10.0.0/8", "bad").is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! End-to-end tests of the userspace pipeline using synthetic events
+
+use latency_probe_userspace::{
+    collector::MetricsCollector,
+    events::EventProcessor,
+    exporter::{InfluxExporter, JsonExporter, MetricsExporter, PrometheusExporter},
+    replay::EventRecorder,
+    testing::{LatencyDistribution, SyntheticConfig, SyntheticEventGenerator},
+    types::LatencyMetrics,
+};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+fn collect(config: SyntheticConfig) -> LatencyMetrics {
+    let mut collector = MetricsCollector::new();
+    for event in SyntheticEventGenerator::new(config) {
+        collector.add_event(&event);
+    }
+    collector.generate_metrics(60)
+}
+
+#[test]
+fn test_constant_latency_percentiles() {
+    let metrics = collect(SyntheticConfig {
+        events: 500,
+        distribution: LatencyDistribution::Constant { latency_ns: 750_000 },
+        ..Default::default()
+    });
+
+    let p = &metrics.percentiles;
+    for value in [p.p50, p.p75, p.p90, p.p95, p.p99, p.p999] {
+        assert_eq!(value, 750.0);
+    }
+}
+
+#[test]
+fn test_percentiles_are_monotonic() {
+    let metrics = collect(SyntheticConfig {
+        events: 10_000,
+        distribution: LatencyDistribution::LogNormal {
+            median_ns: 800_000,
+            sigma: 0.8,
+        },
+        ..Default::default()
+    });
+
+    let p = &metrics.percentiles;
+    assert!(p.p50 <= p.p75);
+    assert!(p.p75 <= p.p90);
+    assert!(p.p90 <= p.p95);
+    assert!(p.p95 <= p.p99);
+    assert!(p.p99 <= p.p999);
+
+    // Median of a log-normal is its scale parameter (800us)
+    assert!((p.p50 - 800.0).abs() < 80.0, "p50 was {}", p.p50);
+}
+
+#[test]
+fn test_bimodal_tail_is_visible() {
+    let metrics = collect(SyntheticConfig {
+        events: 10_000,
+        distribution: LatencyDistribution::Bimodal {
+            fast_ns: 200_000,
+            slow_ns: 150_000_000,
+            slow_fraction: 0.05,
+        },
+        ..Default::default()
+    });
+
+    assert_eq!(metrics.percentiles.p50, 200.0);
+    assert_eq!(metrics.percentiles.p99, 150_000.0);
+
+    // Fast events land in 0-1ms, slow ones in 100ms+
+    let histogram = &metrics.histogram;
+    assert_eq!(
+        histogram.bucket_0_1ms + histogram.bucket_100ms_plus,
+        10_000
+    );
+    assert!(histogram.bucket_100ms_plus > 300 && histogram.bucket_100ms_plus < 700);
+}
+
+#[test]
+fn test_histogram_total_matches_event_count() {
+    let metrics = collect(SyntheticConfig {
+        events: 2_000,
+        connections: 25,
+        distribution: LatencyDistribution::Uniform {
+            min_ns: 1_000,
+            max_ns: 200_000_000,
+        },
+        burst_probability: 0.05,
+        ..Default::default()
+    });
+
+    assert_eq!(metrics.total_events, 2_000);
+    assert_eq!(metrics.histogram.total_count(), 2_000);
+    assert_eq!(metrics.connections.len(), 25);
+
+    let per_connection: u64 = metrics.connections.values().map(|c| c.events).sum();
+    assert_eq!(per_connection, 2_000);
+}
+
+#[test]
+fn test_generator_is_deterministic() {
+    let a: Vec<_> = SyntheticEventGenerator::new(SyntheticConfig::default())
+        .map(|e| (e.timestamp_ns, e.latency_ns))
+        .collect();
+    let b: Vec<_> = SyntheticEventGenerator::new(SyntheticConfig::default())
+        .map(|e| (e.timestamp_ns, e.latency_ns))
+        .collect();
+
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_exporters_end_to_end() {
+    let metrics = collect(SyntheticConfig::default());
+    let dir = tempfile::tempdir().unwrap();
+
+    let json_path = dir.path().join("metrics.json");
+    JsonExporter::new(json_path.clone(), true)
+        .export(&metrics)
+        .unwrap();
+    let parsed: LatencyMetrics =
+        serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(parsed.total_events, metrics.total_events);
+    assert_eq!(parsed.percentiles.p99, metrics.percentiles.p99);
+    assert_eq!(parsed.connections.len(), metrics.connections.len());
+
+    let prom_path = dir.path().join("metrics.prom");
+    PrometheusExporter::new(prom_path.clone())
+        .export(&metrics)
+        .unwrap();
+    let prom = std::fs::read_to_string(&prom_path).unwrap();
+    assert!(prom.contains(&format!(
+        "latency_probe_events_total {}",
+        metrics.total_events
+    )));
+    assert!(prom.contains(&format!(
+        "latency_probe_latency_microseconds{{percentile=\"0.99\"}} {}",
+        metrics.percentiles.p99
+    )));
+
+    let influx_path = dir.path().join("metrics.influx");
+    InfluxExporter::new(influx_path.clone(), "latency".to_string())
+        .export(&metrics)
+        .unwrap();
+    let influx = std::fs::read_to_string(&influx_path).unwrap();
+    assert!(influx.contains(&format!("total_events={}i", metrics.total_events)));
+}
+
+#[tokio::test]
+async fn test_record_and_replay_preserves_metrics() {
+    let config = SyntheticConfig {
+        events: 1_000,
+        ..Default::default()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let recording = dir.path().join("events.jsonl");
+
+    let recorder = EventRecorder::create(&recording).unwrap();
+    for event in SyntheticEventGenerator::new(config.clone()) {
+        recorder.record(&event).unwrap();
+    }
+    recorder.flush().unwrap();
+
+    let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+    let processor = EventProcessor::new(Arc::clone(&collector), 1, false);
+    let summary = processor.replay(&recording).await.unwrap();
+    assert_eq!(summary.events, 1_000);
+
+    let replayed = collector.lock().await.generate_metrics(60);
+    let direct = collect(config);
+
+    assert_eq!(replayed.total_events, direct.total_events);
+    assert_eq!(replayed.percentiles.p50, direct.percentiles.p50);
+    assert_eq!(replayed.percentiles.p999, direct.percentiles.p999);
+    assert_eq!(
+        replayed.histogram.bucket_1_5ms,
+        direct.histogram.bucket_1_5ms
+    );
+}
+
+#[tokio::test]
+async fn test_replay_netns_filter() {
+    let dir = tempfile::tempdir().unwrap();
+    let recording = dir.path().join("events.jsonl");
+
+    // Alternate events between two namespaces
+    let recorder = EventRecorder::create(&recording).unwrap();
+    let events = SyntheticEventGenerator::new(SyntheticConfig {
+        events: 100,
+        ..Default::default()
+    });
+    for (i, mut event) in events.enumerate() {
+        event.netns = if i % 2 == 0 { 4026531840 } else { 4026532288 };
+        recorder.record(&event).unwrap();
+    }
+    recorder.flush().unwrap();
+
+    let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+    let mut processor = EventProcessor::new(Arc::clone(&collector), 1, false);
+    processor.set_netns_filter(vec![4026532288]);
+    let summary = processor.replay(&recording).await.unwrap();
+    assert_eq!(summary.events, 100);
+
+    let metrics = collector.lock().await.generate_metrics(60);
+    assert_eq!(metrics.total_events, 50);
+    assert_eq!(metrics.namespaces.len(), 1);
+    assert_eq!(metrics.namespaces["4026532288"].events, 50);
+}
@@ -0,0 +1,107 @@
+//! Property tests of the percentile and histogram math
+//!
+//! Percentile rounding and bucket boundaries have been wrong before (p999
+//! and the percentile index both rounded the rank down); these check the
+//! invariants on arbitrary samples rather than on hand-picked ones.
+
+use latency_probe_userspace::{
+    digest::{LatencyDigest, RELATIVE_ACCURACY},
+    types::{calculate_percentiles, percentile_rank, LatencyHistogram, Percentiles},
+};
+use proptest::prelude::*;
+
+/// Percentiles in ascending order, with their fractions
+fn ordered(p: &Percentiles) -> [(f64, u64, u64); 6] {
+    [
+        (p.p50, 50, 100),
+        (p.p75, 75, 100),
+        (p.p90, 90, 100),
+        (p.p95, 95, 100),
+        (p.p99, 99, 100),
+        (p.p999, 999, 1000),
+    ]
+}
+
+/// Latencies in microseconds, spanning every histogram bucket
+fn latencies() -> impl Strategy<Value = Vec<f64>> {
+    prop::collection::vec(0.0..200_000.0f64, 1..500)
+}
+
+fn digest(samples: &[f64]) -> LatencyDigest {
+    let mut digest = LatencyDigest::new();
+    for &sample in samples {
+        digest.add(sample);
+    }
+    digest
+}
+
+proptest! {
+    #[test]
+    fn percentiles_are_nearest_ranks(samples in latencies()) {
+        let percentiles = calculate_percentiles(samples.clone());
+        let len = samples.len() as u64;
+
+        let mut previous = f64::MIN;
+        for (value, numerator, denominator) in ordered(&percentiles) {
+            prop_assert!(value >= previous, "{:?} not monotonic", percentiles);
+            previous = value;
+
+            // At least the fraction of samples is at or below the value,
+            // and fewer are strictly below it
+            let rank = percentile_rank(len, numerator, denominator);
+            let at_or_below = samples.iter().filter(|&&s| s <= value).count() as u64;
+            let below = samples.iter().filter(|&&s| s < value).count() as u64;
+            prop_assert!(at_or_below >= rank && below < rank, "{}/{} of {} samples", numerator, denominator, len);
+        }
+    }
+
+    #[test]
+    fn percentile_rank_is_within_samples(len in 1..1_000_000u64, numerator in 0..=1000u64) {
+        let rank = percentile_rank(len, numerator, 1000);
+        prop_assert!((1..=len).contains(&rank));
+        prop_assert!(rank * 1000 >= len * numerator);
+    }
+
+    #[test]
+    fn histogram_counts_every_sample(samples in latencies()) {
+        let histogram = LatencyHistogram::from_samples(&samples);
+        prop_assert_eq!(histogram.total_count(), samples.len() as u64);
+        for (index, count) in histogram.counts().into_iter().enumerate() {
+            let expected = samples.iter().filter(|&&s| LatencyHistogram::bucket_index(s) == index).count();
+            prop_assert_eq!(count, expected as u64);
+        }
+    }
+
+    #[test]
+    fn histogram_merge_equals_combined(a in latencies(), b in latencies()) {
+        let mut merged = LatencyHistogram::from_samples(&a);
+        merged.merge(&LatencyHistogram::from_samples(&b));
+        let combined = LatencyHistogram::from_samples(&[a, b].concat());
+        prop_assert_eq!(merged.counts(), combined.counts());
+    }
+
+    #[test]
+    fn digest_merge_equals_combined(a in latencies(), b in latencies()) {
+        let mut merged = digest(&a);
+        merged.merge(&digest(&b));
+        let combined = digest(&[a, b].concat());
+        prop_assert_eq!(merged.count(), combined.count());
+        prop_assert_eq!(merged.min(), combined.min());
+        prop_assert_eq!(merged.max(), combined.max());
+        prop_assert_eq!(ordered(&merged.percentiles()), ordered(&combined.percentiles()));
+    }
+
+    #[test]
+    fn digest_percentiles_within_accuracy(samples in prop::collection::vec(1.0..200_000.0f64, 1..500)) {
+        let exact = calculate_percentiles(samples.clone());
+        let estimated = digest(&samples).percentiles();
+        for ((exact, _, _), (estimated, _, _)) in ordered(&exact).into_iter().zip(ordered(&estimated)) {
+            prop_assert!(
+                (estimated - exact).abs() <= exact * RELATIVE_ACCURACY * 1.000_001,
+                "estimated {} for {}",
+                estimated,
+                exact
+            );
+        }
+    }
+}
@@ -4,15 +4,20 @@
 //! and measure network latency, packet drops, and connection states.
 
 use aya_ebpf::{
-    macros::{kprobe, tracepoint, xdp},
-    programs::{ProbeContext, TracePointContext, XdpContext},
+    macros::{kprobe, sk_msg, sock_ops, tracepoint, xdp},
+    programs::{ProbeContext, SkMsgContext, SockOpsContext, TracePointContext, XdpContext},
 };
-use aya_ebpf::bindings::xdp_action;
+use aya_ebpf::bindings::{
+    sk_action, xdp_action, BPF_NOEXIST, BPF_SOCK_OPS_ACTIVE_ESTABLISHED_CB,
+    BPF_SOCK_OPS_PASSIVE_ESTABLISHED_CB,
+};
+use aya_ebpf::helpers::gen::bpf_get_socket_cookie;
 use probe_common::{constants::*, types::*};
 
 use crate::{
     helpers::*,
     maps::*,
+    pipeline::submit_event,
     socket_parser::*,
 };
 
@@ -52,6 +57,10 @@ fn try_tcp_sendmsg(ctx: &ProbeContext) -> Result<u32, i64> {
         }
     };
 
+    // Count the bytes queued for sending (size_t size)
+    let size: usize = ctx.arg(2).unwrap_or(0);
+    add_connection_bytes(&key, size as u64, 0);
+
     // Record timestamp for this connection
     let timestamp = get_timestamp();
 
@@ -136,15 +145,20 @@ fn try_tcp_recvmsg(ctx: &ProbeContext) -> Result<u32, i64> {
         return Ok(0);
     }
 
-    // Create and send latency event to userspace
-    let event = create_latency_event(key, current_time, latency_ns, EVENT_TYPE_RECV);
-
     unsafe {
-        EVENTS.output(ctx, &event, 0);
-        // Update the start time for the next measurement
+        // Update the start time for the next measurement. Done before the
+        // event is submitted, as the pipeline does not return here, and
+        // filtered or sampled-out events still advance the start time
         let _ = CONNECTION_START.insert(&key, &current_time, 0);
     }
 
+    // Send the latency event through the pipeline to userspace
+    let mut event = create_latency_event(key, get_netns(sock), current_time, latency_ns, EVENT_TYPE_RECV);
+    event.http_status_class = http_status_class(&key);
+    set_tcp_state(&mut event, sock);
+    event.cookie = get_socket_cookie(sock);
+    submit_event(ctx, &event);
+
     Ok(0)
 }
 
@@ -185,6 +199,12 @@ fn try_tcp_cleanup_rbuf(ctx: &ProbeContext) -> Result<u32, i64> {
         }
     };
 
+    // Count the bytes the application read (int copied)
+    let copied: i32 = ctx.arg(1).unwrap_or(0);
+    if copied > 0 {
+        add_connection_bytes(&key, 0, copied as u64);
+    }
+
     let current_time = get_timestamp();
 
     // Look up start timestamp
@@ -212,15 +232,19 @@ fn try_tcp_cleanup_rbuf(ctx: &ProbeContext) -> Result<u32, i64> {
         return Ok(0);
     }
 
-    // Create and send cleanup event
-    let event = create_latency_event(key, current_time, latency_ns, EVENT_TYPE_CLEANUP);
-
     unsafe {
-        EVENTS.output(ctx, &event, 0);
-        // Update timestamp for next measurement
+        // Update timestamp for next measurement (before the pipeline,
+        // which does not return)
         let _ = CONNECTION_START.insert(&key, &current_time, 0);
     }
 
+    // Send the cleanup event through the pipeline
+    let mut event = create_latency_event(key, get_netns(sock), current_time, latency_ns, EVENT_TYPE_CLEANUP);
+    event.http_status_class = http_status_class(&key);
+    set_tcp_state(&mut event, sock);
+    event.cookie = get_socket_cookie(sock);
+    submit_event(ctx, &event);
+
     Ok(0)
 }
 
@@ -368,6 +392,17 @@ fn try_tcp_set_state(ctx: &ProbeContext) -> Result<u32, i64> {
     // Get the new state from arg1
     let new_state: i32 = ctx.arg(1).ok_or(-1)?;
 
+    record_state_transition(key, new_state);
+
+    Ok(0)
+}
+
+/// Update CONNECTION_STATES for a TCP state machine transition
+///
+/// Shared by the tcp_set_state kprobe and the sock:inet_sock_set_state
+/// tracepoint.
+#[inline(always)]
+fn record_state_transition(key: ConnectionKey, new_state: i32) {
     let timestamp = get_timestamp();
     let pid = get_pid();
 
@@ -416,8 +451,6 @@ fn try_tcp_set_state(ctx: &ProbeContext) -> Result<u32, i64> {
             let _ = CONNECTION_STATES.insert(&key, &new_conn_state, 0);
         }
     }
-
-    Ok(0)
 }
 
 /// Track outgoing TCP connections
@@ -511,6 +544,559 @@ fn try_tcp_close(ctx: &ProbeContext) -> Result<u32, i64> {
     Ok(0)
 }
 
+// ============================================================================
+// Resets and Timeouts
+// ============================================================================
+//
+// Mesh sidecars retry failed requests, so an error the application never
+// sees can still show up in the kernel as a reset or a retransmission
+// timeout. These events carry no latency and bypass sampling: they are rare,
+// and a sampled error rate would be off for short runs.
+
+/// Track RSTs received
+///
+/// Attached to: tcp_reset
+///
+/// Called when a valid RST arrives on a connection.
+#[kprobe]
+pub fn tcp_reset(ctx: ProbeContext) -> u32 {
+    match try_connection_error(&ctx, EVENT_TYPE_RESET_RECEIVED, STAT_RESETS_RECEIVED) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+/// Track RSTs sent
+///
+/// Attached to: tcp_send_active_reset
+///
+/// Called when the local side aborts a connection (e.g. closed with unread
+/// data, or SO_LINGER with a zero timeout).
+#[kprobe]
+pub fn tcp_send_active_reset(ctx: ProbeContext) -> u32 {
+    match try_connection_error(&ctx, EVENT_TYPE_RESET_SENT, STAT_RESETS_SENT) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+/// Track retransmission timeouts
+///
+/// Attached to: tcp_retransmit_timer
+///
+/// Called when the retransmission timer fires because the peer did not
+/// acknowledge data in time.
+#[kprobe]
+pub fn tcp_retransmit_timer(ctx: ProbeContext) -> u32 {
+    match try_connection_error(&ctx, EVENT_TYPE_TIMEOUT, STAT_TIMEOUTS) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_connection_error(ctx: &ProbeContext, event_type: u8, stat: u32) -> Result<u32, i64> {
+    increment_stat(STAT_TOTAL_EVENTS);
+    increment_stat(stat);
+
+    let sock = get_sock_from_context(ctx)?;
+
+    if !is_valid_socket(sock) {
+        increment_stat(STAT_INVALID_SOCKETS);
+        return Ok(0);
+    }
+
+    let key = match extract_connection_key(sock) {
+        Ok(k) => k,
+        Err(_) => {
+            increment_stat(STAT_INVALID_SOCKETS);
+            return Ok(0);
+        }
+    };
+
+    if !matches_service_filter(&key) {
+        increment_stat(STAT_FILTERED_EVENTS);
+        return Ok(0);
+    }
+
+    let mut event = create_latency_event(key, get_netns(sock), get_timestamp(), 0, event_type);
+    set_tcp_state(&mut event, sock);
+    event.cookie = get_socket_cookie(sock);
+    EVENTS.output(ctx, &event, 0);
+
+    Ok(0)
+}
+
+// ============================================================================
+// Listen Queues
+// ============================================================================
+//
+// A listener drops connection requests when its accept queue is full, i.e.
+// the application does not accept() fast enough: at the SYN in
+// tcp_conn_request, or at the handshake's final ACK in
+// tcp_v4_syn_recv_sock / tcp_v6_syn_recv_sock. The kernel counts both as
+// ListenOverflows. The backlog fields are read at offsets userspace
+// resolves from BTF.
+
+/// Track connection requests on listening sockets
+///
+/// Attached to: tcp_conn_request
+///
+/// Called for each SYN a listener receives (IPv4 and IPv6). The listening
+/// socket is the third argument.
+#[kprobe]
+pub fn tcp_conn_request(ctx: ProbeContext) -> u32 {
+    match try_listen_queue(&ctx, 2, true, None) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+/// Track handshakes completing on IPv4 listening sockets
+///
+/// Attached to: tcp_v4_syn_recv_sock
+///
+/// Called when the final ACK of a handshake arrives, to create the
+/// connection's socket. IPv6 listeners call it for IPv4-mapped peers from
+/// tcp_v6_syn_recv_sock, which counts those, so only IPv4 listeners are
+/// counted here.
+#[kprobe]
+pub fn tcp_v4_syn_recv_sock(ctx: ProbeContext) -> u32 {
+    match try_listen_queue(&ctx, 0, false, Some(AF_INET)) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+/// Track handshakes completing on IPv6 listening sockets
+///
+/// Attached to: tcp_v6_syn_recv_sock
+///
+/// Like tcp_v4_syn_recv_sock, for IPv6 (and dual-stack) listeners.
+#[kprobe]
+pub fn tcp_v6_syn_recv_sock(ctx: ProbeContext) -> u32 {
+    match try_listen_queue(&ctx, 0, false, Some(AF_INET6)) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_listen_queue(ctx: &ProbeContext, sock_arg: usize, request: bool, family: Option<u16>) -> Result<u32, i64> {
+    let backlog_offset = read_config(CONFIG_SK_ACK_BACKLOG_OFFSET) as usize;
+    let limit_offset = read_config(CONFIG_SK_MAX_ACK_BACKLOG_OFFSET) as usize;
+    if backlog_offset == 0 || limit_offset == 0 {
+        return Ok(0);
+    }
+
+    let sock: *const sock = ctx.arg(sock_arg).ok_or(-1)?;
+    if family.is_some_and(|family| get_socket_family(sock) != Ok(family)) {
+        return Ok(0);
+    }
+    let key = ListenerKey {
+        netns: get_netns(sock),
+        port: get_local_port(sock)?,
+        _padding: [0; 2],
+    };
+
+    // The two fields are adjacent u16s before 5.5, u32s since
+    let narrow = limit_offset == backlog_offset + 2;
+    let backlog = read_sock_u32(sock, backlog_offset, narrow);
+    let limit = read_sock_u32(sock, limit_offset, narrow);
+
+    // inet_csk_reqsk_queue_is_full(); without SYN cookies the SYN is dropped
+    let syn_queue_offset = read_config(CONFIG_SYN_QUEUE_LEN_OFFSET) as usize;
+    let syn_queue_full = request && syn_queue_offset != 0 && read_sock_u32(sock, syn_queue_offset, false) >= limit;
+
+    record_listener(&key, request, backlog, limit, syn_queue_full);
+    Ok(0)
+}
+
+// ============================================================================
+// DNS and QUIC Latency
+// ============================================================================
+//
+// A query is a udp_sendmsg to port 53; its response is the next datagram the
+// application reads from the same socket (skb_consume_udp). This is the
+// resolution latency the application sees, including any DNS interception
+// by a mesh sidecar.
+//
+// QUIC (when CONFIG_QUIC_PORT is set) is timed the same way: a flight starts
+// with the first datagram sent to the QUIC port since the socket last read
+// one, and ends with the next datagram read.
+
+/// Track DNS queries and QUIC flights
+///
+/// Attached to: udp_sendmsg
+///
+/// Records when a datagram is sent to port 53 or the QUIC port, keyed by
+/// socket.
+#[kprobe]
+pub fn udp_sendmsg(ctx: ProbeContext) -> u32 {
+    match try_udp_sendmsg(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_udp_sendmsg(ctx: &ProbeContext) -> Result<u32, i64> {
+    let sock = get_sock_from_context(ctx)?;
+
+    if !is_valid_socket(sock) {
+        return Ok(0);
+    }
+
+    let mut key = match extract_connection_key(sock) {
+        Ok(k) => k,
+        Err(_) => return Ok(0),
+    };
+
+    // Unconnected sockets (sendto) pass the destination with the message
+    if key.dport == 0 {
+        let msg: *const u8 = ctx.arg(1).ok_or(-1)?;
+        match read_msg_name(msg) {
+            Some((daddr, dport)) => {
+                key.daddr = daddr;
+                key.dport = dport;
+            }
+            None => return Ok(0),
+        }
+    }
+
+    let quic_port = read_config(CONFIG_QUIC_PORT) as u16;
+    if quic_port != 0 && key.dport == quic_port.to_be() {
+        increment_stat(STAT_TOTAL_EVENTS);
+        increment_stat(STAT_QUIC_PACKETS);
+
+        let flight = DnsQuery {
+            key,
+            start_ns: get_timestamp(),
+        };
+
+        unsafe {
+            // Later datagrams of the flight keep its start
+            let _ = QUIC_START.insert(&(sock as u64), &flight, BPF_NOEXIST as u64);
+        }
+        return Ok(0);
+    }
+
+    if key.dport != DNS_PORT.to_be() {
+        return Ok(0);
+    }
+
+    increment_stat(STAT_TOTAL_EVENTS);
+    increment_stat(STAT_DNS_QUERIES);
+
+    let query = DnsQuery {
+        key,
+        start_ns: get_timestamp(),
+    };
+
+    unsafe {
+        // Resolvers often send A and AAAA queries back to back on one
+        // socket; time from the first
+        let _ = DNS_START.insert(&(sock as u64), &query, BPF_NOEXIST as u64);
+    }
+
+    Ok(0)
+}
+
+/// Track DNS and QUIC responses
+///
+/// Attached to: skb_consume_udp
+///
+/// Called when the application has read a datagram. If the socket has an
+/// outstanding DNS query or QUIC flight, reports the time since it was sent.
+#[kprobe]
+pub fn skb_consume_udp(ctx: ProbeContext) -> u32 {
+    match try_skb_consume_udp(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_skb_consume_udp(ctx: &ProbeContext) -> Result<u32, i64> {
+    let sock = get_sock_from_context(ctx)?;
+    let sock_addr = sock as u64;
+
+    let (query, event_type) = unsafe {
+        if let Some(query) = DNS_START.get(&sock_addr) {
+            let query = *query;
+            let _ = DNS_START.remove(&sock_addr);
+            increment_stat(STAT_DNS_RESPONSES);
+            (query, EVENT_TYPE_DNS)
+        } else if let Some(flight) = QUIC_START.get(&sock_addr) {
+            let flight = *flight;
+            let _ = QUIC_START.remove(&sock_addr);
+            increment_stat(STAT_QUIC_RESPONSES);
+            (flight, EVENT_TYPE_QUIC)
+        } else {
+            return Ok(0);
+        }
+    };
+
+    increment_stat(STAT_TOTAL_EVENTS);
+
+    let current_time = get_timestamp();
+    if current_time <= query.start_ns {
+        return Ok(0);
+    }
+
+    let latency_ns = current_time - query.start_ns;
+    if !is_valid_latency(latency_ns) {
+        increment_stat(STAT_INVALID_LATENCY);
+        return Ok(0);
+    }
+
+    let mut event = create_latency_event(query.key, get_netns(sock), current_time, latency_ns, event_type);
+    event.cookie = get_socket_cookie(sock);
+    submit_event(ctx, &event);
+
+    Ok(0)
+}
+
+// ============================================================================
+// HTTP Status Sampling
+// ============================================================================
+//
+// Optional (--http-status-cgroup). A sock_ops program adds established
+// sockets to SOCK_HASH, and an sk_msg program on that map inspects the
+// start of each message the sockets send. Messages starting with an
+// HTTP/1.x status line record the status class for the connection, keyed
+// as the peer sees it, so the peer's receive events can carry it. Both
+// ends must therefore be traced on this node (e.g. sidecar and app).
+
+/// Register established sockets for status sampling
+///
+/// Attached to: cgroup (sock_ops)
+#[sock_ops]
+pub fn sock_ops_established(ctx: SockOpsContext) -> u32 {
+    match try_sock_ops_established(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_sock_ops_established(ctx: &SockOpsContext) -> Result<u32, i64> {
+    let op = ctx.op();
+    if op != BPF_SOCK_OPS_ACTIVE_ESTABLISHED_CB && op != BPF_SOCK_OPS_PASSIVE_ESTABLISHED_CB {
+        return Ok(0);
+    }
+    if ctx.family() != AF_INET as u32 {
+        return Ok(0);
+    }
+
+    // remote_port holds the network order port in its upper 16 bits,
+    // local_port is in host order
+    let mut key = ConnectionKey {
+        saddr: ctx.local_ip4(),
+        daddr: ctx.remote_ip4(),
+        sport: (ctx.local_port() as u16).to_be(),
+        dport: (ctx.remote_port() >> 16) as u16,
+    };
+
+    unsafe {
+        let _ = SOCK_HASH.update(&mut key, &mut *ctx.ops, 0);
+    }
+
+    Ok(0)
+}
+
+/// Assign socket cookies to established sockets
+///
+/// Attached to: cgroup (sock_ops, optional --socket-cookie-cgroup)
+///
+/// Cookies are only generated when first requested; asking for one here
+/// gives every TCP socket in the cgroup a cookie the kprobes can read.
+#[sock_ops]
+pub fn sock_ops_cookie(ctx: SockOpsContext) -> u32 {
+    let op = ctx.op();
+    if op == BPF_SOCK_OPS_ACTIVE_ESTABLISHED_CB || op == BPF_SOCK_OPS_PASSIVE_ESTABLISHED_CB {
+        unsafe {
+            bpf_get_socket_cookie(ctx.ops as *mut _);
+        }
+    }
+    1
+}
+
+/// Classify HTTP responses by status
+///
+/// Attached to: SOCK_HASH (sk_msg)
+///
+/// Always passes the message on; only the first bytes are inspected.
+#[sk_msg]
+pub fn sk_msg_http_status(ctx: SkMsgContext) -> u32 {
+    let _ = try_sk_msg_http_status(&ctx);
+    sk_action::SK_PASS
+}
+
+fn try_sk_msg_http_status(ctx: &SkMsgContext) -> Result<(), i64> {
+    let data = ctx.data();
+    if data + HTTP_STATUS_LINE_LEN > ctx.data_end() {
+        return Ok(());
+    }
+
+    // "HTTP/1.x NNN"
+    let line = unsafe { *(data as *const [u8; HTTP_STATUS_LINE_LEN]) };
+    if &line[..7] != b"HTTP/1." || line[8] != b' ' {
+        return Ok(());
+    }
+    let class = line[9].wrapping_sub(b'0');
+    if !(HTTP_STATUS_MIN_CLASS..=HTTP_STATUS_MAX_CLASS).contains(&class) {
+        return Ok(());
+    }
+
+    increment_stat(STAT_HTTP_RESPONSES);
+
+    // Key the connection as the receiving peer sees it
+    let msg = unsafe { &*ctx.msg };
+    let key = ConnectionKey {
+        saddr: msg.remote_ip4,
+        daddr: msg.local_ip4,
+        sport: (msg.remote_port >> 16) as u16,
+        dport: (msg.local_port as u16).to_be(),
+    };
+
+    let _ = HTTP_STATUS.insert(&key, &class, 0);
+
+    Ok(())
+}
+
+// ============================================================================
+// Tracepoint Attach Mode
+// ============================================================================
+//
+// Stable alternatives to the kprobes above, for kernels where the TCP
+// functions are inlined or renamed. Tracepoint offsets are from the
+// tracefs `format` file and include the 8-byte common header.
+
+/// Track per-connection round-trip time
+///
+/// Attached to: tcp:tcp_probe tracepoint
+///
+/// Fires for each segment received on an established connection. Reports
+/// the connection's smoothed RTT as its latency, and records its congestion
+/// window, slow start threshold and RTT in CONGESTION. The record layout
+/// changed between kernels, so userspace resolves field offsets from
+/// tracefs and stores them in the CONFIG map.
+#[tracepoint]
+pub fn tcp_probe(ctx: TracePointContext) -> u32 {
+    match try_tcp_probe(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_tcp_probe(ctx: &TracePointContext) -> Result<u32, i64> {
+    increment_stat(STAT_TOTAL_EVENTS);
+    increment_stat(STAT_TCP_PROBE_EVENTS);
+
+    let saddr_offset = read_config(CONFIG_TCP_PROBE_SADDR_OFFSET) as usize;
+    let daddr_offset = read_config(CONFIG_TCP_PROBE_DADDR_OFFSET) as usize;
+    let srtt_offset = read_config(CONFIG_TCP_PROBE_SRTT_OFFSET) as usize;
+    if saddr_offset == 0 || daddr_offset == 0 || srtt_offset == 0 {
+        return Ok(0);
+    }
+
+    // saddr/daddr hold a struct sockaddr_in for IPv4:
+    // sin_family @0, sin_port @2 (network order), sin_addr @4
+    let family: u16 = unsafe { ctx.read_at(saddr_offset)? };
+    if family != AF_INET {
+        return Ok(0);
+    }
+
+    let key = unsafe {
+        ConnectionKey {
+            saddr: ctx.read_at(saddr_offset + 4)?,
+            daddr: ctx.read_at(daddr_offset + 4)?,
+            sport: ctx.read_at(saddr_offset + 2)?,
+            dport: ctx.read_at(daddr_offset + 2)?,
+        }
+    };
+
+    // srtt is in microseconds
+    let srtt_us: u32 = unsafe { ctx.read_at(srtt_offset)? };
+
+    let snd_cwnd_offset = read_config(CONFIG_TCP_PROBE_SND_CWND_OFFSET) as usize;
+    if snd_cwnd_offset != 0 && matches_service_filter(&key) {
+        let snd_cwnd: u32 = unsafe { ctx.read_at(snd_cwnd_offset)? };
+        let ssthresh_offset = read_config(CONFIG_TCP_PROBE_SSTHRESH_OFFSET) as usize;
+        let ssthresh: u32 = if ssthresh_offset == 0 { 0 } else { unsafe { ctx.read_at(ssthresh_offset)? } };
+        record_congestion(&key, snd_cwnd, ssthresh, srtt_us);
+    }
+
+    let latency_ns = srtt_us as u64 * 1000;
+    if !is_valid_latency(latency_ns) {
+        increment_stat(STAT_INVALID_LATENCY);
+        return Ok(0);
+    }
+
+    if should_report(&key, EVENT_TYPE_TCP_PROBE) {
+        let skaddr_offset = read_config(CONFIG_TCP_PROBE_SKADDR_OFFSET) as usize;
+        let netns = if skaddr_offset == 0 {
+            0
+        } else {
+            let sock: *const sock = unsafe { ctx.read_at(skaddr_offset).unwrap_or(core::ptr::null()) };
+            get_netns(sock)
+        };
+
+        let event = create_latency_event(key, netns, get_timestamp(), latency_ns, EVENT_TYPE_TCP_PROBE);
+        EVENTS.output(ctx, &event, 0);
+    }
+
+    Ok(0)
+}
+
+/// Track TCP connection state changes
+///
+/// Attached to: sock:inet_sock_set_state tracepoint
+///
+/// Tracepoint counterpart of the tcp_set_state kprobe. The record layout
+/// has been unchanged since the tracepoint was added (4.16):
+///
+/// ```text
+/// field:const void * skaddr;  offset:8;  size:8;
+/// field:int oldstate;         offset:16; size:4;
+/// field:int newstate;         offset:20; size:4;
+/// field:__u16 sport;          offset:24; size:2;
+/// field:__u16 dport;          offset:26; size:2;
+/// field:__u16 family;         offset:28; size:2;
+/// field:__u16 protocol;       offset:30; size:2;
+/// field:__u8 saddr[4];        offset:32; size:4;
+/// field:__u8 daddr[4];        offset:36; size:4;
+/// ```
+#[tracepoint]
+pub fn inet_sock_set_state(ctx: TracePointContext) -> u32 {
+    match try_inet_sock_set_state(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+fn try_inet_sock_set_state(ctx: &TracePointContext) -> Result<u32, i64> {
+    let family: u16 = unsafe { ctx.read_at(28)? };
+    let protocol: u16 = unsafe { ctx.read_at(30)? };
+    if family != AF_INET || protocol != IPPROTO_TCP as u16 {
+        return Ok(0);
+    }
+
+    increment_stat(STAT_TOTAL_EVENTS);
+    increment_stat(STAT_STATE_TRANSITIONS);
+
+    // Ports are recorded in host order, addresses in network order
+    let key = unsafe {
+        ConnectionKey {
+            saddr: ctx.read_at(32)?,
+            daddr: ctx.read_at(36)?,
+            sport: ctx.read_at::<u16>(24)?.to_be(),
+            dport: ctx.read_at::<u16>(26)?.to_be(),
+        }
+    };
+    let new_state: i32 = unsafe { ctx.read_at(20)? };
+
+    record_state_transition(key, new_state);
+
+    Ok(0)
+}
+
 // ============================================================================
 // Context Switch Tracking
 // ============================================================================
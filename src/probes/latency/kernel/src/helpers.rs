@@ -3,13 +3,23 @@
 //! Provides safe wrappers around BPF helper functions and
 //! utility functions for common operations.
 
-use aya_ebpf::helpers::{bpf_get_current_pid_tgid, bpf_ktime_get_ns};
+use aya_ebpf::helpers::{
+    bpf_get_current_pid_tgid, bpf_get_prandom_u32, bpf_ktime_get_boot_ns, bpf_ktime_get_ns,
+};
 use probe_common::{types::*, constants::*};
 
-/// Get current timestamp in nanoseconds
+/// Get current timestamp in nanoseconds, from the configured clock
+///
+/// The TAI helper is only compiled in with the `tai-clock` feature, since
+/// kernels before 6.1 reject programs that reference it.
 #[inline(always)]
 pub fn get_timestamp() -> u64 {
-    unsafe { bpf_ktime_get_ns() }
+    match read_config(CONFIG_CLOCK_SOURCE) {
+        CLOCK_SOURCE_BOOTTIME => unsafe { bpf_ktime_get_boot_ns() },
+        #[cfg(feature = "tai-clock")]
+        CLOCK_SOURCE_TAI => unsafe { aya_ebpf::helpers::bpf_ktime_get_tai_ns() },
+        _ => unsafe { bpf_ktime_get_ns() },
+    }
 }
 
 /// Get current process ID
@@ -28,11 +38,26 @@ pub fn get_tid() -> u32 {
 
 /// Validate latency measurement
 ///
-/// Returns true if the latency is within valid bounds.
-/// Filters out obvious measurement errors.
+/// Returns true if the latency is within valid bounds: the ones userspace
+/// stored in the CONFIG map, or MIN_LATENCY_NS and MAX_LATENCY_NS if it
+/// stored none. Filters out obvious measurement errors, counting the
+/// bound each rejected latency fell outside of.
 #[inline(always)]
 pub fn is_valid_latency(latency_ns: u64) -> bool {
-    latency_ns >= MIN_LATENCY_NS && latency_ns <= MAX_LATENCY_NS
+    let (min_ns, max_ns) = match read_config(CONFIG_MAX_LATENCY_NS) {
+        0 => (MIN_LATENCY_NS, MAX_LATENCY_NS),
+        max_ns => (read_config(CONFIG_MIN_LATENCY_NS), max_ns),
+    };
+
+    if latency_ns < min_ns {
+        increment_stat(STAT_LATENCY_BELOW_MIN);
+        false
+    } else if latency_ns > max_ns {
+        increment_stat(STAT_LATENCY_ABOVE_MAX);
+        false
+    } else {
+        true
+    }
 }
 
 /// Increment a statistics counter
@@ -52,22 +77,237 @@ pub fn increment_stat(stat_id: u32) {
     }
 }
 
+/// Read a runtime configuration value
+///
+/// Returns 0 for unset slots.
+#[inline(always)]
+pub fn read_config(index: u32) -> u64 {
+    use crate::maps::CONFIG;
+
+    CONFIG.get(index).copied().unwrap_or(0)
+}
+
+/// Check whether a connection matches the service filter
+///
+/// Matches if either endpoint is listed, by exact address and port, by port
+/// with a wildcard address, or by address with a wildcard port. Always matches when filtering is disabled.
+#[inline(always)]
+pub fn matches_service_filter(key: &ConnectionKey) -> bool {
+    use crate::maps::SERVICE_FILTER;
+
+    if read_config(CONFIG_FILTER_ENABLED) == 0 {
+        return true;
+    }
+
+    let candidates = [
+        ServiceFilterKey { addr: key.daddr, port: key.dport, _padding: [0; 2] },
+        ServiceFilterKey { addr: 0, port: key.dport, _padding: [0; 2] },
+        ServiceFilterKey { addr: key.daddr, port: 0, _padding: [0; 2] },
+        ServiceFilterKey { addr: key.saddr, port: key.sport, _padding: [0; 2] },
+        ServiceFilterKey { addr: 0, port: key.sport, _padding: [0; 2] },
+        ServiceFilterKey { addr: key.saddr, port: 0, _padding: [0; 2] },
+    ];
+
+    for candidate in candidates.iter() {
+        if unsafe { SERVICE_FILTER.get(candidate) }.is_some() {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Apply kernel-side sampling
+///
+/// Returns true if the event should be sent to userspace. Event types with
+/// their own rate use it instead of the global one. Event sampling uses a
+/// random draw so the decision is independent per CPU; flow sampling keeps
+/// every event of the connections whose hash is a multiple of the rate.
+#[inline(always)]
+pub fn should_sample(key: &ConnectionKey, event_type: u8) -> bool {
+    let mut rate = read_config(CONFIG_EVENT_SAMPLE_RATE + event_type as u32);
+    if rate == 0 {
+        rate = read_config(CONFIG_SAMPLE_RATE);
+    }
+    if rate <= 1 {
+        return true;
+    }
+
+    let draw = if read_config(CONFIG_SAMPLE_MODE) == SAMPLE_MODE_FLOW {
+        key.flow_hash()
+    } else {
+        unsafe { bpf_get_prandom_u32() }
+    };
+    draw as u64 % rate == 0
+}
+
+/// Decide whether a latency event for `key` is reported
+///
+/// Applies the service filter, then sampling, counting discarded events.
+#[inline(always)]
+pub fn should_report(key: &ConnectionKey, event_type: u8) -> bool {
+    if !matches_service_filter(key) {
+        increment_stat(STAT_FILTERED_EVENTS);
+        return false;
+    }
+
+    if !should_sample(key, event_type) {
+        increment_stat(STAT_SAMPLED_OUT_EVENTS);
+        return false;
+    }
+
+    true
+}
+
+/// Add to the byte counters of a connection
+///
+/// Creates the CONNECTION_STATES entry if the connection was established
+/// before the probe attached. Counters are cumulative; userspace computes
+/// throughput from the difference between readings.
+#[inline(always)]
+pub fn add_connection_bytes(key: &ConnectionKey, sent: u64, received: u64) {
+    use crate::maps::CONNECTION_STATES;
+
+    match CONNECTION_STATES.get_ptr_mut(key) {
+        Some(state) => unsafe {
+            (*state).bytes_sent += sent;
+            (*state).bytes_received += received;
+        },
+        None => {
+            let state = ConnectionState {
+                key: *key,
+                start_time_ns: get_timestamp(),
+                close_time_ns: 0,
+                state: CONN_STATE_ESTABLISHED,
+                bytes_sent: sent,
+                bytes_received: received,
+                pid: get_pid(),
+                _padding: [0; 4],
+            };
+            let _ = CONNECTION_STATES.insert(key, &state, 0);
+        }
+    }
+}
+
+/// Record a tcp:tcp_probe reading of a connection's congestion state
+///
+/// A collapse is counted when the congestion window falls to half or less
+/// of the previous reading, as on a loss or a retransmission timeout.
+#[inline(always)]
+pub fn record_congestion(key: &ConnectionKey, snd_cwnd: u32, ssthresh: u32, srtt_us: u32) {
+    use crate::maps::CONGESTION;
+
+    match CONGESTION.get_ptr_mut(key) {
+        Some(state) => unsafe {
+            let state = &mut *state;
+            if (snd_cwnd as u64) * 2 <= state.snd_cwnd as u64 {
+                state.cwnd_collapses += 1;
+            }
+            state.samples += 1;
+            state.srtt_sum_us += srtt_us as u64;
+            state.snd_cwnd = snd_cwnd;
+            state.ssthresh = ssthresh;
+            state.srtt_us = srtt_us;
+            if snd_cwnd < state.min_snd_cwnd {
+                state.min_snd_cwnd = snd_cwnd;
+            }
+            if srtt_us > state.max_srtt_us {
+                state.max_srtt_us = srtt_us;
+            }
+        },
+        None => {
+            let state = CongestionState {
+                samples: 1,
+                srtt_sum_us: srtt_us as u64,
+                cwnd_collapses: 0,
+                snd_cwnd,
+                ssthresh,
+                srtt_us,
+                min_snd_cwnd: snd_cwnd,
+                max_srtt_us: srtt_us,
+                _padding: [0; 4],
+            };
+            let _ = CONGESTION.insert(key, &state, 0);
+        }
+    }
+}
+
+/// Record a connection request or completed handshake on a listener
+///
+/// # Arguments
+///
+/// * `request` - Whether this is a SYN (rather than a handshake's final ACK)
+/// * `backlog` - Accept queue length
+/// * `backlog_limit` - Accept queue limit
+/// * `syn_queue_full` - Whether the SYN queue was full
+#[inline(always)]
+pub fn record_listener(key: &ListenerKey, request: bool, backlog: u32, backlog_limit: u32, syn_queue_full: bool) {
+    use crate::maps::LISTENERS;
+
+    // sk_acceptq_is_full()
+    let overflow = backlog > backlog_limit;
+    match LISTENERS.get_ptr_mut(key) {
+        Some(stats) => unsafe {
+            let stats = &mut *stats;
+            stats.requests += request as u64;
+            stats.overflows += overflow as u64;
+            stats.syn_queue_full += syn_queue_full as u64;
+            if backlog > stats.max_backlog {
+                stats.max_backlog = backlog;
+            }
+            stats.backlog_limit = backlog_limit;
+        },
+        None => {
+            let stats = ListenerStats {
+                requests: request as u64,
+                overflows: overflow as u64,
+                syn_queue_full: syn_queue_full as u64,
+                max_backlog: backlog,
+                backlog_limit,
+            };
+            let _ = LISTENERS.insert(key, &stats, 0);
+        }
+    }
+}
+
 /// Create a latency event
 ///
 /// Constructs a properly formatted LatencyEvent for sending to userspace.
 #[inline(always)]
 pub fn create_latency_event(
     key: ConnectionKey,
+    netns: u32,
     timestamp_ns: u64,
     latency_ns: u64,
     event_type: u8,
 ) -> LatencyEvent {
     LatencyEvent {
         key,
+        netns,
+        cookie: 0,
         timestamp_ns,
         latency_ns,
         pid: get_pid(),
         event_type,
-        _padding: [0; 3],
+        http_status_class: HTTP_STATUS_UNKNOWN,
+        tcp_state: TCP_STATE_UNKNOWN,
+        tcp_flags: 0,
+        protocol: crate::socket_parser::event_protocol(event_type),
+        _padding: [0; 7],
     }
 }
+
+/// Status class of the last HTTP response seen on a connection
+///
+/// Looks up the class recorded by the sk_msg program, keyed as the
+/// receiving (client) side sees the connection. Returns
+/// HTTP_STATUS_UNKNOWN when status sampling is disabled or no response
+/// has been classified.
+#[inline(always)]
+pub fn http_status_class(key: &ConnectionKey) -> u8 {
+    use crate::maps::HTTP_STATUS;
+
+    unsafe { HTTP_STATUS.get(key) }
+        .copied()
+        .unwrap_or(HTTP_STATUS_UNKNOWN)
+}
@@ -13,7 +13,10 @@
 //! tcp_sendmsg() -> Record timestamp in CONNECTION_START map
 //!                  |
 //!                  v
-//! tcp_recvmsg() -> Calculate latency, send event to EVENTS map
+//! tcp_recvmsg() -> Calculate latency, tail call into the PIPELINE
+//!                  |
+//!                  v
+//! filter -> sample -> emit (tail-called stages, send event to EVENTS map)
 //!                  |
 //!                  v
 //! Userspace    -> Read events, aggregate statistics, export metrics
@@ -38,6 +41,7 @@ use aya_ebpf::macros::map;
 mod handlers;
 mod helpers;
 mod maps;
+mod pipeline;
 mod socket_parser;
 
 // Re-export kprobe and XDP functions so they're visible to the loader
@@ -45,12 +49,18 @@ pub use handlers::{
     tcp_cleanup_rbuf, tcp_recvmsg, tcp_sendmsg,
     tcp_drop, kfree_skb_tracepoint,
     tcp_set_state, tcp_v4_connect, tcp_close,
+    tcp_reset, tcp_send_active_reset, tcp_retransmit_timer,
+    tcp_conn_request, tcp_v4_syn_recv_sock, tcp_v6_syn_recv_sock,
+    udp_sendmsg, skb_consume_udp,
+    sock_ops_established, sock_ops_cookie, sk_msg_http_status,
+    tcp_probe, inet_sock_set_state,
     xdp_packet_monitor,
     sched_switch,
 };
+pub use pipeline::{pipeline_filter, pipeline_sample, pipeline_emit};
 
 // Re-export maps for verification
-pub use maps::{CONNECTION_START, EVENTS, STATS, PACKET_DROPS, CONNECTION_STATES, XDP_CONN_STATS, CONTEXT_SWITCHES};
+pub use maps::{CONNECTION_START, EVENTS, STATS, PACKET_DROPS, CONNECTION_STATES, XDP_CONN_STATS, CONTEXT_SWITCHES, CONFIG, SERVICE_FILTER, DNS_START, QUIC_START, SOCK_HASH, HTTP_STATUS, CONGESTION, LISTENERS, PIPELINE, PIPELINE_EVENT};
 
 #[cfg(not(test))]
 #[panic_handler]
@@ -5,7 +5,7 @@
 
 use aya_ebpf::{
     macros::map,
-    maps::{HashMap, PerfEventArray},
+    maps::{Array, HashMap, LruHashMap, PerCpuArray, PerfEventArray, ProgramArray, SockHash},
 };
 use probe_common::{types::*, constants::*};
 
@@ -16,10 +16,22 @@ use probe_common::{types::*, constants::*};
 ///
 /// This map tracks when we first see traffic for a connection,
 /// allowing us to calculate latency on subsequent events.
+#[cfg(not(feature = "percpu-connection-start"))]
 #[map]
 pub static CONNECTION_START: HashMap<ConnectionKey, u64> =
     HashMap::with_max_entries(MAX_CONNECTIONS, 0);
 
+/// Per-CPU variant of CONNECTION_START
+///
+/// Each CPU keeps its own start time per connection, so busy nodes do not
+/// contend on shared buckets. An event is timed from the last event of the
+/// connection on the same CPU. LRU, so entries of flows that moved to
+/// other CPUs are evicted rather than filling the map.
+#[cfg(feature = "percpu-connection-start")]
+#[map]
+pub static CONNECTION_START: aya_ebpf::maps::LruPerCpuHashMap<ConnectionKey, u64> =
+    aya_ebpf::maps::LruPerCpuHashMap::with_max_entries(MAX_CONNECTIONS, 0);
+
 /// Perf event array to send latency events to userspace
 ///
 /// Events are written to this map by kprobes and read by
@@ -28,6 +40,25 @@ pub static CONNECTION_START: HashMap<ConnectionKey, u64> =
 pub static EVENTS: PerfEventArray<LatencyEvent> =
     PerfEventArray::new(0);
 
+/// Stages of the latency event pipeline
+///
+/// Index: PIPELINE_STAGE_* constant
+/// Value: kprobe program, tail-called with the triggering context
+///
+/// Filled by the userspace loader; handlers fall back to doing every stage
+/// inline while a slot is empty.
+#[map]
+pub static PIPELINE: ProgramArray =
+    ProgramArray::with_max_entries(MAX_PIPELINE_STAGES, 0);
+
+/// Event passed between pipeline stages
+///
+/// One slot per CPU. Tail calls keep the context but not the stack, and
+/// a CPU runs one kprobe at a time, so the slot is not shared.
+#[map]
+pub static PIPELINE_EVENT: PerCpuArray<LatencyEvent> =
+    PerCpuArray::with_max_entries(1, 0);
+
 /// Statistics counter map
 ///
 /// Tracks various statistics for monitoring probe health.
@@ -71,3 +102,90 @@ pub static CONTEXT_SWITCHES: PerfEventArray<ContextSwitchEvent> =
 #[map]
 pub static XDP_CONN_STATS: HashMap<ConnectionKey, XdpConnStats> =
     HashMap::with_max_entries(MAX_CONNECTIONS, 0);
+
+/// Runtime configuration written by userspace
+///
+/// Index: CONFIG_* constant (see probe_common::constants)
+/// Value: u64 setting
+///
+/// Rewritten on config reload, so sampling and filtering can change
+/// without reloading the programs.
+#[map]
+pub static CONFIG: Array<u64> =
+    Array::with_max_entries(MAX_CONFIG, 0);
+
+/// Outstanding DNS queries
+///
+/// Key: address of the querying struct sock
+/// Value: DnsQuery (send timestamp and the query's 4-tuple)
+///
+/// Keyed by socket rather than 4-tuple because unconnected UDP sockets
+/// (sendto) have no destination on the socket when the response arrives.
+/// LRU so queries that never get a response are evicted.
+#[map]
+pub static DNS_START: LruHashMap<u64, DnsQuery> =
+    LruHashMap::with_max_entries(MAX_DNS_QUERIES, 0);
+
+/// QUIC flights awaiting a response
+///
+/// Key: address of the sending struct sock
+/// Value: DnsQuery (timestamp of the flight's first datagram and its 4-tuple)
+///
+/// A QUIC connection normally has a socket of its own, so the socket
+/// stands in for the connection ID, which is encrypted in short headers.
+#[map]
+pub static QUIC_START: LruHashMap<u64, DnsQuery> =
+    LruHashMap::with_max_entries(MAX_QUIC_FLIGHTS, 0);
+
+/// Services to report when filtering is enabled
+///
+/// Key: ServiceFilterKey (address and port)
+/// Value: u8 (unused, presence is the match)
+#[map]
+pub static SERVICE_FILTER: HashMap<ServiceFilterKey, u8> =
+    HashMap::with_max_entries(MAX_SERVICE_FILTERS, 0);
+
+/// Established sockets whose outgoing data is sampled for HTTP status lines
+///
+/// Key: ConnectionKey (4-tuple, as seen by the local socket)
+/// Value: the socket
+///
+/// Filled by the sock_ops program; the kernel removes sockets on close.
+/// The sk_msg program is attached to this map.
+#[map]
+pub static SOCK_HASH: SockHash<ConnectionKey> =
+    SockHash::with_max_entries(MAX_CONNECTIONS, 0);
+
+/// Status class of the last HTTP response on each connection
+///
+/// Key: ConnectionKey (4-tuple, as seen by the receiving side)
+/// Value: u8 status class (see HTTP_STATUS_* constants)
+///
+/// Written when a response is sent and read when the client receives it.
+/// LRU so entries for closed connections are evicted.
+#[map]
+pub static HTTP_STATUS: LruHashMap<ConnectionKey, u8> =
+    LruHashMap::with_max_entries(MAX_CONNECTIONS, 0);
+
+/// Congestion control state of each connection
+///
+/// Key: ConnectionKey (4-tuple, as recorded by tcp:tcp_probe)
+/// Value: CongestionState
+///
+/// Updated by the tcp_probe tracepoint when userspace configured the
+/// snd_cwnd offset. LRU so entries for closed connections are evicted.
+#[map]
+pub static CONGESTION: LruHashMap<ConnectionKey, CongestionState> =
+    LruHashMap::with_max_entries(MAX_CONNECTIONS, 0);
+
+/// Accept queue counters of listening sockets
+///
+/// Key: ListenerKey (namespace and port)
+/// Value: ListenerStats
+///
+/// Updated by the tcp_conn_request and tcp_v*_syn_recv_sock kprobes when
+/// userspace configured the backlog offsets. LRU so closed listeners are
+/// evicted.
+#[map]
+pub static LISTENERS: LruHashMap<ListenerKey, ListenerStats> =
+    LruHashMap::with_max_entries(MAX_LISTENERS, 0);
@@ -0,0 +1,98 @@
+//! Tail-called latency event pipeline
+//!
+//! Handlers parse the socket and compute the latency, then hand the event
+//! to a chain of small programs instead of filtering, sampling and emitting
+//! it themselves:
+//!
+//! ```text
+//! handler (parse) -> pipeline_filter -> pipeline_sample -> pipeline_emit
+//! ```
+//!
+//! Each stage is verified on its own, so in-kernel processing can grow
+//! without pushing the handlers past the verifier's instruction limit. The
+//! event travels between stages in the per-CPU PIPELINE_EVENT slot. Stages
+//! are kprobe programs, so only kprobe handlers can enter the pipeline.
+
+use aya_ebpf::{macros::kprobe, programs::ProbeContext};
+use probe_common::{constants::*, types::*};
+
+use crate::{
+    helpers::*,
+    maps::{EVENTS, PIPELINE, PIPELINE_EVENT},
+};
+
+/// Send a latency event through the pipeline
+///
+/// Stores the event for the stages and tail calls the first one, which
+/// does not return. If the pipeline is not loaded, the stages are run
+/// inline instead.
+#[inline(always)]
+pub fn submit_event(ctx: &ProbeContext, event: &LatencyEvent) {
+    if let Some(slot) = PIPELINE_EVENT.get_ptr_mut(0) {
+        unsafe {
+            *slot = *event;
+            let _ = PIPELINE.tail_call(ctx, PIPELINE_STAGE_FILTER);
+        }
+    }
+
+    increment_stat(STAT_PIPELINE_FALLBACKS);
+    if should_report(&event.key, event.event_type) {
+        EVENTS.output(ctx, event, 0);
+    }
+}
+
+/// Continue with the next stage, or emit inline if it is not loaded
+#[inline(always)]
+fn next_stage(ctx: &ProbeContext, event: &LatencyEvent, stage: u32) -> u32 {
+    unsafe {
+        let _ = PIPELINE.tail_call(ctx, stage);
+    }
+
+    increment_stat(STAT_PIPELINE_FALLBACKS);
+    EVENTS.output(ctx, event, 0);
+    0
+}
+
+/// Drop events of connections outside the service filter
+#[kprobe]
+pub fn pipeline_filter(ctx: ProbeContext) -> u32 {
+    let event = match PIPELINE_EVENT.get(0) {
+        Some(event) => event,
+        None => return 1,
+    };
+
+    if !matches_service_filter(&event.key) {
+        increment_stat(STAT_FILTERED_EVENTS);
+        return 0;
+    }
+
+    next_stage(&ctx, event, PIPELINE_STAGE_SAMPLE)
+}
+
+/// Drop events not picked by kernel-side sampling
+#[kprobe]
+pub fn pipeline_sample(ctx: ProbeContext) -> u32 {
+    let event = match PIPELINE_EVENT.get(0) {
+        Some(event) => event,
+        None => return 1,
+    };
+
+    if !should_sample(&event.key, event.event_type) {
+        increment_stat(STAT_SAMPLED_OUT_EVENTS);
+        return 0;
+    }
+
+    next_stage(&ctx, event, PIPELINE_STAGE_EMIT)
+}
+
+/// Send the event to userspace
+#[kprobe]
+pub fn pipeline_emit(ctx: ProbeContext) -> u32 {
+    match PIPELINE_EVENT.get(0) {
+        Some(event) => {
+            EVENTS.output(&ctx, event, 0);
+            0
+        }
+        None => 1,
+    }
+}
@@ -1,28 +1,30 @@
-//! Socket structure parsing utilities with CO-RE support
+//! Socket structure parsing utilities
 //!
 //! Provides production-ready functions to extract connection information from
-//! kernel socket structures using CO-RE (Compile Once, Run Everywhere) and BTF.
+//! kernel socket structures. Field offsets that differ between kernels are
+//! resolved from BTF by userspace and passed through the CONFIG map, so one
+//! object runs on every kernel of the architecture it was built for.
 
-use aya_ebpf::{
-    cty::{c_ulong, c_ushort},
-    helpers::bpf_probe_read_kernel,
-    programs::ProbeContext,
+use aya_ebpf::{helpers::bpf_probe_read_kernel, programs::ProbeContext};
+use probe_common::{
+    constants::*,
+    types::{ConnectionKey, LatencyEvent, SockCommonLayout},
 };
-use core::mem;
-use probe_common::types::ConnectionKey;
 
-/// Kernel struct sock representation (partial)
+use crate::helpers::read_config;
+
+/// Kernel struct sock (opaque)
 ///
-/// We only define the fields we need. CO-RE will handle the actual offsets.
+/// Starts with struct sock_common, whose fields are read at the offsets of
+/// a [`SockCommonLayout`] (see [`read_sock_common`]).
 #[repr(C)]
 pub(crate) struct sock {
-    __sk_common: sock_common,
+    _private: [u8; 0],
 }
 
-/// Kernel struct sock_common (partial)
+/// Fields of kernel struct sock_common the probes use
 ///
 /// Contains the connection 4-tuple we need for tracking.
-#[repr(C)]
 pub(crate) struct sock_common {
     skc_daddr: u32,      // Destination address
     skc_rcv_saddr: u32,  // Source address
@@ -32,11 +34,48 @@ pub(crate) struct sock_common {
     skc_state: u8,       // Connection state
 }
 
+/// Read the sock_common fields of a socket
+///
+/// The start of sock_common is read with one kernel read; the fields are
+/// then taken at the offsets userspace resolved from BTF
+/// (CONFIG_SKC_LAYOUT), or at the default layout's.
+#[inline(always)]
+fn read_sock_common(sock_ptr: *const sock) -> Result<sock_common, i64> {
+    if sock_ptr.is_null() {
+        return Err(-1);
+    }
+
+    let layout = SockCommonLayout::unpack(read_config(CONFIG_SKC_LAYOUT)).unwrap_or(SockCommonLayout::DEFAULT);
+    let window = unsafe { bpf_probe_read_kernel(sock_ptr as *const [u8; SKC_LAYOUT_WINDOW]).map_err(|_| -1)? };
+
+    Ok(sock_common {
+        skc_daddr: u32::from_ne_bytes(window_bytes(&window, layout.daddr)),
+        skc_rcv_saddr: u32::from_ne_bytes(window_bytes(&window, layout.rcv_saddr)),
+        skc_dport: u16::from_ne_bytes(window_bytes(&window, layout.dport)),
+        skc_num: u16::from_ne_bytes(window_bytes(&window, layout.num)),
+        skc_family: u16::from_ne_bytes(window_bytes(&window, layout.family)),
+        skc_state: window_bytes::<1>(&window, layout.state)[0],
+    })
+}
+
+/// Bytes of a field in the sock_common window
+///
+/// Indices are masked so the verifier can bound them; userspace only
+/// configures layouts that fit the window.
+#[inline(always)]
+fn window_bytes<const N: usize>(window: &[u8; SKC_LAYOUT_WINDOW], offset: u8) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = window[(offset as usize + i) & (SKC_LAYOUT_WINDOW - 1)];
+    }
+    bytes
+}
+
 /// IPv4 address family constant
-const AF_INET: u16 = 2;
+pub(crate) const AF_INET: u16 = 2;
 
 /// IPv6 address family constant
-const AF_INET6: u16 = 10;
+pub(crate) const AF_INET6: u16 = 10;
 
 /// TCP connection states we care about
 const TCP_ESTABLISHED: u8 = 1;
@@ -60,17 +99,8 @@ pub fn get_sock_from_context(ctx: &ProbeContext) -> Result<*const sock, i64> {
 /// Uses bpf_probe_read_kernel to safely read from kernel memory.
 /// The BPF verifier ensures this is safe.
 pub fn extract_connection_key(sock_ptr: *const sock) -> Result<ConnectionKey, i64> {
-    if sock_ptr.is_null() {
-        return Err(-1);
-    }
-
-    // Read the sock_common structure from kernel memory
-    let sk_common = unsafe {
-        let ptr = sock_ptr as *const u8;
-        // sk_common is at the beginning of struct sock
-        let common_ptr = ptr as *const sock_common;
-        bpf_probe_read_kernel(common_ptr).map_err(|_| -1)?
-    };
+    // Read the sock_common fields from kernel memory
+    let sk_common = read_sock_common(sock_ptr)?;
 
     // Only handle IPv4 for now (IPv6 support can be added later)
     if sk_common.skc_family != AF_INET {
@@ -90,23 +120,108 @@ pub fn extract_connection_key(sock_ptr: *const sock) -> Result<ConnectionKey, i6
     Ok(key)
 }
 
+/// Read the destination of an unconnected UDP send
+///
+/// `msg_name` is the first field of struct msghdr and, for IPv4 sendto(),
+/// points to a struct sockaddr_in already copied into kernel memory:
+/// sin_family @0, sin_port @2 (network order), sin_addr @4.
+///
+/// # Returns
+///
+/// Destination address and port (network byte order), or None if the
+/// message has no IPv4 destination
+pub fn read_msg_name(msg: *const u8) -> Option<(u32, u16)> {
+    if msg.is_null() {
+        return None;
+    }
+
+    unsafe {
+        let name = bpf_probe_read_kernel(msg as *const *const u8).ok()?;
+        if name.is_null() {
+            return None;
+        }
+
+        let family = bpf_probe_read_kernel(name as *const u16).ok()?;
+        if family != AF_INET {
+            return None;
+        }
+
+        let port = bpf_probe_read_kernel(name.add(2) as *const u16).ok()?;
+        let addr = bpf_probe_read_kernel(name.add(4) as *const u32).ok()?;
+        Some((addr, port))
+    }
+}
+
+/// Get the cookie of a socket
+///
+/// Reads `sock_common.skc_cookie` at the offset userspace resolved from
+/// BTF. The kernel assigns cookies lazily, the first time one is asked for
+/// (by a sock_ops program such as sock_ops_cookie, `ss`, or another BPF
+/// program), so this returns 0 for sockets without one yet, when the
+/// offset is not configured, or when the read fails.
+#[inline(always)]
+pub fn get_socket_cookie(sock_ptr: *const sock) -> u64 {
+    let offset = read_config(CONFIG_SKC_COOKIE_OFFSET) as usize;
+    if offset == 0 || sock_ptr.is_null() {
+        return 0;
+    }
+
+    unsafe { bpf_probe_read_kernel((sock_ptr as *const u8).add(offset) as *const u64).unwrap_or(0) }
+}
+
+/// Get the network namespace inode of a socket
+///
+/// Follows `sock_common.skc_net` to `struct net` and reads `ns.inum`. The
+/// offsets vary between kernels, so userspace resolves them from BTF and
+/// stores them in the CONFIG map. Returns 0 when they are not configured
+/// or the reads fail.
+#[inline(always)]
+pub fn get_netns(sock_ptr: *const sock) -> u32 {
+    let net_offset = read_config(CONFIG_NETNS_SKC_NET_OFFSET) as usize;
+    let inum_offset = read_config(CONFIG_NETNS_INUM_OFFSET) as usize;
+    if net_offset == 0 || sock_ptr.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let net_field = (sock_ptr as *const u8).add(net_offset) as *const *const u8;
+        let net = match bpf_probe_read_kernel(net_field) {
+            Ok(net) if !net.is_null() => net,
+            _ => return 0,
+        };
+        bpf_probe_read_kernel(net.add(inum_offset) as *const u32).unwrap_or(0)
+    }
+}
+
+/// Get the local port of a socket (host byte order)
+#[inline(always)]
+pub fn get_local_port(sock_ptr: *const sock) -> Result<u16, i64> {
+    Ok(read_sock_common(sock_ptr)?.skc_num)
+}
+
+/// Read a counter of struct sock at an offset resolved from BTF
+///
+/// `narrow` fields are u16, as the backlog fields were before 5.5.
+/// Returns 0 if the read fails.
+#[inline(always)]
+pub fn read_sock_u32(sock_ptr: *const sock, offset: usize, narrow: bool) -> u32 {
+    unsafe {
+        let field = (sock_ptr as *const u8).add(offset);
+        if narrow {
+            bpf_probe_read_kernel(field as *const u16).map(u32::from).unwrap_or(0)
+        } else {
+            bpf_probe_read_kernel(field as *const u32).unwrap_or(0)
+        }
+    }
+}
+
 /// Check if socket is valid for tracking
 ///
 /// Validates that the socket represents an established TCP connection
 /// that we want to track.
 pub fn is_valid_socket(sock_ptr: *const sock) -> bool {
-    if sock_ptr.is_null() {
-        return false;
-    }
-
     // Read socket family and state
-    let result = unsafe {
-        let ptr = sock_ptr as *const u8;
-        let common_ptr = ptr as *const sock_common;
-        bpf_probe_read_kernel(common_ptr)
-    };
-
-    match result {
+    match read_sock_common(sock_ptr) {
         Ok(sk_common) => {
             // Only track IPv4 TCP connections that are established
             sk_common.skc_family == AF_INET
@@ -122,30 +237,53 @@ pub fn is_valid_socket(sock_ptr: *const sock) -> bool {
 /// Returns the TCP connection state for the given socket.
 /// Useful for filtering or categorizing connections.
 pub fn get_socket_state(sock_ptr: *const sock) -> Result<u8, i64> {
-    if sock_ptr.is_null() {
-        return Err(-1);
+    Ok(read_sock_common(sock_ptr)?.skc_state)
+}
+
+/// SYN/FIN/RST indicators of a TCP state
+///
+/// Kprobes see the socket rather than the segment, so the flags are
+/// inferred: a socket in the handshake has a SYN outstanding, one past
+/// ESTABLISHED has exchanged a FIN, and one in CLOSE while still being
+/// read was reset or aborted.
+#[inline(always)]
+pub fn tcp_flags(state: u8) -> u8 {
+    match state {
+        TCP_STATE_SYN_SENT | TCP_STATE_SYN_RECV | TCP_STATE_NEW_SYN_RECV => TCP_FLAG_SYN,
+        TCP_STATE_FIN_WAIT1
+        | TCP_STATE_FIN_WAIT2
+        | TCP_STATE_TIME_WAIT
+        | TCP_STATE_CLOSE_WAIT
+        | TCP_STATE_LAST_ACK
+        | TCP_STATE_CLOSING => TCP_FLAG_FIN,
+        TCP_STATE_CLOSE => TCP_FLAG_RST,
+        _ => 0,
     }
+}
 
-    unsafe {
-        let ptr = sock_ptr as *const u8;
-        let common_ptr = ptr as *const sock_common;
-        let sk_common = bpf_probe_read_kernel(common_ptr).map_err(|_| -1)?;
-        Ok(sk_common.skc_state)
+/// Transport protocol of a latency event
+///
+/// Every probe hooks functions of one protocol (tcp_* or udp_*), so the
+/// event type tells the protocol without reading `sk_protocol`, whose
+/// offset in struct sock changed with its bitfield layout.
+#[inline(always)]
+pub fn event_protocol(event_type: u8) -> u8 {
+    match event_type {
+        EVENT_TYPE_DNS | EVENT_TYPE_QUIC => IPPROTO_UDP,
+        _ => IPPROTO_TCP,
     }
 }
 
+/// Record the socket's TCP state and flags in a latency event
+#[inline(always)]
+pub fn set_tcp_state(event: &mut LatencyEvent, sock_ptr: *const sock) {
+    event.tcp_state = get_socket_state(sock_ptr).unwrap_or(TCP_STATE_UNKNOWN);
+    event.tcp_flags = tcp_flags(event.tcp_state);
+}
+
 /// Get socket address family
 ///
 /// Returns the address family (AF_INET, AF_INET6, etc.).
 pub fn get_socket_family(sock_ptr: *const sock) -> Result<u16, i64> {
-    if sock_ptr.is_null() {
-        return Err(-1);
-    }
-
-    unsafe {
-        let ptr = sock_ptr as *const u8;
-        let common_ptr = ptr as *const sock_common;
-        let sk_common = bpf_probe_read_kernel(common_ptr).map_err(|_| -1)?;
-        Ok(sk_common.skc_family)
-    }
+    Ok(read_sock_common(sock_ptr)?.skc_family)
 }